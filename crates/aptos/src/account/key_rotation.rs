@@ -64,8 +64,8 @@ impl RotateKey {
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct RotateSummary {
-    message: Option<String>,
-    transaction: TransactionSummary,
+    pub message: Option<String>,
+    pub transaction: TransactionSummary,
 }
 
 #[async_trait]
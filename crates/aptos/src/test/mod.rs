@@ -304,6 +304,15 @@ impl CliTestFramework {
         .await
     }
 
+    pub async fn show_stake_pool(&self, pool_index: usize) -> CliTypedResult<StakePool> {
+        self.show_validator_stake(pool_index)
+            .await
+            .and_then(|value| {
+                serde_json::from_value(value)
+                    .map_err(|err| CliError::UnexpectedError(err.to_string()))
+            })
+    }
+
     pub async fn initialize_validator(
         &self,
         index: usize,
@@ -672,6 +681,20 @@ impl CliTestFramework {
         .unwrap();
     }
 
+    /// Writes a module's source into the package, ex: to publish a custom contract instead of `hello_blockchain`
+    pub fn add_move_source(&self, name: &str, contents: &str) {
+        let source_path = self
+            .move_dir()
+            .join("sources")
+            .join(format!("{}.move", name));
+        write_to_file(
+            source_path.as_path(),
+            &source_path.as_display().to_string(),
+            contents.as_bytes(),
+        )
+        .unwrap();
+    }
+
     pub fn move_dir(&self) -> PathBuf {
         assert!(self.move_dir.is_some(), "Must have initialized the temp move directory with `CliTestFramework::init_move_dir()` first");
         self.move_dir.as_ref().cloned().unwrap()
@@ -795,6 +818,20 @@ impl CliTestFramework {
         .await
     }
 
+    /// Runs an entry function by its id, ex: "0x1::coin::transfer", with default gas options
+    pub async fn run_entry_function(
+        &self,
+        index: usize,
+        function_id: &str,
+        args: Vec<&str>,
+        type_args: Vec<&str>,
+    ) -> CliTypedResult<TransactionSummary> {
+        let function_id = MemberId::from_str(function_id)
+            .map_err(|err| CliError::UnexpectedError(err.to_string()))?;
+        self.run_function(index, None, function_id, args, type_args)
+            .await
+    }
+
     pub fn move_options(&self, account_strs: BTreeMap<&str, &str>) -> MovePackageDir {
         MovePackageDir {
             package_dir: Some(self.move_dir()),
@@ -922,3 +959,40 @@ impl IndividualValidatorPerformance {
 pub struct ValidatorPerformance {
     pub validators: Vec<IndividualValidatorPerformance>,
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonCoin {
+    value: String,
+}
+
+/// Amounts of a `0x1::stake::StakePool`, as returned by `show_validator_stake`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StakePool {
+    active: JsonCoin,
+    inactive: JsonCoin,
+    pending_active: JsonCoin,
+    pending_inactive: JsonCoin,
+    locked_until_secs: String,
+}
+
+impl StakePool {
+    pub fn active(&self) -> u64 {
+        self.active.value.parse().unwrap()
+    }
+
+    pub fn inactive(&self) -> u64 {
+        self.inactive.value.parse().unwrap()
+    }
+
+    pub fn pending_active(&self) -> u64 {
+        self.pending_active.value.parse().unwrap()
+    }
+
+    pub fn pending_inactive(&self) -> u64 {
+        self.pending_inactive.value.parse().unwrap()
+    }
+
+    pub fn locked_until_secs(&self) -> u64 {
+        self.locked_until_secs.parse().unwrap()
+    }
+}
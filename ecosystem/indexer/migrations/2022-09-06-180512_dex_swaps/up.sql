@@ -0,0 +1,25 @@
+-- Your SQL goes here
+CREATE TABLE dex_swaps
+(
+    event_key        VARCHAR(100) NOT NULL,
+    sequence_number  uint_64      NOT NULL,
+    transaction_hash VARCHAR(255) NOT NULL,
+    dex_address      VARCHAR(66)  NOT NULL,
+    -- ex: 0xabc::swap::LiquidityPool<0x1::aptos_coin::AptosCoin, 0xdef::usdc::USDC>
+    pool             TEXT         NOT NULL,
+    sender           VARCHAR(66)  NOT NULL,
+    coin_in          TEXT         NOT NULL,
+    coin_out         TEXT         NOT NULL,
+    amount_in        uint_128     NOT NULL,
+    amount_out       uint_128     NOT NULL,
+    swapped_at       TIMESTAMP    NOT NULL,
+
+    -- Default time columns
+    inserted_at      TIMESTAMP    NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (event_key, sequence_number)
+);
+
+CREATE INDEX ds_pool_swapped_at_index ON dex_swaps (pool, swapped_at);
+CREATE INDEX ds_sender_index ON dex_swaps (sender);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS minute_transaction_rollups;
+DROP TABLE IF EXISTS hourly_activity_rollups;
+DROP TABLE IF EXISTS hourly_active_accounts;
@@ -0,0 +1,44 @@
+-- Your SQL goes here
+
+-- Each rollup row records the highest version folded into it so that replayed versions are skipped
+CREATE TABLE minute_transaction_rollups
+(
+    bucket                  TIMESTAMP NOT NULL,
+    num_transactions        BIGINT    NOT NULL,
+    num_user_transactions   BIGINT    NOT NULL,
+    num_failed_transactions BIGINT    NOT NULL,
+    last_version            uint_64   NOT NULL,
+
+    -- Default time columns
+    inserted_at             TIMESTAMP NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (bucket)
+);
+
+CREATE TABLE hourly_activity_rollups
+(
+    bucket              TIMESTAMP NOT NULL,
+    gas_used            uint_64   NOT NULL,
+    num_active_accounts BIGINT    NOT NULL,
+    last_version        uint_64   NOT NULL,
+
+    -- Default time columns
+    inserted_at         TIMESTAMP NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (bucket)
+);
+
+-- Accounts already counted towards an hour's num_active_accounts
+CREATE TABLE hourly_active_accounts
+(
+    bucket      TIMESTAMP    NOT NULL,
+    account     VARCHAR(66)  NOT NULL,
+
+    -- Default time columns
+    inserted_at TIMESTAMP    NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (bucket, account)
+);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE IF EXISTS transactions
+    DROP COLUMN IF EXISTS num_events,
+    DROP COLUMN IF EXISTS num_write_set_changes,
+    DROP COLUMN IF EXISTS payload_size_bytes;
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+-- Adding a column with a constant default only updates the catalog (Postgres 11+), so this doesn't rewrite the table.
+-- Transactions indexed before these columns existed read 0 (and a NULL size) until their versions are re-processed
+-- (ex: with `backfill`), which sets them, see "Transaction counts" in the README: backfilling them here would update
+-- every row of `transactions` in the migration's single transaction.
+ALTER TABLE transactions
+ADD COLUMN num_events BIGINT NOT NULL DEFAULT 0,
+ADD COLUMN num_write_set_changes BIGINT NOT NULL DEFAULT 0,
+ADD COLUMN payload_size_bytes BIGINT;
+
+ALTER TABLE transactions
+ALTER COLUMN num_events DROP DEFAULT,
+ALTER COLUMN num_write_set_changes DROP DEFAULT;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS validator_set_snapshots;
+DROP TABLE IF EXISTS epochs;
@@ -0,0 +1,34 @@
+-- Your SQL goes here
+CREATE TABLE epochs
+(
+    epoch               uint_64      NOT NULL,
+    transaction_version uint_64      NOT NULL,
+    transaction_hash    VARCHAR(255) NOT NULL,
+    started_at          TIMESTAMP    NOT NULL,
+
+    -- Default time columns
+    inserted_at         TIMESTAMP    NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (epoch)
+);
+
+-- The validator set each epoch started with
+CREATE TABLE validator_set_snapshots
+(
+    epoch             uint_64      NOT NULL,
+    validator_address VARCHAR(66)  NOT NULL,
+    -- active, pending_active or pending_inactive
+    status            VARCHAR(50)  NOT NULL,
+    voting_power      uint_64      NOT NULL,
+    validator_index   BIGINT,
+    consensus_pubkey  VARCHAR(255),
+
+    -- Default time columns
+    inserted_at       TIMESTAMP    NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (epoch, validator_address)
+);
+
+CREATE INDEX vss_validator_address_index ON validator_set_snapshots (validator_address);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS current_object_ownerships;
+DROP TABLE IF EXISTS objects;
@@ -0,0 +1,42 @@
+-- Your SQL goes here
+-- Every change to an object's 0x1::object::ObjectCore
+CREATE TABLE objects
+(
+    transaction_version    uint_64      NOT NULL,
+    write_set_change_index BIGINT       NOT NULL,
+    object_address         VARCHAR(66)  NOT NULL,
+    -- NULL when the object is deleted
+    owner_address          VARCHAR(66),
+    state_key_hash         VARCHAR(255) NOT NULL,
+    guid_creation_num      uint_64,
+    allow_ungated_transfer BOOLEAN,
+    is_deleted             BOOLEAN      NOT NULL,
+
+    -- Default time columns
+    inserted_at            TIMESTAMP    NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (transaction_version, write_set_change_index)
+);
+
+CREATE INDEX o_object_address_index ON objects (object_address);
+CREATE INDEX o_owner_address_index ON objects (owner_address);
+
+CREATE TABLE current_object_ownerships
+(
+    object_address           VARCHAR(66)  NOT NULL,
+    -- The last owner, if the object is deleted
+    owner_address            VARCHAR(66),
+    state_key_hash           VARCHAR(255) NOT NULL,
+    allow_ungated_transfer   BOOLEAN,
+    last_transaction_version uint_64      NOT NULL,
+    is_deleted               BOOLEAN      NOT NULL,
+
+    -- Default time columns
+    inserted_at              TIMESTAMP    NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (object_address)
+);
+
+CREATE INDEX coo_owner_address_index ON current_object_ownerships (owner_address);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS fungible_asset_activities;
+DROP TABLE IF EXISTS fungible_asset_balances;
+DROP TABLE IF EXISTS fungible_asset_metadata;
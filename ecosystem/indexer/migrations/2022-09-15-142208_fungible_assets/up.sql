@@ -0,0 +1,65 @@
+-- Your SQL goes here
+-- Assets of the fungible asset standard, keyed by the address of their 0x1::fungible_asset::Metadata object
+CREATE TABLE fungible_asset_metadata
+(
+    asset_type          VARCHAR(66)   NOT NULL,
+    creator_address     VARCHAR(66),
+    name                VARCHAR(32)   NOT NULL,
+    symbol              VARCHAR(10)   NOT NULL,
+    decimals            BIGINT        NOT NULL,
+    icon_uri            VARCHAR(512)  NOT NULL,
+    project_uri         VARCHAR(512)  NOT NULL,
+    transaction_version uint_64       NOT NULL,
+
+    -- Default time columns
+    inserted_at         TIMESTAMP     NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (asset_type)
+);
+
+CREATE INDEX fam_creator_address_index ON fungible_asset_metadata (creator_address);
+
+-- The current balance of each 0x1::fungible_asset::FungibleStore
+CREATE TABLE fungible_asset_balances
+(
+    storage_id               VARCHAR(66) NOT NULL,
+    owner_address            VARCHAR(66),
+    asset_type               VARCHAR(66) NOT NULL,
+    amount                   NUMERIC     NOT NULL,
+    is_frozen                BOOLEAN     NOT NULL,
+    last_transaction_version uint_64     NOT NULL,
+
+    -- Default time columns
+    inserted_at              TIMESTAMP   NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (storage_id)
+);
+
+CREATE INDEX fab_owner_address_index ON fungible_asset_balances (owner_address);
+CREATE INDEX fab_asset_type_index ON fungible_asset_balances (asset_type);
+
+CREATE TABLE fungible_asset_activities
+(
+    transaction_version   uint_64      NOT NULL,
+    event_index           BIGINT       NOT NULL,
+    storage_id            VARCHAR(66)  NOT NULL,
+    owner_address         VARCHAR(66),
+    asset_type            VARCHAR(66),
+    type                  VARCHAR(255) NOT NULL,
+    -- NULL for freezes
+    amount                NUMERIC,
+    -- NULL for deposits and withdrawals
+    is_frozen             BOOLEAN,
+    transaction_timestamp TIMESTAMP    NOT NULL,
+
+    -- Default time columns
+    inserted_at           TIMESTAMP    NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (transaction_version, event_index)
+);
+
+CREATE INDEX faa_owner_address_index ON fungible_asset_activities (owner_address);
+CREATE INDEX faa_asset_type_index ON fungible_asset_activities (asset_type);
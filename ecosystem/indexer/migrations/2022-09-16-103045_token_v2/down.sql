@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS current_token_ownerships_v2;
+DROP TABLE IF EXISTS token_activities_v2;
+DROP TABLE IF EXISTS tokens_v2;
+DROP TABLE IF EXISTS collections_v2;
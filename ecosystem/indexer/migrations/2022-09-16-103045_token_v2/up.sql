@@ -0,0 +1,86 @@
+-- Your SQL goes here
+-- Every write of a token v2 collection (0x4::collection::Collection)
+CREATE TABLE collections_v2
+(
+    transaction_version    uint_64       NOT NULL,
+    write_set_change_index BIGINT        NOT NULL,
+    collection_id          VARCHAR(66)   NOT NULL,
+    creator_address        VARCHAR(66)   NOT NULL,
+    collection_name        VARCHAR(128)  NOT NULL,
+    description            TEXT          NOT NULL,
+    uri                    VARCHAR(512)  NOT NULL,
+    current_supply         NUMERIC,
+    max_supply             NUMERIC,
+    total_minted           NUMERIC,
+
+    -- Default time columns
+    inserted_at            TIMESTAMP     NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (transaction_version, write_set_change_index)
+);
+
+CREATE INDEX c2_collection_id_index ON collections_v2 (collection_id);
+CREATE INDEX c2_creator_address_index ON collections_v2 (creator_address);
+
+-- Every write of a token v2 (0x4::token::Token)
+CREATE TABLE tokens_v2
+(
+    transaction_version    uint_64       NOT NULL,
+    write_set_change_index BIGINT        NOT NULL,
+    token_data_id          VARCHAR(66)   NOT NULL,
+    collection_id          VARCHAR(66)   NOT NULL,
+    token_name             VARCHAR(128)  NOT NULL,
+    description            TEXT          NOT NULL,
+    uri                    VARCHAR(512)  NOT NULL,
+    token_index            NUMERIC,
+
+    -- Default time columns
+    inserted_at            TIMESTAMP     NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (transaction_version, write_set_change_index)
+);
+
+CREATE INDEX t2_token_data_id_index ON tokens_v2 (token_data_id);
+CREATE INDEX t2_collection_id_index ON tokens_v2 (collection_id);
+
+CREATE TABLE token_activities_v2
+(
+    transaction_version   uint_64      NOT NULL,
+    event_index           BIGINT       NOT NULL,
+    token_data_id         VARCHAR(66)  NOT NULL,
+    -- NULL for transfers
+    collection_id         VARCHAR(66),
+    type                  VARCHAR(255) NOT NULL,
+    from_address          VARCHAR(66),
+    to_address            VARCHAR(66),
+    transaction_timestamp TIMESTAMP    NOT NULL,
+
+    -- Default time columns
+    inserted_at           TIMESTAMP    NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (transaction_version, event_index)
+);
+
+CREATE INDEX ta2_token_data_id_index ON token_activities_v2 (token_data_id);
+CREATE INDEX ta2_from_address_index ON token_activities_v2 (from_address);
+CREATE INDEX ta2_to_address_index ON token_activities_v2 (to_address);
+
+CREATE TABLE current_token_ownerships_v2
+(
+    token_data_id            VARCHAR(66) NOT NULL,
+    -- The last owner, if the token is burned
+    owner_address            VARCHAR(66),
+    last_transaction_version uint_64     NOT NULL,
+    is_burned                BOOLEAN     NOT NULL,
+
+    -- Default time columns
+    inserted_at              TIMESTAMP   NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (token_data_id)
+);
+
+CREATE INDEX cto2_owner_address_index ON current_token_ownerships_v2 (owner_address);
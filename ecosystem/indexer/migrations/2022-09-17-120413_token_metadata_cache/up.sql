@@ -0,0 +1,23 @@
+-- Your SQL goes here
+-- Off-chain metadata of token URIs, filled in by the URI enricher
+CREATE TABLE token_metadata_cache
+(
+    uri                VARCHAR(512) NOT NULL,
+    resolved_uri       TEXT,
+    metadata           jsonb,
+    image_uri          TEXT,
+    image_content_type VARCHAR(255),
+    image_size_bytes   BIGINT,
+    -- ok or error
+    status             VARCHAR(10)  NOT NULL,
+    error              TEXT,
+    fetched_at         TIMESTAMP    NOT NULL,
+
+    -- Default time columns
+    inserted_at        TIMESTAMP    NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (uri)
+);
+
+CREATE INDEX tmc_status_fetched_at_index ON token_metadata_cache (status, fetched_at);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE IF EXISTS fungible_asset_activities
+    DROP COLUMN IF EXISTS amount_usd;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- USD value of deposits and withdrawals when they were indexed, if a price provider is configured
+ALTER TABLE fungible_asset_activities
+ADD COLUMN amount_usd NUMERIC;
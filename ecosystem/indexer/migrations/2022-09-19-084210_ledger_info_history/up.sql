@@ -0,0 +1,18 @@
+-- Your SQL goes here
+-- Periodic snapshots of the node's ledger info, to track its pruning window and the chain's growth
+CREATE TABLE ledger_info_history
+(
+    chain_id              BIGINT    NOT NULL,
+    epoch                 uint_64   NOT NULL,
+    ledger_version        uint_64   NOT NULL,
+    ledger_timestamp      TIMESTAMP NOT NULL,
+    oldest_ledger_version uint_64   NOT NULL,
+
+    -- Default time columns
+    inserted_at           TIMESTAMP NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (chain_id, ledger_version)
+);
+
+CREATE INDEX lih_inserted_at_index ON ledger_info_history (inserted_at);
@@ -0,0 +1,27 @@
+-- Your SQL goes here
+-- Coin and token (0x3::token) transfers normalized into edges between accounts, for graph analytics
+CREATE TABLE transfer_edges
+(
+    transaction_version   uint_64        NOT NULL,
+    -- Index of the deposit event, or of the withdraw event for burns
+    event_index           BIGINT         NOT NULL,
+    -- NULL for mints
+    from_address          VARCHAR(66),
+    -- NULL for burns
+    to_address            VARCHAR(66),
+    -- Coin type, or token id (creator::collection::name::property_version)
+    asset                 VARCHAR(5000)  NOT NULL,
+    amount                NUMERIC        NOT NULL,
+    -- coin or token
+    kind                  VARCHAR(50)    NOT NULL,
+    transaction_timestamp TIMESTAMP      NOT NULL,
+
+    -- Default time columns
+    inserted_at           TIMESTAMP      NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (transaction_version, event_index)
+);
+
+CREATE INDEX te_from_address_index ON transfer_edges (from_address);
+CREATE INDEX te_to_address_index ON transfer_edges (to_address);
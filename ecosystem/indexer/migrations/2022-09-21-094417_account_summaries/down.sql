@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS account_summary_batches;
+DROP TABLE IF EXISTS account_summaries;
@@ -0,0 +1,36 @@
+-- Your SQL goes here
+-- Per-account aggregates, added to as batches are processed rather than recomputed
+CREATE TABLE account_summaries
+(
+    address             VARCHAR(66)    NOT NULL,
+    -- User transactions sent, including failed ones
+    transactions_sent   BIGINT         NOT NULL,
+    -- Gas fees paid (gas used * gas unit price), in octas
+    gas_spent           NUMERIC        NOT NULL,
+    -- Version of the last user transaction sent
+    last_active_version uint_64        NOT NULL,
+    -- Coin types registered (0x1::account::CoinRegisterEvent). Coin stores can't be removed, so this only grows.
+    coins_held          BIGINT         NOT NULL,
+
+    -- Default time columns
+    inserted_at         TIMESTAMP      NOT NULL DEFAULT NOW(),
+    last_updated        TIMESTAMP      NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (address)
+);
+
+CREATE INDEX as_last_active_version_index ON account_summaries (last_active_version);
+
+-- Version ranges already added to account_summaries, so re-processing them doesn't count them twice
+CREATE TABLE account_summary_batches
+(
+    start_version uint_64   NOT NULL,
+    end_version   uint_64   NOT NULL,
+
+    -- Default time columns
+    inserted_at   TIMESTAMP NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (start_version, end_version)
+);
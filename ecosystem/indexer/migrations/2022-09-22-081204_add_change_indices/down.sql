@@ -0,0 +1,13 @@
+-- This file should undo anything in `up.sql`
+-- Restores the original primary keys, in case they were swapped since
+ALTER TABLE IF EXISTS events
+    DROP CONSTRAINT IF EXISTS events_pkey,
+    DROP CONSTRAINT IF EXISTS events_key_sequence_number_unique,
+    ADD PRIMARY KEY (key, sequence_number),
+    DROP COLUMN IF EXISTS event_index;
+
+ALTER TABLE IF EXISTS write_set_changes
+    DROP CONSTRAINT IF EXISTS write_set_changes_pkey,
+    DROP CONSTRAINT IF EXISTS write_set_changes_transaction_hash_hash_unique,
+    ADD PRIMARY KEY (transaction_hash, hash),
+    DROP COLUMN IF EXISTS write_set_change_index;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+-- Position of each event and write set change within its transaction, so consumers can order them deterministically.
+-- Adding nullable columns without a default only updates the catalog, so this doesn't rewrite the tables. Rows indexed
+-- before these columns existed are NULL until their versions are re-processed (ex: with `backfill`), which sets them:
+-- their physical order can't be trusted to match the transaction's. Once none is NULL, the primary keys can be swapped
+-- to (transaction_hash, event_index) and (transaction_hash, write_set_change_index), see "Event and write set change
+-- indices" in the README.
+ALTER TABLE events
+ADD COLUMN event_index BIGINT;
+
+ALTER TABLE write_set_changes
+ADD COLUMN write_set_change_index BIGINT;
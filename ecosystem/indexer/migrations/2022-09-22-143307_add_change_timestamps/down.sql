@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS ev_transaction_timestamp_index;
+DROP INDEX IF EXISTS wsc_transaction_timestamp_index;
+
+ALTER TABLE IF EXISTS events
+    DROP COLUMN IF EXISTS transaction_timestamp;
+
+ALTER TABLE IF EXISTS write_set_changes
+    DROP COLUMN IF EXISTS transaction_timestamp;
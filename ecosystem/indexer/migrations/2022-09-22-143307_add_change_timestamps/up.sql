@@ -0,0 +1,12 @@
+-- Your SQL goes here
+-- Timestamp of the transaction which emitted the event or made the change, so filtering by time doesn't need a join.
+-- Genesis' is the epoch (it has none). Adding nullable columns without a default only updates the catalog, so this
+-- doesn't rewrite the tables. Rows indexed before these columns existed are NULL until their versions are re-processed
+-- (ex: with `backfill`), which sets them, see "Change timestamps" in the README: backfilling them here would update
+-- every row of both tables in the migration's single transaction. Their indexes are built concurrently after the
+-- migrations (see `database::CONCURRENT_INDEXES`), as building them here would block writes to the tables.
+ALTER TABLE events
+ADD COLUMN transaction_timestamp TIMESTAMP;
+
+ALTER TABLE write_set_changes
+ADD COLUMN transaction_timestamp TIMESTAMP;
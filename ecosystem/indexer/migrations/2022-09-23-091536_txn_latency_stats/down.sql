@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS txn_latency_stat_batches;
+DROP TABLE IF EXISTS txn_latency_stats;
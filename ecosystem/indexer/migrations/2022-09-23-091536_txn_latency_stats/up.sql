@@ -0,0 +1,33 @@
+-- Your SQL goes here
+-- Distribution of user transactions' estimated commit latency (block timestamp minus an estimate of when they were
+-- submitted, derived from their expiration), per minute. Added to as batches are processed.
+CREATE TABLE txn_latency_stats
+(
+    -- Block timestamp, truncated to the minute
+    period_start        TIMESTAMP NOT NULL,
+    -- Lower bound of the latency bucket, in seconds
+    latency_bucket_secs BIGINT    NOT NULL,
+    num_transactions    BIGINT    NOT NULL,
+    -- Sum of the estimated latencies, for averages
+    total_latency_ms    BIGINT    NOT NULL,
+
+    -- Default time columns
+    inserted_at         TIMESTAMP NOT NULL DEFAULT NOW(),
+    last_updated        TIMESTAMP NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (period_start, latency_bucket_secs)
+);
+
+-- Version ranges already added to txn_latency_stats, so re-processing them doesn't count them twice
+CREATE TABLE txn_latency_stat_batches
+(
+    start_version uint_64   NOT NULL,
+    end_version   uint_64   NOT NULL,
+
+    -- Default time columns
+    inserted_at   TIMESTAMP NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (start_version, end_version)
+);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS txn_timestamp_index;
+
+ALTER TABLE IF EXISTS transactions
+    DROP COLUMN IF EXISTS timestamp;
@@ -0,0 +1,35 @@
+-- Your SQL goes here
+-- Timestamp of the transaction's block, so transactions can be filtered (and partitioned) by time without a join.
+-- Genesis' is the epoch (it has none).
+ALTER TABLE transactions
+ADD COLUMN timestamp TIMESTAMP;
+
+-- Backfill the rows indexed before this column existed
+UPDATE transactions t
+SET timestamp = u.timestamp
+FROM user_transactions u
+WHERE t.hash = u.hash;
+
+UPDATE transactions t
+SET timestamp = b.timestamp
+FROM block_metadata_transactions b
+WHERE t.hash = b.hash;
+
+-- State checkpoints end their block, so they have the timestamp of the block's metadata transaction
+UPDATE transactions t
+SET timestamp = (
+    SELECT b.timestamp
+    FROM transactions bt
+    JOIN block_metadata_transactions b ON b.hash = bt.hash
+    WHERE bt.version < t.version
+    ORDER BY bt.version DESC
+    LIMIT 1
+)
+WHERE t.type = 'state_checkpoint_transaction';
+
+UPDATE transactions SET timestamp = TO_TIMESTAMP(0) AT TIME ZONE 'UTC' WHERE timestamp IS NULL;
+
+ALTER TABLE transactions
+ALTER COLUMN timestamp SET NOT NULL;
+
+CREATE INDEX txn_timestamp_index ON transactions (timestamp);
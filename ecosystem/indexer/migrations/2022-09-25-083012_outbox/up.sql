@@ -0,0 +1,29 @@
+-- Your SQL goes here
+-- Messages for external relays, written in the same DB transaction as the data they describe (the outbox pattern).
+-- A relay delivers undelivered messages in id order and marks them delivered, so delivery is at least once.
+CREATE TABLE outbox
+(
+    id                  BIGSERIAL    NOT NULL,
+    -- Kind of message, ex: "events"
+    topic               VARCHAR(255) NOT NULL,
+    transaction_version uint_64      NOT NULL,
+    -- Position of the message among its transaction's messages of the topic
+    message_index       BIGINT       NOT NULL,
+    -- Partitioning key, ex: the event key, so a relay can preserve each stream's order
+    message_key         VARCHAR(255) NOT NULL,
+    payload             JSONB        NOT NULL,
+    -- Set by the relay once delivered
+    delivered_at        TIMESTAMP,
+    delivery_attempts   INT          NOT NULL DEFAULT 0,
+    last_error          TEXT,
+
+    -- Default time columns
+    inserted_at         TIMESTAMP    NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (id),
+    -- Re-processing versions doesn't enqueue their messages twice
+    UNIQUE (topic, transaction_version, message_index)
+);
+
+CREATE INDEX outbox_undelivered_index ON outbox (id) WHERE delivered_at IS NULL;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- A database indexes one chain: ledger_infos holds a single row. Fails if it already holds several, which must be
+-- resolved by hand (or with --force-reset-chain).
+CREATE UNIQUE INDEX ledger_infos_single_row ON ledger_infos ((true));
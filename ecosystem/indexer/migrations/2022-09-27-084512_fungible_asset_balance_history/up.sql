@@ -0,0 +1,17 @@
+-- Your SQL goes here
+-- The balance of each 0x1::fungible_asset::FungibleStore after every transaction which wrote it, for balances as of a
+-- version. Balances are absolute rather than deltas, so a store's balance as of a version is its latest row at or
+-- before it: one lookup on the primary key.
+CREATE TABLE fungible_asset_balance_history
+(
+    storage_id          VARCHAR(66) NOT NULL,
+    transaction_version uint_64     NOT NULL,
+    asset_type          VARCHAR(66) NOT NULL,
+    amount              NUMERIC     NOT NULL,
+
+    -- Default time columns
+    inserted_at         TIMESTAMP   NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (storage_id, transaction_version)
+);
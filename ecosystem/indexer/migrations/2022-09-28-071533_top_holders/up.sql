@@ -0,0 +1,21 @@
+-- Your SQL goes here
+-- The largest holders of each coin and fungible asset, maintained as balances change by the top_holders_processor.
+-- Holders below the top are trimmed, so a holder which fell out re-enters on its next balance change.
+CREATE TABLE top_holders
+(
+    -- Coin type, or address of the fungible asset's metadata object
+    asset_type               VARCHAR(5000) NOT NULL,
+    -- Account holding the coin store, or address of the fungible store object
+    storage_id               VARCHAR(66)   NOT NULL,
+    owner_address            VARCHAR(66),
+    amount                   NUMERIC       NOT NULL,
+    last_transaction_version uint_64       NOT NULL,
+
+    -- Default time columns
+    inserted_at              TIMESTAMP     NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (asset_type, storage_id)
+);
+
+CREATE INDEX top_holders_asset_type_amount_index ON top_holders (asset_type, amount DESC);
@@ -0,0 +1,22 @@
+-- Your SQL goes here
+-- Usage of each Move module per day, by the entry functions user transactions call. unique_senders is estimated from
+-- senders_sketch, a HyperLogLog of the senders, so adding a batch doesn't require every sender seen that day.
+CREATE TABLE module_daily_stats
+(
+    module_address   VARCHAR(66)  NOT NULL,
+    module_name      VARCHAR(255) NOT NULL,
+    date             DATE         NOT NULL,
+    num_transactions BIGINT       NOT NULL,
+    gas_used         uint_64      NOT NULL,
+    unique_senders   BIGINT       NOT NULL,
+    senders_sketch   BYTEA        NOT NULL,
+    last_version     uint_64      NOT NULL,
+
+    -- Default time columns
+    inserted_at      TIMESTAMP    NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (module_address, module_name, date)
+);
+
+CREATE INDEX module_daily_stats_date_index ON module_daily_stats (date);
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+-- Keys of the read API's clients, added and revoked without restarting the API. Only SHA-256 hashes of the keys are
+-- stored; requests_per_minute overrides the API's default rate limit for the key.
+CREATE TABLE api_keys
+(
+    name                VARCHAR(255) NOT NULL,
+    key_hash            VARCHAR(64)  NOT NULL,
+    requests_per_minute BIGINT,
+    is_revoked          BOOLEAN      NOT NULL DEFAULT FALSE,
+
+    -- Default time columns
+    inserted_at         TIMESTAMP    NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (name),
+    UNIQUE (key_hash)
+);
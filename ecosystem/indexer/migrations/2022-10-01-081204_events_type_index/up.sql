@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Events of a type, most recent first (see `queries::get_events_by_type`)
+CREATE INDEX IF NOT EXISTS ev_type_timestamp_index ON events (type, transaction_timestamp DESC);
@@ -0,0 +1,22 @@
+-- Your SQL goes here
+-- Actions operators took on running indexers (ex: disabling a processor) or through the CLI (ex: backfills), for
+-- compliance. Rows are only ever inserted, and survive resets of the indexed data.
+CREATE TABLE operator_audit_log
+(
+    id             BIGSERIAL    NOT NULL,
+    -- Who took the action, as they identified themselves (ex: the X-Operator header of an admin request)
+    actor          VARCHAR(255) NOT NULL,
+    -- Where an admin request came from, NULL for CLI actions
+    remote_address VARCHAR(255),
+    -- ex: "processor.disable", "backfill"
+    action         VARCHAR(255) NOT NULL,
+    parameters     JSONB        NOT NULL,
+
+    -- Default time columns
+    inserted_at    TIMESTAMP    NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX oal_inserted_at_index ON operator_audit_log (inserted_at);
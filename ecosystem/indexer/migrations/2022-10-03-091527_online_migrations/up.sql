@@ -0,0 +1,22 @@
+-- Your SQL goes here
+-- Progress of the table rewrites `migrate-online` runs, so an interrupted rewrite resumes where it stopped. Keys are
+-- the JSON arrays of a row's primary key columns, as text.
+CREATE TABLE online_migrations
+(
+    table_name       VARCHAR(255) NOT NULL,
+    -- The rewritten table, swapped in for table_name at the cutover
+    shadow_table     VARCHAR(255) NOT NULL,
+    -- Key of the last row which existed before writes started being mirrored to the shadow table, NULL if none did
+    backfill_end     JSONB,
+    -- Key of the last row copied so far, NULL before the first chunk
+    backfilled_up_to JSONB,
+    -- backfilling, backfilled or cut_over
+    state            VARCHAR(50)  NOT NULL,
+
+    -- Default time columns
+    started_at       TIMESTAMP    NOT NULL DEFAULT NOW(),
+    updated_at       TIMESTAMP    NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (table_name)
+);
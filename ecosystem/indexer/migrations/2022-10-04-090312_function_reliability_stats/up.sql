@@ -0,0 +1,23 @@
+-- Your SQL goes here
+-- Outcomes of the user transactions calling each entry function per day. abort_codes counts the failed ones which
+-- aborted, by abort location and code (ex: {"0x1::coin: EINSUFFICIENT_BALANCE(0x10006)": 3}), so the most frequent
+-- aborts of a module are a sum over its functions' rows.
+CREATE TABLE function_reliability_stats
+(
+    module_address   VARCHAR(66)  NOT NULL,
+    module_name      VARCHAR(255) NOT NULL,
+    function_name    VARCHAR(255) NOT NULL,
+    date             DATE         NOT NULL,
+    num_transactions BIGINT       NOT NULL,
+    num_failed       BIGINT       NOT NULL,
+    abort_codes      jsonb        NOT NULL,
+    last_version     uint_64      NOT NULL,
+
+    -- Default time columns
+    inserted_at      TIMESTAMP    NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (module_address, module_name, function_name, date)
+);
+
+CREATE INDEX function_reliability_stats_date_index ON function_reliability_stats (date);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+-- Transactions as fetched from the node, written by the default processor with `--archive-raw`, so processors added
+-- later can be backfilled from the DB. data is encoded as encoding says ('json+gzip': the REST API's JSON, gzipped).
+CREATE TABLE raw_transactions
+(
+    version     uint_64     NOT NULL,
+    hash        VARCHAR(66) NOT NULL,
+    encoding    VARCHAR(50) NOT NULL,
+    data        BYTEA       NOT NULL,
+
+    -- Default time columns
+    inserted_at TIMESTAMP   NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (version)
+);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+-- Every event type emitted so far, with the first and last versions emitting it and the number of events, so users can
+-- discover what contracts emit before configuring filter processors. last_version is the rollup's watermark.
+CREATE TABLE event_types_seen
+(
+    type          TEXT      NOT NULL,
+    first_version uint_64   NOT NULL,
+    last_version  uint_64   NOT NULL,
+    num_events    BIGINT    NOT NULL,
+
+    -- Default time columns
+    inserted_at   TIMESTAMP NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (type)
+);
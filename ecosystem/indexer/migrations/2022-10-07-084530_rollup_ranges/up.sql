@@ -0,0 +1,28 @@
+-- Your SQL goes here
+-- Version ranges (inclusive) folded into each rollup, so batches can be folded in any order without double counting.
+-- Adjacent ranges are merged, so a rollup without gaps has a single row.
+CREATE TABLE rollup_ranges
+(
+    rollup        VARCHAR(50) NOT NULL,
+    start_version uint_64     NOT NULL,
+    end_version   uint_64     NOT NULL,
+
+    -- Default time columns
+    inserted_at   TIMESTAMP   NOT NULL DEFAULT NOW(),
+
+    -- Constraints
+    PRIMARY KEY (rollup, start_version)
+);
+
+-- Rollups used to be folded in version order, so every version up to the highest one folded into their buckets counts
+-- as folded
+INSERT INTO rollup_ranges (rollup, start_version, end_version)
+SELECT 'minute_transactions', 0, MAX(last_version) FROM minute_transaction_rollups HAVING COUNT(*) > 0
+UNION ALL
+SELECT 'hourly_activity', 0, MAX(last_version) FROM hourly_activity_rollups HAVING COUNT(*) > 0
+UNION ALL
+SELECT 'module_daily_stats', 0, MAX(last_version) FROM module_daily_stats HAVING COUNT(*) > 0
+UNION ALL
+SELECT 'function_reliability_stats', 0, MAX(last_version) FROM function_reliability_stats HAVING COUNT(*) > 0
+UNION ALL
+SELECT 'event_types_seen', 0, MAX(last_version) FROM event_types_seen HAVING COUNT(*) > 0;
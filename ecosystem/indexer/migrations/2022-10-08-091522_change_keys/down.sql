@@ -0,0 +1,18 @@
+-- This file should undo anything in `up.sql`
+-- Restores the original primary keys, whether swapped by the migration or by `migrate-change-keys`
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_constraint WHERE conname = 'events_key_sequence_number_unique') THEN
+        ALTER TABLE events DROP CONSTRAINT events_pkey;
+        ALTER TABLE events DROP CONSTRAINT events_key_sequence_number_unique;
+        ALTER TABLE events ADD CONSTRAINT events_pkey PRIMARY KEY (key, sequence_number);
+        ALTER TABLE events ALTER COLUMN event_index DROP NOT NULL;
+    END IF;
+    IF EXISTS (SELECT 1 FROM pg_constraint WHERE conname = 'write_set_changes_transaction_hash_hash_unique') THEN
+        ALTER TABLE write_set_changes DROP CONSTRAINT write_set_changes_pkey;
+        ALTER TABLE write_set_changes DROP CONSTRAINT write_set_changes_transaction_hash_hash_unique;
+        ALTER TABLE write_set_changes ADD CONSTRAINT write_set_changes_pkey PRIMARY KEY (transaction_hash, hash);
+        ALTER TABLE write_set_changes ALTER COLUMN write_set_change_index DROP NOT NULL;
+    END IF;
+END
+$$;
@@ -0,0 +1,24 @@
+-- Your SQL goes here
+-- Makes each event's and write set change's position within its transaction part of its primary key, keeping the
+-- original key unique (the processors' upserts conflict on it). Only done here on a database with nothing indexed yet,
+-- where it's instant: on a populated one, rows indexed before the positions were recorded must be re-processed first,
+-- and the keys are then swapped online with `migrate-change-keys` (see `change_keys`), rather than blocking writes
+-- while the new indexes are built.
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM events) THEN
+        ALTER TABLE events ALTER COLUMN event_index SET NOT NULL;
+        ALTER TABLE events DROP CONSTRAINT events_pkey;
+        ALTER TABLE events ADD CONSTRAINT events_pkey PRIMARY KEY (transaction_hash, event_index);
+        ALTER TABLE events ADD CONSTRAINT events_key_sequence_number_unique UNIQUE (key, sequence_number);
+    END IF;
+    IF NOT EXISTS (SELECT 1 FROM write_set_changes) THEN
+        ALTER TABLE write_set_changes ALTER COLUMN write_set_change_index SET NOT NULL;
+        ALTER TABLE write_set_changes DROP CONSTRAINT write_set_changes_pkey;
+        ALTER TABLE write_set_changes
+            ADD CONSTRAINT write_set_changes_pkey PRIMARY KEY (transaction_hash, write_set_change_index);
+        ALTER TABLE write_set_changes
+            ADD CONSTRAINT write_set_changes_transaction_hash_hash_unique UNIQUE (transaction_hash, hash);
+    END IF;
+END
+$$;
@@ -0,0 +1,597 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Minimal AWS request signing (Signature Version 4), used to generate RDS IAM authentication tokens and to call AWS
+//! JSON APIs (ex: Secrets Manager, DynamoDB, Kinesis).
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{fmt, sync::Mutex, time::Duration};
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+/// Serves the credentials of ECS tasks' roles, at `AWS_CONTAINER_CREDENTIALS_RELATIVE_URI`
+const CONTAINER_CREDENTIALS_HOST: &str = "http://169.254.170.2";
+/// Credentials of the task's role are refreshed this long before they expire
+const CREDENTIALS_REFRESH_MARGIN_SECS: i64 = 5 * 60;
+
+/// RDS IAM authentication tokens are valid for 15 minutes
+pub const RDS_AUTH_TOKEN_EXPIRY_SECS: u64 = 15 * 60;
+
+#[derive(Clone)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl std::fmt::Debug for AwsCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "AwsCredentials {{ access_key_id: {:?} }}",
+            self.access_key_id
+        )
+    }
+}
+
+impl AwsCredentials {
+    /// Reads `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and (optionally) `AWS_SESSION_TOKEN`
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            access_key_id: std::env::var("AWS_ACCESS_KEY_ID")
+                .context("AWS_ACCESS_KEY_ID must be set")?,
+            secret_access_key: std::env::var("AWS_SECRET_ACCESS_KEY")
+                .context("AWS_SECRET_ACCESS_KEY must be set")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+
+    /// Fetches the credentials of the ECS task's role (ex: on Fargate) from the container credentials endpoint,
+    /// returning when they expire
+    pub async fn from_container() -> Result<(Self, DateTime<Utc>)> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct ContainerCredentials {
+            access_key_id: String,
+            secret_access_key: String,
+            token: String,
+            expiration: DateTime<Utc>,
+        }
+
+        let url = match std::env::var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI") {
+            Ok(uri) => format!("{}{}", CONTAINER_CREDENTIALS_HOST, uri),
+            Err(_) => std::env::var("AWS_CONTAINER_CREDENTIALS_FULL_URI").context(
+                "AWS_CONTAINER_CREDENTIALS_RELATIVE_URI or AWS_CONTAINER_CREDENTIALS_FULL_URI must be set",
+            )?,
+        };
+        let mut request = reqwest::Client::new().get(&url);
+        if let Ok(token) = std::env::var("AWS_CONTAINER_AUTHORIZATION_TOKEN") {
+            request = request.header("Authorization", token);
+        }
+        let credentials: ContainerCredentials = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Could not fetch container credentials from {}", url))?
+            .json()
+            .await
+            .context("Could not parse container credentials")?;
+        Ok((
+            Self {
+                access_key_id: credentials.access_key_id,
+                secret_access_key: credentials.secret_access_key,
+                session_token: Some(credentials.token),
+            },
+            credentials.expiration,
+        ))
+    }
+}
+
+/// Credentials from the environment (ex: on Lambda), or else the ECS task's role (ex: on Fargate), which are cached
+/// until shortly before they expire
+#[derive(Debug, Default)]
+pub struct CredentialsProvider {
+    container: Mutex<Option<(AwsCredentials, DateTime<Utc>)>>,
+}
+
+impl CredentialsProvider {
+    pub async fn credentials(&self) -> Result<AwsCredentials> {
+        if let Ok(credentials) = AwsCredentials::from_env() {
+            return Ok(credentials);
+        }
+        if let Some((credentials, expiration)) = &*self.container.lock().unwrap() {
+            if *expiration > Utc::now() + chrono::Duration::seconds(CREDENTIALS_REFRESH_MARGIN_SECS)
+            {
+                return Ok(credentials.clone());
+            }
+        }
+        let (credentials, expiration) = AwsCredentials::from_container()
+            .await
+            .context("No AWS credentials in the environment, nor from the ECS task's role")?;
+        *self.container.lock().unwrap() = Some((credentials.clone(), expiration));
+        Ok(credentials)
+    }
+
+    /// `credentials`, for synchronous callers (ex: the connection pool's). Fetching the task role's credentials runs
+    /// on a runtime of its own in another thread, as the caller may be on an async runtime's thread.
+    pub fn blocking_credentials(&self) -> Result<AwsCredentials> {
+        if let Ok(credentials) = AwsCredentials::from_env() {
+            return Ok(credentials);
+        }
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .context("Could not start a runtime to fetch AWS credentials")?
+                        .block_on(self.credentials())
+                })
+                .join()
+                .expect("Fetching AWS credentials panicked")
+        })
+    }
+}
+
+/// Percent-encodes everything but unreserved characters, as SigV4 requires
+pub fn uri_encode(input: &str) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Derives the key for `date` (YYYYMMDD), `region` and `service` and signs `string_to_sign` with it
+pub fn signature(
+    credentials: &AwsCredentials,
+    date: &str,
+    region: &str,
+    service: &str,
+    string_to_sign: &str,
+) -> String {
+    let key = hmac_sha256(
+        format!("AWS4{}", credentials.secret_access_key).as_bytes(),
+        date,
+    );
+    let key = hmac_sha256(&key, region);
+    let key = hmac_sha256(&key, service);
+    let key = hmac_sha256(&key, "aws4_request");
+    hex::encode(hmac_sha256(&key, string_to_sign))
+}
+
+/// Generates the token used as the password of `user` when connecting to an RDS instance at `host:port` with IAM
+/// authentication. This is a presigned `connect` request, without the scheme.
+pub fn rds_auth_token(
+    credentials: &AwsCredentials,
+    region: &str,
+    host: &str,
+    port: u16,
+    user: &str,
+    now: chrono::DateTime<chrono::Utc>,
+) -> String {
+    let service = "rds-db";
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let endpoint = format!("{}:{}", host, port);
+
+    let mut params = vec![
+        ("Action".to_string(), "connect".to_string()),
+        ("DBUser".to_string(), user.to_string()),
+        ("X-Amz-Algorithm".to_string(), ALGORITHM.to_string()),
+        (
+            "X-Amz-Credential".to_string(),
+            format!("{}/{}", credentials.access_key_id, scope),
+        ),
+        ("X-Amz-Date".to_string(), amz_date.clone()),
+        (
+            "X-Amz-Expires".to_string(),
+            RDS_AUTH_TOKEN_EXPIRY_SECS.to_string(),
+        ),
+        ("X-Amz-SignedHeaders".to_string(), "host".to_string()),
+    ];
+    if let Some(session_token) = &credentials.session_token {
+        params.push(("X-Amz-Security-Token".to_string(), session_token.clone()));
+    }
+    params.sort();
+    let query = params
+        .iter()
+        .map(|(key, value)| format!("{}={}", uri_encode(key), uri_encode(value)))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let canonical_request = format!(
+        "GET\n/\n{}\nhost:{}\n\nhost\n{}",
+        query,
+        endpoint,
+        sha256_hex(b"")
+    );
+    let string_to_sign = format!(
+        "{}\n{}\n{}\n{}",
+        ALGORITHM,
+        amz_date,
+        scope,
+        sha256_hex(canonical_request.as_bytes())
+    );
+    format!(
+        "{}/?{}&X-Amz-Signature={}",
+        endpoint,
+        query,
+        signature(credentials, &date, region, service, &string_to_sign)
+    )
+}
+
+/// Signs a POST of `body` to an AWS JSON API at `host` (ex: "secretsmanager.us-east-1.amazonaws.com"), returning
+/// the headers to send: `headers` (ex: `X-Amz-Target`, `Content-Type`) plus the date, session token and authorization
+pub fn signed_post_headers(
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    host: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    now: chrono::DateTime<chrono::Utc>,
+) -> Vec<(String, String)> {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+
+    let mut headers: Vec<(String, String)> = headers
+        .iter()
+        .map(|(name, value)| (name.to_lowercase(), value.trim().to_string()))
+        .collect();
+    headers.push(("host".to_string(), host.to_string()));
+    headers.push(("x-amz-date".to_string(), amz_date.clone()));
+    if let Some(session_token) = &credentials.session_token {
+        headers.push(("x-amz-security-token".to_string(), session_token.clone()));
+    }
+    headers.sort();
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let canonical_request = format!(
+        "POST\n/\n\n{}\n{}\n{}",
+        canonical_headers,
+        signed_headers,
+        sha256_hex(body)
+    );
+    let string_to_sign = format!(
+        "{}\n{}\n{}\n{}",
+        ALGORITHM,
+        amz_date,
+        scope,
+        sha256_hex(canonical_request.as_bytes())
+    );
+    let authorization = format!(
+        "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+        ALGORITHM,
+        credentials.access_key_id,
+        scope,
+        signed_headers,
+        signature(credentials, &date, region, service, &string_to_sign)
+    );
+    headers.retain(|(name, _)| name != "host");
+    headers.push(("authorization".to_string(), authorization));
+    headers
+}
+
+/// An error returned by an AWS JSON API
+#[derive(Debug)]
+pub struct AwsApiError {
+    pub status: StatusCode,
+    /// ex: "ConditionalCheckFailedException"
+    pub error_type: String,
+    pub message: String,
+}
+
+impl AwsApiError {
+    /// Throttled and failed requests succeed when retried later
+    pub fn is_retryable(&self) -> bool {
+        self.status.is_server_error()
+            || matches!(
+                self.error_type.as_str(),
+                "ProvisionedThroughputExceededException"
+                    | "RequestLimitExceeded"
+                    | "ThrottlingException"
+                    | "ServiceUnavailableException"
+            )
+    }
+}
+
+impl fmt::Display for AwsApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "AWS returned {} ({}): {}",
+            self.status, self.error_type, self.message
+        )
+    }
+}
+
+impl std::error::Error for AwsApiError {}
+
+/// Calls the actions of an AWS JSON API (ex: DynamoDB, Kinesis) in a region
+#[derive(Debug)]
+pub struct AwsJsonClient {
+    client: reqwest::Client,
+    credentials: CredentialsProvider,
+    /// ex: "dynamodb"
+    service: &'static str,
+    /// Prefix of the actions' `X-Amz-Target`, ex: "DynamoDB_20120810"
+    target_prefix: &'static str,
+    /// ex: "application/x-amz-json-1.0"
+    content_type: &'static str,
+    region: String,
+    url: String,
+    host: String,
+}
+
+impl AwsJsonClient {
+    /// Sends requests to `endpoint` if given (ex: "http://localhost:8000" for DynamoDB Local), or else to the region's
+    pub fn new(
+        service: &'static str,
+        target_prefix: &'static str,
+        content_type: &'static str,
+        region: String,
+        endpoint: Option<&str>,
+        request_timeout: Duration,
+    ) -> Result<Self> {
+        let (url, host) = match endpoint {
+            Some(endpoint) => {
+                let url = url::Url::parse(endpoint)
+                    .with_context(|| format!("Invalid {} endpoint {}", service, endpoint))?;
+                let host = url
+                    .host_str()
+                    .with_context(|| format!("Invalid {} endpoint {}", service, endpoint))?;
+                let host = match url.port() {
+                    Some(port) => format!("{}:{}", host, port),
+                    None => host.to_string(),
+                };
+                (endpoint.to_string(), host)
+            }
+            None => {
+                let host = format!("{}.{}.amazonaws.com", service, region);
+                (format!("https://{}/", host), host)
+            }
+        };
+        let client = reqwest::Client::builder()
+            .timeout(request_timeout)
+            .build()
+            .with_context(|| format!("Failed to build the {} HTTP client", service))?;
+        Ok(Self {
+            client,
+            credentials: CredentialsProvider::default(),
+            service,
+            target_prefix,
+            content_type,
+            region,
+            url,
+            host,
+        })
+    }
+
+    /// Calls `action` once. Errors returned by the API are `AwsApiError`s.
+    pub async fn try_call(&self, action: &str, body: &str) -> Result<Value> {
+        let target = format!("{}.{}", self.target_prefix, action);
+        let headers = signed_post_headers(
+            &self.credentials.credentials().await?,
+            &self.region,
+            self.service,
+            &self.host,
+            &[
+                ("Content-Type", self.content_type),
+                ("X-Amz-Target", &target),
+            ],
+            body.as_bytes(),
+            Utc::now(),
+        );
+        let mut request = self.client.post(&self.url);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        let response = request
+            .body(body.to_string())
+            .send()
+            .await
+            .with_context(|| format!("{} failed", target))?;
+        let status = response.status();
+        let response: Value = response
+            .json()
+            .await
+            .with_context(|| format!("Failed to parse the response to {}", target))?;
+        if !status.is_success() {
+            return Err(AwsApiError {
+                status,
+                // ex: "com.amazonaws.dynamodb.v20120810#ConditionalCheckFailedException"
+                error_type: response["__type"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .rsplit('#')
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+                message: response["message"]
+                    .as_str()
+                    .or_else(|| response["Message"].as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+            }
+            .into());
+        }
+        Ok(response)
+    }
+
+    /// Calls `action`, retrying throttled and failed requests up to `max_attempts` times, with exponential backoff
+    pub async fn call(&self, action: &str, body: &Value, max_attempts: u32) -> Result<Value> {
+        let body = body.to_string();
+        let mut attempt = 1;
+        loop {
+            match self.try_call(action, &body).await {
+                Err(err)
+                    if attempt < max_attempts
+                        && err
+                            .downcast_ref::<AwsApiError>()
+                            .map_or(false, AwsApiError::is_retryable) =>
+                {
+                    tokio::time::sleep(Duration::from_millis(50 << attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_uri_encode() {
+        assert_eq!(
+            uri_encode("AKID/20220910/us-east-1"),
+            "AKID%2F20220910%2Fus-east-1"
+        );
+        assert_eq!(uri_encode("a b=c~"), "a%20b%3Dc~");
+    }
+
+    /// The examples of AWS's documentation and SigV4 test suite, with AWS's example credentials
+    #[test]
+    fn test_signature_known_answers() {
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        };
+        // get-vanilla, of the SigV4 test suite
+        let canonical_request = format!(
+            "GET\n/\n\nhost:example.amazonaws.com\nx-amz-date:20150830T123600Z\n\nhost;x-amz-date\n{}",
+            sha256_hex(b"")
+        );
+        assert_eq!(
+            sha256_hex(canonical_request.as_bytes()),
+            "bb579772317eb040ac9ed261061d46c1f17a8133879d6129b6e1c25292927e63"
+        );
+        assert_eq!(
+            signature(
+                &credentials,
+                "20150830",
+                "us-east-1",
+                "service",
+                "AWS4-HMAC-SHA256\n20150830T123600Z\n20150830/us-east-1/service/aws4_request\n\
+                 bb579772317eb040ac9ed261061d46c1f17a8133879d6129b6e1c25292927e63"
+            ),
+            "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+        // The IAM ListUsers request of the signing process' documentation
+        assert_eq!(
+            signature(
+                &credentials,
+                "20150830",
+                "us-east-1",
+                "iam",
+                "AWS4-HMAC-SHA256\n20150830T123600Z\n20150830/us-east-1/iam/aws4_request\n\
+                 f536975d06c0309214f805bb90ccff089219ecd68b2577efef23edd43b7e1a59"
+            ),
+            "5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+
+    #[test]
+    fn test_signed_post_headers() {
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: Some("session".to_string()),
+        };
+        let now = chrono::Utc.ymd(2022, 9, 10).and_hms(12, 0, 0);
+        let headers = signed_post_headers(
+            &credentials,
+            "us-east-1",
+            "secretsmanager",
+            "secretsmanager.us-east-1.amazonaws.com",
+            &[("X-Amz-Target", "secretsmanager.GetSecretValue")],
+            b"{}",
+            now,
+        );
+        let names: Vec<&str> = headers.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "x-amz-date",
+                "x-amz-security-token",
+                "x-amz-target",
+                "authorization"
+            ]
+        );
+        let authorization = &headers[3].1;
+        assert!(authorization.starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20220910/us-east-1/secretsmanager/aws4_request, \
+             SignedHeaders=host;x-amz-date;x-amz-security-token;x-amz-target, Signature="
+        ));
+    }
+
+    #[test]
+    fn test_rds_auth_token_shape() {
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        };
+        let now = chrono::Utc.ymd(2022, 9, 10).and_hms(12, 0, 0);
+        let token = rds_auth_token(
+            &credentials,
+            "us-east-1",
+            "db.example.com",
+            5432,
+            "indexer",
+            now,
+        );
+        assert!(token.starts_with("db.example.com:5432/?Action=connect&DBUser=indexer&"));
+        assert!(token.contains(
+            "X-Amz-Credential=AKIDEXAMPLE%2F20220910%2Fus-east-1%2Frds-db%2Faws4_request"
+        ));
+        assert!(token.contains("X-Amz-Date=20220910T120000Z&X-Amz-Expires=900"));
+        // Signing is deterministic
+        assert_eq!(
+            token,
+            rds_auth_token(
+                &credentials,
+                "us-east-1",
+                "db.example.com",
+                5432,
+                "indexer",
+                now
+            )
+        );
+    }
+}
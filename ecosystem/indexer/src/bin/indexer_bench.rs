@@ -0,0 +1,336 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Replays recorded transactions through each processor's pipeline, against a database created for the run and
+//! dropped afterwards, and reports throughput and batch latency. Meant to compare performance changes on the same
+//! fixture and Postgres server.
+#![forbid(unsafe_code)]
+
+use anyhow::Context;
+use aptos_indexer::indexer::{
+    builder::IndexerBuilder,
+    fetcher::TransactionFetcherTrait,
+    pipeline::{start_pipeline, PipelineConfig, ProcessedBatch},
+    stream::load_transactions,
+};
+use aptos_rest_client::{State, Transaction};
+use clap::Parser;
+use diesel::{
+    dsl::sql,
+    pg::PgConnection,
+    sql_types::{Array, BigInt, Text},
+    Connection, RunQueryDsl,
+};
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use url::Url;
+
+#[derive(Debug, Parser)]
+#[clap(author, version, about, long_about = None)]
+struct BenchArgs {
+    /// Directory of JSON files, each an array of API transactions (ex: `testdata/model_conversion/transactions`)
+    #[clap(long)]
+    fixture: PathBuf,
+
+    /// Processors to benchmark, one after the other, ex: "default_processor,token_processor"
+    #[clap(long, use_value_delimiter = true, default_value = "default_processor")]
+    processors: Vec<String>,
+
+    /// Postgres server uri. A database is created on it for the run, and dropped afterwards.
+    #[clap(long, env = "INDEXER_DATABASE_URL")]
+    pg_uri: String,
+
+    /// Maximum number of connections in the Postgres pool
+    #[clap(long, default_value_t = 10)]
+    pg_pool_max_size: u32,
+
+    /// Number of versions the replayed fetcher returns per fetch, like the node's page size
+    #[clap(long, default_value_t = 500)]
+    fetch_size: usize,
+
+    /// Number of versions per processing batch
+    #[clap(long, default_value_t = 100)]
+    batch_size: u8,
+
+    /// Maximum number of batches each processor converts and writes concurrently
+    #[clap(long, default_value_t = 50)]
+    process_concurrency: usize,
+
+    /// Keep the database after the run, ex: to inspect what was written
+    #[clap(long)]
+    keep_database: bool,
+}
+
+/// Serves recorded transactions instead of fetching them from a node. Once they've all been served, fetching
+/// waits forever, like a fetcher caught up with the chain.
+struct ReplayFetcher {
+    transactions: Arc<Vec<Transaction>>,
+    fetch_size: usize,
+    position: usize,
+}
+
+#[async_trait::async_trait]
+impl TransactionFetcherTrait for ReplayFetcher {
+    async fn fetch_next_batch(&mut self) -> Vec<Transaction> {
+        if self.position >= self.transactions.len() {
+            futures::future::pending::<()>().await;
+        }
+        let end = (self.position + self.fetch_size).min(self.transactions.len());
+        let batch = self.transactions[self.position..end].to_vec();
+        self.position = end;
+        batch
+    }
+
+    async fn fetch_version(&self, version: u64) -> Transaction {
+        self.transactions
+            .iter()
+            .find(|txn| txn.version() == Some(version))
+            .cloned()
+            .unwrap_or_else(|| panic!("Version {} isn't in the fixture", version))
+    }
+
+    async fn fetch_ledger_info(&mut self) -> State {
+        State {
+            chain_id: 4,
+            epoch: 0,
+            version: self
+                .transactions
+                .last()
+                .and_then(|txn| txn.version())
+                .unwrap_or(0),
+            timestamp_usecs: 0,
+            oldest_ledger_version: 0,
+            oldest_block_height: 0,
+            block_height: 0,
+        }
+    }
+
+    async fn set_version(&mut self, version: u64) {
+        self.position = self
+            .transactions
+            .iter()
+            .position(|txn| txn.version().unwrap_or(0) >= version)
+            .unwrap_or(self.transactions.len());
+    }
+
+    async fn start(&mut self) {}
+}
+
+/// A database created on the server for the run, dropped (unless kept) when this is dropped
+struct ThrowawayDatabase {
+    server_uri: String,
+    name: String,
+    uri: String,
+    keep: bool,
+}
+
+impl ThrowawayDatabase {
+    fn create(server_uri: &str, keep: bool) -> anyhow::Result<Self> {
+        let name = format!("indexer_bench_{}", std::process::id());
+        let conn = PgConnection::establish(server_uri)
+            .context("Could not connect to the Postgres server")?;
+        diesel::sql_query(format!("CREATE DATABASE {}", name))
+            .execute(&conn)
+            .context("Could not create the benchmark database")?;
+        let mut uri = Url::parse(server_uri)?;
+        uri.set_path(&name);
+        println!("Created database {}", name);
+        Ok(Self {
+            server_uri: server_uri.to_string(),
+            name,
+            uri: uri.to_string(),
+            keep,
+        })
+    }
+
+    fn drop_database(&self) -> anyhow::Result<()> {
+        let conn = PgConnection::establish(&self.server_uri)?;
+        // The pipelines' tasks still hold connections
+        diesel::sql_query(format!(
+            "SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE datname = '{}'",
+            self.name
+        ))
+        .execute(&conn)?;
+        diesel::sql_query(format!("DROP DATABASE IF EXISTS {}", self.name)).execute(&conn)?;
+        Ok(())
+    }
+}
+
+impl Drop for ThrowawayDatabase {
+    fn drop(&mut self) {
+        if self.keep {
+            println!("Kept database {}", self.name);
+        } else if let Err(e) = self.drop_database() {
+            eprintln!("Could not drop database {}: {:?}", self.name, e);
+        }
+    }
+}
+
+/// Number of rows in each table of the database
+fn count_rows(conn: &PgConnection) -> anyhow::Result<BTreeMap<String, i64>> {
+    let tables = diesel::select(sql::<Array<Text>>(
+        "ARRAY(SELECT table_name::text FROM information_schema.tables \
+         WHERE table_schema = 'public' AND table_type = 'BASE TABLE' \
+         AND table_name != '__diesel_schema_migrations')",
+    ))
+    .get_result::<Vec<String>>(conn)?;
+    tables
+        .into_iter()
+        .map(|table| {
+            let count = diesel::select(sql::<BigInt>(&format!("(SELECT count(*) FROM {})", table)))
+                .get_result::<i64>(conn)?;
+            Ok((table, count))
+        })
+        .collect()
+}
+
+/// How many processing batches the pipeline splits `num_transactions` into: each fetch is split into batches
+fn num_batches(num_transactions: usize, fetch_size: usize, batch_size: usize) -> usize {
+    let batches_per_fetch = |num: usize| (num + batch_size - 1) / batch_size;
+    (num_transactions / fetch_size) * batches_per_fetch(fetch_size)
+        + batches_per_fetch(num_transactions % fetch_size)
+}
+
+/// The latency under which a `quantile` of the (sorted) latencies are
+fn percentile(sorted_latencies: &[Duration], quantile: f64) -> Duration {
+    if sorted_latencies.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (quantile * sorted_latencies.len() as f64).ceil() as usize;
+    sorted_latencies[rank.clamp(1, sorted_latencies.len()) - 1]
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    aptos_logger::Logger::new().init();
+    let args = BenchArgs::parse();
+    let transactions = Arc::new(load_transactions(&args.fixture)?);
+    println!(
+        "Loaded {} transactions from {:?}",
+        transactions.len(),
+        args.fixture
+    );
+
+    let database = ThrowawayDatabase::create(&args.pg_uri, args.keep_database)?;
+    let indexer = IndexerBuilder::new(database.uri.clone())
+        .database_config(aptos_indexer::database::DatabaseConfig {
+            max_size: Some(args.pg_pool_max_size),
+            ..Default::default()
+        })
+        // Never contacted, transactions come from the fixture
+        .node_url("http://localhost:8080")
+        .processors(args.processors.clone())
+        .build()?;
+    indexer.run_migrations();
+    let conn = PgConnection::establish(&database.uri)?;
+    let fetch_size = args.fetch_size.max(1);
+    let expected_batches = num_batches(
+        transactions.len(),
+        fetch_size,
+        (args.batch_size as usize).max(1),
+    );
+
+    for (processor_name, mut tailer) in indexer.tailers {
+        tailer.transaction_fetcher = Arc::new(Mutex::new(ReplayFetcher {
+            transactions: transactions.clone(),
+            fetch_size,
+            position: 0,
+        }));
+        let rows_before = count_rows(&conn)?;
+        let config = PipelineConfig {
+            batch_size: args.batch_size,
+            process_concurrency: args.process_concurrency,
+            ..Default::default()
+        };
+
+        let start = Instant::now();
+        let mut results = start_pipeline(tailer, config);
+        let mut latencies = vec![];
+        let mut num_failed_batches = 0;
+        while latencies.len() < expected_batches {
+            let ProcessedBatch {
+                result,
+                processing_time,
+            } = results
+                .recv()
+                .await
+                .context("The pipeline stopped before processing the fixture")?;
+            if let Err(tpe) = result {
+                num_failed_batches += 1;
+                eprintln!("{} failed a batch: {:?}", processor_name, tpe);
+            }
+            latencies.push(processing_time);
+        }
+        let elapsed = start.elapsed();
+        drop(results);
+
+        latencies.sort();
+        let secs = elapsed.as_secs_f64();
+        println!("\n{}", processor_name);
+        println!(
+            "  {} versions in {:.2}s: {:.1} versions/sec",
+            transactions.len(),
+            secs,
+            transactions.len() as f64 / secs
+        );
+        println!(
+            "  {} batches ({} failed), latency p50 {:?}, p99 {:?}, max {:?}",
+            latencies.len(),
+            num_failed_batches,
+            percentile(&latencies, 0.5),
+            percentile(&latencies, 0.99),
+            latencies.last().copied().unwrap_or_default()
+        );
+        for (table, count) in count_rows(&conn)? {
+            let written = count - rows_before.get(&table).copied().unwrap_or(0);
+            if written > 0 {
+                println!(
+                    "  {}: {} rows, {:.1} rows/sec",
+                    table,
+                    written,
+                    written as f64 / secs
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let latencies: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&latencies, 0.5), Duration::from_millis(50));
+        assert_eq!(percentile(&latencies, 0.99), Duration::from_millis(99));
+        assert_eq!(percentile(&latencies, 1.0), Duration::from_millis(100));
+        assert_eq!(percentile(&latencies[..1], 0.99), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 0.99), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_num_batches() {
+        assert_eq!(num_batches(1000, 500, 100), 10);
+        assert_eq!(num_batches(1050, 500, 100), 11);
+        assert_eq!(num_batches(1050, 500, 30), 2 * 17 + 2);
+        assert_eq!(num_batches(5, 500, 100), 1);
+        assert_eq!(num_batches(0, 500, 100), 0);
+    }
+
+    #[test]
+    fn test_load_fixture() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/model_conversion/transactions");
+        let transactions = load_transactions(&dir).unwrap();
+        let versions: Vec<_> = transactions.iter().map(|txn| txn.version()).collect();
+        assert_eq!(versions.first(), Some(&Some(0)));
+        assert!(versions.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
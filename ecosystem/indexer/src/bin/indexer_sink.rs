@@ -0,0 +1,305 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Runs the `kafka_processor` without Postgres, ex: built with `--no-default-features --features kafka` for a
+//! Kafka-only deployment. Its statuses and the chain id are recorded in a local file (`--metadata-file`), in Spanner
+//! (`--spanner-database`, built with the `spanner` feature), in DynamoDB (`--dynamodb-table`, built with the
+//! `dynamodb` feature, ex: on Lambda or Fargate), or only kept in memory without any of them.
+#![forbid(unsafe_code)]
+
+use anyhow::Context;
+use aptos_indexer::{
+    counters::start_inspection_service,
+    indexer::{
+        audit_log::LoggingAuditLog,
+        metadata_handle::{MetadataBackend, MetadataHandle},
+        pipeline::{start_pipeline, PipelineConfig, ProcessedBatch},
+        tailer::Tailer,
+    },
+    processors::kafka_processor::{KafkaConfig, KafkaTransactionProcessor, NAME},
+    schema_registry::{SchemaRegistryConfig, SubjectNameStrategy},
+    secrets,
+};
+use aptos_logger::{error, info, warn};
+use clap::Parser;
+use std::{path::PathBuf, sync::Arc};
+
+#[derive(Debug, Parser)]
+#[clap(author, version, about, long_about = None)]
+struct SinkArgs {
+    /// URL of an Aptos node, ex: "https://fullnode.devnet.aptoslabs.com"
+    #[clap(long, env = "FULLNODE_URL")]
+    node_url: String,
+
+    /// Name the processor's statuses are recorded under, ex: to run several instances
+    #[clap(long, env = "PROCESSOR_NAME", default_value = NAME)]
+    processor_name: String,
+
+    /// JSON file the processor's statuses and the chain id are recorded in, created if it doesn't exist. Without it,
+    /// they're only kept in memory, so every start indexes from `--start-from-version` (or 0) again.
+    #[clap(long, env = "METADATA_FILE")]
+    metadata_file: Option<PathBuf>,
+
+    /// Google Cloud Spanner database the processor's statuses and the chain id are recorded in, instead of
+    /// `--metadata-file`, ex: "projects/my-project/instances/my-instance/databases/indexer". Create its tables with
+    /// `SPANNER_DDL` first.
+    #[cfg(feature = "spanner")]
+    #[clap(long, env = "SPANNER_DATABASE", conflicts_with = "metadata_file")]
+    spanner_database: Option<String>,
+
+    /// OAuth access token for `--spanner-database`. Tokens are requested from the GCE metadata server if unset.
+    #[cfg(feature = "spanner")]
+    #[clap(long, env = "SPANNER_ACCESS_TOKEN")]
+    spanner_access_token: Option<String>,
+
+    /// DynamoDB table the processor's statuses and the chain id are recorded in, instead of `--metadata-file`. Its
+    /// region is `AWS_REGION` (or `AWS_DEFAULT_REGION`).
+    #[cfg(feature = "dynamodb")]
+    #[clap(long, env = "DYNAMODB_TABLE", conflicts_with = "metadata_file")]
+    dynamodb_table: Option<String>,
+
+    /// Where to send `--dynamodb-table`'s requests instead of the region's endpoint, ex: "http://localhost:8000" for
+    /// DynamoDB Local
+    #[cfg(feature = "dynamodb")]
+    #[clap(long, env = "DYNAMODB_ENDPOINT")]
+    dynamodb_endpoint: Option<String>,
+
+    /// If set, will ignore the recorded statuses and start processing from the specified version
+    #[clap(long)]
+    start_from_version: Option<u64>,
+
+    /// If set and the node already pruned the version to resume from, start from the node's oldest version instead
+    /// of failing. The versions in between won't be indexed.
+    #[clap(long)]
+    force_jump_to_oldest: bool,
+
+    /// If set, will make sure that we're still indexing the right chain every 100K transactions
+    #[clap(long)]
+    check_chain_id: bool,
+
+    /// How many versions to fetch and process from a node in parallel
+    #[clap(long, default_value_t = 10)]
+    batch_size: u8,
+
+    /// Maximum number of batches produced concurrently
+    #[clap(long, default_value_t = 50)]
+    process_concurrency: usize,
+
+    /// Kafka topic the `kafka_processor` produces to
+    #[clap(long, env = "KAFKA_TOPIC")]
+    kafka_topic: String,
+
+    /// Base URL of the Confluent REST Proxy the `kafka_processor` produces through, ex: "http://localhost:8082"
+    #[clap(
+        long,
+        env = "KAFKA_REST_PROXY_URL",
+        default_value = "http://localhost:8082"
+    )]
+    kafka_rest_proxy_url: String,
+
+    /// REST Proxy user, if authentication is enabled (ex: a Confluent Cloud API key)
+    #[clap(long, env = "KAFKA_REST_PROXY_USER")]
+    kafka_rest_proxy_user: Option<String>,
+
+    #[clap(long, env = "KAFKA_REST_PROXY_PASSWORD")]
+    kafka_rest_proxy_password: Option<String>,
+
+    /// Base URL of a Confluent Schema Registry, ex: "http://localhost:8081". If set, the `kafka_processor` produces
+    /// Avro messages and registers their schema there.
+    #[clap(long, env = "KAFKA_SCHEMA_REGISTRY_URL")]
+    kafka_schema_registry_url: Option<String>,
+
+    /// Schema registry user, if authentication is enabled (ex: a Confluent Cloud API key)
+    #[clap(long, env = "KAFKA_SCHEMA_REGISTRY_USER")]
+    kafka_schema_registry_user: Option<String>,
+
+    #[clap(long, env = "KAFKA_SCHEMA_REGISTRY_PASSWORD")]
+    kafka_schema_registry_password: Option<String>,
+
+    /// `topic_name`, `record_name` or `topic_record_name`, as Confluent's serializers' `subject.name.strategy`
+    #[clap(long, default_value = "topic_name")]
+    kafka_subject_name_strategy: SubjectNameStrategy,
+
+    #[clap(long, env = "INSPECTION_URL", default_value = "localhost")]
+    inspection_url: String,
+
+    #[clap(long, env = "INSPECTION_PORT", default_value = "9105")]
+    inspection_port: u16,
+
+    /// How many versions to process before logging a "processed X versions" message.
+    /// Set to 0 to disable.
+    #[clap(long, default_value_t = 1000)]
+    emit_every: u64,
+}
+
+impl SinkArgs {
+    /// Resolves the passwords if they reference secrets (see `secrets`)
+    async fn resolve_secrets(&mut self) -> anyhow::Result<()> {
+        if let Some(kafka_rest_proxy_password) = &self.kafka_rest_proxy_password {
+            self.kafka_rest_proxy_password = Some(
+                secrets::resolve(kafka_rest_proxy_password)
+                    .await
+                    .context("Could not resolve --kafka-rest-proxy-password")?,
+            );
+        }
+        if let Some(kafka_schema_registry_password) = &self.kafka_schema_registry_password {
+            self.kafka_schema_registry_password = Some(
+                secrets::resolve(kafka_schema_registry_password)
+                    .await
+                    .context("Could not resolve --kafka-schema-registry-password")?,
+            );
+        }
+        #[cfg(feature = "spanner")]
+        if let Some(spanner_access_token) = &self.spanner_access_token {
+            self.spanner_access_token = Some(
+                secrets::resolve(spanner_access_token)
+                    .await
+                    .context("Could not resolve --spanner-access-token")?,
+            );
+        }
+        Ok(())
+    }
+
+    fn kafka_config(&self) -> KafkaConfig {
+        KafkaConfig {
+            user: self.kafka_rest_proxy_user.clone(),
+            password: self.kafka_rest_proxy_password.clone(),
+            schema_registry: self.kafka_schema_registry_url.as_ref().map(|url| {
+                SchemaRegistryConfig {
+                    url: url.clone(),
+                    user: self.kafka_schema_registry_user.clone(),
+                    password: self.kafka_schema_registry_password.clone(),
+                    subject_name_strategy: self.kafka_subject_name_strategy,
+                }
+            }),
+            ..KafkaConfig::new(self.kafka_rest_proxy_url.clone(), self.kafka_topic.clone())
+        }
+    }
+
+    fn metadata_backend(&self) -> MetadataBackend {
+        if let Some(path) = &self.metadata_file {
+            return MetadataBackend::File(path.clone());
+        }
+        #[cfg(feature = "spanner")]
+        if let Some(database) = &self.spanner_database {
+            return MetadataBackend::Spanner(
+                aptos_indexer::indexer::spanner_metadata::SpannerConfig {
+                    access_token: self.spanner_access_token.clone(),
+                    ..aptos_indexer::indexer::spanner_metadata::SpannerConfig::new(database.clone())
+                },
+            );
+        }
+        #[cfg(feature = "dynamodb")]
+        if let Some(table) = &self.dynamodb_table {
+            return MetadataBackend::DynamoDb {
+                table: table.clone(),
+                endpoint: self.dynamodb_endpoint.clone(),
+            };
+        }
+        warn!("No metadata backend: statuses are only kept in memory, and lost on restart");
+        MetadataBackend::InMemory
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    aptos_logger::Logger::new().init();
+    let mut args = SinkArgs::parse();
+    args.resolve_secrets().await?;
+    let processor_name = &args.processor_name;
+
+    let metadata_handle: Arc<dyn MetadataHandle> = args
+        .metadata_backend()
+        .open()
+        .context("Failed to open the metadata backend")?;
+    let processor = KafkaTransactionProcessor::new(args.kafka_config(), metadata_handle.clone())?
+        .with_name(processor_name.clone());
+    let tailer = Tailer::from_processor(&args.node_url, Arc::new(processor))
+        .context("Failed to instantiate tailer")?;
+
+    start_inspection_service(
+        args.inspection_url.as_str(),
+        args.inspection_port,
+        Some(metadata_handle),
+        [(processor_name.clone(), tailer.switch())]
+            .into_iter()
+            .collect(),
+        Arc::new(LoggingAuditLog),
+    );
+
+    if args.check_chain_id {
+        tailer
+            .check_or_update_chain_id()
+            .await
+            .context("Failed to check the chain id")?;
+    }
+    let start_version = match args.start_from_version {
+        Some(version) => version,
+        None => tailer
+            .get_start_version(processor_name)
+            .context("Cannot resume indexing")?
+            .unwrap_or_default(),
+    };
+    let start_version = tailer
+        .check_start_version(start_version, args.force_jump_to_oldest)
+        .await
+        .context("Cannot resume indexing")?;
+    info!(
+        processor_name = processor_name,
+        start_version = start_version,
+        "Setting starting version..."
+    );
+    tailer.set_fetcher_version(start_version).await;
+    tailer.transaction_fetcher.lock().await.start().await;
+
+    info!(processor_name = processor_name, "Indexing loop started!");
+    let mut results = start_pipeline(
+        tailer.clone(),
+        PipelineConfig {
+            batch_size: args.batch_size,
+            process_concurrency: args.process_concurrency,
+            ..PipelineConfig::default()
+        },
+    );
+    let mut num_processed = 0;
+    let mut version_to_check_chain_id = start_version + 100_000;
+    while let Some(ProcessedBatch { result, .. }) = results.recv().await {
+        let (start_version, end_version) = match &result {
+            Ok(processing_result) => (
+                processing_result.start_version,
+                processing_result.end_version,
+            ),
+            Err(tpe) => {
+                let (_, start_version, end_version, _) = tpe.inner();
+                error!(
+                    processor_name = processor_name,
+                    start_version = *start_version,
+                    end_version = *end_version,
+                    error = format!("{:?}", tpe),
+                    "Failed to produce versions"
+                );
+                (*start_version, *end_version)
+            }
+        };
+        if args.check_chain_id && end_version >= version_to_check_chain_id {
+            tailer
+                .check_or_update_chain_id()
+                .await
+                .context("Failed to check the chain id")?;
+            version_to_check_chain_id = end_version + 100_000;
+        }
+        let previously_processed = num_processed;
+        num_processed += end_version - start_version + 1;
+        if args.emit_every != 0
+            && num_processed / args.emit_every != previously_processed / args.emit_every
+        {
+            info!(
+                processor_name = processor_name,
+                version_processed = end_version,
+                num_processed = num_processed,
+                "Processed version"
+            );
+        }
+    }
+    Ok(())
+}
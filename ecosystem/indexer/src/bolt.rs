@@ -0,0 +1,558 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Minimal client of Neo4j's Bolt protocol (versions 4.1 to 4.4), used by the `neo4j_processor` to run auto-commit
+//! queries. Only built with the `neo4j` feature.
+//!
+//! Messages are PackStream structures, sent in chunks. Parameters and results are `serde_json::Value`s: integers are
+//! encoded as PackStream integers (so must fit in an `i64`), and structures (ex: nodes) are decoded as the list of
+//! their fields. Neither routing (`neo4j://` urls connect to the given server) nor explicit transactions are supported.
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use serde_json::{Map, Number, Value};
+use std::time::Duration;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+use url::Url;
+
+const HANDSHAKE_MAGIC: [u8; 4] = [0x60, 0x60, 0xB0, 0x17];
+/// Proposed versions, preferred first: 4.4, 4.3, 4.2 and 4.1
+const HANDSHAKE_VERSIONS: [[u8; 4]; 4] = [[0, 0, 4, 4], [0, 0, 3, 4], [0, 0, 2, 4], [0, 0, 1, 4]];
+const DEFAULT_PORT: u16 = 7687;
+const MAX_CHUNK_SIZE: usize = u16::MAX as usize;
+const USER_AGENT: &str = concat!("aptos-indexer/", env!("CARGO_PKG_VERSION"));
+
+// Request messages
+const HELLO: u8 = 0x01;
+const GOODBYE: u8 = 0x02;
+const RESET: u8 = 0x0F;
+const RUN: u8 = 0x10;
+const PULL: u8 = 0x3F;
+// Response messages
+const SUCCESS: u8 = 0x70;
+const RECORD: u8 = 0x71;
+const IGNORED: u8 = 0x7E;
+const FAILURE: u8 = 0x7F;
+
+/// Where and how to connect, parsed from a `bolt://`, `bolt+s://`, `neo4j://` or `neo4j+s://` url
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoltAddress {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+}
+
+impl BoltAddress {
+    pub fn parse(url: &str) -> Result<Self> {
+        let parsed = Url::parse(url).with_context(|| format!("Invalid Bolt url {:?}", url))?;
+        let tls = match parsed.scheme() {
+            "bolt" | "neo4j" => false,
+            "bolt+s" | "neo4j+s" => true,
+            scheme => bail!(
+                "Unsupported scheme {:?} in {:?}, expected bolt, bolt+s, neo4j or neo4j+s",
+                scheme,
+                url
+            ),
+        };
+        Ok(Self {
+            host: parsed
+                .host_str()
+                .with_context(|| format!("No host in {:?}", url))?
+                .to_string(),
+            port: parsed.port().unwrap_or(DEFAULT_PORT),
+            tls,
+        })
+    }
+}
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// A message received from the server
+#[derive(Debug, PartialEq)]
+struct Message {
+    signature: u8,
+    fields: Vec<Value>,
+}
+
+/// An authenticated connection to a Neo4j server
+pub struct BoltConnection {
+    stream: Box<dyn Stream>,
+}
+
+impl BoltConnection {
+    /// Connects to the server at `url`, authenticating as `user` if given
+    pub async fn connect(
+        url: &str,
+        user: Option<&str>,
+        password: Option<&str>,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let address = BoltAddress::parse(url)?;
+        tokio::time::timeout(timeout, Self::open(&address, user, password))
+            .await
+            .with_context(|| format!("Timed out connecting to {}", url))?
+            .with_context(|| format!("Failed to connect to {}", url))
+    }
+
+    async fn open(
+        address: &BoltAddress,
+        user: Option<&str>,
+        password: Option<&str>,
+    ) -> Result<Self> {
+        let tcp = TcpStream::connect((address.host.as_str(), address.port)).await?;
+        tcp.set_nodelay(true)?;
+        let stream: Box<dyn Stream> = if address.tls {
+            let connector = tokio_native_tls::native_tls::TlsConnector::new()?;
+            Box::new(
+                tokio_native_tls::TlsConnector::from(connector)
+                    .connect(&address.host, tcp)
+                    .await?,
+            )
+        } else {
+            Box::new(tcp)
+        };
+        let mut connection = Self { stream };
+        connection.handshake().await?;
+
+        let mut auth = Map::new();
+        auth.insert("user_agent".to_string(), USER_AGENT.into());
+        match user {
+            Some(user) => {
+                auth.insert("scheme".to_string(), "basic".into());
+                auth.insert("principal".to_string(), user.into());
+                auth.insert(
+                    "credentials".to_string(),
+                    password.unwrap_or_default().into(),
+                );
+            }
+            None => {
+                auth.insert("scheme".to_string(), "none".into());
+            }
+        }
+        connection.send(HELLO, &[Value::Object(auth)]).await?;
+        let response = connection.receive().await?;
+        ensure!(
+            response.signature == SUCCESS,
+            "Authentication failed: {}",
+            failure_message(&response)
+        );
+        Ok(connection)
+    }
+
+    async fn handshake(&mut self) -> Result<()> {
+        let mut request = HANDSHAKE_MAGIC.to_vec();
+        for version in HANDSHAKE_VERSIONS {
+            request.extend_from_slice(&version);
+        }
+        self.stream.write_all(&request).await?;
+        let mut version = [0; 4];
+        self.stream.read_exact(&mut version).await?;
+        ensure!(
+            version != [0; 4],
+            "The server supports none of Bolt 4.1 to 4.4"
+        );
+        Ok(())
+    }
+
+    /// Runs `query` with `parameters` (a map) in its own transaction on `database`, returning its records. The
+    /// transaction is rolled back if the query fails.
+    pub async fn run(
+        &mut self,
+        database: &str,
+        query: &str,
+        parameters: Value,
+    ) -> Result<Vec<Vec<Value>>> {
+        ensure!(parameters.is_object(), "Query parameters must be a map");
+        let mut extra = Map::new();
+        extra.insert("db".to_string(), database.into());
+        let mut pull = Map::new();
+        pull.insert("n".to_string(), (-1).into());
+        // RUN and PULL are pipelined: if RUN fails, PULL is IGNORED
+        self.send(RUN, &[query.into(), parameters, Value::Object(extra)])
+            .await?;
+        self.send(PULL, &[Value::Object(pull)]).await?;
+
+        let run = self.receive().await?;
+        let mut records = vec![];
+        let mut failure = (run.signature != SUCCESS).then(|| failure_message(&run));
+        loop {
+            let message = self.receive().await?;
+            match message.signature {
+                RECORD => match message.fields.into_iter().next() {
+                    Some(Value::Array(fields)) => records.push(fields),
+                    _ => bail!("Malformed record"),
+                },
+                SUCCESS | IGNORED => break,
+                _ => {
+                    failure.get_or_insert_with(|| failure_message(&message));
+                    break;
+                }
+            }
+        }
+        if let Some(failure) = failure {
+            // A failure puts the connection in a failed state, where requests are ignored until RESET
+            self.send(RESET, &[]).await?;
+            self.receive().await?;
+            bail!(failure);
+        }
+        Ok(records)
+    }
+
+    /// Tells the server the connection is closing
+    pub async fn close(mut self) -> Result<()> {
+        self.send(GOODBYE, &[]).await?;
+        self.stream.shutdown().await?;
+        Ok(())
+    }
+
+    async fn send(&mut self, signature: u8, fields: &[Value]) -> Result<()> {
+        let mut message = vec![];
+        pack_struct_header(&mut message, signature, fields.len())?;
+        for field in fields {
+            pack(&mut message, field)?;
+        }
+        let mut chunks = Vec::with_capacity(message.len() + 4);
+        for chunk in message.chunks(MAX_CHUNK_SIZE) {
+            chunks.extend_from_slice(&(chunk.len() as u16).to_be_bytes());
+            chunks.extend_from_slice(chunk);
+        }
+        chunks.extend_from_slice(&[0, 0]);
+        self.stream.write_all(&chunks).await?;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<Message> {
+        let mut message = vec![];
+        loop {
+            let size = self.stream.read_u16().await? as usize;
+            if size == 0 {
+                // Messages end with an empty chunk, but empty chunks before any data are keep-alives
+                if message.is_empty() {
+                    continue;
+                }
+                break;
+            }
+            let start = message.len();
+            message.resize(start + size, 0);
+            self.stream.read_exact(&mut message[start..]).await?;
+        }
+        unpack_message(&message)
+    }
+}
+
+/// The code and message of a FAILURE, or what else was received
+fn failure_message(message: &Message) -> String {
+    match (message.signature, message.fields.first()) {
+        (FAILURE, Some(metadata)) => format!(
+            "{}: {}",
+            metadata["code"].as_str().unwrap_or("unknown error"),
+            metadata["message"].as_str().unwrap_or_default()
+        ),
+        (signature, _) => format!("unexpected message 0x{:02X}", signature),
+    }
+}
+
+fn pack_header(buffer: &mut Vec<u8>, len: usize, tiny: u8, markers: [u8; 3]) -> Result<()> {
+    if len < 16 {
+        buffer.push(tiny | len as u8);
+    } else if len <= u8::MAX as usize {
+        buffer.push(markers[0]);
+        buffer.push(len as u8);
+    } else if len <= u16::MAX as usize {
+        buffer.push(markers[1]);
+        buffer.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        let len = u32::try_from(len).map_err(|_| anyhow!("{} items is too many", len))?;
+        buffer.push(markers[2]);
+        buffer.extend_from_slice(&len.to_be_bytes());
+    }
+    Ok(())
+}
+
+fn pack_struct_header(buffer: &mut Vec<u8>, signature: u8, fields: usize) -> Result<()> {
+    ensure!(fields < 16, "Structures have at most 15 fields");
+    buffer.push(0xB0 | fields as u8);
+    buffer.push(signature);
+    Ok(())
+}
+
+/// Appends the PackStream encoding of `value` to `buffer`
+fn pack(buffer: &mut Vec<u8>, value: &Value) -> Result<()> {
+    match value {
+        Value::Null => buffer.push(0xC0),
+        Value::Bool(false) => buffer.push(0xC2),
+        Value::Bool(true) => buffer.push(0xC3),
+        Value::Number(number) => match (number.as_i64(), number.as_f64()) {
+            (Some(int), _) => pack_int(buffer, int),
+            (None, Some(_)) if number.is_u64() => {
+                bail!("{} doesn't fit in a PackStream integer", number)
+            }
+            (None, Some(float)) => {
+                buffer.push(0xC1);
+                buffer.extend_from_slice(&float.to_be_bytes());
+            }
+            (None, None) => bail!("{} is not a PackStream number", number),
+        },
+        Value::String(string) => {
+            pack_header(buffer, string.len(), 0x80, [0xD0, 0xD1, 0xD2])?;
+            buffer.extend_from_slice(string.as_bytes());
+        }
+        Value::Array(items) => {
+            pack_header(buffer, items.len(), 0x90, [0xD4, 0xD5, 0xD6])?;
+            for item in items {
+                pack(buffer, item)?;
+            }
+        }
+        Value::Object(entries) => {
+            pack_header(buffer, entries.len(), 0xA0, [0xD8, 0xD9, 0xDA])?;
+            for (key, value) in entries {
+                pack(buffer, &Value::String(key.clone()))?;
+                pack(buffer, value)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn pack_int(buffer: &mut Vec<u8>, int: i64) {
+    if (-16..=127).contains(&int) {
+        buffer.push(int as i8 as u8);
+    } else if let Ok(int) = i8::try_from(int) {
+        buffer.push(0xC8);
+        buffer.push(int as u8);
+    } else if let Ok(int) = i16::try_from(int) {
+        buffer.push(0xC9);
+        buffer.extend_from_slice(&int.to_be_bytes());
+    } else if let Ok(int) = i32::try_from(int) {
+        buffer.push(0xCA);
+        buffer.extend_from_slice(&int.to_be_bytes());
+    } else {
+        buffer.push(0xCB);
+        buffer.extend_from_slice(&int.to_be_bytes());
+    }
+}
+
+/// Decodes PackStream values from a buffer
+struct Unpacker<'a> {
+    buffer: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Unpacker<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.position + len;
+        ensure!(end <= self.buffer.len(), "Truncated PackStream value");
+        let bytes = &self.buffer[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().expect("Took N bytes"))
+    }
+
+    /// The size following a marker, in 1, 2 or 4 bytes
+    fn size(&mut self, bytes: usize) -> Result<usize> {
+        Ok(match bytes {
+            1 => self.take_array::<1>()?[0] as usize,
+            2 => u16::from_be_bytes(self.take_array()?) as usize,
+            _ => u32::from_be_bytes(self.take_array()?) as usize,
+        })
+    }
+
+    fn string(&mut self, len: usize) -> Result<Value> {
+        Ok(Value::String(
+            String::from_utf8(self.take(len)?.to_vec()).context("Invalid UTF-8 string")?,
+        ))
+    }
+
+    fn list(&mut self, len: usize) -> Result<Value> {
+        (0..len)
+            .map(|_| self.unpack())
+            .collect::<Result<_>>()
+            .map(Value::Array)
+    }
+
+    fn map(&mut self, len: usize) -> Result<Value> {
+        let mut map = Map::new();
+        for _ in 0..len {
+            let key = match self.unpack()? {
+                Value::String(key) => key,
+                key => bail!("Map keys must be strings, got {}", key),
+            };
+            map.insert(key, self.unpack()?);
+        }
+        Ok(Value::Object(map))
+    }
+
+    /// Structures (ex: nodes) are decoded as the list of their fields
+    fn structure(&mut self, fields: usize) -> Result<Value> {
+        self.take(1)?;
+        self.list(fields)
+    }
+
+    fn unpack(&mut self) -> Result<Value> {
+        let marker = self.take_array::<1>()?[0];
+        Ok(match marker {
+            0x00..=0x7F | 0xF0..=0xFF => Value::from(marker as i8),
+            0x80..=0x8F => self.string((marker & 0x0F) as usize)?,
+            0x90..=0x9F => self.list((marker & 0x0F) as usize)?,
+            0xA0..=0xAF => self.map((marker & 0x0F) as usize)?,
+            0xB0..=0xBF => self.structure((marker & 0x0F) as usize)?,
+            0xC0 => Value::Null,
+            0xC1 => Number::from_f64(f64::from_be_bytes(self.take_array()?))
+                .map_or(Value::Null, Value::Number),
+            0xC2 => Value::Bool(false),
+            0xC3 => Value::Bool(true),
+            0xC8 => Value::from(i8::from_be_bytes(self.take_array()?)),
+            0xC9 => Value::from(i16::from_be_bytes(self.take_array()?)),
+            0xCA => Value::from(i32::from_be_bytes(self.take_array()?)),
+            0xCB => Value::from(i64::from_be_bytes(self.take_array()?)),
+            0xCC..=0xCE => {
+                let len = self.size(1 << (marker - 0xCC))?;
+                Value::from(self.take(len)?.to_vec())
+            }
+            0xD0..=0xD2 => {
+                let len = self.size(1 << (marker - 0xD0))?;
+                self.string(len)?
+            }
+            0xD4..=0xD6 => {
+                let len = self.size(1 << (marker - 0xD4))?;
+                self.list(len)?
+            }
+            0xD8..=0xDA => {
+                let len = self.size(1 << (marker - 0xD8))?;
+                self.map(len)?
+            }
+            _ => bail!("Unknown PackStream marker 0x{:02X}", marker),
+        })
+    }
+}
+
+/// Decodes a de-chunked message: a structure whose signature is the message's type
+fn unpack_message(buffer: &[u8]) -> Result<Message> {
+    let mut unpacker = Unpacker {
+        buffer,
+        position: 0,
+    };
+    let marker = unpacker.take_array::<1>()?[0];
+    ensure!(
+        (0xB0..=0xBF).contains(&marker),
+        "Messages must be structures, got marker 0x{:02X}",
+        marker
+    );
+    let signature = unpacker.take_array::<1>()?[0];
+    let fields = (0..marker & 0x0F)
+        .map(|_| unpacker.unpack())
+        .collect::<Result<_>>()?;
+    ensure!(
+        unpacker.position == buffer.len(),
+        "Trailing bytes after message 0x{:02X}",
+        signature
+    );
+    Ok(Message { signature, fields })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn packed(value: Value) -> Vec<u8> {
+        let mut buffer = vec![];
+        pack(&mut buffer, &value).unwrap();
+        buffer
+    }
+
+    fn unpacked(buffer: &[u8]) -> Value {
+        Unpacker {
+            buffer,
+            position: 0,
+        }
+        .unpack()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_pack() {
+        // Examples of the PackStream specification
+        assert_eq!(packed(json!(null)), vec![0xC0]);
+        assert_eq!(packed(json!(true)), vec![0xC3]);
+        assert_eq!(packed(json!(1)), vec![0x01]);
+        assert_eq!(packed(json!(-16)), vec![0xF0]);
+        assert_eq!(packed(json!(-17)), vec![0xC8, 0xEF]);
+        assert_eq!(packed(json!(128)), vec![0xC9, 0x00, 0x80]);
+        assert_eq!(packed(json!(-32769)), vec![0xCA, 0xFF, 0xFF, 0x7F, 0xFF]);
+        assert_eq!(
+            packed(json!(2_147_483_648_i64)),
+            vec![0xCB, 0, 0, 0, 0, 0x80, 0, 0, 0]
+        );
+        assert_eq!(
+            packed(json!(1.1)),
+            vec![0xC1, 0x3F, 0xF1, 0x99, 0x99, 0x99, 0x99, 0x99, 0x9A]
+        );
+        assert_eq!(packed(json!("a")), vec![0x81, 0x61]);
+        assert_eq!(
+            packed(json!("abcdefghijklmnopqrstuvwxyz"))[..2],
+            [0xD0, 0x1A]
+        );
+        assert_eq!(packed(json!([1, 2, 3])), vec![0x93, 0x01, 0x02, 0x03]);
+        assert_eq!(packed(json!({"a": 1})), vec![0xA1, 0x81, 0x61, 0x01]);
+        assert!(pack(&mut vec![], &json!(u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn test_unpack() {
+        for value in [
+            json!(null),
+            json!(false),
+            json!(-17),
+            json!(i64::MIN),
+            json!(0.5),
+            json!("x".repeat(300)),
+            json!([1, [2, "b"], { "c": null }]),
+            json!({"version": 42, "amount": "340282366920938463463374607431768211455"}),
+        ] {
+            assert_eq!(unpacked(&packed(value.clone())), value);
+        }
+        // A node, structure 0x4E, is decoded as its fields
+        assert_eq!(
+            unpacked(&[0xB3, 0x4E, 0x01, 0x90, 0xA0]),
+            json!([1, [], {}])
+        );
+
+        let failure = unpack_message(&[
+            0xB1, 0x7F, 0xA2, 0x84, 0x63, 0x6F, 0x64, 0x65, 0x81, 0x58, 0x87, 0x6D, 0x65, 0x73,
+            0x73, 0x61, 0x67, 0x65, 0x81, 0x59,
+        ])
+        .unwrap();
+        assert_eq!(failure.signature, FAILURE);
+        assert_eq!(failure_message(&failure), "X: Y");
+        assert!(unpack_message(&[0xB0, SUCCESS, 0xC0]).is_err());
+    }
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(
+            BoltAddress::parse("bolt://localhost").unwrap(),
+            BoltAddress {
+                host: "localhost".to_string(),
+                port: 7687,
+                tls: false,
+            }
+        );
+        assert_eq!(
+            BoltAddress::parse("neo4j+s://abcd1234.databases.neo4j.io:7688").unwrap(),
+            BoltAddress {
+                host: "abcd1234.databases.neo4j.io".to_string(),
+                port: 7688,
+                tls: true,
+            }
+        );
+        assert!(BoltAddress::parse("http://localhost:7474").is_err());
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Swaps the primary keys of `events` and `write_set_changes` to include each row's position within its transaction
+//! (`migrate-change-keys`), once every row has it. The migration adding the positions swaps them right away on an
+//! empty database; on a populated one, rows indexed before are NULL until re-processed, and rebuilding the keys as a
+//! migration would block the tailer's writes for as long as the new indexes take to build. For each table:
+//!
+//! 1. Rows still without a position are counted, and the swap stops there, reporting the versions to re-process
+//!    (`backfill --processor default_processor`).
+//! 2. A `CHECK (<position> IS NOT NULL)` constraint is added without checking the existing rows, then validated,
+//!    which reads the table without blocking writes. It's what lets `SET NOT NULL` skip scanning the table.
+//! 3. The new primary key's index, and a unique index on the original key (which the processors' upserts still
+//!    conflict on), are built concurrently.
+//! 4. The keys are swapped over the indexes under a short lock, taken with a timeout and retried like
+//!    `migrate-online`'s cutover.
+//!
+//! Every step is skipped if already done, so an interrupted swap resumes where it stopped.
+
+use crate::{
+    database::{create_index_concurrently, PgPoolConnection},
+    online_migration::{primary_key, with_lock_timeout},
+    timescale::is_hypertable,
+};
+use anyhow::{bail, ensure, Result};
+use aptos_logger::info;
+use diesel::{
+    connection::SimpleConnection,
+    prelude::*,
+    sql_query,
+    sql_types::{BigInt, Bool, Nullable, Text},
+};
+use std::time::Duration;
+
+/// A table whose primary key is swapped to `(transaction_hash, <position>)`
+#[derive(Debug)]
+pub struct ChangeKey {
+    pub table: &'static str,
+    /// Column of each row's position within its transaction
+    pub position: &'static str,
+    /// Columns of the original primary key, which stays unique
+    pub original_key: &'static str,
+    /// Name of the unique constraint over the original key, once swapped
+    pub original_key_constraint: &'static str,
+}
+
+pub const CHANGE_KEYS: &[ChangeKey] = &[
+    ChangeKey {
+        table: "events",
+        position: "event_index",
+        original_key: "(key, sequence_number)",
+        original_key_constraint: "events_key_sequence_number_unique",
+    },
+    ChangeKey {
+        table: "write_set_changes",
+        position: "write_set_change_index",
+        original_key: "(transaction_hash, hash)",
+        original_key_constraint: "write_set_changes_transaction_hash_hash_unique",
+    },
+];
+
+#[derive(Debug, QueryableByName)]
+struct Missing {
+    #[sql_type = "BigInt"]
+    num_rows: i64,
+    #[sql_type = "Nullable<BigInt>"]
+    first_version: Option<i64>,
+    #[sql_type = "Nullable<BigInt>"]
+    last_version: Option<i64>,
+}
+
+#[derive(Debug, QueryableByName)]
+struct Exists {
+    #[sql_type = "Bool"]
+    exists: bool,
+}
+
+impl ChangeKey {
+    fn check_constraint(&self) -> String {
+        format!("{}_{}_not_null", self.table, self.position)
+    }
+
+    fn new_key_index(&self) -> String {
+        format!("{}_{}_pkey", self.table, self.position)
+    }
+
+    fn is_swapped(&self, conn: &PgPoolConnection) -> Result<bool> {
+        let key: Vec<String> = primary_key(conn, self.table)?
+            .into_iter()
+            .map(|column| column.name)
+            .collect();
+        Ok(key == ["transaction_hash", self.position])
+    }
+
+    fn has_check_constraint(&self, conn: &PgPoolConnection) -> Result<bool> {
+        let exists: Exists = sql_query(
+            "SELECT EXISTS (SELECT 1 FROM pg_constraint WHERE conrelid = to_regclass($1) AND conname = $2) AS exists",
+        )
+        .bind::<Text, _>(self.table)
+        .bind::<Text, _>(self.check_constraint())
+        .get_result(conn)?;
+        Ok(exists.exists)
+    }
+
+    /// The rows without a position, with the range of versions they belong to
+    fn missing(&self, conn: &PgPoolConnection) -> Result<Missing> {
+        Ok(sql_query(format!(
+            "SELECT COUNT(*) AS num_rows, MIN(t.version) AS first_version, MAX(t.version) AS last_version \
+             FROM {table} c LEFT JOIN transactions t ON t.hash = c.transaction_hash WHERE c.{position} IS NULL",
+            table = self.table,
+            position = self.position,
+        ))
+        .get_result(conn)?)
+    }
+
+    fn swap_sql(&self) -> String {
+        format!(
+            "ALTER TABLE {table} ALTER COLUMN {position} SET NOT NULL;
+ALTER TABLE {table} DROP CONSTRAINT {table}_pkey;
+ALTER TABLE {table} ADD CONSTRAINT {table}_pkey PRIMARY KEY USING INDEX {new_key_index};
+ALTER TABLE {table} ADD CONSTRAINT {unique} UNIQUE USING INDEX {unique};
+ALTER TABLE {table} DROP CONSTRAINT {check};",
+            table = self.table,
+            position = self.position,
+            new_key_index = self.new_key_index(),
+            unique = self.original_key_constraint,
+            check = self.check_constraint(),
+        )
+    }
+
+    /// Swaps the table's primary key, or fails listing the versions to re-process first. Returns whether it was
+    /// swapped now, rather than already. With `timescale`, hypertables are refused: their keys must include their time
+    /// column, which Timescale's setup already appended.
+    pub fn swap(
+        &self,
+        conn: &PgPoolConnection,
+        lock_timeout: Duration,
+        timescale: bool,
+    ) -> Result<bool> {
+        if self.is_swapped(conn)? {
+            return Ok(false);
+        }
+        ensure!(
+            !(timescale && is_hypertable(conn, self.table)?),
+            "{} is a hypertable, whose primary key must include its time column: swap it by hand",
+            self.table
+        );
+        let missing = self.missing(conn)?;
+        if missing.num_rows > 0 {
+            let versions = match (missing.first_version, missing.last_version) {
+                (Some(first), Some(last)) => format!("versions {} to {}", first, last),
+                _ => "unknown versions".to_string(),
+            };
+            bail!(
+                "{} rows of {} have no {} yet, of {}: re-process them with `backfill --processor default_processor` \
+                 first",
+                missing.num_rows,
+                self.table,
+                self.position,
+                versions
+            );
+        }
+
+        if !self.has_check_constraint(conn)? {
+            with_lock_timeout(conn, lock_timeout, || {
+                conn.batch_execute(&format!(
+                    "ALTER TABLE {} ADD CONSTRAINT {} CHECK ({} IS NOT NULL) NOT VALID",
+                    self.table,
+                    self.check_constraint(),
+                    self.position
+                ))?;
+                Ok(())
+            })?;
+        }
+        info!(table = self.table, "Validating positions...");
+        conn.batch_execute(&format!(
+            "ALTER TABLE {} VALIDATE CONSTRAINT {}",
+            self.table,
+            self.check_constraint()
+        ))?;
+
+        create_index_concurrently(
+            conn,
+            &self.new_key_index(),
+            self.table,
+            &format!("(transaction_hash, {})", self.position),
+            true,
+            false,
+        )?;
+        create_index_concurrently(
+            conn,
+            self.original_key_constraint,
+            self.table,
+            self.original_key,
+            true,
+            false,
+        )?;
+
+        with_lock_timeout(conn, lock_timeout, || {
+            conn.batch_execute(&self.swap_sql())?;
+            Ok(())
+        })?;
+        info!(table = self.table, "Swapped primary key");
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swap_sql() {
+        assert_eq!(
+            CHANGE_KEYS[0].swap_sql(),
+            "ALTER TABLE events ALTER COLUMN event_index SET NOT NULL;
+ALTER TABLE events DROP CONSTRAINT events_pkey;
+ALTER TABLE events ADD CONSTRAINT events_pkey PRIMARY KEY USING INDEX events_event_index_pkey;
+ALTER TABLE events ADD CONSTRAINT events_key_sequence_number_unique UNIQUE USING INDEX \
+             events_key_sequence_number_unique;
+ALTER TABLE events DROP CONSTRAINT events_event_index_not_null;"
+        );
+    }
+}
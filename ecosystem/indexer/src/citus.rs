@@ -0,0 +1,171 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Checks for running on Citus (sharded Postgres). Citus routes each inserted row to its shard by the table's
+//! distribution column, so the insert path is unchanged, but an upsert's conflict target must include the
+//! distribution column for Citus to resolve conflicts within a shard. `validate` checks, on startup, that every table
+//! is distributed (or a reference table) and that the upserted ones are distributed by a column of their conflict
+//! target, rather than failing on the first batch.
+
+use crate::database::PgPoolConnection;
+use anyhow::{bail, Context, Result};
+use diesel::{prelude::*, sql_query, sql_types::Text};
+use std::collections::HashMap;
+
+/// Tables processors upsert into, with the columns of their conflict target. Keep in sync with the `on_conflict`s of
+/// the insert paths.
+pub const CONFLICT_TARGETS: &[(&str, &[&str])] = &[
+    ("processor_statuses", &["name", "version"]),
+    ("events", &["key", "sequence_number"]),
+    ("write_set_changes", &["transaction_hash", "hash"]),
+    ("ownerships", &["ownership_id"]),
+    ("current_token_ownerships_v2", &["token_data_id"]),
+    ("fungible_asset_balances", &["storage_id"]),
+    ("top_holders", &["asset_type", "storage_id"]),
+    ("current_object_ownerships", &["object_address"]),
+    ("account_summaries", &["address"]),
+    (
+        "txn_latency_stats",
+        &["period_start", "latency_bucket_secs"],
+    ),
+    ("minute_transaction_rollups", &["bucket"]),
+    ("hourly_activity_rollups", &["bucket"]),
+    (
+        "module_daily_stats",
+        &["module_address", "module_name", "date"],
+    ),
+    (
+        "function_reliability_stats",
+        &["module_address", "module_name", "function_name", "date"],
+    ),
+    ("event_types_seen", &["type"]),
+    ("token_metadata_cache", &["uri"]),
+];
+
+#[derive(Debug, QueryableByName)]
+struct DistributedTable {
+    #[sql_type = "Text"]
+    table_name: String,
+    /// `h` (hash distributed), `n` (reference table) or `a`/`r` (append/range distributed)
+    #[sql_type = "Text"]
+    method: String,
+    #[sql_type = "diesel::sql_types::Nullable<Text>"]
+    column_name: Option<String>,
+}
+
+#[derive(Debug, QueryableByName)]
+struct Table {
+    #[sql_type = "Text"]
+    table_name: String,
+}
+
+/// Problems with how the indexer's tables are distributed, given each table's distribution method and column
+pub fn distribution_problems(
+    tables: &[String],
+    distributed: &HashMap<String, (String, Option<String>)>,
+) -> Vec<String> {
+    let mut problems = vec![];
+    for table in tables {
+        match distributed.get(table) {
+            None => problems.push(format!(
+                "{} is neither distributed nor a reference table",
+                table
+            )),
+            Some((method, column)) if method == "h" => {
+                let target = CONFLICT_TARGETS
+                    .iter()
+                    .find(|(name, _)| *name == table.as_str())
+                    .map(|(_, columns)| *columns);
+                if let (Some(target), Some(column)) = (target, column) {
+                    if !target.contains(&column.as_str()) {
+                        problems.push(format!(
+                            "{} is distributed by {}, but is upserted on ({}): distribute it by one of those \
+                             columns or make it a reference table",
+                            table,
+                            column,
+                            target.join(", ")
+                        ));
+                    }
+                }
+            }
+            Some((method, _)) if method != "n" => problems.push(format!(
+                "{} is append or range distributed: hash distribute it or make it a reference table",
+                table
+            )),
+            Some(_) => {}
+        }
+    }
+    problems
+}
+
+/// Fails if Citus isn't installed or any of the indexer's tables isn't distributed suitably
+pub fn validate(conn: &PgPoolConnection) -> Result<()> {
+    let distributed: HashMap<String, (String, Option<String>)> = sql_query(
+        "SELECT logicalrelid::regclass::text AS table_name, partmethod::text AS method, \
+         column_to_column_name(logicalrelid, partkey) AS column_name FROM pg_dist_partition",
+    )
+    .load::<DistributedTable>(conn)
+    .context("Could not read the distributed tables: is Citus installed?")?
+    .into_iter()
+    .map(|table| (table.table_name, (table.method, table.column_name)))
+    .collect();
+    let tables: Vec<String> = sql_query(
+        "SELECT table_name::text AS table_name FROM information_schema.tables \
+         WHERE table_schema = current_schema() AND table_type = 'BASE TABLE' \
+         AND table_name <> '__diesel_schema_migrations' ORDER BY table_name",
+    )
+    .load::<Table>(conn)?
+    .into_iter()
+    .map(|table| table.table_name)
+    .collect();
+
+    let problems = distribution_problems(&tables, &distributed);
+    if !problems.is_empty() {
+        bail!(
+            "Tables aren't distributed for Citus:\n{}",
+            problems.join("\n")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distribution_problems() {
+        let distributed: HashMap<String, (String, Option<String>)> = [
+            ("transactions", "h", Some("hash")),
+            ("processor_statuses", "h", Some("name")),
+            ("account_summaries", "h", Some("last_active_version")),
+            ("ledger_infos", "n", None),
+        ]
+        .into_iter()
+        .map(|(table, method, column)| {
+            (
+                table.to_string(),
+                (method.to_string(), column.map(str::to_string)),
+            )
+        })
+        .collect();
+        let tables: Vec<String> = [
+            "account_summaries",
+            "events",
+            "ledger_infos",
+            "processor_statuses",
+            "transactions",
+        ]
+        .iter()
+        .map(|table| table.to_string())
+        .collect();
+
+        let problems = distribution_problems(&tables, &distributed);
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("account_summaries is distributed by last_active_version"));
+        assert_eq!(
+            problems[1],
+            "events is neither distributed nor a reference table"
+        );
+    }
+}
@@ -1,21 +1,34 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::{
+    indexer::{
+        audit_log::{AuditLog, OPERATOR_HEADER, UNKNOWN_ACTOR},
+        dispatch::ProcessorSwitches,
+        metadata_handle::{await_version, MetadataHandle},
+    },
+    models::operator_audit_log::OperatorAction,
+};
 use aptos_metrics_core::{
-    register_int_counter, register_int_counter_vec, register_int_gauge_vec, IntCounter,
-    IntCounterVec, IntGaugeVec, TextEncoder,
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    TextEncoder,
 };
 use http::StatusCode;
 use hyper::{
+    server::conn::AddrStream,
     service::{make_service_fn, service_fn},
     Body, Method, Request, Response, Server,
 };
 use inspection_service::inspection_service::encode_metrics;
 use once_cell::sync::Lazy;
 use std::{
+    collections::HashMap,
     convert::Infallible,
     net::{SocketAddr, ToSocketAddrs},
+    sync::Arc,
     thread,
+    time::Duration,
 };
 use tokio::runtime;
 
@@ -49,6 +62,158 @@ pub static PROCESSOR_SUCCESSES: Lazy<IntCounterVec> = Lazy::new(|| {
     .unwrap()
 });
 
+/// Number of versions marked successful that had no data when verified on startup
+pub static VERIFICATION_MISSING_VERSIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_verification_missing_version_count",
+        "Number of versions marked successful that had no data when verified on startup",
+        &["processor_name"]
+    )
+    .unwrap()
+});
+
+/// Number of times versions already processed were found replaced on the node
+pub static REORGS_DETECTED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_reorgs_detected_count",
+        "Number of times processed versions were found replaced on the node",
+        &["processor_name"]
+    )
+    .unwrap()
+});
+
+/// Number of transaction timestamps adjusted before processing, by processor and kind of adjustment (`skew` when
+/// too far in the future of the host's clock, `monotonic` when behind an earlier version's)
+pub static TIMESTAMP_ADJUSTMENTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_timestamp_adjustment_count",
+        "Number of transaction timestamps adjusted before processing",
+        &["processor_name", "kind"]
+    )
+    .unwrap()
+});
+
+/// Number of processed versions re-processed after being replaced on the node
+pub static REORG_REPROCESSED_VERSIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_reorg_reprocessed_version_count",
+        "Number of processed versions re-processed after being replaced on the node",
+        &["processor_name"]
+    )
+    .unwrap()
+});
+
+/// Number of times a commit hook has failed
+pub static COMMIT_HOOK_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_commit_hook_error_count",
+        "Number of times a commit hook has failed",
+        &["hook_name"]
+    )
+    .unwrap()
+});
+
+/// Number of times a rollup has failed to update
+pub static ROLLUP_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_rollup_error_count",
+        "Number of times a rollup has failed to update",
+        &["rollup_name"]
+    )
+    .unwrap()
+});
+
+/// Number of times a batch failed with an error which may go away, and was processed again
+pub static BATCH_RETRIES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_batch_retry_count",
+        "Number of times a batch failed with a retryable error and was processed again",
+        &["processor_name", "error_kind"]
+    )
+    .unwrap()
+});
+
+/// Number of batches which failed for good (with a non-retryable error, or once out of retries): their versions are
+/// left marked failed in the processor's statuses, to be processed again on restart
+pub static BATCHES_DEAD_LETTERED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_batch_dead_letter_count",
+        "Number of batches which failed for good and were left marked failed",
+        &["processor_name", "error_kind"]
+    )
+    .unwrap()
+});
+
+/// Number of times backfilling the versions skipped by `--prioritize-recent-min-gap` failed, and was retried
+pub static BACKFILL_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_backfill_error_count",
+        "Number of times backfilling the versions before the latest version failed",
+        &["processor_name"]
+    )
+    .unwrap()
+});
+
+/// 1 while a processor's backfill is failing (from its first failure until a retry completes it), else 0
+pub static BACKFILL_FAILING: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "indexer_backfill_failing",
+        "Whether backfilling the versions before the latest version is failing",
+        &["processor_name"]
+    )
+    .unwrap()
+});
+
+/// Number of chunked inserts that failed, by table and violated constraint (empty if none was reported)
+pub static INSERT_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_insert_error_count",
+        "Number of chunked inserts that failed, by table and violated constraint",
+        &["table_name", "constraint_name"]
+    )
+    .unwrap()
+});
+
+/// Number of transactions retried after Postgres aborted them for a deadlock or serialization failure, by processor
+pub static DEADLOCK_RETRIES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_deadlock_retry_count",
+        "Number of transactions retried after Postgres aborted them for a deadlock or serialization failure",
+        &["processor_name"]
+    )
+    .unwrap()
+});
+
+/// Number of inserts into a table out of `TABLE_LOCK_ORDER`, by table
+pub static TABLE_LOCK_ORDER_VIOLATIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_table_lock_order_violation_count",
+        "Number of inserts into a table out of the table lock order",
+        &["table_name"]
+    )
+    .unwrap()
+});
+
+/// Number of token URIs fetched by the URI enricher, by outcome
+pub static URI_ENRICHER_FETCHES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_uri_enricher_fetch_count",
+        "Number of token URIs fetched by the URI enricher, by outcome",
+        &["status"]
+    )
+    .unwrap()
+});
+
+/// Number of outbox messages the outbox relay tried to deliver, by outcome
+pub static OUTBOX_RELAY_MESSAGES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_outbox_relay_message_count",
+        "Number of outbox messages the outbox relay tried to deliver, by outcome",
+        &["status"]
+    )
+    .unwrap()
+});
+
 /// Number of times the connection pool has timed out when trying to get a connection
 pub static UNABLE_TO_GET_CONNECTION: Lazy<IntCounter> = Lazy::new(|| {
     register_int_counter!(
@@ -85,6 +250,27 @@ pub static FETCHED_TRANSACTION: Lazy<IntCounter> = Lazy::new(|| {
     .unwrap()
 });
 
+/// Bytes of transaction batches received from the node, as sent (`compressed`) and once decoded (`decompressed`)
+pub static FETCHED_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_fetched_bytes",
+        "Bytes of transaction batches received from the node, as sent and once decoded",
+        &["encoding"]
+    )
+    .unwrap()
+});
+
+/// Batches requested through the fetch cache shared by processors, by whether it had them (`hit`) or fetched them
+/// (`miss`)
+pub static FETCH_CACHE_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_fetch_cache_request_count",
+        "Batches requested through the shared fetch cache, by whether it had them",
+        &["result"]
+    )
+    .unwrap()
+});
+
 /// Max version processed
 pub static LATEST_PROCESSED_VERSION: Lazy<IntGaugeVec> = Lazy::new(|| {
     register_int_gauge_vec!(
@@ -95,7 +281,109 @@ pub static LATEST_PROCESSED_VERSION: Lazy<IntGaugeVec> = Lazy::new(|| {
     .unwrap()
 });
 
-pub fn start_inspection_service(service_address: &str, service_port: u16) {
+/// Number of batches each stage of a tailer's pipeline has handled
+pub static PIPELINE_STAGE_BATCHES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_pipeline_stage_batch_count",
+        "Number of batches each stage of a tailer's pipeline has handled",
+        &["processor_name", "stage"]
+    )
+    .unwrap()
+});
+
+/// Time each stage of a tailer's pipeline spends on a batch, including waiting for the next stage to accept it
+pub static PIPELINE_STAGE_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "indexer_pipeline_stage_seconds",
+        "Time each stage of a tailer's pipeline spends on a batch",
+        &["processor_name", "stage"]
+    )
+    .unwrap()
+});
+
+/// Estimated size of the batches in flight in a tailer's pipeline, when it has a memory budget
+pub static PIPELINE_IN_FLIGHT_BYTES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "indexer_pipeline_in_flight_bytes",
+        "Estimated size of the batches in flight in a tailer's pipeline",
+        &["processor_name"]
+    )
+    .unwrap()
+});
+
+/// Number of batches a tailer's pipeline has spilled to disk for being over its memory budget
+pub static PIPELINE_SPILLED_BATCHES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_pipeline_spilled_batch_count",
+        "Number of batches a tailer's pipeline has spilled to disk for being over its memory budget",
+        &["processor_name"]
+    )
+    .unwrap()
+});
+
+/// Bytes currently allocated, as reported by jemalloc (with the `profiling` feature)
+pub static ALLOCATED_BYTES: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "indexer_allocated_bytes",
+        "Bytes currently allocated, as reported by jemalloc"
+    )
+    .unwrap()
+});
+
+/// Bytes in physically resident pages mapped by jemalloc (with the `profiling` feature)
+pub static RESIDENT_BYTES: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "indexer_resident_bytes",
+        "Bytes in physically resident pages mapped by jemalloc"
+    )
+    .unwrap()
+});
+
+/// Highest allocated bytes seen after each stage of a tailer's pipeline handled a batch (with the `profiling` feature)
+pub static PIPELINE_STAGE_PEAK_ALLOCATED_BYTES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "indexer_pipeline_stage_peak_allocated_bytes",
+        "Highest allocated bytes seen after each stage of a tailer's pipeline handled a batch",
+        &["processor_name", "stage"]
+    )
+    .unwrap()
+});
+
+/// Number of read API requests, by route, response status and client
+pub static READ_API_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "indexer_read_api_request_count",
+        "Number of read API requests, by route, response status and client",
+        &["route", "status", "client"]
+    )
+    .unwrap()
+});
+
+/// Time the read API takes to respond, by route
+pub static READ_API_REQUEST_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "indexer_read_api_request_seconds",
+        "Time the read API takes to respond, by route",
+        &["route"]
+    )
+    .unwrap()
+});
+
+/// How long `/await_version` waits by default, and at most
+const AWAIT_VERSION_DEFAULT_TIMEOUT_SECS: u64 = 30;
+const AWAIT_VERSION_MAX_TIMEOUT_SECS: u64 = 300;
+
+/// Serves `/metrics`, and `/await_version` if given the processors' `metadata`. Admin actions are recorded into
+/// `audit_log` before they're taken. The server runs on a single thread, so the metadata and audit log, which block,
+/// are only called from `spawn_blocking` to keep `/metrics` responsive.
+pub fn start_inspection_service(
+    service_address: &str,
+    service_port: u16,
+    metadata: Option<Arc<dyn MetadataHandle>>,
+    switches: ProcessorSwitches,
+    audit_log: Arc<dyn AuditLog>,
+) {
+    let switches = Arc::new(switches);
     // Only called from places that guarantee that host is parsable, but this must be assumed.
     let addr: SocketAddr = (service_address, service_port)
         .to_socket_addrs()
@@ -110,13 +398,27 @@ pub fn start_inspection_service(service_address: &str, service_port: u16) {
 
     // Spawn the server
     thread::spawn(move || {
-        let make_service =
-            make_service_fn(
-                move |_conn| async move { Ok::<_, Infallible>(service_fn(serve_requests)) },
-            );
+        let make_service = make_service_fn(move |conn: &AddrStream| {
+            let remote_addr = conn.remote_addr();
+            let metadata = metadata.clone();
+            let switches = switches.clone();
+            let audit_log = audit_log.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    serve_requests(
+                        req,
+                        remote_addr,
+                        metadata.clone(),
+                        switches.clone(),
+                        audit_log.clone(),
+                    )
+                }))
+            }
+        });
 
         let runtime = runtime::Builder::new_current_thread()
             .enable_io()
+            .enable_time()
             .disable_lifo_slot()
             .build()
             .unwrap();
@@ -129,7 +431,13 @@ pub fn start_inspection_service(service_address: &str, service_port: u16) {
     });
 }
 
-async fn serve_requests(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
+async fn serve_requests(
+    req: Request<Body>,
+    remote_addr: SocketAddr,
+    metadata: Option<Arc<dyn MetadataHandle>>,
+    switches: Arc<ProcessorSwitches>,
+    audit_log: Arc<dyn AuditLog>,
+) -> Result<Response<Body>, hyper::Error> {
     let mut resp = Response::new(Body::empty());
     match (req.method(), req.uri().path()) {
         // Exposes text encoded metrics
@@ -138,9 +446,205 @@ async fn serve_requests(req: Request<Body>) -> Result<Response<Body>, hyper::Err
             let buffer = encode_metrics(encoder);
             *resp.body_mut() = Body::from(buffer);
         }
+        // Waits until a processor has processed a version: `?processor=<name>&version=<version>[&timeout_secs=<secs>]`
+        (&Method::GET, "/await_version") if metadata.is_some() => {
+            let query: HashMap<String, String> =
+                url::form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+                    .into_owned()
+                    .collect();
+            let (status, body) = serve_await_version(metadata.unwrap(), &query).await;
+            *resp.status_mut() = status;
+            *resp.body_mut() = Body::from(body);
+        }
+        // Whether each processor is enabled, as a JSON object
+        (&Method::GET, "/processors") => {
+            let enabled: HashMap<&String, bool> = switches
+                .iter()
+                .map(|(name, switch)| (name, switch.is_enabled()))
+                .collect();
+            *resp.body_mut() = Body::from(serde_json::to_string(&enabled).unwrap());
+        }
+        // Enables or disables a processor: `/processors/<name>/enable` or `/processors/<name>/disable`
+        (&Method::POST, path) if path.starts_with("/processors/") => {
+            let actor = req
+                .headers()
+                .get(OPERATOR_HEADER)
+                .and_then(|actor| actor.to_str().ok())
+                .unwrap_or(UNKNOWN_ACTOR)
+                .to_string();
+            let path = path.to_string();
+            let (status, body) = tokio::task::spawn_blocking(move || {
+                serve_processor_switch(&switches, &path, audit_log.as_ref(), actor, remote_addr)
+            })
+            .await
+            .unwrap_or_else(|err| (StatusCode::INTERNAL_SERVER_ERROR, format!("{:?}", err)));
+            *resp.status_mut() = status;
+            *resp.body_mut() = Body::from(body);
+        }
         _ => {
             *resp.status_mut() = StatusCode::NOT_FOUND;
         }
     };
     Ok(resp)
 }
+
+fn serve_processor_switch(
+    switches: &ProcessorSwitches,
+    path: &str,
+    audit_log: &dyn AuditLog,
+    actor: String,
+    remote_addr: SocketAddr,
+) -> (StatusCode, String) {
+    let (processor_name, action) = match path.trim_start_matches("/processors/").rsplit_once('/') {
+        Some(parts) => parts,
+        None => return (StatusCode::NOT_FOUND, String::new()),
+    };
+    let enabled = match action {
+        "enable" => true,
+        "disable" => false,
+        _ => return (StatusCode::NOT_FOUND, String::new()),
+    };
+    match switches.get(processor_name) {
+        Some(switch) => {
+            let recorded = audit_log.record(&OperatorAction::new(
+                actor,
+                Some(remote_addr.to_string()),
+                &format!("processor.{}", action),
+                serde_json::json!({ "processor": processor_name }),
+            ));
+            if let Err(err) = recorded {
+                aptos_logger::error!(
+                    processor_name = processor_name,
+                    error = format!("{:?}", err),
+                    "Failed to record switching processor, not switching it"
+                );
+                return (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    format!("Failed to record the action: {}", err),
+                );
+            }
+            switch.set_enabled(enabled);
+            aptos_logger::info!(
+                processor_name = processor_name,
+                enabled = enabled,
+                "Switched processor"
+            );
+            (StatusCode::OK, format!("{} is {}d", processor_name, action))
+        }
+        None => (
+            StatusCode::NOT_FOUND,
+            format!("No processor named {}", processor_name),
+        ),
+    }
+}
+
+async fn serve_await_version(
+    metadata: Arc<dyn MetadataHandle>,
+    query: &HashMap<String, String>,
+) -> (StatusCode, String) {
+    let processor_name = match query.get("processor") {
+        Some(processor_name) => processor_name,
+        None => return (StatusCode::BAD_REQUEST, "Missing processor".to_string()),
+    };
+    let version = match query.get("version").map(|version| version.parse::<u64>()) {
+        Some(Ok(version)) => version,
+        _ => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "Missing or invalid version".to_string(),
+            )
+        }
+    };
+    let timeout_secs = match query.get("timeout_secs").map(|secs| secs.parse::<u64>()) {
+        Some(Ok(secs)) => std::cmp::min(secs, AWAIT_VERSION_MAX_TIMEOUT_SECS),
+        Some(Err(_)) => return (StatusCode::BAD_REQUEST, "Invalid timeout_secs".to_string()),
+        None => AWAIT_VERSION_DEFAULT_TIMEOUT_SECS,
+    };
+    match await_version(
+        metadata,
+        processor_name,
+        version,
+        Duration::from_secs(timeout_secs),
+    )
+    .await
+    {
+        Ok(true) => (
+            StatusCode::OK,
+            format!("{} has processed version {}", processor_name, version),
+        ),
+        Ok(false) => (
+            StatusCode::REQUEST_TIMEOUT,
+            format!(
+                "{} hasn't processed version {} after {}s",
+                processor_name, version, timeout_secs
+            ),
+        ),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{:?}", err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::{
+        audit_log::LoggingAuditLog,
+        metadata_handle::{InMemoryMetadataHandle, VersionStatus},
+    };
+    use std::net::TcpListener;
+
+    const NAME: &str = "test_processor";
+
+    #[tokio::test]
+    async fn test_serve_await_version() {
+        let handle = Arc::new(InMemoryMetadataHandle::new());
+        handle
+            .set_statuses(NAME, &VersionStatus::range(0, 4, true, None))
+            .unwrap();
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        start_inspection_service(
+            "127.0.0.1",
+            port,
+            Some(handle.clone() as Arc<dyn MetadataHandle>),
+            ProcessorSwitches::new(),
+            Arc::new(LoggingAuditLog),
+        );
+
+        let client = reqwest::Client::new();
+        let get = |query: String| {
+            let client = client.clone();
+            async move {
+                let url = format!("http://127.0.0.1:{}/await_version?{}", port, query);
+                // The server starts on its own thread
+                for _ in 0..50 {
+                    if let Ok(response) = client.get(&url).send().await {
+                        return response.status();
+                    }
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                panic!("The inspection service didn't start");
+            }
+        };
+
+        let processed = format!("processor={}&version=4", NAME);
+        assert_eq!(get(processed).await, StatusCode::OK);
+        // Waits, then times out
+        let timed_out = format!("processor={}&version=7&timeout_secs=1", NAME);
+        assert_eq!(get(timed_out).await, StatusCode::REQUEST_TIMEOUT);
+        assert_eq!(get("version=7".to_string()).await, StatusCode::BAD_REQUEST);
+
+        // Returns once the version is processed while waiting
+        let setter = handle.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            setter
+                .set_statuses(NAME, &VersionStatus::range(5, 7, true, None))
+                .unwrap();
+        });
+        let awaited = format!("processor={}&version=7&timeout_secs=10", NAME);
+        assert_eq!(get(awaited).await, StatusCode::OK);
+    }
+}
@@ -0,0 +1,200 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Embedded DuckDB, through the C API of `libduckdb` (linked with the `duckdb` feature; build it or download it from
+//! DuckDB's releases, and point the linker at it with `RUSTFLAGS="-L <dir>"` if it isn't installed system-wide). Only
+//! what the `duckdb_processor` needs is bound: opening a file, and running SQL without reading results back.
+//!
+//! The C API's calls block, so callers on the async runtime should make them with `spawn_blocking`.
+
+use anyhow::{bail, Context, Result};
+use std::{
+    ffi::{c_void, CStr, CString},
+    marker::PhantomData,
+    os::raw::{c_char, c_int},
+    path::Path,
+    ptr,
+};
+
+#[allow(non_camel_case_types)]
+type duckdb_database = *mut c_void;
+#[allow(non_camel_case_types)]
+type duckdb_connection = *mut c_void;
+#[allow(non_camel_case_types)]
+type duckdb_config = *mut c_void;
+/// `DuckDBSuccess` (0) or `DuckDBError` (1)
+#[allow(non_camel_case_types)]
+type duckdb_state = c_int;
+
+const DUCKDB_SUCCESS: duckdb_state = 0;
+
+/// `duckdb_result`: its fields are only read through the API's functions
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct duckdb_result {
+    deprecated_column_count: u64,
+    deprecated_row_count: u64,
+    deprecated_rows_changed: u64,
+    deprecated_columns: *mut c_void,
+    deprecated_error_message: *mut c_char,
+    internal_data: *mut c_void,
+}
+
+#[link(name = "duckdb")]
+extern "C" {
+    fn duckdb_open_ext(
+        path: *const c_char,
+        out_database: *mut duckdb_database,
+        config: duckdb_config,
+        out_error: *mut *mut c_char,
+    ) -> duckdb_state;
+    fn duckdb_close(database: *mut duckdb_database);
+    fn duckdb_connect(
+        database: duckdb_database,
+        out_connection: *mut duckdb_connection,
+    ) -> duckdb_state;
+    fn duckdb_disconnect(connection: *mut duckdb_connection);
+    fn duckdb_query(
+        connection: duckdb_connection,
+        query: *const c_char,
+        out_result: *mut duckdb_result,
+    ) -> duckdb_state;
+    fn duckdb_result_error(result: *mut duckdb_result) -> *const c_char;
+    fn duckdb_destroy_result(result: *mut duckdb_result);
+    fn duckdb_free(ptr: *mut c_void);
+}
+
+/// An open DuckDB file. It takes a single writer: other processes can't open it until it's dropped.
+#[derive(Debug)]
+pub struct Database {
+    database: duckdb_database,
+}
+
+impl Database {
+    /// Opens the DuckDB file at `path`, created if it doesn't exist
+    pub fn open(path: &Path) -> Result<Self> {
+        let c_path = CString::new(path.to_string_lossy().as_bytes())
+            .with_context(|| format!("Invalid DuckDB path {:?}", path))?;
+        Self::open_c_path(c_path.as_ptr())
+            .with_context(|| format!("Could not open DuckDB file {:?}", path))
+    }
+
+    /// Opens an in-memory database
+    pub fn open_in_memory() -> Result<Self> {
+        Self::open_c_path(ptr::null())
+    }
+
+    fn open_c_path(c_path: *const c_char) -> Result<Self> {
+        let mut database: duckdb_database = ptr::null_mut();
+        let mut error: *mut c_char = ptr::null_mut();
+        // SAFETY: `c_path` is NUL-terminated or null (in memory), the config is optional, and `database`/`error` are
+        // out-parameters of the right types. The error message is ours to free.
+        let state = unsafe { duckdb_open_ext(c_path, &mut database, ptr::null_mut(), &mut error) };
+        if state != DUCKDB_SUCCESS {
+            let message = if error.is_null() {
+                "Unknown error".to_string()
+            } else {
+                // SAFETY: a non-null `error` is a NUL-terminated string DuckDB allocated for us
+                unsafe {
+                    let message = CStr::from_ptr(error).to_string_lossy().into_owned();
+                    duckdb_free(error as *mut c_void);
+                    message
+                }
+            };
+            bail!("{}", message);
+        }
+        Ok(Self { database })
+    }
+
+    pub fn connect(&self) -> Result<Connection<'_>> {
+        let mut connection: duckdb_connection = ptr::null_mut();
+        // SAFETY: `self.database` is open until `self` is dropped, which the connection's lifetime prevents
+        let state = unsafe { duckdb_connect(self.database, &mut connection) };
+        if state != DUCKDB_SUCCESS {
+            bail!("Could not connect to the DuckDB database");
+        }
+        Ok(Connection {
+            connection,
+            _database: PhantomData,
+        })
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        // SAFETY: the database was opened, and its connections (which borrow it) are already closed
+        unsafe { duckdb_close(&mut self.database) }
+    }
+}
+
+/// A connection to a `Database`
+#[derive(Debug)]
+pub struct Connection<'a> {
+    connection: duckdb_connection,
+    _database: PhantomData<&'a Database>,
+}
+
+impl Connection<'_> {
+    /// Runs `sql`, one or more statements, stopping at the first error
+    pub fn execute(&self, sql: &str) -> Result<()> {
+        let c_sql = CString::new(sql).context("SQL contains a NUL byte")?;
+        let mut result = duckdb_result {
+            deprecated_column_count: 0,
+            deprecated_row_count: 0,
+            deprecated_rows_changed: 0,
+            deprecated_columns: ptr::null_mut(),
+            deprecated_error_message: ptr::null_mut(),
+            internal_data: ptr::null_mut(),
+        };
+        // SAFETY: the connection is open, `c_sql` is NUL-terminated, and `result` is destroyed below, whether the
+        // query succeeded or not, as the API requires. The error message lives until then.
+        unsafe {
+            let state = duckdb_query(self.connection, c_sql.as_ptr(), &mut result);
+            let error = if state == DUCKDB_SUCCESS {
+                None
+            } else {
+                let error = duckdb_result_error(&mut result);
+                Some(if error.is_null() {
+                    "Unknown error".to_string()
+                } else {
+                    CStr::from_ptr(error).to_string_lossy().into_owned()
+                })
+            };
+            duckdb_destroy_result(&mut result);
+            match error {
+                None => Ok(()),
+                Some(error) => bail!("{}", error),
+            }
+        }
+    }
+}
+
+impl Drop for Connection<'_> {
+    fn drop(&mut self) {
+        // SAFETY: the connection was opened, and its database outlives it
+        unsafe { duckdb_disconnect(&mut self.connection) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let database = Database::open_in_memory().unwrap();
+        let connection = database.connect().unwrap();
+        connection
+            .execute("CREATE TABLE t (a BIGINT PRIMARY KEY); INSERT INTO t VALUES (1), (2);")
+            .unwrap();
+        connection
+            .execute("INSERT OR IGNORE INTO t VALUES (1);")
+            .unwrap();
+        let error = connection
+            .execute("INSERT INTO t VALUES (2);")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Constraint"), "{}", error);
+        assert!(connection.execute("SELECT * FROM missing;").is_err());
+    }
+}
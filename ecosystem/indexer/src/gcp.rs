@@ -0,0 +1,76 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Access tokens for Google Cloud REST APIs (ex: Spanner, Pub/Sub): a configured one, or else the instance's service
+//! account's, from the GCE metadata server (GKE workload identity, Cloud Run, ...).
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+const METADATA_SERVER_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+/// Tokens from the metadata server are refreshed this long before they expire
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize)]
+struct MetadataServerToken {
+    access_token: String,
+    expires_in: u64,
+}
+
+pub struct AccessTokenProvider {
+    access_token: Option<String>,
+    /// Latest token from the metadata server, and when it expires
+    cached: Mutex<Option<(String, Instant)>>,
+}
+
+impl fmt::Debug for AccessTokenProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "AccessTokenProvider {{ configured: {:?} }}",
+            self.access_token.is_some()
+        )
+    }
+}
+
+impl AccessTokenProvider {
+    /// Uses `access_token` if given, or else the metadata server's tokens
+    pub fn new(access_token: Option<String>) -> Self {
+        Self {
+            access_token,
+            cached: Mutex::new(None),
+        }
+    }
+
+    pub async fn token(&self, client: &reqwest::Client) -> Result<String> {
+        if let Some(token) = &self.access_token {
+            return Ok(token.clone());
+        }
+        if let Some((token, expires_at)) = &*self.cached.lock().unwrap() {
+            if *expires_at > Instant::now() + TOKEN_REFRESH_MARGIN {
+                return Ok(token.clone());
+            }
+        }
+        let token: MetadataServerToken = client
+            .get(METADATA_SERVER_TOKEN_URL)
+            .header("Metadata-Flavor", "Google")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context("Failed to get an access token from the GCE metadata server")?
+            .json()
+            .await
+            .context("Failed to parse the GCE metadata server's access token")?;
+        *self.cached.lock().unwrap() = Some((
+            token.access_token.clone(),
+            Instant::now() + Duration::from_secs(token.expires_in),
+        ));
+        Ok(token.access_token)
+    }
+}
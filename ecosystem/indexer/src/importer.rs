@@ -0,0 +1,368 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Bootstraps the default processor's tables from published dumps of historical transactions, rather than
+//! fetching every version from genesis over REST.
+//!
+//! Each table is read from `<dir>/<table>.csv` (or `.csv.gz`), with a header row of column names, or from
+//! `<dir>/<table>.parquet` (see `parquet` for the files supported). Cells are converted according to the column's type
+//! in the models' JSON schema (see `json_schema`): JSON columns hold JSON text, timestamps may be ISO 8601 (with or
+//! without an offset) or microseconds since the epoch, booleans `true`/`false` or `t`/`f`, and empty cells (or Parquet
+//! nulls) of nullable columns are NULL. `inserted_at` defaults to the time of the import.
+//! Rows are inserted with `ON CONFLICT DO NOTHING`, so an interrupted import can be re-run.
+//!
+//! Once every table is imported, the given processors' statuses are fast-forwarded to the dump's last version, so
+//! `run` resumes tailing from the version after it.
+
+use crate::{
+    database::PgPoolConnection,
+    indexer::processor_metadata::{upsert_processor_statuses, START_VERSION_LOOKBACK},
+    models::{
+        events::EventModel,
+        json_schema::{schema_json, JsonSchemaType},
+        processor_statuses::ProcessorStatusModel,
+        transactions::{BlockMetadataTransactionModel, TransactionModel, UserTransactionModel},
+        write_set_changes::WriteSetChangeModel,
+    },
+    parquet::ParquetFile,
+    processors::default_processor::{
+        insert_block_metadata_transactions, insert_events, insert_transactions,
+        insert_user_transactions, insert_write_set_changes,
+    },
+    util::bigdecimal_to_u64,
+};
+use anyhow::{bail, Context, Result};
+use chrono::NaiveDateTime;
+use diesel::QueryResult;
+use flate2::read::GzDecoder;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+/// The tables imported, in order. Only `transactions` is required.
+pub const TABLES: &[&str] = &[
+    "transactions",
+    "user_transactions",
+    "block_metadata_transactions",
+    "events",
+    "write_set_changes",
+];
+
+/// How many processor statuses are upserted at a time when fast-forwarding
+const STATUS_CHUNK_SIZE: u64 = 100_000;
+
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    /// Rows read from each table's file (including any already in the DB)
+    pub rows: BTreeMap<String, usize>,
+    /// Highest version in the `transactions` file
+    pub max_version: Option<u64>,
+}
+
+/// The file holding `table` in `dir`, if any
+fn table_file(dir: &Path, table: &str) -> Option<PathBuf> {
+    ["csv", "csv.gz", "parquet"]
+        .iter()
+        .map(|extension| dir.join(format!("{}.{}", table, extension)))
+        .find(|path| path.exists())
+}
+
+fn open(path: &Path) -> Result<Box<dyn Read>> {
+    let file = File::open(path).with_context(|| format!("Could not open {:?}", path))?;
+    if path
+        .extension()
+        .map_or(false, |extension| extension == "gz")
+    {
+        Ok(Box::new(GzDecoder::new(file)))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// Parses the timestamp formats found in dumps into the models' representation
+pub fn parse_timestamp_cell(cell: &str) -> Result<NaiveDateTime> {
+    if let Ok(micros) = cell.parse::<i64>() {
+        return NaiveDateTime::from_timestamp_opt(
+            micros.div_euclid(1_000_000),
+            (micros.rem_euclid(1_000_000) * 1000) as u32,
+        )
+        .with_context(|| format!("Timestamp out of range: {}", cell));
+    }
+    let trimmed = cell
+        .trim_end_matches(" UTC")
+        .trim_end_matches('Z')
+        .trim_end_matches("+00:00")
+        .trim_end_matches("+00");
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(trimmed, format).ok())
+        .with_context(|| format!("Invalid timestamp: {}", cell))
+}
+
+/// Converts a cell into the serde representation of a column with the given schema
+pub fn cell_value(schema: &Value, cell: &str) -> Result<Value> {
+    if let Some(variants) = schema["anyOf"].as_array() {
+        if cell.is_empty() {
+            return Ok(Value::Null);
+        }
+        return cell_value(&variants[0], cell);
+    }
+    if *schema == <Value as JsonSchemaType>::json_schema() {
+        return serde_json::from_str(cell).with_context(|| format!("Invalid JSON: {}", cell));
+    }
+    if *schema == <NaiveDateTime as JsonSchemaType>::json_schema() {
+        return Ok(json!(parse_timestamp_cell(cell)?
+            .format("%Y-%m-%dT%H:%M:%S%.f")
+            .to_string()));
+    }
+    match schema["type"].as_str() {
+        Some("integer") => Ok(json!(cell
+            .parse::<i64>()
+            .with_context(|| format!("Invalid integer: {}", cell))?)),
+        Some("boolean") => match cell {
+            "true" | "t" | "1" => Ok(json!(true)),
+            "false" | "f" | "0" => Ok(json!(false)),
+            _ => bail!("Invalid boolean: {}", cell),
+        },
+        _ => Ok(json!(cell)),
+    }
+}
+
+/// The column names of a CSV or Parquet file, and its rows' cells (`None` for Parquet nulls)
+fn read_rows(
+    path: &Path,
+) -> Result<(
+    Vec<String>,
+    Box<dyn Iterator<Item = Result<Vec<Option<String>>>>>,
+)> {
+    if path
+        .extension()
+        .map_or(false, |extension| extension == "parquet")
+    {
+        let file = ParquetFile::open(path)?;
+        let headers = file
+            .columns()
+            .iter()
+            .map(|column| column.name.clone())
+            .collect();
+        return Ok((headers, Box::new(file.rows())));
+    }
+    let mut reader = csv::Reader::from_reader(open(path)?);
+    let headers = reader.headers()?.iter().map(str::to_string).collect();
+    let rows = reader.into_records().map(|record| -> Result<_> {
+        Ok(record?.iter().map(|cell| Some(cell.to_string())).collect())
+    });
+    Ok((headers, Box::new(rows)))
+}
+
+/// Reads the rows of a CSV or Parquet file of `model`s, calling `insert` with `chunk_size` of them at a time
+fn import_file<T, F>(path: &Path, model: &str, chunk_size: usize, mut insert: F) -> Result<usize>
+where
+    T: DeserializeOwned,
+    F: FnMut(&[T]) -> Result<()>,
+{
+    let schema = schema_json();
+    let properties = schema["definitions"][model]["properties"]
+        .as_object()
+        .with_context(|| format!("No schema for {}", model))?;
+    let (headers, rows) = read_rows(path)?;
+    let inserted_at = json!(chrono::Utc::now()
+        .naive_utc()
+        .format("%Y-%m-%dT%H:%M:%S%.f")
+        .to_string());
+
+    let mut num_rows = 0;
+    let mut chunk = Vec::with_capacity(chunk_size);
+    for (index, record) in rows.enumerate() {
+        let record = record.with_context(|| format!("{:?}: invalid row {}", path, index + 1))?;
+        let mut row = serde_json::Map::new();
+        row.insert("inserted_at".to_string(), inserted_at.clone());
+        for (column, cell) in headers.iter().zip(record) {
+            // Columns the model doesn't have (ex: ones added by the dump's publisher) are ignored
+            if let Some(column_schema) = properties.get(column) {
+                let value = match cell {
+                    Some(cell) => cell_value(column_schema, &cell)
+                        .with_context(|| format!("{:?}: row {}, {}", path, index + 1, column))?,
+                    None => Value::Null,
+                };
+                row.insert(column.to_string(), value);
+            }
+        }
+        chunk.push(
+            serde_json::from_value(Value::Object(row))
+                .with_context(|| format!("{:?}: invalid row {}", path, index + 1))?,
+        );
+        if chunk.len() == chunk_size {
+            insert(&chunk)?;
+            num_rows += chunk.len();
+            chunk.clear();
+        }
+    }
+    if !chunk.is_empty() {
+        insert(&chunk)?;
+        num_rows += chunk.len();
+    }
+    Ok(num_rows)
+}
+
+/// Wraps one of the default processor's insert functions
+fn inserter<'a, T: 'a>(
+    conn: &'a PgPoolConnection,
+    insert: fn(&PgPoolConnection, &[T]) -> QueryResult<usize>,
+) -> impl FnMut(&[T]) -> Result<()> + 'a {
+    move |rows| {
+        insert(conn, rows)?;
+        Ok(())
+    }
+}
+
+/// Marks `processor_name` successful up to `max_version`. Only the versions the start version query looks back at
+/// are recorded.
+pub fn fast_forward(conn: &PgPoolConnection, processor_name: &str, max_version: u64) -> Result<()> {
+    let mut start_version = max_version.saturating_sub(START_VERSION_LOOKBACK as u64);
+    while start_version <= max_version {
+        let end_version = std::cmp::min(start_version + STATUS_CHUNK_SIZE - 1, max_version);
+        let statuses = ProcessorStatusModel::from_versions(
+            processor_name,
+            start_version,
+            end_version,
+            true,
+            None,
+        );
+        upsert_processor_statuses(conn, &statuses)?;
+        start_version = end_version + 1;
+    }
+    Ok(())
+}
+
+/// Imports the tables found in `dir`, then fast-forwards `processor_names` to the last imported version
+pub fn import(
+    conn: &PgPoolConnection,
+    dir: &Path,
+    processor_names: &[String],
+    chunk_size: usize,
+) -> Result<ImportSummary> {
+    let mut summary = ImportSummary::default();
+    for table in TABLES {
+        let path = match table_file(dir, table) {
+            Some(path) => path,
+            None if *table == "transactions" => {
+                bail!(
+                    "No transactions.csv, transactions.csv.gz or transactions.parquet in {:?}",
+                    dir
+                )
+            }
+            None => continue,
+        };
+        aptos_logger::info!(table = table, path = format!("{:?}", path), "Importing...");
+        let num_rows = match *table {
+            "transactions" => {
+                let mut max_version = None;
+                let mut insert = inserter(conn, insert_transactions);
+                let num_rows = import_file(
+                    &path,
+                    "Transaction",
+                    chunk_size,
+                    |txns: &[TransactionModel]| {
+                        for txn in txns {
+                            let version = bigdecimal_to_u64(&txn.version)?;
+                            max_version = max_version.max(Some(version));
+                        }
+                        insert(txns)
+                    },
+                )?;
+                summary.max_version = max_version;
+                num_rows
+            }
+            "user_transactions" => import_file::<UserTransactionModel, _>(
+                &path,
+                "UserTransaction",
+                chunk_size,
+                inserter(conn, insert_user_transactions),
+            )?,
+            "block_metadata_transactions" => import_file::<BlockMetadataTransactionModel, _>(
+                &path,
+                "BlockMetadataTransaction",
+                chunk_size,
+                inserter(conn, insert_block_metadata_transactions),
+            )?,
+            "events" => import_file::<EventModel, _>(
+                &path,
+                "Event",
+                chunk_size,
+                inserter(conn, insert_events),
+            )?,
+            "write_set_changes" => import_file::<WriteSetChangeModel, _>(
+                &path,
+                "WriteSetChange",
+                chunk_size,
+                inserter(conn, insert_write_set_changes),
+            )?,
+            _ => unreachable!("Unknown table {}", table),
+        };
+        summary.rows.insert(table.to_string(), num_rows);
+    }
+
+    let max_version = summary
+        .max_version
+        .context("The transactions file has no rows")?;
+    for processor_name in processor_names {
+        fast_forward(conn, processor_name, max_version)?;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_values() {
+        let schema = schema_json();
+        let properties = &schema["definitions"]["Transaction"]["properties"];
+        assert_eq!(
+            cell_value(
+                &properties["payload"],
+                r#"{"function":"0x1::coin::transfer"}"#
+            )
+            .unwrap(),
+            json!({"function": "0x1::coin::transfer"})
+        );
+        assert_eq!(
+            cell_value(&properties["version"], "42").unwrap(),
+            json!("42")
+        );
+        assert_eq!(
+            cell_value(&properties["success"], "t").unwrap(),
+            json!(true)
+        );
+        assert_eq!(
+            cell_value(&properties["num_events"], "3").unwrap(),
+            json!(3)
+        );
+        assert_eq!(
+            cell_value(&properties["payload_size_bytes"], "").unwrap(),
+            Value::Null
+        );
+        assert!(cell_value(&properties["num_events"], "three").is_err());
+
+        for cell in [
+            "2022-10-12T18:00:00.5",
+            "2022-10-12 18:00:00.500000 UTC",
+            "2022-10-12T18:00:00.5Z",
+            "1665597600500000",
+        ] {
+            assert_eq!(
+                parse_timestamp_cell(cell).unwrap(),
+                NaiveDateTime::from_timestamp(1_665_597_600, 500_000_000),
+                "{}",
+                cell
+            );
+        }
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! The accounts each committed batch affected, for consumers which want to be told when an account changes rather
+//! than poll for it: senders, the accounts of emitted events' handles (ex: the recipient of a deposit) and the owners
+//! of written or deleted resources. The `Tailer` gathers them for every batch with commit hooks, and hands them to
+//! each hook's `on_accounts_changed` (see `commit_hooks`).
+//!
+//! `AccountChangeFeed` is a commit hook republishing them to in-process subscribers, each watching a set of accounts,
+//! ex: to push notifications to clients connected to a server embedding the indexer. This tree doesn't have such a
+//! server: it would hold the feed and `subscribe` for each client.
+
+use crate::indexer::commit_hooks::CommitHook;
+use anyhow::Result;
+use aptos_rest_client::{
+    aptos_api_types::{Event, WriteSetChange},
+    Transaction,
+};
+use aptos_types::account_address::AccountAddress;
+use async_trait::async_trait;
+use std::{
+    collections::{BTreeSet, HashSet},
+    sync::Arc,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Batches a subscriber may fall behind by before missing some
+pub const DEFAULT_FEED_CAPACITY: usize = 1024;
+
+/// The accounts affected by versions `start_version` to `end_version` (inclusive), as committed by `processors`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountChanges {
+    pub start_version: u64,
+    pub end_version: u64,
+    pub processors: Vec<String>,
+    pub accounts: BTreeSet<String>,
+}
+
+/// Addresses (as `0x` hex literals, without leading zeros) of the accounts `transactions` affected
+pub fn affected_accounts(transactions: &[Transaction]) -> BTreeSet<String> {
+    let mut accounts = BTreeSet::new();
+    for txn in transactions {
+        let (events, changes): (&[Event], &[WriteSetChange]) = match txn {
+            Transaction::UserTransaction(user_txn) => {
+                accounts.insert(user_txn.request.sender.to_string());
+                (&user_txn.events, &user_txn.info.changes)
+            }
+            Transaction::GenesisTransaction(genesis_txn) => {
+                (&genesis_txn.events, &genesis_txn.info.changes)
+            }
+            Transaction::BlockMetadataTransaction(block_metadata_txn) => {
+                (&block_metadata_txn.events, &block_metadata_txn.info.changes)
+            }
+            Transaction::StateCheckpointTransaction(state_checkpoint_txn) => {
+                (&[], &state_checkpoint_txn.info.changes)
+            }
+            Transaction::PendingTransaction(_) => continue,
+        };
+        for event in events {
+            accounts.insert(event.guid.account_address.to_string());
+        }
+        for change in changes {
+            match change {
+                WriteSetChange::WriteResource(resource) => {
+                    accounts.insert(resource.address.to_string());
+                }
+                WriteSetChange::DeleteResource(resource) => {
+                    accounts.insert(resource.address.to_string());
+                }
+                // Modules are code, and table items don't say which account owns the table
+                _ => (),
+            }
+        }
+    }
+    accounts
+}
+
+/// Republishes each batch's affected accounts to subscribers
+#[derive(Debug)]
+pub struct AccountChangeFeed {
+    sender: broadcast::Sender<Arc<AccountChanges>>,
+}
+
+impl Default for AccountChangeFeed {
+    fn default() -> Self {
+        Self::new(DEFAULT_FEED_CAPACITY)
+    }
+}
+
+impl AccountChangeFeed {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// Notifies the subscriber of the batches changing any of `accounts`, from now on
+    pub fn subscribe(&self, accounts: &[AccountAddress]) -> AccountSubscription {
+        AccountSubscription {
+            receiver: self.sender.subscribe(),
+            accounts: accounts
+                .iter()
+                .map(|account| account.to_hex_literal())
+                .collect(),
+        }
+    }
+
+    pub fn num_subscribers(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+#[async_trait]
+impl CommitHook for AccountChangeFeed {
+    fn name(&self) -> &str {
+        "account_change_feed"
+    }
+
+    async fn on_batch_committed(&self, _: u64, _: u64, _: &[String]) -> Result<()> {
+        Ok(())
+    }
+
+    async fn on_accounts_changed(&self, changes: &AccountChanges) -> Result<()> {
+        // Failing means there's no subscriber at the moment, which is fine
+        let _ = self.sender.send(Arc::new(changes.clone()));
+        Ok(())
+    }
+}
+
+pub struct AccountSubscription {
+    receiver: broadcast::Receiver<Arc<AccountChanges>>,
+    accounts: HashSet<String>,
+}
+
+impl AccountSubscription {
+    /// The next batch changing any of the watched accounts, with only those accounts. Fails with `RecvError::Lagged`
+    /// if the subscriber fell behind and missed batches, after which it resumes from the oldest batch still buffered:
+    /// ex: re-read the watched accounts, as any of them may have changed in between.
+    pub async fn next(&mut self) -> Result<AccountChanges, RecvError> {
+        loop {
+            let changes = self.receiver.recv().await?;
+            let accounts: BTreeSet<String> = changes
+                .accounts
+                .iter()
+                .filter(|account| self.accounts.contains(*account))
+                .cloned()
+                .collect();
+            if !accounts.is_empty() {
+                return Ok(AccountChanges {
+                    accounts,
+                    ..(*changes).clone()
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_account_changes() {
+        let txn: Transaction = serde_json::from_value(json!({
+            "type": "user_transaction",
+            "version": "1",
+            "hash": format!("0x{:064x}", 1),
+            "state_change_hash": format!("0x{:064x}", 0),
+            "event_root_hash": format!("0x{:064x}", 0),
+            "gas_used": "1",
+            "success": true,
+            "vm_status": "Executed successfully",
+            "accumulator_root_hash": format!("0x{:064x}", 0),
+            "changes": [{
+                "type": "write_resource",
+                "address": "0xa11ce",
+                "state_key_hash": "0x1234",
+                "data": {"type": "0x1::coin::CoinStore<0x1::aptos_coin::AptosCoin>", "data": {}},
+            }],
+            "sender": "0xa11ce",
+            "sequence_number": "0",
+            "max_gas_amount": "1000",
+            "gas_unit_price": "100",
+            "expiration_timestamp_secs": "1",
+            "payload": {
+                "type": "entry_function_payload",
+                "function": "0x1::coin::transfer",
+                "type_arguments": ["0x1::aptos_coin::AptosCoin"],
+                "arguments": ["0xb0b", "1"],
+            },
+            "events": [{
+                "guid": {"creation_number": "2", "account_address": "0xb0b"},
+                "sequence_number": "0",
+                "type": "0x1::coin::DepositEvent",
+                "data": {"amount": "1"},
+            }],
+            "timestamp": "1",
+        }))
+        .unwrap();
+        let accounts = affected_accounts(&[txn]);
+        assert_eq!(
+            accounts,
+            BTreeSet::from(["0xa11ce".to_string(), "0xb0b".to_string()])
+        );
+
+        let feed = AccountChangeFeed::new(1);
+        let mut bob = feed.subscribe(&[AccountAddress::from_hex_literal("0xb0b").unwrap()]);
+        let mut carol = feed.subscribe(&[AccountAddress::from_hex_literal("0xca401").unwrap()]);
+        assert_eq!(feed.num_subscribers(), 2);
+        let changes = AccountChanges {
+            start_version: 1,
+            end_version: 1,
+            processors: vec!["default_processor".to_string()],
+            accounts,
+        };
+        feed.on_accounts_changed(&changes).await.unwrap();
+        assert_eq!(
+            bob.next().await.unwrap(),
+            AccountChanges {
+                accounts: BTreeSet::from(["0xb0b".to_string()]),
+                ..changes.clone()
+            }
+        );
+
+        // Carol's account didn't change: she only sees that she fell behind the feed
+        feed.on_accounts_changed(&changes).await.unwrap();
+        assert_eq!(carol.next().await, Err(RecvError::Lagged(1)));
+    }
+}
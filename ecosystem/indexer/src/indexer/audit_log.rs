@@ -0,0 +1,75 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Structured audit log of operator actions, for compliance in production deployments: the inspection service's
+//! admin endpoints (enabling and disabling processors) and the CLI's backfills record who did what, with which
+//! parameters, before doing it. An action which can't be recorded isn't taken. Postgres deployments keep the log in
+//! `operator_audit_log` (`PgAuditLog`); every action is also logged, which is all builds without Postgres get.
+//!
+//! Admin requests name their actor with the `X-Operator` header (the inspection service has no authentication of its
+//! own, so this is declarative, and the request's remote address is recorded alongside).
+
+use crate::models::operator_audit_log::OperatorAction;
+#[cfg(feature = "postgres")]
+use crate::{database::PgDbPool, models::operator_audit_log::OperatorActionModel, schema};
+use anyhow::Result;
+#[cfg(feature = "postgres")]
+use diesel::RunQueryDsl;
+use std::fmt::Debug;
+
+/// Header admin requests name their actor with
+pub const OPERATOR_HEADER: &str = "x-operator";
+/// Actor of admin requests without `OPERATOR_HEADER`
+pub const UNKNOWN_ACTOR: &str = "unknown";
+
+pub trait AuditLog: Debug + Send + Sync {
+    /// Records `action`, which mustn't be taken if this fails
+    fn record(&self, action: &OperatorAction) -> Result<()>;
+}
+
+fn log_action(action: &OperatorAction) {
+    aptos_logger::info!(
+        actor = action.actor,
+        remote_address = action.remote_address,
+        action = action.action,
+        parameters = action.parameters.to_string(),
+        "Operator action"
+    );
+}
+
+/// Only logs actions
+#[derive(Debug, Default)]
+pub struct LoggingAuditLog;
+
+impl AuditLog for LoggingAuditLog {
+    fn record(&self, action: &OperatorAction) -> Result<()> {
+        log_action(action);
+        Ok(())
+    }
+}
+
+/// Records actions into `operator_audit_log`
+#[cfg(feature = "postgres")]
+#[derive(Debug)]
+pub struct PgAuditLog {
+    pool: PgDbPool,
+}
+
+#[cfg(feature = "postgres")]
+impl PgAuditLog {
+    pub fn new(pool: PgDbPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl AuditLog for PgAuditLog {
+    fn record(&self, action: &OperatorAction) -> Result<()> {
+        let conn = self.pool.get()?;
+        diesel::insert_into(schema::operator_audit_log::table)
+            .values(action as &OperatorActionModel)
+            .execute(&conn)?;
+        log_action(action);
+        Ok(())
+    }
+}
@@ -0,0 +1,798 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Sets up the connection pools, processors and their tailers from configuration, so every entry point (the
+//! `aptos-indexer` subcommands, tests, embedding services) wires them the same way.
+
+#[cfg(feature = "storage")]
+use crate::indexer::storage_fetcher::NodeStorage;
+#[cfg(feature = "uri_enricher")]
+use crate::indexer::uri_enricher::{UriEnricher, UriEnricherConfig};
+#[cfg(feature = "vm_decoder")]
+use crate::indexer::vm_decoder;
+#[cfg(feature = "duckdb")]
+use crate::processors::duckdb_processor::{
+    DuckDbConfig, DuckDbTransactionProcessor, NAME as DUCKDB_PROCESSOR_NAME,
+};
+#[cfg(feature = "kinesis")]
+use crate::processors::kinesis_processor::{
+    KinesisConfig, KinesisTransactionProcessor, NAME as KINESIS_PROCESSOR_NAME,
+};
+#[cfg(feature = "neo4j")]
+use crate::processors::neo4j_processor::{
+    Neo4jConfig, Neo4jTransactionProcessor, NAME as NEO4J_PROCESSOR_NAME,
+};
+#[cfg(feature = "pubsub")]
+use crate::processors::pubsub_processor::{
+    PubSubConfig, PubSubTransactionProcessor, NAME as PUBSUB_PROCESSOR_NAME,
+};
+#[cfg(feature = "search")]
+use crate::processors::search_processor::{
+    SearchConfig, SearchTransactionProcessor, NAME as SEARCH_PROCESSOR_NAME,
+};
+use crate::{
+    database::{
+        create_concurrent_indexes, new_db_pool_with_config, run_migrations, validate_dml_role,
+        warm_up_pool, DatabaseConfig, PgDbPool,
+    },
+    indexer::{
+        commit_hooks::{CommitHook, CommitHooks},
+        dispatch::{ConnectionBudget, ProcessorQuota, ProcessorSwitches},
+        fetcher::FetcherConfig,
+        framework_addresses::FrameworkAddresses,
+        metadata_handle::{MetadataBackend, MetadataHandle},
+        outbox_relay::{OutboxRelay, OutboxRelayConfig},
+        price_provider::PriceProvider,
+        tailer::Tailer,
+        timestamps::TimestampNormalizer,
+        transaction_processor::{ProcessorBuilder, TransactionProcessor},
+        transform::TransformHook,
+    },
+    models::{
+        top_holder::DEFAULT_TOP_HOLDERS_COUNT, txn_latency_stat::DEFAULT_EXPIRATION_TTL_SECS,
+    },
+    processors::{
+        account_summary_processor::{
+            AccountSummaryTransactionProcessor, NAME as ACCOUNT_SUMMARY_PROCESSOR_NAME,
+        },
+        default_processor::{DefaultTransactionProcessor, NAME as DEFAULT_PROCESSOR_NAME},
+        epoch_processor::{EpochTransactionProcessor, NAME as EPOCH_PROCESSOR_NAME},
+        fungible_asset_processor::{
+            FungibleAssetTransactionProcessor, NAME as FUNGIBLE_ASSET_PROCESSOR_NAME,
+        },
+        object_processor::{ObjectTransactionProcessor, NAME as OBJECT_PROCESSOR_NAME},
+        swap_processor::{SwapTransactionProcessor, NAME as SWAP_PROCESSOR_NAME},
+        token_processor::{TokenTransactionProcessor, NAME as TOKEN_PROCESSOR_NAME},
+        token_v2_processor::{TokenV2TransactionProcessor, NAME as TOKEN_V2_PROCESSOR_NAME},
+        top_holders_processor::{
+            TopHoldersTransactionProcessor, NAME as TOP_HOLDERS_PROCESSOR_NAME,
+        },
+        transfer_edge_processor::{
+            TransferEdgeTransactionProcessor, NAME as TRANSFER_EDGE_PROCESSOR_NAME,
+        },
+        txn_latency_processor::{
+            TxnLatencyTransactionProcessor, NAME as TXN_LATENCY_PROCESSOR_NAME,
+        },
+    },
+    rollups::RollupTask,
+    timescale,
+};
+#[cfg(feature = "kafka")]
+use crate::{
+    indexer::processor_metadata::PgMetadataHandle,
+    processors::kafka_processor::{
+        KafkaConfig, KafkaTransactionProcessor, NAME as KAFKA_PROCESSOR_NAME,
+    },
+};
+use anyhow::{bail, Context, Result};
+use aptos_logger::info;
+#[cfg(feature = "storage")]
+use std::path::PathBuf;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+#[derive(Clone, Debug)]
+pub struct IndexerBuilder {
+    pg_uri: String,
+    migrations_pg_uri: Option<String>,
+    database_config: DatabaseConfig,
+    metadata_pool_size: Option<u32>,
+    timescale: bool,
+    citus: bool,
+    node_url: Option<String>,
+    fetcher_config: FetcherConfig,
+    /// Paths of the node's DB and of the secondary instance's files
+    #[cfg(feature = "storage")]
+    node_db: Option<(PathBuf, PathBuf)>,
+    fetch_from_raw: bool,
+    processors: Vec<String>,
+    quotas: HashMap<String, ProcessorQuota>,
+    dex_addresses: Vec<String>,
+    expiration_ttl_secs: u64,
+    top_holders_count: u64,
+    index_token_uri_data: bool,
+    enable_rollups: bool,
+    outbox: bool,
+    outbox_relay: Option<OutboxRelayConfig>,
+    archive_raw: bool,
+    parallel_conversion: bool,
+    sub_batches: usize,
+    price_provider: Option<Arc<dyn PriceProvider>>,
+    transform: Option<Arc<dyn TransformHook>>,
+    framework_addresses: Option<Arc<FrameworkAddresses>>,
+    max_timestamp_skew: Option<Duration>,
+    commit_hooks: Vec<Arc<dyn CommitHook>>,
+    sink_metadata: Option<MetadataBackend>,
+    #[cfg(feature = "uri_enricher")]
+    uri_enricher: Option<UriEnricherConfig>,
+    #[cfg(feature = "duckdb")]
+    duckdb: Option<DuckDbConfig>,
+    #[cfg(feature = "kafka")]
+    kafka: Option<KafkaConfig>,
+    #[cfg(feature = "kinesis")]
+    kinesis: Option<KinesisConfig>,
+    #[cfg(feature = "neo4j")]
+    neo4j: Option<Neo4jConfig>,
+    #[cfg(feature = "pubsub")]
+    pubsub: Option<PubSubConfig>,
+    #[cfg(feature = "search")]
+    search: Option<SearchConfig>,
+}
+
+impl IndexerBuilder {
+    pub fn new(pg_uri: impl Into<String>) -> Self {
+        Self {
+            pg_uri: pg_uri.into(),
+            migrations_pg_uri: None,
+            database_config: DatabaseConfig::default(),
+            metadata_pool_size: None,
+            timescale: false,
+            citus: false,
+            node_url: None,
+            fetcher_config: FetcherConfig::default(),
+            #[cfg(feature = "storage")]
+            node_db: None,
+            fetch_from_raw: false,
+            processors: vec![],
+            quotas: HashMap::new(),
+            dex_addresses: vec![],
+            expiration_ttl_secs: DEFAULT_EXPIRATION_TTL_SECS,
+            top_holders_count: DEFAULT_TOP_HOLDERS_COUNT,
+            index_token_uri_data: false,
+            enable_rollups: false,
+            outbox: false,
+            outbox_relay: None,
+            archive_raw: false,
+            parallel_conversion: false,
+            sub_batches: 1,
+            price_provider: None,
+            transform: None,
+            framework_addresses: None,
+            max_timestamp_skew: None,
+            commit_hooks: vec![],
+            sink_metadata: None,
+            #[cfg(feature = "uri_enricher")]
+            uri_enricher: None,
+            #[cfg(feature = "duckdb")]
+            duckdb: None,
+            #[cfg(feature = "kafka")]
+            kafka: None,
+            #[cfg(feature = "kinesis")]
+            kinesis: None,
+            #[cfg(feature = "neo4j")]
+            neo4j: None,
+            #[cfg(feature = "pubsub")]
+            pubsub: None,
+            #[cfg(feature = "search")]
+            search: None,
+        }
+    }
+
+    pub fn database_config(mut self, database_config: DatabaseConfig) -> Self {
+        self.database_config = database_config;
+        self
+    }
+
+    /// If set, migrations (and the Timescale setup) run as this URL's user, and the user of the data pools is checked
+    /// to have no DDL rights, so the long-running service can't change the schema
+    pub fn migrations_pg_uri(mut self, migrations_pg_uri: Option<String>) -> Self {
+        self.migrations_pg_uri = migrations_pg_uri;
+        self
+    }
+
+    /// If set, statuses and other metadata are written through a separate pool of this size
+    pub fn metadata_pool_size(mut self, metadata_pool_size: Option<u32>) -> Self {
+        self.metadata_pool_size = metadata_pool_size;
+        self
+    }
+
+    /// Set up TimescaleDB hypertables and continuous aggregates after running migrations (see `timescale`)
+    pub fn timescale(mut self, timescale: bool) -> Self {
+        self.timescale = timescale;
+        self
+    }
+
+    /// Validate that the tables are distributed suitably for Citus (see `citus`) before running
+    pub fn citus(mut self, citus: bool) -> Self {
+        self.citus = citus;
+        self
+    }
+
+    /// URL of the node to fetch transactions from. Required to build tailers.
+    pub fn node_url(mut self, node_url: impl Into<String>) -> Self {
+        self.node_url = Some(node_url.into());
+        self
+    }
+
+    /// Timeouts, connection pooling and protocol of the tailers' connections to the node
+    pub fn fetcher_config(mut self, fetcher_config: FetcherConfig) -> Self {
+        self.fetcher_config = fetcher_config;
+        self
+    }
+
+    /// If set, the tailers read transactions from the DB of a fullnode on the same host (at the first path), opened
+    /// as a secondary instance keeping its files at the second path, instead of from `node_url` (see
+    /// `storage_fetcher`)
+    #[cfg(feature = "storage")]
+    pub fn node_db(mut self, node_db: Option<(PathBuf, PathBuf)>) -> Self {
+        self.node_db = node_db;
+        self
+    }
+
+    /// If set, the tailers read transactions from `raw_transactions` (see `archive_raw`) instead of from `node_url`,
+    /// ex: to backfill a new processor (see `raw_fetcher`)
+    pub fn fetch_from_raw(mut self, fetch_from_raw: bool) -> Self {
+        self.fetch_from_raw = fetch_from_raw;
+        self
+    }
+
+    /// Names of the processors to run, each with its own tailer. See `build_processor` for the naming.
+    pub fn processors(mut self, processors: Vec<String>) -> Self {
+        self.processors = processors;
+        self
+    }
+
+    pub fn processor_quota(
+        mut self,
+        processor_name: impl Into<String>,
+        quota: ProcessorQuota,
+    ) -> Self {
+        self.quotas.insert(processor_name.into(), quota);
+        self
+    }
+
+    /// Addresses of the DEX modules whose swap events the swap processor decodes
+    pub fn dex_addresses(mut self, dex_addresses: Vec<String>) -> Self {
+        self.dex_addresses = dex_addresses;
+        self
+    }
+
+    /// How long before their expiration the txn latency processor assumes transactions were submitted
+    pub fn expiration_ttl_secs(mut self, expiration_ttl_secs: u64) -> Self {
+        self.expiration_ttl_secs = expiration_ttl_secs;
+        self
+    }
+
+    /// How many of the largest holders of each asset the top holders processor serves
+    pub fn top_holders_count(mut self, top_holders_count: u64) -> Self {
+        self.top_holders_count = top_holders_count;
+        self
+    }
+
+    pub fn index_token_uri_data(mut self, index_token_uri_data: bool) -> Self {
+        self.index_token_uri_data = index_token_uri_data;
+        self
+    }
+
+    /// Maintain the rollup tables from the first processor's tailer
+    pub fn enable_rollups(mut self, enable_rollups: bool) -> Self {
+        self.enable_rollups = enable_rollups;
+        self
+    }
+
+    /// Write a message per event into `outbox` from the default processor, for external relays
+    pub fn outbox(mut self, outbox: bool) -> Self {
+        self.outbox = outbox;
+        self
+    }
+
+    /// If set, deliver the messages in `outbox` to an HTTP endpoint in the background
+    pub fn outbox_relay(mut self, config: Option<OutboxRelayConfig>) -> Self {
+        self.outbox_relay = config;
+        self
+    }
+
+    /// Write each transaction as fetched into `raw_transactions` from the default processor, for later backfills.
+    /// The archive would keep what `transform` drops or hashes, so the two can't be combined.
+    pub fn archive_raw(mut self, archive_raw: bool) -> Self {
+        self.archive_raw = archive_raw;
+        self
+    }
+
+    /// Convert batches into models on rayon's thread pool, for processors which support it
+    pub fn parallel_conversion(mut self, parallel_conversion: bool) -> Self {
+        self.parallel_conversion = parallel_conversion;
+        self
+    }
+
+    /// Convert each batch as this many concurrent sub-batches, for processors which support it
+    pub fn sub_batches(mut self, sub_batches: usize) -> Self {
+        self.sub_batches = sub_batches;
+        self
+    }
+
+    /// Transforms the default processor's models before they're inserted, ex: to drop or hash columns (see
+    /// `transform`)
+    pub fn transform(mut self, transform: Option<Arc<dyn TransformHook>>) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// Where a permissioned chain deploys the framework modules, mapped to the canonical addresses in every batch so
+    /// all processors match types as on public networks (see `framework_addresses`)
+    pub fn framework_addresses(mut self, framework_addresses: Option<FrameworkAddresses>) -> Self {
+        self.framework_addresses = framework_addresses
+            .filter(|framework_addresses| !framework_addresses.is_empty())
+            .map(Arc::new);
+        self
+    }
+
+    /// If set, timestamps more than `max_timestamp_skew` ahead of the host's clock are clamped, and timestamps never
+    /// decrease with versions, in every batch (see `timestamps`)
+    pub fn max_timestamp_skew(mut self, max_timestamp_skew: Option<Duration>) -> Self {
+        self.max_timestamp_skew = max_timestamp_skew;
+        self
+    }
+
+    /// Runs `commit_hook` after each batch any processor commits, ex: to invalidate caches (see `commit_hooks`)
+    pub fn commit_hook(mut self, commit_hook: Arc<dyn CommitHook>) -> Self {
+        self.commit_hooks.push(commit_hook);
+        self
+    }
+
+    /// Prices the fungible asset processor attaches USD values to activities with
+    pub fn price_provider(mut self, price_provider: Option<Arc<dyn PriceProvider>>) -> Self {
+        self.price_provider = price_provider;
+        self
+    }
+
+    /// If set, the sink processors (which write outside of Postgres, ex: the `kafka_processor`) record their statuses
+    /// and the chain id there rather than in Postgres. The other processors keep recording theirs in Postgres, within
+    /// their data write transactions.
+    pub fn sink_metadata(mut self, sink_metadata: Option<MetadataBackend>) -> Self {
+        self.sink_metadata = sink_metadata;
+        self
+    }
+
+    /// If set, fetch the off-chain metadata of indexed tokens' URIs in the background
+    #[cfg(feature = "uri_enricher")]
+    pub fn uri_enricher(mut self, config: Option<UriEnricherConfig>) -> Self {
+        self.uri_enricher = config;
+        self
+    }
+
+    /// DuckDB file the `duckdb_processor` appends to. Required to build it.
+    #[cfg(feature = "duckdb")]
+    pub fn duckdb(mut self, config: Option<DuckDbConfig>) -> Self {
+        self.duckdb = config;
+        self
+    }
+
+    /// REST Proxy and topic the `kafka_processor` produces to. Required to build it.
+    #[cfg(feature = "kafka")]
+    pub fn kafka(mut self, config: Option<KafkaConfig>) -> Self {
+        self.kafka = config;
+        self
+    }
+
+    /// Kinesis data stream or Firehose delivery stream the `kinesis_processor` writes to. Required to build it.
+    #[cfg(feature = "kinesis")]
+    pub fn kinesis(mut self, config: Option<KinesisConfig>) -> Self {
+        self.kinesis = config;
+        self
+    }
+
+    /// Neo4j server the `neo4j_processor` writes to. Required to build it.
+    #[cfg(feature = "neo4j")]
+    pub fn neo4j(mut self, config: Option<Neo4jConfig>) -> Self {
+        self.neo4j = config;
+        self
+    }
+
+    /// Pub/Sub topic the `pubsub_processor` publishes to. Required to build it.
+    #[cfg(feature = "pubsub")]
+    pub fn pubsub(mut self, config: Option<PubSubConfig>) -> Self {
+        self.pubsub = config;
+        self
+    }
+
+    /// Elasticsearch or OpenSearch cluster the `search_processor` writes to. Required to build it.
+    #[cfg(feature = "search")]
+    pub fn search(mut self, config: Option<SearchConfig>) -> Self {
+        self.search = config;
+        self
+    }
+
+    /// Creates and warms up the data pool and (if configured) the separate metadata pool
+    pub fn build_pools(&self) -> Result<(PgDbPool, PgDbPool)> {
+        let conn_pool = new_db_pool_with_config(&self.pg_uri, &self.database_config)
+            .context("Failed to create connection pool")?;
+        warm_up_pool(&conn_pool).context("Failed to warm up connection pool")?;
+        info!("Created the connection pool... ");
+        let metadata_pool = match self.metadata_pool_size {
+            Some(max_size) => {
+                let metadata_pool = new_db_pool_with_config(
+                    &self.pg_uri,
+                    &DatabaseConfig {
+                        max_size: Some(max_size),
+                        min_idle: None,
+                        ..self.database_config.clone()
+                    },
+                )
+                .context("Failed to create metadata connection pool")?;
+                warm_up_pool(&metadata_pool)
+                    .context("Failed to warm up metadata connection pool")?;
+                info!(
+                    max_size = max_size,
+                    "Created the metadata connection pool... "
+                );
+                metadata_pool
+            }
+            None => conn_pool.clone(),
+        };
+        Ok((conn_pool, metadata_pool))
+    }
+
+    /// Creates a single connection pool for the migrations user, if one is configured. Connections are established
+    /// when migrations run.
+    pub fn build_migrations_pool(&self) -> Result<Option<PgDbPool>> {
+        self.migrations_pg_uri
+            .as_ref()
+            .map(|migrations_pg_uri| {
+                new_db_pool_with_config(
+                    migrations_pg_uri,
+                    &DatabaseConfig {
+                        max_size: Some(1),
+                        min_idle: Some(0),
+                        // The URL secret is `pg_uri`'s
+                        url_secret: None,
+                        ..self.database_config.clone()
+                    },
+                )
+                .context("Failed to create migrations connection pool")
+            })
+            .transpose()
+    }
+
+    /// Builds the processor named `processor_name`: a processor type, ex: "token_processor", optionally followed by
+    /// ":<instance>" to run several instances of a type, ex: "token_processor:backfill". Statuses are recorded
+    /// under the whole name, so each instance tracks its progress independently, through `sink_metadata_handle` (if
+    /// set) for sinks.
+    #[cfg_attr(
+        not(any(
+            feature = "duckdb",
+            feature = "kafka",
+            feature = "kinesis",
+            feature = "neo4j",
+            feature = "pubsub",
+            feature = "search"
+        )),
+        allow(unused_variables)
+    )]
+    fn build_processor(
+        &self,
+        processor_name: &str,
+        conn_pool: &PgDbPool,
+        metadata_pool: &PgDbPool,
+        sink_metadata_handle: &Option<Arc<dyn MetadataHandle>>,
+    ) -> Result<Arc<dyn TransactionProcessor>> {
+        let processor_type = processor_name
+            .split_once(':')
+            .map_or(processor_name, |(processor_type, _)| processor_type);
+        let name = processor_name.to_string();
+        Ok(match processor_type {
+            DEFAULT_PROCESSOR_NAME => {
+                if self.archive_raw && self.transform.is_some() {
+                    bail!(
+                        "Raw transactions can't be archived with a transform, as the archive would bypass it"
+                    );
+                }
+                Arc::new(
+                    DefaultTransactionProcessor::new(conn_pool.clone())
+                        .with_name(name)
+                        .with_metadata_pool(metadata_pool.clone())
+                        .with_parallel_conversion(self.parallel_conversion)
+                        .with_outbox(self.outbox)
+                        .with_archive_raw(self.archive_raw)
+                        .with_transform(self.transform.clone()),
+                )
+            }
+            TOKEN_PROCESSOR_NAME => Arc::new(
+                TokenTransactionProcessor::new(conn_pool.clone(), self.index_token_uri_data)
+                    .with_name(name)
+                    .with_metadata_pool(metadata_pool.clone()),
+            ),
+            SWAP_PROCESSOR_NAME => Arc::new(
+                SwapTransactionProcessor::new(conn_pool.clone(), self.dex_addresses.clone())
+                    .with_name(name)
+                    .with_metadata_pool(metadata_pool.clone()),
+            ),
+            EPOCH_PROCESSOR_NAME => Arc::new(
+                EpochTransactionProcessor::new(conn_pool.clone())
+                    .with_name(name)
+                    .with_metadata_pool(metadata_pool.clone()),
+            ),
+            OBJECT_PROCESSOR_NAME => Arc::new(
+                ObjectTransactionProcessor::new(conn_pool.clone())
+                    .with_name(name)
+                    .with_metadata_pool(metadata_pool.clone()),
+            ),
+            FUNGIBLE_ASSET_PROCESSOR_NAME => Arc::new(
+                FungibleAssetTransactionProcessor::new(conn_pool.clone())
+                    .with_name(name)
+                    .with_metadata_pool(metadata_pool.clone())
+                    .with_price_provider(self.price_provider.clone()),
+            ),
+            TOKEN_V2_PROCESSOR_NAME => Arc::new(
+                TokenV2TransactionProcessor::new(conn_pool.clone())
+                    .with_name(name)
+                    .with_metadata_pool(metadata_pool.clone())
+                    .with_sub_batches(self.sub_batches),
+            ),
+            TRANSFER_EDGE_PROCESSOR_NAME => Arc::new(
+                TransferEdgeTransactionProcessor::new(conn_pool.clone())
+                    .with_name(name)
+                    .with_metadata_pool(metadata_pool.clone()),
+            ),
+            ACCOUNT_SUMMARY_PROCESSOR_NAME => Arc::new(
+                AccountSummaryTransactionProcessor::new(conn_pool.clone())
+                    .with_name(name)
+                    .with_metadata_pool(metadata_pool.clone()),
+            ),
+            TXN_LATENCY_PROCESSOR_NAME => Arc::new(
+                TxnLatencyTransactionProcessor::new(conn_pool.clone())
+                    .with_name(name)
+                    .with_metadata_pool(metadata_pool.clone())
+                    .with_expiration_ttl_secs(self.expiration_ttl_secs),
+            ),
+            TOP_HOLDERS_PROCESSOR_NAME => Arc::new(
+                TopHoldersTransactionProcessor::new(conn_pool.clone())
+                    .with_name(name)
+                    .with_metadata_pool(metadata_pool.clone())
+                    .with_num_holders(self.top_holders_count),
+            ),
+            #[cfg(feature = "duckdb")]
+            DUCKDB_PROCESSOR_NAME => Arc::new(
+                DuckDbTransactionProcessor::new(
+                    conn_pool.clone(),
+                    self.duckdb
+                        .clone()
+                        .context("A DuckDB file is required to build the duckdb_processor")?,
+                )
+                .with_name(name)
+                .with_metadata_pool(metadata_pool.clone())
+                .with_metadata_handle(sink_metadata_handle.clone()),
+            ),
+            #[cfg(feature = "kafka")]
+            KAFKA_PROCESSOR_NAME => Arc::new(
+                KafkaTransactionProcessor::new(
+                    self.kafka
+                        .clone()
+                        .context("A Kafka topic is required to build the kafka_processor")?,
+                    sink_metadata_handle
+                        .clone()
+                        .unwrap_or_else(|| Arc::new(PgMetadataHandle::new(metadata_pool.clone()))),
+                )?
+                .with_name(name),
+            ),
+            #[cfg(feature = "kinesis")]
+            KINESIS_PROCESSOR_NAME => Arc::new(
+                KinesisTransactionProcessor::new(
+                    conn_pool.clone(),
+                    self.kinesis
+                        .clone()
+                        .context("A Kinesis stream is required to build the kinesis_processor")?,
+                )?
+                .with_name(name)
+                .with_metadata_pool(metadata_pool.clone())
+                .with_metadata_handle(sink_metadata_handle.clone()),
+            ),
+            #[cfg(feature = "neo4j")]
+            NEO4J_PROCESSOR_NAME => Arc::new(
+                Neo4jTransactionProcessor::new(
+                    conn_pool.clone(),
+                    self.neo4j
+                        .clone()
+                        .context("A Neo4j url is required to build the neo4j_processor")?,
+                )?
+                .with_name(name)
+                .with_metadata_pool(metadata_pool.clone())
+                .with_metadata_handle(sink_metadata_handle.clone()),
+            ),
+            #[cfg(feature = "pubsub")]
+            PUBSUB_PROCESSOR_NAME => Arc::new(
+                PubSubTransactionProcessor::new(
+                    conn_pool.clone(),
+                    self.pubsub
+                        .clone()
+                        .context("A Pub/Sub topic is required to build the pubsub_processor")?,
+                )?
+                .with_name(name)
+                .with_metadata_pool(metadata_pool.clone())
+                .with_metadata_handle(sink_metadata_handle.clone()),
+            ),
+            #[cfg(feature = "search")]
+            SEARCH_PROCESSOR_NAME => Arc::new(
+                SearchTransactionProcessor::new(
+                    conn_pool.clone(),
+                    self.search
+                        .clone()
+                        .context("A search url is required to build the search_processor")?,
+                )?
+                .with_name(name)
+                .with_metadata_pool(metadata_pool.clone())
+                .with_metadata_handle(sink_metadata_handle.clone()),
+            ),
+            _ => bail!("Processor unsupported {}", processor_name),
+        })
+    }
+
+    /// Creates the pools, and a tailer for each processor sharing them
+    pub fn build(self) -> Result<Indexer> {
+        let node_url = self
+            .node_url
+            .clone()
+            .context("A node url is required to build tailers")?;
+        #[cfg(feature = "vm_decoder")]
+        if self.fetcher_config.module_cache.is_some() {
+            if let Some(processor_name) = self
+                .processors
+                .iter()
+                .find(|processor_name| vm_decoder::reads_table_items(processor_name))
+            {
+                bail!(
+                    "{} reads table items, which aren't decoded from BCS: run it without --node-bcs",
+                    processor_name
+                );
+            }
+        }
+        let (conn_pool, metadata_pool) = self.build_pools()?;
+        let migrations_pool = self.build_migrations_pool()?;
+        let connection_budget = Arc::new(ConnectionBudget::new(conn_pool.max_size() as usize));
+        #[cfg(not(feature = "storage"))]
+        let fetcher_config = self.fetcher_config.clone();
+        // The tailers share one secondary instance of the node's DB
+        #[cfg(feature = "storage")]
+        let fetcher_config = match &self.node_db {
+            Some((db_path, secondary_path)) => FetcherConfig {
+                node_storage: Some(NodeStorage::open(db_path, secondary_path)?),
+                ..self.fetcher_config.clone()
+            },
+            None => self.fetcher_config.clone(),
+        };
+        let fetcher_config = FetcherConfig {
+            raw_transactions_pool: self.fetch_from_raw.then(|| conn_pool.clone()),
+            ..fetcher_config
+        };
+
+        let sink_metadata_handle = self
+            .sink_metadata
+            .as_ref()
+            .map(MetadataBackend::open)
+            .transpose()
+            .context("Failed to open the sinks' metadata backend")?;
+        let commit_hooks = Arc::new(CommitHooks(self.commit_hooks.clone()));
+        let mut tailers = vec![];
+        for processor_name in &self.processors {
+            info!(processor_name = processor_name, "Instantiating tailer... ");
+            let processor = self.build_processor(
+                processor_name,
+                &conn_pool,
+                &metadata_pool,
+                &sink_metadata_handle,
+            )?;
+            // The tailer only reads and writes metadata (statuses, chain id, rollups), after batches are processed
+            let mut tailer = Tailer::new(&node_url, metadata_pool.clone(), processor)
+                .context("Failed to instantiate tailer")?;
+            tailer.set_fetcher_config(&node_url, &fetcher_config)?;
+            if let Some(framework_addresses) = &self.framework_addresses {
+                tailer.set_framework_addresses(framework_addresses.clone());
+            }
+            if let Some(max_timestamp_skew) = self.max_timestamp_skew {
+                tailer.set_timestamp_normalizer(Arc::new(TimestampNormalizer::new(
+                    max_timestamp_skew,
+                )));
+            }
+            tailer.set_commit_hooks(commit_hooks.clone());
+            if self.enable_rollups && tailers.is_empty() {
+                tailer.set_rollup_task(RollupTask::with_default_rollups());
+            }
+            tailer.set_quota(
+                self.quotas.get(processor_name).cloned().unwrap_or_default(),
+                Some(connection_budget.clone()),
+            );
+            tailers.push((processor_name.clone(), tailer));
+        }
+        #[cfg(feature = "uri_enricher")]
+        let uri_enricher = self
+            .uri_enricher
+            .clone()
+            .map(|config| UriEnricher::new(conn_pool.clone(), config))
+            .transpose()?;
+        let outbox_relay = self
+            .outbox_relay
+            .clone()
+            .map(|config| OutboxRelay::new(conn_pool.clone(), config))
+            .transpose()?;
+        Ok(Indexer {
+            conn_pool,
+            metadata_pool,
+            migrations_pool,
+            tailers,
+            #[cfg(feature = "uri_enricher")]
+            uri_enricher,
+            outbox_relay,
+            timescale: self.timescale,
+            citus: self.citus,
+        })
+    }
+}
+
+/// The pools and a tailer per processor, as configured by an `IndexerBuilder`
+pub struct Indexer {
+    pub conn_pool: PgDbPool,
+    pub metadata_pool: PgDbPool,
+    /// Pool of the DDL-capable user migrations run as, if separate from the data pools' user
+    pub migrations_pool: Option<PgDbPool>,
+    pub tailers: Vec<(String, Tailer)>,
+    #[cfg(feature = "uri_enricher")]
+    pub uri_enricher: Option<UriEnricher>,
+    pub outbox_relay: Option<OutboxRelay>,
+    pub timescale: bool,
+    pub citus: bool,
+}
+
+impl Indexer {
+    /// Runs pending migrations, then sets up Timescale if enabled, as the migrations user if there's one. The
+    /// `CONCURRENT_INDEXES` missing are then built in the background, as they can take long on large tables: queries
+    /// which need them are slower until they're built, but writes aren't blocked.
+    pub fn run_migrations(&self) {
+        let migrations_pool = self.migrations_pool.as_ref().unwrap_or(&self.conn_pool);
+        run_migrations(migrations_pool);
+        if self.timescale {
+            timescale::setup(
+                &migrations_pool
+                    .get()
+                    .expect("Could not get connection for Timescale"),
+            )
+            .expect("Timescale setup failed!");
+        }
+        let migrations_pool = migrations_pool.clone();
+        let timescale = self.timescale;
+        std::thread::spawn(move || {
+            if let Err(e) = migrations_pool
+                .get()
+                .map_err(anyhow::Error::from)
+                .and_then(|conn| create_concurrent_indexes(&conn, timescale))
+            {
+                aptos_logger::error!("Could not build indexes: {:?}", e);
+            }
+        });
+    }
+
+    /// The switch of each tailer's processor, to enable or disable them at runtime
+    pub fn processor_switches(&self) -> ProcessorSwitches {
+        self.tailers
+            .iter()
+            .map(|(processor_name, tailer)| (processor_name.clone(), tailer.switch()))
+            .collect()
+    }
+
+    /// With a separate migrations user, checks the data pools' user can read and write every table but can't
+    /// change the schema
+    pub fn validate_roles(&self) -> Result<()> {
+        if self.migrations_pool.is_some() {
+            validate_dml_role(&self.conn_pool.get()?)?;
+        }
+        Ok(())
+    }
+}
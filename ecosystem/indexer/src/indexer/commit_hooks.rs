@@ -0,0 +1,211 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Hooks the `Tailer` calls once a processor has committed a batch, ex: to purge the CDN or Redis keys of whatever
+//! the batch changed. Hooks run in the background: the next batch doesn't wait for them, and a failing hook is logged
+//! and counted (`indexer_commit_hook_error_count`) without failing the batch, which is already committed. A hook
+//! which must see every batch should make its effects idempotent, since batches are replayed after a restart.
+//!
+//! Each batch's affected accounts are also handed to `on_accounts_changed` (see `account_changes`), ex: to only purge
+//! the keys of those accounts.
+
+use crate::{counters::COMMIT_HOOK_ERRORS, indexer::account_changes::AccountChanges};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::{fmt::Debug, sync::Arc, time::Duration};
+use url::Url;
+
+#[async_trait]
+pub trait CommitHook: Send + Sync + Debug {
+    /// name of the hook, for logging and metrics
+    fn name(&self) -> &str;
+
+    /// Called once `processors` have committed versions `start_version` to `end_version` (inclusive)
+    async fn on_batch_committed(
+        &self,
+        start_version: u64,
+        end_version: u64,
+        processors: &[String],
+    ) -> Result<()>;
+
+    /// Called after `on_batch_committed`, with the accounts the batch affected
+    async fn on_accounts_changed(&self, _changes: &AccountChanges) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The hooks registered on a tailer
+#[derive(Debug, Default)]
+pub struct CommitHooks(pub Vec<Arc<dyn CommitHook>>);
+
+impl CommitHooks {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Runs every hook in its own task, without waiting for them
+    pub fn notify(&self, changes: AccountChanges) {
+        let changes = Arc::new(changes);
+        for hook in &self.0 {
+            let hook = hook.clone();
+            let changes = changes.clone();
+            tokio::spawn(async move {
+                let result = match hook
+                    .on_batch_committed(
+                        changes.start_version,
+                        changes.end_version,
+                        &changes.processors,
+                    )
+                    .await
+                {
+                    Ok(()) => hook.on_accounts_changed(&changes).await,
+                    Err(err) => Err(err),
+                };
+                if let Err(err) = result {
+                    COMMIT_HOOK_ERRORS.with_label_values(&[hook.name()]).inc();
+                    aptos_logger::error!(
+                        hook_name = hook.name(),
+                        start_version = changes.start_version,
+                        end_version = changes.end_version,
+                        error = format!("{:?}", err),
+                        "Commit hook failed"
+                    );
+                }
+            });
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CommittedBatch<'a> {
+    start_version: u64,
+    end_version: u64,
+    processors: &'a [String],
+}
+
+/// POSTs each committed batch as JSON (`{"start_version": .., "end_version": .., "processors": [..]}`) to a URL, ex:
+/// a service purging caches, which doesn't need to link against the indexer
+#[derive(Debug)]
+pub struct WebhookCommitHook {
+    client: reqwest::Client,
+    url: Url,
+}
+
+impl WebhookCommitHook {
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    pub fn new(url: Url) -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .timeout(Self::TIMEOUT)
+                .build()
+                .context("Failed to build the webhook's HTTP client")?,
+            url,
+        })
+    }
+}
+
+#[async_trait]
+impl CommitHook for WebhookCommitHook {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    async fn on_batch_committed(
+        &self,
+        start_version: u64,
+        end_version: u64,
+        processors: &[String],
+    ) -> Result<()> {
+        self.client
+            .post(self.url.clone())
+            .json(&CommittedBatch {
+                start_version,
+                end_version,
+                processors,
+            })
+            .send()
+            .await
+            .with_context(|| format!("Failed to call {}", self.url))?
+            .error_for_status()
+            .with_context(|| format!("{} rejected the batch", self.url))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct RecordingHook {
+        batches: Mutex<Vec<(u64, u64, Vec<String>)>>,
+    }
+
+    #[async_trait]
+    impl CommitHook for RecordingHook {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        async fn on_batch_committed(
+            &self,
+            start_version: u64,
+            end_version: u64,
+            processors: &[String],
+        ) -> Result<()> {
+            self.batches
+                .lock()
+                .unwrap()
+                .push((start_version, end_version, processors.to_vec()));
+            Ok(())
+        }
+    }
+
+    #[derive(Debug)]
+    struct FailingHook;
+
+    #[async_trait]
+    impl CommitHook for FailingHook {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        async fn on_batch_committed(&self, _: u64, _: u64, _: &[String]) -> Result<()> {
+            anyhow::bail!("Cache unavailable")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commit_hooks() {
+        let recording = Arc::new(RecordingHook::default());
+        let hooks = CommitHooks(vec![Arc::new(FailingHook), recording.clone()]);
+        let failures = COMMIT_HOOK_ERRORS.with_label_values(&["failing"]).get();
+
+        hooks.notify(AccountChanges {
+            start_version: 10,
+            end_version: 19,
+            processors: vec!["default_processor".to_string()],
+            accounts: Default::default(),
+        });
+        for _ in 0..100 {
+            if !recording.batches.lock().unwrap().is_empty()
+                && COMMIT_HOOK_ERRORS.with_label_values(&["failing"]).get() > failures
+            {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        // A failing hook doesn't keep the others from running
+        assert_eq!(
+            *recording.batches.lock().unwrap(),
+            vec![(10, 19, vec!["default_processor".to_string()])]
+        );
+        assert_eq!(
+            COMMIT_HOOK_ERRORS.with_label_values(&["failing"]).get(),
+            failures + 1
+        );
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Limits on how much of the shared connection pool each processor may use when several processors run in
+//! the same indexer, and switches to pause processors at runtime.
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{oneshot, watch};
+
+/// How a processor's batches are dispatched
+#[derive(Clone, Debug, Default)]
+pub struct ProcessorQuota {
+    /// When connections are contended, processors with a higher priority are served first
+    pub priority: u8,
+    /// Maximum number of batches (and so DB connections) this processor may work on concurrently
+    pub max_concurrent_connections: Option<usize>,
+}
+
+/// A semaphore shared by all processors, sized to the connection pool. Unlike `tokio::sync::Semaphore`, waiters
+/// are woken in priority order (FIFO within a priority), so a heavyweight low priority processor can't starve a
+/// latency-critical one.
+#[derive(Debug)]
+pub struct ConnectionBudget {
+    state: Mutex<BudgetState>,
+}
+
+#[derive(Debug)]
+struct BudgetState {
+    available: usize,
+    next_ticket: u64,
+    waiters: BinaryHeap<Waiter>,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    priority: u8,
+    ticket: u64,
+    sender: oneshot::Sender<()>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    /// Higher priority first, then whoever has been waiting the longest
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.ticket.cmp(&self.ticket))
+    }
+}
+
+/// Returned to the budget when dropped
+#[derive(Debug)]
+pub struct ConnectionPermit {
+    budget: Arc<ConnectionBudget>,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.budget.release();
+    }
+}
+
+impl ConnectionBudget {
+    pub fn new(permits: usize) -> Self {
+        Self {
+            state: Mutex::new(BudgetState {
+                available: permits,
+                next_ticket: 0,
+                waiters: BinaryHeap::new(),
+            }),
+        }
+    }
+
+    pub async fn acquire(self: Arc<Self>, priority: u8) -> ConnectionPermit {
+        let receiver = {
+            let mut state = self.state.lock().unwrap();
+            let outranked = state
+                .waiters
+                .peek()
+                .map_or(false, |waiter| waiter.priority >= priority);
+            if state.available > 0 && !outranked {
+                state.available -= 1;
+                None
+            } else {
+                let (sender, receiver) = oneshot::channel();
+                let ticket = state.next_ticket;
+                state.next_ticket += 1;
+                state.waiters.push(Waiter {
+                    priority,
+                    ticket,
+                    sender,
+                });
+                Some(receiver)
+            }
+        };
+        if let Some(receiver) = receiver {
+            receiver
+                .await
+                .expect("Permits are only handed over, never dropped");
+        }
+        ConnectionPermit { budget: self }
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        // Hand the permit straight to the highest priority waiter that is still around
+        while let Some(waiter) = state.waiters.pop() {
+            if waiter.sender.send(()).is_ok() {
+                return;
+            }
+        }
+        state.available += 1;
+    }
+}
+
+/// Enables or disables a processor at runtime, ex: to shed load during an incident without restarting. While
+/// disabled, its batches wait before being dispatched, so its status stops advancing and, once its pipeline's
+/// channels are full, it stops fetching. Its fetcher keeps its position, so it resumes where it stopped.
+#[derive(Debug)]
+pub struct ProcessorSwitch {
+    sender: watch::Sender<bool>,
+    receiver: watch::Receiver<bool>,
+}
+
+/// The switch of each running processor, by name
+pub type ProcessorSwitches = HashMap<String, Arc<ProcessorSwitch>>;
+
+impl Default for ProcessorSwitch {
+    fn default() -> Self {
+        let (sender, receiver) = watch::channel(true);
+        Self { sender, receiver }
+    }
+}
+
+impl ProcessorSwitch {
+    pub fn is_enabled(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.sender
+            .send(enabled)
+            .expect("The switch holds a receiver");
+    }
+
+    /// Returns once the processor is enabled
+    pub async fn wait_until_enabled(&self) {
+        let mut receiver = self.receiver.clone();
+        while !*receiver.borrow() {
+            receiver
+                .changed()
+                .await
+                .expect("The switch holds the sender");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_budget_serves_higher_priority_first() {
+        let budget = Arc::new(ConnectionBudget::new(1));
+        let held = budget.clone().acquire(0).await;
+
+        let order = Arc::new(Mutex::new(vec![]));
+        let mut tasks = vec![];
+        for priority in [1, 5, 3] {
+            let budget = budget.clone();
+            let order = order.clone();
+            tasks.push(tokio::spawn(async move {
+                let _permit = budget.acquire(priority).await;
+                order.lock().unwrap().push(priority);
+            }));
+            // Make sure waiters queue up in a known order
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        drop(held);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec![5, 3, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_processor_switch() {
+        let switch = Arc::new(ProcessorSwitch::default());
+        assert!(switch.is_enabled());
+        switch.wait_until_enabled().await;
+
+        switch.set_enabled(false);
+        let waiter = {
+            let switch = switch.clone();
+            tokio::spawn(async move { switch.wait_until_enabled().await })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiter.is_finished());
+
+        switch.set_enabled(true);
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
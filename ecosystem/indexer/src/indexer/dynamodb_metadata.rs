@@ -0,0 +1,479 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Metadata stored in a DynamoDB table, so serverless deployments (Lambda, Fargate) don't need a Postgres just to
+//! track progress: sinks return a `DynamoDbMetadataHandle` from `TransactionProcessor::metadata_handle`. Only built
+//! with the `dynamodb` feature, and selected with `--dynamodb-table` (see `MetadataBackend::DynamoDb`), ex: by
+//! `aptos-indexer-sink`, which doesn't need `postgres`.
+//!
+//! The table (single-table design, with a string partition key `pk` and sort key `sk`) holds, per processor name:
+//! - its ranges of successfully processed versions (`range#<start>` -> `end_version`), as `InMemoryMetadataHandle`
+//!   keeps them, so the number of items doesn't grow with the number of versions processed
+//! - the statuses of its versions which are not (yet) successful (`status#<version>`)
+//!
+//! and the indexed chain's id under `#ledger_info`. A processor name's items are written by a single indexer, so
+//! `set_statuses` reads them, applies the statuses and writes back what changed, without conditions. Writes put before
+//! they delete, and loading merges overlapping ranges, so a crash midway at worst reprocesses versions.
+//!
+//! Requests are signed with the `AWS_*` credentials from the environment (as on Lambda), or else the ECS task's role
+//! (as on Fargate). `MetadataHandle` is synchronous, so requests block the calling thread: the handle needs tokio's
+//! multi-threaded runtime.
+
+use crate::{
+    aws::{AwsApiError, AwsJsonClient},
+    indexer::metadata_handle::{block_on, MetadataHandle, ProcessorState, VersionStatus},
+};
+use anyhow::{ensure, Context, Result};
+use serde_json::{json, Value};
+use std::{fmt, future::Future, time::Duration};
+use tokio::runtime::Handle;
+
+/// Partition and sort key of the chain id's item
+pub const LEDGER_INFO_KEY: &str = "#ledger_info";
+const RANGE_PREFIX: &str = "range#";
+const STATUS_PREFIX: &str = "status#";
+/// `BatchWriteItem` writes at most 25 items per request
+const ITEMS_PER_BATCH: usize = 25;
+/// Throttled and failed requests, and unprocessed items, are retried this often
+const MAX_ATTEMPTS: u32 = 8;
+
+#[derive(Clone, Debug)]
+pub struct DynamoDbConfig {
+    pub table: String,
+    pub region: String,
+    /// Where to send requests instead of the region's endpoint, ex: "http://localhost:8000" for DynamoDB Local
+    pub endpoint: Option<String>,
+    pub request_timeout: Duration,
+}
+
+impl DynamoDbConfig {
+    /// Uses `AWS_REGION` (or `AWS_DEFAULT_REGION`)
+    pub fn new(table: String) -> Result<Self> {
+        Ok(Self {
+            table,
+            region: std::env::var("AWS_REGION")
+                .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+                .context("AWS_REGION must be set")?,
+            endpoint: None,
+            request_timeout: Duration::from_secs(10),
+        })
+    }
+}
+
+fn string(value: &str) -> Value {
+    json!({ "S": value })
+}
+
+fn number(value: u64) -> Value {
+    json!({ "N": value.to_string() })
+}
+
+fn parse_number(item: &Value, attribute: &str) -> Result<u64> {
+    item[attribute]["N"]
+        .as_str()
+        .with_context(|| format!("Item has no number {}: {}", attribute, item))?
+        .parse()
+        .with_context(|| format!("Item's {} isn't a version: {}", attribute, item))
+}
+
+fn key(pk: &str, sk: &str) -> Value {
+    json!({ "pk": string(pk), "sk": string(sk) })
+}
+
+/// Sort keys are strings, so versions are zero-padded to sort in order
+fn range_key(processor_name: &str, start_version: u64) -> Value {
+    key(
+        processor_name,
+        &format!("{}{:020}", RANGE_PREFIX, start_version),
+    )
+}
+
+fn status_key(processor_name: &str, version: u64) -> Value {
+    key(processor_name, &format!("{}{:020}", STATUS_PREFIX, version))
+}
+
+fn put_request(mut item: Value, attributes: Value) -> Value {
+    for (name, value) in attributes.as_object().expect("Attributes are an object") {
+        item[name] = value.clone();
+    }
+    json!({ "PutRequest": { "Item": item } })
+}
+
+fn delete_request(key: Value) -> Value {
+    json!({ "DeleteRequest": { "Key": key } })
+}
+
+/// Rebuilds a processor's state from its items. Overlapping ranges (left by a crash between writes) are merged, and
+/// versions with a status are removed from the ranges, as `ProcessorState::set_status` would.
+fn state_from_items(items: &[Value]) -> Result<ProcessorState> {
+    let mut ranges = vec![];
+    let mut statuses = vec![];
+    for item in items {
+        let sk = item["sk"]["S"].as_str().unwrap_or_default();
+        if sk.starts_with(RANGE_PREFIX) {
+            ranges.push((
+                parse_number(item, "start_version")?,
+                parse_number(item, "end_version")?,
+            ));
+        } else if sk.starts_with(STATUS_PREFIX) {
+            statuses.push(VersionStatus::new(
+                parse_number(item, "version")?,
+                false,
+                item["details"]["S"]
+                    .as_str()
+                    .map(|details| details.to_string()),
+            ));
+        }
+    }
+    ranges.sort_unstable();
+    let mut state = ProcessorState::default();
+    let mut current: Option<(u64, u64)> = None;
+    for (start, end) in ranges {
+        current = match current {
+            Some((current_start, current_end)) if start <= current_end.saturating_add(1) => {
+                Some((current_start, current_end.max(end)))
+            }
+            Some((current_start, current_end)) => {
+                state.successful_ranges.insert(current_start, current_end);
+                Some((start, end))
+            }
+            None => Some((start, end)),
+        };
+    }
+    if let Some((start, end)) = current {
+        state.successful_ranges.insert(start, end);
+    }
+    for status in &statuses {
+        state.set_status(status);
+    }
+    Ok(state)
+}
+
+/// The writes turning `old` into `new`: puts first, then deletes
+fn state_writes(processor_name: &str, old: &ProcessorState, new: &ProcessorState) -> Vec<Value> {
+    let mut puts = vec![];
+    let mut deletes = vec![];
+    for (start, end) in &new.successful_ranges {
+        if old.successful_ranges.get(start) != Some(end) {
+            puts.push(put_request(
+                range_key(processor_name, *start),
+                json!({ "start_version": number(*start), "end_version": number(*end) }),
+            ));
+        }
+    }
+    for (version, status) in &new.statuses {
+        if old.statuses.get(version) != Some(status) {
+            let details = match &status.details {
+                Some(details) => string(details),
+                None => json!({ "NULL": true }),
+            };
+            puts.push(put_request(
+                status_key(processor_name, *version),
+                json!({ "version": number(*version), "details": details }),
+            ));
+        }
+    }
+    for start in old.successful_ranges.keys() {
+        if !new.successful_ranges.contains_key(start) {
+            deletes.push(delete_request(range_key(processor_name, *start)));
+        }
+    }
+    for version in old.statuses.keys() {
+        if !new.statuses.contains_key(version) {
+            deletes.push(delete_request(status_key(processor_name, *version)));
+        }
+    }
+    puts.extend(deletes);
+    puts
+}
+
+pub struct DynamoDbMetadataHandle {
+    config: DynamoDbConfig,
+    client: AwsJsonClient,
+    runtime: Handle,
+}
+
+impl fmt::Debug for DynamoDbMetadataHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DynamoDbMetadataHandle {{ config: {:?} }}", self.config)
+    }
+}
+
+impl DynamoDbMetadataHandle {
+    /// Must be called within a tokio runtime, which the handle's requests then run on
+    pub fn new(config: DynamoDbConfig) -> Result<Self> {
+        let runtime =
+            Handle::try_current().context("The DynamoDB metadata handle needs a tokio runtime")?;
+        let client = AwsJsonClient::new(
+            "dynamodb",
+            "DynamoDB_20120810",
+            "application/x-amz-json-1.0",
+            config.region.clone(),
+            config.endpoint.as_deref(),
+            config.request_timeout,
+        )?;
+        Ok(Self {
+            config,
+            client,
+            runtime,
+        })
+    }
+
+    fn block_on<T>(&self, future: impl Future<Output = T>) -> T {
+        block_on(&self.runtime, future)
+    }
+
+    async fn call(&self, action: &str, body: Value) -> Result<Value> {
+        self.client.call(action, &body, MAX_ATTEMPTS).await
+    }
+
+    async fn load_state(&self, processor_name: &str) -> Result<ProcessorState> {
+        let mut items = vec![];
+        let mut start_key = Value::Null;
+        loop {
+            let mut body = json!({
+                "TableName": self.config.table,
+                "KeyConditionExpression": "pk = :pk",
+                "ExpressionAttributeValues": { ":pk": string(processor_name) },
+                "ConsistentRead": true,
+            });
+            if !start_key.is_null() {
+                body["ExclusiveStartKey"] = start_key;
+            }
+            let mut response = self.call("Query", body).await?;
+            if let Value::Array(page) = response["Items"].take() {
+                items.extend(page);
+            }
+            start_key = response["LastEvaluatedKey"].take();
+            if start_key.is_null() {
+                break;
+            }
+        }
+        state_from_items(&items)
+            .with_context(|| format!("Invalid metadata items for {}", processor_name))
+    }
+
+    /// Writes `requests` in batches, retrying unprocessed items
+    async fn batch_write(&self, requests: Vec<Value>) -> Result<()> {
+        for batch in requests.chunks(ITEMS_PER_BATCH) {
+            let mut pending = batch.to_vec();
+            let mut attempt = 1;
+            while !pending.is_empty() {
+                ensure!(
+                    attempt <= MAX_ATTEMPTS,
+                    "DynamoDB left {} items unprocessed after {} attempts",
+                    pending.len(),
+                    MAX_ATTEMPTS
+                );
+                if attempt > 1 {
+                    tokio::time::sleep(Duration::from_millis(50 << attempt)).await;
+                }
+                let mut response = self
+                    .call(
+                        "BatchWriteItem",
+                        json!({ "RequestItems": { self.config.table.as_str(): pending } }),
+                    )
+                    .await?;
+                pending = match response["UnprocessedItems"][self.config.table.as_str()].take() {
+                    Value::Array(unprocessed) => unprocessed,
+                    _ => vec![],
+                };
+                attempt += 1;
+            }
+        }
+        Ok(())
+    }
+
+    async fn set_statuses_async(
+        &self,
+        processor_name: &str,
+        statuses: &[VersionStatus],
+    ) -> Result<()> {
+        let old = self.load_state(processor_name).await?;
+        let mut new = old.clone();
+        for status in statuses {
+            new.set_status(status);
+        }
+        self.batch_write(state_writes(processor_name, &old, &new))
+            .await
+    }
+
+    fn get_state(&self, processor_name: &str) -> Result<ProcessorState> {
+        self.block_on(self.load_state(processor_name))
+    }
+
+    async fn get_chain_id_async(&self) -> Result<Option<u64>> {
+        let response = self
+            .call(
+                "GetItem",
+                json!({
+                    "TableName": self.config.table,
+                    "Key": key(LEDGER_INFO_KEY, LEDGER_INFO_KEY),
+                    "ConsistentRead": true,
+                }),
+            )
+            .await?;
+        if response["Item"].is_null() {
+            return Ok(None);
+        }
+        parse_number(&response["Item"], "chain_id").map(Some)
+    }
+
+    /// Puts the chain id's item only if there's none, so concurrent replicas don't both record theirs
+    async fn check_or_set_chain_id_async(&self, chain_id: u64) -> Result<Option<u64>> {
+        let mut item = key(LEDGER_INFO_KEY, LEDGER_INFO_KEY);
+        item["chain_id"] = number(chain_id);
+        match self
+            .call(
+                "PutItem",
+                json!({
+                    "TableName": self.config.table,
+                    "Item": item,
+                    "ConditionExpression": "attribute_not_exists(pk)",
+                }),
+            )
+            .await
+        {
+            Ok(_) => Ok(None),
+            Err(err)
+                if err.downcast_ref::<AwsApiError>().map_or(false, |err| {
+                    err.error_type == "ConditionalCheckFailedException"
+                }) =>
+            {
+                self.get_chain_id_async().await
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl MetadataHandle for DynamoDbMetadataHandle {
+    fn set_statuses(&self, processor_name: &str, statuses: &[VersionStatus]) -> Result<()> {
+        self.block_on(self.set_statuses_async(processor_name, statuses))
+            .context("Error updating Processor Status!")
+    }
+
+    fn get_error_versions(&self, processor_name: &str) -> Result<Vec<u64>> {
+        Ok(self.get_state(processor_name)?.error_versions())
+    }
+
+    fn get_max_version(&self, processor_name: &str) -> Result<Option<u64>> {
+        Ok(self.get_state(processor_name)?.max_version())
+    }
+
+    fn get_successful_version_bounds(&self, processor_name: &str) -> Result<Option<(u64, u64)>> {
+        Ok(self.get_state(processor_name)?.successful_version_bounds())
+    }
+
+    fn get_successful_versions(
+        &self,
+        processor_name: &str,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<Vec<u64>> {
+        Ok(self
+            .get_state(processor_name)?
+            .successful_versions(start_version, end_version))
+    }
+
+    fn get_start_version(&self, processor_name: &str) -> Result<Option<u64>> {
+        Ok(self.get_state(processor_name)?.start_version())
+    }
+
+    fn get_chain_id(&self) -> Result<Option<u64>> {
+        self.block_on(self.get_chain_id_async())
+            .context("Error loading chain id from DynamoDB")
+    }
+
+    /// The chain id's item is only put once: recording another chain than the one already recorded fails
+    fn set_chain_id(&self, chain_id: u64) -> Result<()> {
+        let recorded_chain_id = self
+            .check_or_set_chain_id(chain_id)
+            .context("Error updating chain_id!")?;
+        ensure!(
+            recorded_chain_id.map_or(true, |recorded| recorded == chain_id),
+            "Refusing to index chain {}: the DynamoDB table holds data of chain {:?}",
+            chain_id,
+            recorded_chain_id
+        );
+        Ok(())
+    }
+
+    fn check_or_set_chain_id(&self, chain_id: u64) -> Result<Option<u64>> {
+        self.block_on(self.check_or_set_chain_id_async(chain_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAME: &str = "test_processor";
+
+    /// The items `writes` leave, starting from `items`
+    fn apply_writes(items: &mut Vec<Value>, writes: &[Value]) {
+        for write in writes {
+            let key = if write["PutRequest"].is_null() {
+                write["DeleteRequest"]["Key"].clone()
+            } else {
+                write["PutRequest"]["Item"].clone()
+            };
+            items.retain(|item| item["pk"] != key["pk"] || item["sk"] != key["sk"]);
+            if !write["PutRequest"].is_null() {
+                items.push(write["PutRequest"]["Item"].clone());
+            }
+        }
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let mut items = vec![];
+        let mut state = ProcessorState::default();
+        for statuses in [
+            VersionStatus::range(0, 9, true, None),
+            VersionStatus::range(20, 29, true, None),
+            vec![VersionStatus::new(5, false, Some("failed".to_string()))],
+            VersionStatus::range(10, 19, true, None),
+        ] {
+            let mut new = state.clone();
+            for status in &statuses {
+                new.set_status(status);
+            }
+            apply_writes(&mut items, &state_writes(NAME, &state, &new));
+            state = new;
+            assert_eq!(state_from_items(&items).unwrap(), state);
+        }
+        assert_eq!(state.start_version(), Some(5));
+        assert_eq!(state.successful_ranges.len(), 2);
+        // One item per range, plus the failed version's
+        assert_eq!(items.len(), 3);
+    }
+
+    #[test]
+    fn test_state_from_items_after_partial_writes() {
+        // Merging 0-9 and 11-20 puts 0-20 before deleting 11-20
+        let items = vec![
+            put_request(
+                range_key(NAME, 0),
+                json!({ "start_version": number(0), "end_version": number(20) }),
+            )["PutRequest"]["Item"]
+                .clone(),
+            put_request(
+                range_key(NAME, 11),
+                json!({ "start_version": number(11), "end_version": number(20) }),
+            )["PutRequest"]["Item"]
+                .clone(),
+            put_request(
+                status_key(NAME, 15),
+                json!({ "version": number(15), "details": { "NULL": true } }),
+            )["PutRequest"]["Item"]
+                .clone(),
+        ];
+        let state = state_from_items(&items).unwrap();
+        assert_eq!(
+            state.successful_ranges.into_iter().collect::<Vec<_>>(),
+            vec![(0, 14), (16, 20)]
+        );
+        assert_eq!(state.statuses.keys().copied().collect::<Vec<_>>(), vec![15]);
+    }
+}
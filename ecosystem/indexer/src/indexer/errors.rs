@@ -1,25 +1,228 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::util::BigDecimalConversionError;
 use anyhow::Error;
+#[cfg(feature = "postgres")]
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use std::fmt;
 
 // Error, start_version, end_version, name
-type ErrorWithVersionAndName = (Error, u64, u64, &'static str);
+type ErrorWithVersionAndName = (Error, u64, u64, String);
 
 #[derive(Debug)]
 #[allow(clippy::large_enum_variant)]
 pub enum TransactionProcessingError {
-    /// Could not get a connection
-    ConnectionPoolError(ErrorWithVersionAndName),
-    /// Could not commit the transaction
-    TransactionCommitError(ErrorWithVersionAndName),
+    /// Could not get a connection, or lost it while writing
+    DbConnection(ErrorWithVersionAndName),
+    /// A write violated a database constraint, ex: a row inserted twice with different data
+    DbConstraint(ErrorWithVersionAndName),
+    /// Transactions could not be decoded into models
+    Decode(ErrorWithVersionAndName),
+    /// Anything else, ex: a failed query
+    Custom(ErrorWithVersionAndName),
+    /// The versions' statuses could not be recorded, ex: the metadata store was unreachable
+    Metadata(ErrorWithVersionAndName),
 }
 
 impl TransactionProcessingError {
     pub fn inner(&self) -> &ErrorWithVersionAndName {
         match self {
-            TransactionProcessingError::ConnectionPoolError(ewv) => ewv,
-            TransactionProcessingError::TransactionCommitError(ewv) => ewv,
+            TransactionProcessingError::DbConnection(ewv) => ewv,
+            TransactionProcessingError::DbConstraint(ewv) => ewv,
+            TransactionProcessingError::Decode(ewv) => ewv,
+            TransactionProcessingError::Custom(ewv) => ewv,
+            TransactionProcessingError::Metadata(ewv) => ewv,
         }
     }
+
+    /// For metrics, ex: "db_constraint"
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TransactionProcessingError::DbConnection(_) => "db_connection",
+            TransactionProcessingError::DbConstraint(_) => "db_constraint",
+            TransactionProcessingError::Decode(_) => "decode",
+            TransactionProcessingError::Custom(_) => "custom",
+            TransactionProcessingError::Metadata(_) => "metadata",
+        }
+    }
+
+    /// Whether processing the same versions again may succeed, in which case the tailer retries the batch. Connection
+    /// errors are transient, and so are deadlocks and serialization failures (see `is_transient_db_error`), while
+    /// constraint violations and decoding errors will happen again for the same data. Statuses which couldn't be
+    /// recorded are recorded again with the batch, whose writes are idempotent.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            TransactionProcessingError::DbConnection(_) => true,
+            TransactionProcessingError::DbConstraint(_) => false,
+            TransactionProcessingError::Decode(_) => false,
+            TransactionProcessingError::Custom((error, ..)) => is_transient(error),
+            TransactionProcessingError::Metadata(_) => true,
+        }
+    }
+
+    /// Classifies an error raised while writing versions `start_version` to `end_version`
+    #[cfg(feature = "postgres")]
+    pub fn from_db_error(
+        error: DieselError,
+        start_version: u64,
+        end_version: u64,
+        name: &str,
+    ) -> Self {
+        let is_connection_error = matches!(
+            error,
+            DieselError::DatabaseError(DatabaseErrorKind::UnableToSendCommand, _)
+        );
+        let is_constraint_error = match &error {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)
+            | DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => true,
+            DieselError::DatabaseError(_, info) => info.constraint_name().is_some(),
+            _ => false,
+        };
+        let ewv = (
+            Error::from(error),
+            start_version,
+            end_version,
+            name.to_string(),
+        );
+        if is_connection_error {
+            TransactionProcessingError::DbConnection(ewv)
+        } else if is_constraint_error {
+            TransactionProcessingError::DbConstraint(ewv)
+        } else {
+            TransactionProcessingError::Custom(ewv)
+        }
+    }
+}
+
+/// Processor metadata which can't be read back, ex: a version made negative or fractional by a manual edit of
+/// `processor_statuses`. Returned instead of panicking, so the indexer stops with an explanation of what to fix.
+#[derive(Debug)]
+pub struct MetadataCorruptedError {
+    pub processor_name: String,
+    /// What was being read, ex: "max version"
+    pub field: &'static str,
+    pub source: BigDecimalConversionError,
+}
+
+impl MetadataCorruptedError {
+    pub fn new(
+        processor_name: &str,
+        field: &'static str,
+        source: BigDecimalConversionError,
+    ) -> Self {
+        Self {
+            processor_name: processor_name.to_string(),
+            field,
+            source,
+        }
+    }
+}
+
+impl fmt::Display for MetadataCorruptedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Processor metadata corrupted: {}'s {} is invalid ({}). Fix or delete the offending rows of \
+             processor_statuses.",
+            self.processor_name, self.field, self.source
+        )
+    }
+}
+
+impl std::error::Error for MetadataCorruptedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Whether Postgres aborted a transaction which may succeed if run again: it was picked to break a deadlock, or
+/// couldn't be serialized with a concurrent one. Diesel only has a kind for serialization failures, so deadlocks are
+/// recognized by their message.
+#[cfg(feature = "postgres")]
+pub fn is_transient_db_error(error: &DieselError) -> bool {
+    match error {
+        DieselError::DatabaseError(DatabaseErrorKind::SerializationFailure, _) => true,
+        DieselError::DatabaseError(_, info) => {
+            info.message().starts_with("deadlock detected")
+                || info.message().starts_with("could not serialize access")
+        }
+        _ => false,
+    }
+}
+
+#[cfg(feature = "postgres")]
+fn is_transient(error: &Error) -> bool {
+    error
+        .downcast_ref::<DieselError>()
+        .map_or(false, is_transient_db_error)
+}
+
+#[cfg(not(feature = "postgres"))]
+fn is_transient(_error: &Error) -> bool {
+    false
+}
+
+#[cfg(all(test, feature = "postgres"))]
+mod tests {
+    use super::*;
+
+    fn db_error(kind: DatabaseErrorKind) -> DieselError {
+        DieselError::DatabaseError(kind, Box::new("error".to_string()))
+    }
+
+    #[test]
+    fn test_from_db_error_classification() {
+        let tpe = TransactionProcessingError::from_db_error(
+            db_error(DatabaseErrorKind::UnableToSendCommand),
+            1,
+            2,
+            "test",
+        );
+        assert!(matches!(tpe, TransactionProcessingError::DbConnection(_)));
+        assert!(tpe.is_retryable());
+
+        let tpe = TransactionProcessingError::from_db_error(
+            db_error(DatabaseErrorKind::UniqueViolation),
+            1,
+            2,
+            "test",
+        );
+        assert!(matches!(tpe, TransactionProcessingError::DbConstraint(_)));
+        assert!(!tpe.is_retryable());
+
+        let tpe = TransactionProcessingError::from_db_error(
+            db_error(DatabaseErrorKind::SerializationFailure),
+            1,
+            2,
+            "test",
+        );
+        assert!(matches!(tpe, TransactionProcessingError::Custom(_)));
+        assert!(tpe.is_retryable());
+
+        let tpe = TransactionProcessingError::from_db_error(DieselError::NotFound, 1, 2, "test");
+        assert!(matches!(tpe, TransactionProcessingError::Custom(_)));
+        assert!(!tpe.is_retryable());
+
+        let tpe = TransactionProcessingError::from_db_error(
+            DieselError::DatabaseError(
+                DatabaseErrorKind::__Unknown,
+                Box::new("deadlock detected".to_string()),
+            ),
+            1,
+            2,
+            "test",
+        );
+        assert!(tpe.is_retryable());
+        // Errors of rollups and sinks wrap the diesel error
+        let tpe = TransactionProcessingError::Custom((
+            Error::new(db_error(DatabaseErrorKind::SerializationFailure))
+                .context("Failed to update rollup"),
+            1,
+            2,
+            "test".to_string(),
+        ));
+        assert!(tpe.is_retryable());
+        assert_eq!(tpe.kind(), "custom");
+    }
 }
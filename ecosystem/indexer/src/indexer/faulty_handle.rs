@@ -0,0 +1,245 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! A test-only `MetadataHandle` which wraps another one and fails calls at configurable rates, so code recovering
+//! from metadata errors (ex: Postgres connections dropping under the `Tailer`) can be exercised deterministically.
+//! Faults are drawn from a seeded generator: the same seed and sequence of calls always fail the same way.
+
+use crate::{
+    indexer::metadata_handle::{MetadataHandle, VersionStatus},
+    models::ledger_info::LedgerInfoHistory,
+};
+use anyhow::Result;
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// What a `FaultyHandle` call failed with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InjectedFault {
+    /// The connection was lost before the call reached the database
+    ConnectionDrop,
+    /// The call conflicted with a concurrent transaction
+    SerializationFailure,
+    /// The call didn't complete within the handle's timeout
+    Timeout,
+}
+
+impl fmt::Display for InjectedFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InjectedFault::ConnectionDrop => write!(f, "injected fault: connection dropped"),
+            InjectedFault::SerializationFailure => {
+                write!(f, "injected fault: could not serialize access")
+            }
+            InjectedFault::Timeout => write!(f, "injected fault: timed out"),
+        }
+    }
+}
+
+impl std::error::Error for InjectedFault {}
+
+/// Probability (0 to 1) of each fault, per call. Their sum should be at most 1.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FaultRates {
+    pub connection_drop: f64,
+    pub serialization_failure: f64,
+    pub timeout: f64,
+}
+
+#[derive(Debug)]
+pub struct FaultyHandle {
+    inner: Arc<dyn MetadataHandle>,
+    rates: FaultRates,
+    /// How long a call hangs before failing with `InjectedFault::Timeout`
+    timeout: Duration,
+    rng_state: Mutex<u64>,
+    injected: Mutex<Vec<InjectedFault>>,
+}
+
+impl FaultyHandle {
+    pub fn new(inner: Arc<dyn MetadataHandle>, rates: FaultRates, seed: u64) -> Self {
+        Self {
+            inner,
+            rates,
+            timeout: Duration::ZERO,
+            rng_state: Mutex::new(seed),
+            injected: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Every fault injected so far, in order
+    pub fn injected_faults(&self) -> Vec<InjectedFault> {
+        self.injected.lock().unwrap().clone()
+    }
+
+    /// splitmix64, uniform in [0, 1)
+    fn next_draw(&self) -> f64 {
+        let mut state = self.rng_state.lock().unwrap();
+        *state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Fails before the inner handle is called, so a failed write never partially applies
+    fn maybe_fail(&self) -> Result<()> {
+        let draw = self.next_draw();
+        let fault = if draw < self.rates.connection_drop {
+            InjectedFault::ConnectionDrop
+        } else if draw < self.rates.connection_drop + self.rates.serialization_failure {
+            InjectedFault::SerializationFailure
+        } else if draw
+            < self.rates.connection_drop + self.rates.serialization_failure + self.rates.timeout
+        {
+            std::thread::sleep(self.timeout);
+            InjectedFault::Timeout
+        } else {
+            return Ok(());
+        };
+        self.injected.lock().unwrap().push(fault);
+        Err(fault.into())
+    }
+}
+
+impl MetadataHandle for FaultyHandle {
+    fn set_statuses(&self, processor_name: &str, statuses: &[VersionStatus]) -> Result<()> {
+        self.maybe_fail()?;
+        self.inner.set_statuses(processor_name, statuses)
+    }
+
+    fn get_error_versions(&self, processor_name: &str) -> Result<Vec<u64>> {
+        self.maybe_fail()?;
+        self.inner.get_error_versions(processor_name)
+    }
+
+    fn get_max_version(&self, processor_name: &str) -> Result<Option<u64>> {
+        self.maybe_fail()?;
+        self.inner.get_max_version(processor_name)
+    }
+
+    fn get_successful_version_bounds(&self, processor_name: &str) -> Result<Option<(u64, u64)>> {
+        self.maybe_fail()?;
+        self.inner.get_successful_version_bounds(processor_name)
+    }
+
+    fn get_successful_versions(
+        &self,
+        processor_name: &str,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<Vec<u64>> {
+        self.maybe_fail()?;
+        self.inner
+            .get_successful_versions(processor_name, start_version, end_version)
+    }
+
+    fn get_start_version(&self, processor_name: &str) -> Result<Option<u64>> {
+        self.maybe_fail()?;
+        self.inner.get_start_version(processor_name)
+    }
+
+    fn get_chain_id(&self) -> Result<Option<u64>> {
+        self.maybe_fail()?;
+        self.inner.get_chain_id()
+    }
+
+    fn set_chain_id(&self, chain_id: u64) -> Result<()> {
+        self.maybe_fail()?;
+        self.inner.set_chain_id(chain_id)
+    }
+
+    fn check_or_set_chain_id(&self, chain_id: u64) -> Result<Option<u64>> {
+        self.maybe_fail()?;
+        self.inner.check_or_set_chain_id(chain_id)
+    }
+
+    fn record_ledger_info(&self, ledger_info: &LedgerInfoHistory) -> Result<()> {
+        self.maybe_fail()?;
+        self.inner.record_ledger_info(ledger_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::metadata_handle::InMemoryMetadataHandle;
+
+    const NAME: &str = "test_processor";
+
+    fn rates() -> FaultRates {
+        FaultRates {
+            connection_drop: 0.2,
+            serialization_failure: 0.2,
+            timeout: 0.1,
+        }
+    }
+
+    fn faulty_handle(rates: FaultRates, seed: u64) -> FaultyHandle {
+        FaultyHandle::new(Arc::new(InMemoryMetadataHandle::new()), rates, seed)
+    }
+
+    #[test]
+    fn test_faults_are_deterministic() {
+        let run = |seed| {
+            let handle = faulty_handle(rates(), seed);
+            let results: Vec<bool> = (0..100)
+                .map(|version| {
+                    handle
+                        .set_statuses(NAME, &[VersionStatus::new(version, true, None)])
+                        .is_ok()
+                })
+                .collect();
+            (results, handle.injected_faults())
+        };
+        let (results, faults) = run(7);
+        assert_eq!(run(7), (results.clone(), faults.clone()));
+        assert_ne!(run(8).0, results);
+
+        // Roughly the configured rates
+        assert!((30..=70).contains(&faults.len()));
+        assert!(faults.contains(&InjectedFault::ConnectionDrop));
+        assert!(faults.contains(&InjectedFault::SerializationFailure));
+        assert!(faults.contains(&InjectedFault::Timeout));
+    }
+
+    #[test]
+    fn test_no_faults_passes_through() {
+        let handle = faulty_handle(FaultRates::default(), 0);
+        handle.set_chain_id(4).unwrap();
+        handle
+            .set_statuses(NAME, &VersionStatus::range(0, 9, true, None))
+            .unwrap();
+        assert_eq!(handle.get_chain_id().unwrap(), Some(4));
+        assert_eq!(handle.get_start_version(NAME).unwrap(), Some(10));
+        assert!(handle.injected_faults().is_empty());
+    }
+
+    #[test]
+    fn test_retrying_failed_writes_recovers() {
+        let handle = faulty_handle(rates(), 42);
+        for start in (0..100).step_by(10) {
+            let statuses = VersionStatus::range(start, start + 9, true, None);
+            let mut attempts = 0;
+            while let Err(e) = handle.set_statuses(NAME, &statuses) {
+                assert!(e.downcast_ref::<InjectedFault>().is_some());
+                attempts += 1;
+                assert!(attempts < 50, "Too many injected faults in a row");
+            }
+        }
+        assert!(!handle.injected_faults().is_empty());
+
+        // Failed writes didn't apply anything, and the retries filled every version
+        assert_eq!(handle.inner.get_start_version(NAME).unwrap(), Some(100));
+        assert!(handle.inner.get_error_versions(NAME).unwrap().is_empty());
+    }
+}
@@ -1,14 +1,32 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::counters::{FETCHED_TRANSACTION, UNABLE_TO_FETCH_TRANSACTION};
+use crate::counters::{
+    FETCHED_BYTES, FETCHED_TRANSACTION, FETCH_CACHE_REQUESTS, UNABLE_TO_FETCH_TRANSACTION,
+};
+#[cfg(feature = "storage")]
+use crate::indexer::storage_fetcher::{NodeStorage, StorageFetcher};
+use crate::indexer::stream::RestTransactionStream;
+#[cfg(feature = "vm_decoder")]
+use crate::indexer::vm_decoder::{BcsFetcher, ModuleCache};
+#[cfg(feature = "postgres")]
+use crate::{database::PgDbPool, indexer::raw_fetcher::RawTableFetcher};
+use anyhow::Context;
 use aptos_logger::prelude::*;
-use aptos_rest_client::{retriable, retriable_with_404, Client as RestClient, State, Transaction};
+use aptos_rest_client::{
+    error::RestError, retriable, retriable_with_404, Client as RestClient, State, Transaction,
+};
+use flate2::read::GzDecoder;
 use futures::channel::mpsc;
 use futures::{SinkExt, StreamExt};
 use serde_json::Value;
-use std::time::Duration;
-use tokio::task::JoinHandle;
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Read,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{sync::OnceCell, task::JoinHandle};
 use url::Url;
 
 // TODO: make this configurable
@@ -20,30 +38,490 @@ const MAX_THREADS: usize = 10;
 static STARTING_RETRY_TIME: Duration = Duration::from_millis(RETRY_TIME_MILLIS);
 static MAX_RETRY_TIME: Duration = Duration::from_millis(MAX_RETRY_TIME_MILLIS);
 
+/// How to connect to the node, on top of what's in its URL
+#[derive(Clone, Debug, Default)]
+pub struct FetcherConfig {
+    /// How long a request may take before failing, including reading the response (the REST client defaults to
+    /// 10s, which large batches from slow archival nodes can exceed)
+    pub request_timeout: Option<Duration>,
+    /// How long establishing a connection may take (reqwest doesn't time out by default)
+    pub connect_timeout: Option<Duration>,
+    /// Maximum number of idle connections kept open to the node (reqwest doesn't limit it by default)
+    pub pool_max_idle_per_host: Option<usize>,
+    /// How long an idle connection is kept open (reqwest defaults to 90s)
+    pub pool_idle_timeout: Option<Duration>,
+    /// Interval of TCP keep-alive probes on connections (reqwest doesn't send any by default)
+    pub tcp_keepalive: Option<Duration>,
+    /// Speak HTTP/2 to the node without negotiating it first, ex: behind a load balancer which only supports h2c
+    pub http2_prior_knowledge: bool,
+    /// Defaults to `aptos-indexer/<version>`
+    pub user_agent: Option<String>,
+    /// Ask the node to gzip batches of transactions, which shrinks them several times over at the cost of some CPU
+    pub compression: bool,
+    /// If set, start fetching with small, few batches and ramp up to the full size and concurrency, rather than
+    /// hitting the node with the largest requests right after a restart
+    pub slow_start: Option<SlowStart>,
+    /// If set, batches are fetched through this cache, so that fetchers built from clones of this config (ex: the
+    /// tailers of several processors) request each batch from the node once
+    pub shared_cache: Option<Arc<FetchCache>>,
+    /// If set, transactions are read from `raw_transactions` in this pool's DB instead of from the node (see
+    /// `raw_fetcher`)
+    #[cfg(feature = "postgres")]
+    pub raw_transactions_pool: Option<PgDbPool>,
+    /// If set, transactions are read from the storage of a fullnode on the same host instead of its REST API
+    #[cfg(feature = "storage")]
+    pub node_storage: Option<Arc<NodeStorage>>,
+    /// If set, transactions are fetched as BCS and decoded with the node's modules, cached here (see `vm_decoder`)
+    #[cfg(feature = "vm_decoder")]
+    pub module_cache: Option<Arc<ModuleCache>>,
+}
+
+/// Recently fetched batches, keyed by their starting version and size, shared between the fetchers of processors
+/// which tail independently, their backfills and their retries of single versions. Concurrent requests for the same
+/// batch wait for a single fetch. Batches expire `ttl` after being fetched, and the least recently used ones are
+/// evicted past `capacity`. Fetchers only hit it while fetching the same batches, ex: processors started from the same
+/// version and keeping up; a processor lagging behind (or slow starting) fetches its batches itself.
+pub struct FetchCache {
+    capacity: usize,
+    ttl: Duration,
+    batches: Mutex<FetchCacheEntries>,
+}
+
+struct FetchCacheEntry {
+    transactions: Arc<OnceCell<Vec<Transaction>>>,
+    fetched_at: Instant,
+}
+
+#[derive(Default)]
+struct FetchCacheEntries {
+    by_range: HashMap<(u64, u16), FetchCacheEntry>,
+    /// Ranges from the least to the most recently used, for eviction
+    order: VecDeque<(u64, u16)>,
+}
+
+impl FetchCacheEntries {
+    fn touch(&mut self, key: (u64, u16)) {
+        if let Some(position) = self.order.iter().position(|range| *range == key) {
+            self.order.remove(position);
+        }
+        self.order.push_back(key);
+    }
+
+    fn remove_expired(&mut self, ttl: Duration) {
+        let by_range = &mut self.by_range;
+        self.order.retain(|key| {
+            let expired = by_range
+                .get(key)
+                .map_or(true, |entry| entry.fetched_at.elapsed() > ttl);
+            if expired {
+                by_range.remove(key);
+            }
+            !expired
+        });
+    }
+}
+
+impl std::fmt::Debug for FetchCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "FetchCache {{ capacity: {}, ttl: {:?}, len: {} }}",
+            self.capacity,
+            self.ttl,
+            self.len()
+        )
+    }
+}
+
+impl FetchCache {
+    /// Batches kept by default: with full batches, the last 20 * 500 versions
+    pub const DEFAULT_CAPACITY: usize = 20;
+    pub const DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            ttl,
+            batches: Mutex::new(FetchCacheEntries::default()),
+        }
+    }
+
+    /// Number of batches cached, including ones being fetched
+    pub fn len(&self) -> usize {
+        let mut batches = self.batches.lock().unwrap();
+        batches.remove_expired(self.ttl);
+        batches.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The batch of `batch_size` versions from `starting_version`, from the cache or else from `fetch`
+    pub async fn get_or_fetch<F, Fut>(
+        &self,
+        starting_version: u64,
+        batch_size: u16,
+        fetch: F,
+    ) -> Vec<Transaction>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Vec<Transaction>>,
+    {
+        let key = (starting_version, batch_size);
+        let transactions = {
+            let mut batches = self.batches.lock().unwrap();
+            batches.remove_expired(self.ttl);
+            match batches.by_range.get(&key) {
+                Some(entry) => {
+                    FETCH_CACHE_REQUESTS.with_label_values(&["hit"]).inc();
+                    let transactions = entry.transactions.clone();
+                    batches.touch(key);
+                    transactions
+                }
+                None => {
+                    FETCH_CACHE_REQUESTS.with_label_values(&["miss"]).inc();
+                    let transactions = Arc::new(OnceCell::new());
+                    batches.by_range.insert(
+                        key,
+                        FetchCacheEntry {
+                            transactions: transactions.clone(),
+                            fetched_at: Instant::now(),
+                        },
+                    );
+                    batches.order.push_back(key);
+                    while batches.order.len() > self.capacity {
+                        if let Some(oldest) = batches.order.pop_front() {
+                            batches.by_range.remove(&oldest);
+                        }
+                    }
+                    transactions
+                }
+            }
+        };
+        transactions.get_or_init(fetch).await.clone()
+    }
+
+    /// `version`, if it's in a batch fetched already
+    pub fn get_version(&self, version: u64) -> Option<Transaction> {
+        let mut batches = self.batches.lock().unwrap();
+        batches.remove_expired(self.ttl);
+        let found = batches.by_range.iter().find_map(|(key, entry)| {
+            let (starting_version, batch_size) = *key;
+            if version < starting_version || version - starting_version >= batch_size as u64 {
+                return None;
+            }
+            entry
+                .transactions
+                .get()?
+                .iter()
+                .find(|txn| txn.version() == Some(version))
+                .map(|txn| (*key, txn.clone()))
+        });
+        match found {
+            Some((key, txn)) => {
+                FETCH_CACHE_REQUESTS.with_label_values(&["hit"]).inc();
+                batches.touch(key);
+                Some(txn)
+            }
+            None => {
+                FETCH_CACHE_REQUESTS.with_label_values(&["miss"]).inc();
+                None
+            }
+        }
+    }
+}
+
+/// How fetching ramps up after starting
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlowStart {
+    /// Versions fetched per request at first
+    pub initial_batch_size: u16,
+    /// Requests made in parallel at first
+    pub initial_concurrency: usize,
+    /// The batch size and concurrency double every this many successful requests
+    pub batches_per_step: u64,
+}
+
+impl Default for SlowStart {
+    fn default() -> Self {
+        Self {
+            initial_batch_size: 50,
+            initial_concurrency: 1,
+            batches_per_step: 10,
+        }
+    }
+}
+
+/// The batch size and concurrency the fetcher uses, ramping up from a `SlowStart` to the limits
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FetchRamp {
+    pub batch_size: u16,
+    pub concurrency: usize,
+    batches_per_step: u64,
+    successful_batches: u64,
+}
+
+impl FetchRamp {
+    /// At the limits from the start
+    pub fn full() -> Self {
+        Self {
+            batch_size: TRANSACTION_FETCH_BATCH_SIZE,
+            concurrency: MAX_THREADS,
+            batches_per_step: 0,
+            successful_batches: 0,
+        }
+    }
+
+    pub fn new(slow_start: Option<SlowStart>) -> Self {
+        match slow_start {
+            Some(slow_start) => Self {
+                batch_size: slow_start
+                    .initial_batch_size
+                    .clamp(1, TRANSACTION_FETCH_BATCH_SIZE),
+                concurrency: slow_start.initial_concurrency.clamp(1, MAX_THREADS),
+                batches_per_step: slow_start.batches_per_step.max(1),
+                successful_batches: 0,
+            },
+            None => Self::full(),
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.batch_size == TRANSACTION_FETCH_BATCH_SIZE && self.concurrency == MAX_THREADS
+    }
+
+    /// Records `num_batches` successful requests, doubling the batch size and concurrency (up to the limits) for each
+    /// step completed
+    pub fn record_success(&mut self, num_batches: u64) {
+        if self.is_full() {
+            return;
+        }
+        self.successful_batches += num_batches;
+        while self.successful_batches >= self.batches_per_step && !self.is_full() {
+            self.successful_batches -= self.batches_per_step;
+            self.batch_size = self
+                .batch_size
+                .saturating_mul(2)
+                .min(TRANSACTION_FETCH_BATCH_SIZE);
+            self.concurrency = (self.concurrency * 2).min(MAX_THREADS);
+            info!(
+                batch_size = self.batch_size,
+                concurrency = self.concurrency,
+                "Ramping up fetching"
+            );
+        }
+    }
+}
+
+impl FetcherConfig {
+    const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// A fetcher from `node_url`, through a client configured as set here
+    pub fn build_fetcher(&self, node_url: Url) -> anyhow::Result<TransactionFetcher> {
+        let inner = self.build_http_client()?;
+        let compressed_client = self.compression.then(|| CompressedClient {
+            inner: inner.clone(),
+        });
+        Ok(
+            TransactionFetcher::with_client(Self::rest_client(inner, node_url)?, None)
+                .with_compressed_client(compressed_client)
+                .with_slow_start(self.slow_start)
+                .with_fetch_cache(self.shared_cache.clone()),
+        )
+    }
+
+    /// A stream (see `stream`) of the transactions from `starting_version`, from `node_url` through a client
+    /// configured as set here
+    pub fn build_stream(
+        &self,
+        node_url: Url,
+        starting_version: u64,
+    ) -> anyhow::Result<RestTransactionStream> {
+        let inner = self.build_http_client()?;
+        let compressed_client = self.compression.then(|| CompressedClient {
+            inner: inner.clone(),
+        });
+        Ok(
+            RestTransactionStream::new(Self::rest_client(inner, node_url)?, starting_version)
+                .with_compressed_client(compressed_client),
+        )
+    }
+
+    /// A fetcher from `raw_transactions` or the node's storage if set, or else from `node_url`, as BCS if a module
+    /// cache is set or else like `build_fetcher`
+    pub fn build_fetcher_trait(
+        &self,
+        node_url: Url,
+    ) -> anyhow::Result<Arc<tokio::sync::Mutex<dyn TransactionFetcherTrait>>> {
+        #[cfg(feature = "postgres")]
+        if let Some(pool) = &self.raw_transactions_pool {
+            return Ok(Arc::new(tokio::sync::Mutex::new(RawTableFetcher::new(
+                pool.clone(),
+                None,
+            ))));
+        }
+        #[cfg(feature = "storage")]
+        if let Some(node_storage) = &self.node_storage {
+            return Ok(Arc::new(tokio::sync::Mutex::new(StorageFetcher::new(
+                node_storage.clone(),
+                None,
+            ))));
+        }
+        #[cfg(feature = "vm_decoder")]
+        if let Some(module_cache) = &self.module_cache {
+            return Ok(Arc::new(tokio::sync::Mutex::new(BcsFetcher::new(
+                self.build_client(node_url)?,
+                module_cache.clone(),
+                None,
+            ))));
+        }
+        Ok(Arc::new(tokio::sync::Mutex::new(
+            self.build_fetcher(node_url)?,
+        )))
+    }
+
+    pub fn build_client(&self, node_url: Url) -> anyhow::Result<RestClient> {
+        Self::rest_client(self.build_http_client()?, node_url)
+    }
+
+    fn build_http_client(&self) -> anyhow::Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(
+                self.request_timeout
+                    .unwrap_or(Self::DEFAULT_REQUEST_TIMEOUT),
+            )
+            .user_agent(
+                self.user_agent
+                    .clone()
+                    .unwrap_or_else(|| format!("aptos-indexer/{}", env!("CARGO_PKG_VERSION"))),
+            )
+            .cookie_store(true);
+        if let Some(connect_timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(pool_max_idle_per_host) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(pool_max_idle_per_host);
+        }
+        if let Some(pool_idle_timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(pool_idle_timeout);
+        }
+        if let Some(tcp_keepalive) = self.tcp_keepalive {
+            builder = builder.tcp_keepalive(tcp_keepalive);
+        }
+        if self.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        builder
+            .build()
+            .context("Could not build the node's HTTP client")
+    }
+
+    fn rest_client(inner: reqwest::Client, node_url: Url) -> anyhow::Result<RestClient> {
+        // Like `RestClient::new`, a version in the URL's path replaces the default one
+        let path = node_url.path().trim_end_matches('/').to_string();
+        let client = RestClient::from((inner, node_url));
+        if path.is_empty() {
+            Ok(client)
+        } else {
+            Ok(client.version_path_base(format!("{}/", path))?)
+        }
+    }
+}
+
+/// Fetches batches of transactions gzipped, since the REST client doesn't negotiate compression. Shares the REST
+/// client's connections.
+#[derive(Clone, Debug)]
+pub struct CompressedClient {
+    inner: reqwest::Client,
+}
+
+impl CompressedClient {
+    pub(crate) async fn get_transactions(
+        &self,
+        client: &RestClient,
+        start: u64,
+        limit: u16,
+    ) -> Result<Vec<Transaction>, RestError> {
+        let url = Url::parse(&client.path_prefix_string())?.join("transactions")?;
+        let response = self
+            .inner
+            .get(url)
+            .query(&[("start", start.to_string()), ("limit", limit.to_string())])
+            .header(reqwest::header::ACCEPT_ENCODING, "gzip")
+            .send()
+            .await?
+            .error_for_status()?;
+        let is_gzipped = response
+            .headers()
+            .get(reqwest::header::CONTENT_ENCODING)
+            .map_or(false, |encoding| encoding == "gzip");
+        let body = response.bytes().await?;
+        FETCHED_BYTES
+            .with_label_values(&["compressed"])
+            .inc_by(body.len() as u64);
+        let body = if is_gzipped {
+            let mut decompressed = Vec::with_capacity(body.len() * 8);
+            GzDecoder::new(&body[..])
+                .read_to_end(&mut decompressed)
+                .context("Could not decompress the response")?;
+            decompressed
+        } else {
+            body.to_vec()
+        };
+        FETCHED_BYTES
+            .with_label_values(&["decompressed"])
+            .inc_by(body.len() as u64);
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
 #[derive(Debug)]
 pub struct Fetcher {
     client: RestClient,
+    compressed_client: Option<CompressedClient>,
     chain_id: u8,
     current_version: u64,
     highest_known_version: u64,
+    /// Versions before this one were pruned by the node
+    oldest_ledger_version: u64,
+    ramp: FetchRamp,
+    fetch_cache: Option<Arc<FetchCache>>,
     transactions_sender: mpsc::Sender<Vec<Transaction>>,
 }
 
 impl Fetcher {
     pub fn new(
         client: RestClient,
+        compressed_client: Option<CompressedClient>,
         current_version: u64,
         transactions_sender: mpsc::Sender<Vec<Transaction>>,
     ) -> Self {
         Self {
             client,
+            compressed_client,
             chain_id: 0,
             current_version,
             highest_known_version: current_version,
+            oldest_ledger_version: 0,
+            ramp: FetchRamp::full(),
+            fetch_cache: None,
             transactions_sender,
         }
     }
 
+    /// Ramps up from `slow_start`, if set
+    pub fn with_slow_start(mut self, slow_start: Option<SlowStart>) -> Self {
+        self.ramp = FetchRamp::new(slow_start);
+        self
+    }
+
+    /// Fetches batches through `fetch_cache`, if set
+    pub fn with_fetch_cache(mut self, fetch_cache: Option<Arc<FetchCache>>) -> Self {
+        self.fetch_cache = fetch_cache;
+        self
+    }
+
     pub async fn set_highest_known_version(&mut self) -> anyhow::Result<()> {
         let res = RestClient::try_until_ok(
             Some(MAX_RETRY_TIME),
@@ -54,6 +532,7 @@ impl Fetcher {
         .await?;
         let state = res.state();
         self.highest_known_version = state.version;
+        self.oldest_ledger_version = state.oldest_ledger_version;
         self.chain_id = state.chain_id;
         Ok(())
     }
@@ -68,6 +547,11 @@ impl Fetcher {
                         "Failed to set highest known version"
                     );
                     continue;
+                } else if self.current_version < self.oldest_ledger_version {
+                    let err =
+                        pruned_version_error(self.current_version, self.oldest_ledger_version);
+                    error!(error = format!("{:?}", err), "Next version was pruned");
+                    panic!("{:?}", err);
                 } else {
                     sample!(
                         SampleRate::Frequency(10),
@@ -81,14 +565,16 @@ impl Fetcher {
 
             let num_missing = self.highest_known_version - self.current_version;
 
+            let batch_size = self.ramp.batch_size;
             let num_batches = std::cmp::min(
-                (num_missing as f64 / TRANSACTION_FETCH_BATCH_SIZE as f64).ceil() as u64,
-                MAX_THREADS as u64,
+                (num_missing as f64 / batch_size as f64).ceil() as u64,
+                self.ramp.concurrency as u64,
             ) as usize;
 
             info!(
                 num_missing = num_missing,
                 num_batches = num_batches,
+                batch_size = batch_size,
                 current_version = self.current_version,
                 highest_known_version = self.highest_known_version,
                 "Preparing to fetch transactions"
@@ -97,12 +583,16 @@ impl Fetcher {
             let fetch_start = chrono::Utc::now().naive_utc();
             let mut futures = vec![];
             for i in 0..num_batches {
-                futures.push(fetch_nexts(
+                futures.push(fetch_nexts_cached(
                     self.client.clone(),
-                    self.current_version + (i as u64 * TRANSACTION_FETCH_BATCH_SIZE as u64),
+                    self.compressed_client.clone(),
+                    self.fetch_cache.clone(),
+                    self.current_version + (i as u64 * batch_size as u64),
+                    batch_size,
                 ));
             }
             let mut res: Vec<Vec<Transaction>> = futures::future::join_all(futures).await;
+            self.ramp.record_success(num_batches as u64);
             let total_fetched = res.iter().fold(0, |acc, v| acc + v.len());
             let fetch_millis =
                 (chrono::Utc::now().naive_utc() - fetch_start).num_milliseconds() as f64 / 1000.0;
@@ -145,31 +635,78 @@ impl Fetcher {
     }
 }
 
+/// Like `fetch_nexts`, through `fetch_cache` if set
+async fn fetch_nexts_cached(
+    client: RestClient,
+    compressed_client: Option<CompressedClient>,
+    fetch_cache: Option<Arc<FetchCache>>,
+    starting_version: u64,
+    batch_size: u16,
+) -> Vec<Transaction> {
+    match fetch_cache {
+        Some(fetch_cache) => {
+            fetch_cache
+                .get_or_fetch(starting_version, batch_size, || {
+                    fetch_nexts(client, compressed_client, starting_version, batch_size)
+                })
+                .await
+        }
+        None => fetch_nexts(client, compressed_client, starting_version, batch_size).await,
+    }
+}
+
 /// Fetches the next version based on its internal version counter
-/// Under the hood, it fetches TRANSACTION_FETCH_BATCH_SIZE versions in bulk (when needed), and uses that buffer to feed out
+/// Under the hood, it fetches `batch_size` versions in bulk (when needed), and uses that buffer to feed out
 /// In the event it can't fetch, it will keep retrying every RETRY_TIME_MILLIS ms
-async fn fetch_nexts(client: RestClient, starting_version: u64) -> Vec<Transaction> {
+async fn fetch_nexts(
+    client: RestClient,
+    compressed_client: Option<CompressedClient>,
+    starting_version: u64,
+    batch_size: u16,
+) -> Vec<Transaction> {
     let res = RestClient::try_until_ok(
         Some(MAX_RETRY_TIME),
         Some(STARTING_RETRY_TIME),
         retriable_with_404,
-        || client.get_transactions(Some(starting_version), Some(TRANSACTION_FETCH_BATCH_SIZE)),
+        || async {
+            match &compressed_client {
+                Some(compressed_client) => {
+                    compressed_client
+                        .get_transactions(&client, starting_version, batch_size)
+                        .await
+                }
+                None => client
+                    .get_transactions(Some(starting_version), Some(batch_size))
+                    .await
+                    .map(|response| response.into_inner()),
+            }
+        },
     )
     .await;
     match res {
-        Ok(response) => {
+        Ok(txns) => {
             FETCHED_TRANSACTION.inc();
-            remove_null_bytes_from_txns(response.into_inner())
+            remove_null_bytes_from_txns(txns)
         }
         Err(err) => {
             UNABLE_TO_FETCH_TRANSACTION.inc();
             error!(
                 "Could not fetch {} transactions starting at {}. Err: {:?}",
-                TRANSACTION_FETCH_BATCH_SIZE, starting_version, err
+                batch_size, starting_version, err
             );
+            // The node won't ever serve versions it pruned, so say so rather than failing with the fetch error
+            if let Ok(state) = client.get_ledger_information().await {
+                let oldest_ledger_version = state.into_inner().oldest_ledger_version;
+                if starting_version < oldest_ledger_version {
+                    panic!(
+                        "{:?}",
+                        pruned_version_error(starting_version, oldest_ledger_version)
+                    );
+                }
+            }
             panic!(
                 "Could not fetch {} transactions starting at {} in {}ms!",
-                TRANSACTION_FETCH_BATCH_SIZE, starting_version, MAX_RETRY_TIME_MILLIS
+                batch_size, starting_version, MAX_RETRY_TIME_MILLIS
             );
         }
     }
@@ -179,6 +716,9 @@ async fn fetch_nexts(client: RestClient, starting_version: u64) -> Vec<Transacti
 pub struct TransactionFetcher {
     starting_version: u64,
     client: RestClient,
+    compressed_client: Option<CompressedClient>,
+    slow_start: Option<SlowStart>,
+    fetch_cache: Option<Arc<FetchCache>>,
     fetcher_handle: Option<JoinHandle<()>>,
     transactions_sender: Option<mpsc::Sender<Vec<Transaction>>>,
     transaction_receiver: mpsc::Receiver<Vec<Transaction>>,
@@ -186,19 +726,52 @@ pub struct TransactionFetcher {
 
 impl TransactionFetcher {
     pub fn new(node_url: Url, starting_version: Option<u64>) -> Self {
+        Self::with_client(RestClient::new(node_url), starting_version)
+    }
+
+    /// Fetches through `client`, ex: one built from a `FetcherConfig`
+    pub fn with_client(client: RestClient, starting_version: Option<u64>) -> Self {
         let (transactions_sender, transaction_receiver) =
             mpsc::channel::<Vec<Transaction>>(TRANSACTION_CHANNEL_SIZE);
 
-        let client = RestClient::new(node_url);
-
         Self {
             starting_version: starting_version.unwrap_or(0),
             client,
+            compressed_client: None,
+            slow_start: None,
+            fetch_cache: None,
             fetcher_handle: None,
             transactions_sender: Some(transactions_sender),
             transaction_receiver,
         }
     }
+
+    /// Fetches batches of transactions through `compressed_client`, if set
+    pub fn with_compressed_client(mut self, compressed_client: Option<CompressedClient>) -> Self {
+        self.compressed_client = compressed_client;
+        self
+    }
+
+    /// Ramps fetching up from `slow_start` once started, if set
+    pub fn with_slow_start(mut self, slow_start: Option<SlowStart>) -> Self {
+        self.slow_start = slow_start;
+        self
+    }
+
+    /// Fetches batches through `fetch_cache` once started, if set
+    pub fn with_fetch_cache(mut self, fetch_cache: Option<Arc<FetchCache>>) -> Self {
+        self.fetch_cache = fetch_cache;
+        self
+    }
+}
+
+impl Drop for TransactionFetcher {
+    /// Stops fetching once nothing can receive the batches, ex: after a backfill
+    fn drop(&mut self) {
+        if let Some(fetcher_handle) = self.fetcher_handle.take() {
+            fetcher_handle.abort();
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -214,6 +787,13 @@ impl TransactionFetcherTrait for TransactionFetcher {
     /// fetches one version; this used for error checking/repair/etc
     /// In the event it can't, it will keep retrying every RETRY_TIME_MILLIS ms
     async fn fetch_version(&self, version: u64) -> Transaction {
+        if let Some(txn) = self
+            .fetch_cache
+            .as_ref()
+            .and_then(|fetch_cache| fetch_cache.get_version(version))
+        {
+            return txn;
+        }
         loop {
             let res = RestClient::try_until_ok(None, None, retriable_with_404, || {
                 self.client.get_transaction_by_version(version)
@@ -266,16 +846,37 @@ impl TransactionFetcherTrait for TransactionFetcher {
             panic!("TransactionFetcher already started!");
         }
         let client = self.client.clone();
+        let compressed_client = self.compressed_client.clone();
         let transactions_sender = self.transactions_sender.take().unwrap();
         let starting_version = self.starting_version;
+        let slow_start = self.slow_start;
+        let fetch_cache = self.fetch_cache.clone();
         let fetcher_handle = tokio::spawn(async move {
-            let mut fetcher = Fetcher::new(client, starting_version, transactions_sender);
+            let mut fetcher = Fetcher::new(
+                client,
+                compressed_client,
+                starting_version,
+                transactions_sender,
+            )
+            .with_slow_start(slow_start)
+            .with_fetch_cache(fetch_cache);
             fetcher.run().await;
         });
         self.fetcher_handle = Some(fetcher_handle);
     }
 }
 
+/// The error when the node no longer serves `version`, because it only keeps versions from `oldest_ledger_version`
+pub fn pruned_version_error(version: u64, oldest_ledger_version: u64) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Version {} was pruned by the node, which only serves versions from {}. Index from an archival node, or \
+         restart with --force-jump-to-oldest to skip to version {} (the versions in between won't be indexed)",
+        version,
+        oldest_ledger_version,
+        oldest_ledger_version
+    )
+}
+
 pub fn string_null_byte_replacement(value: &mut str) -> String {
     value.replace('\u{0000}', "").replace("\\u0000", "")
 }
@@ -325,3 +926,96 @@ pub trait TransactionFetcherTrait: Send + Sync {
 
     async fn start(&mut self);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_client_keeps_version_path() {
+        let config = FetcherConfig {
+            request_timeout: Some(Duration::from_secs(60)),
+            http2_prior_knowledge: true,
+            ..Default::default()
+        };
+        for (node_url, prefix) in [
+            ("http://localhost:8080", "http://localhost:8080/v1/"),
+            ("http://localhost:8080/v1", "http://localhost:8080/v1/"),
+            ("http://localhost:8080/v1/", "http://localhost:8080/v1/"),
+            ("http://localhost/node/v1", "http://localhost/node/v1/"),
+        ] {
+            let client = config.build_client(Url::parse(node_url).unwrap()).unwrap();
+            assert_eq!(client.path_prefix_string(), prefix);
+        }
+    }
+
+    #[test]
+    fn test_fetch_ramp() {
+        let mut ramp = FetchRamp::new(Some(SlowStart {
+            initial_batch_size: 100,
+            initial_concurrency: 2,
+            batches_per_step: 4,
+        }));
+        assert_eq!((ramp.batch_size, ramp.concurrency), (100, 2));
+        ramp.record_success(3);
+        assert_eq!((ramp.batch_size, ramp.concurrency), (100, 2));
+        ramp.record_success(1);
+        assert_eq!((ramp.batch_size, ramp.concurrency), (200, 4));
+        // Several steps at once, capped at the limits
+        ramp.record_success(12);
+        assert_eq!(
+            (ramp.batch_size, ramp.concurrency),
+            (TRANSACTION_FETCH_BATCH_SIZE, MAX_THREADS)
+        );
+        assert!(ramp.is_full());
+
+        assert_eq!(FetchRamp::new(None), FetchRamp::full());
+        // Out of range settings are clamped
+        let ramp = FetchRamp::new(Some(SlowStart {
+            initial_batch_size: 0,
+            initial_concurrency: 1000,
+            batches_per_step: 0,
+        }));
+        assert_eq!((ramp.batch_size, ramp.concurrency), (1, MAX_THREADS));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_cache() {
+        let cache = FetchCache::new(2, Duration::from_secs(60));
+        let fetches = &std::sync::atomic::AtomicUsize::new(0);
+        let fetch = || async move {
+            fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            tokio::task::yield_now().await;
+            vec![]
+        };
+
+        // Concurrent requests for a batch share one fetch
+        futures::future::join(
+            cache.get_or_fetch(0, 100, fetch),
+            cache.get_or_fetch(0, 100, fetch),
+        )
+        .await;
+        assert_eq!(fetches.load(std::sync::atomic::Ordering::SeqCst), 1);
+        cache.get_or_fetch(0, 100, fetch).await;
+        assert_eq!(fetches.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        // Another size is another batch, and the least recently used batch is evicted past the capacity
+        cache.get_or_fetch(0, 50, fetch).await;
+        cache.get_or_fetch(0, 100, fetch).await;
+        cache.get_or_fetch(100, 100, fetch).await;
+        assert_eq!(cache.len(), 2);
+        cache.get_or_fetch(0, 100, fetch).await;
+        assert_eq!(fetches.load(std::sync::atomic::Ordering::SeqCst), 3);
+        cache.get_or_fetch(0, 50, fetch).await;
+        assert_eq!(fetches.load(std::sync::atomic::Ordering::SeqCst), 4);
+        assert!(cache.get_version(5).is_none());
+
+        // Batches expire
+        let cache = FetchCache::new(2, Duration::from_millis(1));
+        cache.get_or_fetch(0, 100, fetch).await;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert!(cache.is_empty());
+        cache.get_or_fetch(0, 100, fetch).await;
+        assert_eq!(fetches.load(std::sync::atomic::Ordering::SeqCst), 6);
+    }
+}
@@ -0,0 +1,269 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Permissioned forks of Aptos may deploy the framework (`0x1`) and token (`0x3`) modules at other addresses, while
+//! processors match types against the canonical ones (ex: `0x1::coin::DepositEvent`). `FrameworkAddresses` maps the
+//! deployment-specific addresses back to the canonical ones in the types of each batch before any processor sees it:
+//! event types, resource types, table items' key and value types, and entry functions and type arguments. Types are
+//! stored canonical too, so queries written against public networks work unchanged.
+//!
+//! Addresses outside of types (ex: senders, resources' owners, event keys), values within resources and events, and
+//! modules' bytecode are left as is.
+
+use anyhow::{ensure, Context, Result};
+use aptos_rest_client::{
+    aptos_api_types::{Event, MoveStructTag, MoveType, TransactionPayload, WriteSetChange},
+    Transaction,
+};
+use aptos_types::account_address::AccountAddress;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Default)]
+pub struct FrameworkAddresses {
+    /// Canonical address of each deployment-specific address
+    canonical: HashMap<AccountAddress, AccountAddress>,
+}
+
+impl FrameworkAddresses {
+    /// Parses `mappings` of the form `<canonical address>=<deployment address>`, ex: `0x1=0xf1`
+    pub fn new(mappings: &[String]) -> Result<Self> {
+        let mut canonical = HashMap::new();
+        for mapping in mappings {
+            let (from, to) = mapping.split_once('=').with_context(|| {
+                format!(
+                    "Expected <canonical address>=<deployment address>, got {}",
+                    mapping
+                )
+            })?;
+            let from = AccountAddress::from_hex_literal(from.trim())
+                .with_context(|| format!("Invalid address {}", from))?;
+            let to = AccountAddress::from_hex_literal(to.trim())
+                .with_context(|| format!("Invalid address {}", to))?;
+            ensure!(
+                canonical.insert(to, from).is_none(),
+                "{} is mapped more than once",
+                to
+            );
+        }
+        Ok(Self { canonical })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.canonical.is_empty()
+    }
+
+    /// The canonical address `address` stands for, if it's a deployment-specific one
+    pub fn canonical_address(&self, address: &AccountAddress) -> Option<AccountAddress> {
+        self.canonical.get(address).copied()
+    }
+
+    /// Rewrites the deployment-specific addresses of the modules in `type_str`, ex:
+    /// `0xf1::coin::CoinStore<0xf1::aptos_coin::AptosCoin>` into `0x1::coin::CoinStore<0x1::aptos_coin::AptosCoin>`
+    pub fn canonicalize_type_str(&self, type_str: &str) -> String {
+        let mut canonical = String::with_capacity(type_str.len());
+        let mut rest = type_str;
+        while let Some(start) = rest.find("0x") {
+            canonical.push_str(&rest[..start]);
+            let literal_len = 2 + rest[start + 2..]
+                .find(|c: char| !c.is_ascii_hexdigit())
+                .unwrap_or(rest.len() - start - 2);
+            let literal = &rest[start..start + literal_len];
+            rest = &rest[start + literal_len..];
+            // Only addresses of modules, not ex: address-typed values in a type's name
+            match AccountAddress::from_hex_literal(literal)
+                .ok()
+                .filter(|_| rest.starts_with("::"))
+                .and_then(|address| self.canonical_address(&address))
+            {
+                Some(address) => canonical.push_str(&address.to_hex_literal()),
+                None => canonical.push_str(literal),
+            }
+        }
+        canonical.push_str(rest);
+        canonical
+    }
+
+    fn canonicalize_struct_tag(&self, tag: &mut MoveStructTag) {
+        if let Some(address) = self.canonical_address(tag.address.inner()) {
+            tag.address = address.into();
+        }
+        for type_param in &mut tag.generic_type_params {
+            self.canonicalize_move_type(type_param);
+        }
+    }
+
+    fn canonicalize_move_type(&self, typ: &mut MoveType) {
+        match typ {
+            MoveType::Struct(tag) => self.canonicalize_struct_tag(tag),
+            MoveType::Vector { items } => self.canonicalize_move_type(items),
+            MoveType::Reference { to, .. } => self.canonicalize_move_type(to),
+            MoveType::Unparsable(type_str) => *type_str = self.canonicalize_type_str(type_str),
+            _ => (),
+        }
+    }
+
+    fn canonicalize_events(&self, events: &mut [Event]) {
+        for event in events {
+            self.canonicalize_move_type(&mut event.typ);
+        }
+    }
+
+    fn canonicalize_changes(&self, changes: &mut [WriteSetChange]) {
+        for change in changes {
+            match change {
+                WriteSetChange::WriteResource(resource) => {
+                    self.canonicalize_struct_tag(&mut resource.data.typ)
+                }
+                WriteSetChange::DeleteResource(resource) => {
+                    self.canonicalize_struct_tag(&mut resource.resource)
+                }
+                WriteSetChange::WriteTableItem(item) => {
+                    if let Some(data) = &mut item.data {
+                        data.key_type = self.canonicalize_type_str(&data.key_type);
+                        data.value_type = self.canonicalize_type_str(&data.value_type);
+                    }
+                }
+                WriteSetChange::DeleteTableItem(item) => {
+                    if let Some(data) = &mut item.data {
+                        data.key_type = self.canonicalize_type_str(&data.key_type);
+                    }
+                }
+                WriteSetChange::WriteModule(_) | WriteSetChange::DeleteModule(_) => (),
+            }
+        }
+    }
+
+    fn canonicalize_payload(&self, payload: &mut TransactionPayload) {
+        let type_arguments = match payload {
+            TransactionPayload::EntryFunctionPayload(payload) => {
+                let module = &mut payload.function.module;
+                if let Some(address) = self.canonical_address(module.address.inner()) {
+                    module.address = address.into();
+                }
+                &mut payload.type_arguments
+            }
+            TransactionPayload::ScriptPayload(payload) => &mut payload.type_arguments,
+            TransactionPayload::ModuleBundlePayload(_) => return,
+        };
+        for typ in type_arguments {
+            self.canonicalize_move_type(typ);
+        }
+    }
+
+    /// Rewrites the deployment-specific addresses in the types of `txn`
+    pub fn canonicalize(&self, txn: &mut Transaction) {
+        if self.is_empty() {
+            return;
+        }
+        match txn {
+            Transaction::UserTransaction(user_txn) => {
+                self.canonicalize_changes(&mut user_txn.info.changes);
+                self.canonicalize_payload(&mut user_txn.request.payload);
+                self.canonicalize_events(&mut user_txn.events);
+            }
+            Transaction::GenesisTransaction(genesis_txn) => {
+                self.canonicalize_changes(&mut genesis_txn.info.changes);
+                self.canonicalize_events(&mut genesis_txn.events);
+            }
+            Transaction::BlockMetadataTransaction(block_metadata_txn) => {
+                self.canonicalize_changes(&mut block_metadata_txn.info.changes);
+                self.canonicalize_events(&mut block_metadata_txn.events);
+            }
+            Transaction::StateCheckpointTransaction(state_checkpoint_txn) => {
+                self.canonicalize_changes(&mut state_checkpoint_txn.info.changes);
+            }
+            Transaction::PendingTransaction(_) => (),
+        }
+    }
+
+    pub fn canonicalize_all(&self, mut txns: Vec<Transaction>) -> Vec<Transaction> {
+        for txn in &mut txns {
+            self.canonicalize(txn);
+        }
+        txns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_framework_addresses() {
+        assert!(FrameworkAddresses::new(&["0x1".to_string()]).is_err());
+        assert!(
+            FrameworkAddresses::new(&["0x1=0xf1".to_string(), "0x3=0xf1".to_string()]).is_err()
+        );
+        let addresses =
+            FrameworkAddresses::new(&["0x1=0xf1".to_string(), "0x3=0xf3".to_string()]).unwrap();
+
+        assert_eq!(
+            addresses.canonicalize_type_str("0xf1::coin::CoinStore<0xf1::aptos_coin::AptosCoin>"),
+            "0x1::coin::CoinStore<0x1::aptos_coin::AptosCoin>"
+        );
+        assert_eq!(
+            addresses.canonicalize_type_str("0xf3::token::TokenId"),
+            "0x3::token::TokenId"
+        );
+        // Other modules, and addresses which aren't modules', are left alone
+        assert_eq!(
+            addresses.canonicalize_type_str("0xabc::swap::Pool<0xf1::a::A, 0xf1b::b::B>"),
+            "0xabc::swap::Pool<0x1::a::A, 0xf1b::b::B>"
+        );
+        assert_eq!(addresses.canonicalize_type_str("0xf1"), "0xf1");
+
+        let mut txn: Transaction = serde_json::from_value(serde_json::json!({
+            "type": "user_transaction",
+            "version": "1",
+            "hash": format!("0x{:064x}", 1),
+            "state_change_hash": format!("0x{:064x}", 0),
+            "event_root_hash": format!("0x{:064x}", 0),
+            "gas_used": "1",
+            "success": true,
+            "vm_status": "Executed successfully",
+            "accumulator_root_hash": format!("0x{:064x}", 0),
+            "changes": [{
+                "type": "write_resource",
+                "address": "0xf1",
+                "state_key_hash": "0x1234",
+                "data": {
+                    "type": "0xf1::coin::CoinStore<0xf1::aptos_coin::AptosCoin>",
+                    "data": {},
+                },
+            }],
+            "sender": "0xf1",
+            "sequence_number": "0",
+            "max_gas_amount": "1000",
+            "gas_unit_price": "100",
+            "expiration_timestamp_secs": "1",
+            "payload": {
+                "type": "entry_function_payload",
+                "function": "0xf1::coin::transfer",
+                "type_arguments": ["0xf1::aptos_coin::AptosCoin"],
+                "arguments": [],
+            },
+            "events": [{
+                "guid": {"creation_number": "0", "account_address": "0xf1"},
+                "sequence_number": "0",
+                "type": "0xf1::coin::DepositEvent",
+                "data": {"amount": "1"},
+            }],
+            "timestamp": "1",
+        }))
+        .unwrap();
+        addresses.canonicalize(&mut txn);
+        let json = serde_json::to_value(&txn).unwrap();
+        assert_eq!(
+            json["changes"][0]["data"]["type"],
+            "0x1::coin::CoinStore<0x1::aptos_coin::AptosCoin>"
+        );
+        assert_eq!(json["changes"][0]["address"], "0xf1");
+        assert_eq!(json["payload"]["function"], "0x1::coin::transfer");
+        assert_eq!(
+            json["payload"]["type_arguments"][0],
+            "0x1::aptos_coin::AptosCoin"
+        );
+        assert_eq!(json["sender"], "0xf1");
+        assert_eq!(json["events"][0]["type"], "0x1::coin::DepositEvent");
+    }
+}
@@ -0,0 +1,546 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Where processors record which versions they've processed, and which chain is being indexed.
+//! Postgres deployments use `PgMetadataHandle` (`processor_metadata.rs`); builds without Postgres (ex: sink-only
+//! indexers) keep this in memory, optionally persisted to a file, or in another store (see `MetadataBackend`).
+
+#[cfg(feature = "dynamodb")]
+use crate::indexer::dynamodb_metadata::{DynamoDbConfig, DynamoDbMetadataHandle};
+#[cfg(feature = "spanner")]
+use crate::indexer::spanner_metadata::{SpannerConfig, SpannerMetadataHandle};
+use crate::models::ledger_info::LedgerInfoHistory;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::runtime::Handle;
+
+/// How often `await_version` checks the processor's statuses
+pub const AWAIT_VERSION_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Processing status of a single version for a processor
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct VersionStatus {
+    pub version: u64,
+    pub success: bool,
+    pub details: Option<String>,
+}
+
+impl VersionStatus {
+    pub fn new(version: u64, success: bool, details: Option<String>) -> Self {
+        Self {
+            version,
+            success,
+            details,
+        }
+    }
+
+    /// The same status for every version from `start_version` to `end_version` (inclusive)
+    pub fn range(
+        start_version: u64,
+        end_version: u64,
+        success: bool,
+        details: Option<String>,
+    ) -> Vec<Self> {
+        (start_version..=end_version)
+            .map(|version| Self::new(version, success, details.clone()))
+            .collect()
+    }
+}
+
+pub trait MetadataHandle: Send + Sync + Debug {
+    /// Upserts the statuses of `processor_name`
+    fn set_statuses(&self, processor_name: &str, statuses: &[VersionStatus]) -> Result<()>;
+
+    /// Versions which were not successfully processed, so they can be retried
+    fn get_error_versions(&self, processor_name: &str) -> Result<Vec<u64>>;
+
+    /// Highest version with any status
+    fn get_max_version(&self, processor_name: &str) -> Result<Option<u64>>;
+
+    /// Lowest and highest successfully processed versions
+    fn get_successful_version_bounds(&self, processor_name: &str) -> Result<Option<(u64, u64)>>;
+
+    /// Successfully processed versions from `start_version` to `end_version` (inclusive), in order
+    fn get_successful_versions(
+        &self,
+        processor_name: &str,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<Vec<u64>>;
+
+    /// Where to resume processing from: the first version that's either not successful or missing
+    fn get_start_version(&self, processor_name: &str) -> Result<Option<u64>>;
+
+    fn get_chain_id(&self) -> Result<Option<u64>>;
+
+    fn set_chain_id(&self, chain_id: u64) -> Result<()>;
+
+    /// Records `chain_id` as the indexed chain if none is yet, returning the one already recorded otherwise. Handles
+    /// shared by several indexers make this atomic, so replicas starting at the same time don't both record theirs.
+    fn check_or_set_chain_id(&self, chain_id: u64) -> Result<Option<u64>> {
+        let existing_chain_id = self.get_chain_id()?;
+        if existing_chain_id.is_none() {
+            self.set_chain_id(chain_id)?;
+        }
+        Ok(existing_chain_id)
+    }
+
+    /// Records a snapshot of the node's ledger info. Only Postgres keeps this history; other handles drop it.
+    fn record_ledger_info(&self, _ledger_info: &LedgerInfoHistory) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs `future` on `runtime` from a `MetadataHandle` method, for handles whose store is only reachable through async
+/// clients. The methods are called from the runtime's worker threads, so this needs the multi-threaded runtime.
+pub(crate) fn block_on<T>(runtime: &Handle, future: impl Future<Output = T>) -> T {
+    tokio::task::block_in_place(|| runtime.block_on(future))
+}
+
+/// Waits until `processor_name` has successfully processed every version up to `version` (inclusive), ex: for tests
+/// and services which need to read what they've just written. Returns whether it did within `timeout`. The handle's
+/// methods block, so its statuses are read with `spawn_blocking`.
+pub async fn await_version(
+    handle: Arc<dyn MetadataHandle>,
+    processor_name: &str,
+    version: u64,
+    timeout: Duration,
+) -> Result<bool> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let start_version = {
+            let handle = handle.clone();
+            let processor_name = processor_name.to_string();
+            tokio::task::spawn_blocking(move || handle.get_start_version(&processor_name))
+                .await
+                .context("Reading the processor's statuses panicked")??
+        };
+        // Processing resumes from the first version not processed successfully
+        if start_version.map_or(false, |start_version| start_version > version) {
+            return Ok(true);
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(false);
+        }
+        tokio::time::sleep(std::cmp::min(AWAIT_VERSION_POLL_INTERVAL, deadline - now)).await;
+    }
+}
+
+/// A processor's statuses. Successful versions are kept as ranges, so memory use doesn't grow with the number of
+/// versions processed.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub(crate) struct ProcessorState {
+    /// Start -> end (inclusive) of ranges of successfully processed versions
+    pub(crate) successful_ranges: BTreeMap<u64, u64>,
+    /// Statuses of versions which are not (yet) successful
+    pub(crate) statuses: BTreeMap<u64, VersionStatus>,
+}
+
+impl ProcessorState {
+    pub(crate) fn set_status(&mut self, status: &VersionStatus) {
+        if status.success {
+            self.statuses.remove(&status.version);
+            self.add_successful(status.version);
+        } else {
+            self.remove_successful(status.version);
+            self.statuses.insert(status.version, status.clone());
+        }
+    }
+
+    fn successful_range_of(&self, version: u64) -> Option<(u64, u64)> {
+        self.successful_ranges
+            .range(..=version)
+            .next_back()
+            .filter(|(_, end)| **end >= version)
+            .map(|(start, end)| (*start, *end))
+    }
+
+    fn add_successful(&mut self, version: u64) {
+        if self.successful_range_of(version).is_some() {
+            return;
+        }
+        let mut start = version;
+        let mut end = version;
+        if let Some((prev_start, prev_end)) = self
+            .successful_ranges
+            .range(..version)
+            .next_back()
+            .map(|(start, end)| (*start, *end))
+        {
+            if prev_end + 1 == version {
+                start = prev_start;
+            }
+        }
+        if let Some(next_end) = self.successful_ranges.remove(&(version + 1)) {
+            end = next_end;
+        }
+        self.successful_ranges.insert(start, end);
+    }
+
+    fn remove_successful(&mut self, version: u64) {
+        if let Some((start, end)) = self.successful_range_of(version) {
+            self.successful_ranges.remove(&start);
+            if start < version {
+                self.successful_ranges.insert(start, version - 1);
+            }
+            if version < end {
+                self.successful_ranges.insert(version + 1, end);
+            }
+        }
+    }
+
+    pub(crate) fn error_versions(&self) -> Vec<u64> {
+        self.statuses.keys().copied().collect()
+    }
+
+    pub(crate) fn max_version(&self) -> Option<u64> {
+        let max_successful = self.successful_ranges.values().next_back().copied();
+        let max_status = self.statuses.keys().next_back().copied();
+        max_successful.max(max_status)
+    }
+
+    pub(crate) fn successful_version_bounds(&self) -> Option<(u64, u64)> {
+        let min = self.successful_ranges.keys().next().copied();
+        let max = self.successful_ranges.values().next_back().copied();
+        min.zip(max)
+    }
+
+    pub(crate) fn successful_versions(&self, start_version: u64, end_version: u64) -> Vec<u64> {
+        let mut versions = vec![];
+        // The range containing `start_version` (if any) starts before it
+        let first_start = self
+            .successful_range_of(start_version)
+            .map_or(start_version, |(start, _)| start);
+        for (start, end) in self.successful_ranges.range(first_start..=end_version) {
+            versions
+                .extend(std::cmp::max(*start, start_version)..=std::cmp::min(*end, end_version));
+        }
+        versions
+    }
+
+    pub(crate) fn start_version(&self) -> Option<u64> {
+        let first_status = self.statuses.keys().next().copied();
+        match self.successful_ranges.iter().next() {
+            // Right after the first successful range is either missing or not successful
+            Some((start, end)) if first_status.map_or(true, |first| *start < first) => {
+                Some(end + 1)
+            }
+            _ => first_status,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct MetadataState {
+    chain_id: Option<u64>,
+    processors: BTreeMap<String, ProcessorState>,
+}
+
+/// Keeps metadata in memory only: a restart reprocesses from the beginning (or `--start-from-version`)
+#[derive(Debug, Default)]
+pub struct InMemoryMetadataHandle {
+    state: Mutex<MetadataState>,
+}
+
+impl InMemoryMetadataHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_state<T>(&self, f: impl FnOnce(&mut MetadataState) -> T) -> T {
+        f(&mut self.state.lock().unwrap())
+    }
+
+    fn with_processor<T>(&self, processor_name: &str, f: impl FnOnce(&ProcessorState) -> T) -> T {
+        self.with_state(|state| {
+            f(state
+                .processors
+                .get(processor_name)
+                .unwrap_or(&ProcessorState::default()))
+        })
+    }
+}
+
+impl MetadataHandle for InMemoryMetadataHandle {
+    fn set_statuses(&self, processor_name: &str, statuses: &[VersionStatus]) -> Result<()> {
+        self.with_state(|state| {
+            let processor = state
+                .processors
+                .entry(processor_name.to_string())
+                .or_default();
+            for status in statuses {
+                processor.set_status(status);
+            }
+        });
+        Ok(())
+    }
+
+    fn get_error_versions(&self, processor_name: &str) -> Result<Vec<u64>> {
+        Ok(self.with_processor(processor_name, ProcessorState::error_versions))
+    }
+
+    fn get_max_version(&self, processor_name: &str) -> Result<Option<u64>> {
+        Ok(self.with_processor(processor_name, ProcessorState::max_version))
+    }
+
+    fn get_successful_version_bounds(&self, processor_name: &str) -> Result<Option<(u64, u64)>> {
+        Ok(self.with_processor(processor_name, ProcessorState::successful_version_bounds))
+    }
+
+    fn get_successful_versions(
+        &self,
+        processor_name: &str,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<Vec<u64>> {
+        Ok(self.with_processor(processor_name, |processor| {
+            processor.successful_versions(start_version, end_version)
+        }))
+    }
+
+    fn get_start_version(&self, processor_name: &str) -> Result<Option<u64>> {
+        Ok(self.with_processor(processor_name, ProcessorState::start_version))
+    }
+
+    fn get_chain_id(&self) -> Result<Option<u64>> {
+        Ok(self.with_state(|state| state.chain_id))
+    }
+
+    fn set_chain_id(&self, chain_id: u64) -> Result<()> {
+        self.with_state(|state| state.chain_id = Some(chain_id));
+        Ok(())
+    }
+}
+
+/// Keeps metadata in memory and persists it as JSON to a file after every write, so restarts resume where they
+/// left off. Only one indexer may use a given file.
+#[derive(Debug)]
+pub struct FileMetadataHandle {
+    path: PathBuf,
+    inner: InMemoryMetadataHandle,
+}
+
+impl FileMetadataHandle {
+    /// Loads the metadata at `path`, if the file exists
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let state = if path.exists() {
+            let contents = std::fs::read(&path)
+                .with_context(|| format!("Could not read metadata file {:?}", path))?;
+            serde_json::from_slice(&contents)
+                .with_context(|| format!("Could not parse metadata file {:?}", path))?
+        } else {
+            MetadataState::default()
+        };
+        Ok(Self {
+            path,
+            inner: InMemoryMetadataHandle {
+                state: Mutex::new(state),
+            },
+        })
+    }
+
+    /// Writes to a temporary file first, so a crash mid-write can't corrupt the metadata
+    fn persist(&self) -> Result<()> {
+        let contents = self
+            .inner
+            .with_state(|state| serde_json::to_vec(&*state))
+            .context("Could not serialize metadata")?;
+        let tmp_path = self.path.with_extension("tmp");
+        std::fs::write(&tmp_path, contents)
+            .with_context(|| format!("Could not write metadata file {:?}", tmp_path))?;
+        std::fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Could not replace metadata file {:?}", self.path))
+    }
+}
+
+impl MetadataHandle for FileMetadataHandle {
+    fn set_statuses(&self, processor_name: &str, statuses: &[VersionStatus]) -> Result<()> {
+        self.inner.set_statuses(processor_name, statuses)?;
+        self.persist()
+    }
+
+    fn get_error_versions(&self, processor_name: &str) -> Result<Vec<u64>> {
+        self.inner.get_error_versions(processor_name)
+    }
+
+    fn get_max_version(&self, processor_name: &str) -> Result<Option<u64>> {
+        self.inner.get_max_version(processor_name)
+    }
+
+    fn get_successful_version_bounds(&self, processor_name: &str) -> Result<Option<(u64, u64)>> {
+        self.inner.get_successful_version_bounds(processor_name)
+    }
+
+    fn get_successful_versions(
+        &self,
+        processor_name: &str,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<Vec<u64>> {
+        self.inner
+            .get_successful_versions(processor_name, start_version, end_version)
+    }
+
+    fn get_start_version(&self, processor_name: &str) -> Result<Option<u64>> {
+        self.inner.get_start_version(processor_name)
+    }
+
+    fn get_chain_id(&self) -> Result<Option<u64>> {
+        self.inner.get_chain_id()
+    }
+
+    fn set_chain_id(&self, chain_id: u64) -> Result<()> {
+        self.inner.set_chain_id(chain_id)?;
+        self.persist()
+    }
+}
+
+/// Where sinks record their statuses and the chain id, when not in Postgres
+#[derive(Clone, Debug)]
+pub enum MetadataBackend {
+    /// Lost on restart, see `InMemoryMetadataHandle`
+    InMemory,
+    /// See `FileMetadataHandle`
+    File(PathBuf),
+    /// See `SpannerMetadataHandle`
+    #[cfg(feature = "spanner")]
+    Spanner(SpannerConfig),
+    /// See `DynamoDbMetadataHandle`. The region is read from the environment when opened, see `DynamoDbConfig::new`.
+    #[cfg(feature = "dynamodb")]
+    DynamoDb {
+        table: String,
+        endpoint: Option<String>,
+    },
+}
+
+impl MetadataBackend {
+    /// Opens a handle to the backend. Handles of remote stores need to be opened within tokio's runtime.
+    pub fn open(&self) -> Result<Arc<dyn MetadataHandle>> {
+        Ok(match self {
+            MetadataBackend::InMemory => Arc::new(InMemoryMetadataHandle::new()),
+            MetadataBackend::File(path) => Arc::new(FileMetadataHandle::new(path)?),
+            #[cfg(feature = "spanner")]
+            MetadataBackend::Spanner(config) => {
+                Arc::new(SpannerMetadataHandle::new(config.clone())?)
+            }
+            #[cfg(feature = "dynamodb")]
+            MetadataBackend::DynamoDb { table, endpoint } => {
+                Arc::new(DynamoDbMetadataHandle::new(DynamoDbConfig {
+                    endpoint: endpoint.clone(),
+                    ..DynamoDbConfig::new(table.clone())?
+                })?)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAME: &str = "test_processor";
+
+    #[test]
+    fn test_in_memory_statuses_compact() {
+        let handle = InMemoryMetadataHandle::new();
+        assert_eq!(handle.get_start_version(NAME).unwrap(), None);
+
+        handle
+            .set_statuses(NAME, &VersionStatus::range(0, 9, false, None))
+            .unwrap();
+        handle
+            .set_statuses(NAME, &VersionStatus::range(10, 19, true, None))
+            .unwrap();
+        assert_eq!(handle.get_start_version(NAME).unwrap(), Some(0));
+        assert_eq!(handle.get_error_versions(NAME).unwrap().len(), 10);
+
+        handle
+            .set_statuses(NAME, &VersionStatus::range(0, 9, true, None))
+            .unwrap();
+        assert_eq!(handle.get_start_version(NAME).unwrap(), Some(20));
+        assert_eq!(handle.get_max_version(NAME).unwrap(), Some(19));
+        assert!(handle.get_error_versions(NAME).unwrap().is_empty());
+        assert_eq!(
+            handle.with_processor(NAME, |processor| processor.successful_ranges.len()),
+            1
+        );
+
+        // Failing a compacted version splits the range
+        handle
+            .set_statuses(
+                NAME,
+                &[VersionStatus::new(15, false, Some("missing".to_string()))],
+            )
+            .unwrap();
+        assert_eq!(handle.get_error_versions(NAME).unwrap(), vec![15]);
+        assert_eq!(handle.get_start_version(NAME).unwrap(), Some(15));
+        assert_eq!(
+            handle.get_successful_versions(NAME, 13, 17).unwrap(),
+            vec![13, 14, 16, 17]
+        );
+        assert_eq!(
+            handle.get_successful_version_bounds(NAME).unwrap(),
+            Some((0, 19))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_await_version() {
+        let handle = Arc::new(InMemoryMetadataHandle::new());
+        let timeout = Duration::from_millis(10);
+        assert!(!await_version(handle.clone(), NAME, 5, timeout)
+            .await
+            .unwrap());
+
+        handle
+            .set_statuses(NAME, &VersionStatus::range(0, 4, true, None))
+            .unwrap();
+        handle
+            .set_statuses(NAME, &VersionStatus::range(6, 9, true, None))
+            .unwrap();
+        assert!(await_version(handle.clone(), NAME, 4, timeout)
+            .await
+            .unwrap());
+        // Version 5 is missing, so later versions aren't awaited either
+        assert!(!await_version(handle.clone(), NAME, 7, timeout)
+            .await
+            .unwrap());
+
+        handle
+            .set_statuses(NAME, &[VersionStatus::new(5, true, None)])
+            .unwrap();
+        assert!(await_version(handle.clone(), NAME, 7, timeout)
+            .await
+            .unwrap());
+    }
+
+    #[test]
+    fn test_file_handle_persists() {
+        let dir = std::env::temp_dir().join(format!("indexer-metadata-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("metadata.json");
+        let _ = std::fs::remove_file(&path);
+
+        let handle = FileMetadataHandle::new(&path).unwrap();
+        assert_eq!(handle.check_or_set_chain_id(4).unwrap(), None);
+        assert_eq!(handle.check_or_set_chain_id(5).unwrap(), Some(4));
+        handle
+            .set_statuses(NAME, &VersionStatus::range(0, 4, true, None))
+            .unwrap();
+
+        let reloaded = FileMetadataHandle::new(&path).unwrap();
+        assert_eq!(reloaded.get_chain_id().unwrap(), Some(4));
+        assert_eq!(reloaded.get_start_version(NAME).unwrap(), Some(5));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,9 +1,42 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod account_changes;
+pub mod audit_log;
+#[cfg(feature = "postgres")]
+pub mod builder;
+pub mod commit_hooks;
+pub mod dispatch;
+#[cfg(feature = "dynamodb")]
+pub mod dynamodb_metadata;
 pub mod errors;
+#[cfg(test)]
+pub mod faulty_handle;
 pub mod fetcher;
+pub mod framework_addresses;
+#[cfg(feature = "api")]
+pub mod grpc_stream;
 pub mod metadata_fetcher;
+pub mod metadata_handle;
+#[cfg(feature = "postgres")]
+pub mod outbox_relay;
+pub mod pipeline;
+pub mod price_provider;
 pub mod processing_result;
+#[cfg(feature = "postgres")]
+pub mod processor_metadata;
+#[cfg(feature = "postgres")]
+pub mod raw_fetcher;
+#[cfg(feature = "spanner")]
+pub mod spanner_metadata;
+#[cfg(feature = "storage")]
+pub mod storage_fetcher;
+pub mod stream;
 pub mod tailer;
+pub mod timestamps;
 pub mod transaction_processor;
+pub mod transform;
+#[cfg(feature = "uri_enricher")]
+pub mod uri_enricher;
+#[cfg(feature = "vm_decoder")]
+pub mod vm_decoder;
@@ -0,0 +1,168 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Relays the messages processors write into `outbox` to an HTTP endpoint, for deployments which can't run a
+//! message broker's client in the indexer: the endpoint (ex: a serverless function publishing to a queue) receives
+//! batches of messages as JSON.
+//!
+//! Messages are marked delivered once the endpoint acknowledges them with a 2xx, so delivery is at least once: a
+//! message whose acknowledgement is lost is delivered again, and consumers should deduplicate by `id`. Messages are
+//! sent in id order, which follows version order within a processor's batch but not across concurrently committed
+//! batches. Run a single relay per database.
+
+use crate::{
+    counters::OUTBOX_RELAY_MESSAGES, database::PgDbPool,
+    indexer::transaction_processor::get_conn_with_retry, schema::outbox::dsl,
+};
+use anyhow::{bail, Context, Result};
+use bigdecimal::BigDecimal;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use serde::Serialize;
+use std::time::Duration;
+
+#[derive(Clone, Debug)]
+pub struct OutboxRelayConfig {
+    /// Endpoint batches of messages are POSTed to
+    pub url: String,
+    /// Maximum number of messages per request
+    pub batch_size: i64,
+    /// How long to wait before looking for new messages, when there were none
+    pub poll_interval: Duration,
+    /// How long to wait before retrying a batch the endpoint failed to acknowledge
+    pub retry_interval: Duration,
+    pub request_timeout: Duration,
+}
+
+impl Default for OutboxRelayConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            batch_size: 500,
+            poll_interval: Duration::from_secs(1),
+            retry_interval: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// An undelivered message, as sent to the endpoint
+#[derive(Debug, Queryable, Serialize)]
+pub struct PendingOutboxMessage {
+    /// Unique, for consumers to deduplicate redeliveries
+    pub id: i64,
+    pub topic: String,
+    pub transaction_version: BigDecimal,
+    pub message_key: String,
+    pub payload: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+struct DeliveryRequest<'a> {
+    messages: &'a [PendingOutboxMessage],
+}
+
+pub struct OutboxRelay {
+    connection_pool: PgDbPool,
+    config: OutboxRelayConfig,
+    client: reqwest::Client,
+}
+
+impl OutboxRelay {
+    pub fn new(connection_pool: PgDbPool, config: OutboxRelayConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(config.request_timeout)
+            .build()
+            .context("Failed to build the outbox relay's HTTP client")?;
+        Ok(Self {
+            connection_pool,
+            config,
+            client,
+        })
+    }
+
+    /// Relays messages as they're written, until stopped
+    pub async fn run(self) {
+        loop {
+            match self.relay_next_batch().await {
+                Ok(0) => tokio::time::sleep(self.config.poll_interval).await,
+                Ok(_) => (),
+                Err(err) => {
+                    aptos_logger::error!(
+                        error = format!("{:?}", err),
+                        "Failed to relay outbox messages"
+                    );
+                    tokio::time::sleep(self.config.retry_interval).await;
+                }
+            }
+        }
+    }
+
+    /// Delivers the oldest undelivered messages, returning how many. On failure, the messages are left undelivered
+    /// with their attempt recorded, so the next call retries them.
+    pub async fn relay_next_batch(&self) -> Result<usize> {
+        let messages: Vec<PendingOutboxMessage> = dsl::outbox
+            .select((
+                dsl::id,
+                dsl::topic,
+                dsl::transaction_version,
+                dsl::message_key,
+                dsl::payload,
+            ))
+            .filter(dsl::delivered_at.is_null())
+            .order(dsl::id.asc())
+            .limit(self.config.batch_size)
+            .load(&get_conn_with_retry(&self.connection_pool))
+            .context("Error loading outbox messages")?;
+        if messages.is_empty() {
+            return Ok(0);
+        }
+        let ids: Vec<i64> = messages.iter().map(|message| message.id).collect();
+
+        // Not holding a connection while waiting for the endpoint
+        let result = self.deliver(&messages).await;
+        let conn = get_conn_with_retry(&self.connection_pool);
+        match result {
+            Ok(()) => {
+                diesel::update(dsl::outbox.filter(dsl::id.eq_any(ids.clone())))
+                    .set(dsl::delivered_at.eq(chrono::Utc::now().naive_utc()))
+                    .execute(&conn)
+                    .context("Error marking outbox messages delivered")?;
+                OUTBOX_RELAY_MESSAGES
+                    .with_label_values(&["delivered"])
+                    .inc_by(ids.len() as u64);
+                Ok(ids.len())
+            }
+            Err(err) => {
+                diesel::update(dsl::outbox.filter(dsl::id.eq_any(ids.clone())))
+                    .set((
+                        dsl::delivery_attempts.eq(dsl::delivery_attempts + 1),
+                        dsl::last_error.eq(format!("{:#}", err)),
+                    ))
+                    .execute(&conn)
+                    .context("Error recording failed outbox deliveries")?;
+                OUTBOX_RELAY_MESSAGES
+                    .with_label_values(&["failed"])
+                    .inc_by(ids.len() as u64);
+                Err(err)
+            }
+        }
+    }
+
+    async fn deliver(&self, messages: &[PendingOutboxMessage]) -> Result<()> {
+        let response = self
+            .client
+            .post(&self.config.url)
+            .json(&DeliveryRequest { messages })
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!(
+                "The outbox endpoint responded {}: {}",
+                status,
+                response.text().await.unwrap_or_default()
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,371 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Runs a `Tailer` as a pipeline of stages connected by bounded channels, so each stage keeps working while the
+//! next one is busy:
+//! - fetch: takes the batches the `TransactionFetcher` has fetched from the node
+//! - batch: splits them into processing batches of `batch_size` versions
+//! - process: converts and writes up to `process_concurrency` batches at a time, each within one DB transaction
+//!   (with its statuses) so a batch is never marked successful without its data, then updates the rollups
+//!
+//! When a stage falls behind, the channel in front of it fills up and the stages before it wait. With a
+//! `MemoryBudget`, the fetch stage also waits while the batches in flight are estimated to exceed it, or (with a
+//! `spill_dir`) writes the batches it fetches to disk until the process stage catches up.
+
+use crate::{
+    counters::{
+        PIPELINE_IN_FLIGHT_BYTES, PIPELINE_SPILLED_BATCHES, PIPELINE_STAGE_BATCHES,
+        PIPELINE_STAGE_SECONDS,
+    },
+    indexer::{
+        errors::TransactionProcessingError, processing_result::ProcessingResult, tailer::Tailer,
+    },
+};
+use aptos_logger::{info, warn};
+use aptos_rest_client::Transaction;
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc, Notify, Semaphore};
+
+pub type BatchResult = Result<ProcessingResult, TransactionProcessingError>;
+
+/// The result of processing a batch, and how long converting and writing it took
+#[derive(Debug)]
+pub struct ProcessedBatch {
+    pub result: BatchResult,
+    pub processing_time: Duration,
+}
+
+const FETCH_STAGE: &str = "fetch";
+const BATCH_STAGE: &str = "batch";
+const PROCESS_STAGE: &str = "process";
+
+/// Sizes and concurrency of the stages of a `Tailer`'s pipeline
+#[derive(Clone, Debug)]
+pub struct PipelineConfig {
+    /// Number of versions in each processing batch
+    pub batch_size: u8,
+    /// Number of fetched batches buffered between the fetch and batch stages
+    pub fetch_channel_size: usize,
+    /// Number of processing batches buffered between the batch and process stages
+    pub process_channel_size: usize,
+    /// Maximum number of batches processed concurrently (the processor's quota may lower this)
+    pub process_concurrency: usize,
+    /// Number of processed batch results buffered until they're consumed
+    pub result_channel_size: usize,
+    /// If set, caps the estimated size of the batches in flight. Can be shared by several tailers' pipelines.
+    pub memory_budget: Option<Arc<MemoryBudget>>,
+    /// If set (along with `memory_budget`), batches fetched while over budget are written to files in this
+    /// directory, rather than pausing fetching
+    pub spill_dir: Option<PathBuf>,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 10,
+            fetch_channel_size: 10,
+            process_channel_size: 100,
+            process_concurrency: 50,
+            result_channel_size: 100,
+            memory_budget: None,
+            spill_dir: None,
+        }
+    }
+}
+
+/// Caps the estimated size (serialized, in bytes) of the batches in flight in one or more pipelines
+#[derive(Debug)]
+pub struct MemoryBudget {
+    max_bytes: u64,
+    in_flight_bytes: Mutex<u64>,
+    released: Notify,
+}
+
+impl MemoryBudget {
+    pub fn new(max_bytes: u64) -> Self {
+        Self {
+            max_bytes,
+            in_flight_bytes: Mutex::new(0),
+            released: Notify::new(),
+        }
+    }
+
+    /// Reserves `bytes` if they fit in the budget. A batch bigger than the whole budget fits once nothing else is
+    /// in flight, so it can't wait forever.
+    pub fn try_reserve(&self, bytes: u64) -> bool {
+        let mut in_flight_bytes = self.in_flight_bytes.lock().unwrap();
+        if *in_flight_bytes > 0 && *in_flight_bytes + bytes > self.max_bytes {
+            return false;
+        }
+        *in_flight_bytes += bytes;
+        true
+    }
+
+    /// Waits until `bytes` fit in the budget, then reserves them
+    pub async fn reserve(&self, bytes: u64) {
+        loop {
+            let released = self.released.notified();
+            if self.try_reserve(bytes) {
+                return;
+            }
+            released.await;
+        }
+    }
+
+    pub fn release(&self, bytes: u64) {
+        let mut in_flight_bytes = self.in_flight_bytes.lock().unwrap();
+        *in_flight_bytes = in_flight_bytes.saturating_sub(bytes);
+        drop(in_flight_bytes);
+        self.released.notify_waiters();
+    }
+
+    pub fn in_flight_bytes(&self) -> u64 {
+        *self.in_flight_bytes.lock().unwrap()
+    }
+}
+
+/// A batch handed from the fetch stage to the batch stage, with the bytes it reserved from the memory budget
+enum FetchedBatch {
+    InMemory(Vec<Transaction>, u64),
+    Spilled(PathBuf, u64),
+}
+
+/// Starts the stages of `tailer`'s pipeline, which run until the returned receiver is dropped. Every processed
+/// batch is sent to the receiver, in the order batches complete. The fetcher must have been started.
+pub fn start_pipeline(tailer: Tailer, config: PipelineConfig) -> mpsc::Receiver<ProcessedBatch> {
+    let (fetched_sender, fetched_receiver) = mpsc::channel(config.fetch_channel_size.max(1));
+    let (batch_sender, batch_receiver) = mpsc::channel(config.process_channel_size.max(1));
+    let (result_sender, result_receiver) = mpsc::channel(config.result_channel_size.max(1));
+    info!(
+        processor_name = tailer.processor_name(),
+        config = format!("{:?}", config),
+        "Starting the pipeline"
+    );
+
+    tokio::spawn(run_fetch_stage(
+        tailer.clone(),
+        config.memory_budget.clone(),
+        config.spill_dir.clone(),
+        fetched_sender,
+    ));
+    tokio::spawn(run_batch_stage(
+        tailer.clone(),
+        config.batch_size,
+        config.memory_budget.clone(),
+        fetched_receiver,
+        batch_sender,
+    ));
+    tokio::spawn(run_process_stage(
+        tailer,
+        config.process_concurrency,
+        config.memory_budget,
+        batch_receiver,
+        result_sender,
+    ));
+    result_receiver
+}
+
+fn observe_stage(processor_name: &str, stage: &str, start: Instant) {
+    PIPELINE_STAGE_BATCHES
+        .with_label_values(&[processor_name, stage])
+        .inc();
+    PIPELINE_STAGE_SECONDS
+        .with_label_values(&[processor_name, stage])
+        .observe(start.elapsed().as_secs_f64());
+    #[cfg(feature = "profiling")]
+    crate::profiling::observe_stage_allocation(processor_name, stage);
+}
+
+fn observe_memory_budget(processor_name: &str, memory_budget: &MemoryBudget) {
+    PIPELINE_IN_FLIGHT_BYTES
+        .with_label_values(&[processor_name])
+        .set(memory_budget.in_flight_bytes() as i64);
+}
+
+fn spill_path(spill_dir: &std::path::Path, processor_name: &str, batch: &[Transaction]) -> PathBuf {
+    let first_version = batch.first().and_then(|txn| txn.version()).unwrap_or(0);
+    let last_version = batch.last().and_then(|txn| txn.version()).unwrap_or(0);
+    spill_dir.join(format!(
+        "{}-{}-{}.json",
+        processor_name, first_version, last_version
+    ))
+}
+
+async fn run_fetch_stage(
+    tailer: Tailer,
+    memory_budget: Option<Arc<MemoryBudget>>,
+    spill_dir: Option<PathBuf>,
+    sender: mpsc::Sender<FetchedBatch>,
+) {
+    let processor_name = tailer.processor_name();
+    loop {
+        let start = Instant::now();
+        let transactions = tailer
+            .transaction_fetcher
+            .lock()
+            .await
+            .fetch_next_batch()
+            .await;
+        if transactions.is_empty() {
+            continue;
+        }
+        let fetched = match &memory_budget {
+            None => FetchedBatch::InMemory(transactions, 0),
+            Some(memory_budget) => {
+                let serialized = serde_json::to_vec(&transactions)
+                    .expect("Fetched transactions should serialize");
+                let num_bytes = serialized.len() as u64;
+                // When spilling, the batch's bytes are reserved here if they fit, and otherwise once it's loaded back
+                match &spill_dir {
+                    Some(spill_dir) if !memory_budget.try_reserve(num_bytes) => {
+                        let path = spill_path(spill_dir, processor_name, &transactions);
+                        tokio::fs::write(&path, &serialized)
+                            .await
+                            .unwrap_or_else(|e| {
+                                panic!("Could not spill batch to {:?}: {}", path, e)
+                            });
+                        PIPELINE_SPILLED_BATCHES
+                            .with_label_values(&[processor_name])
+                            .inc();
+                        FetchedBatch::Spilled(path, num_bytes)
+                    }
+                    Some(_) => FetchedBatch::InMemory(transactions, num_bytes),
+                    None => {
+                        memory_budget.reserve(num_bytes).await;
+                        FetchedBatch::InMemory(transactions, num_bytes)
+                    }
+                }
+            }
+        };
+        if let Some(memory_budget) = &memory_budget {
+            observe_memory_budget(processor_name, memory_budget);
+        }
+        if sender.send(fetched).await.is_err() {
+            return;
+        }
+        observe_stage(processor_name, FETCH_STAGE, start);
+    }
+}
+
+/// Loads a spilled batch back, once its bytes fit in the memory budget
+async fn unspill(path: PathBuf, num_bytes: u64, memory_budget: &MemoryBudget) -> Vec<Transaction> {
+    memory_budget.reserve(num_bytes).await;
+    let serialized = tokio::fs::read(&path)
+        .await
+        .unwrap_or_else(|e| panic!("Could not read spilled batch {:?}: {}", path, e));
+    if let Err(e) = tokio::fs::remove_file(&path).await {
+        warn!(
+            path = format!("{:?}", path),
+            "Could not remove spilled batch: {}", e
+        );
+    }
+    serde_json::from_slice(&serialized).expect("Spilled batches should deserialize")
+}
+
+async fn run_batch_stage(
+    tailer: Tailer,
+    batch_size: u8,
+    memory_budget: Option<Arc<MemoryBudget>>,
+    mut receiver: mpsc::Receiver<FetchedBatch>,
+    sender: mpsc::Sender<(Vec<Transaction>, u64)>,
+) {
+    let processor_name = tailer.processor_name();
+    let batch_size = (batch_size as usize).max(1);
+    while let Some(fetched) = receiver.recv().await {
+        let start = Instant::now();
+        let (transactions, num_bytes) = match fetched {
+            FetchedBatch::InMemory(transactions, num_bytes) => (transactions, num_bytes),
+            FetchedBatch::Spilled(path, num_bytes) => {
+                let memory_budget = memory_budget
+                    .as_ref()
+                    .expect("Batches are only spilled with a memory budget");
+                (unspill(path, num_bytes, memory_budget).await, num_bytes)
+            }
+        };
+        // Each processing batch releases its share of the fetched batch's bytes once it's processed
+        let num_batches = ((transactions.len() + batch_size - 1) / batch_size) as u64;
+        for (ind, batch) in transactions.chunks(batch_size).enumerate() {
+            let mut batch_bytes = num_bytes / num_batches;
+            if ind == 0 {
+                batch_bytes += num_bytes % num_batches;
+            }
+            if sender.send((batch.to_vec(), batch_bytes)).await.is_err() {
+                return;
+            }
+        }
+        observe_stage(processor_name, BATCH_STAGE, start);
+    }
+}
+
+async fn run_process_stage(
+    tailer: Tailer,
+    process_concurrency: usize,
+    memory_budget: Option<Arc<MemoryBudget>>,
+    mut receiver: mpsc::Receiver<(Vec<Transaction>, u64)>,
+    sender: mpsc::Sender<ProcessedBatch>,
+) {
+    let semaphore = Arc::new(Semaphore::new(process_concurrency.max(1)));
+    while let Some((transactions, num_bytes)) = receiver.recv().await {
+        let permit = semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("Process stage semaphore is never closed");
+        let tailer = tailer.clone();
+        let memory_budget = memory_budget.clone();
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            let start = Instant::now();
+            let result = tailer.process_batch(transactions).await;
+            let processing_time = start.elapsed();
+            observe_stage(tailer.processor_name(), PROCESS_STAGE, start);
+            if let Some(memory_budget) = memory_budget {
+                memory_budget.release(num_bytes);
+                observe_memory_budget(tailer.processor_name(), &memory_budget);
+            }
+            drop(permit);
+            // The consumer is gone once the pipeline is stopped
+            let _ = sender
+                .send(ProcessedBatch {
+                    result,
+                    processing_time,
+                })
+                .await;
+        });
+        if sender.is_closed() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_budget() {
+        let memory_budget = Arc::new(MemoryBudget::new(100));
+        assert!(memory_budget.try_reserve(60));
+        assert!(!memory_budget.try_reserve(60));
+        assert!(memory_budget.try_reserve(40));
+        assert_eq!(memory_budget.in_flight_bytes(), 100);
+
+        let waiter = {
+            let memory_budget = memory_budget.clone();
+            tokio::spawn(async move { memory_budget.reserve(50).await })
+        };
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+        memory_budget.release(60);
+        waiter.await.unwrap();
+        assert_eq!(memory_budget.in_flight_bytes(), 90);
+
+        // A batch bigger than the budget still goes through once nothing else is in flight
+        memory_budget.release(90);
+        assert!(memory_budget.try_reserve(500));
+    }
+}
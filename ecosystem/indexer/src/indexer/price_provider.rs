@@ -0,0 +1,178 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Prices used to attach USD values to activities at indexing time. Prices are the latest known when a batch is
+//! processed, so they're only meaningful while tailing the chain, not when backfilling old versions.
+
+use anyhow::{Context, Result};
+use aptos_rest_client::Client as RestClient;
+use aptos_types::account_address::AccountAddress;
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use std::{collections::HashMap, fmt::Debug, str::FromStr};
+use url::Url;
+
+#[async_trait]
+pub trait PriceProvider: Send + Sync + Debug {
+    /// name of the provider, for logging
+    fn name(&self) -> &'static str;
+
+    /// USD price of one whole unit (ex: 1 APT, not 1 octa) of each of `asset_types`. Assets the provider doesn't
+    /// know are left out.
+    async fn get_usd_prices(&self, asset_types: &[String]) -> Result<HashMap<String, BigDecimal>>;
+}
+
+/// USD value of `amount` base units (ex: octas) of an asset with `decimals` decimals
+pub fn to_usd_value(amount: &BigDecimal, decimals: i64, usd_price: &BigDecimal) -> BigDecimal {
+    let unit =
+        BigDecimal::from_str(&format!("1e-{}", decimals)).expect("Should be a valid decimal");
+    (amount * unit * usd_price).with_scale(6)
+}
+
+/// Where an on-chain oracle publishes the price of an asset: a field of a resource, scaled by `10^-exponent`
+#[derive(Clone, Debug)]
+pub struct OracleFeed {
+    pub account_address: AccountAddress,
+    pub resource_type: String,
+    pub price_field: String,
+    pub exponent: u32,
+}
+
+/// Reads prices from resources published on-chain by an oracle, through a node's REST API
+#[derive(Debug)]
+pub struct ChainOraclePriceProvider {
+    client: RestClient,
+    feeds: HashMap<String, OracleFeed>,
+}
+
+impl ChainOraclePriceProvider {
+    /// `feeds` maps asset types to their price feed
+    pub fn new(node_url: Url, feeds: HashMap<String, OracleFeed>) -> Self {
+        Self {
+            client: RestClient::new(node_url),
+            feeds,
+        }
+    }
+}
+
+#[async_trait]
+impl PriceProvider for ChainOraclePriceProvider {
+    fn name(&self) -> &'static str {
+        "chain_oracle"
+    }
+
+    async fn get_usd_prices(&self, asset_types: &[String]) -> Result<HashMap<String, BigDecimal>> {
+        let mut prices = HashMap::new();
+        for asset_type in asset_types {
+            let feed = match self.feeds.get(asset_type) {
+                Some(feed) => feed,
+                None => continue,
+            };
+            let resource = self
+                .client
+                .get_account_resource(feed.account_address, &feed.resource_type)
+                .await
+                .with_context(|| format!("Failed to read price feed of {}", asset_type))?
+                .into_inner();
+            let price =
+                resource
+                    .as_ref()
+                    .and_then(|resource| match &resource.data[&feed.price_field] {
+                        serde_json::Value::String(price) => BigDecimal::from_str(price).ok(),
+                        serde_json::Value::Number(price) => {
+                            BigDecimal::from_str(&price.to_string()).ok()
+                        }
+                        _ => None,
+                    });
+            if let Some(price) = price {
+                prices.insert(
+                    asset_type.clone(),
+                    to_usd_value(&price, feed.exponent as i64, &BigDecimal::from(1)),
+                );
+            }
+        }
+        Ok(prices)
+    }
+}
+
+/// Reads prices from CoinGecko's `simple/price` endpoint
+#[derive(Debug)]
+pub struct CoinGeckoPriceProvider {
+    client: reqwest::Client,
+    api_url: Url,
+    /// Maps asset types to CoinGecko coin ids, ex: "aptos"
+    coin_ids: HashMap<String, String>,
+}
+
+impl CoinGeckoPriceProvider {
+    pub const DEFAULT_API_URL: &'static str = "https://api.coingecko.com/api/v3/";
+
+    pub fn new(api_url: Url, coin_ids: HashMap<String, String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_url,
+            coin_ids,
+        }
+    }
+}
+
+#[async_trait]
+impl PriceProvider for CoinGeckoPriceProvider {
+    fn name(&self) -> &'static str {
+        "coingecko"
+    }
+
+    async fn get_usd_prices(&self, asset_types: &[String]) -> Result<HashMap<String, BigDecimal>> {
+        let coin_ids: Vec<(&String, &String)> = asset_types
+            .iter()
+            .filter_map(|asset_type| {
+                self.coin_ids
+                    .get(asset_type)
+                    .map(|coin_id| (asset_type, coin_id))
+            })
+            .collect();
+        if coin_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let ids = coin_ids
+            .iter()
+            .map(|(_, coin_id)| coin_id.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+        let response: serde_json::Value = self
+            .client
+            .get(self.api_url.join("simple/price")?)
+            .query(&[("ids", ids.as_str()), ("vs_currencies", "usd")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Failed to parse CoinGecko prices")?;
+        Ok(coin_ids
+            .into_iter()
+            .filter_map(|(asset_type, coin_id)| {
+                let price = response[coin_id.as_str()]["usd"].as_f64()?;
+                Some((
+                    asset_type.clone(),
+                    BigDecimal::from_str(&price.to_string()).ok()?,
+                ))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_usd_value() {
+        let value = to_usd_value(
+            &BigDecimal::from(150_000_000),
+            8,
+            &BigDecimal::from_str("7.5").unwrap(),
+        );
+        assert_eq!(value, BigDecimal::from_str("11.25").unwrap());
+    }
+}
@@ -3,17 +3,26 @@
 
 #[derive(Debug)]
 pub struct ProcessingResult {
-    pub name: &'static str,
+    pub name: String,
     pub start_version: u64,
     pub end_version: u64,
+    /// Whether the processor already recorded success for these versions, within its data write transaction
+    pub status_committed: bool,
 }
 
 impl ProcessingResult {
-    pub fn new(name: &'static str, start_version: u64, end_version: u64) -> Self {
+    pub fn new(name: &str, start_version: u64, end_version: u64) -> Self {
         Self {
-            name,
+            name: name.to_string(),
             start_version,
             end_version,
+            status_committed: false,
         }
     }
+
+    /// Marks that success was already recorded through a `PgTransactionMetadataHandle`
+    pub fn with_status_committed(mut self) -> Self {
+        self.status_committed = true;
+        self
+    }
 }
@@ -0,0 +1,376 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Where processors record which versions they've processed (`processor_statuses`), and which chain is being
+//! indexed (`ledger_infos`, with snapshots of the node's ledger info in `ledger_info_history`).
+
+use crate::{
+    database::{
+        execute_with_better_error, insert_chunked, with_advisory_lock, PgDbPool, PgPoolConnection,
+        CHAIN_ID_LOCK_KEY, TABLE_LOCK_ORDER,
+    },
+    indexer::{
+        errors::MetadataCorruptedError,
+        metadata_handle::{MetadataHandle, VersionStatus},
+        transaction_processor::get_conn_with_retry,
+    },
+    models::{
+        ledger_info::{LedgerInfo, LedgerInfoHistory},
+        processor_statuses::ProcessorStatusModel,
+    },
+    schema::{
+        ledger_info_history, ledger_infos,
+        processor_statuses::{self, dsl},
+    },
+    util::{bigdecimal_to_u64, u64_to_bigdecimal},
+};
+use anyhow::{ensure, Context, Result};
+use bigdecimal::BigDecimal;
+use diesel::{
+    connection::SimpleConnection,
+    pg::upsert::excluded,
+    prelude::*,
+    sql_query,
+    sql_types::{BigInt, Numeric, Text},
+    QueryResult,
+};
+
+/// Upserts processor statuses through `conn`. If `conn` has a transaction open, the statuses commit (or roll back)
+/// along with it.
+pub fn upsert_processor_statuses(
+    conn: &PgPoolConnection,
+    psms: &[ProcessorStatusModel],
+) -> QueryResult<()> {
+    insert_chunked(conn, psms, |chunk| {
+        diesel::insert_into(processor_statuses::table)
+            .values(chunk)
+            .on_conflict((dsl::name, dsl::version))
+            .do_update()
+            .set((
+                dsl::success.eq(excluded(dsl::success)),
+                dsl::details.eq(excluded(dsl::details)),
+                dsl::last_updated.eq(excluded(dsl::last_updated)),
+            ))
+    })?;
+    Ok(())
+}
+
+/// If the database holds data of another chain than `chain_id`, deletes everything the processors wrote (every table
+/// of `TABLE_LOCK_ORDER`, including `ledger_infos` and `processor_statuses`) so indexing starts over. Returns whether
+/// it did. Other indexers writing to the database must be stopped first.
+pub fn reset_if_chain_changed(conn: &PgPoolConnection, chain_id: u64) -> Result<bool> {
+    with_advisory_lock(conn, CHAIN_ID_LOCK_KEY, || {
+        let recorded_chain_ids = ledger_infos::table
+            .select(ledger_infos::chain_id)
+            .load::<i64>(conn)
+            .context("Error loading chain id from db")?;
+        if recorded_chain_ids
+            .iter()
+            .all(|recorded| *recorded == chain_id as i64)
+        {
+            return Ok(false);
+        }
+        aptos_logger::warn!(
+            chain_id = chain_id,
+            recorded_chain_ids = format!("{:?}", recorded_chain_ids),
+            "Deleting the data of another chain"
+        );
+        conn.batch_execute(&format!("TRUNCATE {}", TABLE_LOCK_ORDER.join(", ")))
+            .context("Error deleting the data of another chain")?;
+        Ok(true)
+    })
+}
+
+/// How many versions before the last successful one the start version query looks back for gaps. Increasing it
+/// may result in slower startup.
+pub const START_VERSION_LOOKBACK: i64 = 1_500_000;
+
+/// Where `processor_name` should resume processing from: the first version that's either not successful or missing.
+/// Takes the connection, so it can be read within a transaction (ex: the snapshot of `snapshot create`).
+pub fn query_start_version(conn: &PgPoolConnection, processor_name: &str) -> Result<Option<u64>> {
+    // This query gets the first version that isn't equal to the next version (versions would be sorted of course).
+    // There's also special handling if the gap happens in the beginning.
+    let sql = "
+      WITH raw_boundaries AS
+      (
+          SELECT
+              MAX(version) AS MAX_BLOCK,
+              MIN(version) AS MIN_BLOCK
+          FROM
+              processor_statuses
+          WHERE
+              name = $1
+              AND success = TRUE
+      ),
+      boundaries AS
+      (
+          SELECT
+              MAX(version) AS MAX_BLOCK,
+              MIN(version) AS MIN_BLOCK
+          FROM
+              processor_statuses, raw_boundaries
+          WHERE
+              name = $1
+              AND success = true
+              and version >= GREATEST(MAX_BLOCK - $2, 0)
+
+      ),
+      gap AS
+      (
+          SELECT
+              MIN(version) + 1 AS maybe_gap
+          FROM
+              (
+                  SELECT
+                      version,
+                      LEAD(version) OVER (
+                  ORDER BY
+                      version ASC) AS next_version
+                  FROM
+                      processor_statuses,
+                      boundaries
+                  WHERE
+                      name = $1
+                      AND success = TRUE
+                      AND version >= GREATEST(MAX_BLOCK - $2, 0)
+              ) a
+          WHERE
+              version + 1 <> next_version
+      )
+      SELECT
+          CASE
+              WHEN
+                  MIN_BLOCK <> GREATEST(MAX_BLOCK - $2, 0)
+              THEN
+                  GREATEST(MAX_BLOCK - $2, 0)
+              ELSE
+                  COALESCE(maybe_gap, MAX_BLOCK + 1)
+          END
+          AS version
+      FROM
+          gap, boundaries
+      ";
+    #[derive(Debug, QueryableByName)]
+    pub struct Gap {
+        #[sql_type = "Numeric"]
+        pub version: BigDecimal,
+    }
+    let mut res: Vec<Option<Gap>> = sql_query(sql)
+        .bind::<Text, _>(processor_name)
+        .bind::<BigInt, _>(START_VERSION_LOOKBACK)
+        .get_results(conn)
+        .context("Error loading the start version query")?;
+    res.pop()
+        .flatten()
+        .map(|g| bigdecimal_to_u64(&g.version))
+        .transpose()
+        .map_err(|err| MetadataCorruptedError::new(processor_name, "start version", err).into())
+}
+
+/// A processor's metadata, accessed through a connection with its data write transaction open.
+/// Recording success through this handle commits the statuses atomically with the data, so a crash can't leave
+/// versions marked successful without their data (or the reverse). Processors which do so must return a
+/// `ProcessingResult` with `status_committed` set, so the statuses aren't written a second time.
+pub struct PgTransactionMetadataHandle<'a> {
+    conn: &'a PgPoolConnection,
+    processor_name: &'a str,
+}
+
+impl<'a> PgTransactionMetadataHandle<'a> {
+    pub fn new(conn: &'a PgPoolConnection, processor_name: &'a str) -> Self {
+        Self {
+            conn,
+            processor_name,
+        }
+    }
+
+    /// Marks the versions successful for the processor. Only takes effect once the open transaction commits.
+    pub fn mark_versions_success(&self, start_version: u64, end_version: u64) -> QueryResult<()> {
+        let psms = ProcessorStatusModel::from_versions(
+            self.processor_name,
+            start_version,
+            end_version,
+            true,
+            None,
+        );
+        upsert_processor_statuses(self.conn, &psms)
+    }
+}
+
+/// Metadata stored in Postgres, read and written outside of data write transactions
+#[derive(Clone)]
+pub struct PgMetadataHandle {
+    pool: PgDbPool,
+}
+
+impl std::fmt::Debug for PgMetadataHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = &self.pool.state();
+        write!(
+            f,
+            "PgMetadataHandle {{ connections: {:?}  idle_connections: {:?} }}",
+            state.connections, state.idle_connections
+        )
+    }
+}
+
+impl PgMetadataHandle {
+    pub fn new(pool: PgDbPool) -> Self {
+        Self { pool }
+    }
+
+    fn get_conn(&self) -> PgPoolConnection {
+        get_conn_with_retry(&self.pool)
+    }
+}
+
+impl MetadataHandle for PgMetadataHandle {
+    fn set_statuses(&self, processor_name: &str, statuses: &[VersionStatus]) -> Result<()> {
+        let psms: Vec<ProcessorStatusModel> = statuses
+            .iter()
+            .map(|status| {
+                ProcessorStatusModel::new(
+                    processor_name,
+                    status.version,
+                    status.success,
+                    status.details.clone(),
+                )
+            })
+            .collect();
+        upsert_processor_statuses(&self.get_conn(), &psms)
+            .context("Error updating Processor Status!")
+    }
+
+    fn get_error_versions(&self, processor_name: &str) -> Result<Vec<u64>> {
+        dsl::processor_statuses
+            .select(dsl::version)
+            .filter(dsl::success.eq(false).and(dsl::name.eq(processor_name)))
+            .load::<BigDecimal>(&self.get_conn())
+            .context("Error loading the error versions only query")?
+            .iter()
+            .map(bigdecimal_to_u64)
+            .collect::<Result<_, _>>()
+            .map_err(|err| MetadataCorruptedError::new(processor_name, "error version", err).into())
+    }
+
+    fn get_max_version(&self, processor_name: &str) -> Result<Option<u64>> {
+        dsl::processor_statuses
+            .select(diesel::dsl::max(dsl::version))
+            .filter(dsl::name.eq(processor_name))
+            .first::<Option<BigDecimal>>(&self.get_conn())
+            .context("Error loading the max version query")?
+            .map(|v| bigdecimal_to_u64(&v))
+            .transpose()
+            .map_err(|err| MetadataCorruptedError::new(processor_name, "max version", err).into())
+    }
+
+    fn get_successful_version_bounds(&self, processor_name: &str) -> Result<Option<(u64, u64)>> {
+        let res = dsl::processor_statuses
+            .select((
+                diesel::dsl::min(dsl::version),
+                diesel::dsl::max(dsl::version),
+            ))
+            .filter(dsl::success.eq(true).and(dsl::name.eq(processor_name)))
+            .first::<(Option<BigDecimal>, Option<BigDecimal>)>(&self.get_conn())
+            .context("Error loading the successful version bounds query")?;
+        Ok(match res {
+            (Some(min), Some(max)) => Some((
+                bigdecimal_to_u64(&min).map_err(|err| {
+                    MetadataCorruptedError::new(processor_name, "min successful version", err)
+                })?,
+                bigdecimal_to_u64(&max).map_err(|err| {
+                    MetadataCorruptedError::new(processor_name, "max successful version", err)
+                })?,
+            )),
+            _ => None,
+        })
+    }
+
+    fn get_successful_versions(
+        &self,
+        processor_name: &str,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<Vec<u64>> {
+        dsl::processor_statuses
+            .select(dsl::version)
+            .filter(dsl::success.eq(true).and(dsl::name.eq(processor_name)).and(
+                dsl::version.between(
+                    u64_to_bigdecimal(start_version),
+                    u64_to_bigdecimal(end_version),
+                ),
+            ))
+            .order(dsl::version.asc())
+            .load::<BigDecimal>(&self.get_conn())
+            .context("Error loading the successful versions query")?
+            .iter()
+            .map(bigdecimal_to_u64)
+            .collect::<Result<_, _>>()
+            .map_err(|err| {
+                MetadataCorruptedError::new(processor_name, "successful version", err).into()
+            })
+    }
+
+    fn get_start_version(&self, processor_name: &str) -> Result<Option<u64>> {
+        query_start_version(&self.get_conn(), processor_name)
+    }
+
+    fn get_chain_id(&self) -> Result<Option<u64>> {
+        Ok(ledger_infos::table
+            .select(ledger_infos::chain_id)
+            .first::<i64>(&self.get_conn())
+            .optional()
+            .context("Error loading chain id from db")?
+            .map(|chain_id| chain_id as u64))
+    }
+
+    /// `ledger_infos` holds a single row: recording another chain than the one already recorded fails
+    fn set_chain_id(&self, chain_id: u64) -> Result<()> {
+        let conn = self.get_conn();
+        let recorded_chain_ids = conn
+            .transaction::<_, diesel::result::Error, _>(|| {
+                execute_with_better_error(
+                    &conn,
+                    diesel::insert_into(ledger_infos::table)
+                        .values(LedgerInfo {
+                            chain_id: chain_id as i64,
+                        })
+                        .on_conflict_do_nothing(),
+                )?;
+                ledger_infos::table
+                    .select(ledger_infos::chain_id)
+                    .load::<i64>(&conn)
+            })
+            .context("Error updating chain_id!")?;
+        ensure!(
+            recorded_chain_ids == [chain_id as i64],
+            "Refusing to index chain {}: the database holds data of chain {:?}. Use --force-reset-chain to delete it \
+             and start over.",
+            chain_id,
+            recorded_chain_ids
+        );
+        Ok(())
+    }
+
+    /// Holds `CHAIN_ID_LOCK_KEY` between reading and recording the chain id
+    fn check_or_set_chain_id(&self, chain_id: u64) -> Result<Option<u64>> {
+        with_advisory_lock(&self.get_conn(), CHAIN_ID_LOCK_KEY, || {
+            let existing_chain_id = self.get_chain_id()?;
+            if existing_chain_id.is_none() {
+                self.set_chain_id(chain_id)?;
+            }
+            Ok(existing_chain_id)
+        })
+    }
+
+    fn record_ledger_info(&self, ledger_info: &LedgerInfoHistory) -> Result<()> {
+        execute_with_better_error(
+            &self.get_conn(),
+            diesel::insert_into(ledger_info_history::table)
+                .values(ledger_info)
+                .on_conflict_do_nothing(),
+        )
+        .context("Error recording ledger info!")?;
+        Ok(())
+    }
+}
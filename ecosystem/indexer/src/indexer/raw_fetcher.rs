@@ -0,0 +1,231 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Reads transactions back from `raw_transactions`, the archive the default processor writes with `--archive-raw`,
+//! rather than from a node: adding a processor then backfills it from Postgres itself, at local-disk speeds, including
+//! versions the nodes have since pruned. The archive's ledger has no epochs or blocks of its own, so its `State` only
+//! has the chain id (from `ledger_infos`), the archived version range and the last archived transaction's timestamp.
+
+use crate::{
+    counters::{FETCHED_TRANSACTION, UNABLE_TO_FETCH_TRANSACTION},
+    database::PgDbPool,
+    indexer::{fetcher::TransactionFetcherTrait, stream::TransactionStream},
+    models::raw_transactions::RawTransactionModel,
+    schema::{ledger_infos, raw_transactions},
+    util::{bigdecimal_to_u64, u64_to_bigdecimal},
+};
+use anyhow::{ensure, Context, Result};
+use aptos_logger::prelude::*;
+use aptos_rest_client::{State, Transaction};
+use diesel::{
+    dsl::{max, min},
+    prelude::*,
+};
+use std::time::Duration;
+
+/// How long to wait before looking for new versions, once caught up with the archive
+const POLL_INTERVAL: Duration = Duration::from_millis(300);
+/// Versions read at a time, as many as the REST fetcher requests at once
+const BATCH_SIZE: u16 = 500;
+
+/// Fetches batches of transactions from `raw_transactions`, reading them as they're asked for
+#[derive(Debug)]
+pub struct RawTableFetcher {
+    pool: PgDbPool,
+    current_version: u64,
+}
+
+impl RawTableFetcher {
+    pub fn new(pool: PgDbPool, starting_version: Option<u64>) -> Self {
+        Self {
+            pool,
+            current_version: starting_version.unwrap_or(0),
+        }
+    }
+
+    /// Reads off the async runtime, since diesel blocks
+    async fn read<T, F>(&self, read: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&PgDbPool) -> Result<T> + Send + 'static,
+    {
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || read(&pool))
+            .await
+            .expect("Reading raw_transactions panicked")
+    }
+
+    /// Up to `limit` consecutive archived transactions from `start_version`, stopping at the first version missing
+    fn get_transactions(
+        pool: &PgDbPool,
+        start_version: u64,
+        limit: u16,
+    ) -> Result<Vec<Transaction>> {
+        let rows = raw_transactions::table
+            .filter(raw_transactions::version.ge(u64_to_bigdecimal(start_version)))
+            .filter(raw_transactions::version.lt(u64_to_bigdecimal(start_version + limit as u64)))
+            .order(raw_transactions::version.asc())
+            .load::<RawTransactionModel>(&pool.get()?)
+            .context("Error loading raw transactions")?;
+        let mut transactions = vec![];
+        for (expected_version, row) in (start_version..).zip(rows) {
+            if bigdecimal_to_u64(&row.version)? != expected_version {
+                break;
+            }
+            transactions.push(row.decode()?);
+        }
+        Ok(transactions)
+    }
+
+    /// The archived version range, if any version is archived
+    fn get_version_range(pool: &PgDbPool) -> Result<Option<(u64, u64)>> {
+        let (oldest, latest) = raw_transactions::table
+            .select((
+                min(raw_transactions::version),
+                max(raw_transactions::version),
+            ))
+            .first::<(
+                Option<bigdecimal::BigDecimal>,
+                Option<bigdecimal::BigDecimal>,
+            )>(&pool.get()?)
+            .context("Error loading the archived version range")?;
+        Ok(match (oldest, latest) {
+            (Some(oldest), Some(latest)) => {
+                Some((bigdecimal_to_u64(&oldest)?, bigdecimal_to_u64(&latest)?))
+            }
+            _ => None,
+        })
+    }
+
+    fn get_state(pool: &PgDbPool) -> Result<State> {
+        let chain_id = ledger_infos::table
+            .select(ledger_infos::chain_id)
+            .first::<i64>(&pool.get()?)
+            .optional()
+            .context("Error loading chain id from db")?
+            .context("No chain id is recorded in ledger_infos")?;
+        let (oldest_version, version) =
+            Self::get_version_range(pool)?.context("No transactions are archived")?;
+        let timestamp_usecs = Self::get_transactions(pool, version, 1)?
+            .first()
+            .map_or(0, |txn| txn.timestamp());
+        Ok(State {
+            chain_id: chain_id as u8,
+            epoch: 0,
+            version,
+            timestamp_usecs,
+            oldest_ledger_version: oldest_version,
+            oldest_block_height: 0,
+            block_height: 0,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl TransactionFetcherTrait for RawTableFetcher {
+    /// Reads the next batch, waiting for the version to be archived if it isn't yet, ex: while the default processor
+    /// is still catching up
+    async fn fetch_next_batch(&mut self) -> Vec<Transaction> {
+        loop {
+            let starting_version = self.current_version;
+            let res = self
+                .read(move |pool| Self::get_transactions(pool, starting_version, BATCH_SIZE))
+                .await;
+            match res {
+                Ok(txns) if !txns.is_empty() => {
+                    FETCHED_TRANSACTION.inc();
+                    self.current_version = starting_version + txns.len() as u64;
+                    return txns;
+                }
+                Ok(_) => tokio::time::sleep(POLL_INTERVAL).await,
+                Err(err) => {
+                    UNABLE_TO_FETCH_TRANSACTION.inc();
+                    error!(
+                        version = starting_version,
+                        error = format!("{:?}", err),
+                        "Could not read raw transactions, will retry"
+                    );
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    async fn fetch_version(&self, version: u64) -> Transaction {
+        loop {
+            let res = self
+                .read(move |pool| Self::get_transactions(pool, version, 1))
+                .await;
+            match res {
+                Ok(mut txns) if !txns.is_empty() => {
+                    FETCHED_TRANSACTION.inc();
+                    return txns.remove(0);
+                }
+                Ok(_) => tokio::time::sleep(POLL_INTERVAL).await,
+                Err(err) => {
+                    UNABLE_TO_FETCH_TRANSACTION.inc();
+                    error!(
+                        version = version,
+                        error = format!("{:?}", err),
+                        "Could not read raw transaction, will retry"
+                    );
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    async fn fetch_ledger_info(&mut self) -> State {
+        self.read(Self::get_state)
+            .await
+            .unwrap_or_else(|err| panic!("Failed to read the archive's state: {:?}", err))
+    }
+
+    async fn set_version(&mut self, version: u64) {
+        self.current_version = version;
+    }
+
+    /// Nothing to start: batches are read when asked for
+    async fn start(&mut self) {}
+}
+
+#[async_trait::async_trait]
+impl TransactionStream for RawTableFetcher {
+    /// The next archived transactions, none once the archive's latest version was handed out. Fails on a version
+    /// missing from the middle of the archive rather than skipping it.
+    async fn next_batch(&mut self) -> Result<Vec<Transaction>> {
+        let starting_version = self.current_version;
+        let (txns, version_range) = self
+            .read(move |pool| {
+                Ok((
+                    Self::get_transactions(pool, starting_version, BATCH_SIZE)?,
+                    Self::get_version_range(pool)?,
+                ))
+            })
+            .await?;
+        if txns.is_empty() {
+            if let Some((oldest_version, latest_version)) = version_range {
+                ensure!(
+                    starting_version > latest_version,
+                    "Version {} isn't in raw_transactions, which holds versions {} to {}",
+                    starting_version,
+                    oldest_version,
+                    latest_version
+                );
+            }
+        }
+        self.current_version = starting_version + txns.len() as u64;
+        Ok(txns)
+    }
+
+    async fn ledger_chain_id(&mut self) -> Result<u8> {
+        Ok(self.read(Self::get_state).await?.chain_id)
+    }
+
+    async fn ledger_version(&mut self) -> Result<u64> {
+        Ok(self
+            .read(Self::get_version_range)
+            .await?
+            .map_or(0, |(_, latest_version)| latest_version))
+    }
+}
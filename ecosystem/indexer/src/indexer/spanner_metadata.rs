@@ -0,0 +1,562 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Metadata stored in Google Cloud Spanner, for deployments whose data plane is Spanner: sinks write wherever they
+//! want and return a `SpannerMetadataHandle` from `TransactionProcessor::metadata_handle`. Only built with the
+//! `spanner` feature, and selected with `--spanner-database` (see `MetadataBackend::Spanner`).
+//!
+//! The tables mirror Postgres' `processor_statuses` and `ledger_infos` (see `SPANNER_DDL`), and are read and written
+//! through Spanner's REST API. Requests are authenticated with the configured access token, or one from the GCE
+//! metadata server (GKE workload identity, Cloud Run, ...); with `SPANNER_EMULATOR_HOST` set, they go to the emulator
+//! unauthenticated. `MetadataHandle` is synchronous, so requests block the calling thread: the handle needs tokio's
+//! multi-threaded runtime.
+
+use crate::{
+    gcp::AccessTokenProvider,
+    indexer::{
+        errors::MetadataCorruptedError,
+        metadata_handle::{block_on, MetadataHandle, VersionStatus},
+    },
+    util::bigdecimal_to_u64,
+};
+use anyhow::{bail, ensure, Context, Result};
+use bigdecimal::BigDecimal;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::{fmt, future::Future, str::FromStr, sync::Mutex, time::Duration};
+use tokio::runtime::Handle;
+
+/// Statements creating the tables the handle uses, to run once on the database (ex: with
+/// `gcloud spanner databases ddl update`)
+pub const SPANNER_DDL: &[&str] = &[
+    "CREATE TABLE processor_statuses (
+    name STRING(50) NOT NULL,
+    version INT64 NOT NULL,
+    success BOOL NOT NULL,
+    details STRING(MAX),
+    last_updated TIMESTAMP NOT NULL OPTIONS (allow_commit_timestamp = true),
+) PRIMARY KEY (name, version)",
+    "CREATE TABLE ledger_infos (
+    chain_id INT64 NOT NULL,
+) PRIMARY KEY (chain_id)",
+];
+
+pub const DEFAULT_ENDPOINT: &str = "https://spanner.googleapis.com";
+/// Statuses written per commit. Spanner limits commits to 80k mutated cells, and a status is 5.
+const STATUSES_PER_COMMIT: usize = 5_000;
+/// Read-write transactions aborted by concurrent ones (ex: replicas recording the chain id) are retried this often
+const MAX_TRANSACTION_ATTEMPTS: usize = 5;
+
+#[derive(Clone)]
+pub struct SpannerConfig {
+    /// ex: "projects/my-project/instances/my-instance/databases/indexer"
+    pub database: String,
+    pub endpoint: String,
+    /// OAuth access token. If `None`, tokens are requested from the GCE metadata server.
+    pub access_token: Option<String>,
+    /// Whether `endpoint` is the emulator, which doesn't authenticate requests
+    pub emulator: bool,
+    pub request_timeout: Duration,
+}
+
+impl SpannerConfig {
+    /// Targets the emulator if `SPANNER_EMULATOR_HOST` is set, as Google's client libraries do
+    pub fn new(database: String) -> Self {
+        let emulator_host = std::env::var("SPANNER_EMULATOR_HOST").ok();
+        Self {
+            database,
+            endpoint: emulator_host.as_ref().map_or_else(
+                || DEFAULT_ENDPOINT.to_string(),
+                |host| format!("http://{}", host),
+            ),
+            access_token: None,
+            emulator: emulator_host.is_some(),
+            request_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl fmt::Debug for SpannerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SpannerConfig {{ database: {:?}, endpoint: {:?}, emulator: {:?} }}",
+            self.database, self.endpoint, self.emulator
+        )
+    }
+}
+
+/// An error status returned by Spanner
+#[derive(Debug)]
+pub struct SpannerError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl SpannerError {
+    /// Sessions are deleted after an hour idle, and may be at any time
+    fn is_session_gone(&self) -> bool {
+        self.status == StatusCode::NOT_FOUND && self.message.contains("Session not found")
+    }
+
+    /// Read-write transactions are aborted when they conflict with others, and succeed when retried
+    fn is_aborted(&self) -> bool {
+        self.status == StatusCode::CONFLICT
+    }
+}
+
+impl fmt::Display for SpannerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Spanner returned {}: {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for SpannerError {}
+
+fn spanner_error(err: &anyhow::Error) -> Option<&SpannerError> {
+    err.downcast_ref::<SpannerError>()
+}
+
+/// A query parameter
+enum Param<'a> {
+    String(&'a str),
+    Int64(u64),
+}
+
+/// The `params` and `paramTypes` of a query
+fn query_params(params: &[(&str, Param)]) -> (Value, Value) {
+    let mut values = serde_json::Map::new();
+    let mut types = serde_json::Map::new();
+    for (name, param) in params {
+        let (value, code) = match param {
+            Param::String(value) => (json!(value), "STRING"),
+            // INT64s are encoded as strings, as JSON numbers may not hold them
+            Param::Int64(value) => (json!(value.to_string()), "INT64"),
+        };
+        values.insert(name.to_string(), value);
+        types.insert(name.to_string(), json!({ "code": code }));
+    }
+    (Value::Object(values), Value::Object(types))
+}
+
+/// Parses an INT64 cell, `None` if it's NULL (ex: the `MAX` of no rows)
+fn parse_int64(processor_name: &str, field: &'static str, value: &Value) -> Result<Option<u64>> {
+    let value = match value {
+        Value::Null => return Ok(None),
+        Value::String(value) => value,
+        _ => bail!("Expected an INT64 for {}, got {}", field, value),
+    };
+    let value = BigDecimal::from_str(value)
+        .with_context(|| format!("Expected an INT64 for {}, got {}", field, value))?;
+    bigdecimal_to_u64(&value)
+        .map(Some)
+        .map_err(|err| MetadataCorruptedError::new(processor_name, field, err).into())
+}
+
+/// Upserts `statuses`, with Spanner stamping `last_updated` with the commit's timestamp
+fn statuses_mutation(processor_name: &str, statuses: &[VersionStatus]) -> Value {
+    let values: Vec<Value> = statuses
+        .iter()
+        .map(|status| {
+            json!([
+                processor_name,
+                status.version.to_string(),
+                status.success,
+                status.details,
+                "spanner.commit_timestamp()",
+            ])
+        })
+        .collect();
+    json!({
+        "insertOrUpdate": {
+            "table": "processor_statuses",
+            "columns": ["name", "version", "success", "details", "last_updated"],
+            "values": values,
+        }
+    })
+}
+
+pub struct SpannerMetadataHandle {
+    config: SpannerConfig,
+    client: reqwest::Client,
+    runtime: Handle,
+    session: Mutex<Option<String>>,
+    tokens: AccessTokenProvider,
+}
+
+impl fmt::Debug for SpannerMetadataHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SpannerMetadataHandle {{ config: {:?} }}", self.config)
+    }
+}
+
+impl SpannerMetadataHandle {
+    /// Must be called within a tokio runtime, which the handle's requests then run on
+    pub fn new(config: SpannerConfig) -> Result<Self> {
+        let runtime =
+            Handle::try_current().context("The Spanner metadata handle needs a tokio runtime")?;
+        let client = reqwest::Client::builder()
+            .timeout(config.request_timeout)
+            .build()
+            .context("Failed to build the Spanner HTTP client")?;
+        Ok(Self {
+            tokens: AccessTokenProvider::new(config.access_token.clone()),
+            config,
+            client,
+            runtime,
+            session: Mutex::new(None),
+        })
+    }
+
+    fn block_on<T>(&self, future: impl Future<Output = T>) -> T {
+        block_on(&self.runtime, future)
+    }
+
+    /// POSTs `body` to `path` (under `/v1/`), returning the response's JSON or a `SpannerError`
+    async fn post(&self, path: &str, body: &Value) -> Result<Value> {
+        let mut request = self
+            .client
+            .post(format!("{}/v1/{}", self.config.endpoint, path))
+            .json(body);
+        if !self.config.emulator {
+            request = request.bearer_auth(self.tokens.token(&self.client).await?);
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Spanner request to {} failed", path))?;
+        let status = response.status();
+        let body: Value = response
+            .json()
+            .await
+            .with_context(|| format!("Failed to parse Spanner's response to {}", path))?;
+        if !status.is_success() {
+            return Err(SpannerError {
+                status,
+                message: body["error"]["message"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            }
+            .into());
+        }
+        Ok(body)
+    }
+
+    async fn session(&self) -> Result<String> {
+        if let Some(session) = &*self.session.lock().unwrap() {
+            return Ok(session.clone());
+        }
+        let response = self
+            .post(&format!("{}/sessions", self.config.database), &json!({}))
+            .await
+            .context("Failed to create a Spanner session")?;
+        let session = response["name"]
+            .as_str()
+            .context("Spanner returned no session name")?
+            .to_string();
+        *self.session.lock().unwrap() = Some(session.clone());
+        Ok(session)
+    }
+
+    /// Forgets `session` if `err` is that it's gone, so the next request creates another one
+    fn forget_gone_session(&self, session: &str, err: &anyhow::Error) {
+        if spanner_error(err).map_or(false, SpannerError::is_session_gone) {
+            let mut current = self.session.lock().unwrap();
+            if current.as_deref() == Some(session) {
+                *current = None;
+            }
+        }
+    }
+
+    /// Calls `method` on the session, creating another session once if it's gone
+    async fn call(&self, method: &str, body: &Value) -> Result<Value> {
+        let session = self.session().await?;
+        match self.post(&format!("{}:{}", session, method), body).await {
+            Err(err) if spanner_error(&err).map_or(false, SpannerError::is_session_gone) => {
+                self.forget_gone_session(&session, &err);
+                let session = self.session().await?;
+                self.post(&format!("{}:{}", session, method), body).await
+            }
+            result => result,
+        }
+    }
+
+    /// Runs `sql` in a single-use, strongly consistent read-only transaction
+    async fn query(&self, sql: &str, params: &[(&str, Param<'_>)]) -> Result<Vec<Vec<Value>>> {
+        let (params, param_types) = query_params(params);
+        let response = self
+            .call(
+                "executeSql",
+                &json!({ "sql": sql, "params": params, "paramTypes": param_types }),
+            )
+            .await
+            .with_context(|| format!("Spanner query failed: {}", sql))?;
+        Ok(serde_json::from_value(response["rows"].clone()).unwrap_or_default())
+    }
+
+    /// Values of the query's first column, skipping NULLs
+    fn query_versions(
+        &self,
+        processor_name: &str,
+        field: &'static str,
+        sql: &str,
+        params: &[(&str, Param<'_>)],
+    ) -> Result<Vec<u64>> {
+        let rows = self.block_on(self.query(sql, params))?;
+        let mut versions = vec![];
+        for row in &rows {
+            if let Some(version) = parse_int64(processor_name, field, &row[0])? {
+                versions.push(version);
+            }
+        }
+        Ok(versions)
+    }
+
+    /// Reads the recorded chain ids and records `chain_id` if there's none, in a read-write transaction
+    async fn try_check_or_set_chain_id(&self, chain_id: u64) -> Result<Vec<u64>> {
+        let session = self.session().await?;
+        let result = async {
+            let transaction = self
+                .post(
+                    &format!("{}:beginTransaction", session),
+                    &json!({ "options": { "readWrite": {} } }),
+                )
+                .await?;
+            let transaction_id = transaction["id"]
+                .as_str()
+                .context("Spanner returned no transaction id")?;
+            let rows: Vec<Vec<Value>> = serde_json::from_value(
+                self.post(
+                    &format!("{}:executeSql", session),
+                    &json!({
+                        "sql": "SELECT chain_id FROM ledger_infos",
+                        "transaction": { "id": transaction_id },
+                    }),
+                )
+                .await?["rows"]
+                    .clone(),
+            )
+            .unwrap_or_default();
+            let mut chain_ids = vec![];
+            for row in &rows {
+                chain_ids.extend(parse_int64("ledger_infos", "chain id", &row[0])?);
+            }
+            let mutations = if chain_ids.is_empty() {
+                vec![json!({
+                    "insert": {
+                        "table": "ledger_infos",
+                        "columns": ["chain_id"],
+                        "values": [[chain_id.to_string()]],
+                    }
+                })]
+            } else {
+                vec![]
+            };
+            self.post(
+                &format!("{}:commit", session),
+                &json!({ "transactionId": transaction_id, "mutations": mutations }),
+            )
+            .await?;
+            Ok::<_, anyhow::Error>(chain_ids)
+        }
+        .await;
+        if let Err(err) = &result {
+            self.forget_gone_session(&session, err);
+        }
+        result
+    }
+
+    async fn check_or_set_chain_ids(&self, chain_id: u64) -> Result<Vec<u64>> {
+        let mut attempt = 1;
+        loop {
+            match self.try_check_or_set_chain_id(chain_id).await {
+                Err(err)
+                    if attempt < MAX_TRANSACTION_ATTEMPTS
+                        && spanner_error(&err)
+                            .map_or(false, |err| err.is_aborted() || err.is_session_gone()) =>
+                {
+                    attempt += 1;
+                    tokio::time::sleep(Duration::from_millis(100 * attempt as u64)).await;
+                }
+                result => return result.context("Error updating chain_id!"),
+            }
+        }
+    }
+}
+
+impl MetadataHandle for SpannerMetadataHandle {
+    fn set_statuses(&self, processor_name: &str, statuses: &[VersionStatus]) -> Result<()> {
+        for chunk in statuses.chunks(STATUSES_PER_COMMIT) {
+            self.block_on(self.call(
+                "commit",
+                &json!({
+                    "singleUseTransaction": { "readWrite": {} },
+                    "mutations": [statuses_mutation(processor_name, chunk)],
+                }),
+            ))
+            .context("Error updating Processor Status!")?;
+        }
+        Ok(())
+    }
+
+    fn get_error_versions(&self, processor_name: &str) -> Result<Vec<u64>> {
+        self.query_versions(
+            processor_name,
+            "error version",
+            "SELECT version FROM processor_statuses WHERE name = @name AND success = FALSE",
+            &[("name", Param::String(processor_name))],
+        )
+    }
+
+    fn get_max_version(&self, processor_name: &str) -> Result<Option<u64>> {
+        Ok(self
+            .query_versions(
+                processor_name,
+                "max version",
+                "SELECT MAX(version) FROM processor_statuses WHERE name = @name",
+                &[("name", Param::String(processor_name))],
+            )?
+            .pop())
+    }
+
+    fn get_successful_version_bounds(&self, processor_name: &str) -> Result<Option<(u64, u64)>> {
+        let rows = self.block_on(self.query(
+            "SELECT MIN(version), MAX(version) FROM processor_statuses \
+             WHERE name = @name AND success = TRUE",
+            &[("name", Param::String(processor_name))],
+        ))?;
+        let row = match rows.first() {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let min = parse_int64(processor_name, "min successful version", &row[0])?;
+        let max = parse_int64(processor_name, "max successful version", &row[1])?;
+        Ok(min.zip(max))
+    }
+
+    fn get_successful_versions(
+        &self,
+        processor_name: &str,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<Vec<u64>> {
+        self.query_versions(
+            processor_name,
+            "successful version",
+            "SELECT version FROM processor_statuses \
+             WHERE name = @name AND success = TRUE AND version BETWEEN @start_version AND @end_version \
+             ORDER BY version",
+            &[
+                ("name", Param::String(processor_name)),
+                ("start_version", Param::Int64(start_version)),
+                ("end_version", Param::Int64(end_version)),
+            ],
+        )
+    }
+
+    /// The first version, if it's not successful, or else the version right after the first successful range
+    fn get_start_version(&self, processor_name: &str) -> Result<Option<u64>> {
+        let rows = self.block_on(self.query(
+            "SELECT version, success FROM processor_statuses \
+             WHERE name = @name ORDER BY version LIMIT 1",
+            &[("name", Param::String(processor_name))],
+        ))?;
+        let first = match rows.first() {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        if first[1] != Value::Bool(true) {
+            return parse_int64(processor_name, "start version", &first[0]);
+        }
+        Ok(self
+            .query_versions(
+                processor_name,
+                "start version",
+                "SELECT MIN(version) + 1 FROM processor_statuses AS s \
+                 WHERE name = @name AND success = TRUE AND NOT EXISTS ( \
+                     SELECT 1 FROM processor_statuses AS n \
+                     WHERE n.name = @name AND n.version = s.version + 1 AND n.success = TRUE)",
+                &[("name", Param::String(processor_name))],
+            )?
+            .pop())
+    }
+
+    fn get_chain_id(&self) -> Result<Option<u64>> {
+        Ok(self
+            .query_versions(
+                "ledger_infos",
+                "chain id",
+                "SELECT chain_id FROM ledger_infos",
+                &[],
+            )
+            .context("Error loading chain id from Spanner")?
+            .first()
+            .copied())
+    }
+
+    /// `ledger_infos` holds a single row: recording another chain than the one already recorded fails
+    fn set_chain_id(&self, chain_id: u64) -> Result<()> {
+        let recorded_chain_ids = self.block_on(self.check_or_set_chain_ids(chain_id))?;
+        ensure!(
+            recorded_chain_ids.is_empty() || recorded_chain_ids == [chain_id],
+            "Refusing to index chain {}: the Spanner database holds data of chain {:?}",
+            chain_id,
+            recorded_chain_ids
+        );
+        Ok(())
+    }
+
+    /// Reads and records the chain id in a read-write transaction, so concurrent replicas don't both record theirs
+    fn check_or_set_chain_id(&self, chain_id: u64) -> Result<Option<u64>> {
+        Ok(self
+            .block_on(self.check_or_set_chain_ids(chain_id))?
+            .first()
+            .copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_int64() {
+        assert_eq!(
+            parse_int64("p", "max version", &json!("12")).unwrap(),
+            Some(12)
+        );
+        assert_eq!(parse_int64("p", "max version", &Value::Null).unwrap(), None);
+        assert!(parse_int64("p", "max version", &json!(12)).is_err());
+        let err = parse_int64("p", "max version", &json!("-1")).unwrap_err();
+        assert!(err.downcast_ref::<MetadataCorruptedError>().is_some());
+    }
+
+    #[test]
+    fn test_statuses_mutation() {
+        let mutation = statuses_mutation(
+            "p",
+            &[
+                VersionStatus::new(1, true, None),
+                VersionStatus::new(2, false, Some("failed".to_string())),
+            ],
+        );
+        assert_eq!(
+            mutation["insertOrUpdate"]["values"],
+            json!([
+                ["p", "1", true, null, "spanner.commit_timestamp()"],
+                ["p", "2", false, "failed", "spanner.commit_timestamp()"],
+            ])
+        );
+
+        let (params, types) = query_params(&[
+            ("name", Param::String("p")),
+            ("start_version", Param::Int64(3)),
+        ]);
+        assert_eq!(params, json!({"name": "p", "start_version": "3"}));
+        assert_eq!(
+            types,
+            json!({"name": {"code": "STRING"}, "start_version": {"code": "INT64"}})
+        );
+    }
+}
@@ -0,0 +1,234 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! The fetch layer on its own, for crates which want the node's transactions without the tailer and its processors.
+//! A `TransactionStream` hands out consecutive batches and reports failures to the caller, who decides whether to
+//! retry, rather than retrying forever like the tailer's fetchers. Implementations:
+//! - `RestTransactionStream`: the node's REST API, built with `FetcherConfig::build_stream`
+//! - `ReplayTransactionStream`: transactions recorded as JSON files, ex: test fixtures
+//! - `storage_fetcher::StorageTransactionStream` (with the `storage` feature): a co-located node's DB
+//! - `raw_fetcher::RawTableFetcher` (with the `postgres` feature): the `raw_transactions` archive
+//! - `grpc_stream::GrpcTransactionStream` (with the `api` feature): another indexer's archive, over its gRPC read API
+
+use crate::indexer::fetcher::{remove_null_bytes_from_txns, CompressedClient};
+use anyhow::{ensure, Context, Result};
+use aptos_rest_client::{Client as RestClient, Transaction};
+use std::path::Path;
+
+/// Versions requested at a time by default, the most the node serves at once
+pub const DEFAULT_BATCH_SIZE: u16 = 500;
+
+#[async_trait::async_trait]
+pub trait TransactionStream: Send {
+    /// The next transactions, in version order, or none if the stream is caught up with the ledger
+    async fn next_batch(&mut self) -> Result<Vec<Transaction>>;
+
+    async fn ledger_chain_id(&mut self) -> Result<u8>;
+
+    /// The latest version of the ledger
+    async fn ledger_version(&mut self) -> Result<u64>;
+}
+
+/// Streams transactions from the node's REST API
+#[derive(Debug)]
+pub struct RestTransactionStream {
+    client: RestClient,
+    compressed_client: Option<CompressedClient>,
+    next_version: u64,
+    batch_size: u16,
+    /// As of the last time the node was asked
+    ledger_version: Option<u64>,
+    chain_id: Option<u8>,
+}
+
+impl RestTransactionStream {
+    pub fn new(client: RestClient, starting_version: u64) -> Self {
+        Self {
+            client,
+            compressed_client: None,
+            next_version: starting_version,
+            batch_size: DEFAULT_BATCH_SIZE,
+            ledger_version: None,
+            chain_id: None,
+        }
+    }
+
+    /// Requests batches gzipped through `compressed_client`, if set
+    pub fn with_compressed_client(mut self, compressed_client: Option<CompressedClient>) -> Self {
+        self.compressed_client = compressed_client;
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: u16) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    async fn refresh_ledger_info(&mut self) -> Result<()> {
+        let state = self
+            .client
+            .get_ledger_information()
+            .await
+            .context("Failed to get the node's ledger info")?
+            .into_inner();
+        self.ledger_version = Some(state.version);
+        self.chain_id = Some(state.chain_id);
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl TransactionStream for RestTransactionStream {
+    async fn next_batch(&mut self) -> Result<Vec<Transaction>> {
+        if self
+            .ledger_version
+            .map_or(true, |ledger_version| self.next_version > ledger_version)
+        {
+            self.refresh_ledger_info().await?;
+            if self.next_version > self.ledger_version.unwrap_or_default() {
+                return Ok(vec![]);
+            }
+        }
+        let txns = match &self.compressed_client {
+            Some(compressed_client) => {
+                compressed_client
+                    .get_transactions(&self.client, self.next_version, self.batch_size)
+                    .await
+            }
+            None => self
+                .client
+                .get_transactions(Some(self.next_version), Some(self.batch_size))
+                .await
+                .map(|response| response.into_inner()),
+        }
+        .with_context(|| {
+            format!(
+                "Failed to fetch transactions from version {}",
+                self.next_version
+            )
+        })?;
+        if let Some(version) = txns.last().and_then(|txn| txn.version()) {
+            self.next_version = version + 1;
+        }
+        Ok(remove_null_bytes_from_txns(txns))
+    }
+
+    async fn ledger_chain_id(&mut self) -> Result<u8> {
+        if self.chain_id.is_none() {
+            self.refresh_ledger_info().await?;
+        }
+        Ok(self.chain_id.unwrap_or_default())
+    }
+
+    async fn ledger_version(&mut self) -> Result<u64> {
+        self.refresh_ledger_info().await?;
+        Ok(self.ledger_version.unwrap_or_default())
+    }
+}
+
+/// Reads every `*.json` file in `dir`, each an array of API transactions, returning their transactions ordered by
+/// version
+pub fn load_transactions(dir: &Path) -> Result<Vec<Transaction>> {
+    let mut transactions = vec![];
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Could not read {:?}", dir))? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .map_or(true, |extension| extension != "json")
+        {
+            continue;
+        }
+        let file_transactions: Vec<Transaction> = serde_json::from_slice(&std::fs::read(&path)?)
+            .with_context(|| format!("Could not parse {:?}", path))?;
+        transactions.extend(file_transactions);
+    }
+    ensure!(!transactions.is_empty(), "No transactions in {:?}", dir);
+    transactions.sort_by_key(|txn| txn.version().unwrap_or(0));
+    transactions.dedup_by_key(|txn| txn.version());
+    Ok(transactions)
+}
+
+/// Replays recorded transactions, ex: from `load_transactions`. Once they've all been handed out, the stream is
+/// caught up.
+#[derive(Debug)]
+pub struct ReplayTransactionStream {
+    transactions: Vec<Transaction>,
+    chain_id: u8,
+    batch_size: usize,
+    position: usize,
+}
+
+impl ReplayTransactionStream {
+    pub fn new(transactions: Vec<Transaction>, chain_id: u8) -> Self {
+        Self {
+            transactions,
+            chain_id,
+            batch_size: DEFAULT_BATCH_SIZE as usize,
+            position: 0,
+        }
+    }
+
+    /// Replays the transactions recorded in `dir` (see `load_transactions`)
+    pub fn from_dir(dir: &Path, chain_id: u8) -> Result<Self> {
+        Ok(Self::new(load_transactions(dir)?, chain_id))
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl TransactionStream for ReplayTransactionStream {
+    async fn next_batch(&mut self) -> Result<Vec<Transaction>> {
+        let end = (self.position + self.batch_size).min(self.transactions.len());
+        let batch = self.transactions[self.position..end].to_vec();
+        self.position = end;
+        Ok(batch)
+    }
+
+    async fn ledger_chain_id(&mut self) -> Result<u8> {
+        Ok(self.chain_id)
+    }
+
+    async fn ledger_version(&mut self) -> Result<u64> {
+        Ok(self
+            .transactions
+            .last()
+            .and_then(|txn| txn.version())
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_replay_stream() {
+        let dir =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/model_conversion/transactions");
+        let num_transactions = load_transactions(&dir).unwrap().len();
+        let mut stream = ReplayTransactionStream::from_dir(&dir, 4)
+            .unwrap()
+            .with_batch_size(2);
+        assert_eq!(stream.ledger_chain_id().await.unwrap(), 4);
+
+        let mut versions = vec![];
+        loop {
+            let batch = stream.next_batch().await.unwrap();
+            if batch.is_empty() {
+                break;
+            }
+            assert!(batch.len() <= 2);
+            versions.extend(batch.iter().map(|txn| txn.version().unwrap()));
+        }
+        assert_eq!(versions.len(), num_transactions);
+        assert!(versions.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(
+            stream.ledger_version().await.unwrap(),
+            *versions.last().unwrap()
+        );
+    }
+}
@@ -1,81 +1,191 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 use crate::{
-    database::{execute_with_better_error, PgDbPool},
+    counters::{
+        BATCHES_DEAD_LETTERED, BATCH_RETRIES, REORGS_DETECTED, REORG_REPROCESSED_VERSIONS,
+        VERIFICATION_MISSING_VERSIONS,
+    },
     indexer::{
+        account_changes::{affected_accounts, AccountChanges},
+        commit_hooks::CommitHooks,
+        dispatch::{ConnectionBudget, ConnectionPermit, ProcessorQuota, ProcessorSwitch},
         errors::TransactionProcessingError,
-        fetcher::{TransactionFetcher, TransactionFetcherTrait},
+        fetcher::{
+            pruned_version_error, FetcherConfig, TransactionFetcher, TransactionFetcherTrait,
+        },
+        framework_addresses::FrameworkAddresses,
         processing_result::ProcessingResult,
+        timestamps::TimestampNormalizer,
         transaction_processor::TransactionProcessor,
     },
-    models::ledger_info::LedgerInfo,
-    schema::ledger_infos::{self, dsl},
-    util::bigdecimal_to_u64,
+    models::ledger_info::LedgerInfoHistory,
+};
+#[cfg(feature = "postgres")]
+use crate::{
+    database::{run_migrations, PgDbPool},
+    indexer::processor_metadata::reset_if_chain_changed,
+    rollups::{merge_ranges, RollupTask},
 };
-use anyhow::{ensure, Context, Result};
-use aptos_logger::info;
+#[cfg(feature = "postgres")]
+use anyhow::Context;
+use anyhow::{ensure, Result};
+use aptos_logger::{error, info, warn};
 use aptos_rest_client::Transaction;
-use bigdecimal::BigDecimal;
-use diesel::{
-    prelude::*,
-    sql_query,
-    sql_types::{BigInt, Numeric, Text},
-    RunQueryDsl,
+use std::{fmt::Debug, sync::Arc, time::Duration};
+use tokio::{
+    sync::{Mutex, OwnedSemaphorePermit, Semaphore},
+    task::JoinHandle,
 };
-use std::{fmt::Debug, sync::Arc};
-use tokio::{sync::Mutex, task::JoinHandle};
 use url::{ParseError, Url};
 
-diesel_migrations::embed_migrations!();
+/// How many times a batch is processed before it's left failed, if its errors are retryable (see
+/// `TransactionProcessingError::is_retryable`)
+pub const MAX_BATCH_ATTEMPTS: u32 = 4;
+/// Wait before processing a batch again, doubled with each attempt
+const BATCH_RETRY_BACKOFF: Duration = Duration::from_millis(500);
 
 #[derive(Clone)]
 pub struct Tailer {
     pub transaction_fetcher: Arc<Mutex<dyn TransactionFetcherTrait>>,
     processor: Arc<dyn TransactionProcessor>,
-    connection_pool: PgDbPool,
+    #[cfg(feature = "postgres")]
+    connection_pool: Option<PgDbPool>,
+    #[cfg(feature = "postgres")]
+    rollup_task: Option<Arc<RollupTask>>,
+    quota: ProcessorQuota,
+    processor_semaphore: Option<Arc<Semaphore>>,
+    connection_budget: Option<Arc<ConnectionBudget>>,
+    /// Pauses processing while the processor is disabled (shared by the tailers built by `with_new_fetcher`)
+    switch: Arc<ProcessorSwitch>,
+    /// Where the fetcher fetches from and how, to build other fetchers (see `with_new_fetcher`)
+    node_url: Url,
+    fetcher_config: Option<FetcherConfig>,
+    /// If set, deployment-specific framework addresses are mapped to the canonical ones before processing
+    framework_addresses: Option<Arc<FrameworkAddresses>>,
+    /// If set, timestamps too far in the future or going backwards are adjusted before processing
+    timestamp_normalizer: Option<Arc<TimestampNormalizer>>,
+    /// Notified of every batch the processor commits
+    commit_hooks: Option<Arc<CommitHooks>>,
 }
 
 impl Tailer {
+    /// A tailer which runs migrations and rollups against `connection_pool`
+    #[cfg(feature = "postgres")]
     pub fn new(
         node_url: &str,
         connection_pool: PgDbPool,
         processor: Arc<dyn TransactionProcessor>,
+    ) -> Result<Tailer, ParseError> {
+        let mut tailer = Self::from_processor(node_url, processor)?;
+        tailer.connection_pool = Some(connection_pool);
+        Ok(tailer)
+    }
+
+    /// A tailer which only uses the processor (and its metadata handle), ex: for sink-only deployments
+    pub fn from_processor(
+        node_url: &str,
+        processor: Arc<dyn TransactionProcessor>,
     ) -> Result<Tailer, ParseError> {
         let url = Url::parse(node_url)?;
-        let transaction_fetcher = TransactionFetcher::new(url, None);
+        let transaction_fetcher = TransactionFetcher::new(url.clone(), None);
         Ok(Self {
             transaction_fetcher: Arc::new(Mutex::new(transaction_fetcher)),
-            connection_pool,
             processor,
+            #[cfg(feature = "postgres")]
+            connection_pool: None,
+            #[cfg(feature = "postgres")]
+            rollup_task: None,
+            quota: ProcessorQuota::default(),
+            processor_semaphore: None,
+            connection_budget: None,
+            switch: Arc::new(ProcessorSwitch::default()),
+            node_url: url,
+            fetcher_config: None,
+            framework_addresses: None,
+            timestamp_normalizer: None,
+            commit_hooks: None,
+        })
+    }
+
+    /// Limits how many batches the processor works on concurrently, and (when several processors share a
+    /// connection pool) the priority with which it gets connections from the shared `connection_budget`
+    pub fn set_quota(
+        &mut self,
+        quota: ProcessorQuota,
+        connection_budget: Option<Arc<ConnectionBudget>>,
+    ) {
+        self.processor_semaphore = quota
+            .max_concurrent_connections
+            .map(|max| Arc::new(Semaphore::new(max)));
+        self.connection_budget = connection_budget;
+        self.quota = quota;
+    }
+
+    /// The switch enabling or disabling the processor at runtime
+    pub fn switch(&self) -> Arc<ProcessorSwitch> {
+        self.switch.clone()
+    }
+
+    /// Fetches from `node_url` through a client configured with `config`, instead of the REST client's defaults.
+    /// Must be called before the fetcher is started.
+    pub fn set_fetcher_config(&mut self, node_url: &str, config: &FetcherConfig) -> Result<()> {
+        self.node_url = Url::parse(node_url)?;
+        self.transaction_fetcher = config.build_fetcher_trait(self.node_url.clone())?;
+        self.fetcher_config = Some(config.clone());
+        Ok(())
+    }
+
+    /// A tailer sharing this one's processor and quota, with a new (not yet started) fetcher configured like this
+    /// one's, ex: to backfill a range of versions while this one tails
+    pub fn with_new_fetcher(&self) -> Result<Tailer> {
+        let transaction_fetcher = match &self.fetcher_config {
+            Some(config) => config.build_fetcher_trait(self.node_url.clone())?,
+            None => Arc::new(Mutex::new(TransactionFetcher::new(
+                self.node_url.clone(),
+                None,
+            ))),
+        };
+        Ok(Self {
+            transaction_fetcher,
+            ..self.clone()
         })
     }
 
+    /// Maps the framework addresses of a permissioned chain to the canonical ones in every batch, before the
+    /// processor sees it (see `framework_addresses`)
+    pub fn set_framework_addresses(&mut self, framework_addresses: Arc<FrameworkAddresses>) {
+        self.framework_addresses = Some(framework_addresses);
+    }
+
+    /// Normalizes the timestamps of every batch before the processor sees it (see `timestamps`)
+    pub fn set_timestamp_normalizer(&mut self, timestamp_normalizer: Arc<TimestampNormalizer>) {
+        self.timestamp_normalizer = Some(timestamp_normalizer);
+    }
+
+    /// Runs `commit_hooks` (in the background) after each batch the processor commits
+    pub fn set_commit_hooks(&mut self, commit_hooks: Arc<CommitHooks>) {
+        self.commit_hooks = Some(commit_hooks).filter(|commit_hooks| !commit_hooks.is_empty());
+    }
+
+    /// Updates the given rollups with every batch the processor commits
+    #[cfg(feature = "postgres")]
+    pub fn set_rollup_task(&mut self, rollup_task: RollupTask) {
+        self.rollup_task = Some(Arc::new(rollup_task));
+    }
+
+    #[cfg(feature = "postgres")]
     pub fn run_migrations(&self) {
-        info!("Running migrations...");
-        embedded_migrations::run_with_output(
-            &self
-                .connection_pool
-                .get()
-                .expect("Could not get connection for migrations"),
-            &mut std::io::stdout(),
-        )
-        .expect("migrations failed!");
-        info!("Migrations complete!");
+        run_migrations(
+            self.connection_pool
+                .as_ref()
+                .expect("Migrations need a connection pool"),
+        );
     }
 
     /// If chain id doesn't exist, save it. Otherwise make sure that we're indexing the same chain
     pub async fn check_or_update_chain_id(&self) -> anyhow::Result<usize> {
         info!("Checking if chain id is correct");
-        let conn = self
-            .connection_pool
-            .get()
-            .expect("DB connection should be available at this stage");
-
-        let query_chain = dsl::ledger_infos
-            .select(dsl::chain_id)
-            .load::<i64>(&conn)
-            .expect("Error loading chain id from db");
-        let maybe_existing_chain_id = query_chain.first();
+        let metadata_handle = self.processor.metadata_handle();
 
         let new_chain_id = self
             .transaction_fetcher
@@ -83,11 +193,11 @@ impl Tailer {
             .await
             .fetch_ledger_info()
             .await
-            .chain_id as i64;
+            .chain_id as u64;
 
-        match maybe_existing_chain_id {
+        match metadata_handle.check_or_set_chain_id(new_chain_id)? {
             Some(chain_id) => {
-                ensure!(*chain_id == new_chain_id, "Wrong chain detected! Trying to index chain {} now but existing data is for chain {}", new_chain_id, chain_id);
+                ensure!(chain_id == new_chain_id, "Wrong chain detected! Trying to index chain {} now but existing data is for chain {}", new_chain_id, chain_id);
                 info!(
                     chain_id = chain_id,
                     "Chain id matches! Continuing to index chain"
@@ -97,19 +207,76 @@ impl Tailer {
             None => {
                 info!(
                     chain_id = new_chain_id,
-                    "Adding chain id to db, continue indexing"
+                    "Added chain id to metadata, continue indexing"
                 );
-                execute_with_better_error(
-                    &conn,
-                    diesel::insert_into(ledger_infos::table).values(LedgerInfo {
-                        chain_id: new_chain_id,
-                    }),
-                )
-                .context(r#"Error updating chain_id!"#)
+                Ok(1)
             }
         }
     }
 
+    /// With `--force-reset-chain`: deletes the indexed data if it's of another chain than the node's (see
+    /// `reset_if_chain_changed`), returning whether it did
+    #[cfg(feature = "postgres")]
+    pub async fn reset_if_chain_changed(&self) -> anyhow::Result<bool> {
+        let chain_id = self
+            .transaction_fetcher
+            .lock()
+            .await
+            .fetch_ledger_info()
+            .await
+            .chain_id as u64;
+        reset_if_chain_changed(
+            &self
+                .connection_pool
+                .as_ref()
+                .expect("Resetting the chain needs a connection pool")
+                .get()?,
+            chain_id,
+        )
+    }
+
+    /// Records a snapshot of the node's ledger info (epoch, version, timestamp and oldest version it still serves)
+    pub async fn record_ledger_info(&self) -> anyhow::Result<()> {
+        let state = self
+            .transaction_fetcher
+            .lock()
+            .await
+            .fetch_ledger_info()
+            .await;
+        self.processor
+            .metadata_handle()
+            .record_ledger_info(&LedgerInfoHistory::from_state(&state))
+    }
+
+    /// Makes sure the node still serves `start_version`. If it was pruned, fails, or with `force_jump_to_oldest`
+    /// returns the node's oldest version to start from instead, skipping the versions in between.
+    pub async fn check_start_version(
+        &self,
+        start_version: u64,
+        force_jump_to_oldest: bool,
+    ) -> anyhow::Result<u64> {
+        let oldest_ledger_version = self
+            .transaction_fetcher
+            .lock()
+            .await
+            .fetch_ledger_info()
+            .await
+            .oldest_ledger_version;
+        if start_version >= oldest_ledger_version {
+            return Ok(start_version);
+        }
+        if !force_jump_to_oldest {
+            return Err(pruned_version_error(start_version, oldest_ledger_version));
+        }
+        warn!(
+            processor_name = self.processor_name(),
+            start_version = start_version,
+            oldest_ledger_version = oldest_ledger_version,
+            "Start version was pruned by the node, jumping to its oldest version"
+        );
+        Ok(oldest_ledger_version)
+    }
+
     pub async fn set_fetcher_version(&self, version: u64) {
         self.transaction_fetcher
             .lock()
@@ -132,6 +299,18 @@ impl Tailer {
             .await
             .fetch_next_batch()
             .await;
+        self.process_transactions(transactions, batch_size).await
+    }
+
+    /// Processes `transactions` in parallel batches of `batch_size`
+    pub async fn process_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+        batch_size: u8,
+    ) -> (
+        usize,
+        Vec<Result<ProcessingResult, TransactionProcessingError>>,
+    ) {
         let num_txns = transactions.len();
         let mut tasks = vec![];
         let num_batches = (transactions.len() as f64 / batch_size as f64).ceil() as usize;
@@ -145,9 +324,7 @@ impl Tailer {
                 txns.push(t.clone());
             }
             if !txns.is_empty() {
-                let task = tokio::task::spawn(async move {
-                    self2.processor.process_transactions_with_status(txns).await
-                });
+                let task = tokio::task::spawn(async move { self2.process_batch(txns).await });
                 tasks.push(task);
             }
         }
@@ -156,6 +333,526 @@ impl Tailer {
         (num_txns, results)
     }
 
+    /// Processes one batch once the processor's quota allows it, recording its status
+    pub(crate) async fn process_batch(
+        &self,
+        transactions: Vec<Transaction>,
+    ) -> Result<ProcessingResult, TransactionProcessingError> {
+        self.switch.wait_until_enabled().await;
+        let _permits = self.acquire_dispatch_permits().await;
+        self.process_with_status(transactions).await
+    }
+
+    /// Processes `transactions` (see `process_once`), again with backoff while it fails with retryable errors, up to
+    /// `MAX_BATCH_ATTEMPTS` times. A batch which still fails is left marked failed in the processor's statuses (the
+    /// dead letters, processed again on restart) and counted in `indexer_batch_dead_letter_count`.
+    async fn process_with_status(
+        &self,
+        transactions: Vec<Transaction>,
+    ) -> Result<ProcessingResult, TransactionProcessingError> {
+        let mut attempt = 1;
+        loop {
+            let attempt_transactions = if attempt < MAX_BATCH_ATTEMPTS {
+                transactions.clone()
+            } else {
+                transactions
+            };
+            let tpe = match self.process_once(attempt_transactions).await {
+                Ok(result) => return Ok(result),
+                Err(tpe) => tpe,
+            };
+            let (_, start_version, end_version, _) = tpe.inner();
+            if attempt < MAX_BATCH_ATTEMPTS && tpe.is_retryable() {
+                let backoff = BATCH_RETRY_BACKOFF * 2u32.pow(attempt - 1);
+                BATCH_RETRIES
+                    .with_label_values(&[self.processor.name(), tpe.kind()])
+                    .inc();
+                warn!(
+                    processor_name = self.processor.name(),
+                    start_version = start_version,
+                    end_version = end_version,
+                    attempt = attempt,
+                    backoff_ms = backoff.as_millis() as u64,
+                    error = format!("{:?}", tpe),
+                    "Batch failed with a retryable error, processing it again"
+                );
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+                continue;
+            }
+            BATCHES_DEAD_LETTERED
+                .with_label_values(&[self.processor.name(), tpe.kind()])
+                .inc();
+            error!(
+                processor_name = self.processor.name(),
+                start_version = start_version,
+                end_version = end_version,
+                attempts = attempt,
+                retryable = tpe.is_retryable(),
+                error = format!("{:?}", tpe),
+                "Batch failed, leaving its versions marked failed"
+            );
+            return Err(tpe);
+        }
+    }
+
+    /// Hands `transactions` to the processor, with normalized timestamps and canonical framework addresses, recording
+    /// their status, then updates the rollups and notifies the commit hooks. The batch fails if the rollups do: its
+    /// versions are marked failed, so they're processed (and folded) again.
+    async fn process_once(
+        &self,
+        transactions: Vec<Transaction>,
+    ) -> Result<ProcessingResult, TransactionProcessingError> {
+        let transactions = self.normalize(transactions);
+        let accounts = self
+            .commit_hooks
+            .as_ref()
+            .map(|_| affected_accounts(&transactions));
+        #[cfg(feature = "postgres")]
+        let rollup_transactions = self.rollup_task.as_ref().map(|_| transactions.clone());
+        let result = self
+            .processor
+            .process_transactions_with_status(transactions)
+            .await;
+        #[cfg(feature = "postgres")]
+        let result = match (result, rollup_transactions) {
+            (Ok(result), Some(rollup_transactions)) => {
+                match self.run_rollups(rollup_transactions).await {
+                    Ok(()) => Ok(result),
+                    Err(err) => {
+                        let tpe = TransactionProcessingError::Custom((
+                            err,
+                            result.start_version,
+                            result.end_version,
+                            result.name,
+                        ));
+                        if let Err(error) = self.processor.update_status_err(&tpe) {
+                            error!(
+                                processor_name = self.processor.name(),
+                                error = format!("{:?}", error),
+                                "Could not mark the versions whose rollups failed, they're left marked successful"
+                            );
+                        }
+                        Err(tpe)
+                    }
+                }
+            }
+            (result, _) => result,
+        };
+        if let (Ok(result), Some(commit_hooks), Some(accounts)) =
+            (&result, &self.commit_hooks, accounts)
+        {
+            commit_hooks.notify(AccountChanges {
+                start_version: result.start_version,
+                end_version: result.end_version,
+                processors: vec![result.name.clone()],
+                accounts,
+            });
+        }
+        result
+    }
+
+    /// Normalizes the timestamps and canonicalizes the framework addresses of `transactions`, if enabled
+    fn normalize(&self, transactions: Vec<Transaction>) -> Vec<Transaction> {
+        let transactions = match &self.timestamp_normalizer {
+            Some(timestamp_normalizer) => {
+                timestamp_normalizer.normalize(self.processor.name(), transactions)
+            }
+            None => transactions,
+        };
+        match &self.framework_addresses {
+            Some(framework_addresses) => framework_addresses.canonicalize_all(transactions),
+            None => transactions,
+        }
+    }
+
+    /// Folds into the rollups the versions up to `end_version` the processor committed but the rollups miss, ex:
+    /// because the indexer stopped between the two (see `RollupTask::unfolded_ranges`), returning how many were
+    /// folded. Versions the processor didn't commit are left to be folded once it does.
+    #[cfg(feature = "postgres")]
+    pub async fn refold_rollups(&self, end_version: u64) -> anyhow::Result<usize> {
+        let rollup_task = match &self.rollup_task {
+            Some(rollup_task) => rollup_task.clone(),
+            None => return Ok(0),
+        };
+        let pool = self
+            .connection_pool
+            .clone()
+            .expect("Rollups need a connection pool");
+        let unfolded = tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<(u64, u64)>> {
+            let conn = pool
+                .get()
+                .context("Could not get a DB connection to read the rollups' ranges")?;
+            Ok(rollup_task.unfolded_ranges(&conn, end_version)?)
+        })
+        .await
+        .expect("Reading the rollups' ranges panicked")?;
+
+        let metadata_handle = self.processor.metadata_handle();
+        let mut num_folded = 0;
+        for (start_version, end_version) in unfolded {
+            let mut window_start = start_version;
+            while window_start <= end_version {
+                let window_end = std::cmp::min(
+                    window_start.saturating_add(CATCH_UP_WINDOW - 1),
+                    end_version,
+                );
+                let successful: Vec<(u64, u64)> = metadata_handle
+                    .get_successful_versions(self.processor_name(), window_start, window_end)?
+                    .into_iter()
+                    .map(|version| (version, version))
+                    .collect();
+                for (run_start, run_end) in merge_ranges(&[], &successful) {
+                    // A fetcher can only be started once, so each run gets a new one
+                    num_folded += self
+                        .with_new_fetcher()?
+                        .fold_range(run_start, run_end)
+                        .await?;
+                }
+                window_start = window_end + 1;
+            }
+        }
+        if num_folded > 0 {
+            info!(
+                processor_name = self.processor_name(),
+                num_folded = num_folded,
+                "Folded the versions the rollups missed"
+            );
+        }
+        Ok(num_folded)
+    }
+
+    /// Fetches versions `start_version` to `end_version` (inclusive) and folds them into the rollups, without handing
+    /// them to the processor. Starts the fetcher.
+    #[cfg(feature = "postgres")]
+    async fn fold_range(&self, start_version: u64, end_version: u64) -> anyhow::Result<usize> {
+        self.set_fetcher_version(start_version).await;
+        self.transaction_fetcher.lock().await.start().await;
+        let mut num_folded = 0;
+        loop {
+            let transactions: Vec<Transaction> = self
+                .transaction_fetcher
+                .lock()
+                .await
+                .fetch_next_batch()
+                .await
+                .into_iter()
+                .filter(|txn| txn.version().unwrap() <= end_version)
+                .collect();
+            let reached_end = transactions
+                .last()
+                .map_or(true, |txn| txn.version().unwrap() == end_version);
+            num_folded += transactions.len();
+            self.run_rollups(self.normalize(transactions)).await?;
+            if reached_end {
+                break;
+            }
+        }
+        Ok(num_folded)
+    }
+
+    /// Folds `transactions` into the rollups, once the processor has committed them. Waits for batches being folded
+    /// concurrently (see `RollupTask::run`) off the async runtime.
+    #[cfg(feature = "postgres")]
+    async fn run_rollups(&self, transactions: Vec<Transaction>) -> Result<()> {
+        let rollup_task = match &self.rollup_task {
+            Some(rollup_task) => rollup_task.clone(),
+            None => return Ok(()),
+        };
+        let pool = self
+            .connection_pool
+            .clone()
+            .expect("Rollups need a connection pool");
+        tokio::task::spawn_blocking(move || {
+            let conn = pool
+                .get()
+                .context("Could not get a DB connection to update rollups")?;
+            rollup_task.run(&conn, &transactions)
+        })
+        .await
+        .expect("Updating rollups panicked")
+    }
+
+    pub fn processor_name(&self) -> &str {
+        self.processor.name()
+    }
+
+    /// Processes versions `start_version` to `end_version` (inclusive) and returns how many were processed, ex: to
+    /// backfill a new processor. Starts the fetcher, so the tailer can't be used to tail afterwards.
+    pub async fn backfill(
+        &self,
+        start_version: u64,
+        end_version: u64,
+        batch_size: u8,
+    ) -> anyhow::Result<usize> {
+        ensure!(
+            start_version <= end_version,
+            "Start version {} is after end version {}",
+            start_version,
+            end_version
+        );
+        self.set_fetcher_version(start_version).await;
+        self.transaction_fetcher.lock().await.start().await;
+
+        let processor_name = self.processor.name();
+        let mut num_processed = 0;
+        let mut num_failed_batches = 0;
+        loop {
+            let transactions: Vec<Transaction> = self
+                .transaction_fetcher
+                .lock()
+                .await
+                .fetch_next_batch()
+                .await
+                .into_iter()
+                .filter(|txn| txn.version().unwrap() <= end_version)
+                .collect();
+            let reached_end = transactions
+                .last()
+                .map_or(true, |txn| txn.version().unwrap() == end_version);
+            let (num_txns, results) = self.process_transactions(transactions, batch_size).await;
+            num_processed += num_txns;
+            num_failed_batches += results.iter().filter(|res| res.is_err()).count();
+            info!(
+                processor_name = processor_name,
+                num_processed = num_processed,
+                end_version = end_version,
+                "Backfilled versions"
+            );
+            if reached_end {
+                break;
+            }
+        }
+        ensure!(
+            num_failed_batches == 0,
+            "{} batches failed to backfill, their versions are marked as failed",
+            num_failed_batches
+        );
+        Ok(num_processed)
+    }
+
+    /// The latest version the node has committed
+    pub async fn get_head_version(&self) -> u64 {
+        self.transaction_fetcher
+            .lock()
+            .await
+            .fetch_ledger_info()
+            .await
+            .version
+    }
+
+    /// Processes the versions from `start_version` to `end_version` (inclusive) which aren't marked successful yet,
+    /// returning how many were processed. This backfills the gap left behind when tailing started from the node's
+    /// latest version, including after a restart part way through.
+    ///
+    /// The range is checked `CATCH_UP_WINDOW` versions at a time, and each run of missing versions is backfilled
+    /// by a tailer with its own fetcher, which gets DB connections after this processor's other batches. Backfilled
+    /// versions reach rollups after the more recent ones: that's fine, as rollups fold any version range not folded
+    /// yet, whatever the order (see `rollup_ranges`).
+    pub async fn backfill_missing(
+        &self,
+        start_version: u64,
+        end_version: u64,
+        batch_size: u8,
+    ) -> anyhow::Result<usize> {
+        let mut backfill_tailer = self.with_new_fetcher()?;
+        backfill_tailer.quota.priority = self.quota.priority.saturating_sub(1);
+        let metadata_handle = self.processor.metadata_handle();
+        let mut num_processed = 0;
+        let mut window_start = start_version;
+        while window_start <= end_version {
+            let window_end = std::cmp::min(
+                window_start.saturating_add(CATCH_UP_WINDOW - 1),
+                end_version,
+            );
+            let successful = metadata_handle.get_successful_versions(
+                self.processor_name(),
+                window_start,
+                window_end,
+            )?;
+            for (run_start, run_end) in missing_ranges(window_start, window_end, &successful) {
+                // A fetcher can only be started once, so each run gets a new one
+                num_processed += backfill_tailer
+                    .with_new_fetcher()?
+                    .backfill(run_start, run_end, batch_size)
+                    .await?;
+            }
+            info!(
+                processor_name = self.processor_name(),
+                window_end = window_end,
+                end_version = end_version,
+                num_processed = num_processed,
+                "Caught up on versions"
+            );
+            window_start = window_end + 1;
+        }
+        Ok(num_processed)
+    }
+
+    /// Waits until the processor's quota allows it to work on another batch
+    async fn acquire_dispatch_permits(
+        &self,
+    ) -> (Option<OwnedSemaphorePermit>, Option<ConnectionPermit>) {
+        let processor_permit = match &self.processor_semaphore {
+            Some(semaphore) => Some(
+                semaphore
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("Processor semaphore is never closed"),
+            ),
+            None => None,
+        };
+        let connection_permit = match &self.connection_budget {
+            Some(budget) => Some(budget.clone().acquire(self.quota.priority).await),
+            None => None,
+        };
+        (processor_permit, connection_permit)
+    }
+
+    /// Samples `num_samples` ranges of `sample_size` versions marked successful and checks that the processor's
+    /// data exists for them. This protects against past crashes between writing statuses and committing data.
+    /// Versions found missing are marked as failed and re-processed. Returns the number of missing versions.
+    pub async fn verify_processed_versions(
+        &self,
+        num_samples: u64,
+        sample_size: u64,
+    ) -> anyhow::Result<usize> {
+        ensure!(
+            num_samples > 0 && sample_size > 0,
+            "Must sample at least one version"
+        );
+        let processor_name = self.processor.name();
+        let (min_version, max_version) = match self.processor.get_successful_version_bounds()? {
+            Some(bounds) => bounds,
+            None => {
+                info!(
+                    processor_name = processor_name,
+                    "No successful versions to verify"
+                );
+                return Ok(0);
+            }
+        };
+        // Spread the samples evenly over everything processed so far
+        let stride = std::cmp::max((max_version - min_version + 1) / num_samples, sample_size);
+
+        let mut missing_versions = vec![];
+        let mut sample_start = min_version;
+        while sample_start <= max_version {
+            let sample_end = std::cmp::min(sample_start + sample_size - 1, max_version);
+            let versions = self
+                .processor
+                .get_successful_versions(sample_start, sample_end)?;
+            match self.processor.find_missing_versions(&versions)? {
+                Some(mut missing) => missing_versions.append(&mut missing),
+                None => {
+                    info!(
+                        processor_name = processor_name,
+                        "Processor doesn't support verifying its data, skipping"
+                    );
+                    return Ok(0);
+                }
+            }
+            sample_start += stride;
+        }
+        info!(
+            processor_name = processor_name,
+            min_version = min_version,
+            max_version = max_version,
+            num_missing = missing_versions.len(),
+            "Verified successfully processed versions"
+        );
+        if missing_versions.is_empty() {
+            return Ok(0);
+        }
+
+        VERIFICATION_MISSING_VERSIONS
+            .with_label_values(&[processor_name])
+            .inc_by(missing_versions.len() as u64);
+        self.processor.mark_versions_missing(&missing_versions)?;
+
+        // Re-process each contiguous run of missing versions as a batch
+        let mut runs: Vec<Vec<u64>> = vec![];
+        for version in &missing_versions {
+            match runs.last_mut() {
+                Some(run) if *run.last().unwrap() + 1 == *version => run.push(*version),
+                _ => runs.push(vec![*version]),
+            }
+        }
+        for run in runs {
+            let mut txns = vec![];
+            for version in run {
+                txns.push(self.get_txn(version).await);
+            }
+            self.process_with_status(txns).await.map_err(|err| {
+                anyhow::anyhow!("Failed to re-process missing versions: {:?}", err)
+            })?;
+        }
+        Ok(missing_versions.len())
+    }
+
+    /// Re-fetches the last `num_versions` versions marked successful and compares their hashes with the stored ones,
+    /// for nodes serving speculative data, whose versions may be replaced. From the first replaced version on, the
+    /// processor's data is deleted and those versions are processed again. Returns the first replaced version.
+    pub async fn check_for_reorg(&self, num_versions: u64) -> anyhow::Result<Option<u64>> {
+        ensure!(num_versions > 0, "Must check at least one version");
+        let processor_name = self.processor.name();
+        let max_version = match self.processor.get_successful_version_bounds()? {
+            Some((_, max_version)) => max_version,
+            None => return Ok(None),
+        };
+        let start_version = max_version.saturating_sub(num_versions - 1);
+        let stored_hashes = match self
+            .processor
+            .get_stored_hashes(start_version, max_version)?
+        {
+            Some(stored_hashes) => stored_hashes,
+            None => {
+                info!(
+                    processor_name = processor_name,
+                    "Processor doesn't store transaction hashes, skipping the reorg check"
+                );
+                return Ok(None);
+            }
+        };
+
+        let mut first_replaced_version = None;
+        for (version, stored_hash) in stored_hashes {
+            let txn = self.get_txn(version).await;
+            if txn.transaction_info()?.hash.to_string() != stored_hash {
+                first_replaced_version = Some(version);
+                break;
+            }
+        }
+        let first_replaced_version = match first_replaced_version {
+            Some(version) => version,
+            None => return Ok(None),
+        };
+        warn!(
+            processor_name = processor_name,
+            first_replaced_version = first_replaced_version,
+            max_version = max_version,
+            "Processed versions were replaced on the node, re-processing them"
+        );
+        REORGS_DETECTED.with_label_values(&[processor_name]).inc();
+        REORG_REPROCESSED_VERSIONS
+            .with_label_values(&[processor_name])
+            .inc_by(max_version - first_replaced_version + 1);
+
+        self.processor
+            .revert_versions(first_replaced_version, max_version)?;
+        self.processor
+            .mark_versions_replaced(first_replaced_version, max_version)?;
+        let mut txns = vec![];
+        for version in first_replaced_version..=max_version {
+            txns.push(self.get_txn(version).await);
+        }
+        self.process_with_status(txns)
+            .await
+            .map_err(|err| anyhow::anyhow!("Failed to re-process replaced versions: {:?}", err))?;
+        Ok(Some(first_replaced_version))
+    }
+
     pub async fn get_txn(&self, version: u64) -> Transaction {
         self.transaction_fetcher
             .lock()
@@ -164,91 +861,37 @@ impl Tailer {
             .await
     }
 
-    /// Get starting version from database. Starting version is defined as the first version that's either
-    /// not successful or missing from the DB.
-    pub fn get_start_version(&self, processor_name: &String) -> Option<u64> {
-        let conn = self
-            .connection_pool
-            .get()
-            .expect("DB connection should be available to get starting version");
-
-        // This query gets the first version that isn't equal to the next version (versions would be sorted of course).
-        // There's also special handling if the gap happens in the beginning.
-        let sql = "
-          WITH raw_boundaries AS
-          (
-              SELECT
-                  MAX(version) AS MAX_BLOCK,
-                  MIN(version) AS MIN_BLOCK
-              FROM
-                  processor_statuses
-              WHERE
-                  name = $1
-                  AND success = TRUE
-          ),
-          boundaries AS
-          (
-              SELECT
-                  MAX(version) AS MAX_BLOCK,
-                  MIN(version) AS MIN_BLOCK
-              FROM
-                  processor_statuses, raw_boundaries
-              WHERE
-                  name = $1
-                  AND success = true
-                  and version >= GREATEST(MAX_BLOCK - $2, 0)
-
-          ),
-          gap AS
-          (
-              SELECT
-                  MIN(version) + 1 AS maybe_gap
-              FROM
-                  (
-                      SELECT
-                          version,
-                          LEAD(version) OVER (
-                      ORDER BY
-                          version ASC) AS next_version
-                      FROM
-                          processor_statuses,
-                          boundaries
-                      WHERE
-                          name = $1
-                          AND success = TRUE
-                          AND version >= GREATEST(MAX_BLOCK - $2, 0)
-                  ) a
-              WHERE
-                  version + 1 <> next_version
-          )
-          SELECT
-              CASE
-                  WHEN
-                      MIN_BLOCK <> GREATEST(MAX_BLOCK - $2, 0)
-                  THEN
-                      GREATEST(MAX_BLOCK - $2, 0)
-                  ELSE
-                      COALESCE(maybe_gap, MAX_BLOCK + 1)
-              END
-              AS version
-          FROM
-              gap, boundaries
-          ";
-        #[derive(Debug, QueryableByName)]
-        pub struct Gap {
-            #[sql_type = "Numeric"]
-            pub version: BigDecimal,
-        }
-        let mut res: Vec<Option<Gap>> = sql_query(sql)
-            .bind::<Text, _>(processor_name)
-            // This is the number used to determine how far we look back for gaps. Increasing it may result in slower startup
-            .bind::<BigInt, _>(1500000)
-            .get_results(&conn)
-            .unwrap();
-        res.pop().unwrap().map(|g| bigdecimal_to_u64(&g.version))
+    /// Get starting version from the processor's metadata. Starting version is defined as the first version
+    /// that's either not successful or missing.
+    pub fn get_start_version(&self, processor_name: &String) -> anyhow::Result<Option<u64>> {
+        self.processor
+            .metadata_handle()
+            .get_start_version(processor_name)
     }
 }
 
+/// Number of versions whose statuses `Tailer::backfill_missing` loads at a time
+const CATCH_UP_WINDOW: u64 = 100_000;
+
+/// The runs of versions from `start_version` to `end_version` (inclusive) that aren't in `successful` (sorted)
+pub fn missing_ranges(start_version: u64, end_version: u64, successful: &[u64]) -> Vec<(u64, u64)> {
+    let mut ranges = vec![];
+    let mut next = start_version;
+    for &version in successful {
+        if version < next || version > end_version {
+            continue;
+        }
+        if version > next {
+            ranges.push((next, version - 1));
+        }
+        next = version + 1;
+    }
+    if next <= end_version {
+        ranges.push((next, end_version));
+    }
+    ranges
+}
+
 pub async fn await_tasks<T: Debug>(tasks: Vec<JoinHandle<T>>) -> Vec<T> {
     let mut results = vec![];
     for task in tasks {
@@ -263,11 +906,16 @@ pub async fn await_tasks<T: Debug>(tasks: Vec<JoinHandle<T>>) -> Vec<T> {
     results
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "postgres"))]
 mod test {
     use super::*;
     use crate::{
         database::{new_db_pool, PgPoolConnection},
+        indexer::{
+            faulty_handle::{FaultRates, FaultyHandle},
+            metadata_handle::{InMemoryMetadataHandle, MetadataHandle},
+            transaction_processor::ProcessorBuilder,
+        },
         models::transactions::TransactionModel,
         processors::default_processor::DefaultTransactionProcessor,
     };
@@ -278,6 +926,9 @@ mod test {
     struct FakeFetcher {
         version: u64,
         chain_id: u8,
+        /// What `fetch_version` serves
+        transactions: Vec<Transaction>,
+        oldest_ledger_version: u64,
     }
 
     impl FakeFetcher {
@@ -285,6 +936,8 @@ mod test {
             Self {
                 version: 0,
                 chain_id: 0,
+                transactions: vec![],
+                oldest_ledger_version: 0,
             }
         }
     }
@@ -295,17 +948,21 @@ mod test {
             unimplemented!();
         }
 
-        async fn fetch_version(&self, _version: u64) -> Transaction {
-            unimplemented!();
+        async fn fetch_version(&self, version: u64) -> Transaction {
+            self.transactions
+                .iter()
+                .find(|txn| txn.version() == Some(version))
+                .cloned()
+                .unwrap_or_else(|| unimplemented!("Fetching version {}", version))
         }
 
         async fn fetch_ledger_info(&mut self) -> State {
             State {
                 chain_id: self.chain_id,
                 epoch: 0,
-                version: 0,
+                version: self.version,
                 timestamp_usecs: 0,
-                oldest_ledger_version: 0,
+                oldest_ledger_version: self.oldest_ledger_version,
                 oldest_block_height: 0,
                 block_height: 0,
             }
@@ -330,6 +987,39 @@ mod test {
             "token_propertys",
             "collections",
             "ownerships",
+            "dex_swaps",
+            "minute_transaction_rollups",
+            "hourly_activity_rollups",
+            "hourly_active_accounts",
+            "epochs",
+            "validator_set_snapshots",
+            "objects",
+            "current_object_ownerships",
+            "fungible_asset_activities",
+            "fungible_asset_balances",
+            "fungible_asset_metadata",
+            "collections_v2",
+            "tokens_v2",
+            "token_activities_v2",
+            "current_token_ownerships_v2",
+            "token_metadata_cache",
+            "ledger_info_history",
+            "transfer_edges",
+            "account_summaries",
+            "account_summary_batches",
+            "txn_latency_stats",
+            "txn_latency_stat_batches",
+            "outbox",
+            "raw_transactions",
+            "fungible_asset_balance_history",
+            "top_holders",
+            "module_daily_stats",
+            "function_reliability_stats",
+            "event_types_seen",
+            "rollup_ranges",
+            "api_keys",
+            "operator_audit_log",
+            "online_migrations",
             "write_set_changes",
             "events",
             "user_transactions",
@@ -669,6 +1359,9 @@ mod test {
         assert!(bmt1.is_some());
         assert_eq!(events1.len(), 1);
         assert_eq!(wsc1.len(), 2);
+        assert_eq!(tx1.num_events, 1);
+        assert_eq!(tx1.num_write_set_changes, 2);
+        assert_eq!(tx1.payload_size_bytes, Some(0));
 
         // This is the genesis transaction
         let (tx0, ut0, bmt0, events0, wsc0) =
@@ -802,6 +1495,9 @@ mod test {
         assert_eq!(events2.first().unwrap().type_, "0x1::Whatever::FakeEvent1");
         assert_eq!(events2.get(1).unwrap().type_, "0x1::Whatever::FakeEvent2");
         assert_eq!(wsc2.len(), 2);
+        assert_eq!(tx2.num_events, 2);
+        assert_eq!(tx2.num_write_set_changes, 2);
+        assert!(tx2.payload_size_bytes.unwrap() > 0);
 
         // Message Transaction -> 0xb8bbd3936b05e3643f4b4f910bb00c9b6fa817c1935c74b9a16b5b7a2c8a69a3
         let message_txn: Transaction = serde_json::from_value(json!(
@@ -881,4 +1577,289 @@ mod test {
         tailer.set_fetcher_version(4).await;
         assert!(tailer.check_or_update_chain_id().await.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_processor_instances_track_progress_independently() {
+        if crate::should_skip_pg_tests() {
+            return;
+        }
+        let (conn_pool, _tailer) = setup_indexer().unwrap();
+        let transactions: Vec<Transaction> = serde_json::from_str(include_str!(
+            "../../testdata/model_conversion/transactions/block_metadata.json"
+        ))
+        .unwrap();
+        let version = transactions[0].version().unwrap();
+
+        let live = DefaultTransactionProcessor::new(conn_pool.clone())
+            .with_name("default_processor:live".to_string());
+        let backfill = DefaultTransactionProcessor::new(conn_pool)
+            .with_name("default_processor:backfill".to_string());
+        let result = live
+            .process_transactions_with_status(transactions)
+            .await
+            .unwrap();
+        assert_eq!(result.name, "default_processor:live");
+
+        assert_eq!(live.get_max_version().unwrap(), Some(version));
+        assert_eq!(backfill.get_max_version().unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_check_for_reorg() {
+        if crate::should_skip_pg_tests() {
+            return;
+        }
+        let (conn_pool, mut tailer) = setup_indexer().unwrap();
+        let transactions: Vec<Transaction> = serde_json::from_str(include_str!(
+            "../../testdata/model_conversion/transactions/block_metadata.json"
+        ))
+        .unwrap();
+        let version = transactions[0].version().unwrap();
+        tailer
+            .processor
+            .process_transactions_with_status(transactions.clone())
+            .await
+            .unwrap();
+
+        // Unchanged on the node
+        let mut fetcher = FakeFetcher::new(Url::parse("http://fake-url.aptos.dev").unwrap(), None);
+        fetcher.transactions = transactions.clone();
+        tailer.transaction_fetcher = Arc::new(Mutex::new(fetcher));
+        assert_eq!(tailer.check_for_reorg(10).await.unwrap(), None);
+
+        // Replaced on the node
+        let mut replaced = serde_json::to_value(&transactions[0]).unwrap();
+        let replaced_hash = format!("0x{}", "ab".repeat(32));
+        replaced["hash"] = json!(replaced_hash);
+        let mut fetcher = FakeFetcher::new(Url::parse("http://fake-url.aptos.dev").unwrap(), None);
+        fetcher.transactions = vec![serde_json::from_value(replaced).unwrap()];
+        tailer.transaction_fetcher = Arc::new(Mutex::new(fetcher));
+        assert_eq!(tailer.check_for_reorg(10).await.unwrap(), Some(version));
+
+        let (txn, _, bmt, ..) =
+            TransactionModel::get_by_version(version, &conn_pool.get().unwrap()).unwrap();
+        assert_eq!(txn.hash, replaced_hash);
+        assert_eq!(bmt.unwrap().hash, replaced_hash);
+        assert_eq!(tailer.check_for_reorg(10).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_record_ledger_info() {
+        use crate::schema::ledger_info_history::dsl;
+        use diesel::{QueryDsl, RunQueryDsl};
+
+        if crate::should_skip_pg_tests() {
+            return;
+        }
+        let (conn_pool, tailer) = setup_indexer().unwrap();
+        tailer.set_fetcher_version(4).await;
+        tailer.record_ledger_info().await.unwrap();
+        // The same ledger version is only recorded once
+        tailer.record_ledger_info().await.unwrap();
+        tailer.set_fetcher_version(10).await;
+        tailer.record_ledger_info().await.unwrap();
+
+        let snapshots: Vec<LedgerInfoHistory> = dsl::ledger_info_history
+            .order(dsl::ledger_version)
+            .load(&conn_pool.get().unwrap())
+            .unwrap();
+        let snapshots: Vec<_> = snapshots
+            .iter()
+            .map(|snapshot| (snapshot.chain_id, snapshot.ledger_version.to_string()))
+            .collect();
+        assert_eq!(
+            snapshots,
+            vec![(4, "4".to_string()), (10, "10".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_event_types_seen_out_of_order() {
+        use crate::{
+            models::rollups::EventTypeSeen, rollups::event_types_seen::EventTypesSeenRollups,
+            schema::event_types_seen::dsl, test_utils::TransactionFactory,
+        };
+        use diesel::RunQueryDsl;
+
+        if crate::should_skip_pg_tests() {
+            return;
+        }
+        let (conn_pool, _tailer) = setup_indexer().unwrap();
+        let conn = conn_pool.get().unwrap();
+        let rollups = RollupTask::new(vec![Box::new(EventTypesSeenRollups)]);
+        let batch = |version: u64| {
+            let mut factory = TransactionFactory::new(version);
+            vec![factory
+                .user_transaction("0xa11ce")
+                .event(0, "0x1::coin::DepositEvent", json!({"amount": "1"}))
+                .event(1, "0x1::coin::DepositEvent", json!({"amount": "2"}))
+                .build()]
+        };
+        rollups.run(&conn, &batch(20)).unwrap();
+        // A backfilled batch lowers first_version, and its events are counted
+        rollups.run(&conn, &batch(10)).unwrap();
+        // Replaying a batch is a no-op
+        rollups.run(&conn, &batch(10)).unwrap();
+
+        let types: Vec<EventTypeSeen> = dsl::event_types_seen.load(&conn).unwrap();
+        assert_eq!(types.len(), 1);
+        assert_eq!(types[0].first_version.to_string(), "10");
+        assert_eq!(types[0].last_version.to_string(), "20");
+        assert_eq!(types[0].num_events, 4);
+    }
+
+    #[tokio::test]
+    async fn test_unfolded_ranges() {
+        use crate::{
+            rollups::event_types_seen::EventTypesSeenRollups, test_utils::TransactionFactory,
+        };
+
+        if crate::should_skip_pg_tests() {
+            return;
+        }
+        let (conn_pool, _tailer) = setup_indexer().unwrap();
+        let conn = conn_pool.get().unwrap();
+        let rollups = RollupTask::new(vec![Box::new(EventTypesSeenRollups)]);
+        let batch = |version: u64| {
+            let mut factory = TransactionFactory::new(version);
+            vec![factory.user_transaction("0xa11ce").build()]
+        };
+        // Nothing folded yet: the versions predate the rollup
+        assert!(rollups.unfolded_ranges(&conn, 30).unwrap().is_empty());
+        rollups.run(&conn, &batch(10)).unwrap();
+        rollups.run(&conn, &batch(20)).unwrap();
+        // The batches committed after 10 but not folded, ex: before a restart
+        assert_eq!(
+            rollups.unfolded_ranges(&conn, 30).unwrap(),
+            vec![(11, 19), (21, 30)]
+        );
+        rollups.run(&conn, &batch(15)).unwrap();
+        assert_eq!(
+            rollups.unfolded_ranges(&conn, 20).unwrap(),
+            vec![(11, 14), (16, 19)]
+        );
+    }
+
+    /// Processes every batch, recording its statuses through a `FaultyHandle`
+    struct FaultyMetadataProcessor {
+        handle: Arc<FaultyHandle>,
+    }
+
+    impl Debug for FaultyMetadataProcessor {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "FaultyMetadataProcessor")
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl TransactionProcessor for FaultyMetadataProcessor {
+        fn name(&self) -> &str {
+            "faulty_metadata_processor"
+        }
+
+        async fn process_transactions(
+            &self,
+            _transactions: Vec<Transaction>,
+            start_version: u64,
+            end_version: u64,
+        ) -> Result<ProcessingResult, TransactionProcessingError> {
+            Ok(ProcessingResult::new(
+                self.name(),
+                start_version,
+                end_version,
+            ))
+        }
+
+        fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+            self.handle.clone()
+        }
+    }
+
+    /// A tailer whose processor records statuses through a `FaultyHandle`, with the handle it wraps
+    fn faulty_metadata_tailer(
+        rates: FaultRates,
+        seed: u64,
+    ) -> (Tailer, Arc<FaultyHandle>, Arc<InMemoryMetadataHandle>) {
+        let inner = Arc::new(InMemoryMetadataHandle::new());
+        let handle = Arc::new(FaultyHandle::new(inner.clone(), rates, seed));
+        let processor = FaultyMetadataProcessor {
+            handle: handle.clone(),
+        };
+        let tailer =
+            Tailer::from_processor("http://fake-url.aptos.dev", Arc::new(processor)).unwrap();
+        (tailer, handle, inner)
+    }
+
+    #[tokio::test]
+    async fn test_status_write_failures_fail_batches() {
+        use crate::test_utils::TransactionFactory;
+
+        let mut factory = TransactionFactory::new(0);
+        let mut batch = || {
+            (0..5)
+                .map(|_| factory.user_transaction("0xa11ce").build())
+                .collect::<Vec<_>>()
+        };
+
+        // Status writes failing now and then fail their batches, which are processed again until they're recorded
+        let (tailer, handle, inner) = faulty_metadata_tailer(
+            FaultRates {
+                connection_drop: 0.25,
+                ..FaultRates::default()
+            },
+            8,
+        );
+        for _ in 0..4 {
+            tailer.process_batch(batch()).await.unwrap();
+        }
+        assert!(!handle.injected_faults().is_empty());
+        let name = tailer.processor_name().to_string();
+        assert_eq!(inner.get_start_version(&name).unwrap(), Some(20));
+        assert!(inner.get_error_versions(&name).unwrap().is_empty());
+
+        // Status writes failing every time fail the batch rather than panicking the tailer
+        let (tailer, handle, _) = faulty_metadata_tailer(
+            FaultRates {
+                connection_drop: 1.0,
+                ..FaultRates::default()
+            },
+            0,
+        );
+        let tpe = tailer.process_batch(batch()).await.unwrap_err();
+        assert_eq!(tpe.kind(), "metadata");
+        assert!(tpe.is_retryable());
+        assert_eq!(handle.injected_faults().len(), MAX_BATCH_ATTEMPTS as usize);
+    }
+
+    #[tokio::test]
+    async fn test_check_start_version() {
+        if crate::should_skip_pg_tests() {
+            return;
+        }
+        let (_conn_pool, mut tailer) = setup_indexer().unwrap();
+        let mut fetcher = FakeFetcher::new(Url::parse("http://fake-url.aptos.dev").unwrap(), None);
+        fetcher.oldest_ledger_version = 100;
+        tailer.transaction_fetcher = Arc::new(Mutex::new(fetcher));
+
+        assert_eq!(tailer.check_start_version(100, false).await.unwrap(), 100);
+        assert_eq!(tailer.check_start_version(150, false).await.unwrap(), 150);
+        let err = tailer.check_start_version(50, false).await.unwrap_err();
+        assert!(err.to_string().contains("--force-jump-to-oldest"));
+        assert_eq!(tailer.check_start_version(50, true).await.unwrap(), 100);
+    }
+
+    #[test]
+    fn test_missing_ranges() {
+        assert_eq!(missing_ranges(10, 20, &[]), vec![(10, 20)]);
+        assert_eq!(
+            missing_ranges(10, 20, &[10, 11, 14, 15, 20]),
+            vec![(12, 13), (16, 19)]
+        );
+        assert_eq!(missing_ranges(10, 12, &[10, 11, 12]), vec![]);
+        // Versions outside of the range are ignored
+        assert_eq!(
+            missing_ranges(10, 12, &[5, 11, 30]),
+            vec![(10, 10), (12, 12)]
+        );
+    }
 }
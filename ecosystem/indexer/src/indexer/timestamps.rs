@@ -0,0 +1,162 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Block timestamps occasionally appear slightly in the future of the indexer host's clock, and some downstream
+//! constraints (ex: `CHECK (timestamp <= now())`, partitions created ahead of time) reject them. `TimestampNormalizer`
+//! rewrites the timestamps of each batch before any processor converts it into models:
+//!
+//! - a timestamp more than the configured skew ahead of the host's clock is clamped to the host's clock plus the skew;
+//! - a timestamp behind the one of an earlier version (ex: after clamping, if the host's clock then steps back) is
+//!   raised to it, so timestamps never decrease with versions.
+//!
+//! Every adjustment is counted in `indexer_timestamp_adjustment_count`. Genesis and pending transactions have no
+//! timestamp, and expiration timestamps are left as is.
+
+use crate::counters::TIMESTAMP_ADJUSTMENTS;
+use aptos_rest_client::Transaction;
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug)]
+pub struct TimestampNormalizer {
+    max_skew: Duration,
+    /// Highest version normalized so far, and its timestamp (in microseconds)
+    high_water: Mutex<Option<(u64, u64)>>,
+}
+
+impl TimestampNormalizer {
+    /// Tolerates timestamps up to `max_skew` ahead of the host's clock
+    pub fn new(max_skew: Duration) -> Self {
+        Self {
+            max_skew,
+            high_water: Mutex::new(None),
+        }
+    }
+
+    fn timestamp_mut(txn: &mut Transaction) -> Option<&mut u64> {
+        match txn {
+            Transaction::UserTransaction(user_txn) => Some(&mut user_txn.timestamp.0),
+            Transaction::BlockMetadataTransaction(block_metadata_txn) => {
+                Some(&mut block_metadata_txn.timestamp.0)
+            }
+            Transaction::StateCheckpointTransaction(state_checkpoint_txn) => {
+                Some(&mut state_checkpoint_txn.timestamp.0)
+            }
+            Transaction::GenesisTransaction(_) | Transaction::PendingTransaction(_) => None,
+        }
+    }
+
+    /// Normalizes the timestamps of `txns` (in order of version) as of `now`, counting the adjustments under
+    /// `processor_name`
+    pub fn normalize_at(
+        &self,
+        processor_name: &str,
+        mut txns: Vec<Transaction>,
+        now: SystemTime,
+    ) -> Vec<Transaction> {
+        let max_timestamp = (now + self.max_skew)
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_micros() as u64)
+            .unwrap_or(u64::MAX);
+        let mut high_water = self.high_water.lock().unwrap();
+        for txn in &mut txns {
+            let version = match txn.version() {
+                Some(version) => version,
+                None => continue,
+            };
+            let timestamp = match Self::timestamp_mut(txn) {
+                Some(timestamp) => timestamp,
+                None => continue,
+            };
+            if *timestamp > max_timestamp {
+                *timestamp = max_timestamp;
+                TIMESTAMP_ADJUSTMENTS
+                    .with_label_values(&[processor_name, "skew"])
+                    .inc();
+            }
+            match *high_water {
+                Some((high_version, _)) if version <= high_version => (),
+                Some((_, high_timestamp)) if *timestamp < high_timestamp => {
+                    *timestamp = high_timestamp;
+                    TIMESTAMP_ADJUSTMENTS
+                        .with_label_values(&[processor_name, "monotonic"])
+                        .inc();
+                    *high_water = Some((version, high_timestamp));
+                }
+                _ => *high_water = Some((version, *timestamp)),
+            }
+        }
+        txns
+    }
+
+    pub fn normalize(&self, processor_name: &str, txns: Vec<Transaction>) -> Vec<Transaction> {
+        self.normalize_at(processor_name, txns, SystemTime::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_metadata(version: u64, timestamp: u64) -> Transaction {
+        serde_json::from_value(serde_json::json!({
+            "type": "block_metadata_transaction",
+            "version": version.to_string(),
+            "hash": format!("0x{:064x}", version),
+            "state_change_hash": format!("0x{:064x}", 0),
+            "event_root_hash": format!("0x{:064x}", 0),
+            "gas_used": "0",
+            "success": true,
+            "vm_status": "Executed successfully",
+            "accumulator_root_hash": format!("0x{:064x}", 0),
+            "changes": [],
+            "id": format!("0x{:064x}", version),
+            "epoch": "1",
+            "round": version.to_string(),
+            "events": [],
+            "previous_block_votes_bitvec": [],
+            "proposer": "0x1",
+            "failed_proposer_indices": [],
+            "timestamp": timestamp.to_string(),
+        }))
+        .unwrap()
+    }
+
+    fn timestamps(txns: &[Transaction]) -> Vec<u64> {
+        txns.iter().map(|txn| txn.timestamp()).collect()
+    }
+
+    #[test]
+    fn test_timestamp_normalizer() {
+        let normalizer = TimestampNormalizer::new(Duration::from_secs(2));
+        let now = UNIX_EPOCH + Duration::from_secs(100);
+
+        // Within the skew: untouched. Beyond: clamped.
+        let txns = normalizer.normalize_at(
+            "test",
+            vec![
+                block_metadata(1, 101_000_000),
+                block_metadata(2, 105_000_000),
+            ],
+            now,
+        );
+        assert_eq!(timestamps(&txns), vec![101_000_000, 102_000_000]);
+
+        // The host's clock stepped back: never behind an earlier version's
+        let txns = normalizer.normalize_at(
+            "test",
+            vec![
+                block_metadata(3, 105_000_000),
+                block_metadata(4, 99_000_000),
+            ],
+            now - Duration::from_secs(10),
+        );
+        assert_eq!(timestamps(&txns), vec![102_000_000, 102_000_000]);
+
+        // Earlier versions, ex: a backfill, are only clamped
+        let txns = normalizer.normalize_at("test", vec![block_metadata(1, 50_000_000)], now);
+        assert_eq!(timestamps(&txns), vec![50_000_000]);
+    }
+}
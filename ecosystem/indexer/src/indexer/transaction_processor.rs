@@ -1,32 +1,116 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::database::get_chunks;
-use crate::util::bigdecimal_to_u64;
+#[cfg(feature = "postgres")]
 use crate::{
-    counters::{
-        GOT_CONNECTION, PROCESSOR_ERRORS, PROCESSOR_INVOCATIONS, PROCESSOR_SUCCESSES,
-        UNABLE_TO_GET_CONNECTION,
+    counters::{GOT_CONNECTION, UNABLE_TO_GET_CONNECTION},
+    database::{PgDbPool, PgPoolConnection},
+    indexer::processor_metadata::{PgMetadataHandle, PgTransactionMetadataHandle},
+};
+use crate::{
+    counters::{PROCESSOR_ERRORS, PROCESSOR_INVOCATIONS, PROCESSOR_SUCCESSES},
+    indexer::{
+        errors::TransactionProcessingError,
+        metadata_handle::{MetadataHandle, VersionStatus},
+        processing_result::ProcessingResult,
     },
-    database::{execute_with_better_error, PgDbPool, PgPoolConnection},
-    indexer::{errors::TransactionProcessingError, processing_result::ProcessingResult},
-    models::processor_statuses::ProcessorStatusModel,
-    schema,
 };
+use anyhow::Context;
 use aptos_rest_client::Transaction;
 use async_trait::async_trait;
-use diesel::pg::upsert::excluded;
-use diesel::{prelude::*, RunQueryDsl};
-use field_count::FieldCount;
-use schema::processor_statuses::{self, dsl};
-use std::fmt::Debug;
+use std::{fmt::Debug, sync::Arc};
+
+/// The name and pools of a processor writing to Postgres. Processors hold one to share `ProcessorBuilder`'s builder
+/// methods, get their connections from it, and return its `metadata_handle` from `TransactionProcessor`'s.
+#[cfg(feature = "postgres")]
+#[derive(Clone)]
+pub struct ProcessorBase {
+    name: String,
+    connection_pool: PgDbPool,
+    metadata_pool: PgDbPool,
+    /// Where statuses are recorded instead of the metadata pool, if set
+    metadata_handle: Option<Arc<dyn MetadataHandle>>,
+}
+
+#[cfg(feature = "postgres")]
+impl ProcessorBase {
+    /// Named `name` (the processor's type name), with statuses written through `connection_pool`
+    pub fn new(name: &str, connection_pool: PgDbPool) -> Self {
+        Self {
+            name: name.to_string(),
+            metadata_pool: connection_pool.clone(),
+            connection_pool,
+            metadata_handle: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn connection_pool(&self) -> &PgDbPool {
+        &self.connection_pool
+    }
+
+    pub fn metadata_pool(&self) -> &PgDbPool {
+        &self.metadata_pool
+    }
+
+    /// Gets a connection from the data pool.
+    /// If it was unable to do so (default timeout: 30s), it will keep retrying until it can.
+    pub fn get_conn(&self) -> PgPoolConnection {
+        get_conn_with_retry(&self.connection_pool)
+    }
+
+    /// Records statuses (and the chain id) through the metadata pool, unless set `with_metadata_handle`
+    pub fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+        match &self.metadata_handle {
+            Some(metadata_handle) => metadata_handle.clone(),
+            None => Arc::new(PgMetadataHandle::new(self.metadata_pool.clone())),
+        }
+    }
+
+    /// Opens a handle to write the processor's statuses within the data write transaction on `conn`
+    pub fn transaction_metadata_handle<'a>(
+        &'a self,
+        conn: &'a PgPoolConnection,
+    ) -> PgTransactionMetadataHandle<'a> {
+        PgTransactionMetadataHandle::new(conn, &self.name)
+    }
+}
+
+/// Builder methods shared by the processors holding a `ProcessorBase`
+#[cfg(feature = "postgres")]
+pub trait ProcessorBuilder: Sized {
+    fn base_mut(&mut self) -> &mut ProcessorBase;
+
+    /// Records statuses under `name` rather than the processor's type name, ex: to run several instances
+    fn with_name(mut self, name: String) -> Self {
+        self.base_mut().name = name;
+        self
+    }
+
+    /// Writes this processor's statuses through a separate pool, so they don't compete with bulk inserts
+    fn with_metadata_pool(mut self, metadata_pool: PgDbPool) -> Self {
+        self.base_mut().metadata_pool = metadata_pool;
+        self
+    }
+
+    /// If set, records this processor's statuses through `metadata_handle` rather than Postgres, ex: in Spanner. Only
+    /// for processors which don't write them within their data write transactions, ie: sinks.
+    fn with_metadata_handle(mut self, metadata_handle: Option<Arc<dyn MetadataHandle>>) -> Self {
+        self.base_mut().metadata_handle = metadata_handle;
+        self
+    }
+}
 
 /// The `TransactionProcessor` is used by an instance of a `Tailer` to process transactions
 #[async_trait]
 pub trait TransactionProcessor: Send + Sync + Debug {
     /// name of the processor, for status logging
-    /// This will get stored in the database for each (`TransactionProcessor`, transaction_version) pair
-    fn name(&self) -> &'static str;
+    /// This will get stored in the database for each (`TransactionProcessor`, transaction_version) pair, so instances
+    /// of the same processor with different names track their progress independently
+    fn name(&self) -> &str;
 
     /// Process all transactions within a block and processes it. This method will be called from `process_transaction_with_status`
     /// In case a transaction cannot be processed, we will fail the entire block.
@@ -37,34 +121,36 @@ pub trait TransactionProcessor: Send + Sync + Debug {
         end_version: u64,
     ) -> Result<ProcessingResult, TransactionProcessingError>;
 
-    /// Gets a reference to the connection pool
-    /// This is used by the `get_conn()` helper below
-    fn connection_pool(&self) -> &PgDbPool;
+    /// Where this processor's statuses (and the chain id) are recorded, outside of data write transactions. Processors
+    /// writing to Postgres return their `ProcessorBase`'s, sinks the one they're configured with.
+    fn metadata_handle(&self) -> Arc<dyn MetadataHandle>;
 
-    //* Below are helper methods that don't need to be implemented *//
+    /// Returns which of the given versions (all marked successful) have no data in the processor's tables.
+    /// This is used by `--verify-on-start` to detect past crashes between writing statuses and committing data.
+    /// Processors whose output can't be checked per version return `Ok(None)`.
+    fn find_missing_versions(&self, _versions: &[u64]) -> anyhow::Result<Option<Vec<u64>>> {
+        Ok(None)
+    }
 
-    /// Gets the connection.
-    /// If it was unable to do so (default timeout: 30s), it will keep retrying until it can.
-    fn get_conn(&self) -> PgPoolConnection {
-        let pool = self.connection_pool();
-        loop {
-            match pool.get() {
-                Ok(conn) => {
-                    GOT_CONNECTION.inc();
-                    return conn;
-                }
-                Err(err) => {
-                    UNABLE_TO_GET_CONNECTION.inc();
-                    aptos_logger::error!(
-                        "Could not get DB connection from pool, will retry in {:?}. Err: {:?}",
-                        pool.connection_timeout(),
-                        err
-                    );
-                }
-            };
-        }
+    /// Returns the (version, hash) of the given versions' transactions as stored by the processor.
+    /// This is used by `--reorg-check-versions` to detect versions replaced on the node since they were processed.
+    /// Processors which don't store transaction hashes return `Ok(None)`.
+    fn get_stored_hashes(
+        &self,
+        _start_version: u64,
+        _end_version: u64,
+    ) -> anyhow::Result<Option<Vec<(u64, String)>>> {
+        Ok(None)
     }
 
+    /// Deletes the processor's data for versions `start_version` to `end_version` (inclusive), so they can be
+    /// processed again after being replaced on the node. Only called when `get_stored_hashes` is supported.
+    fn revert_versions(&self, _start_version: u64, _end_version: u64) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    //* Below are helper methods that don't need to be implemented *//
+
     /// This is a helper method, tying together the other helper methods to allow tracking status in the DB
     async fn process_transactions_with_status(
         &self,
@@ -81,38 +167,56 @@ pub trait TransactionProcessor: Send + Sync + Debug {
         let start_version = txns.first().unwrap().version().unwrap();
         let end_version = txns.last().unwrap().version().unwrap();
 
-        self.mark_versions_started(start_version, end_version);
+        let metadata_error = |error| {
+            TransactionProcessingError::Metadata((
+                error,
+                start_version,
+                end_version,
+                self.name().to_string(),
+            ))
+        };
+        self.mark_versions_started(start_version, end_version)
+            .map_err(metadata_error)?;
         let res = self
             .process_transactions(txns, start_version, end_version)
             .await;
         // Handle block success/failure
-        match res.as_ref() {
-            Ok(processing_result) => self.update_status_success(processing_result),
-            Err(tpe) => self.update_status_err(tpe),
-        };
-        res
+        match res {
+            Ok(processing_result) => {
+                // Without its status, the batch is failed so it's processed (and its status recorded) again
+                self.update_status_success(&processing_result)
+                    .map_err(metadata_error)?;
+                Ok(processing_result)
+            }
+            Err(tpe) => {
+                if let Err(error) = self.update_status_err(&tpe) {
+                    aptos_logger::error!(
+                        processor_name = self.name(),
+                        start_version = start_version,
+                        end_version = end_version,
+                        error = format!("{:?}", error),
+                        "Could not mark the failed versions, they're left marked started"
+                    );
+                }
+                Err(tpe)
+            }
+        }
     }
 
-    /// Writes that a version has been started for this `TransactionProcessor` to the DB
-    fn mark_versions_started(&self, start_version: u64, end_version: u64) {
+    /// Writes that a version has been started for this `TransactionProcessor` to the metadata store
+    fn mark_versions_started(&self, start_version: u64, end_version: u64) -> anyhow::Result<()> {
         aptos_logger::debug!(
             "[{}] Marking processing versions started from versions {} to {}",
             self.name(),
             start_version,
             end_version
         );
-        let psms = ProcessorStatusModel::from_versions(
-            self.name(),
-            start_version,
-            end_version,
-            false,
-            None,
-        );
-        self.apply_processor_status(&psms);
+        let statuses = VersionStatus::range(start_version, end_version, false, None);
+        self.apply_processor_status(&statuses)
     }
 
-    /// Writes that a version has been completed successfully for this `TransactionProcessor` to the DB
-    fn update_status_success(&self, processing_result: &ProcessingResult) {
+    /// Writes that a version has been completed successfully for this `TransactionProcessor` to the metadata store
+    fn update_status_success(&self, processing_result: &ProcessingResult) -> anyhow::Result<()> {
         aptos_logger::debug!(
             "[{}] Marking processing version OK from versions {} to {}",
             self.name(),
@@ -120,79 +224,113 @@ pub trait TransactionProcessor: Send + Sync + Debug {
             processing_result.end_version
         );
         PROCESSOR_SUCCESSES.with_label_values(&[self.name()]).inc();
-        let psms = ProcessorStatusModel::from_versions(
-            self.name(),
+        if processing_result.status_committed {
+            // Already written atomically with the data
+            return Ok(());
+        }
+        let statuses = VersionStatus::range(
             processing_result.start_version,
             processing_result.end_version,
             true,
             None,
         );
-        self.apply_processor_status(&psms);
+        self.apply_processor_status(&statuses)
     }
 
-    /// Writes that a version has errored for this `TransactionProcessor` to the DB
-    fn update_status_err(&self, tpe: &TransactionProcessingError) {
+    /// Writes that a version has errored for this `TransactionProcessor` to the metadata store
+    fn update_status_err(&self, tpe: &TransactionProcessingError) -> anyhow::Result<()> {
         aptos_logger::debug!(
             "[{}] Marking processing version Err: {:?}",
             self.name(),
             tpe
         );
         PROCESSOR_ERRORS.with_label_values(&[self.name()]).inc();
-        let psm = ProcessorStatusModel::from_transaction_processing_err(tpe);
-        self.apply_processor_status(&psm);
-    }
-
-    /// Actually performs the write for a `ProcessorStatusModel` changeset
-    fn apply_processor_status(&self, psms: &[ProcessorStatusModel]) {
-        let conn = self.get_conn();
-        let chunks = get_chunks(psms.len(), ProcessorStatusModel::field_count());
-        for (start_ind, end_ind) in chunks {
-            execute_with_better_error(
-                &conn,
-                diesel::insert_into(processor_statuses::table)
-                    .values(&psms[start_ind..end_ind])
-                    .on_conflict((dsl::name, dsl::version))
-                    .do_update()
-                    .set((
-                        dsl::success.eq(excluded(dsl::success)),
-                        dsl::details.eq(excluded(dsl::details)),
-                        dsl::last_updated.eq(excluded(dsl::last_updated)),
-                    )),
-            )
-            .expect("Error updating Processor Status!");
-        }
+        let (error, start_version, end_version, _) = tpe.inner();
+        let statuses =
+            VersionStatus::range(*start_version, *end_version, false, Some(error.to_string()));
+        self.apply_processor_status(&statuses)
+    }
+
+    /// Actually performs the write of statuses
+    fn apply_processor_status(&self, statuses: &[VersionStatus]) -> anyhow::Result<()> {
+        self.metadata_handle()
+            .set_statuses(self.name(), statuses)
+            .context("Error updating Processor Status")
     }
 
-    /// Gets all versions which were not successfully processed for this `TransactionProcessor` from the DB
+    /// Gets all versions which were not successfully processed for this `TransactionProcessor`
     /// This is so the `Tailer` can know which versions to retry
-    fn get_error_versions(&self) -> Vec<u64> {
-        let conn = self.get_conn();
-
-        dsl::processor_statuses
-            .select(dsl::version)
-            .filter(
-                dsl::success
-                    .eq(false)
-                    .and(dsl::name.eq(self.name().to_string())),
-            )
-            .load::<bigdecimal::BigDecimal>(&conn)
-            .expect("Error loading the error versions only query")
-            .iter()
-            .map(bigdecimal_to_u64)
-            .collect()
+    fn get_error_versions(&self) -> anyhow::Result<Vec<u64>> {
+        self.metadata_handle().get_error_versions(self.name())
     }
 
-    /// Gets the highest version for this `TransactionProcessor` from the DB
+    /// Gets the highest version for this `TransactionProcessor`
     /// This is so we know where to resume from on restarts
-    fn get_max_version(&self) -> Option<u64> {
-        let conn = self.get_conn();
+    fn get_max_version(&self) -> anyhow::Result<Option<u64>> {
+        self.metadata_handle().get_max_version(self.name())
+    }
 
-        let res = dsl::processor_statuses
-            .select(diesel::dsl::max(dsl::version))
-            .filter(dsl::name.eq(self.name().to_string()))
-            .first::<Option<bigdecimal::BigDecimal>>(&conn);
+    /// Gets the lowest and highest versions marked successful for this `TransactionProcessor`
+    fn get_successful_version_bounds(&self) -> anyhow::Result<Option<(u64, u64)>> {
+        self.metadata_handle()
+            .get_successful_version_bounds(self.name())
+    }
+
+    /// Gets the versions between `start_version` and `end_version` (inclusive) marked successful for this
+    /// `TransactionProcessor`
+    fn get_successful_versions(
+        &self,
+        start_version: u64,
+        end_version: u64,
+    ) -> anyhow::Result<Vec<u64>> {
+        self.metadata_handle()
+            .get_successful_versions(self.name(), start_version, end_version)
+    }
 
-        res.expect("Error loading the max version query")
-            .map(|v| bigdecimal_to_u64(&v))
+    /// Writes that versions marked successful turned out to have no data, so they're retried like errors
+    fn mark_versions_missing(&self, versions: &[u64]) -> anyhow::Result<()> {
+        let statuses: Vec<VersionStatus> = versions
+            .iter()
+            .map(|version| {
+                VersionStatus::new(
+                    *version,
+                    false,
+                    Some("Data missing on startup verification".to_string()),
+                )
+            })
+            .collect();
+        self.apply_processor_status(&statuses)
+    }
+
+    /// Writes that versions marked successful were replaced on the node, until they're processed again
+    fn mark_versions_replaced(&self, start_version: u64, end_version: u64) -> anyhow::Result<()> {
+        let statuses = VersionStatus::range(
+            start_version,
+            end_version,
+            false,
+            Some("Replaced on the node".to_string()),
+        );
+        self.apply_processor_status(&statuses)
+    }
+}
+
+/// Gets a connection from `pool`, retrying until it can
+#[cfg(feature = "postgres")]
+pub(crate) fn get_conn_with_retry(pool: &PgDbPool) -> PgPoolConnection {
+    loop {
+        match pool.get() {
+            Ok(conn) => {
+                GOT_CONNECTION.inc();
+                return conn;
+            }
+            Err(err) => {
+                UNABLE_TO_GET_CONNECTION.inc();
+                aptos_logger::error!(
+                    "Could not get DB connection from pool, will retry in {:?}. Err: {:?}",
+                    pool.connection_timeout(),
+                    err
+                );
+            }
+        };
     }
 }
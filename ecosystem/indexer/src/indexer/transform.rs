@@ -0,0 +1,218 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Hooks run on models between their conversion from transactions and their insertion, ex: for privacy-constrained
+//! deployments which mustn't store some fields as is. `FieldTransforms` is the configured hook: rules of the form
+//! `<table>.<column>=<action>` either drop a column (ex: `user_transactions.payload=drop` to strip large payloads) or
+//! replace it with a keyed hash (ex: `user_transactions.sender=hash`), which still lets rows be joined on it.
+//!
+//! A dropped column keeps a placeholder of its type, since columns aren't nullable: JSON becomes `null`, text becomes
+//! empty, numbers 0 and booleans false. Hashes are hex HMAC-SHA256s of the value with the configured key; JSON values
+//! are hashed as text, and hashing a number or boolean is rejected, since the hash couldn't be stored in the column.
+
+use anyhow::{bail, Context, Result};
+use hmac::{Hmac, Mac};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use std::{collections::HashMap, fmt::Debug, str::FromStr, sync::Arc};
+
+/// Transforms the rows of a table before they're inserted
+pub trait TransformHook: Debug + Send + Sync {
+    /// Whether `transform` changes rows of `table`, so other tables skip the round trip through JSON
+    fn applies_to(&self, table: &str) -> bool;
+
+    /// Transforms a row of `table`, serialized as a JSON object of its columns
+    fn transform(&self, table: &str, row: &mut Value) -> Result<()>;
+}
+
+/// Runs `hook` on `rows` of `table`
+pub fn apply<T: Serialize + DeserializeOwned>(
+    hook: &dyn TransformHook,
+    table: &str,
+    rows: Vec<T>,
+) -> Result<Vec<T>> {
+    if !hook.applies_to(table) {
+        return Ok(rows);
+    }
+    rows.into_iter()
+        .map(|row| {
+            let mut value = serde_json::to_value(row)?;
+            hook.transform(table, &mut value)?;
+            serde_json::from_value(value)
+                .with_context(|| format!("A transform left a row of {} invalid", table))
+        })
+        .collect()
+}
+
+/// Runs several hooks in turn, ex: dropping some columns, then encrypting others (see `encryption`)
+#[derive(Debug)]
+pub struct TransformHooks(pub Vec<Arc<dyn TransformHook>>);
+
+impl TransformHook for TransformHooks {
+    fn applies_to(&self, table: &str) -> bool {
+        self.0.iter().any(|hook| hook.applies_to(table))
+    }
+
+    fn transform(&self, table: &str, row: &mut Value) -> Result<()> {
+        for hook in self.0.iter().filter(|hook| hook.applies_to(table)) {
+            hook.transform(table, row)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldAction {
+    Drop,
+    Hash,
+}
+
+impl FromStr for FieldAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "drop" => Self::Drop,
+            "hash" => Self::Hash,
+            _ => bail!("Unknown transform {}, expected drop or hash", s),
+        })
+    }
+}
+
+/// Drops or hashes the configured columns
+pub struct FieldTransforms {
+    /// Action on each column of each table
+    rules: HashMap<String, HashMap<String, FieldAction>>,
+    hash_key: Vec<u8>,
+}
+
+impl Debug for FieldTransforms {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FieldTransforms {{ rules: {:?} }}", self.rules)
+    }
+}
+
+impl FieldTransforms {
+    /// Parses `rules` of the form `<table>.<column>=<drop|hash>`. Hashes are keyed with `hash_key`, which must be set
+    /// (and kept secret) if any column is hashed, so hashes of addresses can't be reversed by hashing every address.
+    pub fn new(rules: &[String], hash_key: Option<String>) -> Result<Self> {
+        let mut parsed: HashMap<String, HashMap<String, FieldAction>> = HashMap::new();
+        for rule in rules {
+            let (column, action) = rule
+                .split_once('=')
+                .with_context(|| format!("Expected <table>.<column>=<action>, got {}", rule))?;
+            let (table, column) = column
+                .split_once('.')
+                .with_context(|| format!("Expected <table>.<column>=<action>, got {}", rule))?;
+            parsed
+                .entry(table.to_string())
+                .or_default()
+                .insert(column.to_string(), action.parse()?);
+        }
+        let hashes = parsed
+            .values()
+            .any(|columns| columns.values().any(|action| *action == FieldAction::Hash));
+        let hash_key = match hash_key {
+            Some(hash_key) if !hash_key.is_empty() => hash_key.into_bytes(),
+            _ if hashes => bail!("Hashing columns requires a hash key"),
+            _ => vec![],
+        };
+        Ok(Self {
+            rules: parsed,
+            hash_key,
+        })
+    }
+
+    fn hash(&self, value: &str) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.hash_key).expect("HMAC accepts keys of any size");
+        mac.update(value.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+impl TransformHook for FieldTransforms {
+    fn applies_to(&self, table: &str) -> bool {
+        self.rules.contains_key(table)
+    }
+
+    fn transform(&self, table: &str, row: &mut Value) -> Result<()> {
+        let columns = match self.rules.get(table) {
+            Some(columns) => columns,
+            None => return Ok(()),
+        };
+        for (column, action) in columns {
+            let value = match row.get_mut(column) {
+                Some(value) => value,
+                None => bail!("{} has no column {}", table, column),
+            };
+            *value = match (action, &*value) {
+                (FieldAction::Drop, Value::String(_)) => Value::String(String::new()),
+                (FieldAction::Drop, Value::Number(_)) => Value::from(0),
+                (FieldAction::Drop, Value::Bool(_)) => Value::Bool(false),
+                (FieldAction::Drop, _) => Value::Null,
+                (FieldAction::Hash, Value::String(text)) => Value::String(self.hash(text)),
+                (FieldAction::Hash, Value::Null) => Value::Null,
+                (FieldAction::Hash, Value::Number(_) | Value::Bool(_)) => {
+                    bail!("{}.{} can't be hashed: it isn't text", table, column)
+                }
+                (FieldAction::Hash, json) => Value::String(self.hash(&json.to_string())),
+            };
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Row {
+        sender: String,
+        payload: Value,
+        gas_used: i64,
+    }
+
+    #[test]
+    fn test_field_transforms() {
+        let rules = vec![
+            "user_transactions.sender=hash".to_string(),
+            "user_transactions.payload=drop".to_string(),
+        ];
+        assert!(FieldTransforms::new(&rules, None).is_err());
+        assert!(FieldTransforms::new(&["user_transactions.sender".to_string()], None).is_err());
+        assert!(
+            FieldTransforms::new(&["user_transactions.sender=mask".to_string()], None).is_err()
+        );
+
+        let transforms = FieldTransforms::new(&rules, Some("key".to_string())).unwrap();
+        let rows = vec![Row {
+            sender: "0x1".to_string(),
+            payload: serde_json::json!({"function": "0x1::coin::transfer"}),
+            gas_used: 7,
+        }];
+        // Other tables are left alone
+        let untouched = apply(&transforms, "transactions", rows).unwrap();
+        assert_eq!(untouched[0].sender, "0x1");
+
+        let transformed = apply(&transforms, "user_transactions", untouched).unwrap();
+        assert_eq!(transformed[0].payload, Value::Null);
+        assert_eq!(transformed[0].gas_used, 7);
+        assert_eq!(transformed[0].sender.len(), 64);
+        assert_eq!(transformed[0].sender, transforms.hash("0x1"));
+        assert_ne!(
+            transforms.hash("0x1"),
+            FieldTransforms::new(&rules, Some("other".to_string()))
+                .unwrap()
+                .hash("0x1")
+        );
+
+        let hash_number =
+            FieldTransforms::new(&["t.gas_used=hash".to_string()], Some("key".to_string()))
+                .unwrap();
+        assert!(apply(&hash_number, "t", transformed).is_err());
+    }
+}
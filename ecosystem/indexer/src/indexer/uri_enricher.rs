@@ -0,0 +1,346 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fetches the off-chain metadata (JSON documents and images) that newly indexed tokens' URIs point to, into
+//! `token_metadata_cache`. This makes requests to arbitrary hosts named by token creators, so it's only built
+//! with the `uri_enricher` feature and only runs when enabled.
+//!
+//! URIs are fetched at most once each, at a limited rate. Failed fetches are retried after a while.
+
+use crate::{
+    counters::URI_ENRICHER_FETCHES,
+    database::{insert_chunked, PgDbPool},
+    indexer::transaction_processor::get_conn_with_retry,
+    models::token_metadata_cache::{TokenMetadataCacheModel, FETCH_STATUS_OK},
+    schema,
+};
+use anyhow::{bail, Context, Result};
+use diesel::{
+    pg::upsert::excluded,
+    sql_query,
+    sql_types::{BigInt, Text, Timestamp},
+    ExpressionMethods, RunQueryDsl,
+};
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[derive(Clone, Debug)]
+pub struct UriEnricherConfig {
+    /// Gateway `ipfs://` URIs are fetched through, ex: "https://ipfs.io/ipfs/"
+    pub ipfs_gateway: String,
+    /// Gateway `ar://` URIs are fetched through
+    pub arweave_gateway: String,
+    /// Maximum number of requests made per second, across all hosts
+    pub requests_per_sec: f64,
+    /// Number of URIs looked up per poll
+    pub batch_size: i64,
+    /// How long to wait before looking for new URIs, when there were none
+    pub poll_interval: Duration,
+    /// How long to wait before fetching a URI that failed again
+    pub retry_failed_after: Duration,
+    pub request_timeout: Duration,
+    /// Documents larger than this are recorded as failures rather than downloaded
+    pub max_document_bytes: u64,
+}
+
+impl Default for UriEnricherConfig {
+    fn default() -> Self {
+        Self {
+            ipfs_gateway: "https://ipfs.io/ipfs/".to_string(),
+            arweave_gateway: "https://arweave.net/".to_string(),
+            requests_per_sec: 5.0,
+            batch_size: 100,
+            poll_interval: Duration::from_secs(10),
+            retry_failed_after: Duration::from_secs(24 * 60 * 60),
+            request_timeout: Duration::from_secs(10),
+            max_document_bytes: 1024 * 1024,
+        }
+    }
+}
+
+/// Resolves a token URI to the HTTP(S) URL to fetch it from. Returns `None` for unsupported schemes (ex: `data:`).
+pub fn resolve_uri(uri: &str, config: &UriEnricherConfig) -> Option<String> {
+    let uri = uri.trim();
+    let with_gateway = |gateway: &str, path: &str| {
+        format!(
+            "{}/{}",
+            gateway.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        )
+    };
+    if let Some(path) = uri.strip_prefix("ipfs://") {
+        // `ipfs://ipfs/<cid>` is a common mistake for `ipfs://<cid>`
+        let path = path.strip_prefix("ipfs/").unwrap_or(path);
+        Some(with_gateway(&config.ipfs_gateway, path))
+    } else if let Some(path) = uri.strip_prefix("ar://") {
+        Some(with_gateway(&config.arweave_gateway, path))
+    } else if uri.starts_with("https://") || uri.starts_with("http://") {
+        Some(uri.to_string())
+    } else {
+        None
+    }
+}
+
+/// Spaces out requests so no more than `requests_per_sec` are made
+struct RateLimiter {
+    interval: Duration,
+    next: Instant,
+}
+
+impl RateLimiter {
+    fn new(requests_per_sec: f64) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / requests_per_sec.max(0.001)),
+            next: Instant::now(),
+        }
+    }
+
+    async fn wait(&mut self) {
+        tokio::time::sleep_until(self.next).await;
+        self.next = std::cmp::max(self.next, Instant::now()) + self.interval;
+    }
+}
+
+pub struct UriEnricher {
+    connection_pool: PgDbPool,
+    config: UriEnricherConfig,
+    client: reqwest::Client,
+    rate_limiter: RateLimiter,
+}
+
+impl UriEnricher {
+    pub fn new(connection_pool: PgDbPool, config: UriEnricherConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(config.request_timeout)
+            .build()
+            .context("Failed to build the URI enricher's HTTP client")?;
+        Ok(Self {
+            connection_pool,
+            rate_limiter: RateLimiter::new(config.requests_per_sec),
+            config,
+            client,
+        })
+    }
+
+    /// Enriches URIs as tokens are indexed, until stopped
+    pub async fn run(mut self) {
+        loop {
+            match self.enrich_next_batch().await {
+                Ok(0) => tokio::time::sleep(self.config.poll_interval).await,
+                Ok(_) => (),
+                Err(err) => {
+                    aptos_logger::error!(error = format!("{:?}", err), "Failed to enrich URIs");
+                    tokio::time::sleep(self.config.poll_interval).await;
+                }
+            }
+        }
+    }
+
+    /// Fetches a batch of URIs that haven't been fetched (or failed long enough ago), returning how many
+    pub async fn enrich_next_batch(&mut self) -> Result<usize> {
+        let uris = self.get_pending_uris()?;
+        let mut entries = vec![];
+        for uri in uris {
+            let entry = self.fetch(uri).await;
+            URI_ENRICHER_FETCHES
+                .with_label_values(&[entry.status.as_str()])
+                .inc();
+            entries.push(entry);
+        }
+        self.insert_entries(&entries);
+        Ok(entries.len())
+    }
+
+    fn get_pending_uris(&self) -> Result<Vec<String>> {
+        #[derive(Debug, QueryableByName)]
+        struct PendingUri {
+            #[sql_type = "Text"]
+            uri: String,
+        }
+        let retry_before = chrono::Utc::now().naive_utc()
+            - chrono::Duration::from_std(self.config.retry_failed_after)?;
+        let sql = "
+            SELECT uris.uri
+            FROM (
+                SELECT uri FROM token_datas
+                UNION
+                SELECT uri FROM tokens_v2
+            ) AS uris
+            WHERE uris.uri <> '' AND NOT EXISTS (
+                SELECT 1 FROM token_metadata_cache AS cache
+                WHERE cache.uri = uris.uri AND (cache.status = $1 OR cache.fetched_at > $2)
+            )
+            LIMIT $3
+        ";
+        Ok(sql_query(sql)
+            .bind::<Text, _>(FETCH_STATUS_OK)
+            .bind::<Timestamp, _>(retry_before)
+            .bind::<BigInt, _>(self.config.batch_size)
+            .load::<PendingUri>(&get_conn_with_retry(&self.connection_pool))
+            .context("Error loading URIs to enrich")?
+            .into_iter()
+            .map(|pending| pending.uri)
+            .collect())
+    }
+
+    async fn fetch(&mut self, uri: String) -> TokenMetadataCacheModel {
+        let resolved_uri = match resolve_uri(&uri, &self.config) {
+            Some(resolved_uri) => resolved_uri,
+            None => {
+                return TokenMetadataCacheModel::error(
+                    uri,
+                    None,
+                    "Unsupported URI scheme".to_string(),
+                )
+            }
+        };
+        match self.fetch_resolved(&resolved_uri).await {
+            Ok((metadata, image_uri, image_content_type, image_size_bytes)) => {
+                TokenMetadataCacheModel {
+                    uri,
+                    resolved_uri: Some(resolved_uri),
+                    metadata,
+                    image_uri,
+                    image_content_type,
+                    image_size_bytes,
+                    status: FETCH_STATUS_OK.to_string(),
+                    error: None,
+                    fetched_at: chrono::Utc::now().naive_utc(),
+                    inserted_at: chrono::Utc::now().naive_utc(),
+                }
+            }
+            Err(err) => {
+                TokenMetadataCacheModel::error(uri, Some(resolved_uri), format!("{:#}", err))
+            }
+        }
+    }
+
+    /// Fetches a document and, if it names one, the headers of its image
+    #[allow(clippy::type_complexity)]
+    async fn fetch_resolved(
+        &mut self,
+        resolved_uri: &str,
+    ) -> Result<(
+        Option<serde_json::Value>,
+        Option<String>,
+        Option<String>,
+        Option<i64>,
+    )> {
+        self.rate_limiter.wait().await;
+        let response = self
+            .client
+            .get(resolved_uri)
+            .send()
+            .await?
+            .error_for_status()?;
+        let content_type = get_content_type(&response);
+        if content_type
+            .as_deref()
+            .map_or(false, |content_type| content_type.starts_with("image/"))
+        {
+            let size = response.content_length().map(|size| size as i64);
+            return Ok((None, Some(resolved_uri.to_string()), content_type, size));
+        }
+        if response
+            .content_length()
+            .map_or(false, |size| size > self.config.max_document_bytes)
+        {
+            bail!(
+                "Document is larger than {} bytes",
+                self.config.max_document_bytes
+            );
+        }
+        let body = response.bytes().await?;
+        if body.len() as u64 > self.config.max_document_bytes {
+            bail!(
+                "Document is larger than {} bytes",
+                self.config.max_document_bytes
+            );
+        }
+        let metadata: serde_json::Value =
+            serde_json::from_slice(&body).context("Document isn't JSON")?;
+
+        let image_uri = match metadata["image"].as_str() {
+            Some(image) => image.to_string(),
+            None => return Ok((Some(metadata), None, None, None)),
+        };
+        // The image is optional: failing to reach it doesn't fail the document
+        let (image_content_type, image_size_bytes) = match resolve_uri(&image_uri, &self.config) {
+            Some(resolved_image_uri) => {
+                self.rate_limiter.wait().await;
+                match self.client.head(&resolved_image_uri).send().await {
+                    Ok(response) if response.status().is_success() => (
+                        get_content_type(&response),
+                        response.content_length().map(|size| size as i64),
+                    ),
+                    _ => (None, None),
+                }
+            }
+            None => (None, None),
+        };
+        Ok((
+            Some(metadata),
+            Some(image_uri),
+            image_content_type,
+            image_size_bytes,
+        ))
+    }
+
+    fn insert_entries(&self, entries: &[TokenMetadataCacheModel]) {
+        use schema::token_metadata_cache::dsl;
+
+        let conn = get_conn_with_retry(&self.connection_pool);
+        insert_chunked(&conn, entries, |chunk| {
+            diesel::insert_into(schema::token_metadata_cache::table)
+                .values(chunk)
+                .on_conflict(dsl::uri)
+                .do_update()
+                .set((
+                    dsl::resolved_uri.eq(excluded(dsl::resolved_uri)),
+                    dsl::metadata.eq(excluded(dsl::metadata)),
+                    dsl::image_uri.eq(excluded(dsl::image_uri)),
+                    dsl::image_content_type.eq(excluded(dsl::image_content_type)),
+                    dsl::image_size_bytes.eq(excluded(dsl::image_size_bytes)),
+                    dsl::status.eq(excluded(dsl::status)),
+                    dsl::error.eq(excluded(dsl::error)),
+                    dsl::fetched_at.eq(excluded(dsl::fetched_at)),
+                ))
+        })
+        .expect("Error inserting row into token_metadata_cache");
+    }
+}
+
+fn get_content_type(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_uri() {
+        let config = UriEnricherConfig::default();
+        assert_eq!(
+            resolve_uri("ipfs://bafybeigdyrzt/1.json", &config).unwrap(),
+            "https://ipfs.io/ipfs/bafybeigdyrzt/1.json"
+        );
+        assert_eq!(
+            resolve_uri("ipfs://ipfs/bafybeigdyrzt", &config).unwrap(),
+            "https://ipfs.io/ipfs/bafybeigdyrzt"
+        );
+        assert_eq!(
+            resolve_uri("ar://abc", &config).unwrap(),
+            "https://arweave.net/abc"
+        );
+        assert_eq!(
+            resolve_uri(" https://aptos.dev/1.json", &config).unwrap(),
+            "https://aptos.dev/1.json"
+        );
+        assert!(resolve_uri("data:application/json,{}", &config).is_none());
+    }
+}
@@ -0,0 +1,343 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fetches transactions from the node's REST API as BCS, and decodes them into the API's JSON types with the Move
+//! VM's value annotator, the same way the node renders them: resources, events and payloads are laid out with the
+//! modules that define their types, fetched from the node (`accounts/<address>/module/<name>`) and cached, so every
+//! processor sees the same transactions as in the JSON mode, with a fraction of the bytes over the wire.
+//!
+//! Table items aren't decoded (`data` is empty): their key and value types come from the node's table info, which the
+//! REST API doesn't serve. Processors reading table items (see `TABLE_ITEM_PROCESSORS`) need the JSON mode: the
+//! builder refuses to run them with a module cache.
+
+use crate::{
+    counters::{FETCHED_TRANSACTION, UNABLE_TO_FETCH_TRANSACTION},
+    indexer::fetcher::{remove_null_bytes_from_txns, TransactionFetcherTrait},
+};
+use anyhow::{Context, Result};
+use aptos_api_types::{AsConverter, TransactionOnChainData};
+use aptos_logger::prelude::*;
+use aptos_rest_client::{error::RestError, Client as RestClient, State, Transaction};
+use aptos_state_view::StateView;
+use aptos_types::{
+    access_path::Path,
+    state_store::{state_key::StateKey, state_storage_usage::StateStorageUsage},
+    transaction::Transaction as StorageTransaction,
+};
+use aptos_vm::data_cache::IntoMoveResolver;
+use reqwest::StatusCode;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use storage_interface::DbReader;
+use tokio::runtime::Handle;
+
+/// How long to wait before fetching again, once caught up with the node or after a failure
+const POLL_INTERVAL: Duration = Duration::from_millis(300);
+/// Versions fetched at a time, as many as the REST fetcher requests at once
+const BATCH_SIZE: u16 = 500;
+
+/// Processors whose output depends on table items: the token processors (token data and ownerships are table items
+/// of `0x3::token`), and those writing write set changes as fetched
+pub const TABLE_ITEM_PROCESSORS: &[&str] = &[
+    "default_processor",
+    "duckdb_processor",
+    "kafka_processor",
+    "kinesis_processor",
+    "pubsub_processor",
+    "token_processor",
+    "token_v2_processor",
+];
+
+/// Whether the processor named `processor_name` (ex: "token_processor:backfill") reads table items
+pub fn reads_table_items(processor_name: &str) -> bool {
+    let processor_type = processor_name
+        .split_once(':')
+        .map_or(processor_name, |(processor_type, _)| processor_type);
+    TABLE_ITEM_PROCESSORS.contains(&processor_type)
+}
+
+/// Modules fetched from the node (or found missing), shared by the fetchers of all processors
+#[derive(Debug, Default)]
+pub struct ModuleCache {
+    modules: Mutex<HashMap<StateKey, Option<Vec<u8>>>>,
+}
+
+impl ModuleCache {
+    pub fn len(&self) -> usize {
+        self.modules.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forgets the modules `txns` publish or upgrade, so they're fetched again. Upgrades are compatible, so the
+    /// latest version of a module decodes values written with earlier ones.
+    fn evict_published(&self, txns: &[TransactionOnChainData]) {
+        let mut modules = self.modules.lock().unwrap();
+        for txn in txns {
+            for (state_key, _) in txn.changes.iter() {
+                if let StateKey::AccessPath(access_path) = state_key {
+                    if access_path.is_code() {
+                        modules.remove(state_key);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Serves modules from the node (through the cache) to the annotator. Its reads block, so it's only used off the
+/// async runtime.
+struct RestModuleView {
+    client: RestClient,
+    cache: Arc<ModuleCache>,
+    runtime: Handle,
+}
+
+impl StateView for RestModuleView {
+    fn get_state_value(&self, state_key: &StateKey) -> Result<Option<Vec<u8>>> {
+        let module_id = match state_key {
+            StateKey::AccessPath(access_path) => match access_path.get_path() {
+                Path::Code(module_id) => module_id,
+                // Values are laid out with modules only
+                Path::Resource(_) => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        if let Some(module) = self.cache.modules.lock().unwrap().get(state_key) {
+            return Ok(module.clone());
+        }
+        let module = match self.runtime.block_on(
+            self.client
+                .get_account_module_bcs(*module_id.address(), module_id.name().as_str()),
+        ) {
+            Ok(response) => Some(response.into_inner().to_vec()),
+            Err(RestError::Api(err)) if err.status_code == StatusCode::NOT_FOUND => None,
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to fetch module {}", module_id))
+            }
+        };
+        self.cache
+            .modules
+            .lock()
+            .unwrap()
+            .insert(state_key.clone(), module.clone());
+        Ok(module)
+    }
+
+    fn is_genesis(&self) -> bool {
+        false
+    }
+
+    fn get_usage(&self) -> Result<StateStorageUsage> {
+        Ok(StateStorageUsage::new_untracked())
+    }
+}
+
+/// The converter's access to the node's DB, only used for table info, which isn't available over REST
+struct NoTableInfo;
+
+impl DbReader for NoTableInfo {
+    fn indexer_enabled(&self) -> bool {
+        false
+    }
+}
+
+/// Decodes transactions fetched as BCS, with modules from the node
+#[derive(Debug)]
+pub struct VmDecoder {
+    client: RestClient,
+    cache: Arc<ModuleCache>,
+}
+
+impl VmDecoder {
+    pub fn new(client: RestClient, cache: Arc<ModuleCache>) -> Self {
+        Self { client, cache }
+    }
+
+    /// Up to `limit` transactions from `start_version`, as far as the node committed
+    pub async fn get_transactions(
+        &self,
+        start_version: u64,
+        limit: u16,
+    ) -> Result<Vec<Transaction>> {
+        let txns = self
+            .client
+            .get_transactions_bcs(Some(start_version), Some(limit))
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to fetch transactions from version {}",
+                    start_version
+                )
+            })?
+            .into_inner();
+        let first_timestamp = match txns.first() {
+            // Like the API, the timestamp of a transaction is its block's, which changes with each block metadata
+            Some(txn) if !matches!(txn.transaction, StorageTransaction::BlockMetadata(_)) => {
+                self.client
+                    .get_block_by_version(start_version, false)
+                    .await
+                    .with_context(|| {
+                        format!("Failed to fetch the block of version {}", start_version)
+                    })?
+                    .into_inner()
+                    .block_timestamp
+                    .0
+            }
+            Some(_) => 0,
+            None => return Ok(vec![]),
+        };
+        let view = RestModuleView {
+            client: self.client.clone(),
+            cache: self.cache.clone(),
+            runtime: Handle::current(),
+        };
+        let txns = tokio::task::spawn_blocking(move || decode(view, txns, first_timestamp))
+            .await
+            .expect("Decoding transactions panicked")?;
+        Ok(remove_null_bytes_from_txns(txns))
+    }
+}
+
+fn decode(
+    view: RestModuleView,
+    txns: Vec<TransactionOnChainData>,
+    mut timestamp: u64,
+) -> Result<Vec<Transaction>> {
+    view.cache.evict_published(&txns);
+    let resolver = view.into_move_resolver();
+    let converter = resolver.as_converter(Arc::new(NoTableInfo));
+    txns.into_iter()
+        .map(|txn| {
+            let version = txn.version;
+            if let StorageTransaction::BlockMetadata(ref block_metadata) = txn.transaction {
+                timestamp = block_metadata.timestamp_usecs();
+            }
+            converter
+                .try_into_onchain_transaction(timestamp, txn)
+                .with_context(|| format!("Failed to decode version {}", version))
+        })
+        .collect()
+}
+
+/// Fetches batches of transactions as BCS, decoding them with a `VmDecoder`
+#[derive(Debug)]
+pub struct BcsFetcher {
+    decoder: VmDecoder,
+    current_version: u64,
+    /// As of the last time the node was asked
+    highest_known_version: u64,
+}
+
+impl BcsFetcher {
+    pub fn new(client: RestClient, cache: Arc<ModuleCache>, starting_version: Option<u64>) -> Self {
+        Self {
+            decoder: VmDecoder::new(client, cache),
+            current_version: starting_version.unwrap_or(0),
+            highest_known_version: 0,
+        }
+    }
+
+    /// Whether the node has committed the current version, asking it again once past the highest known version
+    async fn has_next_version(&mut self) -> bool {
+        if self.current_version > self.highest_known_version {
+            match self.decoder.client.get_ledger_information().await {
+                Ok(state) => self.highest_known_version = state.into_inner().version,
+                Err(err) => error!(
+                    error = format!("{:?}", err),
+                    "Failed to get the node's ledger info"
+                ),
+            }
+        }
+        self.current_version <= self.highest_known_version
+    }
+}
+
+#[async_trait::async_trait]
+impl TransactionFetcherTrait for BcsFetcher {
+    /// Fetches the next batch, waiting for new versions once caught up with the node
+    async fn fetch_next_batch(&mut self) -> Vec<Transaction> {
+        loop {
+            if !self.has_next_version().await {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                continue;
+            }
+            match self
+                .decoder
+                .get_transactions(self.current_version, BATCH_SIZE)
+                .await
+            {
+                Ok(txns) if !txns.is_empty() => {
+                    FETCHED_TRANSACTION.inc();
+                    self.current_version += txns.len() as u64;
+                    return txns;
+                }
+                Ok(_) => tokio::time::sleep(POLL_INTERVAL).await,
+                Err(err) => {
+                    UNABLE_TO_FETCH_TRANSACTION.inc();
+                    error!(
+                        version = self.current_version,
+                        error = format!("{:?}", err),
+                        "Could not fetch transactions as BCS, will retry"
+                    );
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    async fn fetch_version(&self, version: u64) -> Transaction {
+        loop {
+            match self.decoder.get_transactions(version, 1).await {
+                Ok(mut txns) if !txns.is_empty() => {
+                    FETCHED_TRANSACTION.inc();
+                    return txns.remove(0);
+                }
+                Ok(_) => tokio::time::sleep(POLL_INTERVAL).await,
+                Err(err) => {
+                    UNABLE_TO_FETCH_TRANSACTION.inc();
+                    error!(
+                        version = version,
+                        error = format!("{:?}", err),
+                        "Could not fetch version as BCS, will retry"
+                    );
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    async fn fetch_ledger_info(&mut self) -> State {
+        self.decoder
+            .client
+            .get_ledger_information()
+            .await
+            .unwrap_or_else(|err| panic!("Failed to get the node's ledger info: {:?}", err))
+            .into_inner()
+    }
+
+    async fn set_version(&mut self, version: u64) {
+        self.current_version = version;
+    }
+
+    /// Nothing to start: batches are fetched when asked for
+    async fn start(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_table_items() {
+        assert!(reads_table_items("token_processor"));
+        assert!(reads_table_items("token_v2_processor:backfill"));
+        assert!(!reads_table_items("epoch_processor"));
+        assert!(!reads_table_items("token"));
+    }
+}
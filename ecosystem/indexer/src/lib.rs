@@ -4,19 +4,56 @@
 // Increase recursion limit for `serde_json::json!` macro parsing
 #![recursion_limit = "256"]
 
+#[cfg(feature = "postgres")]
 #[macro_use]
 extern crate diesel_migrations;
 
 // Need to use this for because src/schema.rs uses the macros and is autogenerated
+#[cfg(feature = "postgres")]
 #[macro_use]
 extern crate diesel;
 
+pub mod aws;
+#[cfg(feature = "neo4j")]
+pub mod bolt;
+#[cfg(feature = "postgres")]
+pub mod change_keys;
+#[cfg(feature = "postgres")]
+pub mod citus;
 pub mod counters;
+#[cfg(feature = "postgres")]
 pub mod database;
+#[cfg(feature = "duckdb")]
+pub mod duckdb;
+pub mod encryption;
+pub mod gcp;
+#[cfg(feature = "postgres")]
+pub mod importer;
 pub mod indexer;
 pub mod models;
+#[cfg(feature = "postgres")]
+pub mod online_migration;
+pub mod parquet;
 pub mod processors;
+#[cfg(feature = "profiling")]
+pub mod profiling;
+#[cfg(feature = "api")]
+pub mod queries;
+#[cfg(feature = "api")]
+pub mod read_api;
+#[cfg(feature = "postgres")]
+pub mod rollups;
+#[cfg(feature = "postgres")]
 pub mod schema;
+#[cfg(feature = "kafka")]
+pub mod schema_registry;
+pub mod secrets;
+#[cfg(feature = "postgres")]
+pub mod snapshot;
+#[cfg(test)]
+pub mod test_utils;
+#[cfg(feature = "postgres")]
+pub mod timescale;
 mod util;
 
 /// By default, skips test unless `INDEXER_DATABASE_URL` is set.
@@ -0,0 +1,161 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+#![allow(clippy::extra_unused_lifetimes)]
+#[cfg(feature = "postgres")]
+use crate::schema::{
+    account_summaries as account_summarys, account_summary_batches as account_summary_batchs,
+};
+use crate::util::u64_to_bigdecimal;
+use aptos_rest_client::aptos_api_types::Transaction as APITransaction;
+use bigdecimal::{BigDecimal, Zero};
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const COIN_REGISTER_EVENT_TYPE: &str = "0x1::account::CoinRegisterEvent";
+
+/// Per-account aggregates. Rows built from a batch hold the batch's contribution, which is added to the stored
+/// row when upserted.
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone, PartialEq)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "account_summaries"))]
+#[cfg_attr(feature = "postgres", primary_key(address))]
+pub struct AccountSummary {
+    pub address: String,
+    /// User transactions sent, including failed ones
+    pub transactions_sent: i64,
+    /// Gas fees paid (gas used * gas unit price), in octas
+    pub gas_spent: BigDecimal,
+    pub last_active_version: BigDecimal,
+    /// Coin types registered. Coin stores can't be removed, so this only grows.
+    pub coins_held: i64,
+    pub inserted_at: chrono::NaiveDateTime,
+    pub last_updated: chrono::NaiveDateTime,
+}
+
+impl AccountSummary {
+    fn new(address: String, version: u64) -> Self {
+        let now = chrono::Utc::now().naive_utc();
+        Self {
+            address,
+            transactions_sent: 0,
+            gas_spent: BigDecimal::zero(),
+            last_active_version: u64_to_bigdecimal(version),
+            coins_held: 0,
+            inserted_at: now,
+            last_updated: now,
+        }
+    }
+
+    /// The contribution of the user transactions in `transactions` to each account's summary, ordered by address
+    /// so concurrent batches upsert (and lock) rows in the same order
+    pub fn from_transactions(transactions: &[APITransaction]) -> Vec<Self> {
+        let mut summaries: BTreeMap<String, Self> = BTreeMap::new();
+        for transaction in transactions {
+            let user_txn = match transaction {
+                APITransaction::UserTransaction(user_txn) => user_txn,
+                _ => continue,
+            };
+            let version = *user_txn.info.version.inner();
+            let sender = user_txn.request.sender.to_string();
+            let summary = summaries
+                .entry(sender.clone())
+                .or_insert_with(|| Self::new(sender, version));
+            summary.transactions_sent += 1;
+            summary.gas_spent += u64_to_bigdecimal(*user_txn.info.gas_used.inner())
+                * u64_to_bigdecimal(*user_txn.request.gas_unit_price.inner());
+            summary.last_active_version = u64_to_bigdecimal(version);
+
+            for event in &user_txn.events {
+                if event.typ.to_string() == COIN_REGISTER_EVENT_TYPE {
+                    let account = event.guid.account_address.to_string();
+                    summaries
+                        .entry(account.clone())
+                        .or_insert_with(|| Self::new(account, version))
+                        .coins_held += 1;
+                }
+            }
+        }
+        summaries.into_values().collect()
+    }
+}
+
+/// A range of versions already added to `account_summaries`
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "account_summary_batches"))]
+#[cfg_attr(feature = "postgres", primary_key(start_version, end_version))]
+pub struct AccountSummaryBatch {
+    pub start_version: BigDecimal,
+    pub end_version: BigDecimal,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+impl AccountSummaryBatch {
+    pub fn new(start_version: u64, end_version: u64) -> Self {
+        Self {
+            start_version: u64_to_bigdecimal(start_version),
+            end_version: u64_to_bigdecimal(end_version),
+            inserted_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+// Prevent conflicts with other things named `AccountSummary`
+pub type AccountSummaryModel = AccountSummary;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TransactionFactory;
+    use serde_json::json;
+
+    #[test]
+    fn test_account_summaries() {
+        let mut factory = TransactionFactory::new(10);
+        let transactions = vec![
+            factory
+                .user_transaction("0xb0b")
+                .gas_used(5)
+                .event(0, COIN_REGISTER_EVENT_TYPE, json!({"type_info": {}}))
+                .build(),
+            factory.user_transaction("0xa11ce").gas_used(3).build(),
+            factory
+                .user_transaction("0xb0b")
+                .gas_used(7)
+                .event(0, COIN_REGISTER_EVENT_TYPE, json!({"type_info": {}}))
+                .build(),
+        ];
+        let summaries: Vec<_> = AccountSummary::from_transactions(&transactions)
+            .into_iter()
+            .map(|summary| {
+                (
+                    summary.address,
+                    summary.transactions_sent,
+                    summary.gas_spent.to_string(),
+                    summary.last_active_version.to_string(),
+                    summary.coins_held,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summaries,
+            vec![
+                (
+                    "0xa11ce".to_string(),
+                    1,
+                    "300".to_string(),
+                    "11".to_string(),
+                    0
+                ),
+                (
+                    "0xb0b".to_string(),
+                    2,
+                    "1200".to_string(),
+                    "12".to_string(),
+                    2
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,43 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+#![allow(clippy::extra_unused_lifetimes)]
+#[cfg(feature = "postgres")]
+use crate::schema::api_keys;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// A client of the read API, identified by a key of which only the hash is stored
+#[derive(Clone, Debug, Deserialize, FieldCount, Serialize, PartialEq)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "api_keys"))]
+#[cfg_attr(feature = "postgres", primary_key(name))]
+pub struct ApiKey {
+    pub name: String,
+    /// Hex SHA-256 of the key
+    pub key_hash: String,
+    /// NULL for the API's default limit
+    pub requests_per_minute: Option<i64>,
+    pub is_revoked: bool,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+impl ApiKey {
+    pub fn new(name: String, key: &str, requests_per_minute: Option<i64>) -> Self {
+        Self {
+            name,
+            key_hash: hash_api_key(key),
+            requests_per_minute,
+            is_revoked: false,
+            inserted_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+/// How keys are stored and looked up: keys are random, so an unsalted hash doesn't make them guessable
+pub fn hash_api_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+// Prevent conflicts with other things named `ApiKey`
+pub type ApiKeyModel = ApiKey;
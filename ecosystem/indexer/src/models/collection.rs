@@ -1,13 +1,18 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 #![allow(clippy::extra_unused_lifetimes)]
+#[cfg(feature = "postgres")]
 use crate::schema::collections;
 use crate::util::u64_to_bigdecimal;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Associations, Debug, Identifiable, Insertable, Queryable, Serialize, Clone)]
-#[diesel(table_name = "collections")]
-#[primary_key(collection_id)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[cfg_attr(
+    feature = "postgres",
+    derive(Associations, Identifiable, Insertable, Queryable)
+)]
+#[cfg_attr(feature = "postgres", diesel(table_name = "collections"))]
+#[cfg_attr(feature = "postgres", primary_key(collection_id))]
 pub struct Collection {
     pub collection_id: String,
     pub creator: String,
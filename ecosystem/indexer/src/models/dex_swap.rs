@@ -0,0 +1,234 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+#![allow(clippy::extra_unused_lifetimes)]
+use crate::models::{events::EventModel, transactions::UserTransaction};
+#[cfg(feature = "postgres")]
+use crate::schema::dex_swaps;
+use bigdecimal::{BigDecimal, Zero};
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Field names used by the common DEX swap events for the amounts of each side of the pool
+const X_IN_FIELDS: [&str; 2] = ["amount_x_in", "x_in"];
+const Y_IN_FIELDS: [&str; 2] = ["amount_y_in", "y_in"];
+const X_OUT_FIELDS: [&str; 2] = ["amount_x_out", "x_out"];
+const Y_OUT_FIELDS: [&str; 2] = ["amount_y_out", "y_out"];
+const SENDER_FIELDS: [&str; 2] = ["user", "sender"];
+
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "dex_swaps"))]
+#[cfg_attr(feature = "postgres", primary_key(event_key, sequence_number))]
+pub struct DexSwap {
+    pub event_key: String,
+    pub sequence_number: bigdecimal::BigDecimal,
+    pub transaction_hash: String,
+    pub dex_address: String,
+    pub pool: String,
+    pub sender: String,
+    pub coin_in: String,
+    pub coin_out: String,
+    pub amount_in: bigdecimal::BigDecimal,
+    pub amount_out: bigdecimal::BigDecimal,
+    pub swapped_at: chrono::NaiveDateTime,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+impl DexSwap {
+    /// Normalizes a `<dex_address>::<module>::<Name>SwapEvent<X, Y, ..>` event into a swap, if the event
+    /// was emitted by one of `dex_addresses`. The first two type arguments are taken as the pool's coins.
+    pub fn from_event(
+        event: &EventModel,
+        txn: &UserTransaction,
+        dex_addresses: &[String],
+    ) -> Option<Self> {
+        let (address, module, name, type_args) = parse_struct_tag(&event.type_)?;
+        if !name.ends_with("SwapEvent")
+            || type_args.len() < 2
+            || !dex_addresses
+                .iter()
+                .any(|dex| is_same_address(dex, address))
+        {
+            return None;
+        }
+        let (coin_x, coin_y) = (type_args[0].clone(), type_args[1].clone());
+
+        let x_in = get_amount(&event.data, &X_IN_FIELDS);
+        let y_in = get_amount(&event.data, &Y_IN_FIELDS);
+        let x_out = get_amount(&event.data, &X_OUT_FIELDS);
+        let y_out = get_amount(&event.data, &Y_OUT_FIELDS);
+        let (coin_in, coin_out, amount_in, amount_out) = if x_in > BigDecimal::zero() {
+            (coin_x, coin_y, x_in, y_out)
+        } else if y_in > BigDecimal::zero() {
+            (coin_y, coin_x, y_in, x_out)
+        } else {
+            return None;
+        };
+
+        let sender = SENDER_FIELDS
+            .iter()
+            .find_map(|field| event.data.get(*field).and_then(|v| v.as_str()))
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| txn.sender.clone());
+
+        Some(Self {
+            event_key: event.key.clone(),
+            sequence_number: event.sequence_number.clone(),
+            transaction_hash: event.transaction_hash.clone(),
+            dex_address: address.to_string(),
+            pool: format!("{}::{}<{}>", address, module, type_args.join(", ")),
+            sender,
+            coin_in,
+            coin_out,
+            amount_in,
+            amount_out,
+            swapped_at: txn.timestamp,
+            inserted_at: chrono::Utc::now().naive_utc(),
+        })
+    }
+}
+
+/// Splits a move struct tag such as `0x1::coin::CoinStore<0x1::aptos_coin::AptosCoin>` into
+/// (address, module, name, type arguments). Nested generics are kept intact within their argument.
+pub fn parse_struct_tag(type_str: &str) -> Option<(&str, &str, &str, Vec<String>)> {
+    let (base, generics) = match type_str.find('<') {
+        Some(ind) => (&type_str[..ind], type_str[ind + 1..].strip_suffix('>')?),
+        None => (type_str, ""),
+    };
+    let mut parts = base.splitn(3, "::");
+    let address = parts.next()?;
+    let module = parts.next()?;
+    let name = parts.next()?;
+
+    let mut type_args = vec![];
+    let mut depth = 0;
+    let mut current = String::new();
+    for c in generics.chars() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ',' if depth == 0 => {
+                type_args.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => (),
+        }
+        current.push(c);
+    }
+    if !current.trim().is_empty() {
+        type_args.push(current.trim().to_string());
+    }
+    Some((address, module, name, type_args))
+}
+
+/// Compares two hex addresses ignoring case and leading zeroes, e.g. `0x01` and `0x1`
+fn is_same_address(a: &str, b: &str) -> bool {
+    let trim = |addr: &str| {
+        addr.trim_start_matches("0x")
+            .trim_start_matches('0')
+            .to_lowercase()
+    };
+    trim(a) == trim(b)
+}
+
+/// Amounts are serialized as strings by the API; missing or malformed fields are treated as zero
+fn get_amount(data: &serde_json::Value, fields: &[&str]) -> BigDecimal {
+    fields
+        .iter()
+        .find_map(|field| data.get(*field))
+        .and_then(|value| match value {
+            serde_json::Value::String(s) => BigDecimal::from_str(s).ok(),
+            serde_json::Value::Number(n) => BigDecimal::from_str(&n.to_string()).ok(),
+            _ => None,
+        })
+        .unwrap_or_else(BigDecimal::zero)
+}
+
+// Prevent conflicts with other things named `DexSwap`
+pub type DexSwapModel = DexSwap;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::u64_to_bigdecimal;
+    use serde_json::json;
+
+    fn user_txn() -> UserTransaction {
+        let now = chrono::Utc::now().naive_utc();
+        UserTransaction {
+            hash: "0xabc".to_string(),
+            signature: serde_json::Value::Null,
+            sender: "0xfeed".to_string(),
+            sequence_number: u64_to_bigdecimal(0),
+            max_gas_amount: u64_to_bigdecimal(0),
+            expiration_timestamp_secs: now,
+            gas_unit_price: u64_to_bigdecimal(0),
+            timestamp: now,
+            inserted_at: now,
+        }
+    }
+
+    fn event(type_: &str, data: serde_json::Value) -> EventModel {
+        EventModel {
+            transaction_hash: "0xabc".to_string(),
+            key: "0x0100".to_string(),
+            sequence_number: u64_to_bigdecimal(3),
+            type_: type_.to_string(),
+            data,
+            inserted_at: chrono::Utc::now().naive_utc(),
+            event_index: 0,
+            transaction_timestamp: chrono::Utc::now().naive_utc(),
+        }
+    }
+
+    #[test]
+    fn test_parse_struct_tag() {
+        let (address, module, name, type_args) = parse_struct_tag(
+            "0xa::swap::SwapEvent<0x1::aptos_coin::AptosCoin, 0xb::lp::LP<0x1::a::A, 0x1::b::B>>",
+        )
+        .unwrap();
+        assert_eq!(address, "0xa");
+        assert_eq!(module, "swap");
+        assert_eq!(name, "SwapEvent");
+        assert_eq!(
+            type_args,
+            vec![
+                "0x1::aptos_coin::AptosCoin".to_string(),
+                "0xb::lp::LP<0x1::a::A, 0x1::b::B>".to_string()
+            ]
+        );
+        assert!(parse_struct_tag("0x1::coin").is_none());
+    }
+
+    #[test]
+    fn test_swap_from_event() {
+        let dexes = vec!["0x0a".to_string()];
+        let swap = DexSwap::from_event(
+            &event(
+                "0xa::swap::SwapEvent<0x1::aptos_coin::AptosCoin, 0xb::usdc::USDC>",
+                json!({"amount_x_in": "0", "amount_y_in": "500", "amount_x_out": "20", "amount_y_out": "0"}),
+            ),
+            &user_txn(),
+            &dexes,
+        )
+        .unwrap();
+        assert_eq!(swap.coin_in, "0xb::usdc::USDC");
+        assert_eq!(swap.coin_out, "0x1::aptos_coin::AptosCoin");
+        assert_eq!(swap.amount_in, u64_to_bigdecimal(500));
+        assert_eq!(swap.amount_out, u64_to_bigdecimal(20));
+        assert_eq!(swap.sender, "0xfeed");
+
+        // Not one of the configured DEXes
+        assert!(DexSwap::from_event(
+            &event(
+                "0xc::swap::SwapEvent<0x1::aptos_coin::AptosCoin, 0xb::usdc::USDC>",
+                json!({"x_in": "1", "y_out": "1"}),
+            ),
+            &user_txn(),
+            &dexes,
+        )
+        .is_none());
+    }
+}
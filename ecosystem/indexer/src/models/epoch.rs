@@ -0,0 +1,187 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+#![allow(clippy::extra_unused_lifetimes)]
+use crate::models::{
+    events::EventModel,
+    transactions::{BlockMetadataTransaction, TransactionModel, UserTransaction},
+    write_set_changes::WriteSetChangeModel,
+};
+#[cfg(feature = "postgres")]
+use crate::schema::{epochs, validator_set_snapshots};
+use bigdecimal::{BigDecimal, Zero};
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, str::FromStr};
+
+pub const NEW_EPOCH_EVENT_TYPE: &str = "0x1::reconfiguration::NewEpochEvent";
+pub const VALIDATOR_SET_TYPE: &str = "0x1::stake::ValidatorSet";
+
+/// Lists of `0x1::stake::ValidatorSet` and the status recorded for the validators in them
+const VALIDATOR_SET_LISTS: [(&str, &str); 3] = [
+    ("active_validators", "active"),
+    ("pending_active", "pending_active"),
+    ("pending_inactive", "pending_inactive"),
+];
+
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "epochs"))]
+#[cfg_attr(feature = "postgres", primary_key(epoch))]
+pub struct Epoch {
+    pub epoch: BigDecimal,
+    pub transaction_version: BigDecimal,
+    pub transaction_hash: String,
+    pub started_at: chrono::NaiveDateTime,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+/// A validator in the validator set as of the start of an epoch
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "validator_set_snapshots"))]
+#[cfg_attr(feature = "postgres", primary_key(epoch, validator_address))]
+pub struct ValidatorSetSnapshot {
+    pub epoch: BigDecimal,
+    pub validator_address: String,
+    /// One of `active`, `pending_active` or `pending_inactive`
+    pub status: String,
+    pub voting_power: BigDecimal,
+    pub validator_index: Option<i64>,
+    pub consensus_pubkey: Option<String>,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+impl Epoch {
+    /// Epochs started (and the validator sets they started with) by transactions emitting a `NewEpochEvent`.
+    /// The validator set is read from the `0x1::stake::ValidatorSet` written by the same transaction.
+    pub fn from_transactions(
+        txns: &[TransactionModel],
+        user_txns: &[UserTransaction],
+        bm_txns: &[BlockMetadataTransaction],
+        events: &[EventModel],
+        write_set_changes: &[WriteSetChangeModel],
+    ) -> (Vec<Self>, Vec<ValidatorSetSnapshot>) {
+        let versions: HashMap<&str, &BigDecimal> = txns
+            .iter()
+            .map(|txn| (txn.hash.as_str(), &txn.version))
+            .collect();
+        let timestamps: HashMap<&str, chrono::NaiveDateTime> = user_txns
+            .iter()
+            .map(|txn| (txn.hash.as_str(), txn.timestamp))
+            .chain(bm_txns.iter().map(|txn| (txn.hash.as_str(), txn.timestamp)))
+            .collect();
+
+        let mut epochs = vec![];
+        let mut snapshots = vec![];
+        for event in events.iter().filter(|e| e.type_ == NEW_EPOCH_EVENT_TYPE) {
+            let epoch = match get_u64_string(&event.data, "epoch") {
+                Some(epoch) => epoch,
+                None => continue,
+            };
+            let transaction_version = match versions.get(event.transaction_hash.as_str()) {
+                Some(version) => (*version).clone(),
+                None => continue,
+            };
+            epochs.push(Self {
+                epoch: epoch.clone(),
+                transaction_version,
+                transaction_hash: event.transaction_hash.clone(),
+                // Genesis has no timestamp
+                started_at: timestamps
+                    .get(event.transaction_hash.as_str())
+                    .copied()
+                    .unwrap_or_else(|| chrono::NaiveDateTime::from_timestamp(0, 0)),
+                inserted_at: chrono::Utc::now().naive_utc(),
+            });
+            if let Some(validator_set) = write_set_changes.iter().find(|wsc| {
+                wsc.transaction_hash == event.transaction_hash
+                    && wsc.type_ == "write_resource"
+                    && wsc.data["type"] == VALIDATOR_SET_TYPE
+            }) {
+                snapshots.extend(ValidatorSetSnapshot::from_validator_set(
+                    &epoch,
+                    &validator_set.data["data"],
+                ));
+            }
+        }
+        (epochs, snapshots)
+    }
+}
+
+impl ValidatorSetSnapshot {
+    /// Every validator in the data of a `0x1::stake::ValidatorSet` resource
+    pub fn from_validator_set(epoch: &BigDecimal, validator_set: &serde_json::Value) -> Vec<Self> {
+        let mut snapshots = vec![];
+        for (list, status) in VALIDATOR_SET_LISTS {
+            let validators = match validator_set[list].as_array() {
+                Some(validators) => validators,
+                None => continue,
+            };
+            for validator in validators {
+                let validator_address = match validator["addr"].as_str() {
+                    Some(addr) => addr.to_string(),
+                    None => continue,
+                };
+                snapshots.push(Self {
+                    epoch: epoch.clone(),
+                    validator_address,
+                    status: status.to_string(),
+                    voting_power: get_u64_string(validator, "voting_power")
+                        .unwrap_or_else(BigDecimal::zero),
+                    validator_index: validator["config"]["validator_index"]
+                        .as_str()
+                        .and_then(|index| index.parse().ok()),
+                    consensus_pubkey: validator["config"]["consensus_pubkey"]
+                        .as_str()
+                        .map(|pubkey| pubkey.to_string()),
+                    inserted_at: chrono::Utc::now().naive_utc(),
+                });
+            }
+        }
+        snapshots
+    }
+}
+
+/// u64s are serialized as strings by the API
+fn get_u64_string(data: &serde_json::Value, field: &str) -> Option<BigDecimal> {
+    data.get(field)
+        .and_then(|value| value.as_str())
+        .and_then(|value| BigDecimal::from_str(value).ok())
+}
+
+// Prevent conflicts with other things named `Epoch`
+pub type EpochModel = Epoch;
+pub type ValidatorSetSnapshotModel = ValidatorSetSnapshot;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::u64_to_bigdecimal;
+    use serde_json::json;
+
+    #[test]
+    fn test_validator_set_snapshot() {
+        let validator_set = json!({
+            "consensus_scheme": 0,
+            "active_validators": [{
+                "addr": "0xa",
+                "voting_power": "100",
+                "config": {"consensus_pubkey": "0xab", "validator_index": "0"},
+            }],
+            "pending_active": [],
+            "pending_inactive": [{
+                "addr": "0xb",
+                "voting_power": "50",
+                "config": {"consensus_pubkey": "0xcd", "validator_index": "1"},
+            }],
+        });
+        let snapshots =
+            ValidatorSetSnapshot::from_validator_set(&u64_to_bigdecimal(7), &validator_set);
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].validator_address, "0xa");
+        assert_eq!(snapshots[0].status, "active");
+        assert_eq!(snapshots[0].voting_power, u64_to_bigdecimal(100));
+        assert_eq!(snapshots[1].status, "pending_inactive");
+        assert_eq!(snapshots[1].validator_index, Some(1));
+    }
+}
@@ -1,30 +1,50 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 #![allow(clippy::extra_unused_lifetimes)]
+#[cfg(feature = "postgres")]
 use crate::{models::transactions::Transaction, schema::events};
 use aptos_rest_client::aptos_api_types::Event as APIEvent;
 use bigdecimal::{BigDecimal, FromPrimitive};
 use field_count::FieldCount;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Associations, Debug, FieldCount, Identifiable, Insertable, Queryable, Serialize)]
-#[diesel(table_name = "events")]
-#[belongs_to(Transaction, foreign_key = "transaction_hash")]
-#[primary_key(key, sequence_number)]
+#[derive(Debug, Deserialize, FieldCount, Serialize)]
+#[cfg_attr(
+    feature = "postgres",
+    derive(Associations, Identifiable, Insertable, Queryable)
+)]
+#[cfg_attr(feature = "postgres", diesel(table_name = "events"))]
+#[cfg_attr(
+    feature = "postgres",
+    belongs_to(Transaction, foreign_key = "transaction_hash")
+)]
+#[cfg_attr(feature = "postgres", primary_key(key, sequence_number))]
 pub struct Event {
     pub transaction_hash: String,
     pub key: String,
     pub sequence_number: bigdecimal::BigDecimal,
-    #[diesel(column_name = type)]
+    #[cfg_attr(feature = "postgres", diesel(column_name = type))]
+    #[serde(rename = "type")]
     pub type_: String,
     pub data: serde_json::Value,
 
     // Default time columns
     pub inserted_at: chrono::NaiveDateTime,
+    /// Position of the event within its transaction. NULL for the events indexed before it was recorded, until their
+    /// versions are re-processed.
+    pub event_index: Option<i64>,
+    /// Timestamp of the emitting transaction (the epoch for genesis), so filtering by time doesn't need a join. NULL
+    /// for the events indexed before it was recorded, until their versions are re-processed.
+    pub transaction_timestamp: Option<chrono::NaiveDateTime>,
 }
 
 impl Event {
-    pub fn from_event(transaction_hash: String, event: &APIEvent) -> Self {
+    pub fn from_event(
+        transaction_hash: String,
+        event_index: usize,
+        transaction_timestamp: chrono::NaiveDateTime,
+        event: &APIEvent,
+    ) -> Self {
         let event_key: aptos_types::event::EventKey = event.guid.into();
         Event {
             transaction_hash,
@@ -34,17 +54,31 @@ impl Event {
             type_: event.typ.to_string(),
             data: event.data.clone(),
             inserted_at: chrono::Utc::now().naive_utc(),
+            event_index: Some(event_index as i64),
+            transaction_timestamp: Some(transaction_timestamp),
         }
     }
 
-    pub fn from_events(transaction_hash: String, events: &[APIEvent]) -> Option<Vec<Self>> {
+    pub fn from_events(
+        transaction_hash: String,
+        transaction_timestamp: chrono::NaiveDateTime,
+        events: &[APIEvent],
+    ) -> Option<Vec<Self>> {
         if events.is_empty() {
             return None;
         }
         Some(
             events
                 .iter()
-                .map(|event| Self::from_event(transaction_hash.clone(), event))
+                .enumerate()
+                .map(|(index, event)| {
+                    Self::from_event(
+                        transaction_hash.clone(),
+                        index,
+                        transaction_timestamp,
+                        event,
+                    )
+                })
                 .collect::<Vec<EventModel>>(),
         )
     }
@@ -0,0 +1,368 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+#![allow(clippy::extra_unused_lifetimes)]
+#[cfg(feature = "postgres")]
+use crate::schema::{
+    fungible_asset_activities as fungible_asset_activitys,
+    fungible_asset_balance_history as fungible_asset_balance_historys, fungible_asset_balances,
+    fungible_asset_metadata as fungible_asset_metadatas,
+};
+use crate::{
+    models::{object::OBJECT_CORE_TYPE, transactions::parse_timestamp},
+    util::u64_to_bigdecimal,
+};
+use aptos_rest_client::aptos_api_types::{
+    Event as APIEvent, Transaction as APITransaction, WriteResource,
+    WriteSetChange as APIWriteSetChange,
+};
+use bigdecimal::{BigDecimal, Zero};
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+};
+
+pub const FUNGIBLE_ASSET_METADATA_TYPE: &str = "0x1::fungible_asset::Metadata";
+pub const FUNGIBLE_STORE_TYPE: &str = "0x1::fungible_asset::FungibleStore";
+pub const DEPOSIT_EVENT_TYPE: &str = "0x1::fungible_asset::DepositEvent";
+pub const WITHDRAW_EVENT_TYPE: &str = "0x1::fungible_asset::WithdrawEvent";
+pub const FROZEN_EVENT_TYPE: &str = "0x1::fungible_asset::FrozenEvent";
+
+/// A fungible asset, identified by the address of its `0x1::fungible_asset::Metadata` object
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "fungible_asset_metadata"))]
+#[cfg_attr(feature = "postgres", primary_key(asset_type))]
+pub struct FungibleAssetMetadata {
+    pub asset_type: String,
+    /// Owner of the metadata object when it was created
+    pub creator_address: Option<String>,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: i64,
+    pub icon_uri: String,
+    pub project_uri: String,
+    pub transaction_version: BigDecimal,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+/// The current balance of each `0x1::fungible_asset::FungibleStore`
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "fungible_asset_balances"))]
+#[cfg_attr(feature = "postgres", primary_key(storage_id))]
+pub struct FungibleAssetBalance {
+    /// Address of the store object
+    pub storage_id: String,
+    /// NULL until a transaction writing the store's `ObjectCore` is processed
+    pub owner_address: Option<String>,
+    pub asset_type: String,
+    pub amount: BigDecimal,
+    pub is_frozen: bool,
+    pub last_transaction_version: BigDecimal,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+/// The balance of a `0x1::fungible_asset::FungibleStore` after a transaction which wrote it
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(
+    feature = "postgres",
+    diesel(table_name = "fungible_asset_balance_history")
+)]
+#[cfg_attr(feature = "postgres", primary_key(storage_id, transaction_version))]
+pub struct FungibleAssetBalanceHistory {
+    pub storage_id: String,
+    pub transaction_version: BigDecimal,
+    pub asset_type: String,
+    pub amount: BigDecimal,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+impl From<&FungibleAssetBalance> for FungibleAssetBalanceHistory {
+    fn from(balance: &FungibleAssetBalance) -> Self {
+        Self {
+            storage_id: balance.storage_id.clone(),
+            transaction_version: balance.last_transaction_version.clone(),
+            asset_type: balance.asset_type.clone(),
+            amount: balance.amount.clone(),
+            inserted_at: balance.inserted_at,
+        }
+    }
+}
+
+/// A deposit into, withdrawal from or freezing of a `0x1::fungible_asset::FungibleStore`
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "fungible_asset_activities"))]
+#[cfg_attr(feature = "postgres", primary_key(transaction_version, event_index))]
+pub struct FungibleAssetActivity {
+    pub transaction_version: BigDecimal,
+    pub event_index: i64,
+    pub storage_id: String,
+    pub owner_address: Option<String>,
+    pub asset_type: Option<String>,
+    #[cfg_attr(feature = "postgres", diesel(column_name = type))]
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// NULL for freezes
+    pub amount: Option<BigDecimal>,
+    /// NULL for deposits and withdrawals
+    pub is_frozen: Option<bool>,
+    pub transaction_timestamp: chrono::NaiveDateTime,
+    pub inserted_at: chrono::NaiveDateTime,
+    /// USD value of the amount when it was indexed, if a price provider is configured and knows the asset
+    pub amount_usd: Option<BigDecimal>,
+}
+
+impl FungibleAssetActivity {
+    /// The assets created, stores written and activities of `transactions`. Balances are the latest of each store
+    /// in the batch, with the balance history holding every write, and `transactions` must be in version order.
+    pub fn from_transactions(
+        transactions: &[APITransaction],
+    ) -> (
+        Vec<FungibleAssetMetadata>,
+        Vec<FungibleAssetBalance>,
+        Vec<FungibleAssetBalanceHistory>,
+        Vec<Self>,
+    ) {
+        let mut metadata = vec![];
+        let mut balances: BTreeMap<String, FungibleAssetBalance> = BTreeMap::new();
+        let mut balance_history = vec![];
+        let mut activities = vec![];
+        for transaction in transactions {
+            let info = match transaction.transaction_info() {
+                Ok(info) => info,
+                Err(_) => continue,
+            };
+            let version = *info.version.inner();
+            let (txn_metadata, txn_balances) = parse_write_set(version, &info.changes);
+            if let APITransaction::UserTransaction(user_txn) = transaction {
+                activities.extend(Self::from_events(
+                    version,
+                    parse_timestamp(user_txn.timestamp, user_txn.info.version),
+                    &user_txn.events,
+                    &txn_balances,
+                ));
+            }
+            metadata.extend(txn_metadata);
+            balance_history.extend(txn_balances.values().map(FungibleAssetBalanceHistory::from));
+            for (storage_id, mut balance) in txn_balances {
+                // A store's `ObjectCore` is only written when it's created or transferred
+                if balance.owner_address.is_none() {
+                    balance.owner_address = balances
+                        .get(&storage_id)
+                        .and_then(|previous| previous.owner_address.clone());
+                }
+                balances.insert(storage_id, balance);
+            }
+        }
+        (
+            metadata,
+            balances.into_values().collect(),
+            balance_history,
+            activities,
+        )
+    }
+
+    /// Activities of the fungible asset events of a transaction, with the asset and owner taken from the stores
+    /// it wrote
+    pub fn from_events(
+        transaction_version: u64,
+        transaction_timestamp: chrono::NaiveDateTime,
+        events: &[APIEvent],
+        balances: &HashMap<String, FungibleAssetBalance>,
+    ) -> Vec<Self> {
+        events
+            .iter()
+            .enumerate()
+            .filter_map(|(index, event)| {
+                let type_ = event.typ.to_string();
+                let (amount, is_frozen) = match type_.as_str() {
+                    DEPOSIT_EVENT_TYPE | WITHDRAW_EVENT_TYPE => (
+                        Some(
+                            event.data["amount"]
+                                .as_str()
+                                .and_then(|amount| BigDecimal::from_str(amount).ok())?,
+                        ),
+                        None,
+                    ),
+                    FROZEN_EVENT_TYPE => (None, Some(event.data["frozen"].as_bool()?)),
+                    _ => return None,
+                };
+                let storage_id = event.guid.account_address.to_string();
+                let balance = balances.get(&storage_id);
+                Some(Self {
+                    transaction_version: u64_to_bigdecimal(transaction_version),
+                    event_index: index as i64,
+                    owner_address: balance.and_then(|balance| balance.owner_address.clone()),
+                    asset_type: balance.map(|balance| balance.asset_type.clone()),
+                    storage_id,
+                    type_,
+                    amount,
+                    is_frozen,
+                    transaction_timestamp,
+                    inserted_at: chrono::Utc::now().naive_utc(),
+                    amount_usd: None,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The assets created and stores written by a transaction's write set, with owners taken from the `ObjectCore`s
+/// it wrote. Deleted stores are skipped, since a store must be empty to be deleted.
+pub(crate) fn parse_write_set(
+    transaction_version: u64,
+    changes: &[APIWriteSetChange],
+) -> (
+    Vec<FungibleAssetMetadata>,
+    HashMap<String, FungibleAssetBalance>,
+) {
+    let mut owners = HashMap::new();
+    let mut metadata = vec![];
+    let mut balances = HashMap::new();
+    for change in changes {
+        let (address, data) = match change {
+            APIWriteSetChange::WriteResource(WriteResource { address, data, .. }) => {
+                (address.to_string(), data)
+            }
+            _ => continue,
+        };
+        let value =
+            serde_json::to_value(&data.data).expect("Should be able to parse write resource data");
+        match data.typ.to_string().as_str() {
+            OBJECT_CORE_TYPE => {
+                if let Some(owner) = value["owner"].as_str() {
+                    owners.insert(address, owner.to_string());
+                }
+            }
+            FUNGIBLE_ASSET_METADATA_TYPE => metadata.push(FungibleAssetMetadata {
+                asset_type: address,
+                creator_address: None,
+                name: value["name"].as_str().unwrap_or_default().to_string(),
+                symbol: value["symbol"].as_str().unwrap_or_default().to_string(),
+                decimals: value["decimals"].as_i64().unwrap_or_default(),
+                icon_uri: value["icon_uri"].as_str().unwrap_or_default().to_string(),
+                project_uri: value["project_uri"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                transaction_version: u64_to_bigdecimal(transaction_version),
+                inserted_at: chrono::Utc::now().naive_utc(),
+            }),
+            FUNGIBLE_STORE_TYPE => {
+                let asset_type = match value["metadata"]["inner"].as_str() {
+                    Some(asset_type) => asset_type.to_string(),
+                    None => continue,
+                };
+                balances.insert(
+                    address.clone(),
+                    FungibleAssetBalance {
+                        storage_id: address,
+                        owner_address: None,
+                        asset_type,
+                        amount: value["balance"]
+                            .as_str()
+                            .and_then(|balance| BigDecimal::from_str(balance).ok())
+                            .unwrap_or_else(BigDecimal::zero),
+                        is_frozen: value["frozen"].as_bool().unwrap_or_default(),
+                        last_transaction_version: u64_to_bigdecimal(transaction_version),
+                        inserted_at: chrono::Utc::now().naive_utc(),
+                    },
+                );
+            }
+            _ => (),
+        }
+    }
+    for asset in &mut metadata {
+        asset.creator_address = owners.get(&asset.asset_type).cloned();
+    }
+    for (storage_id, balance) in &mut balances {
+        balance.owner_address = owners.get(storage_id).cloned();
+    }
+    (metadata, balances)
+}
+
+// Prevent conflicts with other things named `FungibleAssetActivity`
+pub type FungibleAssetMetadataModel = FungibleAssetMetadata;
+pub type FungibleAssetBalanceModel = FungibleAssetBalance;
+pub type FungibleAssetBalanceHistoryModel = FungibleAssetBalanceHistory;
+pub type FungibleAssetActivityModel = FungibleAssetActivity;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn write_resource(address: &str, typ: &str, data: serde_json::Value) -> APIWriteSetChange {
+        serde_json::from_value(json!({
+            "type": "write_resource",
+            "address": address,
+            "state_key_hash": "0x1234",
+            "data": {"type": typ, "data": data},
+        }))
+        .unwrap()
+    }
+
+    fn event(store: &str, typ: &str, data: serde_json::Value) -> APIEvent {
+        serde_json::from_value(json!({
+            "key": "0x0400000000000000000000000000000000000000000000000000000000000000000000000000b0b5",
+            "guid": {"creation_number": "4", "account_address": store},
+            "sequence_number": "0",
+            "type": typ,
+            "data": data,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_fungible_asset_write_set() {
+        let changes = vec![
+            write_resource(
+                "0xa55e7",
+                FUNGIBLE_ASSET_METADATA_TYPE,
+                json!({"name": "Token", "symbol": "TOK", "decimals": 8, "icon_uri": "", "project_uri": ""}),
+            ),
+            write_resource(
+                "0xa55e7",
+                OBJECT_CORE_TYPE,
+                json!({"allow_ungated_transfer": false, "guid_creation_num": "1125899906842625", "owner": "0xc0ffee"}),
+            ),
+            write_resource(
+                "0xb0b5",
+                FUNGIBLE_STORE_TYPE,
+                json!({"metadata": {"inner": "0xa55e7"}, "balance": "100", "frozen": false}),
+            ),
+            write_resource(
+                "0xb0b5",
+                OBJECT_CORE_TYPE,
+                json!({"allow_ungated_transfer": false, "guid_creation_num": "1125899906842625", "owner": "0xb0b"}),
+            ),
+        ];
+        let (metadata, balances) = parse_write_set(5, &changes);
+        assert_eq!(metadata.len(), 1);
+        assert_eq!(metadata[0].asset_type, "0xa55e7");
+        assert_eq!(metadata[0].creator_address, Some("0xc0ffee".to_string()));
+        assert_eq!(metadata[0].decimals, 8);
+        let balance = &balances["0xb0b5"];
+        assert_eq!(balance.owner_address, Some("0xb0b".to_string()));
+        assert_eq!(balance.asset_type, "0xa55e7");
+        assert_eq!(balance.amount, u64_to_bigdecimal(100));
+
+        let now = chrono::Utc::now().naive_utc();
+        let events = vec![
+            event("0xb0b5", DEPOSIT_EVENT_TYPE, json!({"amount": "100"})),
+            event("0xb0b5", "0x1::coin::DepositEvent", json!({"amount": "1"})),
+            event("0xb0b5", FROZEN_EVENT_TYPE, json!({"frozen": true})),
+        ];
+        let activities = FungibleAssetActivity::from_events(5, now, &events, &balances);
+        assert_eq!(activities.len(), 2);
+        assert_eq!(activities[0].amount, Some(u64_to_bigdecimal(100)));
+        assert_eq!(activities[0].asset_type, Some("0xa55e7".to_string()));
+        assert_eq!(activities[0].owner_address, Some("0xb0b".to_string()));
+        assert_eq!(activities[1].event_index, 2);
+        assert_eq!(activities[1].is_frozen, Some(true));
+    }
+}
@@ -0,0 +1,774 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! JSON schema of the models' serde representation, so sinks and the read API emit a documented shape.
+//! Every field keeps its column's name (`type_` is serialized as `type`), numerics are decimal strings and
+//! timestamps are UTC without an offset.
+
+use crate::models::{
+    account_summary::{AccountSummary, AccountSummaryBatch},
+    api_key::ApiKey,
+    collection::Collection,
+    dex_swap::DexSwap,
+    epoch::{Epoch, ValidatorSetSnapshot},
+    events::Event,
+    fungible_asset::{
+        FungibleAssetActivity, FungibleAssetBalance, FungibleAssetBalanceHistory,
+        FungibleAssetMetadata,
+    },
+    ledger_info::{LedgerInfo, LedgerInfoHistory},
+    metadata::Metadata,
+    object::{CurrentObjectOwnership, Object},
+    online_migration::OnlineMigration,
+    operator_audit_log::OperatorAction,
+    outbox::OutboxMessage,
+    ownership::Ownership,
+    raw_transactions::RawTransaction,
+    rollups::{
+        EventTypeSeen, FunctionReliabilityStat, HourlyActiveAccount, HourlyActivityRollup,
+        MinuteTransactionRollup, ModuleDailyStat, RollupRange,
+    },
+    token::TokenData,
+    token_metadata_cache::TokenMetadataCache,
+    token_property::TokenProperty,
+    token_v2::{CollectionV2, CurrentTokenOwnershipV2, TokenActivityV2, TokenV2},
+    top_holder::TopHolder,
+    transactions::{BlockMetadataTransaction, Transaction, UserTransaction},
+    transfer_edge::TransferEdge,
+    txn_latency_stat::{TxnLatencyStat, TxnLatencyStatBatch},
+    write_set_changes::WriteSetChange,
+};
+use bigdecimal::BigDecimal;
+use chrono::{NaiveDate, NaiveDateTime};
+use serde_json::{json, Value};
+
+/// JSON schema of a field type's serde representation
+pub trait JsonSchemaType {
+    fn json_schema() -> Value;
+
+    fn is_optional() -> bool {
+        false
+    }
+}
+
+impl JsonSchemaType for String {
+    fn json_schema() -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl JsonSchemaType for bool {
+    fn json_schema() -> Value {
+        json!({ "type": "boolean" })
+    }
+}
+
+impl JsonSchemaType for i64 {
+    fn json_schema() -> Value {
+        json!({ "type": "integer" })
+    }
+}
+
+impl JsonSchemaType for BigDecimal {
+    fn json_schema() -> Value {
+        json!({ "type": "string", "pattern": "^-?[0-9]+(\\.[0-9]+)?$" })
+    }
+}
+
+impl JsonSchemaType for Vec<u8> {
+    fn json_schema() -> Value {
+        json!({ "type": "array", "items": { "type": "integer", "minimum": 0, "maximum": 255 } })
+    }
+}
+
+impl JsonSchemaType for NaiveDate {
+    fn json_schema() -> Value {
+        json!({ "type": "string", "format": "date" })
+    }
+}
+
+impl JsonSchemaType for NaiveDateTime {
+    fn json_schema() -> Value {
+        json!({
+            "type": "string",
+            "description": "UTC timestamp without an offset, ex: 2022-09-10T12:00:00.123456",
+        })
+    }
+}
+
+impl JsonSchemaType for Value {
+    fn json_schema() -> Value {
+        json!({})
+    }
+}
+
+impl<T: JsonSchemaType> JsonSchemaType for Option<T> {
+    fn json_schema() -> Value {
+        json!({ "anyOf": [T::json_schema(), { "type": "null" }] })
+    }
+
+    fn is_optional() -> bool {
+        true
+    }
+}
+
+macro_rules! field_name {
+    ($field:ident) => {
+        stringify!($field)
+    };
+    ($field:ident, $name:literal) => {
+        $name
+    };
+}
+
+/// Builds the schema of a model stored in `$table`. Listing a field that doesn't exist, with the wrong type, or
+/// leaving one out fails to compile, so schemas can't drift from the models.
+macro_rules! model_schema {
+    ($table:literal, $model:ident { $($field:ident $(as $name:literal)?: $ty:ty),* $(,)? }) => {{
+        #[allow(dead_code)]
+        fn check_fields(model: $model) {
+            let $model { $($field),* } = model;
+            $(let _: $ty = $field;)*
+        }
+
+        let mut properties = serde_json::Map::new();
+        let mut required = vec![];
+        $(
+            properties.insert(
+                field_name!($field $(, $name)?).to_string(),
+                <$ty as JsonSchemaType>::json_schema(),
+            );
+            if !<$ty as JsonSchemaType>::is_optional() {
+                required.push(field_name!($field $(, $name)?));
+            }
+        )*
+        (
+            stringify!($model),
+            json!({
+                "title": stringify!($model),
+                "description": concat!("A row of the `", $table, "` table"),
+                "type": "object",
+                "properties": properties,
+                "required": required,
+                "additionalProperties": false,
+            }),
+        )
+    }};
+}
+
+/// JSON schema (draft 7) of every model, under `definitions`
+pub fn schema_json() -> Value {
+    let definitions: serde_json::Map<String, Value> = vec![
+        model_schema!(
+            "account_summaries",
+            AccountSummary {
+                address: String,
+                transactions_sent: i64,
+                gas_spent: BigDecimal,
+                last_active_version: BigDecimal,
+                coins_held: i64,
+                inserted_at: NaiveDateTime,
+                last_updated: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "account_summary_batches",
+            AccountSummaryBatch {
+                start_version: BigDecimal,
+                end_version: BigDecimal,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "api_keys",
+            ApiKey {
+                name: String,
+                key_hash: String,
+                requests_per_minute: Option<i64>,
+                is_revoked: bool,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "block_metadata_transactions",
+            BlockMetadataTransaction {
+                hash: String,
+                id: String,
+                round: BigDecimal,
+                previous_block_votes: Value,
+                proposer: String,
+                timestamp: NaiveDateTime,
+                inserted_at: NaiveDateTime,
+                epoch: BigDecimal,
+                previous_block_votes_bitvec: Value,
+                failed_proposer_indices: Value,
+            }
+        ),
+        model_schema!(
+            "collections",
+            Collection {
+                collection_id: String,
+                creator: String,
+                name: String,
+                description: String,
+                max_amount: BigDecimal,
+                uri: String,
+                created_at: NaiveDateTime,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "collections_v2",
+            CollectionV2 {
+                transaction_version: BigDecimal,
+                write_set_change_index: i64,
+                collection_id: String,
+                creator_address: String,
+                collection_name: String,
+                description: String,
+                uri: String,
+                current_supply: Option<BigDecimal>,
+                max_supply: Option<BigDecimal>,
+                total_minted: Option<BigDecimal>,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "current_object_ownerships",
+            CurrentObjectOwnership {
+                object_address: String,
+                owner_address: Option<String>,
+                state_key_hash: String,
+                allow_ungated_transfer: Option<bool>,
+                last_transaction_version: BigDecimal,
+                is_deleted: bool,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "current_token_ownerships_v2",
+            CurrentTokenOwnershipV2 {
+                token_data_id: String,
+                owner_address: Option<String>,
+                last_transaction_version: BigDecimal,
+                is_burned: bool,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "dex_swaps",
+            DexSwap {
+                event_key: String,
+                sequence_number: BigDecimal,
+                transaction_hash: String,
+                dex_address: String,
+                pool: String,
+                sender: String,
+                coin_in: String,
+                coin_out: String,
+                amount_in: BigDecimal,
+                amount_out: BigDecimal,
+                swapped_at: NaiveDateTime,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "epochs",
+            Epoch {
+                epoch: BigDecimal,
+                transaction_version: BigDecimal,
+                transaction_hash: String,
+                started_at: NaiveDateTime,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "event_types_seen",
+            EventTypeSeen {
+                type_ as "type": String,
+                first_version: BigDecimal,
+                last_version: BigDecimal,
+                num_events: i64,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "events",
+            Event {
+                transaction_hash: String,
+                key: String,
+                sequence_number: BigDecimal,
+                type_ as "type": String,
+                data: Value,
+                inserted_at: NaiveDateTime,
+                event_index: Option<i64>,
+                transaction_timestamp: Option<NaiveDateTime>,
+            }
+        ),
+        model_schema!(
+            "function_reliability_stats",
+            FunctionReliabilityStat {
+                module_address: String,
+                module_name: String,
+                function_name: String,
+                date: NaiveDate,
+                num_transactions: i64,
+                num_failed: i64,
+                abort_codes: Value,
+                last_version: BigDecimal,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "fungible_asset_activities",
+            FungibleAssetActivity {
+                transaction_version: BigDecimal,
+                event_index: i64,
+                storage_id: String,
+                owner_address: Option<String>,
+                asset_type: Option<String>,
+                type_ as "type": String,
+                amount: Option<BigDecimal>,
+                is_frozen: Option<bool>,
+                transaction_timestamp: NaiveDateTime,
+                inserted_at: NaiveDateTime,
+                amount_usd: Option<BigDecimal>,
+            }
+        ),
+        model_schema!(
+            "fungible_asset_balance_history",
+            FungibleAssetBalanceHistory {
+                storage_id: String,
+                transaction_version: BigDecimal,
+                asset_type: String,
+                amount: BigDecimal,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "fungible_asset_balances",
+            FungibleAssetBalance {
+                storage_id: String,
+                owner_address: Option<String>,
+                asset_type: String,
+                amount: BigDecimal,
+                is_frozen: bool,
+                last_transaction_version: BigDecimal,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "fungible_asset_metadata",
+            FungibleAssetMetadata {
+                asset_type: String,
+                creator_address: Option<String>,
+                name: String,
+                symbol: String,
+                decimals: i64,
+                icon_uri: String,
+                project_uri: String,
+                transaction_version: BigDecimal,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "hourly_active_accounts",
+            HourlyActiveAccount {
+                bucket: NaiveDateTime,
+                account: String,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "hourly_activity_rollups",
+            HourlyActivityRollup {
+                bucket: NaiveDateTime,
+                gas_used: BigDecimal,
+                num_active_accounts: i64,
+                last_version: BigDecimal,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "ledger_info_history",
+            LedgerInfoHistory {
+                chain_id: i64,
+                epoch: BigDecimal,
+                ledger_version: BigDecimal,
+                ledger_timestamp: NaiveDateTime,
+                oldest_ledger_version: BigDecimal,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!("ledger_infos", LedgerInfo { chain_id: i64 }),
+        model_schema!(
+            "metadatas",
+            Metadata {
+                token_id: String,
+                name: Option<String>,
+                symbol: Option<String>,
+                seller_fee_basis_points: Option<BigDecimal>,
+                description: Option<String>,
+                image: String,
+                external_url: Option<String>,
+                animation_url: Option<String>,
+                attributes: Option<Value>,
+                properties: Option<Value>,
+                last_updated_at: NaiveDateTime,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "minute_transaction_rollups",
+            MinuteTransactionRollup {
+                bucket: NaiveDateTime,
+                num_transactions: i64,
+                num_user_transactions: i64,
+                num_failed_transactions: i64,
+                last_version: BigDecimal,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "module_daily_stats",
+            ModuleDailyStat {
+                module_address: String,
+                module_name: String,
+                date: NaiveDate,
+                num_transactions: i64,
+                gas_used: BigDecimal,
+                unique_senders: i64,
+                senders_sketch: Vec<u8>,
+                last_version: BigDecimal,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "objects",
+            Object {
+                transaction_version: BigDecimal,
+                write_set_change_index: i64,
+                object_address: String,
+                owner_address: Option<String>,
+                state_key_hash: String,
+                guid_creation_num: Option<BigDecimal>,
+                allow_ungated_transfer: Option<bool>,
+                is_deleted: bool,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "online_migrations",
+            OnlineMigration {
+                table_name: String,
+                shadow_table: String,
+                backfill_end: Option<Value>,
+                backfilled_up_to: Option<Value>,
+                state: String,
+                started_at: NaiveDateTime,
+                updated_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "operator_audit_log",
+            OperatorAction {
+                actor: String,
+                remote_address: Option<String>,
+                action: String,
+                parameters: Value,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "outbox",
+            OutboxMessage {
+                topic: String,
+                transaction_version: BigDecimal,
+                message_index: i64,
+                message_key: String,
+                payload: Value,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "ownerships",
+            Ownership {
+                ownership_id: String,
+                token_id: String,
+                owner: String,
+                amount: BigDecimal,
+                updated_at: NaiveDateTime,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "raw_transactions",
+            RawTransaction {
+                version: BigDecimal,
+                hash: String,
+                encoding: String,
+                data: Vec<u8>,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "rollup_ranges",
+            RollupRange {
+                rollup: String,
+                start_version: BigDecimal,
+                end_version: BigDecimal,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "token_activities_v2",
+            TokenActivityV2 {
+                transaction_version: BigDecimal,
+                event_index: i64,
+                token_data_id: String,
+                collection_id: Option<String>,
+                type_ as "type": String,
+                from_address: Option<String>,
+                to_address: Option<String>,
+                transaction_timestamp: NaiveDateTime,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "token_datas",
+            TokenData {
+                token_data_id: String,
+                creator: String,
+                collection: String,
+                name: String,
+                description: String,
+                max_amount: BigDecimal,
+                supply: BigDecimal,
+                uri: String,
+                royalty_payee_address: String,
+                royalty_points_denominator: BigDecimal,
+                royalty_points_numerator: BigDecimal,
+                mutability_config: String,
+                property_keys: String,
+                property_values: String,
+                property_types: String,
+                minted_at: NaiveDateTime,
+                inserted_at: NaiveDateTime,
+                last_minted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "token_metadata_cache",
+            TokenMetadataCache {
+                uri: String,
+                resolved_uri: Option<String>,
+                metadata: Option<Value>,
+                image_uri: Option<String>,
+                image_content_type: Option<String>,
+                image_size_bytes: Option<i64>,
+                status: String,
+                error: Option<String>,
+                fetched_at: NaiveDateTime,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "token_propertys",
+            TokenProperty {
+                token_id: String,
+                previous_token_id: String,
+                property_keys: String,
+                property_values: String,
+                property_types: String,
+                updated_at: NaiveDateTime,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "tokens_v2",
+            TokenV2 {
+                transaction_version: BigDecimal,
+                write_set_change_index: i64,
+                token_data_id: String,
+                collection_id: String,
+                token_name: String,
+                description: String,
+                uri: String,
+                token_index: Option<BigDecimal>,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "top_holders",
+            TopHolder {
+                asset_type: String,
+                storage_id: String,
+                owner_address: Option<String>,
+                amount: BigDecimal,
+                last_transaction_version: BigDecimal,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "transactions",
+            Transaction {
+                type_ as "type": String,
+                payload: Value,
+                version: BigDecimal,
+                hash: String,
+                state_root_hash: String,
+                event_root_hash: String,
+                gas_used: BigDecimal,
+                success: bool,
+                vm_status: String,
+                accumulator_root_hash: String,
+                inserted_at: NaiveDateTime,
+                num_events: i64,
+                num_write_set_changes: i64,
+                payload_size_bytes: Option<i64>,
+                timestamp: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "transfer_edges",
+            TransferEdge {
+                transaction_version: BigDecimal,
+                event_index: i64,
+                from_address: Option<String>,
+                to_address: Option<String>,
+                asset: String,
+                amount: BigDecimal,
+                kind: String,
+                transaction_timestamp: NaiveDateTime,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "txn_latency_stats",
+            TxnLatencyStat {
+                period_start: NaiveDateTime,
+                latency_bucket_secs: i64,
+                num_transactions: i64,
+                total_latency_ms: i64,
+                inserted_at: NaiveDateTime,
+                last_updated: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "txn_latency_stat_batches",
+            TxnLatencyStatBatch {
+                start_version: BigDecimal,
+                end_version: BigDecimal,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "user_transactions",
+            UserTransaction {
+                hash: String,
+                signature: Value,
+                sender: String,
+                sequence_number: BigDecimal,
+                max_gas_amount: BigDecimal,
+                expiration_timestamp_secs: NaiveDateTime,
+                gas_unit_price: BigDecimal,
+                timestamp: NaiveDateTime,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "validator_set_snapshots",
+            ValidatorSetSnapshot {
+                epoch: BigDecimal,
+                validator_address: String,
+                status: String,
+                voting_power: BigDecimal,
+                validator_index: Option<i64>,
+                consensus_pubkey: Option<String>,
+                inserted_at: NaiveDateTime,
+            }
+        ),
+        model_schema!(
+            "write_set_changes",
+            WriteSetChange {
+                transaction_hash: String,
+                hash: String,
+                type_ as "type": String,
+                address: String,
+                module: Value,
+                resource: Value,
+                data: Value,
+                inserted_at: NaiveDateTime,
+                write_set_change_index: Option<i64>,
+                transaction_timestamp: Option<NaiveDateTime>,
+            }
+        ),
+    ]
+    .into_iter()
+    .map(|(name, schema)| (name.to_string(), schema))
+    .collect();
+
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "Aptos indexer models",
+        "definitions": definitions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::u64_to_bigdecimal;
+    use std::collections::BTreeSet;
+
+    fn property_names(definition: &str) -> BTreeSet<String> {
+        schema_json()["definitions"][definition]["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn test_schema_matches_serialized_models() {
+        let now = chrono::Utc::now().naive_utc();
+        let ownership = Ownership::new(
+            "token".to_string(),
+            "0x1".to_string(),
+            u64_to_bigdecimal(1),
+            now,
+            now,
+        );
+        let json = serde_json::to_value(&ownership).unwrap();
+        let keys: BTreeSet<String> = json.as_object().unwrap().keys().cloned().collect();
+        assert_eq!(keys, property_names("Ownership"));
+        let round_trip: Ownership = serde_json::from_value(json).unwrap();
+        assert_eq!(round_trip.ownership_id, ownership.ownership_id);
+
+        let event = Event {
+            transaction_hash: "0xabc".to_string(),
+            key: "0x0".to_string(),
+            sequence_number: u64_to_bigdecimal(0),
+            type_: "0x1::coin::DepositEvent".to_string(),
+            data: json!({ "amount": "1" }),
+            inserted_at: now,
+            event_index: Some(0),
+            transaction_timestamp: Some(now),
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "0x1::coin::DepositEvent");
+        let keys: BTreeSet<String> = json.as_object().unwrap().keys().cloned().collect();
+        assert_eq!(keys, property_names("Event"));
+    }
+
+    #[test]
+    fn test_optional_fields_are_not_required() {
+        let schema = schema_json();
+        let required = schema["definitions"]["Metadata"]["required"]
+            .as_array()
+            .unwrap();
+        assert!(required.contains(&json!("token_id")));
+        assert!(!required.contains(&json!("name")));
+    }
+}
@@ -1,11 +1,48 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 #![allow(clippy::extra_unused_lifetimes)]
-use crate::schema::ledger_infos;
+#[cfg(feature = "postgres")]
+use crate::schema::{ledger_info_history as ledger_info_historys, ledger_infos};
+use crate::util::u64_to_bigdecimal;
+use aptos_rest_client::State;
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Identifiable, Insertable, Queryable)]
-#[diesel(table_name = "ledger_infos")]
-#[primary_key(chain_id)]
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "ledger_infos"))]
+#[cfg_attr(feature = "postgres", primary_key(chain_id))]
 pub struct LedgerInfo {
     pub chain_id: i64,
 }
+
+/// A snapshot of the node's ledger info, recorded periodically to track its pruning window and the chain's growth
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "ledger_info_history"))]
+#[cfg_attr(feature = "postgres", primary_key(chain_id, ledger_version))]
+pub struct LedgerInfoHistory {
+    pub chain_id: i64,
+    pub epoch: BigDecimal,
+    pub ledger_version: BigDecimal,
+    pub ledger_timestamp: chrono::NaiveDateTime,
+    /// Versions before this one were pruned by the node
+    pub oldest_ledger_version: BigDecimal,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+impl LedgerInfoHistory {
+    pub fn from_state(state: &State) -> Self {
+        Self {
+            chain_id: state.chain_id as i64,
+            epoch: u64_to_bigdecimal(state.epoch),
+            ledger_version: u64_to_bigdecimal(state.version),
+            ledger_timestamp: chrono::NaiveDateTime::from_timestamp(
+                (state.timestamp_usecs / 1_000_000) as i64,
+                0,
+            ),
+            oldest_ledger_version: u64_to_bigdecimal(state.oldest_ledger_version),
+            inserted_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
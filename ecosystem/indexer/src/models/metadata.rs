@@ -1,15 +1,18 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 #![allow(clippy::extra_unused_lifetimes)]
+#[cfg(feature = "postgres")]
 use crate::schema::metadatas;
 use field_count::FieldCount;
 use serde::{Deserialize, Serialize};
 
-#[derive(
-    Associations, Debug, FieldCount, Identifiable, Insertable, Queryable, Serialize, Clone,
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(
+    feature = "postgres",
+    derive(Associations, Identifiable, Insertable, Queryable)
 )]
-#[diesel(table_name = "metadata")]
-#[primary_key(token_id)]
+#[cfg_attr(feature = "postgres", diesel(table_name = "metadata"))]
+#[cfg_attr(feature = "postgres", primary_key(token_id))]
 pub struct Metadata {
     pub token_id: String,
     pub name: Option<String>,
@@ -1,13 +1,31 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod account_summary;
+pub mod api_key;
 pub mod collection;
+pub mod dex_swap;
+pub mod epoch;
 pub mod events;
+pub mod fungible_asset;
+pub mod json_schema;
 pub mod ledger_info;
 pub mod metadata;
+pub mod object;
+pub mod online_migration;
+pub mod operator_audit_log;
+pub mod outbox;
 pub mod ownership;
+#[cfg(feature = "postgres")]
 pub mod processor_statuses;
+pub mod raw_transactions;
+pub mod rollups;
 pub mod token;
+pub mod token_metadata_cache;
 pub mod token_property;
+pub mod token_v2;
+pub mod top_holder;
 pub mod transactions;
+pub mod transfer_edge;
+pub mod txn_latency_stat;
 pub mod write_set_changes;
@@ -0,0 +1,215 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+#![allow(clippy::extra_unused_lifetimes)]
+#[cfg(feature = "postgres")]
+use crate::schema::{current_object_ownerships, objects};
+use crate::util::u64_to_bigdecimal;
+use aptos_rest_client::aptos_api_types::{
+    DeleteResource, Transaction as APITransaction, WriteResource,
+    WriteSetChange as APIWriteSetChange,
+};
+use bigdecimal::BigDecimal;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, str::FromStr};
+
+pub const OBJECT_CORE_TYPE: &str = "0x1::object::ObjectCore";
+
+/// A change to an object's `0x1::object::ObjectCore`: its creation, a transfer (which also emits a
+/// `0x1::object::TransferEvent`) or its deletion
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "objects"))]
+#[cfg_attr(
+    feature = "postgres",
+    primary_key(transaction_version, write_set_change_index)
+)]
+pub struct Object {
+    pub transaction_version: BigDecimal,
+    pub write_set_change_index: i64,
+    pub object_address: String,
+    /// NULL when the object is deleted
+    pub owner_address: Option<String>,
+    pub state_key_hash: String,
+    pub guid_creation_num: Option<BigDecimal>,
+    pub allow_ungated_transfer: Option<bool>,
+    pub is_deleted: bool,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+/// Who owns each object now
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "current_object_ownerships"))]
+#[cfg_attr(feature = "postgres", primary_key(object_address))]
+pub struct CurrentObjectOwnership {
+    pub object_address: String,
+    /// The last owner, if the object is deleted
+    pub owner_address: Option<String>,
+    pub state_key_hash: String,
+    pub allow_ungated_transfer: Option<bool>,
+    pub last_transaction_version: BigDecimal,
+    pub is_deleted: bool,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+impl Object {
+    pub fn from_write_set_change(
+        transaction_version: u64,
+        write_set_change_index: usize,
+        write_set_change: &APIWriteSetChange,
+    ) -> Option<Self> {
+        match write_set_change {
+            APIWriteSetChange::WriteResource(WriteResource {
+                address,
+                state_key_hash,
+                data,
+            }) if data.typ.to_string() == OBJECT_CORE_TYPE => {
+                let data = serde_json::to_value(&data.data)
+                    .expect("Should be able to parse write resource data");
+                Some(Self {
+                    transaction_version: u64_to_bigdecimal(transaction_version),
+                    write_set_change_index: write_set_change_index as i64,
+                    object_address: address.to_string(),
+                    owner_address: data["owner"].as_str().map(|owner| owner.to_string()),
+                    state_key_hash: state_key_hash.clone(),
+                    guid_creation_num: data["guid_creation_num"]
+                        .as_str()
+                        .and_then(|num| BigDecimal::from_str(num).ok()),
+                    allow_ungated_transfer: data["allow_ungated_transfer"].as_bool(),
+                    is_deleted: false,
+                    inserted_at: chrono::Utc::now().naive_utc(),
+                })
+            }
+            APIWriteSetChange::DeleteResource(DeleteResource {
+                address,
+                state_key_hash,
+                resource,
+            }) if resource.to_string() == OBJECT_CORE_TYPE => Some(Self {
+                transaction_version: u64_to_bigdecimal(transaction_version),
+                write_set_change_index: write_set_change_index as i64,
+                object_address: address.to_string(),
+                owner_address: None,
+                state_key_hash: state_key_hash.clone(),
+                guid_creation_num: None,
+                allow_ungated_transfer: None,
+                is_deleted: true,
+                inserted_at: chrono::Utc::now().naive_utc(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Every `ObjectCore` change in `transactions`, in version order
+    pub fn from_transactions(transactions: &[APITransaction]) -> Vec<Self> {
+        let mut objects = vec![];
+        for transaction in transactions {
+            let info = match transaction.transaction_info() {
+                Ok(info) => info,
+                Err(_) => continue,
+            };
+            let version = *info.version.inner();
+            objects.extend(
+                info.changes
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(index, wsc)| Self::from_write_set_change(version, index, wsc)),
+            );
+        }
+        objects
+    }
+}
+
+impl CurrentObjectOwnership {
+    /// The latest state of each object changed in `objects`, which must be in version order
+    pub fn from_objects(objects: &[Object]) -> Vec<Self> {
+        let mut current: BTreeMap<&str, Self> = BTreeMap::new();
+        for object in objects {
+            // Deletions don't say who the owner was, so keep the previous one from this batch (if any)
+            let owner_address = match (
+                &object.owner_address,
+                current.get(object.object_address.as_str()),
+            ) {
+                (None, Some(previous)) => previous.owner_address.clone(),
+                (owner_address, _) => owner_address.clone(),
+            };
+            current.insert(
+                object.object_address.as_str(),
+                Self {
+                    object_address: object.object_address.clone(),
+                    owner_address,
+                    state_key_hash: object.state_key_hash.clone(),
+                    allow_ungated_transfer: object.allow_ungated_transfer,
+                    last_transaction_version: object.transaction_version.clone(),
+                    is_deleted: object.is_deleted,
+                    inserted_at: object.inserted_at,
+                },
+            );
+        }
+        current.into_values().collect()
+    }
+}
+
+// Prevent conflicts with other things named `Object`
+pub type ObjectModel = Object;
+pub type CurrentObjectOwnershipModel = CurrentObjectOwnership;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn write_object_core(owner: &str) -> APIWriteSetChange {
+        serde_json::from_value(json!({
+            "type": "write_resource",
+            "address": "0xa11ce",
+            "state_key_hash": "0x1234",
+            "data": {
+                "type": "0x1::object::ObjectCore",
+                "data": {
+                    "allow_ungated_transfer": true,
+                    "guid_creation_num": "1125899906842625",
+                    "owner": owner,
+                    "transfer_events": {"counter": "0", "guid": {"id": {"addr": "0xa11ce", "creation_num": "1125899906842624"}}},
+                },
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_object_ownership() {
+        let delete: APIWriteSetChange = serde_json::from_value(json!({
+            "type": "delete_resource",
+            "address": "0xa11ce",
+            "state_key_hash": "0x1234",
+            "resource": "0x1::object::ObjectCore",
+        }))
+        .unwrap();
+        let objects = vec![
+            Object::from_write_set_change(10, 0, &write_object_core("0xb0b")).unwrap(),
+            Object::from_write_set_change(11, 2, &write_object_core("0xca41")).unwrap(),
+            Object::from_write_set_change(12, 1, &delete).unwrap(),
+        ];
+        assert_eq!(objects[0].object_address, "0xa11ce");
+        assert_eq!(objects[0].owner_address, Some("0xb0b".to_string()));
+        assert_eq!(objects[0].allow_ungated_transfer, Some(true));
+        assert!(objects[2].is_deleted);
+
+        let current = CurrentObjectOwnership::from_objects(&objects);
+        assert_eq!(current.len(), 1);
+        assert_eq!(current[0].owner_address, Some("0xca41".to_string()));
+        assert_eq!(current[0].last_transaction_version, u64_to_bigdecimal(12));
+        assert!(current[0].is_deleted);
+
+        // Other resources are ignored
+        let other: APIWriteSetChange = serde_json::from_value(json!({
+            "type": "delete_resource",
+            "address": "0xa11ce",
+            "state_key_hash": "0x1234",
+            "resource": "0x1::coin::CoinStore<0x1::aptos_coin::AptosCoin>",
+        }))
+        .unwrap();
+        assert!(Object::from_write_set_change(13, 0, &other).is_none());
+    }
+}
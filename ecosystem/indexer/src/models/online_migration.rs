@@ -0,0 +1,31 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+#![allow(clippy::extra_unused_lifetimes)]
+#[cfg(feature = "postgres")]
+use crate::schema::online_migrations;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+pub const STATE_BACKFILLING: &str = "backfilling";
+pub const STATE_BACKFILLED: &str = "backfilled";
+pub const STATE_CUT_OVER: &str = "cut_over";
+
+/// Progress of a table rewrite run by `migrate-online` (see `online_migration`)
+#[derive(Clone, Debug, Deserialize, FieldCount, Serialize, PartialEq)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "online_migrations"))]
+#[cfg_attr(feature = "postgres", primary_key(table_name))]
+pub struct OnlineMigration {
+    pub table_name: String,
+    pub shadow_table: String,
+    /// Key of the last row which existed before writes were mirrored, None if the table was empty
+    pub backfill_end: Option<serde_json::Value>,
+    /// Key of the last row copied so far
+    pub backfilled_up_to: Option<serde_json::Value>,
+    pub state: String,
+    pub started_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+// Prevent conflicts with other things named `OnlineMigration`
+pub type OnlineMigrationModel = OnlineMigration;
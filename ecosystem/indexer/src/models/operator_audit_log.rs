@@ -0,0 +1,41 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+#![allow(clippy::extra_unused_lifetimes)]
+#[cfg(feature = "postgres")]
+use crate::schema::operator_audit_log as operator_actions;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+/// An action an operator took, as recorded in `operator_audit_log`. `id` is left to its default.
+#[derive(Clone, Debug, Deserialize, FieldCount, Serialize, PartialEq)]
+#[cfg_attr(feature = "postgres", derive(Insertable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "operator_audit_log"))]
+pub struct OperatorAction {
+    pub actor: String,
+    /// Where an admin request came from, None for CLI actions
+    pub remote_address: Option<String>,
+    /// ex: `processor.disable`
+    pub action: String,
+    pub parameters: serde_json::Value,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+impl OperatorAction {
+    pub fn new(
+        actor: String,
+        remote_address: Option<String>,
+        action: &str,
+        parameters: serde_json::Value,
+    ) -> Self {
+        Self {
+            actor,
+            remote_address,
+            action: action.to_string(),
+            parameters,
+            inserted_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+// Prevent conflicts with other things named `OperatorAction`
+pub type OperatorActionModel = OperatorAction;
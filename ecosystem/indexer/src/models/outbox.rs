@@ -0,0 +1,55 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+#![allow(clippy::extra_unused_lifetimes)]
+use crate::models::{events::EventModel, transactions::TransactionModel};
+#[cfg(feature = "postgres")]
+use crate::schema::outbox as outbox_messages;
+use bigdecimal::BigDecimal;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Topic of the messages for indexed events
+pub const EVENTS_TOPIC: &str = "events";
+
+/// A message for external relays, written in the same DB transaction as the data it describes. The columns the relay
+/// maintains (`id`, `delivered_at`, ...) are left to their defaults.
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(feature = "postgres", derive(Insertable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "outbox"))]
+pub struct OutboxMessage {
+    pub topic: String,
+    pub transaction_version: BigDecimal,
+    /// Position of the message among its transaction's messages of the topic
+    pub message_index: i64,
+    /// Partitioning key, so a relay can preserve each stream's order
+    pub message_key: String,
+    pub payload: serde_json::Value,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+impl OutboxMessage {
+    /// A message per event, keyed by event key, with the event's serde representation as payload
+    pub fn from_events(transactions: &[TransactionModel], events: &[EventModel]) -> Vec<Self> {
+        let versions: HashMap<&str, &BigDecimal> = transactions
+            .iter()
+            .map(|transaction| (transaction.hash.as_str(), &transaction.version))
+            .collect();
+        events
+            .iter()
+            .map(|event| Self {
+                topic: EVENTS_TOPIC.to_string(),
+                transaction_version: versions[event.transaction_hash.as_str()].clone(),
+                message_index: event
+                    .event_index
+                    .expect("Processed events have their index"),
+                message_key: event.key.clone(),
+                payload: serde_json::to_value(event).expect("Events serialize to JSON"),
+                inserted_at: chrono::Utc::now().naive_utc(),
+            })
+            .collect()
+    }
+}
+
+// Prevent conflicts with other things named `OutboxMessage`
+pub type OutboxMessageModel = OutboxMessage;
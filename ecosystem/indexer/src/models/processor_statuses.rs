@@ -11,7 +11,7 @@ use field_count::FieldCount;
 #[changeset_options(treat_none_as_null = "true")]
 #[diesel(table_name = processor_statuses)]
 pub struct ProcessorStatus {
-    pub name: &'static str,
+    pub name: String,
     pub version: bigdecimal::BigDecimal,
     pub success: bool,
     pub details: Option<String>,
@@ -19,9 +19,9 @@ pub struct ProcessorStatus {
 }
 
 impl ProcessorStatus {
-    pub fn new(name: &'static str, version: u64, success: bool, details: Option<String>) -> Self {
+    pub fn new(name: &str, version: u64, success: bool, details: Option<String>) -> Self {
         Self {
-            name,
+            name: name.to_string(),
             version: bigdecimal::BigDecimal::from_u64(version)
                 .expect("Should be able to convert u64 to big decimal"),
             success,
@@ -41,18 +41,17 @@ impl ProcessorStatus {
         )
     }
 
+    /// Statuses for every version from `start_version` to `end_version` (inclusive)
     pub fn from_versions(
-        name: &'static str,
+        name: &str,
         start_version: u64,
         end_version: u64,
         success: bool,
         details: Option<String>,
     ) -> Vec<Self> {
-        let mut status: Vec<Self> = vec![Self::new(name, start_version, success, details.clone())];
-        for version in start_version + 1..end_version {
-            status.push(Self::new(name, version, success, details.clone()));
-        }
-        status
+        (start_version..=end_version)
+            .map(|version| Self::new(name, version, success, details.clone()))
+            .collect()
     }
 }
 
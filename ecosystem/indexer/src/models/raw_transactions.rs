@@ -0,0 +1,108 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+#![allow(clippy::extra_unused_lifetimes)]
+#[cfg(feature = "postgres")]
+use crate::schema::raw_transactions;
+use crate::util::{bigdecimal_to_u64, u64_to_bigdecimal};
+use anyhow::{bail, Context, Result};
+use aptos_rest_client::aptos_api_types::Transaction as APITransaction;
+use bigdecimal::BigDecimal;
+use field_count::FieldCount;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// The REST API's JSON representation of the transaction, gzipped
+pub const JSON_GZIP_ENCODING: &str = "json+gzip";
+
+/// A transaction as fetched from the node, archived so processors added later can be backfilled from the DB
+#[derive(Debug, Deserialize, FieldCount, Serialize)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", primary_key(version))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "raw_transactions"))]
+pub struct RawTransaction {
+    pub version: BigDecimal,
+    pub hash: String,
+    /// How `data` is encoded, `JSON_GZIP_ENCODING` for now
+    pub encoding: String,
+    pub data: Vec<u8>,
+
+    // Default time columns
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+impl RawTransaction {
+    /// Archives the transactions with a version (all of them, as pending transactions aren't fetched)
+    pub fn from_transactions(transactions: &[APITransaction]) -> Result<Vec<Self>> {
+        transactions
+            .iter()
+            .filter_map(|txn| txn.version().map(|version| (version, txn)))
+            .map(|(version, txn)| {
+                let mut encoder = GzEncoder::new(vec![], Compression::default());
+                serde_json::to_writer(&mut encoder, txn)?;
+                encoder.flush()?;
+                Ok(Self {
+                    version: u64_to_bigdecimal(version),
+                    hash: txn
+                        .transaction_info()
+                        .map(|info| info.hash.to_string())
+                        .unwrap_or_default(),
+                    encoding: JSON_GZIP_ENCODING.to_string(),
+                    data: encoder.finish()?,
+                    inserted_at: chrono::Utc::now().naive_utc(),
+                })
+            })
+            .collect()
+    }
+
+    /// The archived transaction, as the node returned it
+    pub fn decode(&self) -> Result<APITransaction> {
+        decode(
+            bigdecimal_to_u64(&self.version)?,
+            &self.encoding,
+            &self.data,
+        )
+    }
+}
+
+/// The transaction of `version` archived as `data`, in `encoding`, ex: as served by the gRPC read API
+pub fn decode(version: u64, encoding: &str, data: &[u8]) -> Result<APITransaction> {
+    if encoding != JSON_GZIP_ENCODING {
+        bail!(
+            "Raw transaction {} has unknown encoding {}",
+            version,
+            encoding
+        );
+    }
+    let mut json = vec![];
+    GzDecoder::new(data)
+        .read_to_end(&mut json)
+        .with_context(|| format!("Could not decompress raw transaction {}", version))?;
+    serde_json::from_slice(&json)
+        .with_context(|| format!("Could not parse raw transaction {}", version))
+}
+
+// Prevent conflicts with other things named `RawTransaction`
+pub type RawTransactionModel = RawTransaction;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TransactionFactory;
+
+    #[test]
+    fn test_round_trip() {
+        let mut factory = TransactionFactory::new(5);
+        let txns = vec![
+            factory.user_transaction("0xa11ce").build(),
+            factory.user_transaction("0xb0b").build(),
+        ];
+        let raw = RawTransaction::from_transactions(&txns).unwrap();
+        assert_eq!(raw.len(), 2);
+        assert_eq!(raw[1].version, u64_to_bigdecimal(6));
+        assert_eq!(
+            serde_json::to_value(raw[1].decode().unwrap()).unwrap(),
+            serde_json::to_value(&txns[1]).unwrap()
+        );
+    }
+}
@@ -0,0 +1,188 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+#![allow(clippy::extra_unused_lifetimes)]
+// Diesel infers `EventTypeSeen`'s table as `event_type_seens`
+#[cfg(feature = "postgres")]
+use crate::schema::{
+    event_types_seen as event_type_seens, function_reliability_stats, hourly_active_accounts,
+    hourly_activity_rollups, minute_transaction_rollups, module_daily_stats, rollup_ranges,
+};
+use crate::util::u64_to_bigdecimal;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, FieldCount, Serialize)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(
+    feature = "postgres",
+    diesel(table_name = "minute_transaction_rollups")
+)]
+#[cfg_attr(feature = "postgres", primary_key(bucket))]
+pub struct MinuteTransactionRollup {
+    pub bucket: chrono::NaiveDateTime,
+    pub num_transactions: i64,
+    pub num_user_transactions: i64,
+    pub num_failed_transactions: i64,
+    pub last_version: bigdecimal::BigDecimal,
+
+    // Default time columns
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+impl MinuteTransactionRollup {
+    pub fn new(
+        bucket: chrono::NaiveDateTime,
+        num_transactions: i64,
+        num_user_transactions: i64,
+        num_failed_transactions: i64,
+        last_version: u64,
+    ) -> Self {
+        Self {
+            bucket,
+            num_transactions,
+            num_user_transactions,
+            num_failed_transactions,
+            last_version: u64_to_bigdecimal(last_version),
+            inserted_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, FieldCount, Serialize)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "hourly_activity_rollups"))]
+#[cfg_attr(feature = "postgres", primary_key(bucket))]
+pub struct HourlyActivityRollup {
+    pub bucket: chrono::NaiveDateTime,
+    pub gas_used: bigdecimal::BigDecimal,
+    pub num_active_accounts: i64,
+    pub last_version: bigdecimal::BigDecimal,
+
+    // Default time columns
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+impl HourlyActivityRollup {
+    pub fn new(
+        bucket: chrono::NaiveDateTime,
+        gas_used: u64,
+        num_active_accounts: i64,
+        last_version: u64,
+    ) -> Self {
+        Self {
+            bucket,
+            gas_used: u64_to_bigdecimal(gas_used),
+            num_active_accounts,
+            last_version: u64_to_bigdecimal(last_version),
+            inserted_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, FieldCount, Serialize)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "hourly_active_accounts"))]
+#[cfg_attr(feature = "postgres", primary_key(bucket, account))]
+pub struct HourlyActiveAccount {
+    pub bucket: chrono::NaiveDateTime,
+    pub account: String,
+
+    // Default time columns
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+impl HourlyActiveAccount {
+    pub fn new(bucket: chrono::NaiveDateTime, account: String) -> Self {
+        Self {
+            bucket,
+            account,
+            inserted_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, FieldCount, Serialize)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "module_daily_stats"))]
+#[cfg_attr(feature = "postgres", primary_key(module_address, module_name, date))]
+pub struct ModuleDailyStat {
+    pub module_address: String,
+    pub module_name: String,
+    pub date: chrono::NaiveDate,
+    pub num_transactions: i64,
+    pub gas_used: bigdecimal::BigDecimal,
+    /// Estimated from `senders_sketch`
+    pub unique_senders: i64,
+    /// HyperLogLog registers of the senders
+    pub senders_sketch: Vec<u8>,
+    pub last_version: bigdecimal::BigDecimal,
+
+    // Default time columns
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, FieldCount, Serialize)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(
+    feature = "postgres",
+    diesel(table_name = "function_reliability_stats")
+)]
+#[cfg_attr(
+    feature = "postgres",
+    primary_key(module_address, module_name, function_name, date)
+)]
+pub struct FunctionReliabilityStat {
+    pub module_address: String,
+    pub module_name: String,
+    pub function_name: String,
+    pub date: chrono::NaiveDate,
+    pub num_transactions: i64,
+    pub num_failed: i64,
+    /// Number of the failed transactions which aborted, by abort location and code (see `abort_code`)
+    pub abort_codes: serde_json::Value,
+    pub last_version: bigdecimal::BigDecimal,
+
+    // Default time columns
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, FieldCount, Serialize)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "event_types_seen"))]
+#[cfg_attr(feature = "postgres", primary_key(type_))]
+pub struct EventTypeSeen {
+    #[cfg_attr(feature = "postgres", diesel(column_name = type))]
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub first_version: bigdecimal::BigDecimal,
+    pub last_version: bigdecimal::BigDecimal,
+    pub num_events: i64,
+
+    // Default time columns
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+/// Versions `start_version` to `end_version` (inclusive) have been folded into `rollup`
+#[derive(Debug, Deserialize, FieldCount, Serialize)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "rollup_ranges"))]
+#[cfg_attr(feature = "postgres", primary_key(rollup, start_version))]
+pub struct RollupRange {
+    pub rollup: String,
+    pub start_version: bigdecimal::BigDecimal,
+    pub end_version: bigdecimal::BigDecimal,
+
+    // Default time columns
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+impl RollupRange {
+    pub fn new(rollup: &str, start_version: u64, end_version: u64) -> Self {
+        Self {
+            rollup: rollup.to_string(),
+            start_version: u64_to_bigdecimal(start_version),
+            end_version: u64_to_bigdecimal(end_version),
+            inserted_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
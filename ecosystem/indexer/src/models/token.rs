@@ -1,15 +1,21 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 #![allow(clippy::extra_unused_lifetimes)]
-use crate::{models::events::Event, schema::token_datas};
+use crate::models::events::Event;
+#[cfg(feature = "postgres")]
+use crate::schema::token_datas;
 use aptos_rest_client::types;
 use std::{fmt, fmt::Formatter};
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Associations, Debug, Identifiable, Insertable, Queryable, Serialize, Clone)]
-#[diesel(table_name = "token_datas")]
-#[primary_key(token_data_id)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[cfg_attr(
+    feature = "postgres",
+    derive(Associations, Identifiable, Insertable, Queryable)
+)]
+#[cfg_attr(feature = "postgres", diesel(table_name = "token_datas"))]
+#[cfg_attr(feature = "postgres", primary_key(token_data_id))]
 pub struct TokenData {
     pub token_data_id: String,
     pub creator: String,
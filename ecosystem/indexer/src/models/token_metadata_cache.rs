@@ -0,0 +1,52 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+#![allow(clippy::extra_unused_lifetimes)]
+#[cfg(feature = "postgres")]
+use crate::schema::token_metadata_cache as token_metadata_caches;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+
+pub const FETCH_STATUS_OK: &str = "ok";
+pub const FETCH_STATUS_ERROR: &str = "error";
+
+/// The off-chain metadata a token URI points to, as fetched by the URI enricher. Tokens sharing a URI share a row.
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "token_metadata_cache"))]
+#[cfg_attr(feature = "postgres", primary_key(uri))]
+pub struct TokenMetadataCache {
+    pub uri: String,
+    /// The HTTP(S) URL fetched, ex: through an IPFS gateway. NULL if the URI's scheme isn't supported.
+    pub resolved_uri: Option<String>,
+    /// The JSON document, if the URI points to one
+    pub metadata: Option<serde_json::Value>,
+    /// The image of the token: the metadata's `image`, or the URI itself if it points to an image
+    pub image_uri: Option<String>,
+    pub image_content_type: Option<String>,
+    pub image_size_bytes: Option<i64>,
+    /// One of `ok` or `error`
+    pub status: String,
+    pub error: Option<String>,
+    pub fetched_at: chrono::NaiveDateTime,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+impl TokenMetadataCache {
+    pub fn error(uri: String, resolved_uri: Option<String>, error: String) -> Self {
+        Self {
+            uri,
+            resolved_uri,
+            metadata: None,
+            image_uri: None,
+            image_content_type: None,
+            image_size_bytes: None,
+            status: FETCH_STATUS_ERROR.to_string(),
+            error: Some(error),
+            fetched_at: chrono::Utc::now().naive_utc(),
+            inserted_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+// Prevent conflicts with other things named `TokenMetadataCache`
+pub type TokenMetadataCacheModel = TokenMetadataCache;
@@ -1,12 +1,17 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 #![allow(clippy::extra_unused_lifetimes)]
+#[cfg(feature = "postgres")]
 use crate::schema::token_propertys;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Associations, Debug, Identifiable, Insertable, Queryable, Serialize, Clone)]
-#[diesel(table_name = "token_propertys")]
-#[primary_key(token_id)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[cfg_attr(
+    feature = "postgres",
+    derive(Associations, Identifiable, Insertable, Queryable)
+)]
+#[cfg_attr(feature = "postgres", diesel(table_name = "token_propertys"))]
+#[cfg_attr(feature = "postgres", primary_key(token_id))]
 pub struct TokenProperty {
     pub token_id: String,
     pub previous_token_id: String,
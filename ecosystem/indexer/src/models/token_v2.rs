@@ -0,0 +1,348 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+#![allow(clippy::extra_unused_lifetimes)]
+#[cfg(feature = "postgres")]
+use crate::schema::{
+    collections_v2 as collection_v2s, current_token_ownerships_v2 as current_token_ownership_v2s,
+    token_activities_v2 as token_activity_v2s, tokens_v2 as token_v2s,
+};
+use crate::{
+    models::{object::CurrentObjectOwnership, transactions::parse_timestamp},
+    util::u64_to_bigdecimal,
+};
+use aptos_rest_client::aptos_api_types::{
+    Event as APIEvent, Transaction as APITransaction, WriteResource,
+    WriteSetChange as APIWriteSetChange,
+};
+use bigdecimal::BigDecimal;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+pub const COLLECTION_V2_TYPE: &str = "0x4::collection::Collection";
+pub const FIXED_SUPPLY_TYPE: &str = "0x4::collection::FixedSupply";
+pub const UNLIMITED_SUPPLY_TYPE: &str = "0x4::collection::UnlimitedSupply";
+pub const TOKEN_V2_TYPE: &str = "0x4::token::Token";
+pub const MINT_EVENT_TYPE: &str = "0x4::collection::MintEvent";
+pub const BURN_EVENT_TYPE: &str = "0x4::collection::BurnEvent";
+pub const TRANSFER_EVENT_TYPE: &str = "0x1::object::TransferEvent";
+
+/// A write of a token v2 collection (`0x4::collection::Collection`), identified by the address of its object
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "collections_v2"))]
+#[cfg_attr(
+    feature = "postgres",
+    primary_key(transaction_version, write_set_change_index)
+)]
+pub struct CollectionV2 {
+    pub transaction_version: BigDecimal,
+    pub write_set_change_index: i64,
+    pub collection_id: String,
+    pub creator_address: String,
+    pub collection_name: String,
+    pub description: String,
+    pub uri: String,
+    /// NULL if the supply isn't tracked
+    pub current_supply: Option<BigDecimal>,
+    /// NULL if the supply is unlimited or isn't tracked
+    pub max_supply: Option<BigDecimal>,
+    pub total_minted: Option<BigDecimal>,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+/// A write of a token v2 (`0x4::token::Token`), identified by the address of its object
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "tokens_v2"))]
+#[cfg_attr(
+    feature = "postgres",
+    primary_key(transaction_version, write_set_change_index)
+)]
+pub struct TokenV2 {
+    pub transaction_version: BigDecimal,
+    pub write_set_change_index: i64,
+    pub token_data_id: String,
+    pub collection_id: String,
+    pub token_name: String,
+    pub description: String,
+    pub uri: String,
+    pub token_index: Option<BigDecimal>,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+/// A mint, burn or transfer of a token v2
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "token_activities_v2"))]
+#[cfg_attr(feature = "postgres", primary_key(transaction_version, event_index))]
+pub struct TokenActivityV2 {
+    pub transaction_version: BigDecimal,
+    pub event_index: i64,
+    pub token_data_id: String,
+    /// NULL for transfers
+    pub collection_id: Option<String>,
+    #[cfg_attr(feature = "postgres", diesel(column_name = type))]
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub from_address: Option<String>,
+    pub to_address: Option<String>,
+    pub transaction_timestamp: chrono::NaiveDateTime,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+/// Who owns each token v2 now
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(
+    feature = "postgres",
+    diesel(table_name = "current_token_ownerships_v2")
+)]
+#[cfg_attr(feature = "postgres", primary_key(token_data_id))]
+pub struct CurrentTokenOwnershipV2 {
+    pub token_data_id: String,
+    /// The last owner, if the token is burned
+    pub owner_address: Option<String>,
+    pub last_transaction_version: BigDecimal,
+    pub is_burned: bool,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+impl TokenV2 {
+    /// The collections and tokens written by `transactions`, and the mints, burns and transfers they emitted.
+    /// Transfers are emitted for every object, so callers keep only the ones of known tokens.
+    pub fn from_transactions(
+        transactions: &[APITransaction],
+    ) -> (Vec<CollectionV2>, Vec<Self>, Vec<TokenActivityV2>) {
+        let mut collections = vec![];
+        let mut tokens = vec![];
+        let mut activities = vec![];
+        for transaction in transactions {
+            let info = match transaction.transaction_info() {
+                Ok(info) => info,
+                Err(_) => continue,
+            };
+            let version = *info.version.inner();
+            let (txn_collections, txn_tokens) = parse_write_set(version, &info.changes);
+            collections.extend(txn_collections);
+            tokens.extend(txn_tokens);
+            if let APITransaction::UserTransaction(user_txn) = transaction {
+                activities.extend(TokenActivityV2::from_events(
+                    version,
+                    parse_timestamp(user_txn.timestamp, user_txn.info.version),
+                    &user_txn.events,
+                ));
+            }
+        }
+        (collections, tokens, activities)
+    }
+}
+
+impl TokenActivityV2 {
+    pub fn from_events(
+        transaction_version: u64,
+        transaction_timestamp: chrono::NaiveDateTime,
+        events: &[APIEvent],
+    ) -> Vec<Self> {
+        events
+            .iter()
+            .enumerate()
+            .filter_map(|(index, event)| {
+                let type_ = event.typ.to_string();
+                let get_address = |field: &str| event.data[field].as_str().map(|a| a.to_string());
+                let (token_data_id, collection_id, from_address, to_address) = match type_.as_str()
+                {
+                    // Emitted by the collection
+                    MINT_EVENT_TYPE | BURN_EVENT_TYPE => (
+                        get_address("token")?,
+                        Some(event.guid.account_address.to_string()),
+                        None,
+                        None,
+                    ),
+                    TRANSFER_EVENT_TYPE => (
+                        get_address("object")?,
+                        None,
+                        get_address("from"),
+                        get_address("to"),
+                    ),
+                    _ => return None,
+                };
+                Some(Self {
+                    transaction_version: u64_to_bigdecimal(transaction_version),
+                    event_index: index as i64,
+                    token_data_id,
+                    collection_id,
+                    type_,
+                    from_address,
+                    to_address,
+                    transaction_timestamp,
+                    inserted_at: chrono::Utc::now().naive_utc(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl CurrentTokenOwnershipV2 {
+    /// Ownerships of the objects in `ownerships` that are tokens. Burning a token deletes its object.
+    pub fn from_object_ownerships(
+        ownerships: &[CurrentObjectOwnership],
+        token_data_ids: &HashSet<String>,
+    ) -> Vec<Self> {
+        ownerships
+            .iter()
+            .filter(|ownership| token_data_ids.contains(&ownership.object_address))
+            .map(|ownership| Self {
+                token_data_id: ownership.object_address.clone(),
+                owner_address: ownership.owner_address.clone(),
+                last_transaction_version: ownership.last_transaction_version.clone(),
+                is_burned: ownership.is_deleted,
+                inserted_at: ownership.inserted_at,
+            })
+            .collect()
+    }
+}
+
+/// The collections and tokens written by a transaction's write set. Supplies are resources of the collection's
+/// object, written alongside it.
+fn parse_write_set(
+    transaction_version: u64,
+    changes: &[APIWriteSetChange],
+) -> (Vec<CollectionV2>, Vec<TokenV2>) {
+    let mut supplies = HashMap::new();
+    let mut collections = vec![];
+    let mut tokens = vec![];
+    for (index, change) in changes.iter().enumerate() {
+        let (address, data) = match change {
+            APIWriteSetChange::WriteResource(WriteResource { address, data, .. }) => {
+                (address.to_string(), data)
+            }
+            _ => continue,
+        };
+        let value =
+            serde_json::to_value(&data.data).expect("Should be able to parse write resource data");
+        let get_string = |field: &str| value[field].as_str().unwrap_or_default().to_string();
+        match data.typ.to_string().as_str() {
+            COLLECTION_V2_TYPE => collections.push(CollectionV2 {
+                transaction_version: u64_to_bigdecimal(transaction_version),
+                write_set_change_index: index as i64,
+                collection_id: address,
+                creator_address: get_string("creator"),
+                collection_name: get_string("name"),
+                description: get_string("description"),
+                uri: get_string("uri"),
+                current_supply: None,
+                max_supply: None,
+                total_minted: None,
+                inserted_at: chrono::Utc::now().naive_utc(),
+            }),
+            FIXED_SUPPLY_TYPE | UNLIMITED_SUPPLY_TYPE => {
+                supplies.insert(address, value);
+            }
+            TOKEN_V2_TYPE => tokens.push(TokenV2 {
+                transaction_version: u64_to_bigdecimal(transaction_version),
+                write_set_change_index: index as i64,
+                token_data_id: address,
+                collection_id: value["collection"]["inner"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                token_name: get_string("name"),
+                description: get_string("description"),
+                uri: get_string("uri"),
+                token_index: get_u64_string(&value, "index"),
+                inserted_at: chrono::Utc::now().naive_utc(),
+            }),
+            _ => (),
+        }
+    }
+    for collection in &mut collections {
+        if let Some(supply) = supplies.get(&collection.collection_id) {
+            collection.current_supply = get_u64_string(supply, "current_supply");
+            collection.max_supply = get_u64_string(supply, "max_supply");
+            collection.total_minted = get_u64_string(supply, "total_minted");
+        }
+    }
+    (collections, tokens)
+}
+
+/// u64s are serialized as strings by the API
+fn get_u64_string(data: &serde_json::Value, field: &str) -> Option<BigDecimal> {
+    data.get(field)
+        .and_then(|value| value.as_str())
+        .and_then(|value| BigDecimal::from_str(value).ok())
+}
+
+// Prevent conflicts with the token v1 models
+pub type CollectionV2Model = CollectionV2;
+pub type TokenV2Model = TokenV2;
+pub type TokenActivityV2Model = TokenActivityV2;
+pub type CurrentTokenOwnershipV2Model = CurrentTokenOwnershipV2;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::object::Object;
+    use serde_json::json;
+
+    fn write_resource(address: &str, typ: &str, data: serde_json::Value) -> APIWriteSetChange {
+        serde_json::from_value(json!({
+            "type": "write_resource",
+            "address": address,
+            "state_key_hash": "0x1234",
+            "data": {"type": typ, "data": data},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_token_v2_write_set() {
+        let changes = vec![
+            write_resource(
+                "0xc011",
+                COLLECTION_V2_TYPE,
+                json!({"creator": "0xa11ce", "description": "", "name": "Collection", "uri": "https://aptos.dev"}),
+            ),
+            write_resource(
+                "0xc011",
+                FIXED_SUPPLY_TYPE,
+                json!({"current_supply": "1", "max_supply": "100", "total_minted": "1"}),
+            ),
+            write_resource(
+                "0x70c3",
+                TOKEN_V2_TYPE,
+                json!({"collection": {"inner": "0xc011"}, "index": "1", "description": "", "name": "Token #1", "uri": ""}),
+            ),
+            write_resource(
+                "0x70c3",
+                "0x1::object::ObjectCore",
+                json!({"allow_ungated_transfer": true, "guid_creation_num": "1125899906842625", "owner": "0xb0b"}),
+            ),
+        ];
+        let (collections, tokens) = parse_write_set(3, &changes);
+        assert_eq!(collections.len(), 1);
+        assert_eq!(collections[0].creator_address, "0xa11ce");
+        assert_eq!(collections[0].max_supply, Some(u64_to_bigdecimal(100)));
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_data_id, "0x70c3");
+        assert_eq!(tokens[0].collection_id, "0xc011");
+        assert_eq!(tokens[0].token_index, Some(u64_to_bigdecimal(1)));
+
+        let objects: Vec<Object> = changes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, wsc)| Object::from_write_set_change(3, index, wsc))
+            .collect();
+        let token_data_ids = tokens.iter().map(|t| t.token_data_id.clone()).collect();
+        let ownerships = CurrentTokenOwnershipV2::from_object_ownerships(
+            &CurrentObjectOwnership::from_objects(&objects),
+            &token_data_ids,
+        );
+        assert_eq!(ownerships.len(), 1);
+        assert_eq!(ownerships[0].owner_address, Some("0xb0b".to_string()));
+        assert!(!ownerships[0].is_burned);
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+#![allow(clippy::extra_unused_lifetimes)]
+#[cfg(feature = "postgres")]
+use crate::schema::top_holders;
+use crate::{
+    models::{
+        dex_swap::parse_struct_tag, fungible_asset::parse_write_set, transfer_edge::COIN_STORE_TYPE,
+    },
+    util::u64_to_bigdecimal,
+};
+use aptos_rest_client::aptos_api_types::{
+    Transaction as APITransaction, WriteResource, WriteSetChange as APIWriteSetChange,
+};
+use bigdecimal::BigDecimal;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Holders tracked per asset by default: queries can read up to this many
+pub const DEFAULT_TOP_HOLDERS_COUNT: u64 = 100;
+
+/// A holder of a coin or fungible asset, among the largest ones. Balances are absolute, so a row is simply replaced
+/// by the holder's latest balance.
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone, PartialEq)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "top_holders"))]
+#[cfg_attr(feature = "postgres", primary_key(asset_type, storage_id))]
+pub struct TopHolder {
+    /// Coin type (ex: `0x1::aptos_coin::AptosCoin`), or address of the fungible asset's metadata object
+    pub asset_type: String,
+    /// Account holding the `CoinStore`, or address of the `FungibleStore` object
+    pub storage_id: String,
+    /// NULL until a transaction writing the fungible store's `ObjectCore` is processed
+    pub owner_address: Option<String>,
+    pub amount: BigDecimal,
+    pub last_transaction_version: BigDecimal,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+impl TopHolder {
+    /// The latest balance of each coin and fungible store written by `transactions` (which must be in version
+    /// order), ordered by asset and store so concurrent batches upsert (and lock) rows in the same order
+    pub fn from_transactions(transactions: &[APITransaction]) -> Vec<Self> {
+        let mut holders: BTreeMap<(String, String), Self> = BTreeMap::new();
+        for transaction in transactions {
+            let info = match transaction.transaction_info() {
+                Ok(info) => info,
+                Err(_) => continue,
+            };
+            let version = *info.version.inner();
+            let (_, fungible_stores) = parse_write_set(version, &info.changes);
+            let stores = coin_stores(version, &info.changes).into_iter().chain(
+                fungible_stores.into_values().map(|balance| Self {
+                    asset_type: balance.asset_type,
+                    storage_id: balance.storage_id,
+                    owner_address: balance.owner_address,
+                    amount: balance.amount,
+                    last_transaction_version: balance.last_transaction_version,
+                    inserted_at: balance.inserted_at,
+                }),
+            );
+            for mut holder in stores {
+                let key = (holder.asset_type.clone(), holder.storage_id.clone());
+                // A fungible store's `ObjectCore` is only written when it's created or transferred
+                if holder.owner_address.is_none() {
+                    holder.owner_address = holders
+                        .get(&key)
+                        .and_then(|previous| previous.owner_address.clone());
+                }
+                holders.insert(key, holder);
+            }
+        }
+        holders.into_values().collect()
+    }
+}
+
+/// The balances of the `CoinStore<T>`s written by a transaction. Coin stores belong to the account holding them.
+fn coin_stores(transaction_version: u64, changes: &[APIWriteSetChange]) -> Vec<TopHolder> {
+    changes
+        .iter()
+        .filter_map(|change| {
+            let (address, data) = match change {
+                APIWriteSetChange::WriteResource(WriteResource { address, data, .. }) => {
+                    (address.to_string(), data)
+                }
+                _ => return None,
+            };
+            let type_ = data.typ.to_string();
+            let coin_type = match parse_struct_tag(&type_) {
+                Some((struct_address, module, name, mut type_args))
+                    if format!("{}::{}::{}", struct_address, module, name) == COIN_STORE_TYPE
+                        && type_args.len() == 1 =>
+                {
+                    type_args.remove(0)
+                }
+                _ => return None,
+            };
+            let value = serde_json::to_value(&data.data)
+                .expect("Should be able to parse write resource data");
+            Some(TopHolder {
+                asset_type: coin_type,
+                storage_id: address.clone(),
+                owner_address: Some(address),
+                amount: value["coin"]["value"]
+                    .as_str()
+                    .and_then(|amount| BigDecimal::from_str(amount).ok())?,
+                last_transaction_version: u64_to_bigdecimal(transaction_version),
+                inserted_at: chrono::Utc::now().naive_utc(),
+            })
+        })
+        .collect()
+}
+
+// Prevent conflicts with other things named `TopHolder`
+pub type TopHolderModel = TopHolder;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{fungible_asset::FUNGIBLE_STORE_TYPE, object::OBJECT_CORE_TYPE};
+    use serde_json::json;
+
+    fn write_resource(address: &str, typ: &str, data: serde_json::Value) -> APIWriteSetChange {
+        serde_json::from_value(json!({
+            "type": "write_resource",
+            "address": address,
+            "state_key_hash": "0x1234",
+            "data": {"type": typ, "data": data},
+        }))
+        .unwrap()
+    }
+
+    fn coin_store(value: &str) -> serde_json::Value {
+        json!({
+            "coin": {"value": value},
+            "frozen": false,
+            "deposit_events": {"counter": "0", "guid": {"id": {"addr": "0xb0b", "creation_num": "2"}}},
+            "withdraw_events": {"counter": "0", "guid": {"id": {"addr": "0xb0b", "creation_num": "3"}}},
+        })
+    }
+
+    #[test]
+    fn test_top_holders_from_write_sets() {
+        let apt = "0x1::coin::CoinStore<0x1::aptos_coin::AptosCoin>";
+        let holders = coin_stores(
+            7,
+            &[
+                write_resource("0xb0b", apt, coin_store("100")),
+                write_resource("0xb0b", "0x1::account::Account", json!({})),
+            ],
+        );
+        assert_eq!(holders.len(), 1);
+        assert_eq!(holders[0].asset_type, "0x1::aptos_coin::AptosCoin");
+        assert_eq!(holders[0].storage_id, "0xb0b");
+        assert_eq!(holders[0].owner_address, Some("0xb0b".to_string()));
+        assert_eq!(holders[0].amount, u64_to_bigdecimal(100));
+
+        let (_, fungible_stores) = parse_write_set(
+            8,
+            &[
+                write_resource(
+                    "0xb0b5",
+                    FUNGIBLE_STORE_TYPE,
+                    json!({"metadata": {"inner": "0xa55e7"}, "balance": "5", "frozen": false}),
+                ),
+                write_resource(
+                    "0xb0b5",
+                    OBJECT_CORE_TYPE,
+                    json!({"allow_ungated_transfer": false, "guid_creation_num": "1125899906842625", "owner": "0xb0b"}),
+                ),
+            ],
+        );
+        assert_eq!(
+            fungible_stores["0xb0b5"].owner_address,
+            Some("0xb0b".to_string())
+        );
+    }
+}
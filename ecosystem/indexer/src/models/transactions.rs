@@ -5,30 +5,40 @@
 #![allow(clippy::extra_unused_lifetimes)]
 #![allow(clippy::unused_unit)]
 
+#[cfg(feature = "postgres")]
 use crate::{
     database::PgPoolConnection,
-    models::{events::EventModel, write_set_changes::WriteSetChangeModel},
     schema::{block_metadata_transactions, transactions, user_transactions},
+};
+use crate::{
+    models::{events::EventModel, write_set_changes::WriteSetChangeModel},
     util::u64_to_bigdecimal,
 };
 use aptos_rest_client::aptos_api_types::{
     Address, BlockMetadataTransaction as APIBlockMetadataTransaction,
     Transaction as APITransaction, TransactionInfo, UserTransaction as APIUserTransaction, U64,
 };
+#[cfg(feature = "postgres")]
 use diesel::{
     BelongingToDsl, ExpressionMethods, GroupedBy, OptionalExtension, QueryDsl, RunQueryDsl,
 };
 use field_count::FieldCount;
 use futures::future::Either;
-use serde::Serialize;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
 static SECONDS_IN_10_YEARS: i64 = 60 * 60 * 24 * 365 * 10;
 
-#[derive(AsChangeset, Debug, FieldCount, Identifiable, Insertable, Queryable, Serialize)]
-#[primary_key(hash)]
-#[diesel(table_name = "transactions")]
+#[derive(Debug, Deserialize, FieldCount, Serialize)]
+#[cfg_attr(
+    feature = "postgres",
+    derive(AsChangeset, Identifiable, Insertable, Queryable)
+)]
+#[cfg_attr(feature = "postgres", primary_key(hash))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "transactions"))]
 pub struct Transaction {
-    #[diesel(column_name = type)]
+    #[cfg_attr(feature = "postgres", diesel(column_name = type))]
+    #[serde(rename = "type")]
     pub type_: String,
     pub payload: serde_json::Value,
     pub version: bigdecimal::BigDecimal,
@@ -41,8 +51,15 @@ pub struct Transaction {
     pub accumulator_root_hash: String,
     // Default time columns
     pub inserted_at: chrono::NaiveDateTime,
+    pub num_events: i64,
+    pub num_write_set_changes: i64,
+    /// Size of the JSON serialized payload. NULL for transactions indexed before it was recorded.
+    pub payload_size_bytes: Option<i64>,
+    /// Timestamp of the transaction's block. Genesis' is the epoch (it has none).
+    pub timestamp: chrono::NaiveDateTime,
 }
 
+#[cfg(feature = "postgres")]
 impl Transaction {
     pub fn get_many_by_version(
         start_version: u64,
@@ -73,11 +90,13 @@ impl Transaction {
                 .grouped_by(&txs);
 
         let mut events: Vec<Vec<EventModel>> = EventModel::belonging_to(&txs)
+            .order(crate::schema::events::event_index.asc())
             .load::<EventModel>(connection)?
             .grouped_by(&txs);
 
         let mut write_set_changes: Vec<Vec<WriteSetChangeModel>> =
             WriteSetChangeModel::belonging_to(&txs)
+                .order(crate::schema::write_set_changes::write_set_change_index.asc())
                 .load::<WriteSetChangeModel>(connection)?
                 .grouped_by(&txs);
 
@@ -162,10 +181,12 @@ impl Transaction {
 
         let events = crate::schema::events::table
             .filter(crate::schema::events::transaction_hash.eq(&self.hash))
+            .order(crate::schema::events::event_index.asc())
             .load::<EventModel>(connection)?;
 
         let write_set_changes = crate::schema::write_set_changes::table
             .filter(crate::schema::write_set_changes::transaction_hash.eq(&self.hash))
+            .order(crate::schema::write_set_changes::write_set_change_index.asc())
             .load::<WriteSetChangeModel>(connection)?;
 
         match self.type_.as_str() {
@@ -192,7 +213,9 @@ impl Transaction {
             write_set_changes,
         ))
     }
+}
 
+impl Transaction {
     pub fn from_transaction(
         transaction: &APITransaction,
     ) -> (
@@ -201,7 +224,7 @@ impl Transaction {
         Option<Vec<EventModel>>,
         Option<Vec<WriteSetChangeModel>>,
     ) {
-        match transaction {
+        let (mut txn, user_or_bmt, maybe_event_list, maybe_wsc_list) = match transaction {
             APITransaction::UserTransaction(tx) => (
                 Self::from_transaction_info(
                     &tx.info,
@@ -210,9 +233,14 @@ impl Transaction {
                     transaction.type_str().to_string(),
                 ),
                 Some(Either::Left(UserTransaction::from_transaction(tx))),
-                EventModel::from_events(tx.info.hash.to_string(), &tx.events),
+                EventModel::from_events(
+                    tx.info.hash.to_string(),
+                    parse_timestamp(tx.timestamp, tx.info.version),
+                    &tx.events,
+                ),
                 WriteSetChangeModel::from_write_set_changes(
                     tx.info.hash.to_string(),
+                    parse_timestamp(tx.timestamp, tx.info.version),
                     &tx.info.changes,
                 ),
             ),
@@ -224,9 +252,14 @@ impl Transaction {
                     transaction.type_str().to_string(),
                 ),
                 None,
-                EventModel::from_events(tx.info.hash.to_string(), &tx.events),
+                EventModel::from_events(
+                    tx.info.hash.to_string(),
+                    chrono::NaiveDateTime::from_timestamp(0, 0),
+                    &tx.events,
+                ),
                 WriteSetChangeModel::from_write_set_changes(
                     tx.info.hash.to_string(),
+                    chrono::NaiveDateTime::from_timestamp(0, 0),
                     &tx.info.changes,
                 ),
             ),
@@ -239,9 +272,14 @@ impl Transaction {
                 Some(Either::Right(BlockMetadataTransaction::from_transaction(
                     tx,
                 ))),
-                EventModel::from_events(tx.info.hash.to_string(), &tx.events),
+                EventModel::from_events(
+                    tx.info.hash.to_string(),
+                    parse_timestamp(tx.timestamp, tx.info.version),
+                    &tx.events,
+                ),
                 WriteSetChangeModel::from_write_set_changes(
                     tx.info.hash.to_string(),
+                    parse_timestamp(tx.timestamp, tx.info.version),
                     &tx.info.changes,
                 ),
             ),
@@ -258,7 +296,16 @@ impl Transaction {
             APITransaction::PendingTransaction(..) => {
                 unreachable!()
             }
-        }
+        };
+        txn.num_events = maybe_event_list
+            .as_ref()
+            .map_or(0, |event_list| event_list.len() as i64);
+        txn.num_write_set_changes = maybe_wsc_list
+            .as_ref()
+            .map_or(0, |wsc_list| wsc_list.len() as i64);
+        txn.timestamp =
+            chrono::NaiveDateTime::from_timestamp((transaction.timestamp() / 1_000_000) as i64, 0);
+        (txn, user_or_bmt, maybe_event_list, maybe_wsc_list)
     }
 
     fn from_transaction_info(
@@ -266,6 +313,13 @@ impl Transaction {
         payload: serde_json::Value,
         type_: String,
     ) -> Self {
+        let payload_size_bytes = if payload.is_null() {
+            0
+        } else {
+            serde_json::to_vec(&payload)
+                .expect("Unable to serialize transaction payload")
+                .len() as i64
+        };
         Self {
             type_,
             payload,
@@ -278,6 +332,12 @@ impl Transaction {
             vm_status: info.vm_status.clone(),
             accumulator_root_hash: info.accumulator_root_hash.to_string(),
             inserted_at: chrono::Utc::now().naive_utc(),
+            // Set from the events and write set changes in `from_transaction`
+            num_events: 0,
+            num_write_set_changes: 0,
+            payload_size_bytes: Some(payload_size_bytes),
+            // Set from the transaction in `from_transaction`
+            timestamp: chrono::NaiveDateTime::from_timestamp(0, 0),
         }
     }
 
@@ -289,15 +349,51 @@ impl Transaction {
         Vec<BlockMetadataTransaction>,
         Vec<EventModel>,
         Vec<WriteSetChangeModel>,
+    ) {
+        Self::merge_converted(transactions.iter().map(Self::from_transaction))
+    }
+
+    /// Like `from_transactions`, but converts the transactions on rayon's thread pool, which is faster for big
+    /// batches. The models are still returned in version order.
+    pub fn from_transactions_parallel(
+        transactions: &[APITransaction],
+    ) -> (
+        Vec<Self>,
+        Vec<UserTransaction>,
+        Vec<BlockMetadataTransaction>,
+        Vec<EventModel>,
+        Vec<WriteSetChangeModel>,
+    ) {
+        let converted: Vec<_> = transactions
+            .par_iter()
+            .map(Self::from_transaction)
+            .collect();
+        Self::merge_converted(converted.into_iter())
+    }
+
+    /// Merges the output of `from_transaction` for each transaction, in order, into one vector per model
+    fn merge_converted(
+        converted: impl Iterator<
+            Item = (
+                Transaction,
+                Option<Either<UserTransaction, BlockMetadataTransaction>>,
+                Option<Vec<EventModel>>,
+                Option<Vec<WriteSetChangeModel>>,
+            ),
+        >,
+    ) -> (
+        Vec<Self>,
+        Vec<UserTransaction>,
+        Vec<BlockMetadataTransaction>,
+        Vec<EventModel>,
+        Vec<WriteSetChangeModel>,
     ) {
         let mut txns = vec![];
         let mut user_txns = vec![];
         let mut bm_txns = vec![];
         let mut events = vec![];
         let mut wscs = vec![];
-        for (txn, user_or_bmt, maybe_event_list, maybe_wsc_list) in
-            transactions.iter().map(Self::from_transaction)
-        {
+        for (txn, user_or_bmt, maybe_event_list, maybe_wsc_list) in converted {
             txns.push(txn);
             match user_or_bmt {
                 Some(Either::Left(user_transaction_model)) => {
@@ -335,12 +431,14 @@ impl Transaction {
     }
 }
 
-#[derive(
-    AsChangeset, Associations, Debug, FieldCount, Identifiable, Insertable, Queryable, Serialize,
+#[derive(Debug, Deserialize, FieldCount, Serialize)]
+#[cfg_attr(
+    feature = "postgres",
+    derive(AsChangeset, Associations, Identifiable, Insertable, Queryable)
 )]
-#[belongs_to(Transaction, foreign_key = "hash")]
-#[primary_key(hash)]
-#[diesel(table_name = "user_transactions")]
+#[cfg_attr(feature = "postgres", belongs_to(Transaction, foreign_key = "hash"))]
+#[cfg_attr(feature = "postgres", primary_key(hash))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "user_transactions"))]
 pub struct UserTransaction {
     pub hash: String,
     pub signature: serde_json::Value,
@@ -379,12 +477,17 @@ impl UserTransaction {
     }
 }
 
-#[derive(
-    AsChangeset, Associations, Debug, FieldCount, Identifiable, Insertable, Queryable, Serialize,
+#[derive(Debug, Deserialize, FieldCount, Serialize)]
+#[cfg_attr(
+    feature = "postgres",
+    derive(AsChangeset, Associations, Identifiable, Insertable, Queryable)
+)]
+#[cfg_attr(feature = "postgres", belongs_to(Transaction, foreign_key = "hash"))]
+#[cfg_attr(feature = "postgres", primary_key("hash"))]
+#[cfg_attr(
+    feature = "postgres",
+    diesel(table_name = "block_metadata_transactions")
 )]
-#[belongs_to(Transaction, foreign_key = "hash")]
-#[primary_key("hash")]
-#[diesel(table_name = "block_metadata_transactions")]
 pub struct BlockMetadataTransaction {
     pub hash: String,
     pub id: String,
@@ -421,7 +524,7 @@ impl BlockMetadataTransaction {
     }
 }
 
-fn parse_timestamp(ts: U64, version: U64) -> chrono::NaiveDateTime {
+pub fn parse_timestamp(ts: U64, version: U64) -> chrono::NaiveDateTime {
     chrono::NaiveDateTime::from_timestamp_opt((*ts.inner() / 1000000) as i64, 0)
         .unwrap_or_else(|| panic!("Could not parse timestamp {:?} for version {}", ts, version))
 }
@@ -460,4 +563,174 @@ mod tests {
         let ts3 = parse_timestamp_secs(U64::from(1659386386), U64::from(2));
         assert_eq!(ts3.timestamp(), 1659386386);
     }
+
+    fn test_transaction(version: u64) -> APITransaction {
+        let info = serde_json::json!({
+            "version": version.to_string(),
+            "hash": format!("0x{:064x}", version),
+            "state_change_hash": format!("0x{:064x}", 0),
+            "event_root_hash": format!("0x{:064x}", 0),
+            "gas_used": "0",
+            "success": true,
+            "vm_status": "Executed successfully",
+            "accumulator_root_hash": format!("0x{:064x}", 0),
+            "changes": [],
+            "timestamp": "1649395495746947",
+        });
+        let mut txn = info.as_object().unwrap().clone();
+        if version % 2 == 0 {
+            txn.insert("type".into(), "state_checkpoint_transaction".into());
+        } else {
+            txn.insert("type".into(), "block_metadata_transaction".into());
+            txn.extend(
+                serde_json::json!({
+                    "id": format!("0x{:064x}", version),
+                    "epoch": "1",
+                    "round": version.to_string(),
+                    "previous_block_votes_bitvec": [],
+                    "proposer": "0x1",
+                    "failed_proposer_indices": [],
+                    "events": [{
+                        "guid": {"account_address": "0x1", "creation_number": "3"},
+                        "sequence_number": version.to_string(),
+                        "type": "0x1::block::NewBlockEvent",
+                        "data": {},
+                    }],
+                })
+                .as_object()
+                .unwrap()
+                .clone(),
+            );
+        }
+        serde_json::from_value(serde_json::Value::Object(txn)).unwrap()
+    }
+
+    #[test]
+    fn test_parallel_conversion_keeps_version_order() {
+        let transactions: Vec<APITransaction> = (1..=200).map(test_transaction).collect();
+        let (txns, _, bm_txns, events, _) = Transaction::from_transactions(&transactions);
+        let (parallel_txns, _, parallel_bm_txns, parallel_events, _) =
+            Transaction::from_transactions_parallel(&transactions);
+
+        let hashes = |txns: &[Transaction]| txns.iter().map(|t| t.hash.clone()).collect::<Vec<_>>();
+        assert_eq!(hashes(&txns), hashes(&parallel_txns));
+        assert_eq!(txns.len(), 200);
+        assert_eq!(
+            bm_txns.iter().map(|t| &t.hash).collect::<Vec<_>>(),
+            parallel_bm_txns.iter().map(|t| &t.hash).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            events
+                .iter()
+                .map(|e| &e.transaction_hash)
+                .collect::<Vec<_>>(),
+            parallel_events
+                .iter()
+                .map(|e| &e.transaction_hash)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(parallel_events.len(), 100);
+    }
+
+    /// `inserted_at` is the time of conversion, so goldens leave it out
+    fn without_inserted_at(value: serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::Array(values) => {
+                values.into_iter().map(without_inserted_at).collect()
+            }
+            serde_json::Value::Object(mut fields) => {
+                fields.remove("inserted_at");
+                fields
+                    .into_iter()
+                    .map(|(key, value)| (key, without_inserted_at(value)))
+                    .collect()
+            }
+            value => value,
+        }
+    }
+
+    /// Path and values of the first difference between two JSON values, for a readable failure
+    fn first_difference(
+        path: String,
+        expected: &serde_json::Value,
+        actual: &serde_json::Value,
+    ) -> Option<String> {
+        match (expected, actual) {
+            (serde_json::Value::Object(expected), serde_json::Value::Object(actual)) => {
+                expected.keys().chain(actual.keys()).find_map(|key| {
+                    first_difference(
+                        format!("{}.{}", path, key),
+                        expected.get(key).unwrap_or(&serde_json::Value::Null),
+                        actual.get(key).unwrap_or(&serde_json::Value::Null),
+                    )
+                })
+            }
+            (serde_json::Value::Array(expected), serde_json::Value::Array(actual))
+                if expected.len() == actual.len() =>
+            {
+                expected
+                    .iter()
+                    .zip(actual)
+                    .enumerate()
+                    .find_map(|(i, (expected, actual))| {
+                        first_difference(format!("{}[{}]", path, i), expected, actual)
+                    })
+            }
+            _ if expected == actual => None,
+            _ => Some(format!("{}: expected {}, got {}", path, expected, actual)),
+        }
+    }
+
+    /// Converts the recorded transactions in `testdata/model_conversion/transactions` and compares the models to
+    /// the goldens of the same name in `testdata/model_conversion/goldens`. After an intended change, regenerate the
+    /// goldens with `UPDATE_GOLDENFILES=1 cargo test -p aptos-indexer test_golden_model_conversion` and review the diff.
+    #[test]
+    fn test_golden_model_conversion() {
+        let dir =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/model_conversion");
+        let update = std::env::var("UPDATE_GOLDENFILES").is_ok();
+        let mut fixtures: Vec<_> = std::fs::read_dir(dir.join("transactions"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        fixtures.sort();
+        assert!(!fixtures.is_empty());
+
+        for fixture in fixtures {
+            let name = fixture.file_name().unwrap().to_str().unwrap().to_string();
+            let transactions: Vec<APITransaction> =
+                serde_json::from_slice(&std::fs::read(&fixture).unwrap())
+                    .unwrap_or_else(|e| panic!("Failed to parse {}: {}", name, e));
+            let (txns, user_txns, bm_txns, events, write_set_changes) =
+                Transaction::from_transactions(&transactions);
+            let actual = without_inserted_at(serde_json::json!({
+                "transactions": txns,
+                "user_transactions": user_txns,
+                "block_metadata_transactions": bm_txns,
+                "events": events,
+                "write_set_changes": write_set_changes,
+            }));
+
+            let golden = dir.join("goldens").join(&name);
+            if update {
+                let mut contents = serde_json::to_string_pretty(&actual).unwrap();
+                contents.push('\n');
+                std::fs::write(&golden, contents).unwrap();
+                continue;
+            }
+            let expected: serde_json::Value =
+                serde_json::from_slice(&std::fs::read(&golden).unwrap_or_else(|e| {
+                    panic!("Missing golden for {} ({}), see the test's doc", name, e)
+                }))
+                .unwrap();
+            if let Some(difference) = first_difference(String::new(), &expected, &actual) {
+                panic!(
+                    "Models converted from {} don't match the golden at {}\nActual:\n{}",
+                    name,
+                    difference,
+                    serde_json::to_string_pretty(&actual).unwrap()
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,349 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+#![allow(clippy::extra_unused_lifetimes)]
+#[cfg(feature = "postgres")]
+use crate::schema::transfer_edges;
+use crate::{
+    models::{dex_swap::parse_struct_tag, token::TokenId, transactions::parse_timestamp},
+    util::u64_to_bigdecimal,
+};
+use aptos_rest_client::aptos_api_types::{
+    Event as APIEvent, Transaction as APITransaction, WriteResource,
+    WriteSetChange as APIWriteSetChange,
+};
+use bigdecimal::BigDecimal;
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, str::FromStr};
+
+pub const COIN_STORE_TYPE: &str = "0x1::coin::CoinStore";
+pub const COIN_DEPOSIT_EVENT_TYPE: &str = "0x1::coin::DepositEvent";
+pub const COIN_WITHDRAW_EVENT_TYPE: &str = "0x1::coin::WithdrawEvent";
+pub const TOKEN_DEPOSIT_EVENT_TYPE: &str = "0x3::token::DepositEvent";
+pub const TOKEN_WITHDRAW_EVENT_TYPE: &str = "0x3::token::WithdrawEvent";
+
+pub const COIN_KIND: &str = "coin";
+pub const TOKEN_KIND: &str = "token";
+
+/// An amount of a coin or token moving between two accounts, from a withdraw event matched with a deposit event of
+/// the same transaction. Deposits without a matching withdrawal are mints, and withdrawals without a matching
+/// deposit are burns.
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "transfer_edges"))]
+#[cfg_attr(feature = "postgres", primary_key(transaction_version, event_index))]
+pub struct TransferEdge {
+    pub transaction_version: BigDecimal,
+    /// Index of the deposit event, or of the withdraw event for burns
+    pub event_index: i64,
+    /// NULL for mints
+    pub from_address: Option<String>,
+    /// NULL for burns
+    pub to_address: Option<String>,
+    /// Coin type, or token id (`creator::collection::name::property_version`)
+    pub asset: String,
+    pub amount: BigDecimal,
+    /// `coin` or `token`
+    pub kind: String,
+    pub transaction_timestamp: chrono::NaiveDateTime,
+    pub inserted_at: chrono::NaiveDateTime,
+}
+
+/// A deposit or withdraw event, with the account it's from and the asset it moved
+struct Movement {
+    event_index: usize,
+    account: String,
+    asset: String,
+    amount: BigDecimal,
+    kind: &'static str,
+    is_deposit: bool,
+}
+
+impl TransferEdge {
+    /// Transfer edges of the user transactions in `transactions`
+    pub fn from_transactions(transactions: &[APITransaction]) -> Vec<Self> {
+        let mut edges = vec![];
+        for transaction in transactions {
+            if let APITransaction::UserTransaction(user_txn) = transaction {
+                edges.extend(Self::from_events(
+                    *user_txn.info.version.inner(),
+                    parse_timestamp(user_txn.timestamp, user_txn.info.version),
+                    &user_txn.events,
+                    &user_txn.info.changes,
+                ));
+            }
+        }
+        edges
+    }
+
+    /// Matches each deposit with the first unmatched withdrawal of the same asset and amount, in event order
+    pub fn from_events(
+        transaction_version: u64,
+        transaction_timestamp: chrono::NaiveDateTime,
+        events: &[APIEvent],
+        changes: &[APIWriteSetChange],
+    ) -> Vec<Self> {
+        let coin_types = coin_types_by_event_handle(changes);
+        let movements: Vec<Movement> = events
+            .iter()
+            .enumerate()
+            .filter_map(|(index, event)| Movement::from_event(index, event, &coin_types))
+            .collect();
+
+        let mut matched = vec![false; movements.len()];
+        let mut edges = vec![];
+        let edge = |from: Option<&Movement>, to: Option<&Movement>, movement: &Movement| Self {
+            transaction_version: u64_to_bigdecimal(transaction_version),
+            event_index: movement.event_index as i64,
+            from_address: from.map(|from| from.account.clone()),
+            to_address: to.map(|to| to.account.clone()),
+            asset: movement.asset.clone(),
+            amount: movement.amount.clone(),
+            kind: movement.kind.to_string(),
+            transaction_timestamp,
+            inserted_at: chrono::Utc::now().naive_utc(),
+        };
+        for (deposit_index, deposit) in movements.iter().enumerate() {
+            if !deposit.is_deposit {
+                continue;
+            }
+            let withdrawal_index = (0..movements.len()).find(|&index| {
+                let withdrawal = &movements[index];
+                !withdrawal.is_deposit
+                    && !matched[index]
+                    && withdrawal.kind == deposit.kind
+                    && withdrawal.asset == deposit.asset
+                    && withdrawal.amount == deposit.amount
+            });
+            matched[deposit_index] = true;
+            match withdrawal_index {
+                Some(withdrawal_index) => {
+                    matched[withdrawal_index] = true;
+                    edges.push(edge(
+                        Some(&movements[withdrawal_index]),
+                        Some(deposit),
+                        deposit,
+                    ));
+                }
+                None => edges.push(edge(None, Some(deposit), deposit)),
+            }
+        }
+        for (index, withdrawal) in movements.iter().enumerate() {
+            if !matched[index] {
+                edges.push(edge(Some(withdrawal), None, withdrawal));
+            }
+        }
+        edges.sort_by_key(|edge| edge.event_index);
+        edges
+    }
+}
+
+impl Movement {
+    fn from_event(
+        event_index: usize,
+        event: &APIEvent,
+        coin_types: &HashMap<(String, u64), String>,
+    ) -> Option<Self> {
+        let account = event.guid.account_address.to_string();
+        let type_ = event.typ.to_string();
+        let (asset, kind, is_deposit) = match type_.as_str() {
+            COIN_DEPOSIT_EVENT_TYPE | COIN_WITHDRAW_EVENT_TYPE => (
+                coin_types
+                    .get(&(account.clone(), *event.guid.creation_number.inner()))?
+                    .clone(),
+                COIN_KIND,
+                type_ == COIN_DEPOSIT_EVENT_TYPE,
+            ),
+            TOKEN_DEPOSIT_EVENT_TYPE | TOKEN_WITHDRAW_EVENT_TYPE => (
+                serde_json::from_value::<TokenId>(event.data["id"].clone())
+                    .ok()?
+                    .to_string(),
+                TOKEN_KIND,
+                type_ == TOKEN_DEPOSIT_EVENT_TYPE,
+            ),
+            _ => return None,
+        };
+        Some(Self {
+            event_index,
+            account,
+            asset,
+            amount: event.data["amount"]
+                .as_str()
+                .and_then(|amount| BigDecimal::from_str(amount).ok())?,
+            kind,
+            is_deposit,
+        })
+    }
+}
+
+/// Coin events don't say which coin they're for, but the `CoinStore<T>` emitting them is written by the same
+/// transaction: maps (account, creation number) of its event handles to `T`
+fn coin_types_by_event_handle(changes: &[APIWriteSetChange]) -> HashMap<(String, u64), String> {
+    let mut coin_types = HashMap::new();
+    for change in changes {
+        let (address, data) = match change {
+            APIWriteSetChange::WriteResource(WriteResource { address, data, .. }) => {
+                (address.to_string(), data)
+            }
+            _ => continue,
+        };
+        let type_ = data.typ.to_string();
+        let coin_type = match parse_struct_tag(&type_) {
+            Some((struct_address, module, name, mut type_args))
+                if format!("{}::{}::{}", struct_address, module, name) == COIN_STORE_TYPE
+                    && type_args.len() == 1 =>
+            {
+                type_args.remove(0)
+            }
+            _ => continue,
+        };
+        let value =
+            serde_json::to_value(&data.data).expect("Should be able to parse write resource data");
+        for handle in ["deposit_events", "withdraw_events"] {
+            if let Some(creation_num) = value[handle]["guid"]["id"]["creation_num"]
+                .as_str()
+                .and_then(|num| num.parse::<u64>().ok())
+            {
+                coin_types.insert((address.clone(), creation_num), coin_type.clone());
+            }
+        }
+    }
+    coin_types
+}
+
+// Prevent conflicts with other things named `TransferEdge`
+pub type TransferEdgeModel = TransferEdge;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event(
+        account: &str,
+        creation_number: u64,
+        type_: &str,
+        data: serde_json::Value,
+    ) -> APIEvent {
+        serde_json::from_value(json!({
+            "guid": {"creation_number": creation_number.to_string(), "account_address": account},
+            "sequence_number": "0",
+            "type": type_,
+            "data": data,
+        }))
+        .unwrap()
+    }
+
+    fn coin_store(account: &str) -> APIWriteSetChange {
+        serde_json::from_value(json!({
+            "type": "write_resource",
+            "address": account,
+            "state_key_hash": "0x1234",
+            "data": {
+                "type": "0x1::coin::CoinStore<0x1::aptos_coin::AptosCoin>",
+                "data": {
+                    "coin": {"value": "100"},
+                    "deposit_events": {"counter": "1", "guid": {"id": {"addr": account, "creation_num": "2"}}},
+                    "frozen": false,
+                    "withdraw_events": {"counter": "1", "guid": {"id": {"addr": account, "creation_num": "3"}}},
+                },
+            },
+        }))
+        .unwrap()
+    }
+
+    fn token_event(account: &str, type_: &str, amount: &str) -> APIEvent {
+        event(
+            account,
+            4,
+            type_,
+            json!({
+                "amount": amount,
+                "id": {
+                    "token_data_id": {"creator": "0xc0ffee", "collection": "Cats", "name": "Tom"},
+                    "property_version": "0",
+                },
+            }),
+        )
+    }
+
+    #[test]
+    fn test_transfer_edges() {
+        let now = chrono::Utc::now().naive_utc();
+        let events = vec![
+            event(
+                "0xa11ce",
+                3,
+                COIN_WITHDRAW_EVENT_TYPE,
+                json!({"amount": "10"}),
+            ),
+            event("0xb0b", 2, COIN_DEPOSIT_EVENT_TYPE, json!({"amount": "10"})),
+            token_event("0xa11ce", TOKEN_WITHDRAW_EVENT_TYPE, "1"),
+            token_event("0xb0b", TOKEN_DEPOSIT_EVENT_TYPE, "1"),
+            // Minted to, and burned from, the same accounts
+            event(
+                "0xa11ce",
+                2,
+                COIN_DEPOSIT_EVENT_TYPE,
+                json!({"amount": "7"}),
+            ),
+            token_event("0xb0b", TOKEN_WITHDRAW_EVENT_TYPE, "2"),
+        ];
+        let changes = vec![coin_store("0xa11ce"), coin_store("0xb0b")];
+        let edges = TransferEdge::from_events(5, now, &events, &changes);
+        let summary: Vec<_> = edges
+            .iter()
+            .map(|edge| {
+                (
+                    edge.event_index,
+                    edge.from_address.as_deref(),
+                    edge.to_address.as_deref(),
+                    edge.asset.as_str(),
+                    edge.amount.to_string(),
+                    edge.kind.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    1,
+                    Some("0xa11ce"),
+                    Some("0xb0b"),
+                    "0x1::aptos_coin::AptosCoin",
+                    "10".to_string(),
+                    COIN_KIND
+                ),
+                (
+                    3,
+                    Some("0xa11ce"),
+                    Some("0xb0b"),
+                    "0xc0ffee::Cats::Tom::0",
+                    "1".to_string(),
+                    TOKEN_KIND
+                ),
+                (
+                    4,
+                    None,
+                    Some("0xa11ce"),
+                    "0x1::aptos_coin::AptosCoin",
+                    "7".to_string(),
+                    COIN_KIND
+                ),
+                (
+                    5,
+                    Some("0xb0b"),
+                    None,
+                    "0xc0ffee::Cats::Tom::0",
+                    "2".to_string(),
+                    TOKEN_KIND
+                ),
+            ]
+        );
+
+        // Coin events of stores the transaction didn't write are skipped
+        let edges = TransferEdge::from_events(5, now, &events[..2], &[]);
+        assert!(edges.is_empty());
+    }
+}
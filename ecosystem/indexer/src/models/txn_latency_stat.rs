@@ -0,0 +1,194 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+#![allow(clippy::extra_unused_lifetimes)]
+#[cfg(feature = "postgres")]
+use crate::schema::{txn_latency_stat_batches as txn_latency_stat_batchs, txn_latency_stats};
+use crate::util::u64_to_bigdecimal;
+use aptos_rest_client::aptos_api_types::Transaction as APITransaction;
+use bigdecimal::BigDecimal;
+use chrono::{NaiveDateTime, Timelike};
+use field_count::FieldCount;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// How long before their expiration transactions are assumed to have been submitted. SDKs and wallets set the
+/// expiration to the time of submission plus a fixed duration, which is 30 seconds for the Aptos CLI and Rust SDK.
+pub const DEFAULT_EXPIRATION_TTL_SECS: u64 = 30;
+
+/// Lower bounds of the latency buckets, in seconds
+pub const LATENCY_BUCKETS_SECS: &[i64] = &[0, 1, 2, 3, 5, 10, 15, 20, 30, 60, 120, 300, 600];
+
+/// How many user transactions committed in a minute with an estimated latency in a bucket. Rows built from a batch
+/// hold the batch's contribution, which is added to the stored row when upserted.
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone, PartialEq)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", primary_key(period_start, latency_bucket_secs))]
+pub struct TxnLatencyStat {
+    /// Block timestamp, truncated to the minute
+    pub period_start: NaiveDateTime,
+    /// Lower bound of the latency bucket (see `LATENCY_BUCKETS_SECS`)
+    pub latency_bucket_secs: i64,
+    pub num_transactions: i64,
+    /// Sum of the estimated latencies, for averages
+    pub total_latency_ms: i64,
+    pub inserted_at: NaiveDateTime,
+    pub last_updated: NaiveDateTime,
+}
+
+impl TxnLatencyStat {
+    /// Estimated time from submission to commit: the block's timestamp minus the expiration less
+    /// `expiration_ttl_secs`. Transactions expiring later than assumed would have negative latencies, so those are 0.
+    pub fn estimate_latency_ms(
+        block_timestamp_us: u64,
+        expiration_timestamp_secs: u64,
+        expiration_ttl_secs: u64,
+    ) -> i64 {
+        let submitted_ms =
+            (expiration_timestamp_secs as i64 - expiration_ttl_secs as i64).saturating_mul(1000);
+        std::cmp::max((block_timestamp_us / 1000) as i64 - submitted_ms, 0)
+    }
+
+    /// Lower bound of the bucket of `latency_ms`
+    pub fn latency_bucket_secs(latency_ms: i64) -> i64 {
+        LATENCY_BUCKETS_SECS
+            .iter()
+            .rev()
+            .find(|bound| latency_ms >= **bound * 1000)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The contribution of the user transactions in `transactions` to each minute's distribution, ordered by key so
+    /// concurrent batches upsert (and lock) rows in the same order
+    pub fn from_transactions(
+        transactions: &[APITransaction],
+        expiration_ttl_secs: u64,
+    ) -> Vec<Self> {
+        let now = chrono::Utc::now().naive_utc();
+        let mut stats: BTreeMap<(NaiveDateTime, i64), Self> = BTreeMap::new();
+        for transaction in transactions {
+            let user_txn = match transaction {
+                APITransaction::UserTransaction(user_txn) => user_txn,
+                _ => continue,
+            };
+            let block_timestamp_us = *user_txn.timestamp.inner();
+            let latency_ms = Self::estimate_latency_ms(
+                block_timestamp_us,
+                *user_txn.request.expiration_timestamp_secs.inner(),
+                expiration_ttl_secs,
+            );
+            let period_start =
+                NaiveDateTime::from_timestamp((block_timestamp_us / 1_000_000) as i64, 0)
+                    .with_second(0)
+                    .unwrap();
+            let latency_bucket_secs = Self::latency_bucket_secs(latency_ms);
+            let stat = stats
+                .entry((period_start, latency_bucket_secs))
+                .or_insert_with(|| Self {
+                    period_start,
+                    latency_bucket_secs,
+                    num_transactions: 0,
+                    total_latency_ms: 0,
+                    inserted_at: now,
+                    last_updated: now,
+                });
+            stat.num_transactions += 1;
+            stat.total_latency_ms += latency_ms;
+        }
+        stats.into_values().collect()
+    }
+}
+
+/// A range of versions already added to `txn_latency_stats`
+#[derive(Debug, Deserialize, FieldCount, Serialize, Clone)]
+#[cfg_attr(feature = "postgres", derive(Identifiable, Insertable, Queryable))]
+#[cfg_attr(feature = "postgres", diesel(table_name = "txn_latency_stat_batches"))]
+#[cfg_attr(feature = "postgres", primary_key(start_version, end_version))]
+pub struct TxnLatencyStatBatch {
+    pub start_version: BigDecimal,
+    pub end_version: BigDecimal,
+    pub inserted_at: NaiveDateTime,
+}
+
+impl TxnLatencyStatBatch {
+    pub fn new(start_version: u64, end_version: u64) -> Self {
+        Self {
+            start_version: u64_to_bigdecimal(start_version),
+            end_version: u64_to_bigdecimal(end_version),
+            inserted_at: chrono::Utc::now().naive_utc(),
+        }
+    }
+}
+
+// Prevent conflicts with other things named `TxnLatencyStat`
+pub type TxnLatencyStatModel = TxnLatencyStat;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn user_transaction(version: u64, timestamp_us: u64, expiration_secs: u64) -> APITransaction {
+        serde_json::from_value(json!({
+            "type": "user_transaction",
+            "version": version.to_string(),
+            "hash": format!("0x{:064x}", version),
+            "state_change_hash": format!("0x{:064x}", 0),
+            "event_root_hash": format!("0x{:064x}", 0),
+            "gas_used": "1",
+            "success": true,
+            "vm_status": "Executed successfully",
+            "accumulator_root_hash": format!("0x{:064x}", 0),
+            "changes": [],
+            "sender": "0xa11ce",
+            "sequence_number": "0",
+            "max_gas_amount": "1000",
+            "gas_unit_price": "100",
+            "expiration_timestamp_secs": expiration_secs.to_string(),
+            "payload": {
+                "type": "entry_function_payload",
+                "function": "0x1::coin::transfer",
+                "type_arguments": [],
+                "arguments": [],
+            },
+            "events": [],
+            "timestamp": timestamp_us.to_string(),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_txn_latency_stats() {
+        assert_eq!(TxnLatencyStat::latency_bucket_secs(0), 0);
+        assert_eq!(TxnLatencyStat::latency_bucket_secs(2_500), 2);
+        assert_eq!(TxnLatencyStat::latency_bucket_secs(4_000_000), 600);
+
+        // Submitted at 1_665_597_600 (expiring 30 seconds later)
+        let transactions = vec![
+            user_transaction(10, 1_665_597_601_500_000, 1_665_597_630),
+            user_transaction(11, 1_665_597_601_900_000, 1_665_597_630),
+            user_transaction(12, 1_665_597_612_000_000, 1_665_597_630),
+            // Expiring later than assumed: counted as no latency
+            user_transaction(13, 1_665_597_660_000_000, 1_665_598_000),
+        ];
+        let stats: Vec<_> = TxnLatencyStat::from_transactions(&transactions, 30)
+            .into_iter()
+            .map(|stat| {
+                (
+                    stat.period_start.timestamp(),
+                    stat.latency_bucket_secs,
+                    stat.num_transactions,
+                    stat.total_latency_ms,
+                )
+            })
+            .collect();
+        assert_eq!(
+            stats,
+            vec![
+                (1_665_597_600, 1, 2, 3_400),
+                (1_665_597_600, 10, 1, 12_000),
+                (1_665_597_660, 0, 1, 0),
+            ]
+        );
+    }
+}
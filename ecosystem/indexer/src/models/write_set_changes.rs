@@ -1,25 +1,32 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 #![allow(clippy::extra_unused_lifetimes)]
+#[cfg(feature = "postgres")]
 use crate::{models::transactions::Transaction, schema::write_set_changes};
 use aptos_rest_client::aptos_api_types::{
     DeleteModule, DeleteResource, DeleteTableItem, WriteModule, WriteResource,
     WriteSetChange as APIWriteSetChange, WriteTableItem,
 };
 use field_count::FieldCount;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-#[derive(
-    AsChangeset, Associations, Debug, FieldCount, Identifiable, Insertable, Queryable, Serialize,
+#[derive(Debug, Deserialize, FieldCount, Serialize)]
+#[cfg_attr(
+    feature = "postgres",
+    derive(AsChangeset, Associations, Identifiable, Insertable, Queryable)
 )]
-#[diesel(table_name = "write_set_changes")]
-#[belongs_to(Transaction, foreign_key = "transaction_hash")]
-#[primary_key(transaction_hash, hash)]
+#[cfg_attr(feature = "postgres", diesel(table_name = "write_set_changes"))]
+#[cfg_attr(
+    feature = "postgres",
+    belongs_to(Transaction, foreign_key = "transaction_hash")
+)]
+#[cfg_attr(feature = "postgres", primary_key(transaction_hash, hash))]
 pub struct WriteSetChange {
     pub transaction_hash: String,
     pub hash: String,
-    #[diesel(column_name = type)]
+    #[cfg_attr(feature = "postgres", diesel(column_name = type))]
+    #[serde(rename = "type")]
     pub type_: String,
     pub address: String,
     pub module: serde_json::Value,
@@ -28,13 +35,23 @@ pub struct WriteSetChange {
 
     // Default time columns
     pub inserted_at: chrono::NaiveDateTime,
+    /// Position of the change within its transaction. NULL for the changes indexed before it was recorded, until their
+    /// versions are re-processed.
+    pub write_set_change_index: Option<i64>,
+    /// Timestamp of the transaction making the change (the epoch for genesis), so filtering by time doesn't need a
+    /// join. NULL for the changes indexed before it was recorded, until their versions are re-processed.
+    pub transaction_timestamp: Option<chrono::NaiveDateTime>,
 }
 
 impl WriteSetChange {
     pub fn from_write_set_change(
         transaction_hash: String,
+        write_set_change_index: usize,
+        transaction_timestamp: chrono::NaiveDateTime,
         write_set_change: &APIWriteSetChange,
     ) -> Self {
+        let write_set_change_index = Some(write_set_change_index as i64);
+        let transaction_timestamp = Some(transaction_timestamp);
         match write_set_change {
             APIWriteSetChange::DeleteModule(DeleteModule {
                 address,
@@ -49,6 +66,8 @@ impl WriteSetChange {
                 resource: Default::default(),
                 data: Default::default(),
                 inserted_at: chrono::Utc::now().naive_utc(),
+                write_set_change_index,
+                transaction_timestamp,
             },
             APIWriteSetChange::DeleteResource(DeleteResource {
                 address,
@@ -63,6 +82,8 @@ impl WriteSetChange {
                 resource: serde_json::to_value(resource).expect("Should be able to parse resource"),
                 data: Default::default(),
                 inserted_at: chrono::Utc::now().naive_utc(),
+                write_set_change_index,
+                transaction_timestamp,
             },
             APIWriteSetChange::DeleteTableItem(DeleteTableItem {
                 state_key_hash,
@@ -81,6 +102,8 @@ impl WriteSetChange {
                     "key": key,
                 }),
                 inserted_at: chrono::Utc::now().naive_utc(),
+                write_set_change_index,
+                transaction_timestamp,
             },
             APIWriteSetChange::WriteModule(WriteModule {
                 address,
@@ -95,6 +118,8 @@ impl WriteSetChange {
                 resource: Default::default(),
                 data: serde_json::to_value(data).unwrap(),
                 inserted_at: chrono::Utc::now().naive_utc(),
+                write_set_change_index,
+                transaction_timestamp,
             },
             APIWriteSetChange::WriteResource(WriteResource {
                 address,
@@ -110,6 +135,8 @@ impl WriteSetChange {
                 data: serde_json::to_value(data)
                     .expect("Should be able to parse write resource data"),
                 inserted_at: chrono::Utc::now().naive_utc(),
+                write_set_change_index,
+                transaction_timestamp,
             },
             APIWriteSetChange::WriteTableItem(WriteTableItem {
                 state_key_hash,
@@ -130,12 +157,15 @@ impl WriteSetChange {
                     "value": value,
                 }),
                 inserted_at: chrono::Utc::now().naive_utc(),
+                write_set_change_index,
+                transaction_timestamp,
             },
         }
     }
 
     pub fn from_write_set_changes(
         transaction_hash: String,
+        transaction_timestamp: chrono::NaiveDateTime,
         write_set_changes: &[APIWriteSetChange],
     ) -> Option<Vec<Self>> {
         if write_set_changes.is_empty() {
@@ -144,8 +174,14 @@ impl WriteSetChange {
         Some(
             write_set_changes
                 .iter()
-                .map(|write_set_change| {
-                    Self::from_write_set_change(transaction_hash.clone(), write_set_change)
+                .enumerate()
+                .map(|(index, write_set_change)| {
+                    Self::from_write_set_change(
+                        transaction_hash.clone(),
+                        index,
+                        transaction_timestamp,
+                        write_set_change,
+                    )
                 })
                 .collect::<Vec<WriteSetChangeModel>>(),
         )
@@ -0,0 +1,600 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rewrites of large tables while the indexer keeps writing to them (`migrate-online`), ex: adding a `chain_id`
+//! column, or repartitioning `events`, which as a regular migration would hold the tailer up for hours. The operator
+//! creates the rewritten table, `<table>_online_new`, with its new definition; then:
+//!
+//! 1. `prepare` installs a trigger mirroring every write to the table into the rewritten one (dual writes), and records
+//!    the key of the table's last row: rows up to it predate the mirroring, later ones are mirrored.
+//! 2. `backfill` copies the rows up to that key in chunks, in the order of the primary key, recording its progress in
+//!    `online_migrations` along with each chunk, so an interrupted backfill resumes where it stopped.
+//! 3. `cutover` swaps the tables under a short lock, which the tailer's writes wait for, and keeps the original as
+//!    `<table>_online_old` for the operator to drop once satisfied.
+//!
+//! Columns are copied by name: columns only the rewritten table has take their default (ex: `chain_id BIGINT NOT
+//! NULL DEFAULT 1`), and columns it doesn't have are dropped. Both tables need a primary key. Locks are taken with a
+//! timeout and retried, so the tailer's writes are never held up for longer than it.
+
+use crate::{
+    database::PgPoolConnection,
+    models::online_migration::{
+        OnlineMigration, OnlineMigrationModel, STATE_BACKFILLED, STATE_BACKFILLING, STATE_CUT_OVER,
+    },
+    schema::online_migrations,
+};
+use anyhow::{bail, ensure, Context, Result};
+use aptos_logger::{info, warn};
+use diesel::{
+    prelude::*,
+    sql_query,
+    sql_types::{BigInt, Nullable, Text},
+};
+use serde_json::Value;
+use std::time::Duration;
+
+/// Suffix of the rewritten table, until the cutover
+pub const SHADOW_SUFFIX: &str = "_online_new";
+/// Suffix the original table is renamed with at the cutover
+pub const OLD_SUFFIX: &str = "_online_old";
+/// How many times taking a table's lock is attempted before giving up
+pub const LOCK_ATTEMPTS: u32 = 20;
+
+#[derive(Debug, QueryableByName)]
+pub(crate) struct Column {
+    #[sql_type = "Text"]
+    pub(crate) name: String,
+    #[sql_type = "Text"]
+    pub(crate) type_: String,
+}
+
+#[derive(Debug, QueryableByName)]
+struct LastKey {
+    #[sql_type = "Nullable<Text>"]
+    last_key: Option<String>,
+}
+
+#[derive(Debug, QueryableByName)]
+struct Chunk {
+    #[sql_type = "BigInt"]
+    num_rows: i64,
+    #[sql_type = "Nullable<Text>"]
+    last_key: Option<String>,
+}
+
+/// Table names are interpolated into SQL, so only plain lowercase identifiers are accepted
+fn validate_table_name(table: &str) -> Result<()> {
+    ensure!(
+        !table.is_empty()
+            && table.len() + SHADOW_SUFFIX.len() <= 63
+            && table.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+            && table
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'),
+        "Invalid table name {:?}",
+        table
+    );
+    Ok(())
+}
+
+pub fn shadow_table(table: &str) -> String {
+    format!("{}{}", table, SHADOW_SUFFIX)
+}
+
+fn sync_function(table: &str) -> String {
+    format!("{}_online_sync", table)
+}
+
+fn columns(conn: &PgPoolConnection, table: &str) -> Result<Vec<Column>> {
+    Ok(sql_query(
+        "SELECT attname::text AS name, format_type(atttypid, atttypmod) AS type_ FROM pg_attribute \
+         WHERE attrelid = to_regclass($1) AND attnum > 0 AND NOT attisdropped ORDER BY attnum",
+    )
+    .bind::<Text, _>(table)
+    .load(conn)?)
+}
+
+pub(crate) fn primary_key(conn: &PgPoolConnection, table: &str) -> Result<Vec<Column>> {
+    Ok(sql_query(
+        "SELECT a.attname::text AS name, format_type(a.atttypid, a.atttypmod) AS type_ \
+         FROM pg_index i JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey) \
+         WHERE i.indrelid = to_regclass($1) AND i.indisprimary \
+         ORDER BY array_position(i.indkey::int2[], a.attnum)",
+    )
+    .bind::<Text, _>(table)
+    .load(conn)?)
+}
+
+fn table_exists(conn: &PgPoolConnection, table: &str) -> Result<bool> {
+    #[derive(Debug, QueryableByName)]
+    struct Exists {
+        #[sql_type = "diesel::sql_types::Bool"]
+        exists: bool,
+    }
+    let exists: Exists = sql_query("SELECT to_regclass($1) IS NOT NULL AS exists")
+        .bind::<Text, _>(table)
+        .get_result(conn)?;
+    Ok(exists.exists)
+}
+
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// The statements a rewrite of a table runs, from the definitions of both tables
+#[derive(Debug, PartialEq)]
+struct Plan {
+    table: String,
+    shadow: String,
+    /// Columns both tables have
+    columns: Vec<String>,
+    /// Primary key of the original table, with the columns' types
+    key: Vec<(String, String)>,
+}
+
+impl Plan {
+    fn load(conn: &PgPoolConnection, table: &str) -> Result<Self> {
+        let shadow = shadow_table(table);
+        let shadow_columns: Vec<String> = columns(conn, &shadow)?
+            .into_iter()
+            .map(|column| column.name)
+            .collect();
+        ensure!(
+            !shadow_columns.is_empty(),
+            "The rewritten table {} doesn't exist",
+            shadow
+        );
+        ensure!(
+            !primary_key(conn, &shadow)?.is_empty(),
+            "The rewritten table {} has no primary key",
+            shadow
+        );
+        let columns: Vec<String> = columns(conn, table)?
+            .into_iter()
+            .map(|column| column.name)
+            .filter(|name| shadow_columns.contains(name))
+            .collect();
+        ensure!(
+            !columns.is_empty(),
+            "{} has no column in common with {}",
+            table,
+            shadow
+        );
+        let key: Vec<(String, String)> = primary_key(conn, table)?
+            .into_iter()
+            .map(|column| (column.name, column.type_))
+            .collect();
+        ensure!(!key.is_empty(), "{} has no primary key", table);
+        Ok(Self {
+            table: table.to_string(),
+            shadow,
+            columns,
+            key,
+        })
+    }
+
+    fn column_list(&self, prefix: &str) -> String {
+        self.columns
+            .iter()
+            .map(|column| format!("{}\"{}\"", prefix, column))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn key_list(&self) -> String {
+        let columns: Vec<String> = self
+            .key
+            .iter()
+            .map(|(column, _)| format!("\"{}\"", column))
+            .collect();
+        format!("({})", columns.join(", "))
+    }
+
+    /// Orders rows by key, so the primary key's index is used
+    fn key_order(&self, direction: &str) -> String {
+        let columns: Vec<String> = self
+            .key
+            .iter()
+            .map(|(column, _)| format!("\"{}\" {}", column, direction))
+            .collect();
+        columns.join(", ")
+    }
+
+    /// A row's key as JSON text, ex: `["0xabc", "3"]`
+    fn key_json(&self) -> String {
+        let columns: Vec<String> = self
+            .key
+            .iter()
+            .map(|(column, _)| format!("\"{}\"::text", column))
+            .collect();
+        format!("json_build_array({})::text", columns.join(", "))
+    }
+
+    /// A key recorded by `key_json`, as a row literal comparable with `key_list`
+    fn key_literal(&self, key: &Value) -> Result<String> {
+        let values = key
+            .as_array()
+            .filter(|values| values.len() == self.key.len())
+            .with_context(|| format!("Malformed key {} of {}", key, self.table))?;
+        let mut literals = vec![];
+        for (value, (_, type_)) in values.iter().zip(&self.key) {
+            let value = value
+                .as_str()
+                .with_context(|| format!("Malformed key {} of {}", key, self.table))?;
+            literals.push(format!("{}::{}", quote_literal(value), type_));
+        }
+        Ok(format!("({})", literals.join(", ")))
+    }
+
+    /// Creates the trigger mirroring the writes to the table. Updates are mirrored as a delete and an insert, so rows
+    /// the backfill hasn't copied yet are mirrored whole.
+    fn sync_sql(&self) -> String {
+        let function = sync_function(&self.table);
+        let key_match: Vec<String> = self
+            .key
+            .iter()
+            .map(|(column, _)| format!("\"{}\" = OLD.\"{}\"", column, column))
+            .collect();
+        format!(
+            "CREATE OR REPLACE FUNCTION {function}() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'UPDATE' OR TG_OP = 'DELETE' THEN
+        DELETE FROM \"{shadow}\" WHERE {key_match};
+    END IF;
+    IF TG_OP = 'INSERT' OR TG_OP = 'UPDATE' THEN
+        INSERT INTO \"{shadow}\" ({columns}) VALUES ({values}) ON CONFLICT DO NOTHING;
+    END IF;
+    RETURN NULL;
+END
+$$ LANGUAGE plpgsql;
+CREATE TRIGGER {function} AFTER INSERT OR UPDATE OR DELETE ON \"{table}\"
+    FOR EACH ROW EXECUTE FUNCTION {function}();",
+            function = function,
+            shadow = self.shadow,
+            table = self.table,
+            key_match = key_match.join(" AND "),
+            columns = self.column_list(""),
+            values = self.column_list("NEW."),
+        )
+    }
+
+    fn drop_sync_sql(&self) -> String {
+        format!(
+            "DROP TRIGGER IF EXISTS {function} ON \"{table}\"; DROP FUNCTION IF EXISTS {function}();",
+            function = sync_function(&self.table),
+            table = self.table,
+        )
+    }
+
+    /// Copies the next `chunk_size` rows after `after`, up to `end`, returning how many it copied and the key of the
+    /// last one. The rows are locked while they're copied, so a concurrent update or delete (mirrored by the trigger)
+    /// either waits for the copy, or is seen by it.
+    fn chunk_sql(&self, after: Option<&Value>, end: &Value, chunk_size: i64) -> Result<String> {
+        let after = match after {
+            Some(after) => format!(" AND {} > {}", self.key_list(), self.key_literal(after)?),
+            None => String::new(),
+        };
+        Ok(format!(
+            "WITH chunk AS (
+    SELECT * FROM \"{table}\" WHERE {key} <= {end}{after} ORDER BY {order} LIMIT {chunk_size} FOR SHARE
+), copied AS (
+    INSERT INTO \"{shadow}\" ({columns}) SELECT {columns} FROM chunk ON CONFLICT DO NOTHING
+)
+SELECT COUNT(*) AS num_rows, (SELECT {key_json} FROM chunk ORDER BY {order_desc} LIMIT 1) AS last_key FROM chunk",
+            table = self.table,
+            shadow = self.shadow,
+            key = self.key_list(),
+            order = self.key_order("ASC"),
+            order_desc = self.key_order("DESC"),
+            end = self.key_literal(end)?,
+            after = after,
+            chunk_size = chunk_size,
+            columns = self.column_list(""),
+            key_json = self.key_json(),
+        ))
+    }
+}
+
+fn is_lock_timeout(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<diesel::result::Error>(),
+        Some(diesel::result::Error::DatabaseError(_, info))
+            if info.message().contains("lock timeout")
+    )
+}
+
+/// Runs `transaction` with a `lock_timeout`, again if it times out waiting for a lock, up to `LOCK_ATTEMPTS` times
+pub(crate) fn with_lock_timeout<T>(
+    conn: &PgPoolConnection,
+    lock_timeout: Duration,
+    transaction: impl Fn() -> Result<T>,
+) -> Result<T> {
+    let mut attempt = 1;
+    loop {
+        let result = conn.transaction::<_, anyhow::Error, _>(|| {
+            conn.batch_execute(&format!(
+                "SET LOCAL lock_timeout = '{}ms'",
+                lock_timeout.as_millis()
+            ))?;
+            transaction()
+        });
+        match result {
+            Err(err) if is_lock_timeout(&err) && attempt < LOCK_ATTEMPTS => {
+                warn!(
+                    attempt = attempt,
+                    "Timed out waiting for a lock, will retry"
+                );
+                attempt += 1;
+                std::thread::sleep(lock_timeout);
+            }
+            result => return result,
+        }
+    }
+}
+
+pub fn get_migration(conn: &PgPoolConnection, table: &str) -> Result<Option<OnlineMigration>> {
+    Ok(online_migrations::table
+        .find(table)
+        .first::<OnlineMigrationModel>(conn)
+        .optional()?)
+}
+
+/// Starts rewriting `table`, creating the rewritten table with `ddl` (which must create `<table>_online_new`) unless
+/// it exists, or returns the rewrite in progress
+pub fn prepare(
+    conn: &PgPoolConnection,
+    table: &str,
+    ddl: Option<&str>,
+    lock_timeout: Duration,
+) -> Result<OnlineMigration> {
+    validate_table_name(table)?;
+    if let Some(migration) = get_migration(conn, table)? {
+        ensure!(
+            migration.state != STATE_CUT_OVER,
+            "{} was already rewritten (at {}): abort the rewrite to forget it before rewriting it again",
+            table,
+            migration.updated_at
+        );
+        info!(table = table, state = migration.state, "Resuming rewrite");
+        return Ok(migration);
+    }
+    ensure!(table_exists(conn, table)?, "{} doesn't exist", table);
+    let shadow = shadow_table(table);
+    if !table_exists(conn, &shadow)? {
+        let ddl = ddl.with_context(|| format!("Give the SQL creating {}", shadow))?;
+        conn.batch_execute(ddl)
+            .with_context(|| format!("Failed to create {}", shadow))?;
+    }
+    let plan = Plan::load(conn, table)?;
+
+    // The trigger's creation waits for the table's writers and holds later ones up until it commits, so every row is
+    // either visible to the query of the last key, or mirrored
+    let migration = with_lock_timeout(conn, lock_timeout, || {
+        conn.batch_execute(&plan.sync_sql())?;
+        let end = sql_query(format!(
+            "SELECT {} AS last_key FROM \"{}\" ORDER BY {} LIMIT 1",
+            plan.key_json(),
+            table,
+            plan.key_order("DESC")
+        ))
+        .get_result::<LastKey>(conn)
+        .optional()?
+        .and_then(|key| key.last_key);
+        let now = chrono::Utc::now().naive_utc();
+        let migration = OnlineMigration {
+            table_name: table.to_string(),
+            shadow_table: shadow.clone(),
+            backfill_end: end.map(|end| serde_json::from_str(&end)).transpose()?,
+            backfilled_up_to: None,
+            state: STATE_BACKFILLING.to_string(),
+            started_at: now,
+            updated_at: now,
+        };
+        diesel::insert_into(online_migrations::table)
+            .values(&migration)
+            .execute(conn)?;
+        Ok(migration)
+    })?;
+    info!(table = table, shadow_table = shadow, "Mirroring writes");
+    Ok(migration)
+}
+
+/// Copies the rows of `migration`'s table which predate the mirroring, `chunk_size` at a time, pausing `chunk_pause`
+/// between chunks. Returns the number of rows copied.
+pub fn backfill(
+    conn: &PgPoolConnection,
+    migration: &mut OnlineMigration,
+    chunk_size: i64,
+    chunk_pause: Duration,
+) -> Result<u64> {
+    ensure!(chunk_size > 0, "The chunk size must be positive");
+    if migration.state != STATE_BACKFILLING {
+        return Ok(0);
+    }
+    let plan = Plan::load(conn, &migration.table_name)?;
+    let mut num_copied = 0;
+    loop {
+        let end = match &migration.backfill_end {
+            Some(end) => end,
+            None => break,
+        };
+        let (chunk, updated_at) = conn.transaction::<_, anyhow::Error, _>(|| {
+            let chunk: Chunk =
+                sql_query(plan.chunk_sql(migration.backfilled_up_to.as_ref(), end, chunk_size)?)
+                    .get_result(conn)?;
+            let last_key: Option<Value> = chunk
+                .last_key
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()?;
+            let now = chrono::Utc::now().naive_utc();
+            if let Some(last_key) = &last_key {
+                diesel::update(online_migrations::table.find(&migration.table_name))
+                    .set((
+                        online_migrations::backfilled_up_to.eq(last_key),
+                        online_migrations::updated_at.eq(now),
+                    ))
+                    .execute(conn)?;
+            }
+            Ok(((chunk.num_rows, last_key), now))
+        })?;
+        match chunk {
+            (num_rows, Some(last_key)) => {
+                num_copied += num_rows as u64;
+                migration.backfilled_up_to = Some(last_key);
+                migration.updated_at = updated_at;
+                info!(
+                    table = migration.table_name,
+                    num_copied = num_copied,
+                    backfilled_up_to = migration.backfilled_up_to,
+                    "Copied chunk"
+                );
+                if num_rows < chunk_size {
+                    break;
+                }
+                std::thread::sleep(chunk_pause);
+            }
+            (_, None) => break,
+        }
+    }
+    diesel::update(online_migrations::table.find(&migration.table_name))
+        .set((
+            online_migrations::state.eq(STATE_BACKFILLED),
+            online_migrations::updated_at.eq(chrono::Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+    migration.state = STATE_BACKFILLED.to_string();
+    Ok(num_copied)
+}
+
+/// Swaps the rewritten table in for the backfilled `migration`'s, keeping the original as `<table>_online_old`.
+/// Writes to the table wait for the swap, which only renames tables.
+pub fn cutover(
+    conn: &PgPoolConnection,
+    migration: &mut OnlineMigration,
+    lock_timeout: Duration,
+) -> Result<()> {
+    ensure!(
+        migration.state == STATE_BACKFILLED,
+        "{} can't be cut over while {}",
+        migration.table_name,
+        migration.state
+    );
+    let plan = Plan::load(conn, &migration.table_name)?;
+    let old = format!("{}{}", migration.table_name, OLD_SUFFIX);
+    ensure!(
+        !table_exists(conn, &old)?,
+        "{} exists: drop it (or rename it) before cutting over",
+        old
+    );
+    with_lock_timeout(conn, lock_timeout, || {
+        conn.batch_execute(&format!(
+            "LOCK TABLE \"{table}\", \"{shadow}\" IN ACCESS EXCLUSIVE MODE; {drop_sync} \
+             ALTER TABLE \"{table}\" RENAME TO \"{old}\"; ALTER TABLE \"{shadow}\" RENAME TO \"{table}\";",
+            table = plan.table,
+            shadow = plan.shadow,
+            drop_sync = plan.drop_sync_sql(),
+            old = old,
+        ))?;
+        diesel::update(online_migrations::table.find(&migration.table_name))
+            .set((
+                online_migrations::state.eq(STATE_CUT_OVER),
+                online_migrations::updated_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .execute(conn)?;
+        Ok(())
+    })?;
+    migration.state = STATE_CUT_OVER.to_string();
+    info!(table = migration.table_name, old_table = old, "Cut over");
+    Ok(())
+}
+
+/// Stops rewriting `table`: drops the trigger and the rewritten table, and forgets the progress. A rewrite which was
+/// cut over is only forgotten.
+pub fn abort(conn: &PgPoolConnection, table: &str, lock_timeout: Duration) -> Result<()> {
+    validate_table_name(table)?;
+    let migration = match get_migration(conn, table)? {
+        Some(migration) => migration,
+        None => bail!("{} isn't being rewritten", table),
+    };
+    with_lock_timeout(conn, lock_timeout, || {
+        if migration.state != STATE_CUT_OVER {
+            conn.batch_execute(&format!(
+                "DROP TRIGGER IF EXISTS {function} ON \"{table}\"; DROP FUNCTION IF EXISTS {function}(); \
+                 DROP TABLE IF EXISTS \"{shadow}\";",
+                function = sync_function(table),
+                table = table,
+                shadow = migration.shadow_table,
+            ))?;
+        }
+        diesel::delete(online_migrations::table.find(table)).execute(conn)?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan() -> Plan {
+        Plan {
+            table: "events".to_string(),
+            shadow: shadow_table("events"),
+            columns: vec!["transaction_hash".to_string(), "event_index".to_string()],
+            key: vec![
+                (
+                    "transaction_hash".to_string(),
+                    "character varying".to_string(),
+                ),
+                ("event_index".to_string(), "bigint".to_string()),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_validate_table_name() {
+        assert!(validate_table_name("events").is_ok());
+        assert!(validate_table_name("current_token_ownerships_v2").is_ok());
+        assert!(validate_table_name("").is_err());
+        assert!(validate_table_name("Events").is_err());
+        assert!(validate_table_name("events; DROP TABLE events").is_err());
+        assert!(validate_table_name("1events").is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let plan = plan();
+        assert_eq!(
+            plan.key_literal(&serde_json::json!(["0xa'b", "3"]))
+                .unwrap(),
+            "('0xa''b'::character varying, '3'::bigint)"
+        );
+        assert!(plan.key_literal(&serde_json::json!(["0xab"])).is_err());
+        assert!(plan.key_literal(&serde_json::json!(["0xab", 3])).is_err());
+
+        let sql = plan
+            .chunk_sql(
+                Some(&serde_json::json!(["0xab", "1"])),
+                &serde_json::json!(["0xff", "0"]),
+                100,
+            )
+            .unwrap();
+        assert!(sql.contains(
+            "WHERE (\"transaction_hash\", \"event_index\") <= ('0xff'::character varying, '0'::bigint) \
+             AND (\"transaction_hash\", \"event_index\") > ('0xab'::character varying, '1'::bigint) \
+             ORDER BY \"transaction_hash\" ASC, \"event_index\" ASC LIMIT 100 FOR SHARE"
+        ));
+        assert!(sql.contains(
+            "INSERT INTO \"events_online_new\" (\"transaction_hash\", \"event_index\") \
+             SELECT \"transaction_hash\", \"event_index\" FROM chunk ON CONFLICT DO NOTHING"
+        ));
+
+        let sync = plan.sync_sql();
+        assert!(sync.contains(
+            "DELETE FROM \"events_online_new\" WHERE \"transaction_hash\" = OLD.\"transaction_hash\" \
+             AND \"event_index\" = OLD.\"event_index\";"
+        ));
+        assert!(sync.contains("VALUES (NEW.\"transaction_hash\", NEW.\"event_index\")"));
+        assert!(sync.contains("AFTER INSERT OR UPDATE OR DELETE ON \"events\""));
+    }
+}
@@ -0,0 +1,1054 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Minimal Parquet reader, used by `import` to load dumps published as Parquet.
+//!
+//! Only flat schemas (required or optional columns, no nested or repeated ones) are supported, with data pages v1 and
+//! v2, the PLAIN, dictionary (PLAIN_DICTIONARY/RLE_DICTIONARY) and RLE encodings, and uncompressed, Snappy or gzip
+//! column chunks. Values are read as text cells, as in a CSV dump: timestamps as microseconds since the epoch,
+//! decimals in base 10, and binary columns as UTF-8. Row groups are read one at a time.
+
+use anyhow::{bail, ensure, Context, Result};
+use flate2::read::MultiGzDecoder;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+const MAGIC: &[u8; 4] = b"PAR1";
+/// Days between the Julian day INT96 timestamps count from and the Unix epoch
+const JULIAN_DAY_OF_EPOCH: i64 = 2_440_588;
+const MICROS_PER_DAY: i64 = 86_400_000_000;
+
+// Physical types
+const BOOLEAN: i64 = 0;
+const INT32: i64 = 1;
+const INT64: i64 = 2;
+const INT96: i64 = 3;
+const FLOAT: i64 = 4;
+const DOUBLE: i64 = 5;
+const BYTE_ARRAY: i64 = 6;
+const FIXED_LEN_BYTE_ARRAY: i64 = 7;
+
+// Encodings
+const PLAIN: i64 = 0;
+const PLAIN_DICTIONARY: i64 = 2;
+const RLE: i64 = 3;
+const RLE_DICTIONARY: i64 = 8;
+
+// Compression codecs
+const UNCOMPRESSED: i64 = 0;
+const SNAPPY: i64 = 1;
+const GZIP: i64 = 2;
+
+// Page types
+const DATA_PAGE: i64 = 0;
+const DICTIONARY_PAGE: i64 = 2;
+const DATA_PAGE_V2: i64 = 3;
+
+/// A value of Thrift's compact protocol, which Parquet's metadata and page headers are encoded with
+#[derive(Clone, Debug, PartialEq)]
+enum Thrift {
+    Bool(bool),
+    Int(i64),
+    Double(f64),
+    Binary(Vec<u8>),
+    List(Vec<Thrift>),
+    Map(Vec<(Thrift, Thrift)>),
+    Struct(BTreeMap<i16, Thrift>),
+}
+
+impl Thrift {
+    fn field(&self, id: i16) -> Option<&Thrift> {
+        match self {
+            Thrift::Struct(fields) => fields.get(&id),
+            _ => None,
+        }
+    }
+
+    fn opt_int(&self, id: i16) -> Option<i64> {
+        match self.field(id) {
+            Some(Thrift::Int(int)) => Some(*int),
+            _ => None,
+        }
+    }
+
+    fn int(&self, id: i16) -> Result<i64> {
+        self.opt_int(id)
+            .with_context(|| format!("Missing integer field {}", id))
+    }
+
+    fn opt_bool(&self, id: i16) -> Option<bool> {
+        match self.field(id) {
+            Some(Thrift::Bool(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn string(&self, id: i16) -> Result<String> {
+        match self.field(id) {
+            Some(Thrift::Binary(bytes)) => {
+                String::from_utf8(bytes.clone()).context("Invalid UTF-8 string")
+            }
+            _ => bail!("Missing string field {}", id),
+        }
+    }
+
+    fn list(&self, id: i16) -> Result<&[Thrift]> {
+        match self.field(id) {
+            Some(Thrift::List(items)) => Ok(items),
+            _ => bail!("Missing list field {}", id),
+        }
+    }
+}
+
+/// Reads bytes, varints and Thrift compact protocol values from a buffer
+struct Reader<'a> {
+    buffer: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buffer: &'a [u8]) -> Self {
+        Self {
+            buffer,
+            position: 0,
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.position + len;
+        ensure!(end <= self.buffer.len(), "Truncated data");
+        let bytes = &self.buffer[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("Varint too long")
+    }
+
+    fn zigzag(&mut self) -> Result<i64> {
+        let value = self.varint()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    /// A value of compact type `kind`. Booleans outside of structs' field headers take a byte.
+    fn value(&mut self, kind: u8) -> Result<Thrift> {
+        Ok(match kind {
+            1 | 2 => Thrift::Bool(self.byte()? == 1),
+            3 => Thrift::Int(self.byte()? as i8 as i64),
+            4..=6 => Thrift::Int(self.zigzag()?),
+            7 => Thrift::Double(f64::from_le_bytes(self.take(8)?.try_into()?)),
+            8 => {
+                let len = self.varint()? as usize;
+                Thrift::Binary(self.take(len)?.to_vec())
+            }
+            9 | 10 => {
+                let header = self.byte()?;
+                let len = match header >> 4 {
+                    15 => self.varint()? as usize,
+                    len => len as usize,
+                };
+                Thrift::List(
+                    (0..len)
+                        .map(|_| self.value(header & 0x0F))
+                        .collect::<Result<_>>()?,
+                )
+            }
+            11 => {
+                let len = self.varint()? as usize;
+                let kinds = if len > 0 { self.byte()? } else { 0 };
+                Thrift::Map(
+                    (0..len)
+                        .map(|_| Ok((self.value(kinds >> 4)?, self.value(kinds & 0x0F)?)))
+                        .collect::<Result<_>>()?,
+                )
+            }
+            12 => self.structure()?,
+            _ => bail!("Unknown Thrift compact type {}", kind),
+        })
+    }
+
+    fn structure(&mut self) -> Result<Thrift> {
+        let mut fields = BTreeMap::new();
+        let mut last_id = 0_i16;
+        loop {
+            let header = self.byte()?;
+            if header == 0 {
+                return Ok(Thrift::Struct(fields));
+            }
+            let id = match header >> 4 {
+                0 => self.zigzag()? as i16,
+                delta => last_id + delta as i16,
+            };
+            let value = match header & 0x0F {
+                // Booleans' values are their field header's type
+                1 => Thrift::Bool(true),
+                2 => Thrift::Bool(false),
+                kind => self.value(kind)?,
+            };
+            fields.insert(id, value);
+            last_id = id;
+        }
+    }
+}
+
+/// Decompresses the raw (unframed) Snappy format
+pub fn snappy_decompress(input: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = Reader::new(input);
+    let len = reader.varint()? as usize;
+    let mut output = Vec::with_capacity(len);
+    while reader.position < input.len() {
+        let tag = reader.byte()?;
+        let (copy_len, offset) = match tag & 0b11 {
+            0 => {
+                let literal_len = match tag >> 2 {
+                    len @ 0..=59 => len as usize + 1,
+                    len => {
+                        let mut bytes = [0; 4];
+                        let num_bytes = len as usize - 59;
+                        bytes[..num_bytes].copy_from_slice(reader.take(num_bytes)?);
+                        u32::from_le_bytes(bytes) as usize + 1
+                    }
+                };
+                output.extend_from_slice(reader.take(literal_len)?);
+                continue;
+            }
+            1 => (
+                4 + ((tag >> 2) & 0b111) as usize,
+                ((tag as usize >> 5) << 8) | reader.byte()? as usize,
+            ),
+            2 => (
+                (tag >> 2) as usize + 1,
+                u16::from_le_bytes(reader.take(2)?.try_into()?) as usize,
+            ),
+            _ => (
+                (tag >> 2) as usize + 1,
+                u32::from_le_bytes(reader.take(4)?.try_into()?) as usize,
+            ),
+        };
+        ensure!(
+            offset > 0 && offset <= output.len(),
+            "Invalid Snappy copy offset {}",
+            offset
+        );
+        // Copies may overlap the bytes they produce, so go byte by byte
+        for _ in 0..copy_len {
+            output.push(output[output.len() - offset]);
+        }
+    }
+    ensure!(
+        output.len() == len,
+        "Snappy data decompressed to {} bytes rather than {}",
+        output.len(),
+        len
+    );
+    Ok(output)
+}
+
+fn decompress(codec: i64, data: &[u8], uncompressed_size: usize) -> Result<Vec<u8>> {
+    match codec {
+        UNCOMPRESSED => Ok(data.to_vec()),
+        SNAPPY => snappy_decompress(data),
+        GZIP => {
+            let mut output = Vec::with_capacity(uncompressed_size);
+            MultiGzDecoder::new(data).read_to_end(&mut output)?;
+            Ok(output)
+        }
+        _ => bail!(
+            "Unsupported compression codec {} (only uncompressed, Snappy and gzip are)",
+            codec
+        ),
+    }
+}
+
+/// Decodes `count` values of the RLE/bit-packing hybrid encoding
+pub fn decode_hybrid(data: &[u8], bit_width: u32, count: usize) -> Result<Vec<u32>> {
+    ensure!(bit_width <= 32, "Invalid bit width {}", bit_width);
+    let mut reader = Reader::new(data);
+    let mut values = Vec::with_capacity(count);
+    while values.len() < count {
+        let header = reader.varint()?;
+        if header & 1 == 0 {
+            // A run of the same value, in the fewest bytes holding `bit_width` bits
+            let mut bytes = [0; 4];
+            let num_bytes = (bit_width as usize + 7) / 8;
+            bytes[..num_bytes].copy_from_slice(reader.take(num_bytes)?);
+            let value = u32::from_le_bytes(bytes);
+            values.extend(std::iter::repeat(value).take((header >> 1) as usize));
+        } else {
+            // Groups of 8 values of `bit_width` bits, least significant bit first
+            let num_values = (header >> 1) as usize * 8;
+            let bytes = reader.take(num_values * bit_width as usize / 8)?;
+            for index in 0..num_values {
+                let mut value = 0_u64;
+                for bit in 0..bit_width as usize {
+                    let position = index * bit_width as usize + bit;
+                    value |= (((bytes[position / 8] >> (position % 8)) & 1) as u64) << bit;
+                }
+                values.push(value as u32);
+            }
+        }
+    }
+    values.truncate(count);
+    Ok(values)
+}
+
+/// Hybrid-encoded data preceded by its length, as 4 bytes, and the rest of the buffer
+fn length_prefixed(data: &[u8]) -> Result<(&[u8], &[u8])> {
+    ensure!(data.len() >= 4, "Truncated length prefix");
+    let len = u32::from_le_bytes(data[..4].try_into()?) as usize;
+    ensure!(data.len() >= 4 + len, "Truncated levels");
+    Ok((&data[4..4 + len], &data[4 + len..]))
+}
+
+/// How a column's values are turned into cells
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Conversion {
+    Plain,
+    TimestampMillis,
+    TimestampMicros,
+    TimestampNanos,
+    Decimal(u32),
+}
+
+#[derive(Clone, Debug)]
+pub struct Column {
+    pub name: String,
+    pub optional: bool,
+    physical_type: i64,
+    type_length: usize,
+    conversion: Conversion,
+}
+
+impl Column {
+    fn from_schema_element(element: &Thrift) -> Result<Self> {
+        let name = element.string(4)?;
+        ensure!(
+            element.opt_int(5).unwrap_or_default() == 0,
+            "Column {}: nested columns aren't supported",
+            name
+        );
+        let repetition = element.opt_int(3).unwrap_or_default();
+        ensure!(
+            repetition != 2,
+            "Column {}: repeated columns aren't supported",
+            name
+        );
+        // The logical type (field 10) supersedes the converted type (field 6), but writers still set both
+        let timestamp_unit = element
+            .field(10)
+            .and_then(|logical_type| logical_type.field(8))
+            .and_then(|timestamp| timestamp.field(2))
+            .and_then(|unit| match unit {
+                Thrift::Struct(fields) => fields.keys().next().copied(),
+                _ => None,
+            });
+        let conversion = match (timestamp_unit, element.opt_int(6)) {
+            (Some(1), _) | (None, Some(9)) => Conversion::TimestampMillis,
+            (Some(2), _) | (None, Some(10)) => Conversion::TimestampMicros,
+            (Some(3), _) => Conversion::TimestampNanos,
+            (_, Some(5)) => Conversion::Decimal(element.opt_int(7).unwrap_or_default() as u32),
+            _ => Conversion::Plain,
+        };
+        Ok(Self {
+            name,
+            optional: repetition == 1,
+            physical_type: element.int(1)?,
+            type_length: element.opt_int(2).unwrap_or_default() as usize,
+            conversion,
+        })
+    }
+
+    fn integer_cell(&self, value: i64) -> String {
+        match self.conversion {
+            Conversion::TimestampMillis => (value * 1000).to_string(),
+            Conversion::TimestampNanos => value.div_euclid(1000).to_string(),
+            Conversion::Decimal(scale) => decimal_cell(value as i128, scale),
+            Conversion::Plain | Conversion::TimestampMicros => value.to_string(),
+        }
+    }
+
+    fn bytes_cell(&self, bytes: &[u8]) -> Result<String> {
+        match self.conversion {
+            Conversion::Decimal(scale) => {
+                ensure!(
+                    !bytes.is_empty() && bytes.len() <= 16,
+                    "Column {}: unsupported decimal of {} bytes",
+                    self.name,
+                    bytes.len()
+                );
+                // Big-endian two's complement, sign-extended to 16 bytes
+                let mut extended = [if bytes[0] & 0x80 != 0 { 0xFF } else { 0 }; 16];
+                extended[16 - bytes.len()..].copy_from_slice(bytes);
+                Ok(decimal_cell(i128::from_be_bytes(extended), scale))
+            }
+            _ => String::from_utf8(bytes.to_vec())
+                .with_context(|| format!("Column {}: invalid UTF-8", self.name)),
+        }
+    }
+
+    /// Decodes `count` PLAIN-encoded values into cells
+    fn plain_cells(&self, data: &[u8], count: usize) -> Result<Vec<String>> {
+        let mut reader = Reader::new(data);
+        let mut cells = Vec::with_capacity(count);
+        for index in 0..count {
+            let cell = match self.physical_type {
+                BOOLEAN => {
+                    let byte = data.get(index / 8).context("Truncated boolean values")?;
+                    ((byte >> (index % 8)) & 1 == 1).to_string()
+                }
+                INT32 => self.integer_cell(i32::from_le_bytes(reader.take(4)?.try_into()?) as i64),
+                INT64 => self.integer_cell(i64::from_le_bytes(reader.take(8)?.try_into()?)),
+                INT96 => {
+                    let bytes = reader.take(12)?;
+                    let nanos = i64::from_le_bytes(bytes[..8].try_into()?);
+                    let julian_day = u32::from_le_bytes(bytes[8..].try_into()?) as i64;
+                    ((julian_day - JULIAN_DAY_OF_EPOCH) * MICROS_PER_DAY + nanos.div_euclid(1000))
+                        .to_string()
+                }
+                FLOAT => f32::from_le_bytes(reader.take(4)?.try_into()?).to_string(),
+                DOUBLE => f64::from_le_bytes(reader.take(8)?.try_into()?).to_string(),
+                BYTE_ARRAY => {
+                    let len = u32::from_le_bytes(reader.take(4)?.try_into()?) as usize;
+                    self.bytes_cell(reader.take(len)?)?
+                }
+                FIXED_LEN_BYTE_ARRAY => self.bytes_cell(reader.take(self.type_length)?)?,
+                physical_type => bail!(
+                    "Column {}: unknown physical type {}",
+                    self.name,
+                    physical_type
+                ),
+            };
+            cells.push(cell);
+        }
+        Ok(cells)
+    }
+
+    /// Decodes the `count` values of a data page, encoded with `encoding`, into cells
+    fn value_cells(
+        &self,
+        data: &[u8],
+        encoding: i64,
+        count: usize,
+        dictionary: &Option<Vec<String>>,
+    ) -> Result<Vec<String>> {
+        match encoding {
+            PLAIN => self.plain_cells(data, count),
+            PLAIN_DICTIONARY | RLE_DICTIONARY => {
+                let dictionary = dictionary
+                    .as_ref()
+                    .with_context(|| format!("Column {}: no dictionary page", self.name))?;
+                let bit_width = *data.first().context("Truncated dictionary indices")?;
+                decode_hybrid(&data[1..], bit_width as u32, count)?
+                    .into_iter()
+                    .map(|index| {
+                        dictionary.get(index as usize).cloned().with_context(|| {
+                            format!("Column {}: dictionary index out of range", self.name)
+                        })
+                    })
+                    .collect()
+            }
+            RLE if self.physical_type == BOOLEAN => {
+                let (data, _) = length_prefixed(data)?;
+                Ok(decode_hybrid(data, 1, count)?
+                    .into_iter()
+                    .map(|value| (value == 1).to_string())
+                    .collect())
+            }
+            _ => bail!("Column {}: unsupported encoding {}", self.name, encoding),
+        }
+    }
+}
+
+/// Formats `unscaled` divided by 10^`scale`
+fn decimal_cell(unscaled: i128, scale: u32) -> String {
+    let digits = unscaled.unsigned_abs().to_string();
+    let sign = if unscaled < 0 { "-" } else { "" };
+    if scale == 0 {
+        return format!("{}{}", sign, digits);
+    }
+    let digits = format!("{:0>width$}", digits, width = scale as usize + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - scale as usize);
+    format!("{}{}.{}", sign, integer, fraction)
+}
+
+/// A Parquet file's schema and row groups, read from its footer
+pub struct ParquetFile {
+    path: PathBuf,
+    file: File,
+    columns: Vec<Column>,
+    row_groups: Vec<Thrift>,
+}
+
+impl ParquetFile {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = File::open(path).with_context(|| format!("Could not open {:?}", path))?;
+        let len = file.seek(SeekFrom::End(0))?;
+        ensure!(len >= 12, "{:?} is too short to be a Parquet file", path);
+        let mut footer = [0; 8];
+        file.seek(SeekFrom::End(-8))?;
+        file.read_exact(&mut footer)?;
+        ensure!(&footer[4..] == MAGIC, "{:?} isn't a Parquet file", path);
+        let metadata_len = u32::from_le_bytes(footer[..4].try_into()?) as u64;
+        ensure!(metadata_len + 12 <= len, "{:?}: invalid footer", path);
+        let mut metadata = vec![0; metadata_len as usize];
+        file.seek(SeekFrom::End(-8 - metadata_len as i64))?;
+        file.read_exact(&mut metadata)?;
+        let metadata = Reader::new(&metadata)
+            .structure()
+            .with_context(|| format!("{:?}: invalid metadata", path))?;
+
+        // The first schema element is the root, the others its columns
+        let columns = metadata
+            .list(2)?
+            .iter()
+            .skip(1)
+            .map(Column::from_schema_element)
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("{:?}: unsupported schema", path))?;
+        let row_groups = metadata.list(4)?.to_vec();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            columns,
+            row_groups,
+        })
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// The cells of each column of a row group, `None` for nulls
+    fn read_row_group(&mut self, index: usize) -> Result<Vec<Vec<Option<String>>>> {
+        let row_group = self.row_groups[index].clone();
+        let chunks = row_group.list(1)?;
+        ensure!(
+            chunks.len() == self.columns.len(),
+            "{} column chunks for {} columns",
+            chunks.len(),
+            self.columns.len()
+        );
+        let mut columns = Vec::with_capacity(chunks.len());
+        for (column, chunk) in self.columns.clone().iter().zip(chunks) {
+            let metadata = chunk
+                .field(3)
+                .with_context(|| format!("Column {}: no column metadata", column.name))?;
+            ensure!(
+                chunk.field(1).is_none(),
+                "Column {}: column chunks in other files aren't supported",
+                column.name
+            );
+            // The dictionary page, if any, comes first
+            let start = match metadata.opt_int(11) {
+                Some(offset) if offset > 0 => offset,
+                _ => metadata.int(9)?,
+            };
+            let mut data = vec![0; metadata.int(7)? as usize];
+            self.file.seek(SeekFrom::Start(start as u64))?;
+            self.file.read_exact(&mut data)?;
+            columns.push(
+                read_column_chunk(column, metadata, &data)
+                    .with_context(|| format!("{:?}: row group {}", self.path, index))?,
+            );
+        }
+        Ok(columns)
+    }
+
+    /// The rows of every row group, one row group in memory at a time
+    pub fn rows(self) -> ParquetRows {
+        ParquetRows {
+            file: self,
+            next_row_group: 0,
+            rows: vec![].into_iter(),
+        }
+    }
+}
+
+/// Reads the cells of a column chunk, whose pages (including their headers) are in `data`
+fn read_column_chunk(
+    column: &Column,
+    metadata: &Thrift,
+    data: &[u8],
+) -> Result<Vec<Option<String>>> {
+    let codec = metadata.int(4)?;
+    let num_values = metadata.int(5)? as usize;
+    let mut reader = Reader::new(data);
+    let mut dictionary = None;
+    let mut cells = Vec::with_capacity(num_values);
+    while cells.len() < num_values {
+        let header = reader.structure()?;
+        let uncompressed_size = header.int(2)? as usize;
+        let page = reader.take(header.int(3)? as usize)?;
+        match header.int(1)? {
+            DICTIONARY_PAGE => {
+                let dictionary_header = header.field(7).context("No dictionary page header")?;
+                let page = decompress(codec, page, uncompressed_size)?;
+                dictionary = Some(column.plain_cells(&page, dictionary_header.int(1)? as usize)?);
+            }
+            DATA_PAGE => {
+                let data_header = header.field(5).context("No data page header")?;
+                let count = data_header.int(1)? as usize;
+                let page = decompress(codec, page, uncompressed_size)?;
+                let (levels, values) = if column.optional {
+                    ensure!(
+                        data_header.int(3)? == RLE,
+                        "Column {}: only RLE definition levels are supported",
+                        column.name
+                    );
+                    let (levels, values) = length_prefixed(&page)?;
+                    (Some(decode_hybrid(levels, 1, count)?), values)
+                } else {
+                    (None, &page[..])
+                };
+                push_cells(
+                    &mut cells,
+                    column,
+                    levels,
+                    values,
+                    data_header.int(2)?,
+                    count,
+                    &dictionary,
+                )?;
+            }
+            DATA_PAGE_V2 => {
+                let data_header = header.field(8).context("No data page v2 header")?;
+                let count = data_header.int(1)? as usize;
+                let levels_len = data_header.int(5)? as usize;
+                ensure!(
+                    data_header.int(6)? == 0 && levels_len <= page.len(),
+                    "Column {}: invalid levels",
+                    column.name
+                );
+                // Levels are never compressed, values are unless said otherwise
+                let levels = if column.optional {
+                    Some(decode_hybrid(&page[..levels_len], 1, count)?)
+                } else {
+                    None
+                };
+                let values = if data_header.opt_bool(7).unwrap_or(true) {
+                    decompress(
+                        codec,
+                        &page[levels_len..],
+                        uncompressed_size.saturating_sub(levels_len),
+                    )?
+                } else {
+                    page[levels_len..].to_vec()
+                };
+                push_cells(
+                    &mut cells,
+                    column,
+                    levels,
+                    &values,
+                    data_header.int(4)?,
+                    count,
+                    &dictionary,
+                )?;
+            }
+            // Index pages aren't needed
+            _ => {}
+        }
+    }
+    Ok(cells)
+}
+
+/// Appends the cells of a data page: its values where the definition level is 1, and nulls where it's 0
+fn push_cells(
+    cells: &mut Vec<Option<String>>,
+    column: &Column,
+    levels: Option<Vec<u32>>,
+    values: &[u8],
+    encoding: i64,
+    count: usize,
+    dictionary: &Option<Vec<String>>,
+) -> Result<()> {
+    match levels {
+        Some(levels) => {
+            let num_values = levels.iter().filter(|level| **level == 1).count();
+            let mut values = column
+                .value_cells(values, encoding, num_values, dictionary)?
+                .into_iter();
+            for level in levels {
+                cells.push(if level == 1 { values.next() } else { None });
+            }
+        }
+        None => cells.extend(
+            column
+                .value_cells(values, encoding, count, dictionary)?
+                .into_iter()
+                .map(Some),
+        ),
+    }
+    Ok(())
+}
+
+/// Iterates over the rows of a Parquet file
+pub struct ParquetRows {
+    file: ParquetFile,
+    next_row_group: usize,
+    rows: std::vec::IntoIter<Vec<Option<String>>>,
+}
+
+impl Iterator for ParquetRows {
+    type Item = Result<Vec<Option<String>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(row) = self.rows.next() {
+                return Some(Ok(row));
+            }
+            if self.next_row_group == self.file.row_groups.len() {
+                return None;
+            }
+            let columns = match self.file.read_row_group(self.next_row_group) {
+                Ok(columns) => columns,
+                Err(err) => {
+                    // Don't read past a row group which failed
+                    self.next_row_group = self.file.row_groups.len();
+                    return Some(Err(err));
+                }
+            };
+            self.next_row_group += 1;
+            let num_rows = columns.first().map_or(0, |column| column.len());
+            let mut columns: Vec<_> = columns.into_iter().map(|cells| cells.into_iter()).collect();
+            self.rows = (0..num_rows)
+                .map(|_| {
+                    columns
+                        .iter_mut()
+                        .map(|cells| cells.next().flatten())
+                        .collect()
+                })
+                .collect::<Vec<_>>()
+                .into_iter();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    /// Encodes Thrift compact structs, to write test files
+    fn write_struct(buffer: &mut Vec<u8>, fields: &[(i16, Thrift)]) {
+        let mut last_id = 0;
+        for (id, value) in fields {
+            let kind = match value {
+                Thrift::Bool(true) => 1,
+                Thrift::Bool(false) => 2,
+                Thrift::Int(_) => 6,
+                Thrift::Binary(_) => 8,
+                Thrift::List(_) => 9,
+                Thrift::Struct(_) => 12,
+                _ => unimplemented!(),
+            };
+            if (1..=15).contains(&(id - last_id)) {
+                buffer.push((((id - last_id) as u8) << 4) | kind);
+            } else {
+                buffer.push(kind);
+                write_value(buffer, &Thrift::Int(*id as i64));
+            }
+            last_id = *id;
+            write_value(buffer, value);
+        }
+        buffer.push(0);
+    }
+
+    fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            buffer.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        buffer.push(value as u8);
+    }
+
+    fn write_value(buffer: &mut Vec<u8>, value: &Thrift) {
+        match value {
+            Thrift::Bool(_) => {}
+            Thrift::Int(int) => write_varint(buffer, ((int << 1) ^ (int >> 63)) as u64),
+            Thrift::Binary(bytes) => {
+                write_varint(buffer, bytes.len() as u64);
+                buffer.extend_from_slice(bytes);
+            }
+            Thrift::List(items) => {
+                let kind = match items.first() {
+                    Some(Thrift::Binary(_)) => 8,
+                    Some(Thrift::Int(_)) => 5,
+                    _ => 12,
+                };
+                buffer.push(((items.len() as u8) << 4) | kind);
+                for item in items {
+                    write_value(buffer, item);
+                }
+            }
+            Thrift::Struct(fields) => {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|(id, value)| (*id, value.clone()))
+                    .collect();
+                write_struct(buffer, &fields)
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn structure(fields: Vec<(i16, Thrift)>) -> Thrift {
+        Thrift::Struct(fields.into_iter().collect())
+    }
+
+    fn string(value: &str) -> Thrift {
+        Thrift::Binary(value.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_thrift() {
+        let value = structure(vec![
+            (1, Thrift::Int(-3)),
+            (2, string("version")),
+            (4, Thrift::List(vec![Thrift::Int(1), Thrift::Int(300)])),
+            (20, Thrift::Bool(true)),
+            (21, structure(vec![(1, Thrift::Int(1 << 40))])),
+        ]);
+        let mut buffer = vec![];
+        write_value(&mut buffer, &value);
+        assert_eq!(Reader::new(&buffer).structure().unwrap(), value);
+        // A field id delta of 0 is followed by the id, as a zigzag varint
+        assert_eq!(
+            Reader::new(&[0x05, 0x28, 0x06, 0x00]).structure().unwrap(),
+            structure(vec![(20, Thrift::Int(3))])
+        );
+    }
+
+    #[test]
+    fn test_snappy() {
+        // "abcd" as a literal, then a copy of 8 bytes 4 back (overlapping) and a copy of 3 bytes 11 back
+        let compressed = [
+            15,
+            0x0C,
+            b'a',
+            b'b',
+            b'c',
+            b'd',
+            0b0001_0001,
+            4,
+            0b0000_1010,
+            11,
+            0,
+        ];
+        assert_eq!(
+            snappy_decompress(&compressed).unwrap(),
+            b"abcdabcdabcdbcd".to_vec()
+        );
+        assert!(snappy_decompress(&[4, 0b0000_1010, 5, 0]).is_err());
+    }
+
+    #[test]
+    fn test_decode_hybrid() {
+        // A run of 3 ones, then a bit-packed group of 8 values of 3 bits: 0 to 7
+        let data = [0x06, 0x01, 0x03, 0x88, 0xC6, 0xFA];
+        assert_eq!(
+            decode_hybrid(&data, 3, 10).unwrap(),
+            vec![1, 1, 1, 0, 1, 2, 3, 4, 5, 6]
+        );
+        assert_eq!(decode_hybrid(&[0x08], 0, 4).unwrap(), vec![0; 4]);
+    }
+
+    #[test]
+    fn test_decimal_cell() {
+        assert_eq!(decimal_cell(12345, 2), "123.45");
+        assert_eq!(decimal_cell(-5, 3), "-0.005");
+        assert_eq!(decimal_cell(42, 0), "42");
+    }
+
+    /// A column chunk of `pages` (headers and bodies) and its metadata
+    fn column_chunk(
+        file: &mut Vec<u8>,
+        name: &str,
+        codec: i64,
+        num_values: i64,
+        pages: &[(Thrift, Vec<u8>)],
+    ) -> Thrift {
+        let offset = file.len() as i64;
+        for (header, body) in pages {
+            write_value(file, header);
+            file.extend_from_slice(body);
+        }
+        structure(vec![
+            (2, Thrift::Int(offset)),
+            (
+                3,
+                structure(vec![
+                    (1, Thrift::Int(0)),
+                    (2, Thrift::List(vec![Thrift::Int(0)])),
+                    (3, Thrift::List(vec![string(name)])),
+                    (4, Thrift::Int(codec)),
+                    (5, Thrift::Int(num_values)),
+                    (6, Thrift::Int(0)),
+                    (7, Thrift::Int(file.len() as i64 - offset)),
+                    (9, Thrift::Int(offset)),
+                ]),
+            ),
+        ])
+    }
+
+    fn page_header(
+        kind: i64,
+        body: &[u8],
+        uncompressed_size: usize,
+        header: (i16, Thrift),
+    ) -> Thrift {
+        structure(vec![
+            (1, Thrift::Int(kind)),
+            (2, Thrift::Int(uncompressed_size as i64)),
+            (3, Thrift::Int(body.len() as i64)),
+            header,
+        ])
+    }
+
+    #[test]
+    fn test_read_file() {
+        let mut file = MAGIC.to_vec();
+
+        // `version`: required INT64, PLAIN, uncompressed, in a v1 data page
+        let values: Vec<u8> = [7_i64, 8, 9].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let header = page_header(
+            DATA_PAGE,
+            &values,
+            values.len(),
+            (
+                5,
+                structure(vec![
+                    (1, Thrift::Int(3)),
+                    (2, Thrift::Int(PLAIN)),
+                    (3, Thrift::Int(RLE)),
+                    (4, Thrift::Int(RLE)),
+                ]),
+            ),
+        );
+        let version = column_chunk(&mut file, "version", UNCOMPRESSED, 3, &[(header, values)]);
+
+        // `hash`: optional BYTE_ARRAY, dictionary encoded, gzipped, with a null in the middle
+        let mut dictionary = vec![];
+        for value in ["0xa", "0xb"] {
+            dictionary.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            dictionary.extend_from_slice(value.as_bytes());
+        }
+        let gzipped_dictionary = gzip(&dictionary);
+        let dictionary_header = page_header(
+            DICTIONARY_PAGE,
+            &gzipped_dictionary,
+            dictionary.len(),
+            (
+                7,
+                structure(vec![(1, Thrift::Int(2)), (2, Thrift::Int(PLAIN))]),
+            ),
+        );
+        // Definition levels 1, 0, 1 as a bit-packed group, then indices 1, 0 as a bit-packed group of 1 bit
+        let data = [2, 0, 0, 0, 0x03, 0x05, 0x01, 0x03, 0x01];
+        let gzipped_data = gzip(&data);
+        let data_header = page_header(
+            DATA_PAGE,
+            &gzipped_data,
+            data.len(),
+            (
+                5,
+                structure(vec![
+                    (1, Thrift::Int(3)),
+                    (2, Thrift::Int(RLE_DICTIONARY)),
+                    (3, Thrift::Int(RLE)),
+                    (4, Thrift::Int(RLE)),
+                ]),
+            ),
+        );
+        let hash = column_chunk(
+            &mut file,
+            "hash",
+            GZIP,
+            3,
+            &[
+                (dictionary_header, gzipped_dictionary),
+                (data_header, gzipped_data),
+            ],
+        );
+
+        let metadata = structure(vec![
+            (1, Thrift::Int(1)),
+            (
+                2,
+                Thrift::List(vec![
+                    structure(vec![(4, string("schema")), (5, Thrift::Int(2))]),
+                    structure(vec![
+                        (1, Thrift::Int(INT64)),
+                        (3, Thrift::Int(0)),
+                        (4, string("version")),
+                    ]),
+                    structure(vec![
+                        (1, Thrift::Int(BYTE_ARRAY)),
+                        (3, Thrift::Int(1)),
+                        (4, string("hash")),
+                        (6, Thrift::Int(0)),
+                    ]),
+                ]),
+            ),
+            (3, Thrift::Int(3)),
+            (
+                4,
+                Thrift::List(vec![structure(vec![
+                    (1, Thrift::List(vec![version, hash])),
+                    (2, Thrift::Int(0)),
+                    (3, Thrift::Int(3)),
+                ])]),
+            ),
+        ]);
+        let mut footer = vec![];
+        write_value(&mut footer, &metadata);
+        file.extend_from_slice(&footer);
+        file.extend_from_slice(&(footer.len() as u32).to_le_bytes());
+        file.extend_from_slice(MAGIC);
+
+        let path =
+            std::env::temp_dir().join(format!("test_read_file_{}.parquet", std::process::id()));
+        std::fs::write(&path, &file).unwrap();
+        let parquet = ParquetFile::open(&path).unwrap();
+        assert_eq!(
+            parquet
+                .columns()
+                .iter()
+                .map(|column| (column.name.as_str(), column.optional))
+                .collect::<Vec<_>>(),
+            vec![("version", false), ("hash", true)]
+        );
+        let rows = parquet.rows().collect::<Result<Vec<_>>>().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec![Some("7".to_string()), Some("0xb".to_string())],
+                vec![Some("8".to_string()), None],
+                vec![Some("9".to_string()), Some("0xa".to_string())],
+            ]
+        );
+    }
+}
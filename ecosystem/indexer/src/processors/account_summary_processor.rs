@@ -0,0 +1,173 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    database::{insert_chunked, with_deadlock_retry, PgDbPool, PgPoolConnection},
+    indexer::{
+        errors::TransactionProcessingError,
+        metadata_handle::MetadataHandle,
+        processing_result::ProcessingResult,
+        transaction_processor::{ProcessorBase, ProcessorBuilder, TransactionProcessor},
+    },
+    models::account_summary::{AccountSummaryBatch, AccountSummaryModel},
+    schema,
+    util::{bigdecimal_to_u64, u64_to_bigdecimal},
+};
+use aptos_rest_client::Transaction;
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use diesel::{
+    dsl::sql,
+    sql_types::{BigInt, Numeric, Timestamp},
+    Connection, ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl,
+};
+use std::{fmt::Debug, sync::Arc};
+
+pub const NAME: &str = "account_summary_processor";
+
+/// Maintains per-account aggregates (`account_summaries`) by adding each batch's contribution to the stored rows,
+/// rather than recomputing them from the raw tables
+pub struct AccountSummaryTransactionProcessor {
+    base: ProcessorBase,
+}
+
+impl AccountSummaryTransactionProcessor {
+    pub fn new(connection_pool: PgDbPool) -> Self {
+        Self {
+            base: ProcessorBase::new(NAME, connection_pool),
+        }
+    }
+}
+
+impl Debug for AccountSummaryTransactionProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = &self.base.connection_pool().state();
+        write!(
+            f,
+            "AccountSummaryTransactionProcessor {{ connections: {:?}  idle_connections: {:?} }}",
+            state.connections, state.idle_connections
+        )
+    }
+}
+
+/// Ranges of versions overlapping `start_version` to `end_version` that were already added to the summaries. They're
+/// locked until the transaction ends, so a concurrent retry of the same versions waits and then skips them.
+fn get_counted_ranges(
+    conn: &PgPoolConnection,
+    start_version: u64,
+    end_version: u64,
+) -> QueryResult<Vec<(u64, u64)>> {
+    use schema::account_summary_batches::dsl;
+
+    let ranges = dsl::account_summary_batches
+        .select((dsl::start_version, dsl::end_version))
+        .filter(dsl::start_version.le(u64_to_bigdecimal(end_version)))
+        .filter(dsl::end_version.ge(u64_to_bigdecimal(start_version)))
+        .for_update()
+        .load::<(BigDecimal, BigDecimal)>(conn)?;
+    // A corrupted range fails the batch, rather than the indexer
+    let to_u64 = |version: &BigDecimal| {
+        bigdecimal_to_u64(version)
+            .map_err(|err| diesel::result::Error::DeserializationError(Box::new(err)))
+    };
+    ranges
+        .iter()
+        .map(|(start, end)| Ok((to_u64(start)?, to_u64(end)?)))
+        .collect()
+}
+
+fn upsert_account_summaries(
+    conn: &PgPoolConnection,
+    summaries: &[AccountSummaryModel],
+) -> QueryResult<usize> {
+    use schema::account_summaries::dsl::*;
+
+    insert_chunked(conn, summaries, |chunk| {
+        diesel::insert_into(schema::account_summaries::table)
+            .values(chunk)
+            .on_conflict(address)
+            .do_update()
+            .set((
+                transactions_sent.eq(sql::<BigInt>(
+                    "account_summaries.transactions_sent + EXCLUDED.transactions_sent",
+                )),
+                gas_spent.eq(sql::<Numeric>(
+                    "account_summaries.gas_spent + EXCLUDED.gas_spent",
+                )),
+                last_active_version.eq(sql::<Numeric>(
+                    "GREATEST(account_summaries.last_active_version, EXCLUDED.last_active_version)",
+                )),
+                coins_held.eq(sql::<BigInt>(
+                    "account_summaries.coins_held + EXCLUDED.coins_held",
+                )),
+                last_updated.eq(sql::<Timestamp>("EXCLUDED.last_updated")),
+            ))
+    })
+}
+
+impl ProcessorBuilder for AccountSummaryTransactionProcessor {
+    fn base_mut(&mut self) -> &mut ProcessorBase {
+        &mut self.base
+    }
+}
+
+#[async_trait]
+impl TransactionProcessor for AccountSummaryTransactionProcessor {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn process_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<ProcessingResult, TransactionProcessingError> {
+        let tx_result = with_deadlock_retry(self.name(), self.base.get_conn(), |conn| {
+            conn.transaction::<(), diesel::result::Error, _>(|| {
+                // Versions processed before (ex: after a restart from an earlier version) mustn't be added again
+                let counted_ranges = get_counted_ranges(conn, start_version, end_version)?;
+                let summaries = if counted_ranges.is_empty() {
+                    AccountSummaryModel::from_transactions(&transactions)
+                } else {
+                    let uncounted: Vec<Transaction> = transactions
+                        .iter()
+                        .filter(|transaction| {
+                            let version = transaction.version().unwrap_or_default();
+                            !counted_ranges
+                                .iter()
+                                .any(|(start, end)| (*start..=*end).contains(&version))
+                        })
+                        .cloned()
+                        .collect();
+                    AccountSummaryModel::from_transactions(&uncounted)
+                };
+                upsert_account_summaries(conn, &summaries)?;
+                diesel::insert_into(schema::account_summary_batches::table)
+                    .values(AccountSummaryBatch::new(start_version, end_version))
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+                self.base
+                    .transaction_metadata_handle(conn)
+                    .mark_versions_success(start_version, end_version)
+            })
+        })
+        .await;
+        match tx_result {
+            Ok(_) => Ok(
+                ProcessingResult::new(self.name(), start_version, end_version)
+                    .with_status_committed(),
+            ),
+            Err(err) => Err(TransactionProcessingError::from_db_error(
+                err,
+                start_version,
+                end_version,
+                self.name(),
+            )),
+        }
+    }
+
+    fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+        self.base.metadata_handle()
+    }
+}
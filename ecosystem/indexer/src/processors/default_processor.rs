@@ -2,38 +2,89 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    database::{execute_with_better_error, get_chunks, PgDbPool, PgPoolConnection},
+    database::{insert_chunked, with_deadlock_retry, PgDbPool, PgPoolConnection},
     indexer::{
-        errors::TransactionProcessingError, processing_result::ProcessingResult,
-        transaction_processor::TransactionProcessor,
+        errors::TransactionProcessingError,
+        metadata_handle::MetadataHandle,
+        processing_result::ProcessingResult,
+        processor_metadata::PgTransactionMetadataHandle,
+        transaction_processor::{ProcessorBase, ProcessorBuilder, TransactionProcessor},
+        transform::{self, TransformHook},
     },
     models::{
         events::EventModel,
+        outbox::OutboxMessageModel,
+        raw_transactions::RawTransactionModel,
         transactions::{BlockMetadataTransactionModel, TransactionModel, UserTransactionModel},
         write_set_changes::WriteSetChangeModel,
     },
     schema,
+    util::{bigdecimal_to_u64, u64_to_bigdecimal, BigDecimalConversionError},
 };
+use anyhow::Context;
 use aptos_rest_client::Transaction;
 use async_trait::async_trait;
-use field_count::FieldCount;
-use std::fmt::Debug;
+use diesel::{pg::upsert::excluded, ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl};
+use std::{collections::HashSet, fmt::Debug, sync::Arc};
 
 pub const NAME: &str = "default_processor";
 
 pub struct DefaultTransactionProcessor {
-    connection_pool: PgDbPool,
+    base: ProcessorBase,
+    parallel_conversion: bool,
+    outbox: bool,
+    archive_raw: bool,
+    transform: Option<Arc<dyn TransformHook>>,
 }
 
+type Models = (
+    Vec<TransactionModel>,
+    Vec<UserTransactionModel>,
+    Vec<BlockMetadataTransactionModel>,
+    Vec<EventModel>,
+    Vec<WriteSetChangeModel>,
+);
+
 impl DefaultTransactionProcessor {
     pub fn new(connection_pool: PgDbPool) -> Self {
-        Self { connection_pool }
+        Self {
+            base: ProcessorBase::new(NAME, connection_pool),
+            parallel_conversion: false,
+            outbox: false,
+            archive_raw: false,
+            transform: None,
+        }
+    }
+
+    /// Runs `transform` on the models before inserting them, if set
+    pub fn with_transform(mut self, transform: Option<Arc<dyn TransformHook>>) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// Converts each batch into models on rayon's thread pool rather than on the batch's task
+    pub fn with_parallel_conversion(mut self, parallel_conversion: bool) -> Self {
+        self.parallel_conversion = parallel_conversion;
+        self
+    }
+
+    /// Also writes a message per event into `outbox`, in the same DB transaction, for external relays
+    pub fn with_outbox(mut self, outbox: bool) -> Self {
+        self.outbox = outbox;
+        self
+    }
+
+    /// Also writes each transaction as fetched into `raw_transactions`, in the same DB transaction, so processors added
+    /// later can be backfilled from the DB
+    pub fn with_archive_raw(mut self, archive_raw: bool) -> Self {
+        self.archive_raw = archive_raw;
+        self
     }
 }
 
 impl Debug for DefaultTransactionProcessor {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let state = &self.connection_pool.state();
+        let state = &self.base.connection_pool().state();
         write!(
             f,
             "DefaultTransactionProcessor {{ connections: {:?}  idle_connections: {:?} }}",
@@ -42,77 +93,118 @@ impl Debug for DefaultTransactionProcessor {
     }
 }
 
-fn insert_events(conn: &PgPoolConnection, events: &Vec<EventModel>) {
-    let chunks = get_chunks(events.len(), EventModel::field_count());
-    for (start_ind, end_ind) in chunks {
-        execute_with_better_error(
-            conn,
-            diesel::insert_into(schema::events::table)
-                .values(&events[start_ind..end_ind])
-                .on_conflict_do_nothing(),
-        )
-        .expect("Error inserting row into database");
-    }
+pub(crate) fn insert_events(conn: &PgPoolConnection, events: &[EventModel]) -> QueryResult<usize> {
+    insert_chunked(conn, events, |chunk| {
+        diesel::insert_into(schema::events::table)
+            .values(chunk)
+            .on_conflict((schema::events::key, schema::events::sequence_number))
+            .do_update()
+            .set((
+                schema::events::event_index.eq(excluded(schema::events::event_index)),
+                schema::events::transaction_timestamp
+                    .eq(excluded(schema::events::transaction_timestamp)),
+            ))
+    })
 }
 
-fn insert_write_set_changes(conn: &PgPoolConnection, write_set_changes: &Vec<WriteSetChangeModel>) {
-    let chunks = get_chunks(write_set_changes.len(), WriteSetChangeModel::field_count());
-    for (start_ind, end_ind) in chunks {
-        execute_with_better_error(
-            conn,
-            diesel::insert_into(schema::write_set_changes::table)
-                .values(&write_set_changes[start_ind..end_ind])
-                .on_conflict_do_nothing(),
-        )
-        .expect("Error inserting row into database");
-    }
+pub(crate) fn insert_write_set_changes(
+    conn: &PgPoolConnection,
+    write_set_changes: &[WriteSetChangeModel],
+) -> QueryResult<usize> {
+    insert_chunked(conn, write_set_changes, |chunk| {
+        diesel::insert_into(schema::write_set_changes::table)
+            .values(chunk)
+            .on_conflict((
+                schema::write_set_changes::transaction_hash,
+                schema::write_set_changes::hash,
+            ))
+            .do_update()
+            .set((
+                schema::write_set_changes::write_set_change_index
+                    .eq(excluded(schema::write_set_changes::write_set_change_index)),
+                schema::write_set_changes::transaction_timestamp
+                    .eq(excluded(schema::write_set_changes::transaction_timestamp)),
+            ))
+    })
 }
 
-fn insert_transactions(conn: &PgPoolConnection, txns: &[TransactionModel]) {
-    let chunks = get_chunks(txns.len(), TransactionModel::field_count());
-    for (start_ind, end_ind) in chunks {
-        execute_with_better_error(
-            conn,
-            diesel::insert_into(schema::transactions::table)
-                .values(&txns[start_ind..end_ind])
-                .on_conflict_do_nothing(),
-        )
-        .expect("Error inserting row into database");
-    }
+pub(crate) fn insert_transactions(
+    conn: &PgPoolConnection,
+    txns: &[TransactionModel],
+) -> QueryResult<usize> {
+    insert_chunked(conn, txns, |chunk| {
+        diesel::insert_into(schema::transactions::table)
+            .values(chunk)
+            .on_conflict(schema::transactions::hash)
+            .do_update()
+            .set((
+                schema::transactions::num_events.eq(excluded(schema::transactions::num_events)),
+                schema::transactions::num_write_set_changes
+                    .eq(excluded(schema::transactions::num_write_set_changes)),
+                schema::transactions::payload_size_bytes
+                    .eq(excluded(schema::transactions::payload_size_bytes)),
+            ))
+    })
 }
 
-fn insert_user_transactions(conn: &PgPoolConnection, user_txns: &[UserTransactionModel]) {
-    let chunks = get_chunks(user_txns.len(), UserTransactionModel::field_count());
-    for (start_ind, end_ind) in chunks {
-        execute_with_better_error(
-            conn,
-            diesel::insert_into(schema::user_transactions::table)
-                .values(&user_txns[start_ind..end_ind])
-                .on_conflict_do_nothing(),
-        )
-        .expect("Error inserting row into database");
-    }
+pub(crate) fn insert_user_transactions(
+    conn: &PgPoolConnection,
+    user_txns: &[UserTransactionModel],
+) -> QueryResult<usize> {
+    insert_chunked(conn, user_txns, |chunk| {
+        diesel::insert_into(schema::user_transactions::table)
+            .values(chunk)
+            .on_conflict_do_nothing()
+    })
 }
 
-fn insert_block_metadata_transactions(
+pub(crate) fn insert_block_metadata_transactions(
     conn: &PgPoolConnection,
     bm_txns: &[BlockMetadataTransactionModel],
-) {
-    let chunks = get_chunks(bm_txns.len(), UserTransactionModel::field_count());
-    for (start_ind, end_ind) in chunks {
-        execute_with_better_error(
-            conn,
-            diesel::insert_into(schema::block_metadata_transactions::table)
-                .values(&bm_txns[start_ind..end_ind])
-                .on_conflict_do_nothing(),
-        )
-        .expect("Error inserting row into database");
-    }
+) -> QueryResult<usize> {
+    insert_chunked(conn, bm_txns, |chunk| {
+        diesel::insert_into(schema::block_metadata_transactions::table)
+            .values(chunk)
+            .on_conflict_do_nothing()
+    })
+}
+
+pub(crate) fn insert_outbox_messages(
+    conn: &PgPoolConnection,
+    messages: &[OutboxMessageModel],
+) -> QueryResult<usize> {
+    insert_chunked(conn, messages, |chunk| {
+        diesel::insert_into(schema::outbox::table)
+            .values(chunk)
+            .on_conflict_do_nothing()
+    })
 }
 
-fn insert_to_db(
+pub(crate) fn insert_raw_transactions(
     conn: &PgPoolConnection,
-    name: &'static str,
+    raw_txns: &[RawTransactionModel],
+) -> QueryResult<usize> {
+    insert_chunked(conn, raw_txns, |chunk| {
+        diesel::insert_into(schema::raw_transactions::table)
+            .values(chunk)
+            .on_conflict_do_nothing()
+    })
+}
+
+fn transform_models(transform: &dyn TransformHook, models: Models) -> anyhow::Result<Models> {
+    let (txns, user_txns, bm_txns, events, write_set_changes) = models;
+    Ok((
+        transform::apply(transform, "transactions", txns)?,
+        transform::apply(transform, "user_transactions", user_txns)?,
+        transform::apply(transform, "block_metadata_transactions", bm_txns)?,
+        transform::apply(transform, "events", events)?,
+        transform::apply(transform, "write_set_changes", write_set_changes)?,
+    ))
+}
+
+async fn insert_to_db(
+    conn: PgPoolConnection,
+    name: &str,
     start_version: u64,
     end_version: u64,
     txns: Vec<TransactionModel>,
@@ -120,6 +212,8 @@ fn insert_to_db(
     bm_txns: Vec<BlockMetadataTransactionModel>,
     events: Vec<EventModel>,
     wscs: Vec<WriteSetChangeModel>,
+    outbox_messages: Vec<OutboxMessageModel>,
+    raw_txns: Vec<RawTransactionModel>,
 ) -> Result<(), diesel::result::Error> {
     aptos_logger::trace!(
         "[{}] inserting versions {} to {}",
@@ -127,22 +221,34 @@ fn insert_to_db(
         start_version,
         end_version
     );
-    conn.build_transaction()
-        .read_write()
-        .run::<_, diesel::result::Error, _>(|| {
-            insert_transactions(conn, &txns);
-            insert_user_transactions(conn, &user_txns);
-            insert_block_metadata_transactions(conn, &bm_txns);
-            insert_events(conn, &events);
-            insert_write_set_changes(conn, &wscs);
-            Ok(())
-        })
+    with_deadlock_retry(name, conn, |conn| {
+        conn.build_transaction()
+            .read_write()
+            .run::<_, diesel::result::Error, _>(|| {
+                insert_transactions(conn, &txns)?;
+                insert_user_transactions(conn, &user_txns)?;
+                insert_block_metadata_transactions(conn, &bm_txns)?;
+                insert_events(conn, &events)?;
+                insert_write_set_changes(conn, &wscs)?;
+                insert_outbox_messages(conn, &outbox_messages)?;
+                insert_raw_transactions(conn, &raw_txns)?;
+                PgTransactionMetadataHandle::new(conn, name)
+                    .mark_versions_success(start_version, end_version)
+            })
+    })
+    .await
+}
+
+impl ProcessorBuilder for DefaultTransactionProcessor {
+    fn base_mut(&mut self) -> &mut ProcessorBase {
+        &mut self.base
+    }
 }
 
 #[async_trait]
 impl TransactionProcessor for DefaultTransactionProcessor {
-    fn name(&self) -> &'static str {
-        NAME
+    fn name(&self) -> &str {
+        self.base.name()
     }
 
     async fn process_transactions(
@@ -151,12 +257,42 @@ impl TransactionProcessor for DefaultTransactionProcessor {
         start_version: u64,
         end_version: u64,
     ) -> Result<ProcessingResult, TransactionProcessingError> {
-        let (txns, user_txns, bm_txns, events, write_set_changes) =
-            TransactionModel::from_transactions(&transactions);
+        let models = if self.parallel_conversion {
+            TransactionModel::from_transactions_parallel(&transactions)
+        } else {
+            TransactionModel::from_transactions(&transactions)
+        };
+        let (txns, user_txns, bm_txns, events, write_set_changes) = match &self.transform {
+            Some(transform) => transform_models(transform.as_ref(), models).map_err(|err| {
+                TransactionProcessingError::Custom((
+                    err,
+                    start_version,
+                    end_version,
+                    self.name().to_string(),
+                ))
+            })?,
+            None => models,
+        };
+        let outbox_messages = if self.outbox {
+            OutboxMessageModel::from_events(&txns, &events)
+        } else {
+            vec![]
+        };
+        let raw_txns = if self.archive_raw {
+            RawTransactionModel::from_transactions(&transactions).map_err(|err| {
+                TransactionProcessingError::Custom((
+                    err,
+                    start_version,
+                    end_version,
+                    self.name().to_string(),
+                ))
+            })?
+        } else {
+            vec![]
+        };
 
-        let conn = self.get_conn();
         let tx_result = insert_to_db(
-            &conn,
+            self.base.get_conn(),
             self.name(),
             start_version,
             end_version,
@@ -165,23 +301,128 @@ impl TransactionProcessor for DefaultTransactionProcessor {
             bm_txns,
             events,
             write_set_changes,
-        );
+            outbox_messages,
+            raw_txns,
+        )
+        .await;
         match tx_result {
-            Ok(_) => Ok(ProcessingResult::new(
-                self.name(),
-                start_version,
-                end_version,
-            )),
-            Err(err) => Err(TransactionProcessingError::TransactionCommitError((
-                anyhow::Error::from(err),
+            Ok(_) => Ok(
+                ProcessingResult::new(self.name(), start_version, end_version)
+                    .with_status_committed(),
+            ),
+            Err(err) => Err(TransactionProcessingError::from_db_error(
+                err,
                 start_version,
                 end_version,
                 self.name(),
-            ))),
+            )),
         }
     }
 
-    fn connection_pool(&self) -> &PgDbPool {
-        &self.connection_pool
+    fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+        self.base.metadata_handle()
+    }
+
+    /// Every version has a row in `transactions`
+    fn find_missing_versions(&self, versions: &[u64]) -> anyhow::Result<Option<Vec<u64>>> {
+        let conn = self.base.get_conn();
+        let found: HashSet<u64> = schema::transactions::table
+            .select(schema::transactions::version)
+            .filter(
+                schema::transactions::version.eq_any(
+                    versions
+                        .iter()
+                        .map(|v| u64_to_bigdecimal(*v))
+                        .collect::<Vec<_>>(),
+                ),
+            )
+            .load::<bigdecimal::BigDecimal>(&conn)
+            .context("Error loading versions from transactions")?
+            .iter()
+            .map(bigdecimal_to_u64)
+            .collect::<Result<_, BigDecimalConversionError>>()
+            .context("Invalid version in transactions")?;
+        Ok(Some(
+            versions
+                .iter()
+                .filter(|version| !found.contains(version))
+                .copied()
+                .collect(),
+        ))
+    }
+
+    /// Hashes are in `transactions`
+    fn get_stored_hashes(
+        &self,
+        start_version: u64,
+        end_version: u64,
+    ) -> anyhow::Result<Option<Vec<(u64, String)>>> {
+        let conn = self.base.get_conn();
+        let hashes = schema::transactions::table
+            .select((schema::transactions::version, schema::transactions::hash))
+            .filter(schema::transactions::version.between(
+                u64_to_bigdecimal(start_version),
+                u64_to_bigdecimal(end_version),
+            ))
+            .order(schema::transactions::version.asc())
+            .load::<(bigdecimal::BigDecimal, String)>(&conn)
+            .context("Error loading hashes from transactions")?
+            .into_iter()
+            .map(|(version, hash)| Ok((bigdecimal_to_u64(&version)?, hash)))
+            .collect::<Result<_, BigDecimalConversionError>>()
+            .context("Invalid version in transactions")?;
+        Ok(Some(hashes))
+    }
+
+    /// Every table is keyed by transaction hash, so the replaced transactions' rows are found through `transactions`.
+    /// Their undelivered outbox messages are dropped; delivered ones can't be taken back.
+    fn revert_versions(&self, start_version: u64, end_version: u64) -> anyhow::Result<()> {
+        let conn = self.base.get_conn();
+        conn.build_transaction()
+            .read_write()
+            .run::<_, diesel::result::Error, _>(|| {
+                let hashes: Vec<String> = schema::transactions::table
+                    .select(schema::transactions::hash)
+                    .filter(schema::transactions::version.between(
+                        u64_to_bigdecimal(start_version),
+                        u64_to_bigdecimal(end_version),
+                    ))
+                    .load(&conn)?;
+                diesel::delete(
+                    schema::events::table
+                        .filter(schema::events::transaction_hash.eq_any(hashes.clone())),
+                )
+                .execute(&conn)?;
+                diesel::delete(
+                    schema::write_set_changes::table
+                        .filter(schema::write_set_changes::transaction_hash.eq_any(hashes.clone())),
+                )
+                .execute(&conn)?;
+                diesel::delete(
+                    schema::user_transactions::table
+                        .filter(schema::user_transactions::hash.eq_any(hashes.clone())),
+                )
+                .execute(&conn)?;
+                diesel::delete(
+                    schema::block_metadata_transactions::table
+                        .filter(schema::block_metadata_transactions::hash.eq_any(hashes.clone())),
+                )
+                .execute(&conn)?;
+                diesel::delete(
+                    schema::transactions::table.filter(schema::transactions::hash.eq_any(hashes)),
+                )
+                .execute(&conn)?;
+                diesel::delete(
+                    schema::outbox::table
+                        .filter(schema::outbox::transaction_version.between(
+                            u64_to_bigdecimal(start_version),
+                            u64_to_bigdecimal(end_version),
+                        ))
+                        .filter(schema::outbox::delivered_at.is_null()),
+                )
+                .execute(&conn)?;
+                Ok(())
+            })?;
+        Ok(())
     }
 }
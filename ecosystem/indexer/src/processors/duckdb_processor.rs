@@ -0,0 +1,381 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Appends the default processor's tables (`transactions`, `user_transactions`, `block_metadata_transactions`,
+//! `events` and `write_set_changes`) to a local DuckDB file, for laptops and ephemeral analysis environments. Only
+//! built with the `duckdb` feature.
+//!
+//! DuckDB is embedded through its C API (see `crate::duckdb`): each batch's rows are written to newline-delimited JSON
+//! files, which are loaded with `read_json` in a single DuckDB transaction. Tables are created on the first batch, with
+//! the same columns and primary keys as in Postgres (numeric columns as `UBIGINT`, `jsonb` ones as `JSON`), and rows
+//! already in the file are skipped, so re-processing a batch is harmless. DuckDB files take a single writer, so batches
+//! are appended one at a time, each opening and closing the file, which can only be read (ex: with `duckdb -readonly`)
+//! between them. Statuses are still recorded in Postgres.
+
+use crate::{
+    database::PgDbPool,
+    duckdb::Database,
+    indexer::{
+        errors::TransactionProcessingError,
+        metadata_handle::MetadataHandle,
+        processing_result::ProcessingResult,
+        transaction_processor::{ProcessorBase, ProcessorBuilder, TransactionProcessor},
+    },
+    models::transactions::TransactionModel,
+};
+use anyhow::{Context, Result};
+use aptos_rest_client::Transaction;
+use async_trait::async_trait;
+use serde::Serialize;
+use std::{
+    fmt::Debug,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::sync::Mutex;
+
+pub const NAME: &str = "duckdb_processor";
+
+/// A table's columns, in the order of the models' fields, and its primary key
+struct TableSchema {
+    name: &'static str,
+    columns: &'static [(&'static str, &'static str)],
+    primary_key: &'static [&'static str],
+}
+
+const TABLES: [TableSchema; 5] = [
+    TableSchema {
+        name: "transactions",
+        columns: &[
+            ("type", "VARCHAR"),
+            ("payload", "JSON"),
+            ("version", "UBIGINT"),
+            ("hash", "VARCHAR"),
+            ("state_root_hash", "VARCHAR"),
+            ("event_root_hash", "VARCHAR"),
+            ("gas_used", "UBIGINT"),
+            ("success", "BOOLEAN"),
+            ("vm_status", "VARCHAR"),
+            ("accumulator_root_hash", "VARCHAR"),
+            ("inserted_at", "TIMESTAMP"),
+            ("num_events", "BIGINT"),
+            ("num_write_set_changes", "BIGINT"),
+            ("payload_size_bytes", "BIGINT"),
+            ("timestamp", "TIMESTAMP"),
+        ],
+        primary_key: &["hash"],
+    },
+    TableSchema {
+        name: "user_transactions",
+        columns: &[
+            ("hash", "VARCHAR"),
+            ("signature", "JSON"),
+            ("sender", "VARCHAR"),
+            ("sequence_number", "UBIGINT"),
+            ("max_gas_amount", "UBIGINT"),
+            ("expiration_timestamp_secs", "TIMESTAMP"),
+            ("gas_unit_price", "UBIGINT"),
+            ("timestamp", "TIMESTAMP"),
+            ("inserted_at", "TIMESTAMP"),
+        ],
+        primary_key: &["hash"],
+    },
+    TableSchema {
+        name: "block_metadata_transactions",
+        columns: &[
+            ("hash", "VARCHAR"),
+            ("id", "VARCHAR"),
+            ("round", "UBIGINT"),
+            ("previous_block_votes", "JSON"),
+            ("proposer", "VARCHAR"),
+            ("timestamp", "TIMESTAMP"),
+            ("inserted_at", "TIMESTAMP"),
+            ("epoch", "UBIGINT"),
+            ("previous_block_votes_bitvec", "JSON"),
+            ("failed_proposer_indices", "JSON"),
+        ],
+        primary_key: &["hash"],
+    },
+    TableSchema {
+        name: "events",
+        columns: &[
+            ("transaction_hash", "VARCHAR"),
+            ("key", "VARCHAR"),
+            ("sequence_number", "UBIGINT"),
+            ("type", "VARCHAR"),
+            ("data", "JSON"),
+            ("inserted_at", "TIMESTAMP"),
+            ("event_index", "BIGINT"),
+            ("transaction_timestamp", "TIMESTAMP"),
+        ],
+        primary_key: &["transaction_hash", "event_index"],
+    },
+    TableSchema {
+        name: "write_set_changes",
+        columns: &[
+            ("transaction_hash", "VARCHAR"),
+            ("hash", "VARCHAR"),
+            ("type", "VARCHAR"),
+            ("address", "VARCHAR"),
+            ("module", "JSON"),
+            ("resource", "JSON"),
+            ("data", "JSON"),
+            ("inserted_at", "TIMESTAMP"),
+            ("write_set_change_index", "BIGINT"),
+            ("transaction_timestamp", "TIMESTAMP"),
+        ],
+        primary_key: &["transaction_hash", "write_set_change_index"],
+    },
+];
+
+/// Quotes `s` as a SQL string literal
+fn quote_literal(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+impl TableSchema {
+    fn create_statement(&self) -> String {
+        let columns: Vec<String> = self
+            .columns
+            .iter()
+            .map(|(name, typ)| format!("\"{}\" {}", name, typ))
+            .collect();
+        let primary_key: Vec<String> = self
+            .primary_key
+            .iter()
+            .map(|name| format!("\"{}\"", name))
+            .collect();
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} ({}, PRIMARY KEY ({}));",
+            self.name,
+            columns.join(", "),
+            primary_key.join(", ")
+        )
+    }
+
+    /// Loads the rows of the newline-delimited JSON file at `path`, skipping the ones already in the table
+    fn insert_statement(&self, path: &Path) -> String {
+        let columns: Vec<String> = self
+            .columns
+            .iter()
+            .map(|(name, typ)| format!("{}: {}", quote_literal(name), quote_literal(typ)))
+            .collect();
+        format!(
+            "INSERT OR IGNORE INTO {} SELECT * FROM read_json({}, format = 'newline_delimited', columns = {{{}}});",
+            self.name,
+            quote_literal(&path.to_string_lossy()),
+            columns.join(", ")
+        )
+    }
+}
+
+/// The script appending a batch: `files` are the tables' files, `None` for tables without rows in the batch
+fn batch_script(files: &[Option<PathBuf>]) -> String {
+    let mut statements: Vec<String> = TABLES.iter().map(TableSchema::create_statement).collect();
+    statements.push("BEGIN TRANSACTION;".to_string());
+    for (table, file) in TABLES.iter().zip(files) {
+        if let Some(path) = file {
+            statements.push(table.insert_statement(path));
+        }
+    }
+    statements.push("COMMIT;".to_string());
+    statements.join("\n")
+}
+
+/// Writes `rows` to a newline-delimited JSON file at `path`, if there are any
+fn write_rows<T: Serialize>(path: PathBuf, rows: &[T]) -> Result<Option<PathBuf>> {
+    if rows.is_empty() {
+        return Ok(None);
+    }
+    let file =
+        std::fs::File::create(&path).with_context(|| format!("Could not create {:?}", path))?;
+    let mut writer = BufWriter::new(file);
+    for row in rows {
+        serde_json::to_writer(&mut writer, row)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(Some(path))
+}
+
+#[derive(Clone, Debug)]
+pub struct DuckDbConfig {
+    /// The DuckDB file, created if it doesn't exist
+    pub path: PathBuf,
+    /// Where batches' rows are staged before being loaded. Defaults to the system's temporary directory.
+    pub staging_dir: Option<PathBuf>,
+}
+
+impl DuckDbConfig {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            staging_dir: None,
+        }
+    }
+}
+
+/// Appends the default processor's tables to a DuckDB file
+pub struct DuckDbTransactionProcessor {
+    base: ProcessorBase,
+    config: DuckDbConfig,
+    /// Held while a batch is appended, as the file takes a single writer
+    write_lock: Mutex<()>,
+}
+
+impl DuckDbTransactionProcessor {
+    pub fn new(connection_pool: PgDbPool, config: DuckDbConfig) -> Self {
+        Self {
+            base: ProcessorBase::new(NAME, connection_pool),
+            config,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// Stages the batch's rows, one file per table, under names unique to the batch
+    fn stage(
+        &self,
+        transactions: &[Transaction],
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<Vec<Option<PathBuf>>> {
+        let (txns, user_txns, bm_txns, events, write_set_changes) =
+            TransactionModel::from_transactions(transactions);
+        let dir = self
+            .config
+            .staging_dir
+            .clone()
+            .unwrap_or_else(std::env::temp_dir);
+        let path = |table: &str| {
+            dir.join(format!(
+                "{}-{}-{}-{}-{}.ndjson",
+                self.base.name(),
+                std::process::id(),
+                start_version,
+                end_version,
+                table
+            ))
+        };
+        Ok(vec![
+            write_rows(path(TABLES[0].name), &txns)?,
+            write_rows(path(TABLES[1].name), &user_txns)?,
+            write_rows(path(TABLES[2].name), &bm_txns)?,
+            write_rows(path(TABLES[3].name), &events)?,
+            write_rows(path(TABLES[4].name), &write_set_changes)?,
+        ])
+    }
+
+    /// Runs `script` against the file, stopping at the first error. The file is closed afterwards, which rolls back
+    /// the batch's transaction if it failed.
+    async fn run_script(&self, script: String) -> Result<()> {
+        let _guard = self.write_lock.lock().await;
+        let path = self.config.path.clone();
+        tokio::task::spawn_blocking(move || {
+            let database = Database::open(&path)?;
+            let connection = database.connect()?;
+            connection
+                .execute(&script)
+                .with_context(|| format!("Could not append to {:?}", path))
+        })
+        .await
+        .context("Appending to DuckDB panicked")?
+    }
+
+    async fn append(
+        &self,
+        transactions: &[Transaction],
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<()> {
+        let files = self.stage(transactions, start_version, end_version);
+        let result = match &files {
+            Ok(files) => self.run_script(batch_script(files)).await,
+            Err(_) => Ok(()),
+        };
+        for path in files.iter().flatten().flatten() {
+            let _ = std::fs::remove_file(path);
+        }
+        files?;
+        result
+    }
+}
+
+impl Debug for DuckDbTransactionProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "DuckDbTransactionProcessor {{ path: {:?} }}",
+            self.config.path
+        )
+    }
+}
+
+impl ProcessorBuilder for DuckDbTransactionProcessor {
+    fn base_mut(&mut self) -> &mut ProcessorBase {
+        &mut self.base
+    }
+}
+
+#[async_trait]
+impl TransactionProcessor for DuckDbTransactionProcessor {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn process_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<ProcessingResult, TransactionProcessingError> {
+        self.append(&transactions, start_version, end_version)
+            .await
+            .map_err(|err| {
+                TransactionProcessingError::Custom((
+                    err,
+                    start_version,
+                    end_version,
+                    self.name().to_string(),
+                ))
+            })?;
+        Ok(ProcessingResult::new(
+            self.name(),
+            start_version,
+            end_version,
+        ))
+    }
+
+    fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+        self.base.metadata_handle()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_script() {
+        let script = batch_script(&[
+            Some(PathBuf::from("/tmp/it's-transactions.ndjson")),
+            None,
+            None,
+            Some(PathBuf::from("/tmp/events.ndjson")),
+            None,
+        ]);
+        let lines: Vec<&str> = script.lines().collect();
+        assert_eq!(lines.len(), TABLES.len() + 4);
+        assert!(lines[3].starts_with(
+            "CREATE TABLE IF NOT EXISTS events (\"transaction_hash\" VARCHAR, \"key\" VARCHAR,"
+        ));
+        assert!(lines[3].ends_with("PRIMARY KEY (\"transaction_hash\", \"event_index\"));"));
+        assert_eq!(lines[5], "BEGIN TRANSACTION;");
+        assert!(lines[6].starts_with(
+            "INSERT OR IGNORE INTO transactions SELECT * FROM read_json('/tmp/it''s-transactions.ndjson',"
+        ));
+        assert!(lines[7].starts_with("INSERT OR IGNORE INTO events"));
+        assert!(lines[7].contains("columns = {'transaction_hash': 'VARCHAR', 'key': 'VARCHAR',"));
+        assert_eq!(lines[8], "COMMIT;");
+    }
+}
@@ -0,0 +1,119 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    database::{insert_chunked, with_deadlock_retry, PgDbPool, PgPoolConnection},
+    indexer::{
+        errors::TransactionProcessingError,
+        metadata_handle::MetadataHandle,
+        processing_result::ProcessingResult,
+        transaction_processor::{ProcessorBase, ProcessorBuilder, TransactionProcessor},
+    },
+    models::{
+        epoch::{EpochModel, ValidatorSetSnapshotModel},
+        transactions::TransactionModel,
+    },
+    schema,
+};
+use aptos_rest_client::Transaction;
+use async_trait::async_trait;
+use diesel::{Connection, QueryResult};
+use std::{fmt::Debug, sync::Arc};
+
+pub const NAME: &str = "epoch_processor";
+
+/// Records every new epoch (`0x1::reconfiguration::NewEpochEvent`) into `epochs`, and the validator set it started
+/// with into `validator_set_snapshots`
+pub struct EpochTransactionProcessor {
+    base: ProcessorBase,
+}
+
+impl EpochTransactionProcessor {
+    pub fn new(connection_pool: PgDbPool) -> Self {
+        Self {
+            base: ProcessorBase::new(NAME, connection_pool),
+        }
+    }
+}
+
+impl Debug for EpochTransactionProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = &self.base.connection_pool().state();
+        write!(
+            f,
+            "EpochTransactionProcessor {{ connections: {:?}  idle_connections: {:?} }}",
+            state.connections, state.idle_connections
+        )
+    }
+}
+
+fn insert_epochs(conn: &PgPoolConnection, epochs: &[EpochModel]) -> QueryResult<usize> {
+    insert_chunked(conn, epochs, |chunk| {
+        diesel::insert_into(schema::epochs::table)
+            .values(chunk)
+            .on_conflict_do_nothing()
+    })
+}
+
+fn insert_validator_set_snapshots(
+    conn: &PgPoolConnection,
+    snapshots: &[ValidatorSetSnapshotModel],
+) -> QueryResult<usize> {
+    insert_chunked(conn, snapshots, |chunk| {
+        diesel::insert_into(schema::validator_set_snapshots::table)
+            .values(chunk)
+            .on_conflict_do_nothing()
+    })
+}
+
+impl ProcessorBuilder for EpochTransactionProcessor {
+    fn base_mut(&mut self) -> &mut ProcessorBase {
+        &mut self.base
+    }
+}
+
+#[async_trait]
+impl TransactionProcessor for EpochTransactionProcessor {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn process_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<ProcessingResult, TransactionProcessingError> {
+        let (txns, user_txns, bm_txns, events, write_set_changes) =
+            TransactionModel::from_transactions(&transactions);
+        let (epochs, snapshots) =
+            EpochModel::from_transactions(&txns, &user_txns, &bm_txns, &events, &write_set_changes);
+
+        let tx_result = with_deadlock_retry(self.name(), self.base.get_conn(), |conn| {
+            conn.transaction::<(), diesel::result::Error, _>(|| {
+                insert_epochs(conn, &epochs)?;
+                insert_validator_set_snapshots(conn, &snapshots)?;
+                self.base
+                    .transaction_metadata_handle(conn)
+                    .mark_versions_success(start_version, end_version)
+            })
+        })
+        .await;
+        match tx_result {
+            Ok(_) => Ok(
+                ProcessingResult::new(self.name(), start_version, end_version)
+                    .with_status_committed(),
+            ),
+            Err(err) => Err(TransactionProcessingError::from_db_error(
+                err,
+                start_version,
+                end_version,
+                self.name(),
+            )),
+        }
+    }
+
+    fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+        self.base.metadata_handle()
+    }
+}
@@ -0,0 +1,244 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    database::{
+        insert_chunked, latest_version_wins, with_deadlock_retry, PgDbPool, PgPoolConnection,
+    },
+    indexer::{
+        errors::TransactionProcessingError,
+        metadata_handle::MetadataHandle,
+        price_provider::{to_usd_value, PriceProvider},
+        processing_result::ProcessingResult,
+        transaction_processor::{ProcessorBase, ProcessorBuilder, TransactionProcessor},
+    },
+    models::fungible_asset::{
+        FungibleAssetActivityModel, FungibleAssetBalanceHistoryModel, FungibleAssetBalanceModel,
+        FungibleAssetMetadataModel,
+    },
+    schema,
+};
+use aptos_rest_client::Transaction;
+use async_trait::async_trait;
+use diesel::{
+    dsl::sql,
+    sql_types::{Bool, Nullable, Numeric, Text},
+    Connection, ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl,
+};
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
+
+pub const NAME: &str = "fungible_asset_processor";
+
+/// Indexes the fungible asset standard (`0x1::fungible_asset`): assets into `fungible_asset_metadata`, the current
+/// balance of each store into `fungible_asset_balances` (and every balance it had into
+/// `fungible_asset_balance_history`) and deposits, withdrawals and freezes into `fungible_asset_activities`
+pub struct FungibleAssetTransactionProcessor {
+    base: ProcessorBase,
+    price_provider: Option<Arc<dyn PriceProvider>>,
+}
+
+impl FungibleAssetTransactionProcessor {
+    pub fn new(connection_pool: PgDbPool) -> Self {
+        Self {
+            base: ProcessorBase::new(NAME, connection_pool),
+            price_provider: None,
+        }
+    }
+
+    /// Attaches the USD value of deposits and withdrawals, at the provider's current prices
+    pub fn with_price_provider(mut self, price_provider: Option<Arc<dyn PriceProvider>>) -> Self {
+        self.price_provider = price_provider;
+        self
+    }
+
+    /// Sets `amount_usd` on the activities whose asset's price and decimals are known. Failing to get prices
+    /// doesn't fail the batch: the values are left NULL.
+    async fn attach_usd_values(
+        &self,
+        metadata: &[FungibleAssetMetadataModel],
+        activities: &mut [FungibleAssetActivityModel],
+    ) {
+        let price_provider = match &self.price_provider {
+            Some(price_provider) => price_provider,
+            None => return,
+        };
+        let mut asset_types: Vec<String> = activities
+            .iter()
+            .filter(|activity| activity.amount.is_some())
+            .filter_map(|activity| activity.asset_type.clone())
+            .collect();
+        asset_types.sort();
+        asset_types.dedup();
+        if asset_types.is_empty() {
+            return;
+        }
+
+        let mut decimals: HashMap<String, i64> = metadata
+            .iter()
+            .map(|asset| (asset.asset_type.clone(), asset.decimals))
+            .collect();
+        decimals.extend(get_asset_decimals(&self.base.get_conn(), &asset_types));
+        let prices = match price_provider.get_usd_prices(&asset_types).await {
+            Ok(prices) => prices,
+            Err(err) => {
+                aptos_logger::warn!(
+                    price_provider = price_provider.name(),
+                    error = format!("{:?}", err),
+                    "Failed to get USD prices"
+                );
+                return;
+            }
+        };
+        for activity in activities {
+            if let (Some(amount), Some(asset_type)) = (&activity.amount, &activity.asset_type) {
+                if let (Some(decimals), Some(price)) =
+                    (decimals.get(asset_type), prices.get(asset_type))
+                {
+                    activity.amount_usd = Some(to_usd_value(amount, *decimals, price));
+                }
+            }
+        }
+    }
+}
+
+impl Debug for FungibleAssetTransactionProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = &self.base.connection_pool().state();
+        write!(
+            f,
+            "FungibleAssetTransactionProcessor {{ price_provider: {:?} connections: {:?}  idle_connections: {:?} }}",
+            self.price_provider.as_ref().map(|provider| provider.name()),
+            state.connections,
+            state.idle_connections
+        )
+    }
+}
+
+fn insert_fungible_asset_metadata(
+    conn: &PgPoolConnection,
+    metadata: &[FungibleAssetMetadataModel],
+) -> QueryResult<usize> {
+    insert_chunked(conn, metadata, |chunk| {
+        diesel::insert_into(schema::fungible_asset_metadata::table)
+            .values(chunk)
+            .on_conflict_do_nothing()
+    })
+}
+
+fn insert_fungible_asset_balances(
+    conn: &PgPoolConnection,
+    balances: &[FungibleAssetBalanceModel],
+) -> QueryResult<usize> {
+    use schema::fungible_asset_balances::dsl::*;
+
+    let table = "fungible_asset_balances";
+    insert_chunked(conn, balances, |chunk| {
+        diesel::insert_into(schema::fungible_asset_balances::table)
+            .values(chunk)
+            .on_conflict(storage_id)
+            .do_update()
+            .set((
+                // The owner is only known when the store's `ObjectCore` was written, so keep the existing one
+                owner_address.eq(sql::<Nullable<Text>>(
+                    "CASE WHEN EXCLUDED.last_transaction_version >= fungible_asset_balances.last_transaction_version \
+                     THEN COALESCE(EXCLUDED.owner_address, fungible_asset_balances.owner_address) \
+                     ELSE fungible_asset_balances.owner_address END",
+                )),
+                asset_type.eq(latest_version_wins::<Text>(table, "asset_type")),
+                amount.eq(latest_version_wins::<Numeric>(table, "amount")),
+                is_frozen.eq(latest_version_wins::<Bool>(table, "is_frozen")),
+                last_transaction_version.eq(latest_version_wins::<Numeric>(
+                    table,
+                    "last_transaction_version",
+                )),
+            ))
+    })
+}
+
+fn insert_fungible_asset_balance_history(
+    conn: &PgPoolConnection,
+    balance_history: &[FungibleAssetBalanceHistoryModel],
+) -> QueryResult<usize> {
+    insert_chunked(conn, balance_history, |chunk| {
+        diesel::insert_into(schema::fungible_asset_balance_history::table)
+            .values(chunk)
+            .on_conflict_do_nothing()
+    })
+}
+
+fn insert_fungible_asset_activities(
+    conn: &PgPoolConnection,
+    activities: &[FungibleAssetActivityModel],
+) -> QueryResult<usize> {
+    insert_chunked(conn, activities, |chunk| {
+        diesel::insert_into(schema::fungible_asset_activities::table)
+            .values(chunk)
+            .on_conflict_do_nothing()
+    })
+}
+
+/// Decimals of the assets indexed by earlier batches
+fn get_asset_decimals(conn: &PgPoolConnection, asset_types: &[String]) -> HashMap<String, i64> {
+    use schema::fungible_asset_metadata::dsl;
+
+    dsl::fungible_asset_metadata
+        .select((dsl::asset_type, dsl::decimals))
+        .filter(dsl::asset_type.eq_any(asset_types))
+        .load::<(String, i64)>(conn)
+        .expect("Error loading decimals from fungible_asset_metadata")
+        .into_iter()
+        .collect()
+}
+
+impl ProcessorBuilder for FungibleAssetTransactionProcessor {
+    fn base_mut(&mut self) -> &mut ProcessorBase {
+        &mut self.base
+    }
+}
+
+#[async_trait]
+impl TransactionProcessor for FungibleAssetTransactionProcessor {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn process_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<ProcessingResult, TransactionProcessingError> {
+        let (metadata, balances, balance_history, mut activities) =
+            FungibleAssetActivityModel::from_transactions(&transactions);
+        self.attach_usd_values(&metadata, &mut activities).await;
+
+        let tx_result = with_deadlock_retry(self.name(), self.base.get_conn(), |conn| {
+            conn.transaction::<(), diesel::result::Error, _>(|| {
+                insert_fungible_asset_metadata(conn, &metadata)?;
+                insert_fungible_asset_balances(conn, &balances)?;
+                insert_fungible_asset_balance_history(conn, &balance_history)?;
+                insert_fungible_asset_activities(conn, &activities)?;
+                self.base
+                    .transaction_metadata_handle(conn)
+                    .mark_versions_success(start_version, end_version)
+            })
+        })
+        .await;
+        match tx_result {
+            Ok(_) => Ok(
+                ProcessingResult::new(self.name(), start_version, end_version)
+                    .with_status_committed(),
+            ),
+            Err(err) => Err(TransactionProcessingError::from_db_error(
+                err,
+                start_version,
+                end_version,
+                self.name(),
+            )),
+        }
+    }
+
+    fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+        self.base.metadata_handle()
+    }
+}
@@ -0,0 +1,375 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Produces transactions and their events as JSON messages to a Kafka topic, through a Confluent REST Proxy (v2 API).
+//! Only built with the `kafka` feature. With a schema registry configured, messages are Avro instead, framed in
+//! Confluent's wire format (see `schema_registry`), so consumers can use Confluent's Avro deserializer.
+//!
+//! Messages are `StreamRecord`s: one per transaction and one per event, keyed by the record's account, so an account's
+//! messages land in the same partition, in version order. Batches are produced one request after the other for that
+//! reason. Delivery is at least once: re-processing a batch produces its messages again, so consumers should
+//! deduplicate on `(version, event_index)`. It can't be exactly once: the REST Proxy's v2 API has no transactional
+//! produce, so messages can't be committed atomically with the processor's status.
+//!
+//! Statuses (and the chain id) are recorded through the metadata handle it's built with, so it doesn't need Postgres:
+//! `aptos-indexer-sink` runs it in builds without the `postgres` feature.
+
+use crate::{
+    indexer::{
+        errors::TransactionProcessingError, metadata_handle::MetadataHandle,
+        processing_result::ProcessingResult, transaction_processor::TransactionProcessor,
+    },
+    processors::stream_records::StreamRecord,
+    schema_registry::{SchemaRegistryClient, SchemaRegistryConfig},
+};
+use anyhow::{bail, ensure, Context, Result};
+use aptos_rest_client::Transaction;
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{fmt::Debug, sync::Arc, time::Duration};
+
+pub const NAME: &str = "kafka_processor";
+
+/// Kafka's default `message.max.bytes`
+pub const MAX_MESSAGE_BYTES: usize = 1_000_000;
+/// Records per produce request
+const MAX_BATCH_RECORDS: usize = 500;
+/// Below the REST Proxy's default request size limit, accounting for base64
+const MAX_BATCH_BYTES: usize = 5_000_000;
+/// Throttled and failed requests are retried this often
+const MAX_ATTEMPTS: u32 = 8;
+/// `error_code` of the records the REST Proxy failed to produce with a retriable Kafka error
+const RETRIABLE_ERROR_CODE: i64 = 2;
+
+#[derive(Clone)]
+pub struct KafkaConfig {
+    /// Base URL of the Confluent REST Proxy, ex: "http://localhost:8082"
+    pub rest_proxy_url: String,
+    pub topic: String,
+    /// Basic auth credentials of the REST Proxy, ex: a Confluent Cloud API key and secret
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub request_timeout: Duration,
+    /// Serializes messages in Avro, registering their schema with this registry
+    pub schema_registry: Option<SchemaRegistryConfig>,
+}
+
+impl KafkaConfig {
+    pub fn new(rest_proxy_url: String, topic: String) -> Self {
+        Self {
+            rest_proxy_url,
+            topic,
+            user: None,
+            password: None,
+            request_timeout: Duration::from_secs(30),
+            schema_registry: None,
+        }
+    }
+}
+
+impl Debug for KafkaConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "KafkaConfig {{ rest_proxy_url: {:?}, topic: {:?}, user: {:?}, schema_registry: {:?} }}",
+            self.rest_proxy_url,
+            self.topic,
+            self.user,
+            self.schema_registry.as_ref().map(|registry| &registry.url)
+        )
+    }
+}
+
+/// Where the REST Proxy produced a record, or why it couldn't
+#[derive(Debug, Deserialize)]
+struct ProducedOffset {
+    error_code: Option<i64>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProduceResponse {
+    offsets: Vec<ProducedOffset>,
+}
+
+/// A message to produce: its key (the record's account) and value
+#[derive(Clone, Debug, PartialEq)]
+pub struct KafkaMessage {
+    pub key: String,
+    pub value: Vec<u8>,
+}
+
+impl KafkaMessage {
+    pub fn from_record(record: &StreamRecord) -> Result<Self> {
+        Self::with_value(record, record.to_bytes()?)
+    }
+
+    /// A message of `record`, serialized as `value`
+    pub fn with_value(record: &StreamRecord, value: Vec<u8>) -> Result<Self> {
+        ensure!(
+            value.len() <= MAX_MESSAGE_BYTES,
+            "The message of version {} (event {:?}) is {} bytes, more than {}",
+            record.version,
+            record.event_index,
+            value.len(),
+            MAX_MESSAGE_BYTES
+        );
+        Ok(Self {
+            key: record.account.clone(),
+            value,
+        })
+    }
+}
+
+/// Splits `messages` into the batches of produce requests within the limits
+fn produce_batches(messages: &[KafkaMessage]) -> Vec<&[KafkaMessage]> {
+    let mut batches = vec![];
+    let (mut start, mut batch_bytes) = (0, 0);
+    for (index, message) in messages.iter().enumerate() {
+        let message_bytes = message.key.len() + message.value.len();
+        if index - start == MAX_BATCH_RECORDS || batch_bytes + message_bytes > MAX_BATCH_BYTES {
+            batches.push(&messages[start..index]);
+            start = index;
+            batch_bytes = 0;
+        }
+        batch_bytes += message_bytes;
+    }
+    if start < messages.len() {
+        batches.push(&messages[start..]);
+    }
+    batches
+}
+
+/// The body of a produce request of `messages`, in the REST Proxy's binary embedded format
+fn produce_request(messages: &[KafkaMessage]) -> Value {
+    json!({
+        "records": messages
+            .iter()
+            .map(|message| json!({
+                "key": base64::encode(message.key.as_bytes()),
+                "value": base64::encode(&message.value),
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Produces transactions and events to a Kafka topic
+pub struct KafkaTransactionProcessor {
+    name: String,
+    metadata_handle: Arc<dyn MetadataHandle>,
+    config: KafkaConfig,
+    client: reqwest::Client,
+    schema_registry: Option<SchemaRegistryClient>,
+}
+
+impl KafkaTransactionProcessor {
+    /// Records statuses through `metadata_handle`, ex: a `PgMetadataHandle`, or a `FileMetadataHandle` without Postgres
+    pub fn new(config: KafkaConfig, metadata_handle: Arc<dyn MetadataHandle>) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(config.request_timeout)
+            .build()
+            .context("Failed to build the Kafka REST Proxy client")?;
+        let schema_registry = config
+            .schema_registry
+            .clone()
+            .map(SchemaRegistryClient::new);
+        Ok(Self {
+            name: NAME.to_string(),
+            metadata_handle,
+            config,
+            client,
+            schema_registry,
+        })
+    }
+
+    /// Records statuses under `name` rather than "kafka_processor", ex: to run several instances
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// The message of `record`, in Avro if a schema registry is configured
+    async fn message(&self, record: &StreamRecord) -> Result<KafkaMessage> {
+        match &self.schema_registry {
+            Some(schema_registry) => KafkaMessage::with_value(
+                record,
+                schema_registry
+                    .serialize_stream_record(&self.config.topic, record)
+                    .await?,
+            ),
+            None => KafkaMessage::from_record(record),
+        }
+    }
+
+    /// Produces one request's messages. Returns the messages to retry (those which failed with a retriable error, or
+    /// all of them if the request did), or an error if any failed otherwise.
+    async fn try_produce(&self, messages: &[KafkaMessage]) -> Result<Vec<KafkaMessage>> {
+        let mut request = self
+            .client
+            .post(format!(
+                "{}/topics/{}",
+                self.config.rest_proxy_url.trim_end_matches('/'),
+                self.config.topic
+            ))
+            .header("Content-Type", "application/vnd.kafka.binary.v2+json")
+            .header("Accept", "application/vnd.kafka.v2+json")
+            .json(&produce_request(messages));
+        if let Some(user) = &self.config.user {
+            request = request.basic_auth(user, self.config.password.as_ref());
+        }
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(_) => return Ok(messages.to_vec()),
+        };
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            return Ok(messages.to_vec());
+        }
+        if !status.is_success() {
+            bail!(
+                "Producing to {} failed with {}: {}",
+                self.config.topic,
+                status,
+                response.text().await.unwrap_or_default()
+            );
+        }
+        let response: ProduceResponse = response
+            .json()
+            .await
+            .context("Failed to parse the REST Proxy's response")?;
+        let mut retries = vec![];
+        for (message, offset) in messages.iter().zip(&response.offsets) {
+            match offset.error_code {
+                None => {}
+                Some(RETRIABLE_ERROR_CODE) => retries.push(message.clone()),
+                Some(error_code) => bail!(
+                    "Producing to {} failed ({}): {}",
+                    self.config.topic,
+                    error_code,
+                    offset.error.as_deref().unwrap_or_default()
+                ),
+            }
+        }
+        Ok(retries)
+    }
+
+    async fn produce(&self, messages: &[KafkaMessage]) -> Result<()> {
+        for batch in produce_batches(messages) {
+            let mut pending = batch.to_vec();
+            let mut attempt = 1;
+            while !pending.is_empty() {
+                if attempt > 1 {
+                    ensure!(
+                        attempt <= MAX_ATTEMPTS,
+                        "Producing to {} still failed after {} attempts",
+                        self.config.topic,
+                        MAX_ATTEMPTS
+                    );
+                    tokio::time::sleep(Duration::from_millis(50 << attempt)).await;
+                }
+                pending = self.try_produce(&pending).await?;
+                attempt += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Debug for KafkaTransactionProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "KafkaTransactionProcessor {{ config: {:?} }}",
+            self.config
+        )
+    }
+}
+
+#[async_trait]
+impl TransactionProcessor for KafkaTransactionProcessor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn process_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<ProcessingResult, TransactionProcessingError> {
+        let to_processing_error = |err| {
+            TransactionProcessingError::Custom((
+                err,
+                start_version,
+                end_version,
+                self.name().to_string(),
+            ))
+        };
+        let mut messages = vec![];
+        for transaction in &transactions {
+            for record in StreamRecord::from_transaction(transaction, MAX_MESSAGE_BYTES)
+                .map_err(to_processing_error)?
+            {
+                messages.push(self.message(&record).await.map_err(to_processing_error)?);
+            }
+        }
+        self.produce(&messages).await.map_err(to_processing_error)?;
+        Ok(ProcessingResult::new(
+            self.name(),
+            start_version,
+            end_version,
+        ))
+    }
+
+    fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+        self.metadata_handle.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TransactionFactory;
+
+    #[test]
+    fn test_produce_request() {
+        let mut factory = TransactionFactory::new(7);
+        let txn = factory
+            .user_transaction("0xa11ce")
+            .event_from("0xb0b", 0, "0x1::coin::DepositEvent", json!({}))
+            .build();
+        let messages: Vec<KafkaMessage> = StreamRecord::from_transaction(&txn, MAX_MESSAGE_BYTES)
+            .unwrap()
+            .iter()
+            .map(|record| KafkaMessage::from_record(record).unwrap())
+            .collect();
+        assert_eq!(messages[0].key, "0xa11ce");
+        assert_eq!(messages[1].key, "0xb0b");
+
+        let request = produce_request(&messages);
+        let records = request["records"].as_array().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1]["key"], base64::encode("0xb0b"));
+        let value = base64::decode(records[1]["value"].as_str().unwrap()).unwrap();
+        let value: Value = serde_json::from_slice(&value).unwrap();
+        assert_eq!(value["kind"], "event");
+        assert_eq!(value["version"], 7);
+    }
+
+    #[test]
+    fn test_produce_batches() {
+        let message = KafkaMessage {
+            key: "0xa11ce".to_string(),
+            value: vec![0; 10],
+        };
+        let messages = vec![message; MAX_BATCH_RECORDS * 2 + 1];
+        let batches = produce_batches(&messages);
+        assert_eq!(
+            batches.iter().map(|batch| batch.len()).collect::<Vec<_>>(),
+            vec![MAX_BATCH_RECORDS, MAX_BATCH_RECORDS, 1]
+        );
+        assert!(produce_batches(&[]).is_empty());
+    }
+}
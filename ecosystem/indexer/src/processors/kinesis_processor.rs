@@ -0,0 +1,293 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Writes transactions and their events as JSON records to an AWS Kinesis data stream or Firehose delivery stream,
+//! feeding lake ingestion pipelines without an ETL out of Postgres. Only built with the `kinesis` feature.
+//!
+//! Records are `StreamRecord`s: one per transaction and one per event, partitioned by account. Kinesis streams use the
+//! account as the partition key, so an account's records stay ordered within a shard; Firehose has no partition key,
+//! so records carry it as `account` for dynamic partitioning, and end with a newline so delivered objects are
+//! newline-delimited JSON. Delivery is at least once: records failed by the service are retried, and re-processing a
+//! batch writes its records again, so consumers should deduplicate on `(version, event_index)`. Statuses are still
+//! recorded in Postgres.
+
+use crate::{
+    aws::AwsJsonClient,
+    database::PgDbPool,
+    indexer::{
+        errors::TransactionProcessingError,
+        metadata_handle::MetadataHandle,
+        processing_result::ProcessingResult,
+        transaction_processor::{ProcessorBase, ProcessorBuilder, TransactionProcessor},
+    },
+    processors::stream_records::StreamRecord,
+};
+use anyhow::{bail, ensure, Result};
+use aptos_rest_client::Transaction;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::{fmt::Debug, str::FromStr, sync::Arc, time::Duration};
+
+pub const NAME: &str = "kinesis_processor";
+
+/// Records larger than this (Firehose's limit, just below Kinesis') can't be written
+pub const MAX_RECORD_BYTES: usize = 1000 * 1024;
+/// `PutRecords` and `PutRecordBatch` take at most 500 records
+const MAX_BATCH_RECORDS: usize = 500;
+/// Below both services' request size limits (5 MiB and 4 MiB), accounting for base64
+const MAX_BATCH_BYTES: usize = 3 * 1024 * 1024;
+/// Throttled requests, and records failed by the service, are retried this often
+const MAX_ATTEMPTS: u32 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KinesisTarget {
+    /// A Kinesis data stream
+    Stream,
+    /// A Kinesis Data Firehose delivery stream
+    Firehose,
+}
+
+impl FromStr for KinesisTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "stream" => Ok(Self::Stream),
+            "firehose" => Ok(Self::Firehose),
+            _ => bail!("Unknown Kinesis target {}, expected stream or firehose", s),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct KinesisConfig {
+    pub target: KinesisTarget,
+    /// Name of the data stream or delivery stream
+    pub stream_name: String,
+    pub region: String,
+    /// Where to send requests instead of the region's endpoint, ex: "http://localhost:4566" for localstack
+    pub endpoint: Option<String>,
+    pub request_timeout: Duration,
+}
+
+/// Splits `records` into the entries of requests within the services' limits
+fn request_entries(target: KinesisTarget, records: &[StreamRecord]) -> Result<Vec<Vec<Value>>> {
+    let mut requests = vec![];
+    let mut entries = vec![];
+    let mut batch_bytes = 0;
+    for record in records {
+        let mut bytes = record.to_bytes()?;
+        if target == KinesisTarget::Firehose {
+            bytes.push(b'\n');
+        }
+        ensure!(
+            bytes.len() <= MAX_RECORD_BYTES,
+            "The record of version {} (event {:?}) is {} bytes, more than {}",
+            record.version,
+            record.event_index,
+            bytes.len(),
+            MAX_RECORD_BYTES
+        );
+        if entries.len() == MAX_BATCH_RECORDS || batch_bytes + bytes.len() > MAX_BATCH_BYTES {
+            requests.push(std::mem::take(&mut entries));
+            batch_bytes = 0;
+        }
+        batch_bytes += bytes.len();
+        entries.push(match target {
+            KinesisTarget::Stream => {
+                json!({ "Data": base64::encode(&bytes), "PartitionKey": record.account })
+            }
+            KinesisTarget::Firehose => json!({ "Data": base64::encode(&bytes) }),
+        });
+    }
+    if !entries.is_empty() {
+        requests.push(entries);
+    }
+    Ok(requests)
+}
+
+/// Writes transactions and events to a Kinesis data stream or Firehose delivery stream
+pub struct KinesisTransactionProcessor {
+    base: ProcessorBase,
+    config: KinesisConfig,
+    client: AwsJsonClient,
+}
+
+impl KinesisTransactionProcessor {
+    pub fn new(connection_pool: PgDbPool, config: KinesisConfig) -> Result<Self> {
+        let client = match config.target {
+            KinesisTarget::Stream => AwsJsonClient::new(
+                "kinesis",
+                "Kinesis_20131202",
+                "application/x-amz-json-1.1",
+                config.region.clone(),
+                config.endpoint.as_deref(),
+                config.request_timeout,
+            ),
+            KinesisTarget::Firehose => AwsJsonClient::new(
+                "firehose",
+                "Firehose_20150804",
+                "application/x-amz-json-1.1",
+                config.region.clone(),
+                config.endpoint.as_deref(),
+                config.request_timeout,
+            ),
+        }?;
+        Ok(Self {
+            base: ProcessorBase::new(NAME, connection_pool),
+            config,
+            client,
+        })
+    }
+
+    /// Writes `entries` in one request, then retries the ones the service failed (ex: throttled shards)
+    async fn put_entries(&self, mut entries: Vec<Value>) -> Result<()> {
+        let (action, stream_field, results_field) = match self.config.target {
+            KinesisTarget::Stream => ("PutRecords", "StreamName", "Records"),
+            KinesisTarget::Firehose => ("PutRecordBatch", "DeliveryStreamName", "RequestResponses"),
+        };
+        let mut attempt = 1;
+        loop {
+            let response = self
+                .client
+                .call(
+                    action,
+                    &json!({ stream_field: self.config.stream_name, "Records": entries }),
+                    MAX_ATTEMPTS,
+                )
+                .await?;
+            // Results are in the order of the entries
+            let failed: Vec<(Value, Value)> = entries
+                .into_iter()
+                .zip(
+                    response[results_field]
+                        .as_array()
+                        .cloned()
+                        .unwrap_or_default(),
+                )
+                .filter(|(_, result)| !result["ErrorCode"].is_null())
+                .collect();
+            if failed.is_empty() {
+                return Ok(());
+            }
+            if attempt == MAX_ATTEMPTS {
+                bail!(
+                    "{} records failed after {} attempts, ex: {}",
+                    failed.len(),
+                    MAX_ATTEMPTS,
+                    failed[0].1
+                );
+            }
+            entries = failed.into_iter().map(|(entry, _)| entry).collect();
+            tokio::time::sleep(Duration::from_millis(50 << attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    async fn put_records(&self, records: &[StreamRecord]) -> Result<()> {
+        for entries in request_entries(self.config.target, records)? {
+            self.put_entries(entries).await?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for KinesisTransactionProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "KinesisTransactionProcessor {{ target: {:?}  stream_name: {:?} }}",
+            self.config.target, self.config.stream_name
+        )
+    }
+}
+
+impl ProcessorBuilder for KinesisTransactionProcessor {
+    fn base_mut(&mut self) -> &mut ProcessorBase {
+        &mut self.base
+    }
+}
+
+#[async_trait]
+impl TransactionProcessor for KinesisTransactionProcessor {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn process_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<ProcessingResult, TransactionProcessingError> {
+        let to_processing_error = |err| {
+            TransactionProcessingError::Custom((
+                err,
+                start_version,
+                end_version,
+                self.name().to_string(),
+            ))
+        };
+        let mut records = vec![];
+        for transaction in &transactions {
+            records.extend(
+                StreamRecord::from_transaction(transaction, MAX_RECORD_BYTES)
+                    .map_err(to_processing_error)?,
+            );
+        }
+        self.put_records(&records)
+            .await
+            .map_err(to_processing_error)?;
+        Ok(ProcessingResult::new(
+            self.name(),
+            start_version,
+            end_version,
+        ))
+    }
+
+    fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+        self.base.metadata_handle()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TransactionFactory;
+
+    #[test]
+    fn test_request_entries() {
+        let mut factory = TransactionFactory::new(10);
+        let txn = factory
+            .user_transaction("0xa11ce")
+            .event_from("0xb0b", 0, "0x1::coin::DepositEvent", json!({}))
+            .build();
+        let records = StreamRecord::from_transaction(&txn, MAX_RECORD_BYTES).unwrap();
+
+        let entries = request_entries(KinesisTarget::Stream, &records).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0][0]["PartitionKey"], "0xa11ce");
+        assert_eq!(entries[0][1]["PartitionKey"], "0xb0b");
+        let entries = request_entries(KinesisTarget::Firehose, &records).unwrap();
+        assert!(entries[0][0].get("PartitionKey").is_none());
+        let data = base64::decode(entries[0][0]["Data"].as_str().unwrap()).unwrap();
+        assert_eq!(data.last(), Some(&b'\n'));
+    }
+
+    #[test]
+    fn test_request_entries_split() {
+        let record = StreamRecord {
+            kind: "event",
+            version: 1,
+            event_index: Some(0),
+            account: "0x1".to_string(),
+            data: json!({}),
+            changes_omitted: false,
+        };
+        let records = vec![record; MAX_BATCH_RECORDS + 1];
+        let entries = request_entries(KinesisTarget::Stream, &records).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].len(), MAX_BATCH_RECORDS);
+        assert_eq!(entries[1].len(), 1);
+    }
+}
@@ -1,5 +1,39 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 
+#[cfg(feature = "postgres")]
+pub mod account_summary_processor;
+#[cfg(feature = "postgres")]
 pub mod default_processor;
+#[cfg(feature = "duckdb")]
+pub mod duckdb_processor;
+#[cfg(feature = "postgres")]
+pub mod epoch_processor;
+#[cfg(feature = "postgres")]
+pub mod fungible_asset_processor;
+#[cfg(feature = "kafka")]
+pub mod kafka_processor;
+#[cfg(feature = "kinesis")]
+pub mod kinesis_processor;
+#[cfg(feature = "neo4j")]
+pub mod neo4j_processor;
+#[cfg(feature = "postgres")]
+pub mod object_processor;
+pub mod processor_helpers;
+#[cfg(feature = "pubsub")]
+pub mod pubsub_processor;
+#[cfg(feature = "search")]
+pub mod search_processor;
+pub mod stream_records;
+#[cfg(feature = "postgres")]
+pub mod swap_processor;
+#[cfg(feature = "postgres")]
 pub mod token_processor;
+#[cfg(feature = "postgres")]
+pub mod token_v2_processor;
+#[cfg(feature = "postgres")]
+pub mod top_holders_processor;
+#[cfg(feature = "postgres")]
+pub mod transfer_edge_processor;
+#[cfg(feature = "postgres")]
+pub mod txn_latency_processor;
@@ -0,0 +1,238 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Writes the account/transfer graph straight into Neo4j: an `Account` node per address and a `TRANSFERRED`
+//! relationship per transfer edge (see `TransferEdge`), so graph queries don't need an ETL out of Postgres. Only
+//! built with the `neo4j` feature.
+//!
+//! Each batch is upserted with a single Cypher statement, run over Bolt (see `bolt`) in its own transaction, so it's
+//! committed atomically and re-processing a batch is idempotent. The connection is re-opened after any failure.
+//! Statuses are still recorded in Postgres.
+
+use crate::{
+    bolt::{BoltAddress, BoltConnection},
+    database::PgDbPool,
+    indexer::{
+        errors::TransactionProcessingError,
+        metadata_handle::MetadataHandle,
+        processing_result::ProcessingResult,
+        transaction_processor::{ProcessorBase, ProcessorBuilder, TransactionProcessor},
+    },
+    models::transfer_edge::TransferEdgeModel,
+    util::bigdecimal_to_u64,
+};
+use anyhow::{Context, Result};
+use aptos_rest_client::Transaction;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::{fmt::Debug, sync::Arc, time::Duration};
+use tokio::sync::Mutex;
+
+pub const NAME: &str = "neo4j_processor";
+
+/// Upserts the edges of a batch. Mints and burns have no counterparty, so only their account node is upserted.
+/// Amounts are strings, as they may not fit in Neo4j's 64 bit integers.
+pub const UPSERT_EDGES_STATEMENT: &str = "\
+UNWIND $edges AS edge
+FOREACH (address IN [a IN [edge.from_address, edge.to_address] WHERE a IS NOT NULL] |
+    MERGE (account:Account {address: address})
+    ON CREATE SET account.first_version = edge.version
+    SET account.last_version = edge.version)
+WITH edge WHERE edge.from_address IS NOT NULL AND edge.to_address IS NOT NULL
+MATCH (from:Account {address: edge.from_address}), (to:Account {address: edge.to_address})
+MERGE (from)-[transfer:TRANSFERRED {version: edge.version, event_index: edge.event_index}]->(to)
+SET transfer.asset = edge.asset,
+    transfer.amount = edge.amount,
+    transfer.kind = edge.kind,
+    transfer.timestamp = edge.timestamp";
+
+#[derive(Clone, Debug)]
+pub struct Neo4jConfig {
+    /// Bolt URL of the Neo4j server, ex: "bolt://localhost:7687" or "neo4j+s://<id>.databases.neo4j.io"
+    pub url: String,
+    pub database: String,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub request_timeout: Duration,
+}
+
+impl Default for Neo4jConfig {
+    fn default() -> Self {
+        Self {
+            url: "bolt://localhost:7687".to_string(),
+            database: "neo4j".to_string(),
+            user: None,
+            password: None,
+            request_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Upserts coin and token transfers into a Neo4j graph of `Account` nodes connected by `TRANSFERRED` relationships
+pub struct Neo4jTransactionProcessor {
+    base: ProcessorBase,
+    config: Neo4jConfig,
+    /// Opened on the first batch, and dropped after a failure so the next attempt reconnects
+    connection: Mutex<Option<BoltConnection>>,
+}
+
+impl Neo4jTransactionProcessor {
+    pub fn new(connection_pool: PgDbPool, config: Neo4jConfig) -> Result<Self> {
+        BoltAddress::parse(&config.url)?;
+        Ok(Self {
+            base: ProcessorBase::new(NAME, connection_pool),
+            config,
+            connection: Mutex::new(None),
+        })
+    }
+
+    /// Runs `statement` with `parameters` in its own transaction
+    async fn run_statement(&self, statement: &str, parameters: Value) -> Result<()> {
+        let mut connection = self.connection.lock().await;
+        if connection.is_none() {
+            *connection = Some(
+                BoltConnection::connect(
+                    &self.config.url,
+                    self.config.user.as_deref(),
+                    self.config.password.as_deref(),
+                    self.config.request_timeout,
+                )
+                .await?,
+            );
+        }
+        let result = tokio::time::timeout(
+            self.config.request_timeout,
+            connection.as_mut().expect("Connected above").run(
+                &self.config.database,
+                statement,
+                parameters,
+            ),
+        )
+        .await
+        .context("Timed out running the statement")
+        .and_then(|result| result.context("Neo4j failed to run the statement"));
+        if result.is_err() {
+            *connection = None;
+        }
+        result.map(|_| ())
+    }
+}
+
+impl Debug for Neo4jTransactionProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Neo4jTransactionProcessor {{ url: {:?}, database: {:?} }}",
+            self.config.url, self.config.database
+        )
+    }
+}
+
+/// The `$edges` parameter of `UPSERT_EDGES_STATEMENT`
+pub fn edges_parameter(edges: &[TransferEdgeModel]) -> Result<Value> {
+    edges
+        .iter()
+        .map(|edge| {
+            let version = bigdecimal_to_u64(&edge.transaction_version)
+                .context("Invalid transfer edge version")?;
+            Ok(json!({
+                    "version": version,
+                    "event_index": edge.event_index,
+                    "from_address": edge.from_address,
+                    "to_address": edge.to_address,
+                    "asset": edge.asset,
+                    "amount": edge.amount.to_string(),
+                    "kind": edge.kind,
+                    "timestamp": edge.transaction_timestamp.format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+            }))
+        })
+        .collect::<Result<_>>()
+        .map(Value::Array)
+}
+
+impl ProcessorBuilder for Neo4jTransactionProcessor {
+    fn base_mut(&mut self) -> &mut ProcessorBase {
+        &mut self.base
+    }
+}
+
+#[async_trait]
+impl TransactionProcessor for Neo4jTransactionProcessor {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn process_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<ProcessingResult, TransactionProcessingError> {
+        let to_processing_error = |err| {
+            TransactionProcessingError::Custom((
+                err,
+                start_version,
+                end_version,
+                self.name().to_string(),
+            ))
+        };
+        let edges = TransferEdgeModel::from_transactions(&transactions);
+        if !edges.is_empty() {
+            let edges = edges_parameter(&edges).map_err(to_processing_error)?;
+            self.run_statement(UPSERT_EDGES_STATEMENT, json!({ "edges": edges }))
+                .await
+                .map_err(to_processing_error)?;
+        }
+        Ok(ProcessingResult::new(
+            self.name(),
+            start_version,
+            end_version,
+        ))
+    }
+
+    fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+        self.base.metadata_handle()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigdecimal::BigDecimal;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_edges_parameter() {
+        let timestamp = chrono::NaiveDateTime::from_timestamp(1_663_000_000, 5_000);
+        let edge = TransferEdgeModel {
+            transaction_version: BigDecimal::from(42),
+            event_index: 1,
+            from_address: Some("0xa11ce".to_string()),
+            to_address: None,
+            asset: "0x1::aptos_coin::AptosCoin".to_string(),
+            amount: BigDecimal::from_str("340282366920938463463374607431768211455").unwrap(),
+            kind: "coin".to_string(),
+            transaction_timestamp: timestamp,
+            inserted_at: timestamp,
+        };
+        assert_eq!(
+            edges_parameter(&[edge.clone()]).unwrap(),
+            json!([{
+                "version": 42,
+                "event_index": 1,
+                "from_address": "0xa11ce",
+                "to_address": null,
+                "asset": "0x1::aptos_coin::AptosCoin",
+                "amount": "340282366920938463463374607431768211455",
+                "kind": "coin",
+                "timestamp": "2022-09-12T16:26:40.000005",
+            }])
+        );
+
+        let edge = TransferEdgeModel {
+            transaction_version: BigDecimal::from(-1),
+            ..edge
+        };
+        assert!(edges_parameter(&[edge]).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    database::{
+        insert_chunked, latest_version_wins, with_deadlock_retry, PgDbPool, PgPoolConnection,
+    },
+    indexer::{
+        errors::TransactionProcessingError,
+        metadata_handle::MetadataHandle,
+        processing_result::ProcessingResult,
+        transaction_processor::{ProcessorBase, ProcessorBuilder, TransactionProcessor},
+    },
+    models::object::{CurrentObjectOwnershipModel, ObjectModel},
+    schema,
+};
+use aptos_rest_client::Transaction;
+use async_trait::async_trait;
+use diesel::{
+    dsl::sql,
+    sql_types::{Bool, Nullable, Numeric, Text},
+    Connection, ExpressionMethods, QueryResult,
+};
+use std::{fmt::Debug, sync::Arc};
+
+pub const NAME: &str = "object_processor";
+
+/// Tracks the creation, transfer and deletion of objects (`0x1::object::ObjectCore`) into `objects`, and who owns
+/// each object now into `current_object_ownerships`
+pub struct ObjectTransactionProcessor {
+    base: ProcessorBase,
+}
+
+impl ObjectTransactionProcessor {
+    pub fn new(connection_pool: PgDbPool) -> Self {
+        Self {
+            base: ProcessorBase::new(NAME, connection_pool),
+        }
+    }
+}
+
+impl Debug for ObjectTransactionProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = &self.base.connection_pool().state();
+        write!(
+            f,
+            "ObjectTransactionProcessor {{ connections: {:?}  idle_connections: {:?} }}",
+            state.connections, state.idle_connections
+        )
+    }
+}
+
+fn insert_objects(conn: &PgPoolConnection, objects: &[ObjectModel]) -> QueryResult<usize> {
+    insert_chunked(conn, objects, |chunk| {
+        diesel::insert_into(schema::objects::table)
+            .values(chunk)
+            .on_conflict_do_nothing()
+    })
+}
+
+fn insert_current_object_ownerships(
+    conn: &PgPoolConnection,
+    ownerships: &[CurrentObjectOwnershipModel],
+) -> QueryResult<usize> {
+    use schema::current_object_ownerships::dsl::*;
+
+    let table = "current_object_ownerships";
+    insert_chunked(conn, ownerships, |chunk| {
+        diesel::insert_into(schema::current_object_ownerships::table)
+            .values(chunk)
+            .on_conflict(object_address)
+            .do_update()
+            .set((
+                // Deletions don't say who the owner was, so keep the existing one
+                owner_address.eq(sql::<Nullable<Text>>(
+                    "CASE WHEN EXCLUDED.last_transaction_version >= current_object_ownerships.last_transaction_version \
+                     THEN COALESCE(EXCLUDED.owner_address, current_object_ownerships.owner_address) \
+                     ELSE current_object_ownerships.owner_address END",
+                )),
+                state_key_hash.eq(latest_version_wins::<Text>(table, "state_key_hash")),
+                allow_ungated_transfer.eq(latest_version_wins::<Nullable<Bool>>(
+                    table,
+                    "allow_ungated_transfer",
+                )),
+                is_deleted.eq(latest_version_wins::<Bool>(table, "is_deleted")),
+                last_transaction_version.eq(latest_version_wins::<Numeric>(
+                    table,
+                    "last_transaction_version",
+                )),
+            ))
+    })
+}
+
+impl ProcessorBuilder for ObjectTransactionProcessor {
+    fn base_mut(&mut self) -> &mut ProcessorBase {
+        &mut self.base
+    }
+}
+
+#[async_trait]
+impl TransactionProcessor for ObjectTransactionProcessor {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn process_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<ProcessingResult, TransactionProcessingError> {
+        let objects = ObjectModel::from_transactions(&transactions);
+        let current_ownerships = CurrentObjectOwnershipModel::from_objects(&objects);
+
+        let tx_result = with_deadlock_retry(self.name(), self.base.get_conn(), |conn| {
+            conn.transaction::<(), diesel::result::Error, _>(|| {
+                insert_objects(conn, &objects)?;
+                insert_current_object_ownerships(conn, &current_ownerships)?;
+                self.base
+                    .transaction_metadata_handle(conn)
+                    .mark_versions_success(start_version, end_version)
+            })
+        })
+        .await;
+        match tx_result {
+            Ok(_) => Ok(
+                ProcessingResult::new(self.name(), start_version, end_version)
+                    .with_status_committed(),
+            ),
+            Err(err) => Err(TransactionProcessingError::from_db_error(
+                err,
+                start_version,
+                end_version,
+                self.name(),
+            )),
+        }
+    }
+
+    fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+        self.base.metadata_handle()
+    }
+}
@@ -0,0 +1,118 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Helpers shared by processors
+
+use anyhow::{Context, Result};
+use aptos_rest_client::Transaction;
+use std::sync::Arc;
+
+/// Splits `transactions` into at most `num_sub_batches` contiguous sub-batches of (nearly) equal size, in version
+/// order. Transactions are moved, not cloned.
+pub fn split_sub_batches(
+    mut transactions: Vec<Transaction>,
+    num_sub_batches: usize,
+) -> Vec<Vec<Transaction>> {
+    let num_sub_batches = num_sub_batches.clamp(1, std::cmp::max(transactions.len(), 1));
+    let sub_batch_size = (transactions.len() + num_sub_batches - 1) / num_sub_batches;
+    let mut sub_batches = Vec::with_capacity(num_sub_batches);
+    while transactions.len() > sub_batch_size {
+        let rest = transactions.split_off(sub_batch_size);
+        sub_batches.push(transactions);
+        transactions = rest;
+    }
+    sub_batches.push(transactions);
+    sub_batches
+}
+
+/// Converts the sub-batches of `transactions` concurrently on tokio's blocking threads, for processors whose
+/// conversion is CPU-heavy, and returns each sub-batch's output in version order. Callers merge the outputs and write
+/// them in a single DB transaction, so the batch is still committed atomically.
+pub async fn convert_sub_batches<T, F>(
+    transactions: Vec<Transaction>,
+    num_sub_batches: usize,
+    convert: F,
+) -> Result<Vec<T>>
+where
+    T: Send + 'static,
+    F: Fn(&[Transaction]) -> T + Send + Sync + 'static,
+{
+    if num_sub_batches <= 1 {
+        return Ok(vec![convert(&transactions)]);
+    }
+    let convert = Arc::new(convert);
+    let handles: Vec<_> = split_sub_batches(transactions, num_sub_batches)
+        .into_iter()
+        .map(|sub_batch| {
+            let convert = convert.clone();
+            tokio::task::spawn_blocking(move || convert(&sub_batch))
+        })
+        .collect();
+    let mut outputs = Vec::with_capacity(handles.len());
+    for handle in handles {
+        outputs.push(handle.await.context("Converting a sub-batch failed")?);
+    }
+    Ok(outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn transactions(num_transactions: u64) -> Vec<Transaction> {
+        (0..num_transactions)
+            .map(|version| {
+                serde_json::from_value(json!({
+                    "type": "state_checkpoint_transaction",
+                    "version": version.to_string(),
+                    "hash": format!("0x{:064x}", version),
+                    "state_change_hash": format!("0x{:064x}", 0),
+                    "event_root_hash": format!("0x{:064x}", 0),
+                    "gas_used": "0",
+                    "success": true,
+                    "vm_status": "Executed successfully",
+                    "accumulator_root_hash": format!("0x{:064x}", 0),
+                    "changes": [],
+                    "timestamp": "1",
+                }))
+                .unwrap()
+            })
+            .collect()
+    }
+
+    fn versions(sub_batches: &[Vec<Transaction>]) -> Vec<Vec<u64>> {
+        sub_batches
+            .iter()
+            .map(|sub_batch| {
+                sub_batch
+                    .iter()
+                    .map(|transaction| transaction.version().unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_sub_batches() {
+        assert_eq!(
+            versions(&split_sub_batches(transactions(7), 3)),
+            vec![vec![0, 1, 2], vec![3, 4, 5], vec![6]]
+        );
+        assert_eq!(
+            versions(&split_sub_batches(transactions(2), 4)),
+            vec![vec![0], vec![1]]
+        );
+        assert_eq!(split_sub_batches(vec![], 4).len(), 1);
+
+        let outputs = convert_sub_batches(transactions(10), 4, |sub_batch| {
+            sub_batch
+                .iter()
+                .map(|transaction| transaction.version().unwrap())
+                .collect::<Vec<_>>()
+        })
+        .await
+        .unwrap();
+        assert_eq!(outputs.concat(), (0..10).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,358 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Publishes transactions and their events as JSON messages to a GCP Pub/Sub topic, for GCP-native consumers of chain
+//! data. Only built with the `pubsub` feature.
+//!
+//! Messages are `StreamRecord`s: one per transaction and one per event, with the record's account as ordering key, so
+//! subscriptions with message ordering enabled receive an account's messages in version order. Batches are published
+//! one request after the other for that reason. Messages carry the configured attributes (ex: `event_type`, `version`),
+//! so subscriptions can filter on them. Delivery is at least once: re-processing a batch publishes its messages again,
+//! so consumers should deduplicate on `(version, event_index)`. Statuses are still recorded in Postgres.
+//!
+//! Requests are authenticated with the configured access token, or else the GCE metadata server's; with
+//! `PUBSUB_EMULATOR_HOST` set, they go to the emulator unauthenticated.
+
+use crate::{
+    database::PgDbPool,
+    gcp::AccessTokenProvider,
+    indexer::{
+        errors::TransactionProcessingError,
+        metadata_handle::MetadataHandle,
+        processing_result::ProcessingResult,
+        transaction_processor::{ProcessorBase, ProcessorBuilder, TransactionProcessor},
+    },
+    processors::stream_records::StreamRecord,
+};
+use anyhow::{bail, ensure, Context, Result};
+use aptos_rest_client::Transaction;
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde_json::{json, Map, Value};
+use std::{fmt::Debug, str::FromStr, sync::Arc, time::Duration};
+
+pub const NAME: &str = "pubsub_processor";
+pub const DEFAULT_ENDPOINT: &str = "https://pubsub.googleapis.com";
+
+/// Messages larger than this, attributes included, wouldn't fit a request
+pub const MAX_MESSAGE_BYTES: usize = 7_000_000;
+/// A publish request takes at most 1000 messages
+const MAX_BATCH_MESSAGES: usize = 1000;
+/// Below the 10 MB request size limit, accounting for base64
+const MAX_BATCH_BYTES: usize = 7_000_000;
+/// Throttled and failed requests are retried this often
+const MAX_ATTEMPTS: u32 = 8;
+
+/// An attribute of the published messages
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageAttribute {
+    /// "transaction" or "event"
+    Kind,
+    Version,
+    /// The type of events. Transactions' messages don't have it.
+    EventType,
+    Account,
+}
+
+impl MessageAttribute {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Kind => "kind",
+            Self::Version => "version",
+            Self::EventType => "event_type",
+            Self::Account => "account",
+        }
+    }
+
+    fn value(&self, record: &StreamRecord) -> Option<String> {
+        match self {
+            Self::Kind => Some(record.kind.to_string()),
+            Self::Version => Some(record.version.to_string()),
+            Self::EventType => record.event_type().map(|typ| typ.to_string()),
+            Self::Account => Some(record.account.clone()),
+        }
+    }
+}
+
+impl FromStr for MessageAttribute {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "kind" => Ok(Self::Kind),
+            "version" => Ok(Self::Version),
+            "event_type" => Ok(Self::EventType),
+            "account" => Ok(Self::Account),
+            _ => bail!(
+                "Unknown message attribute {}, expected kind, version, event_type or account",
+                s
+            ),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PubSubConfig {
+    /// ex: "projects/my-project/topics/aptos-transactions"
+    pub topic: String,
+    pub endpoint: String,
+    /// OAuth access token. If `None`, tokens are requested from the GCE metadata server.
+    pub access_token: Option<String>,
+    /// Whether `endpoint` is the emulator, which doesn't authenticate requests
+    pub emulator: bool,
+    pub attributes: Vec<MessageAttribute>,
+    pub request_timeout: Duration,
+}
+
+impl PubSubConfig {
+    /// Targets the emulator if `PUBSUB_EMULATOR_HOST` is set, as Google's client libraries do
+    pub fn new(topic: String) -> Self {
+        let emulator_host = std::env::var("PUBSUB_EMULATOR_HOST").ok();
+        Self {
+            topic,
+            endpoint: emulator_host.as_ref().map_or_else(
+                || DEFAULT_ENDPOINT.to_string(),
+                |host| format!("http://{}", host),
+            ),
+            access_token: None,
+            emulator: emulator_host.is_some(),
+            attributes: vec![
+                MessageAttribute::Kind,
+                MessageAttribute::Version,
+                MessageAttribute::EventType,
+            ],
+            request_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl Debug for PubSubConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "PubSubConfig {{ topic: {:?}, endpoint: {:?}, attributes: {:?} }}",
+            self.topic, self.endpoint, self.attributes
+        )
+    }
+}
+
+/// Splits `records` into the messages of publish requests within Pub/Sub's limits
+fn publish_requests(
+    attributes: &[MessageAttribute],
+    records: &[StreamRecord],
+) -> Result<Vec<Value>> {
+    let mut requests = vec![];
+    let mut messages = vec![];
+    let mut batch_bytes = 0;
+    for record in records {
+        let data = record.to_bytes()?;
+        let message_attributes: Map<String, Value> = attributes
+            .iter()
+            .filter_map(|attribute| {
+                attribute
+                    .value(record)
+                    .map(|value| (attribute.name().to_string(), Value::from(value)))
+            })
+            .collect();
+        let message_bytes = data.len()
+            + message_attributes
+                .iter()
+                .map(|(name, value)| name.len() + value.as_str().map_or(0, str::len))
+                .sum::<usize>();
+        ensure!(
+            message_bytes <= MAX_MESSAGE_BYTES,
+            "The message of version {} (event {:?}) is {} bytes, more than {}",
+            record.version,
+            record.event_index,
+            message_bytes,
+            MAX_MESSAGE_BYTES
+        );
+        if messages.len() == MAX_BATCH_MESSAGES || batch_bytes + message_bytes > MAX_BATCH_BYTES {
+            requests.push(json!({ "messages": std::mem::take(&mut messages) }));
+            batch_bytes = 0;
+        }
+        batch_bytes += message_bytes;
+        messages.push(json!({
+            "data": base64::encode(&data),
+            "attributes": message_attributes,
+            "orderingKey": record.account,
+        }));
+    }
+    if !messages.is_empty() {
+        requests.push(json!({ "messages": messages }));
+    }
+    Ok(requests)
+}
+
+/// Publishes transactions and events to a Pub/Sub topic
+pub struct PubSubTransactionProcessor {
+    base: ProcessorBase,
+    config: PubSubConfig,
+    client: reqwest::Client,
+    tokens: AccessTokenProvider,
+}
+
+impl PubSubTransactionProcessor {
+    pub fn new(connection_pool: PgDbPool, config: PubSubConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(config.request_timeout)
+            .build()
+            .context("Failed to build the Pub/Sub client")?;
+        Ok(Self {
+            base: ProcessorBase::new(NAME, connection_pool),
+            tokens: AccessTokenProvider::new(config.access_token.clone()),
+            config,
+            client,
+        })
+    }
+
+    /// Publishes one request's messages. Returns whether a failure may succeed when retried.
+    async fn try_publish(&self, request: &Value) -> Result<(), (anyhow::Error, bool)> {
+        let mut builder = self
+            .client
+            .post(format!(
+                "{}/v1/{}:publish",
+                self.config.endpoint, self.config.topic
+            ))
+            .json(request);
+        if !self.config.emulator {
+            let token = self
+                .tokens
+                .token(&self.client)
+                .await
+                .map_err(|err| (err, true))?;
+            builder = builder.bearer_auth(token);
+        }
+        let response = builder
+            .send()
+            .await
+            .map_err(|err| (anyhow::Error::new(err).context("Failed to publish"), true))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let retryable = status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+            return Err((
+                anyhow::anyhow!("Publishing failed with {}: {}", status, body),
+                retryable,
+            ));
+        }
+        Ok(())
+    }
+
+    async fn publish(&self, records: &[StreamRecord]) -> Result<()> {
+        for request in publish_requests(&self.config.attributes, records)? {
+            let mut attempt = 1;
+            loop {
+                match self.try_publish(&request).await {
+                    Ok(()) => break,
+                    Err((_, true)) if attempt < MAX_ATTEMPTS => {
+                        tokio::time::sleep(Duration::from_millis(50 << attempt)).await;
+                        attempt += 1;
+                    }
+                    Err((err, _)) => return Err(err),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Debug for PubSubTransactionProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "PubSubTransactionProcessor {{ config: {:?} }}",
+            self.config
+        )
+    }
+}
+
+impl ProcessorBuilder for PubSubTransactionProcessor {
+    fn base_mut(&mut self) -> &mut ProcessorBase {
+        &mut self.base
+    }
+}
+
+#[async_trait]
+impl TransactionProcessor for PubSubTransactionProcessor {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn process_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<ProcessingResult, TransactionProcessingError> {
+        let to_processing_error = |err| {
+            TransactionProcessingError::Custom((
+                err,
+                start_version,
+                end_version,
+                self.name().to_string(),
+            ))
+        };
+        let mut records = vec![];
+        for transaction in &transactions {
+            records.extend(
+                StreamRecord::from_transaction(transaction, MAX_MESSAGE_BYTES)
+                    .map_err(to_processing_error)?,
+            );
+        }
+        self.publish(&records).await.map_err(to_processing_error)?;
+        Ok(ProcessingResult::new(
+            self.name(),
+            start_version,
+            end_version,
+        ))
+    }
+
+    fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+        self.base.metadata_handle()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TransactionFactory;
+
+    #[test]
+    fn test_publish_requests() {
+        let mut factory = TransactionFactory::new(7);
+        let txn = factory
+            .user_transaction("0xa11ce")
+            .event_from("0xb0b", 0, "0x1::coin::DepositEvent", json!({}))
+            .build();
+        let records = StreamRecord::from_transaction(&txn, MAX_MESSAGE_BYTES).unwrap();
+        let attributes = vec![MessageAttribute::Version, MessageAttribute::EventType];
+        let requests = publish_requests(&attributes, &records).unwrap();
+        assert_eq!(requests.len(), 1);
+        let messages = &requests[0]["messages"];
+        assert_eq!(messages[0]["orderingKey"], "0xa11ce");
+        assert_eq!(messages[0]["attributes"], json!({"version": "7"}));
+        assert_eq!(messages[1]["orderingKey"], "0xb0b");
+        assert_eq!(
+            messages[1]["attributes"],
+            json!({"version": "7", "event_type": "0x1::coin::DepositEvent"})
+        );
+
+        let records = vec![records[1].clone(); MAX_BATCH_MESSAGES + 1];
+        let requests = publish_requests(&attributes, &records).unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0]["messages"].as_array().unwrap().len(),
+            MAX_BATCH_MESSAGES
+        );
+    }
+
+    #[test]
+    fn test_message_attribute() {
+        assert_eq!(
+            "event_type".parse::<MessageAttribute>().unwrap(),
+            MessageAttribute::EventType
+        );
+        assert!("type".parse::<MessageAttribute>().is_err());
+    }
+}
@@ -0,0 +1,458 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Indexes user transactions into Elasticsearch or OpenSearch for explorer-style free-text search over their
+//! payloads, event data and the modules they call or publish. Only built with the `search` feature.
+//!
+//! Documents go to daily indices (`<prefix>-YYYY.MM.DD`, by transaction timestamp) with the transaction version as
+//! their id, so re-processing a batch overwrites the same documents. The processor manages an index template
+//! matching these indices and a lifecycle policy deleting them after the retention period: an ILM policy on
+//! Elasticsearch, or an ISM policy on OpenSearch (which has no ILM). Both are created before the first batch is
+//! written. Statuses are still recorded in Postgres.
+
+use crate::{
+    database::PgDbPool,
+    indexer::{
+        errors::TransactionProcessingError,
+        metadata_handle::MetadataHandle,
+        processing_result::ProcessingResult,
+        transaction_processor::{ProcessorBase, ProcessorBuilder, TransactionProcessor},
+    },
+    models::transactions::parse_timestamp,
+};
+use anyhow::{bail, Context, Result};
+use aptos_rest_client::{
+    aptos_api_types::{TransactionPayload, WriteModule, WriteSetChange as APIWriteSetChange},
+    Transaction,
+};
+use async_trait::async_trait;
+use reqwest::{Method, StatusCode};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{fmt::Debug, str::FromStr, sync::Arc, time::Duration};
+use tokio::sync::OnceCell;
+
+pub const NAME: &str = "search_processor";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchEngine {
+    Elasticsearch,
+    OpenSearch,
+}
+
+impl FromStr for SearchEngine {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "elasticsearch" => Ok(Self::Elasticsearch),
+            "opensearch" => Ok(Self::OpenSearch),
+            _ => bail!(
+                "Unknown search engine {}, expected elasticsearch or opensearch",
+                s
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchConfig {
+    /// URL of the cluster, ex: "http://localhost:9200"
+    pub url: String,
+    pub engine: SearchEngine,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// Indices are named `<index_prefix>-YYYY.MM.DD`. Also the name of the index template and lifecycle policy.
+    pub index_prefix: String,
+    /// Indices are deleted once they're this old
+    pub retention_days: u32,
+    pub request_timeout: Duration,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:9200".to_string(),
+            engine: SearchEngine::OpenSearch,
+            user: None,
+            password: None,
+            index_prefix: "aptos-transactions".to_string(),
+            retention_days: 30,
+            request_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl SearchConfig {
+    /// Path and body of the request creating the lifecycle policy
+    pub fn lifecycle_policy(&self) -> (String, Value) {
+        let max_age = format!("{}d", self.retention_days);
+        match self.engine {
+            SearchEngine::Elasticsearch => (
+                format!("_ilm/policy/{}", self.index_prefix),
+                json!({
+                    "policy": {
+                        "phases": {
+                            "hot": {"actions": {}},
+                            "delete": {"min_age": max_age, "actions": {"delete": {}}},
+                        },
+                    },
+                }),
+            ),
+            SearchEngine::OpenSearch => (
+                format!("_plugins/_ism/policies/{}", self.index_prefix),
+                json!({
+                    "policy": {
+                        "description": "Deletes indexed transactions after the retention period",
+                        "default_state": "hot",
+                        "states": [
+                            {
+                                "name": "hot",
+                                "actions": [],
+                                "transitions": [
+                                    {"state_name": "delete", "conditions": {"min_index_age": max_age}},
+                                ],
+                            },
+                            {"name": "delete", "actions": [{"delete": {}}], "transitions": []},
+                        ],
+                        // ISM policies attach themselves to new indices, rather than being named by the template
+                        "ism_template": [
+                            {"index_patterns": [format!("{}-*", self.index_prefix)], "priority": 100},
+                        ],
+                    },
+                }),
+            ),
+        }
+    }
+
+    /// Body of the index template: the mappings of `SearchDocument`, and the lifecycle policy on Elasticsearch
+    pub fn index_template(&self) -> Value {
+        let mut settings = json!({"number_of_shards": 1});
+        if self.engine == SearchEngine::Elasticsearch {
+            settings["index.lifecycle.name"] = Value::from(self.index_prefix.clone());
+        }
+        json!({
+            "index_patterns": [format!("{}-*", self.index_prefix)],
+            "template": {
+                "settings": settings,
+                "mappings": {
+                    "dynamic": false,
+                    "properties": {
+                        "version": {"type": "long"},
+                        "hash": {"type": "keyword"},
+                        "timestamp": {"type": "date"},
+                        "sender": {"type": "keyword"},
+                        "success": {"type": "boolean"},
+                        "vm_status": {"type": "text"},
+                        "function": {"type": "keyword"},
+                        "payload": {"type": "text"},
+                        "event_types": {"type": "keyword"},
+                        "event_data": {"type": "text"},
+                        "module_names": {"type": "keyword"},
+                    },
+                },
+            },
+        })
+    }
+}
+
+/// What's indexed of a user transaction. Payloads and event data are indexed as JSON text, so any value in them
+/// (addresses, arguments, amounts) can be searched for.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SearchDocument {
+    pub version: u64,
+    pub hash: String,
+    pub timestamp: chrono::NaiveDateTime,
+    pub sender: String,
+    pub success: bool,
+    pub vm_status: String,
+    /// Entry function called, ex: "0x1::coin::transfer". NULL for scripts and module bundles.
+    pub function: Option<String>,
+    pub payload: String,
+    pub event_types: Vec<String>,
+    pub event_data: Vec<String>,
+    /// Modules called (`<address>::<name>`) and published by the transaction
+    pub module_names: Vec<String>,
+}
+
+impl SearchDocument {
+    /// Only user transactions are indexed
+    pub fn from_transaction(transaction: &Transaction) -> Option<Self> {
+        let user_txn = match transaction {
+            Transaction::UserTransaction(user_txn) => user_txn,
+            _ => return None,
+        };
+        let mut module_names = vec![];
+        let function = match &user_txn.request.payload {
+            TransactionPayload::EntryFunctionPayload(payload) => {
+                module_names.push(payload.function.module.to_string());
+                Some(payload.function.to_string())
+            }
+            _ => None,
+        };
+        for change in &user_txn.info.changes {
+            if let APIWriteSetChange::WriteModule(WriteModule { data, .. }) = change {
+                // ABIs aren't sent by the node, so they're parsed from the bytecode
+                if let Some(abi) = data.clone().try_parse_abi().ok().and_then(|data| data.abi) {
+                    module_names.push(format!("{}::{}", abi.address, abi.name));
+                }
+            }
+        }
+        Some(Self {
+            version: *user_txn.info.version.inner(),
+            hash: user_txn.info.hash.to_string(),
+            timestamp: parse_timestamp(user_txn.timestamp, user_txn.info.version),
+            sender: user_txn.request.sender.to_string(),
+            success: user_txn.info.success,
+            vm_status: user_txn.info.vm_status.clone(),
+            function,
+            payload: serde_json::to_string(&user_txn.request.payload)
+                .expect("Unable to serialize transaction payload"),
+            event_types: user_txn
+                .events
+                .iter()
+                .map(|event| event.typ.to_string())
+                .collect(),
+            event_data: user_txn
+                .events
+                .iter()
+                .map(|event| event.data.to_string())
+                .collect(),
+            module_names,
+        })
+    }
+
+    pub fn index_name(&self, index_prefix: &str) -> String {
+        format!("{}-{}", index_prefix, self.timestamp.format("%Y.%m.%d"))
+    }
+}
+
+/// Newline delimited body of a `_bulk` request indexing `documents`
+pub fn bulk_body(index_prefix: &str, documents: &[SearchDocument]) -> String {
+    let mut body = String::new();
+    for document in documents {
+        let action = json!({
+            "index": {"_index": document.index_name(index_prefix), "_id": document.version.to_string()},
+        });
+        body.push_str(&action.to_string());
+        body.push('\n');
+        body.push_str(&serde_json::to_string(document).expect("Unable to serialize document"));
+        body.push('\n');
+    }
+    body
+}
+
+/// Indexes user transactions into Elasticsearch or OpenSearch, for free-text search
+pub struct SearchTransactionProcessor {
+    base: ProcessorBase,
+    config: SearchConfig,
+    client: reqwest::Client,
+    /// Set once the index template and lifecycle policy exist
+    setup: OnceCell<()>,
+}
+
+impl SearchTransactionProcessor {
+    pub fn new(connection_pool: PgDbPool, config: SearchConfig) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(config.request_timeout)
+            .build()
+            .context("Failed to build the search client")?;
+        Ok(Self {
+            base: ProcessorBase::new(NAME, connection_pool),
+            config,
+            client,
+            setup: OnceCell::new(),
+        })
+    }
+
+    fn request(&self, method: Method, path: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}/{}", self.config.url.trim_end_matches('/'), path);
+        let request = self.client.request(method, url);
+        match &self.config.user {
+            Some(user) => request.basic_auth(user, self.config.password.as_ref()),
+            None => request,
+        }
+    }
+
+    /// Creates the lifecycle policy if it doesn't exist, and creates or updates the index template
+    async fn ensure_setup(&self) -> Result<()> {
+        let (policy_path, policy) = self.config.lifecycle_policy();
+        let response = self
+            .request(Method::PUT, &policy_path)
+            .json(&policy)
+            .send()
+            .await
+            .context("Failed to create the lifecycle policy")?;
+        // ISM refuses to overwrite an existing policy without its sequence number: keep the existing one
+        if !response.status().is_success() && response.status() != StatusCode::CONFLICT {
+            bail!(
+                "Failed to create the lifecycle policy: {}",
+                response.text().await.unwrap_or_default()
+            );
+        }
+
+        let response = self
+            .request(
+                Method::PUT,
+                &format!("_index_template/{}", self.config.index_prefix),
+            )
+            .json(&self.config.index_template())
+            .send()
+            .await
+            .context("Failed to create the index template")?;
+        if !response.status().is_success() {
+            bail!(
+                "Failed to create the index template: {}",
+                response.text().await.unwrap_or_default()
+            );
+        }
+        Ok(())
+    }
+
+    async fn index_documents(&self, documents: &[SearchDocument]) -> Result<()> {
+        self.setup.get_or_try_init(|| self.ensure_setup()).await?;
+        let response = self
+            .request(Method::POST, "_bulk")
+            .header(reqwest::header::CONTENT_TYPE, "application/x-ndjson")
+            .body(bulk_body(&self.config.index_prefix, documents))
+            .send()
+            .await
+            .context("Failed to send the bulk request")?;
+        let status = response.status();
+        let body: Value = response
+            .json()
+            .await
+            .with_context(|| format!("Failed to read the bulk response ({})", status))?;
+        if !status.is_success() {
+            bail!("Bulk request failed with {}: {}", status, body);
+        }
+        // Items fail individually, ex: on a mapping conflict
+        if body["errors"].as_bool().unwrap_or(false) {
+            let first_error = body["items"]
+                .as_array()
+                .and_then(|items| items.iter().find(|item| !item["index"]["error"].is_null()))
+                .map(|item| item["index"]["error"].clone())
+                .unwrap_or_default();
+            bail!("Failed to index documents: {}", first_error);
+        }
+        Ok(())
+    }
+}
+
+impl Debug for SearchTransactionProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SearchTransactionProcessor {{ url: {:?}  engine: {:?} }}",
+            self.config.url, self.config.engine
+        )
+    }
+}
+
+impl ProcessorBuilder for SearchTransactionProcessor {
+    fn base_mut(&mut self) -> &mut ProcessorBase {
+        &mut self.base
+    }
+}
+
+#[async_trait]
+impl TransactionProcessor for SearchTransactionProcessor {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn process_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<ProcessingResult, TransactionProcessingError> {
+        let documents: Vec<SearchDocument> = transactions
+            .iter()
+            .filter_map(SearchDocument::from_transaction)
+            .collect();
+        if !documents.is_empty() {
+            self.index_documents(&documents).await.map_err(|err| {
+                TransactionProcessingError::Custom((
+                    err,
+                    start_version,
+                    end_version,
+                    self.name().to_string(),
+                ))
+            })?;
+        }
+        Ok(ProcessingResult::new(
+            self.name(),
+            start_version,
+            end_version,
+        ))
+    }
+
+    fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+        self.base.metadata_handle()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bulk_body() {
+        let document = SearchDocument {
+            version: 42,
+            hash: "0x1234".to_string(),
+            timestamp: chrono::NaiveDateTime::from_timestamp(1_663_000_000, 0),
+            sender: "0xa11ce".to_string(),
+            success: true,
+            vm_status: "Executed successfully".to_string(),
+            function: Some("0x1::coin::transfer".to_string()),
+            payload: "{}".to_string(),
+            event_types: vec!["0x1::coin::WithdrawEvent".to_string()],
+            event_data: vec![r#"{"amount":"10"}"#.to_string()],
+            module_names: vec!["0x1::coin".to_string()],
+        };
+        let body = bulk_body("aptos-transactions", &[document.clone()]);
+        let lines: Vec<Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert!(body.ends_with('\n'));
+        assert_eq!(
+            lines[0],
+            json!({"index": {"_index": "aptos-transactions-2022.09.12", "_id": "42"}})
+        );
+        assert_eq!(lines[1]["function"], "0x1::coin::transfer");
+        assert_eq!(lines[1]["event_data"][0], r#"{"amount":"10"}"#);
+        assert_eq!(lines.len(), 2);
+    }
+
+    #[test]
+    fn test_lifecycle_policy() {
+        let config = SearchConfig {
+            retention_days: 7,
+            ..Default::default()
+        };
+        let (path, policy) = config.lifecycle_policy();
+        assert_eq!(path, "_plugins/_ism/policies/aptos-transactions");
+        assert_eq!(
+            policy["policy"]["states"][0]["transitions"][0]["conditions"]["min_index_age"],
+            "7d"
+        );
+        assert!(config.index_template()["template"]["settings"]
+            .get("index.lifecycle.name")
+            .is_none());
+
+        let config = SearchConfig {
+            engine: SearchEngine::Elasticsearch,
+            ..config
+        };
+        let (path, policy) = config.lifecycle_policy();
+        assert_eq!(path, "_ilm/policy/aptos-transactions");
+        assert_eq!(policy["policy"]["phases"]["delete"]["min_age"], "7d");
+        assert_eq!(
+            config.index_template()["template"]["settings"]["index.lifecycle.name"],
+            "aptos-transactions"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! The records stream sinks (ex: `kinesis_processor`, `pubsub_processor`) write: each transaction is a record, without
+//! its events, and each event another. Records are partitioned by account: the sender of user transactions (the
+//! proposer of block metadata ones, `0x1` for others), and the address of events' keys.
+
+use anyhow::{Context, Result};
+use aptos_rest_client::{aptos_api_types::Event, Transaction};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// A transaction (without its events) or one of its events, as a record of a stream
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StreamRecord {
+    /// "transaction" or "event"
+    pub kind: &'static str,
+    pub version: u64,
+    /// Index of the event in its transaction. NULL for transactions.
+    pub event_index: Option<u64>,
+    /// Account the record is partitioned by
+    pub account: String,
+    /// The transaction (without its events) or event, as the node's API serves it
+    pub data: Value,
+    /// Whether the transaction's write set changes were dropped, to fit in a record
+    pub changes_omitted: bool,
+}
+
+impl StreamRecord {
+    /// A record for the transaction, then one per event. Pending transactions have none. The transaction's write set
+    /// changes are dropped if its record would be larger than `max_record_bytes` otherwise.
+    pub fn from_transaction(
+        transaction: &Transaction,
+        max_record_bytes: usize,
+    ) -> Result<Vec<Self>> {
+        let (version, account, events): (u64, String, &[Event]) = match transaction {
+            Transaction::UserTransaction(txn) => (
+                *txn.info.version.inner(),
+                txn.request.sender.to_string(),
+                txn.events.as_slice(),
+            ),
+            Transaction::BlockMetadataTransaction(txn) => (
+                *txn.info.version.inner(),
+                txn.proposer.to_string(),
+                txn.events.as_slice(),
+            ),
+            Transaction::GenesisTransaction(txn) => (
+                *txn.info.version.inner(),
+                "0x1".to_string(),
+                txn.events.as_slice(),
+            ),
+            Transaction::StateCheckpointTransaction(txn) => {
+                (*txn.info.version.inner(), "0x1".to_string(), &[])
+            }
+            Transaction::PendingTransaction(_) => return Ok(vec![]),
+        };
+        let mut data =
+            serde_json::to_value(transaction).context("Unable to serialize transaction")?;
+        if let Some(fields) = data.as_object_mut() {
+            fields.remove("events");
+        }
+        let mut records = vec![Self {
+            kind: "transaction",
+            version,
+            event_index: None,
+            account,
+            data,
+            changes_omitted: false,
+        }];
+        if records[0].to_bytes()?.len() > max_record_bytes {
+            records[0].data["changes"] = json!([]);
+            records[0].changes_omitted = true;
+        }
+        for (index, event) in events.iter().enumerate() {
+            records.push(Self {
+                kind: "event",
+                version,
+                event_index: Some(index as u64),
+                account: event.guid.account_address.to_string(),
+                data: serde_json::to_value(event).context("Unable to serialize event")?,
+                changes_omitted: false,
+            });
+        }
+        Ok(records)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        serde_json::to_vec(self).context("Unable to serialize record")
+    }
+
+    /// The type of the event, ex: "0x1::coin::DepositEvent". `None` for transactions.
+    pub fn event_type(&self) -> Option<&str> {
+        self.event_index.and(self.data["type"].as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TransactionFactory;
+
+    #[test]
+    fn test_records() {
+        let mut factory = TransactionFactory::new(10);
+        let txn = factory
+            .user_transaction("0xa11ce")
+            .entry_function("0x1::coin::transfer", &[], vec![])
+            .event_from(
+                "0xb0b",
+                0,
+                "0x1::coin::DepositEvent",
+                json!({"amount": "10"}),
+            )
+            .build();
+        let records = StreamRecord::from_transaction(&txn, 1024 * 1024).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].kind, "transaction");
+        assert_eq!(records[0].version, 10);
+        assert_eq!(records[0].account, "0xa11ce");
+        assert_eq!(records[0].event_type(), None);
+        assert!(records[0].data.get("events").is_none());
+        assert!(!records[0].changes_omitted);
+        assert_eq!(records[1].kind, "event");
+        assert_eq!(records[1].event_index, Some(0));
+        assert_eq!(records[1].account, "0xb0b");
+        assert_eq!(records[1].event_type(), Some("0x1::coin::DepositEvent"));
+        assert_eq!(records[1].data["data"]["amount"], "10");
+
+        let records = StreamRecord::from_transaction(&txn, 10).unwrap();
+        assert!(records[0].changes_omitted);
+        assert_eq!(records[0].data["changes"], json!([]));
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    database::{insert_chunked, with_deadlock_retry, PgDbPool, PgPoolConnection},
+    indexer::{
+        errors::TransactionProcessingError,
+        metadata_handle::MetadataHandle,
+        processing_result::ProcessingResult,
+        transaction_processor::{ProcessorBase, ProcessorBuilder, TransactionProcessor},
+    },
+    models::{dex_swap::DexSwapModel, transactions::TransactionModel},
+    schema,
+};
+use aptos_rest_client::Transaction;
+use async_trait::async_trait;
+use diesel::{Connection, QueryResult};
+use std::{fmt::Debug, sync::Arc};
+
+pub const NAME: &str = "swap_processor";
+
+/// Decodes the swap events emitted by a configured list of DEX addresses into the normalized `dex_swaps` table
+pub struct SwapTransactionProcessor {
+    base: ProcessorBase,
+    dex_addresses: Vec<String>,
+}
+
+impl SwapTransactionProcessor {
+    pub fn new(connection_pool: PgDbPool, dex_addresses: Vec<String>) -> Self {
+        Self {
+            base: ProcessorBase::new(NAME, connection_pool),
+            dex_addresses,
+        }
+    }
+}
+
+impl Debug for SwapTransactionProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = &self.base.connection_pool().state();
+        write!(
+            f,
+            "SwapTransactionProcessor {{ dex_addresses: {:?} connections: {:?}  idle_connections: {:?} }}",
+            self.dex_addresses, state.connections, state.idle_connections
+        )
+    }
+}
+
+fn insert_dex_swaps(conn: &PgPoolConnection, swaps: &[DexSwapModel]) -> QueryResult<usize> {
+    insert_chunked(conn, swaps, |chunk| {
+        diesel::insert_into(schema::dex_swaps::table)
+            .values(chunk)
+            .on_conflict_do_nothing()
+    })
+}
+
+impl ProcessorBuilder for SwapTransactionProcessor {
+    fn base_mut(&mut self) -> &mut ProcessorBase {
+        &mut self.base
+    }
+}
+
+#[async_trait]
+impl TransactionProcessor for SwapTransactionProcessor {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn process_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<ProcessingResult, TransactionProcessingError> {
+        let txns_with_events = TransactionModel::from_transactions_for_tokens(&transactions);
+        let swaps: Vec<DexSwapModel> = txns_with_events
+            .iter()
+            .flat_map(|(txn, events)| {
+                events.iter().filter_map(move |event| {
+                    DexSwapModel::from_event(event, txn, &self.dex_addresses)
+                })
+            })
+            .collect();
+
+        let tx_result = with_deadlock_retry(self.name(), self.base.get_conn(), |conn| {
+            conn.transaction::<(), diesel::result::Error, _>(|| {
+                insert_dex_swaps(conn, &swaps)?;
+                self.base
+                    .transaction_metadata_handle(conn)
+                    .mark_versions_success(start_version, end_version)
+            })
+        })
+        .await;
+        match tx_result {
+            Ok(_) => Ok(
+                ProcessingResult::new(self.name(), start_version, end_version)
+                    .with_status_committed(),
+            ),
+            Err(err) => Err(TransactionProcessingError::from_db_error(
+                err,
+                start_version,
+                end_version,
+                self.name(),
+            )),
+        }
+    }
+
+    fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+        self.base.metadata_handle()
+    }
+}
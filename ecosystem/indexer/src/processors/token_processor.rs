@@ -1,7 +1,7 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::database::get_chunks;
+use crate::database::insert_chunked;
 use crate::models::token::{
     CreateCollectionEventType, CreateTokenDataEventType, MintTokenEventType,
     MutateTokenPropertyMapEventType, TokenData, TokenEvent,
@@ -10,10 +10,13 @@ use crate::schema::token_datas::dsl::token_datas;
 use crate::schema::token_datas::{last_minted_at, supply};
 use crate::util::{ensure_not_negative, u64_to_bigdecimal};
 use crate::{
-    database::{execute_with_better_error, PgDbPool, PgPoolConnection},
+    database::{execute_with_better_error, with_deadlock_retry, PgDbPool, PgPoolConnection},
     indexer::{
-        errors::TransactionProcessingError, metadata_fetcher::MetaDataFetcher,
-        processing_result::ProcessingResult, transaction_processor::TransactionProcessor,
+        errors::TransactionProcessingError,
+        metadata_fetcher::MetaDataFetcher,
+        metadata_handle::MetadataHandle,
+        processing_result::ProcessingResult,
+        transaction_processor::{ProcessorBase, ProcessorBuilder, TransactionProcessor},
     },
     models::{
         collection::Collection,
@@ -28,20 +31,19 @@ use crate::{
 use aptos_rest_client::Transaction;
 use async_trait::async_trait;
 use diesel::{Connection, ExpressionMethods, QueryDsl, RunQueryDsl};
-use field_count::FieldCount;
-use std::fmt::Debug;
+use std::{fmt::Debug, sync::Arc};
 
 pub const NAME: &str = "token_processor";
 
 pub struct TokenTransactionProcessor {
-    connection_pool: PgDbPool,
+    base: ProcessorBase,
     index_token_uri: bool,
 }
 
 impl TokenTransactionProcessor {
     pub fn new(connection_pool: PgDbPool, index_token_uri: bool) -> Self {
         Self {
-            connection_pool,
+            base: ProcessorBase::new(NAME, connection_pool),
             index_token_uri,
         }
     }
@@ -49,7 +51,7 @@ impl TokenTransactionProcessor {
 
 impl Debug for TokenTransactionProcessor {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let state = &self.connection_pool.state();
+        let state = &self.base.connection_pool().state();
         write!(
             f,
             "TokenTransactionProcessor {{ connections: {:?}  idle_connections: {:?} }}",
@@ -242,10 +244,16 @@ fn process_token_on_chain_data(
     }
 }
 
+impl ProcessorBuilder for TokenTransactionProcessor {
+    fn base_mut(&mut self) -> &mut ProcessorBase {
+        &mut self.base
+    }
+}
+
 #[async_trait]
 impl TransactionProcessor for TokenTransactionProcessor {
-    fn name(&self) -> &'static str {
-        NAME
+    fn name(&self) -> &str {
+        self.base.name()
     }
 
     async fn process_transactions(
@@ -256,7 +264,7 @@ impl TransactionProcessor for TokenTransactionProcessor {
     ) -> Result<ProcessingResult, TransactionProcessingError> {
         let txns_with_events = TransactionModel::from_transactions_for_tokens(&transactions);
 
-        let conn = self.get_conn();
+        let conn = self.base.get_conn();
         let mut token_uris: Vec<(String, String)> = vec![];
 
         // filter events to only keep token events
@@ -274,52 +282,55 @@ impl TransactionProcessor for TokenTransactionProcessor {
             })
             .collect();
 
-        let mut tx_result = conn.transaction::<(), diesel::result::Error, _>(|| {
-            process_token_on_chain_data(&conn, &txns_with_token_events, &mut token_uris);
-            Ok(())
-        });
+        let mut tx_result = with_deadlock_retry(self.name(), conn, |conn| {
+            conn.transaction::<(), diesel::result::Error, _>(|| {
+                token_uris.clear();
+                process_token_on_chain_data(conn, &txns_with_token_events, &mut token_uris);
+                self.base
+                    .transaction_metadata_handle(conn)
+                    .mark_versions_success(start_version, end_version)
+            })
+        })
+        .await;
 
         if let Err(err) = tx_result {
-            return Err(TransactionProcessingError::TransactionCommitError((
-                anyhow::Error::from(err),
+            return Err(TransactionProcessingError::from_db_error(
+                err,
                 start_version,
                 end_version,
                 self.name(),
-            )));
+            ));
         };
         if self.index_token_uri {
             let mut res: Vec<Metadata> = vec![];
             get_all_metadata(&token_uris, &mut res).await;
-            tx_result = conn.transaction::<(), diesel::result::Error, _>(|| {
-                let chunks = get_chunks(res.len(), Metadata::field_count());
-                for (start_ind, end_ind) in chunks {
-                    execute_with_better_error(
-                        &conn,
+            tx_result = with_deadlock_retry(self.name(), self.base.get_conn(), |conn| {
+                conn.transaction::<(), diesel::result::Error, _>(|| {
+                    insert_chunked(conn, &res, |chunk| {
                         diesel::insert_into(schema::metadatas::table)
-                            .values(&res[start_ind..end_ind])
-                            .on_conflict_do_nothing(),
-                    )
-                    .expect("Error inserting row into metadatas");
-                }
-                Ok(())
-            });
+                            .values(chunk)
+                            .on_conflict_do_nothing()
+                    })?;
+                    Ok(())
+                })
+            })
+            .await;
         }
         match tx_result {
-            Ok(_) => Ok(ProcessingResult::new(
-                self.name(),
-                start_version,
-                end_version,
-            )),
-            Err(err) => Err(TransactionProcessingError::TransactionCommitError((
-                anyhow::Error::from(err),
+            Ok(_) => Ok(
+                ProcessingResult::new(self.name(), start_version, end_version)
+                    .with_status_committed(),
+            ),
+            Err(err) => Err(TransactionProcessingError::from_db_error(
+                err,
                 start_version,
                 end_version,
                 self.name(),
-            ))),
+            )),
         }
     }
 
-    fn connection_pool(&self) -> &PgDbPool {
-        &self.connection_pool
+    fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+        self.base.metadata_handle()
     }
 }
@@ -0,0 +1,245 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    database::{
+        insert_chunked, latest_version_wins, with_deadlock_retry, PgDbPool, PgPoolConnection,
+    },
+    indexer::{
+        errors::TransactionProcessingError,
+        metadata_handle::MetadataHandle,
+        processing_result::ProcessingResult,
+        transaction_processor::{ProcessorBase, ProcessorBuilder, TransactionProcessor},
+    },
+    models::{
+        object::{CurrentObjectOwnershipModel, ObjectModel},
+        token_v2::{
+            CollectionV2Model, CurrentTokenOwnershipV2Model, TokenActivityV2Model, TokenV2Model,
+            TRANSFER_EVENT_TYPE,
+        },
+    },
+    processors::processor_helpers::convert_sub_batches,
+    schema,
+};
+use aptos_rest_client::Transaction;
+use async_trait::async_trait;
+use diesel::{
+    dsl::sql,
+    sql_types::{Bool, Nullable, Numeric, Text},
+    Connection, ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl,
+};
+use std::{collections::HashSet, fmt::Debug, sync::Arc};
+
+pub const NAME: &str = "token_v2_processor";
+
+/// Indexes token v2 (digital assets built on objects, `0x4::collection` and `0x4::token`) into `collections_v2`,
+/// `tokens_v2`, `token_activities_v2` and who owns each token now into `current_token_ownerships_v2`. Token v1 is
+/// indexed by the token processor.
+pub struct TokenV2TransactionProcessor {
+    base: ProcessorBase,
+    sub_batches: usize,
+}
+
+impl TokenV2TransactionProcessor {
+    pub fn new(connection_pool: PgDbPool) -> Self {
+        Self {
+            base: ProcessorBase::new(NAME, connection_pool),
+            sub_batches: 1,
+        }
+    }
+
+    /// Converts each batch as this many sub-batches in parallel. They're still written in a single DB transaction.
+    pub fn with_sub_batches(mut self, sub_batches: usize) -> Self {
+        self.sub_batches = sub_batches;
+        self
+    }
+}
+
+impl Debug for TokenV2TransactionProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = &self.base.connection_pool().state();
+        write!(
+            f,
+            "TokenV2TransactionProcessor {{ connections: {:?}  idle_connections: {:?} }}",
+            state.connections, state.idle_connections
+        )
+    }
+}
+
+fn insert_collections_v2(
+    conn: &PgPoolConnection,
+    collections: &[CollectionV2Model],
+) -> QueryResult<usize> {
+    insert_chunked(conn, collections, |chunk| {
+        diesel::insert_into(schema::collections_v2::table)
+            .values(chunk)
+            .on_conflict_do_nothing()
+    })
+}
+
+fn insert_tokens_v2(conn: &PgPoolConnection, tokens: &[TokenV2Model]) -> QueryResult<usize> {
+    insert_chunked(conn, tokens, |chunk| {
+        diesel::insert_into(schema::tokens_v2::table)
+            .values(chunk)
+            .on_conflict_do_nothing()
+    })
+}
+
+fn insert_token_activities_v2(
+    conn: &PgPoolConnection,
+    activities: &[TokenActivityV2Model],
+) -> QueryResult<usize> {
+    insert_chunked(conn, activities, |chunk| {
+        diesel::insert_into(schema::token_activities_v2::table)
+            .values(chunk)
+            .on_conflict_do_nothing()
+    })
+}
+
+fn insert_current_token_ownerships_v2(
+    conn: &PgPoolConnection,
+    ownerships: &[CurrentTokenOwnershipV2Model],
+) -> QueryResult<usize> {
+    use schema::current_token_ownerships_v2::dsl::*;
+
+    let table = "current_token_ownerships_v2";
+    insert_chunked(conn, ownerships, |chunk| {
+        diesel::insert_into(schema::current_token_ownerships_v2::table)
+            .values(chunk)
+            .on_conflict(token_data_id)
+            .do_update()
+            .set((
+                // Burns don't say who the owner was, so keep the existing one
+                owner_address.eq(sql::<Nullable<Text>>(
+                    "CASE WHEN EXCLUDED.last_transaction_version >= current_token_ownerships_v2.last_transaction_version \
+                     THEN COALESCE(EXCLUDED.owner_address, current_token_ownerships_v2.owner_address) \
+                     ELSE current_token_ownerships_v2.owner_address END",
+                )),
+                is_burned.eq(latest_version_wins::<Bool>(table, "is_burned")),
+                last_transaction_version.eq(latest_version_wins::<Numeric>(
+                    table,
+                    "last_transaction_version",
+                )),
+            ))
+    })
+}
+
+/// Which of `object_addresses` are tokens indexed by earlier batches
+fn get_existing_token_data_ids(
+    conn: &PgPoolConnection,
+    object_addresses: Vec<String>,
+) -> HashSet<String> {
+    use schema::current_token_ownerships_v2::dsl::*;
+
+    if object_addresses.is_empty() {
+        return HashSet::new();
+    }
+    current_token_ownerships_v2
+        .select(token_data_id)
+        .filter(token_data_id.eq_any(object_addresses))
+        .load::<String>(conn)
+        .expect("Error loading token ids from current_token_ownerships_v2")
+        .into_iter()
+        .collect()
+}
+
+impl ProcessorBuilder for TokenV2TransactionProcessor {
+    fn base_mut(&mut self) -> &mut ProcessorBase {
+        &mut self.base
+    }
+}
+
+#[async_trait]
+impl TransactionProcessor for TokenV2TransactionProcessor {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn process_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<ProcessingResult, TransactionProcessingError> {
+        let outputs = convert_sub_batches(transactions, self.sub_batches, |sub_batch| {
+            (
+                TokenV2Model::from_transactions(sub_batch),
+                ObjectModel::from_transactions(sub_batch),
+            )
+        })
+        .await
+        .map_err(|err| {
+            TransactionProcessingError::Decode((
+                err,
+                start_version,
+                end_version,
+                self.name().to_string(),
+            ))
+        })?;
+        let (mut collections, mut tokens, mut activities, mut objects) =
+            (vec![], vec![], vec![], vec![]);
+        for ((sub_collections, sub_tokens, sub_activities), sub_objects) in outputs {
+            collections.extend(sub_collections);
+            tokens.extend(sub_tokens);
+            activities.extend(sub_activities);
+            objects.extend(sub_objects);
+        }
+        let object_ownerships = CurrentObjectOwnershipModel::from_objects(&objects);
+
+        let conn = self.base.get_conn();
+        // Transfers and owner changes are recorded for every object, so only keep those of tokens created in this
+        // batch or already indexed
+        let mut token_data_ids: HashSet<String> =
+            tokens.iter().map(|t| t.token_data_id.clone()).collect();
+        let unknown_addresses = object_ownerships
+            .iter()
+            .map(|ownership| &ownership.object_address)
+            .chain(activities.iter().map(|activity| &activity.token_data_id))
+            .filter(|address| !token_data_ids.contains(*address))
+            .cloned()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        token_data_ids.extend(get_existing_token_data_ids(&conn, unknown_addresses));
+        let activities: Vec<TokenActivityV2Model> = activities
+            .into_iter()
+            .filter(|activity| {
+                activity.type_ != TRANSFER_EVENT_TYPE
+                    || token_data_ids.contains(&activity.token_data_id)
+            })
+            .collect();
+        let ownerships = CurrentTokenOwnershipV2Model::from_object_ownerships(
+            &object_ownerships,
+            &token_data_ids,
+        );
+
+        let tx_result = with_deadlock_retry(self.name(), conn, |conn| {
+            conn.transaction::<(), diesel::result::Error, _>(|| {
+                insert_collections_v2(conn, &collections)?;
+                insert_tokens_v2(conn, &tokens)?;
+                insert_token_activities_v2(conn, &activities)?;
+                insert_current_token_ownerships_v2(conn, &ownerships)?;
+                self.base
+                    .transaction_metadata_handle(conn)
+                    .mark_versions_success(start_version, end_version)
+            })
+        })
+        .await;
+        match tx_result {
+            Ok(_) => Ok(
+                ProcessingResult::new(self.name(), start_version, end_version)
+                    .with_status_committed(),
+            ),
+            Err(err) => Err(TransactionProcessingError::from_db_error(
+                err,
+                start_version,
+                end_version,
+                self.name(),
+            )),
+        }
+    }
+
+    fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+        self.base.metadata_handle()
+    }
+}
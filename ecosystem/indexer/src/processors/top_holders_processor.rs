@@ -0,0 +1,170 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    database::{
+        insert_chunked, latest_version_wins, with_deadlock_retry, PgDbPool, PgPoolConnection,
+    },
+    indexer::{
+        errors::TransactionProcessingError,
+        metadata_handle::MetadataHandle,
+        processing_result::ProcessingResult,
+        transaction_processor::{ProcessorBase, ProcessorBuilder, TransactionProcessor},
+    },
+    models::top_holder::{TopHolderModel, DEFAULT_TOP_HOLDERS_COUNT},
+    schema,
+};
+use aptos_rest_client::Transaction;
+use async_trait::async_trait;
+use diesel::{
+    dsl::sql,
+    sql_query,
+    sql_types::{Array, BigInt, Nullable, Numeric, Text},
+    Connection, ExpressionMethods, QueryResult, RunQueryDsl,
+};
+use std::{fmt::Debug, sync::Arc};
+
+pub const NAME: &str = "top_holders_processor";
+
+/// Holders tracked per asset, as a multiple of those served: a holder whose balance drops is kept a while below the
+/// top, so it's still there if a larger holder's balance drops below it
+const TRACKED_HOLDERS_FACTOR: u64 = 2;
+
+/// Maintains the largest holders of each coin and fungible asset in `top_holders`, as balances change, so rich lists
+/// don't have to be computed over `fungible_asset_balance_history` or every coin store
+pub struct TopHoldersTransactionProcessor {
+    base: ProcessorBase,
+    num_holders: u64,
+}
+
+impl TopHoldersTransactionProcessor {
+    pub fn new(connection_pool: PgDbPool) -> Self {
+        Self {
+            base: ProcessorBase::new(NAME, connection_pool),
+            num_holders: DEFAULT_TOP_HOLDERS_COUNT,
+        }
+    }
+
+    /// Serves the `num_holders` largest holders of each asset
+    pub fn with_num_holders(mut self, num_holders: u64) -> Self {
+        self.num_holders = num_holders;
+        self
+    }
+}
+
+impl Debug for TopHoldersTransactionProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = &self.base.connection_pool().state();
+        write!(
+            f,
+            "TopHoldersTransactionProcessor {{ num_holders: {:?} connections: {:?}  idle_connections: {:?} }}",
+            self.num_holders, state.connections, state.idle_connections
+        )
+    }
+}
+
+fn insert_top_holders(conn: &PgPoolConnection, holders: &[TopHolderModel]) -> QueryResult<usize> {
+    use schema::top_holders::dsl::*;
+
+    let table = "top_holders";
+    insert_chunked(conn, holders, |chunk| {
+        diesel::insert_into(schema::top_holders::table)
+            .values(chunk)
+            .on_conflict((asset_type, storage_id))
+            .do_update()
+            .set((
+                // A fungible store's owner is only known when its `ObjectCore` was written, so keep the existing one
+                owner_address.eq(sql::<Nullable<Text>>(
+                    "CASE WHEN EXCLUDED.last_transaction_version >= top_holders.last_transaction_version \
+                     THEN COALESCE(EXCLUDED.owner_address, top_holders.owner_address) \
+                     ELSE top_holders.owner_address END",
+                )),
+                amount.eq(latest_version_wins::<Numeric>(table, "amount")),
+                last_transaction_version.eq(latest_version_wins::<Numeric>(
+                    table,
+                    "last_transaction_version",
+                )),
+            ))
+    })
+}
+
+/// Drops the holders of `asset_types` which emptied their store or fell below the `tracked` largest
+fn trim_top_holders(
+    conn: &PgPoolConnection,
+    asset_types: &[String],
+    tracked: u64,
+) -> QueryResult<usize> {
+    if asset_types.is_empty() {
+        return Ok(0);
+    }
+    sql_query(
+        "DELETE FROM top_holders t USING ( \
+             SELECT asset_type, storage_id, amount, \
+                 ROW_NUMBER() OVER (PARTITION BY asset_type ORDER BY amount DESC, storage_id) AS rank \
+             FROM top_holders WHERE asset_type = ANY($1) \
+         ) ranked \
+         WHERE t.asset_type = ranked.asset_type AND t.storage_id = ranked.storage_id \
+             AND (ranked.rank > $2 OR ranked.amount = 0)",
+    )
+    .bind::<Array<Text>, _>(asset_types)
+    .bind::<BigInt, _>(tracked as i64)
+    .execute(conn)
+}
+
+impl ProcessorBuilder for TopHoldersTransactionProcessor {
+    fn base_mut(&mut self) -> &mut ProcessorBase {
+        &mut self.base
+    }
+}
+
+#[async_trait]
+impl TransactionProcessor for TopHoldersTransactionProcessor {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn process_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<ProcessingResult, TransactionProcessingError> {
+        let holders = TopHolderModel::from_transactions(&transactions);
+        let mut asset_types: Vec<String> = holders
+            .iter()
+            .map(|holder| holder.asset_type.clone())
+            .collect();
+        asset_types.dedup();
+
+        let tx_result = with_deadlock_retry(self.name(), self.base.get_conn(), |conn| {
+            conn.transaction::<(), diesel::result::Error, _>(|| {
+                insert_top_holders(conn, &holders)?;
+                trim_top_holders(
+                    conn,
+                    &asset_types,
+                    self.num_holders * TRACKED_HOLDERS_FACTOR,
+                )?;
+                self.base
+                    .transaction_metadata_handle(conn)
+                    .mark_versions_success(start_version, end_version)
+            })
+        })
+        .await;
+        match tx_result {
+            Ok(_) => Ok(
+                ProcessingResult::new(self.name(), start_version, end_version)
+                    .with_status_committed(),
+            ),
+            Err(err) => Err(TransactionProcessingError::from_db_error(
+                err,
+                start_version,
+                end_version,
+                self.name(),
+            )),
+        }
+    }
+
+    fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+        self.base.metadata_handle()
+    }
+}
@@ -0,0 +1,104 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    database::{insert_chunked, with_deadlock_retry, PgDbPool, PgPoolConnection},
+    indexer::{
+        errors::TransactionProcessingError,
+        metadata_handle::MetadataHandle,
+        processing_result::ProcessingResult,
+        transaction_processor::{ProcessorBase, ProcessorBuilder, TransactionProcessor},
+    },
+    models::transfer_edge::TransferEdgeModel,
+    schema,
+};
+use aptos_rest_client::Transaction;
+use async_trait::async_trait;
+use diesel::{Connection, QueryResult};
+use std::{fmt::Debug, sync::Arc};
+
+pub const NAME: &str = "transfer_edge_processor";
+
+/// Normalizes coin and token transfers into edges between accounts (`transfer_edges`), the input format of graph
+/// analytics pipelines
+pub struct TransferEdgeTransactionProcessor {
+    base: ProcessorBase,
+}
+
+impl TransferEdgeTransactionProcessor {
+    pub fn new(connection_pool: PgDbPool) -> Self {
+        Self {
+            base: ProcessorBase::new(NAME, connection_pool),
+        }
+    }
+}
+
+impl Debug for TransferEdgeTransactionProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = &self.base.connection_pool().state();
+        write!(
+            f,
+            "TransferEdgeTransactionProcessor {{ connections: {:?}  idle_connections: {:?} }}",
+            state.connections, state.idle_connections
+        )
+    }
+}
+
+fn insert_transfer_edges(
+    conn: &PgPoolConnection,
+    edges: &[TransferEdgeModel],
+) -> QueryResult<usize> {
+    insert_chunked(conn, edges, |chunk| {
+        diesel::insert_into(schema::transfer_edges::table)
+            .values(chunk)
+            .on_conflict_do_nothing()
+    })
+}
+
+impl ProcessorBuilder for TransferEdgeTransactionProcessor {
+    fn base_mut(&mut self) -> &mut ProcessorBase {
+        &mut self.base
+    }
+}
+
+#[async_trait]
+impl TransactionProcessor for TransferEdgeTransactionProcessor {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn process_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<ProcessingResult, TransactionProcessingError> {
+        let edges = TransferEdgeModel::from_transactions(&transactions);
+
+        let tx_result = with_deadlock_retry(self.name(), self.base.get_conn(), |conn| {
+            conn.transaction::<(), diesel::result::Error, _>(|| {
+                insert_transfer_edges(conn, &edges)?;
+                self.base
+                    .transaction_metadata_handle(conn)
+                    .mark_versions_success(start_version, end_version)
+            })
+        })
+        .await;
+        match tx_result {
+            Ok(_) => Ok(
+                ProcessingResult::new(self.name(), start_version, end_version)
+                    .with_status_committed(),
+            ),
+            Err(err) => Err(TransactionProcessingError::from_db_error(
+                err,
+                start_version,
+                end_version,
+                self.name(),
+            )),
+        }
+    }
+
+    fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+        self.base.metadata_handle()
+    }
+}
@@ -0,0 +1,177 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    database::{insert_chunked, with_deadlock_retry, PgDbPool, PgPoolConnection},
+    indexer::{
+        errors::TransactionProcessingError,
+        metadata_handle::MetadataHandle,
+        processing_result::ProcessingResult,
+        transaction_processor::{ProcessorBase, ProcessorBuilder, TransactionProcessor},
+    },
+    models::txn_latency_stat::{
+        TxnLatencyStatBatch, TxnLatencyStatModel, DEFAULT_EXPIRATION_TTL_SECS,
+    },
+    schema,
+    util::{bigdecimal_to_u64, u64_to_bigdecimal},
+};
+use aptos_rest_client::Transaction;
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use diesel::{
+    dsl::sql,
+    sql_types::{BigInt, Timestamp},
+    Connection, ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl,
+};
+use std::{fmt::Debug, sync::Arc};
+
+pub const NAME: &str = "txn_latency_processor";
+
+/// Maintains the per-minute distribution of user transactions' estimated commit latency (`txn_latency_stats`), for
+/// network health dashboards. Latencies are estimated from expirations, see `TxnLatencyStat::estimate_latency_ms`.
+pub struct TxnLatencyTransactionProcessor {
+    base: ProcessorBase,
+    expiration_ttl_secs: u64,
+}
+
+impl TxnLatencyTransactionProcessor {
+    pub fn new(connection_pool: PgDbPool) -> Self {
+        Self {
+            base: ProcessorBase::new(NAME, connection_pool),
+            expiration_ttl_secs: DEFAULT_EXPIRATION_TTL_SECS,
+        }
+    }
+
+    /// How long before their expiration transactions are assumed to have been submitted
+    pub fn with_expiration_ttl_secs(mut self, expiration_ttl_secs: u64) -> Self {
+        self.expiration_ttl_secs = expiration_ttl_secs;
+        self
+    }
+}
+
+impl Debug for TxnLatencyTransactionProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = &self.base.connection_pool().state();
+        write!(
+            f,
+            "TxnLatencyTransactionProcessor {{ connections: {:?}  idle_connections: {:?} }}",
+            state.connections, state.idle_connections
+        )
+    }
+}
+
+/// Ranges of versions overlapping `start_version` to `end_version` that were already added to the stats. They're
+/// locked until the transaction ends, so a concurrent retry of the same versions waits and then skips them.
+fn get_counted_ranges(
+    conn: &PgPoolConnection,
+    start_version: u64,
+    end_version: u64,
+) -> QueryResult<Vec<(u64, u64)>> {
+    use schema::txn_latency_stat_batches::dsl;
+
+    let ranges = dsl::txn_latency_stat_batches
+        .select((dsl::start_version, dsl::end_version))
+        .filter(dsl::start_version.le(u64_to_bigdecimal(end_version)))
+        .filter(dsl::end_version.ge(u64_to_bigdecimal(start_version)))
+        .for_update()
+        .load::<(BigDecimal, BigDecimal)>(conn)?;
+    // A corrupted range fails the batch, rather than the indexer
+    let to_u64 = |version: &BigDecimal| {
+        bigdecimal_to_u64(version)
+            .map_err(|err| diesel::result::Error::DeserializationError(Box::new(err)))
+    };
+    ranges
+        .iter()
+        .map(|(start, end)| Ok((to_u64(start)?, to_u64(end)?)))
+        .collect()
+}
+
+fn upsert_txn_latency_stats(
+    conn: &PgPoolConnection,
+    stats: &[TxnLatencyStatModel],
+) -> QueryResult<usize> {
+    use schema::txn_latency_stats::dsl::*;
+
+    insert_chunked(conn, stats, |chunk| {
+        diesel::insert_into(schema::txn_latency_stats::table)
+            .values(chunk)
+            .on_conflict((period_start, latency_bucket_secs))
+            .do_update()
+            .set((
+                num_transactions.eq(sql::<BigInt>(
+                    "txn_latency_stats.num_transactions + EXCLUDED.num_transactions",
+                )),
+                total_latency_ms.eq(sql::<BigInt>(
+                    "txn_latency_stats.total_latency_ms + EXCLUDED.total_latency_ms",
+                )),
+                last_updated.eq(sql::<Timestamp>("EXCLUDED.last_updated")),
+            ))
+    })
+}
+
+impl ProcessorBuilder for TxnLatencyTransactionProcessor {
+    fn base_mut(&mut self) -> &mut ProcessorBase {
+        &mut self.base
+    }
+}
+
+#[async_trait]
+impl TransactionProcessor for TxnLatencyTransactionProcessor {
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    async fn process_transactions(
+        &self,
+        transactions: Vec<Transaction>,
+        start_version: u64,
+        end_version: u64,
+    ) -> Result<ProcessingResult, TransactionProcessingError> {
+        let tx_result = with_deadlock_retry(self.name(), self.base.get_conn(), |conn| {
+            conn.transaction::<(), diesel::result::Error, _>(|| {
+                // Versions processed before (ex: after a restart from an earlier version) mustn't be counted again
+                let counted_ranges = get_counted_ranges(conn, start_version, end_version)?;
+                let stats = if counted_ranges.is_empty() {
+                    TxnLatencyStatModel::from_transactions(&transactions, self.expiration_ttl_secs)
+                } else {
+                    let uncounted: Vec<Transaction> = transactions
+                        .iter()
+                        .filter(|transaction| {
+                            let version = transaction.version().unwrap_or_default();
+                            !counted_ranges
+                                .iter()
+                                .any(|(start, end)| (*start..=*end).contains(&version))
+                        })
+                        .cloned()
+                        .collect();
+                    TxnLatencyStatModel::from_transactions(&uncounted, self.expiration_ttl_secs)
+                };
+                upsert_txn_latency_stats(conn, &stats)?;
+                diesel::insert_into(schema::txn_latency_stat_batches::table)
+                    .values(TxnLatencyStatBatch::new(start_version, end_version))
+                    .on_conflict_do_nothing()
+                    .execute(conn)?;
+                self.base
+                    .transaction_metadata_handle(conn)
+                    .mark_versions_success(start_version, end_version)
+            })
+        })
+        .await;
+        match tx_result {
+            Ok(_) => Ok(
+                ProcessingResult::new(self.name(), start_version, end_version)
+                    .with_status_committed(),
+            ),
+            Err(err) => Err(TransactionProcessingError::from_db_error(
+                err,
+                start_version,
+                end_version,
+                self.name(),
+            )),
+        }
+    }
+
+    fn metadata_handle(&self) -> Arc<dyn MetadataHandle> {
+        self.base.metadata_handle()
+    }
+}
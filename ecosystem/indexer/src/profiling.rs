@@ -0,0 +1,105 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Exports jemalloc's allocation stats as metrics, to track memory use (and regressions) in production. The stats
+//! only cover the whole process when jemalloc is the global allocator, which the `aptos-indexer` binary sets up
+//! with the `profiling` feature.
+
+use crate::counters::{ALLOCATED_BYTES, PIPELINE_STAGE_PEAK_ALLOCATED_BYTES, RESIDENT_BYTES};
+use std::{
+    ffi::c_void,
+    os::raw::c_char,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+/// Reads a `size_t` stat, ex: "stats.allocated". `name` must be NUL-terminated.
+fn read_stat(name: &[u8]) -> Option<u64> {
+    let mut value: usize = 0;
+    let mut len = std::mem::size_of::<usize>();
+    // SAFETY: `name` is NUL-terminated, and `value`/`len` describe a `size_t`, which is what these stats hold
+    let ret = unsafe {
+        jemalloc_sys::mallctl(
+            name.as_ptr() as *const c_char,
+            &mut value as *mut usize as *mut c_void,
+            &mut len,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    (ret == 0).then(|| value as u64)
+}
+
+/// jemalloc caches its stats; advancing the epoch refreshes them
+fn refresh_stats() {
+    let mut epoch: u64 = 1;
+    let mut len = std::mem::size_of::<u64>();
+    // SAFETY: "epoch" reads and writes a `uint64_t`, which `epoch`/`len` describe
+    unsafe {
+        jemalloc_sys::mallctl(
+            b"epoch\0".as_ptr() as *const c_char,
+            &mut epoch as *mut u64 as *mut c_void,
+            &mut len,
+            &mut epoch as *mut u64 as *mut c_void,
+            len,
+        );
+    }
+}
+
+/// Bytes currently allocated by the application
+pub fn allocated_bytes() -> Option<u64> {
+    refresh_stats();
+    read_stat(b"stats.allocated\0")
+}
+
+/// Bytes in physically resident pages mapped by the allocator
+pub fn resident_bytes() -> Option<u64> {
+    refresh_stats();
+    read_stat(b"stats.resident\0")
+}
+
+/// Records the allocated bytes after a pipeline stage handled a batch, keeping the stage's peak
+pub fn observe_stage_allocation(processor_name: &str, stage: &str) {
+    if let Some(allocated) = allocated_bytes() {
+        let peak = PIPELINE_STAGE_PEAK_ALLOCATED_BYTES.with_label_values(&[processor_name, stage]);
+        if allocated as i64 > peak.get() {
+            peak.set(allocated as i64);
+        }
+    }
+}
+
+static EXPORTER_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Updates `indexer_allocated_bytes` and `indexer_resident_bytes` every `interval`, in the background. Only the first
+/// call starts the exporter.
+pub fn start_allocation_stats_exporter(interval: Duration) {
+    if EXPORTER_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    std::thread::spawn(move || loop {
+        if let Some(allocated) = allocated_bytes() {
+            ALLOCATED_BYTES.set(allocated as i64);
+        }
+        if let Some(resident) = resident_bytes() {
+            RESIDENT_BYTES.set(resident as i64);
+        }
+        std::thread::sleep(interval);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[global_allocator]
+    static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
+
+    #[test]
+    fn test_allocated_bytes_grow_with_allocations() {
+        let before = allocated_bytes().expect("jemalloc stats should be enabled");
+        let buffer = vec![1u8; 64 * 1024 * 1024];
+        let after = allocated_bytes().unwrap();
+        assert!(after >= before + buffer.len() as u64 / 2);
+        assert!(resident_bytes().unwrap() > 0);
+    }
+}
@@ -0,0 +1,431 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Typed readers for the indexer's tables, so services reading the indexed data can depend on this crate rather
+//! than hand-writing queries against the schema.
+
+use crate::{
+    database::PgPoolConnection,
+    encryption::ColumnEncryption,
+    models::{
+        events::EventModel,
+        fungible_asset::FungibleAssetActivityModel,
+        ownership::Ownership,
+        raw_transactions::RawTransactionModel,
+        rollups::EventTypeSeen,
+        top_holder::TopHolderModel,
+        transactions::{BlockMetadataTransactionModel, TransactionModel, UserTransactionModel},
+        write_set_changes::WriteSetChangeModel,
+    },
+    rollups::hll::merge_all,
+    schema::{
+        event_types_seen, events, function_reliability_stats, fungible_asset_activities,
+        fungible_asset_balance_history, fungible_asset_balances, ledger_infos, module_daily_stats,
+        ownerships, raw_transactions, top_holders, transactions, user_transactions,
+    },
+    util::u64_to_bigdecimal,
+};
+use aptos_types::account_address::AccountAddress;
+use bigdecimal::{BigDecimal, Zero};
+use diesel::{prelude::*, result::Error as DieselError, QueryResult};
+use std::collections::BTreeMap;
+
+/// A transaction with everything the default processor stores for it
+pub type TransactionWithDetails = (
+    TransactionModel,
+    Option<UserTransactionModel>,
+    Option<BlockMetadataTransactionModel>,
+    Vec<EventModel>,
+    Vec<WriteSetChangeModel>,
+);
+
+/// Transactions with `start_version <= version < end_version`, in version order
+pub fn get_transactions_by_version_range(
+    conn: &PgPoolConnection,
+    start_version: u64,
+    end_version: u64,
+) -> QueryResult<Vec<TransactionModel>> {
+    transactions::table
+        .filter(transactions::version.ge(u64_to_bigdecimal(start_version)))
+        .filter(transactions::version.lt(u64_to_bigdecimal(end_version)))
+        .order(transactions::version.asc())
+        .load::<TransactionModel>(conn)
+}
+
+/// A transaction along with its user/block metadata transaction, events and write set changes
+pub fn get_transaction_with_details(
+    conn: &PgPoolConnection,
+    version: u64,
+) -> QueryResult<Option<TransactionWithDetails>> {
+    TransactionModel::get_by_version(version, conn).optional()
+}
+
+/// Decrypts the columns `encryption` encrypted in `details` (see `encryption`). Rows of other readers can be decrypted
+/// with `ColumnEncryption::decrypt_rows` directly.
+pub fn decrypt_transaction_with_details(
+    encryption: &ColumnEncryption,
+    details: TransactionWithDetails,
+) -> anyhow::Result<TransactionWithDetails> {
+    let (txn, user_txn, block_metadata_txn, events, wscs) = details;
+    Ok((
+        encryption
+            .decrypt_rows("transactions", vec![txn])?
+            .remove(0),
+        encryption
+            .decrypt_rows("user_transactions", user_txn.into_iter().collect())?
+            .pop(),
+        encryption
+            .decrypt_rows(
+                "block_metadata_transactions",
+                block_metadata_txn.into_iter().collect(),
+            )?
+            .pop(),
+        encryption.decrypt_rows("events", events)?,
+        encryption.decrypt_rows("write_set_changes", wscs)?,
+    ))
+}
+
+/// User transactions sent by `sender`, most recent first
+pub fn get_user_transactions_by_sender(
+    conn: &PgPoolConnection,
+    sender: &AccountAddress,
+    limit: i64,
+) -> QueryResult<Vec<UserTransactionModel>> {
+    get_user_transactions_by_sender_before(conn, sender, None, limit)
+}
+
+/// User transactions sent by `sender` with a sequence number below `before_sequence_number` (if given), most recent
+/// first, to page through an account's history
+pub fn get_user_transactions_by_sender_before(
+    conn: &PgPoolConnection,
+    sender: &AccountAddress,
+    before_sequence_number: Option<u64>,
+    limit: i64,
+) -> QueryResult<Vec<UserTransactionModel>> {
+    let mut query = user_transactions::table
+        .filter(user_transactions::sender.eq(sender.to_hex_literal()))
+        .into_boxed();
+    if let Some(sequence_number) = before_sequence_number {
+        query =
+            query.filter(user_transactions::sequence_number.lt(u64_to_bigdecimal(sequence_number)));
+    }
+    query
+        .order(user_transactions::sequence_number.desc())
+        .limit(limit)
+        .load::<UserTransactionModel>(conn)
+}
+
+/// Events emitted to any of `account`'s event handles, most recent first within each handle
+pub fn get_events_by_account(
+    conn: &PgPoolConnection,
+    account: &AccountAddress,
+    limit: i64,
+) -> QueryResult<Vec<EventModel>> {
+    get_events_by_account_after(conn, account, None, limit)
+}
+
+/// Events of `account` following the event with key and sequence number `after` (if given), in the order of
+/// `get_events_by_account`, to page through them
+pub fn get_events_by_account_after(
+    conn: &PgPoolConnection,
+    account: &AccountAddress,
+    after: Option<(&str, u64)>,
+    limit: i64,
+) -> QueryResult<Vec<EventModel>> {
+    // Event keys are the hex of the handle's creation number (8 bytes) followed by the account address
+    let mut query = events::table
+        .filter(events::key.like(format!("{}{}", "_".repeat(16), account.to_hex())))
+        .into_boxed();
+    if let Some((key, sequence_number)) = after {
+        query = query.filter(
+            events::key.gt(key).or(events::key
+                .eq(key)
+                .and(events::sequence_number.lt(u64_to_bigdecimal(sequence_number)))),
+        );
+    }
+    query
+        .order((events::key.asc(), events::sequence_number.desc()))
+        .limit(limit)
+        .load::<EventModel>(conn)
+}
+
+/// Events of a single event handle, from `start_sequence_number` on
+pub fn get_events_by_key(
+    conn: &PgPoolConnection,
+    key: &str,
+    start_sequence_number: u64,
+    limit: i64,
+) -> QueryResult<Vec<EventModel>> {
+    events::table
+        .filter(events::key.eq(key))
+        .filter(events::sequence_number.ge(u64_to_bigdecimal(start_sequence_number)))
+        .order(events::sequence_number.asc())
+        .limit(limit)
+        .load::<EventModel>(conn)
+}
+
+/// Events of type `type_` (ex: `0x1::coin::DepositEvent`), most recent first. Events without a timestamp (indexed
+/// before it was recorded, until their versions are re-processed) are left out.
+pub fn get_events_by_type(
+    conn: &PgPoolConnection,
+    type_: &str,
+    limit: i64,
+) -> QueryResult<Vec<EventModel>> {
+    events::table
+        .filter(events::type_.eq(type_))
+        .filter(events::transaction_timestamp.is_not_null())
+        .order(events::transaction_timestamp.desc())
+        .limit(limit)
+        .load::<EventModel>(conn)
+}
+
+/// Fungible asset activities of the stores `owner` owned at the time, of versions below `before_version` (if given),
+/// most recent first
+pub fn get_fungible_asset_activities_by_owner(
+    conn: &PgPoolConnection,
+    owner: &AccountAddress,
+    before_version: Option<u64>,
+    limit: i64,
+) -> QueryResult<Vec<FungibleAssetActivityModel>> {
+    let mut query = fungible_asset_activities::table
+        .filter(fungible_asset_activities::owner_address.eq(owner.to_hex_literal()))
+        .into_boxed();
+    if let Some(version) = before_version {
+        query = query
+            .filter(fungible_asset_activities::transaction_version.lt(u64_to_bigdecimal(version)));
+    }
+    query
+        .order((
+            fungible_asset_activities::transaction_version.desc(),
+            fungible_asset_activities::event_index.desc(),
+        ))
+        .limit(limit)
+        .load::<FungibleAssetActivityModel>(conn)
+}
+
+/// Balance of the fungible asset `asset_type` held by `owner` right after `version`: the sum, over the owner's stores of
+/// the asset, of each store's latest balance at or before `version`. Balance history rows are absolute balances, so
+/// this is one primary key lookup per store, however long the history. Stores are found by their current owner, so a
+/// store since transferred (only secondary stores can be) counts for its new owner.
+pub fn get_balance(
+    conn: &PgPoolConnection,
+    owner: &AccountAddress,
+    asset_type: &str,
+    version: u64,
+) -> QueryResult<BigDecimal> {
+    let storage_ids = fungible_asset_balances::table
+        .select(fungible_asset_balances::storage_id)
+        .filter(fungible_asset_balances::owner_address.eq(owner.to_hex_literal()))
+        .filter(fungible_asset_balances::asset_type.eq(asset_type))
+        .load::<String>(conn)?;
+    let mut balance = BigDecimal::zero();
+    for storage_id in storage_ids {
+        if let Some(amount) = fungible_asset_balance_history::table
+            .select(fungible_asset_balance_history::amount)
+            .filter(fungible_asset_balance_history::storage_id.eq(storage_id))
+            .filter(
+                fungible_asset_balance_history::transaction_version.le(u64_to_bigdecimal(version)),
+            )
+            .order(fungible_asset_balance_history::transaction_version.desc())
+            .first::<BigDecimal>(conn)
+            .optional()?
+        {
+            balance += amount;
+        }
+    }
+    Ok(balance)
+}
+
+/// The `limit` largest holders of `asset_type` (a coin type or a fungible asset's metadata address), largest first.
+/// Only as many as the top holders processor serves (`--top-holders-count`) are guaranteed to be the actual largest.
+pub fn get_top_holders(
+    conn: &PgPoolConnection,
+    asset_type: &str,
+    limit: i64,
+) -> QueryResult<Vec<TopHolderModel>> {
+    top_holders::table
+        .filter(top_holders::asset_type.eq(asset_type))
+        .order((top_holders::amount.desc(), top_holders::storage_id.asc()))
+        .limit(limit)
+        .load::<TopHolderModel>(conn)
+}
+
+/// Estimated number of distinct accounts which called the module `module_address::module_name` between `start_date`
+/// and `end_date` (inclusive), from the union of its daily sender sketches in `module_daily_stats`
+pub fn get_module_unique_senders(
+    conn: &PgPoolConnection,
+    module_address: &AccountAddress,
+    module_name: &str,
+    start_date: chrono::NaiveDate,
+    end_date: chrono::NaiveDate,
+) -> QueryResult<u64> {
+    let sketches = module_daily_stats::table
+        .select(module_daily_stats::senders_sketch)
+        .filter(module_daily_stats::module_address.eq(module_address.to_hex_literal()))
+        .filter(module_daily_stats::module_name.eq(module_name))
+        .filter(module_daily_stats::date.between(start_date, end_date))
+        .load::<Vec<u8>>(conn)?;
+    let merged = merge_all(sketches.iter().map(Vec::as_slice))
+        .map_err(|e| DieselError::DeserializationError(e.into()))?;
+    Ok(merged.estimate())
+}
+
+/// `(function_name, num_transactions, num_failed)` of each entry function of `module_address::module_name` called
+/// between `start_date` and `end_date` (inclusive), from `function_reliability_stats`, lowest success rate first
+pub fn get_function_success_rates(
+    conn: &PgPoolConnection,
+    module_address: &AccountAddress,
+    module_name: &str,
+    start_date: chrono::NaiveDate,
+    end_date: chrono::NaiveDate,
+) -> QueryResult<Vec<(String, i64, i64)>> {
+    let rows = function_reliability_stats::table
+        .select((
+            function_reliability_stats::function_name,
+            function_reliability_stats::num_transactions,
+            function_reliability_stats::num_failed,
+        ))
+        .filter(function_reliability_stats::module_address.eq(module_address.to_hex_literal()))
+        .filter(function_reliability_stats::module_name.eq(module_name))
+        .filter(function_reliability_stats::date.between(start_date, end_date))
+        .load::<(String, i64, i64)>(conn)?;
+    let mut functions: BTreeMap<String, (i64, i64)> = BTreeMap::new();
+    for (function_name, num_transactions, num_failed) in rows {
+        let totals = functions.entry(function_name).or_default();
+        totals.0 += num_transactions;
+        totals.1 += num_failed;
+    }
+    let mut rates: Vec<(String, i64, i64)> = functions
+        .into_iter()
+        .map(|(function_name, (num_transactions, num_failed))| {
+            (function_name, num_transactions, num_failed)
+        })
+        .collect();
+    // Compares the failure rates `a_failed / a_total` and `b_failed / b_total` without dividing
+    rates.sort_by(|(_, a_total, a_failed), (_, b_total, b_failed)| {
+        (*b_failed as i128 * *a_total as i128).cmp(&(*a_failed as i128 * *b_total as i128))
+    });
+    Ok(rates)
+}
+
+/// The `limit` most frequent abort codes (see `rollups::function_reliability_stats::abort_code`) of the transactions
+/// calling `module_address::module_name` between `start_date` and `end_date` (inclusive), with their counts
+pub fn get_top_abort_codes(
+    conn: &PgPoolConnection,
+    module_address: &AccountAddress,
+    module_name: &str,
+    start_date: chrono::NaiveDate,
+    end_date: chrono::NaiveDate,
+    limit: usize,
+) -> QueryResult<Vec<(String, i64)>> {
+    let rows = function_reliability_stats::table
+        .select(function_reliability_stats::abort_codes)
+        .filter(function_reliability_stats::module_address.eq(module_address.to_hex_literal()))
+        .filter(function_reliability_stats::module_name.eq(module_name))
+        .filter(function_reliability_stats::date.between(start_date, end_date))
+        .load::<serde_json::Value>(conn)?;
+    let mut counts: BTreeMap<String, i64> = BTreeMap::new();
+    for abort_codes in rows {
+        let abort_codes = serde_json::from_value::<BTreeMap<String, i64>>(abort_codes)
+            .map_err(|e| DieselError::DeserializationError(e.into()))?;
+        for (code, count) in abort_codes {
+            *counts.entry(code).or_default() += count;
+        }
+    }
+    let mut counts: Vec<(String, i64)> = counts.into_iter().collect();
+    counts.sort_by(|(a_code, a_count), (b_code, b_count)| {
+        b_count.cmp(a_count).then_with(|| a_code.cmp(b_code))
+    });
+    counts.truncate(limit);
+    Ok(counts)
+}
+
+/// The event types emitted so far (see `rollups::event_types_seen`) which start with `prefix`, ex: `0xcafe::` for those
+/// of the modules at `0xcafe`, most emitted first
+pub fn get_event_types_seen(
+    conn: &PgPoolConnection,
+    prefix: &str,
+) -> QueryResult<Vec<EventTypeSeen>> {
+    // `_` and `%` are wildcards in a LIKE pattern, and module and struct names are full of `_`s
+    let pattern = format!(
+        "{}%",
+        prefix
+            .replace('\\', "\\\\")
+            .replace('_', "\\_")
+            .replace('%', "\\%")
+    );
+    event_types_seen::table
+        .filter(event_types_seen::type_.like(pattern))
+        .order((
+            event_types_seen::num_events.desc(),
+            event_types_seen::type_.asc(),
+        ))
+        .load::<EventTypeSeen>(conn)
+}
+
+/// Tokens currently held by `owner` (non-zero amounts only)
+pub fn get_token_ownerships(
+    conn: &PgPoolConnection,
+    owner: &AccountAddress,
+) -> QueryResult<Vec<Ownership>> {
+    let rows = ownerships::table
+        .filter(ownerships::owner.eq(owner.to_hex_literal()))
+        .filter(ownerships::amount.gt(u64_to_bigdecimal(0)))
+        .order(ownerships::token_id.asc())
+        .load::<(
+            String,
+            Option<String>,
+            Option<String>,
+            bigdecimal::BigDecimal,
+            chrono::NaiveDateTime,
+            chrono::NaiveDateTime,
+        )>(conn)?;
+    // `token_id` and `owner` are nullable in the schema, but always written
+    Ok(rows
+        .into_iter()
+        .map(
+            |(ownership_id, token_id, owner, amount, updated_at, inserted_at)| Ownership {
+                ownership_id,
+                token_id: token_id.unwrap_or_default(),
+                owner: owner.unwrap_or_default(),
+                amount,
+                updated_at,
+                inserted_at,
+            },
+        )
+        .collect())
+}
+
+/// Archived transactions with `start_version <= version < end_version`, in version order
+pub fn get_raw_transactions_by_version_range(
+    conn: &PgPoolConnection,
+    start_version: u64,
+    end_version: u64,
+) -> QueryResult<Vec<RawTransactionModel>> {
+    raw_transactions::table
+        .filter(raw_transactions::version.ge(u64_to_bigdecimal(start_version)))
+        .filter(raw_transactions::version.lt(u64_to_bigdecimal(end_version)))
+        .order(raw_transactions::version.asc())
+        .load::<RawTransactionModel>(conn)
+}
+
+/// The oldest and latest archived versions, if any version is archived
+pub fn get_raw_transaction_version_range(
+    conn: &PgPoolConnection,
+) -> QueryResult<Option<(BigDecimal, BigDecimal)>> {
+    let (oldest, latest) = raw_transactions::table
+        .select((
+            diesel::dsl::min(raw_transactions::version),
+            diesel::dsl::max(raw_transactions::version),
+        ))
+        .first::<(Option<BigDecimal>, Option<BigDecimal>)>(conn)?;
+    Ok(oldest.zip(latest))
+}
+
+/// The chain id the indexer recorded, if it ever fetched from a node
+pub fn get_chain_id(conn: &PgPoolConnection) -> QueryResult<Option<i64>> {
+    ledger_infos::table
+        .select(ledger_infos::chain_id)
+        .first::<i64>(conn)
+        .optional()
+}
@@ -0,0 +1,210 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! API keys and per-key rate limits of the read API. Keys come from the configuration (`--api-keys`) and from the
+//! `api_keys` table, which is re-read every `KEYS_REFRESH_INTERVAL`, so keys are added and revoked without a restart.
+//! Each key gets a token bucket holding a minute of its requests: bursts up to its limit pass, then requests are let
+//! through at the limit's rate.
+
+use crate::{
+    database::PgDbPool,
+    models::api_key::{hash_api_key, ApiKey},
+    schema::api_keys::dsl,
+};
+use anyhow::{Context, Result};
+use diesel::prelude::*;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+const KEYS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The client a request was authenticated as
+#[derive(Clone, Debug, PartialEq)]
+pub struct Client {
+    pub name: String,
+    pub requests_per_minute: u64,
+}
+
+#[derive(Debug)]
+struct TableKeys {
+    loaded_at: Instant,
+    /// Clients by key hash
+    clients: HashMap<String, Client>,
+}
+
+#[derive(Debug)]
+pub struct ApiKeys {
+    /// Clients of the configured keys, by key hash
+    configured: HashMap<String, Client>,
+    pool: Option<PgDbPool>,
+    table_keys: Mutex<Option<TableKeys>>,
+    default_requests_per_minute: u64,
+}
+
+impl ApiKeys {
+    /// Parses `keys` of the form `<name>=<key>`, and reads the `api_keys` table through `pool` (if any). Keys without
+    /// a limit of their own get `default_requests_per_minute`.
+    pub fn new(
+        keys: &[String],
+        pool: Option<PgDbPool>,
+        default_requests_per_minute: u64,
+    ) -> Result<Self> {
+        let mut configured = HashMap::new();
+        for key in keys {
+            let (name, key) = key
+                .split_once('=')
+                .with_context(|| format!("Expected <name>=<key>, got {}", key))?;
+            configured.insert(
+                hash_api_key(key.trim()),
+                Client {
+                    name: name.trim().to_string(),
+                    requests_per_minute: default_requests_per_minute,
+                },
+            );
+        }
+        Ok(Self {
+            configured,
+            pool,
+            table_keys: Mutex::new(None),
+            default_requests_per_minute,
+        })
+    }
+
+    /// The client holding `key`, if it's known and not revoked. Failing to read the table fails closed: only
+    /// configured keys are accepted until it can be read again.
+    pub fn authenticate(&self, key: &str) -> Option<Client> {
+        let key_hash = hash_api_key(key);
+        if let Some(client) = self.configured.get(&key_hash) {
+            return Some(client.clone());
+        }
+        let pool = self.pool.as_ref()?;
+        let mut table_keys = self.table_keys.lock().unwrap();
+        let stale = table_keys.as_ref().map_or(true, |keys| {
+            keys.loaded_at.elapsed() >= KEYS_REFRESH_INTERVAL
+        });
+        if stale {
+            match self.load_table_keys(pool) {
+                Ok(clients) => {
+                    *table_keys = Some(TableKeys {
+                        loaded_at: Instant::now(),
+                        clients,
+                    })
+                }
+                Err(err) => {
+                    aptos_logger::error!(
+                        error = format!("{:?}", err),
+                        "Failed to load API keys from api_keys"
+                    );
+                    *table_keys = None;
+                }
+            }
+        }
+        table_keys.as_ref()?.clients.get(&key_hash).cloned()
+    }
+
+    fn load_table_keys(&self, pool: &PgDbPool) -> Result<HashMap<String, Client>> {
+        let conn = pool.get()?;
+        let keys = dsl::api_keys
+            .filter(dsl::is_revoked.eq(false))
+            .load::<ApiKey>(&conn)?;
+        Ok(keys
+            .into_iter()
+            .map(|key| {
+                let client = Client {
+                    name: key.name,
+                    requests_per_minute: key
+                        .requests_per_minute
+                        .map_or(self.default_requests_per_minute, |limit| {
+                            limit.max(0) as u64
+                        }),
+                };
+                (key.key_hash, client)
+            })
+            .collect())
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Token buckets of the clients
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    /// Takes a request from `client`'s bucket, or returns how long until it holds one
+    pub fn check(&self, client: &Client) -> Result<(), Duration> {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: &Client, now: Instant) -> Result<(), Duration> {
+        let capacity = client.requests_per_minute as f64;
+        let per_sec = capacity / 60.0;
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(client.name.clone()).or_insert(Bucket {
+            tokens: capacity,
+            refilled_at: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * per_sec).min(capacity);
+        bucket.refilled_at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else if per_sec > 0.0 {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec))
+        } else {
+            Err(Duration::from_secs(60))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_keys() {
+        assert!(ApiKeys::new(&["no-name".to_string()], None, 60).is_err());
+        let keys = ApiKeys::new(&["alice=s3cret".to_string()], None, 60).unwrap();
+        assert_eq!(
+            keys.authenticate("s3cret"),
+            Some(Client {
+                name: "alice".to_string(),
+                requests_per_minute: 60,
+            })
+        );
+        assert_eq!(keys.authenticate("guess"), None);
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::default();
+        let client = Client {
+            name: "alice".to_string(),
+            requests_per_minute: 2,
+        };
+        let start = Instant::now();
+        assert!(limiter.check_at(&client, start).is_ok());
+        assert!(limiter.check_at(&client, start).is_ok());
+        let retry_after = limiter.check_at(&client, start).unwrap_err();
+        assert!((retry_after.as_secs_f64() - 30.0).abs() < 0.001);
+        // Other clients have their own bucket
+        let bob = Client {
+            name: "bob".to_string(),
+            ..client.clone()
+        };
+        assert!(limiter.check_at(&bob, start).is_ok());
+        assert!(limiter
+            .check_at(&client, start + Duration::from_secs(30))
+            .is_ok());
+    }
+}
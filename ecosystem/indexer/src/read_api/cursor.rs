@@ -0,0 +1,86 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Opaque cursors of the read API's list endpoints. A list's next page is read from the position of its last row
+//! (keyset pagination) rather than an offset, so rows landing while a client pages through aren't returned twice nor
+//! shift rows past it: new rows either sort before the cursor (ex: a newer transaction of an account listed newest
+//! first) or are part of the pages to come.
+//!
+//! A position is a `version` and an `index` within it, whose meaning depends on the list: transactions are positioned
+//! by version, an account's transactions by sequence number (the index), and events by event handle (the version:
+//! the handle's creation number) and sequence number. Tokens carry a hash of the request's filters, so a cursor can't
+//! be replayed against another query, and are base64 (URL safe) of: the format version, the position and the hash.
+
+use sha2::{Digest, Sha256};
+
+/// Bumped when the layout changes, so older cursors are rejected rather than misread
+const CURSOR_FORMAT_VERSION: u8 = 1;
+const CURSOR_LEN: usize = 1 + 8 + 8 + 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cursor {
+    pub version: u64,
+    pub index: u64,
+}
+
+impl Cursor {
+    pub fn new(version: u64, index: u64) -> Self {
+        Self { version, index }
+    }
+
+    pub fn encode(&self, filter_hash: u64) -> String {
+        let mut bytes = Vec::with_capacity(CURSOR_LEN);
+        bytes.push(CURSOR_FORMAT_VERSION);
+        bytes.extend(self.version.to_be_bytes());
+        bytes.extend(self.index.to_be_bytes());
+        bytes.extend(filter_hash.to_be_bytes());
+        base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+    }
+
+    /// The position `token` encodes, if it's a cursor of the query whose filters hash to `filter_hash`
+    pub fn decode(token: &str, filter_hash: u64) -> Option<Self> {
+        let bytes = base64::decode_config(token, base64::URL_SAFE_NO_PAD).ok()?;
+        if bytes.len() != CURSOR_LEN || bytes[0] != CURSOR_FORMAT_VERSION {
+            return None;
+        }
+        let read_u64 = |offset: usize| {
+            u64::from_be_bytes(bytes[offset..offset + 8].try_into().expect("8 bytes"))
+        };
+        if read_u64(17) != filter_hash {
+            return None;
+        }
+        Some(Self::new(read_u64(1), read_u64(9)))
+    }
+}
+
+/// Hash of a query's filters (ex: its route and account), to tie cursors to it
+pub fn filter_hash(filters: &str) -> u64 {
+    let digest = Sha256::digest(filters.as_bytes());
+    u64::from_be_bytes(digest[..8].try_into().expect("SHA-256 is 32 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor() {
+        let hash = filter_hash("/v1/accounts/0x1/events");
+        let cursor = Cursor::new(7, 42);
+        let token = cursor.encode(hash);
+        assert_eq!(Cursor::decode(&token, hash), Some(cursor));
+        // Another query's cursor, or a garbled one, is rejected
+        assert_eq!(
+            Cursor::decode(&token, filter_hash("/v1/accounts/0x2/events")),
+            None
+        );
+        assert_eq!(Cursor::decode(&token[1..], hash), None);
+        assert_eq!(Cursor::decode("not a cursor!", hash), None);
+        let mut bytes = base64::decode_config(&token, base64::URL_SAFE_NO_PAD).unwrap();
+        bytes[0] = CURSOR_FORMAT_VERSION + 1;
+        assert_eq!(
+            Cursor::decode(&base64::encode_config(bytes, base64::URL_SAFE_NO_PAD), hash),
+            None
+        );
+    }
+}
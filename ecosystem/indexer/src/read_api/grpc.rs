@@ -0,0 +1,411 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! The read API over gRPC (`proto/aptos/indexer/read/v1/read.proto`), for internal services which prefer it to JSON
+//! over HTTP. It's backed by the same readers (`queries`), and counted and timed in the same metrics, with the route
+//! `grpc:<method>` and the client `grpc`. It doesn't check API keys, so it should only listen on internal addresses.
+//!
+//! `SubscribeEvents` polls the event handle for new events every `SUBSCRIPTION_POLL_INTERVAL` once caught up, so it
+//! works against any replica of the database, whichever process indexes it.
+//!
+//! `GetRawTransactions` serves the `raw_transactions` archive (see `--archive-raw`) as stored, for other indexers'
+//! `GrpcTransactionStream`s to fetch from rather than from a node. It takes up to `MAX_RAW_LIMIT` versions at once.
+
+use super::{proto, DEFAULT_LIMIT, MAX_LIMIT};
+use crate::{
+    counters::{READ_API_REQUESTS, READ_API_REQUEST_SECONDS},
+    database::{PgDbPool, PgPoolConnection},
+    models::{
+        events::EventModel, fungible_asset::FungibleAssetActivityModel,
+        raw_transactions::RawTransactionModel, transactions::TransactionModel,
+    },
+    queries,
+    util::bigdecimal_to_u64,
+};
+use aptos_types::account_address::AccountAddress;
+use bigdecimal::BigDecimal;
+use futures::Stream;
+use proto::indexer_read_server::{IndexerRead, IndexerReadServer};
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    pin::Pin,
+    time::{Duration, Instant},
+};
+use tonic::{Request, Response, Status};
+
+/// How often subscriptions look for new events once caught up
+const SUBSCRIPTION_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Most versions `GetRawTransactions` returns at once, as many as the REST fetcher requests
+pub const MAX_RAW_LIMIT: u32 = 500;
+
+#[derive(Debug)]
+pub struct GrpcReadService {
+    pool: PgDbPool,
+}
+
+impl GrpcReadService {
+    pub fn new(pool: PgDbPool) -> Self {
+        Self { pool }
+    }
+
+    /// Runs `query` on a connection off the async runtime, recording it under `method`
+    async fn query<T: Send + 'static>(
+        &self,
+        method: &'static str,
+        query: impl FnOnce(&PgPoolConnection) -> Result<T, Status> + Send + 'static,
+    ) -> Result<Response<T>, Status> {
+        let start = Instant::now();
+        let result = run_query(self.pool.clone(), query).await;
+        let code = match &result {
+            Ok(_) => tonic::Code::Ok,
+            Err(status) => status.code(),
+        };
+        let code = (code as i32).to_string();
+        READ_API_REQUESTS
+            .with_label_values(&[method, code.as_str(), "grpc"])
+            .inc();
+        READ_API_REQUEST_SECONDS
+            .with_label_values(&[method])
+            .observe(start.elapsed().as_secs_f64());
+        result.map(Response::new)
+    }
+}
+
+async fn run_query<T: Send + 'static>(
+    pool: PgDbPool,
+    query: impl FnOnce(&PgPoolConnection) -> Result<T, Status> + Send + 'static,
+) -> Result<T, Status> {
+    tokio::task::spawn_blocking(move || {
+        let conn = pool.get().map_err(|err| {
+            aptos_logger::error!(error = format!("{:?}", err), "No connection for read API");
+            Status::unavailable("No database connection")
+        })?;
+        query(&conn)
+    })
+    .await
+    .expect("Read API query panicked")
+}
+
+fn db_error(err: diesel::result::Error) -> Status {
+    aptos_logger::error!(error = format!("{:?}", err), "Read API query failed");
+    Status::internal("Query failed")
+}
+
+fn to_u64(value: &BigDecimal) -> Result<u64, Status> {
+    bigdecimal_to_u64(value).map_err(|err| Status::internal(err.to_string()))
+}
+
+/// Requested limit, 0 for the default
+fn limit(limit: u32) -> i64 {
+    match limit {
+        0 => DEFAULT_LIMIT,
+        limit => (limit as i64).min(MAX_LIMIT),
+    }
+}
+
+fn timestamp_usecs(timestamp: &chrono::NaiveDateTime) -> i64 {
+    timestamp.timestamp() * 1_000_000 + timestamp.timestamp_subsec_micros() as i64
+}
+
+fn transaction_to_proto(txn: TransactionModel) -> Result<proto::Transaction, Status> {
+    Ok(proto::Transaction {
+        version: to_u64(&txn.version)?,
+        gas_used: to_u64(&txn.gas_used)?,
+        timestamp_usecs: timestamp_usecs(&txn.timestamp),
+        hash: txn.hash,
+        r#type: txn.type_,
+        payload: txn.payload.to_string(),
+        state_root_hash: txn.state_root_hash,
+        event_root_hash: txn.event_root_hash,
+        success: txn.success,
+        vm_status: txn.vm_status,
+        accumulator_root_hash: txn.accumulator_root_hash,
+        num_events: txn.num_events as u64,
+        num_write_set_changes: txn.num_write_set_changes as u64,
+    })
+}
+
+/// `rows` from `start_version`, up to the first version missing
+fn raw_transactions_to_proto(
+    start_version: u64,
+    rows: Vec<RawTransactionModel>,
+) -> Result<Vec<proto::RawTransaction>, Status> {
+    let mut transactions = vec![];
+    for (expected_version, row) in (start_version..).zip(rows) {
+        let version = to_u64(&row.version)?;
+        if version != expected_version {
+            break;
+        }
+        transactions.push(proto::RawTransaction {
+            version,
+            encoding: row.encoding,
+            data: row.data,
+        });
+    }
+    Ok(transactions)
+}
+
+fn event_to_proto(event: EventModel) -> Result<proto::Event, Status> {
+    Ok(proto::Event {
+        sequence_number: to_u64(&event.sequence_number)?,
+        transaction_timestamp_usecs: event
+            .transaction_timestamp
+            .as_ref()
+            .map_or(-1, timestamp_usecs),
+        transaction_hash: event.transaction_hash,
+        event_index: event.event_index.unwrap_or(-1),
+        key: event.key,
+        r#type: event.type_,
+        data: event.data.to_string(),
+    })
+}
+
+fn activity_to_proto(activity: FungibleAssetActivityModel) -> Result<proto::Activity, Status> {
+    Ok(proto::Activity {
+        transaction_version: to_u64(&activity.transaction_version)?,
+        transaction_timestamp_usecs: timestamp_usecs(&activity.transaction_timestamp),
+        amount: activity
+            .amount
+            .map(|amount| amount.to_string())
+            .unwrap_or_default(),
+        event_index: activity.event_index,
+        storage_id: activity.storage_id,
+        owner_address: activity.owner_address.unwrap_or_default(),
+        asset_type: activity.asset_type.unwrap_or_default(),
+        r#type: activity.type_,
+        is_frozen: activity.is_frozen.unwrap_or_default(),
+    })
+}
+
+#[async_trait::async_trait]
+impl IndexerRead for GrpcReadService {
+    type SubscribeEventsStream = Pin<Box<dyn Stream<Item = Result<proto::Event, Status>> + Send>>;
+
+    async fn get_transactions(
+        &self,
+        request: Request<proto::GetTransactionsRequest>,
+    ) -> Result<Response<proto::GetTransactionsResponse>, Status> {
+        let request = request.into_inner();
+        self.query("grpc:GetTransactions", move |conn| {
+            let txns = queries::get_transactions_by_version_range(
+                conn,
+                request.start_version,
+                request
+                    .start_version
+                    .saturating_add(limit(request.limit) as u64),
+            )
+            .map_err(db_error)?;
+            Ok(proto::GetTransactionsResponse {
+                transactions: txns
+                    .into_iter()
+                    .map(transaction_to_proto)
+                    .collect::<Result<_, _>>()?,
+            })
+        })
+        .await
+    }
+
+    async fn get_events_by_type(
+        &self,
+        request: Request<proto::GetEventsByTypeRequest>,
+    ) -> Result<Response<proto::GetEventsByTypeResponse>, Status> {
+        let request = request.into_inner();
+        self.query("grpc:GetEventsByType", move |conn| {
+            let events = queries::get_events_by_type(conn, &request.r#type, limit(request.limit))
+                .map_err(db_error)?;
+            Ok(proto::GetEventsByTypeResponse {
+                events: events
+                    .into_iter()
+                    .map(event_to_proto)
+                    .collect::<Result<_, _>>()?,
+            })
+        })
+        .await
+    }
+
+    async fn get_account_activities(
+        &self,
+        request: Request<proto::GetAccountActivitiesRequest>,
+    ) -> Result<Response<proto::GetAccountActivitiesResponse>, Status> {
+        let request = request.into_inner();
+        let owner = AccountAddress::from_hex_literal(&request.address).map_err(|_| {
+            Status::invalid_argument(format!("Invalid address {}", request.address))
+        })?;
+        self.query("grpc:GetAccountActivities", move |conn| {
+            let before_version = match request.before_version {
+                0 => None,
+                version => Some(version),
+            };
+            let activities = queries::get_fungible_asset_activities_by_owner(
+                conn,
+                &owner,
+                before_version,
+                limit(request.limit),
+            )
+            .map_err(db_error)?;
+            Ok(proto::GetAccountActivitiesResponse {
+                activities: activities
+                    .into_iter()
+                    .map(activity_to_proto)
+                    .collect::<Result<_, _>>()?,
+            })
+        })
+        .await
+    }
+
+    async fn subscribe_events(
+        &self,
+        request: Request<proto::SubscribeEventsRequest>,
+    ) -> Result<Response<Self::SubscribeEventsStream>, Status> {
+        let request = request.into_inner();
+        READ_API_REQUESTS
+            .with_label_values(&["grpc:SubscribeEvents", "0", "grpc"])
+            .inc();
+        // Empty once the stream failed, which ends it
+        let state = Some((
+            self.pool.clone(),
+            request.start_sequence_number,
+            VecDeque::new(),
+        ));
+        let key = request.key;
+        let stream = futures::stream::unfold(state, move |state| {
+            let key = key.clone();
+            async move {
+                let (pool, mut next, mut buffered) = state?;
+                loop {
+                    if let Some(event) = buffered.pop_front() {
+                        return Some((Ok(event), Some((pool, next, buffered))));
+                    }
+                    let query_key = key.clone();
+                    let events = run_query(pool.clone(), move |conn| {
+                        queries::get_events_by_key(conn, &query_key, next, MAX_LIMIT)
+                            .map_err(db_error)?
+                            .into_iter()
+                            .map(event_to_proto)
+                            .collect::<Result<Vec<_>, _>>()
+                    })
+                    .await;
+                    match events {
+                        Ok(events) => match events.last() {
+                            Some(last) => {
+                                next = last.sequence_number + 1;
+                                buffered.extend(events);
+                            }
+                            None => tokio::time::sleep(SUBSCRIPTION_POLL_INTERVAL).await,
+                        },
+                        Err(status) => return Some((Err(status), None)),
+                    }
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn get_raw_transactions(
+        &self,
+        request: Request<proto::GetRawTransactionsRequest>,
+    ) -> Result<Response<proto::GetRawTransactionsResponse>, Status> {
+        let request = request.into_inner();
+        let limit = match request.limit {
+            0 => MAX_RAW_LIMIT,
+            limit => limit.min(MAX_RAW_LIMIT),
+        };
+        self.query("grpc:GetRawTransactions", move |conn| {
+            let rows = queries::get_raw_transactions_by_version_range(
+                conn,
+                request.start_version,
+                request.start_version.saturating_add(limit as u64),
+            )
+            .map_err(db_error)?;
+            Ok(proto::GetRawTransactionsResponse {
+                transactions: raw_transactions_to_proto(request.start_version, rows)?,
+            })
+        })
+        .await
+    }
+
+    async fn get_archive_info(
+        &self,
+        _request: Request<proto::GetArchiveInfoRequest>,
+    ) -> Result<Response<proto::ArchiveInfo>, Status> {
+        self.query("grpc:GetArchiveInfo", |conn| {
+            let chain_id = queries::get_chain_id(conn)
+                .map_err(db_error)?
+                .ok_or_else(|| Status::failed_precondition("No chain id is recorded"))?;
+            let version_range =
+                queries::get_raw_transaction_version_range(conn).map_err(db_error)?;
+            let (oldest_version, latest_version) = match version_range {
+                Some((oldest, latest)) => (Some(to_u64(&oldest)?), Some(to_u64(&latest)?)),
+                None => (None, None),
+            };
+            Ok(proto::ArchiveInfo {
+                chain_id: chain_id as u32,
+                oldest_version,
+                latest_version,
+            })
+        })
+        .await
+    }
+}
+
+/// Serves the gRPC read API on `address` from `pool`, until the server fails
+pub async fn serve_grpc(pool: PgDbPool, address: SocketAddr) -> anyhow::Result<()> {
+    aptos_logger::info!(
+        address = address.to_string(),
+        "Serving the read API over gRPC"
+    );
+    tonic::transport::Server::builder()
+        .add_service(IndexerReadServer::new(GrpcReadService::new(pool)))
+        .serve(address)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(limit(0), DEFAULT_LIMIT);
+        assert_eq!(limit(10), 10);
+        assert_eq!(limit(u32::MAX), MAX_LIMIT);
+
+        let timestamp = chrono::NaiveDateTime::from_timestamp(1_665_000_000, 123_456_000);
+        assert_eq!(timestamp_usecs(&timestamp), 1_665_000_000_123_456);
+
+        let event = proto::Event {
+            key: "0x0".to_string(),
+            sequence_number: 7,
+            ..Default::default()
+        };
+        let bytes = prost::Message::encode_to_vec(&event);
+        assert_eq!(
+            <proto::Event as prost::Message>::decode(bytes.as_slice()).unwrap(),
+            event
+        );
+    }
+
+    #[test]
+    fn test_raw_transactions_to_proto() {
+        let row = |version: u64| RawTransactionModel {
+            version: crate::util::u64_to_bigdecimal(version),
+            hash: String::new(),
+            encoding: "json+gzip".to_string(),
+            data: vec![version as u8],
+            inserted_at: chrono::NaiveDateTime::from_timestamp(0, 0),
+        };
+        let transactions = raw_transactions_to_proto(5, vec![row(5), row(6), row(8)]).unwrap();
+        assert_eq!(
+            transactions
+                .iter()
+                .map(|txn| (txn.version, txn.data.clone()))
+                .collect::<Vec<_>>(),
+            vec![(5, vec![5]), (6, vec![6])]
+        );
+        assert!(raw_transactions_to_proto(5, vec![row(6)])
+            .unwrap()
+            .is_empty());
+    }
+}
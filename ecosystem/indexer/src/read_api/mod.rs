@@ -0,0 +1,595 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! A read-only HTTP API over the indexed tables, serving the typed readers of `queries` as JSON, so external
+//! developers can use the indexed data without database access. Every request needs an API key (`x-api-key` header,
+//! or `Authorization: Bearer <key>`), and is rate limited per key (see `auth`). Requests are counted by route, status
+//! and client in `indexer_read_api_request_count`, and timed in `indexer_read_api_request_seconds`. Its OpenAPI
+//! document is served without a key at `/spec` (see `spec`).
+//!
+//! Routes (all `GET`). Lists take `limit` (at most `MAX_LIMIT`), and respond with the cursor of their next page in
+//! `CURSOR_HEADER`, to pass as `cursor` to read it (see `cursor`), except for the ones noted:
+//! - `/v1/transactions?start_version=`: transactions from a version, in version order
+//! - `/v1/transactions/<version>`: a transaction with its events and write set changes
+//! - `/v1/accounts/<address>/transactions`: user transactions sent by an account, latest first
+//! - `/v1/accounts/<address>/events`: events of an account's event handles
+//! - `/v1/accounts/<address>/tokens`: tokens an account holds, all at once
+//! - `/v1/accounts/<address>/balance?asset_type=&version=`: fungible asset balance as of a version
+//! - `/v1/events/<event key>?start_sequence_number=`: events of an event handle
+//! - `/v1/assets/<asset type>/top_holders`: largest holders of a coin or fungible asset, up to `limit`
+//! - `/stream/transactions?start_version=`: server-sent events of transactions from a version on (see `stream`)
+
+pub mod auth;
+pub mod cursor;
+pub mod grpc;
+pub mod proto;
+pub mod spec;
+pub mod stream;
+
+use crate::{
+    counters::{READ_API_REQUESTS, READ_API_REQUEST_SECONDS},
+    database::{PgDbPool, PgPoolConnection},
+    queries,
+    util::bigdecimal_to_u64,
+};
+use anyhow::Result;
+use aptos_types::account_address::AccountAddress;
+use auth::{ApiKeys, Client, RateLimiter};
+use bigdecimal::BigDecimal;
+use cursor::Cursor;
+use http::{header, HeaderValue, StatusCode};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server,
+};
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Value};
+use spec::{openapi_spec, SPEC_PATH};
+use std::{
+    collections::HashMap, convert::Infallible, net::SocketAddr, str::FromStr, sync::Arc,
+    time::Instant,
+};
+use stream::CommittedVersions;
+
+/// Response header with the cursor of a list's next page, given back as the `cursor` parameter
+pub const CURSOR_HEADER: &str = "x-indexer-cursor";
+pub const DEFAULT_LIMIT: i64 = 25;
+/// Route of requests rejected before they're routed, in metrics
+const OTHER_ROUTE: &str = "other";
+pub const MAX_LIMIT: i64 = 1000;
+
+#[derive(Clone, Debug)]
+pub struct ReadApiConfig {
+    pub address: SocketAddr,
+    /// Keys of the form `<name>=<key>`, on top of those in `api_keys`
+    pub api_keys: Vec<String>,
+    /// Limit of the keys which don't have their own
+    pub default_requests_per_minute: u64,
+    /// Address to serve the API over gRPC on as well, if any (see `grpc`)
+    pub grpc_address: Option<SocketAddr>,
+    /// Versions the indexer commits, when serving from the indexing process, to push to streams right away
+    pub committed_versions: Option<Arc<CommittedVersions>>,
+}
+
+/// An error response: `{"error": <message>}` with the status
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    pub fn not_found() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "Not found")
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+
+    fn into_response(self) -> Response<Body> {
+        json_response(self.status, &json!({ "error": self.message }))
+    }
+}
+
+impl From<diesel::result::Error> for ApiError {
+    fn from(err: diesel::result::Error) -> Self {
+        aptos_logger::error!(error = format!("{:?}", err), "Read API query failed");
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "Query failed")
+    }
+}
+
+/// What a request asks for, parsed from its path
+#[derive(Clone, Debug, PartialEq)]
+pub enum Endpoint {
+    Transactions,
+    Transaction(u64),
+    AccountTransactions(AccountAddress),
+    AccountEvents(AccountAddress),
+    AccountTokens(AccountAddress),
+    AccountBalance(AccountAddress),
+    EventsByKey(String),
+    TopHolders(String),
+    StreamTransactions,
+}
+
+impl Endpoint {
+    pub fn parse(path: &str) -> Result<Self, ApiError> {
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        let address = |address: &str| {
+            AccountAddress::from_hex_literal(address)
+                .map_err(|_| ApiError::bad_request(format!("Invalid address {}", address)))
+        };
+        Ok(match segments.as_slice() {
+            ["v1", "transactions"] => Self::Transactions,
+            ["v1", "transactions", version] => Self::Transaction(
+                version
+                    .parse()
+                    .map_err(|_| ApiError::bad_request(format!("Invalid version {}", version)))?,
+            ),
+            ["v1", "accounts", account, "transactions"] => {
+                Self::AccountTransactions(address(account)?)
+            }
+            ["v1", "accounts", account, "events"] => Self::AccountEvents(address(account)?),
+            ["v1", "accounts", account, "tokens"] => Self::AccountTokens(address(account)?),
+            ["v1", "accounts", account, "balance"] => Self::AccountBalance(address(account)?),
+            ["v1", "events", key] => Self::EventsByKey(key.to_string()),
+            // Asset types have `<`, `>` and `,` in them, so they come percent-encoded
+            ["v1", "assets", asset_type, "top_holders"] => Self::TopHolders(
+                percent_decode_str(asset_type)
+                    .decode_utf8()
+                    .map_err(|_| ApiError::bad_request("Invalid asset type"))?
+                    .into_owned(),
+            ),
+            ["stream", "transactions"] => Self::StreamTransactions,
+            _ => return Err(ApiError::not_found()),
+        })
+    }
+
+    /// The route's pattern, for metrics
+    pub fn route(&self) -> &'static str {
+        match self {
+            Self::Transactions => "/v1/transactions",
+            Self::Transaction(_) => "/v1/transactions/:version",
+            Self::AccountTransactions(_) => "/v1/accounts/:address/transactions",
+            Self::AccountEvents(_) => "/v1/accounts/:address/events",
+            Self::AccountTokens(_) => "/v1/accounts/:address/tokens",
+            Self::AccountBalance(_) => "/v1/accounts/:address/balance",
+            Self::EventsByKey(_) => "/v1/events/:key",
+            Self::TopHolders(_) => "/v1/assets/:asset_type/top_holders",
+            Self::StreamTransactions => "/stream/transactions",
+        }
+    }
+
+    /// Hash of the endpoint's filters, which its cursors carry
+    fn filter_hash(&self) -> u64 {
+        cursor::filter_hash(&format!("{:?}", self))
+    }
+
+    /// The `cursor` parameter, if given
+    fn cursor(&self, params: &HashMap<String, String>) -> Result<Option<Cursor>, ApiError> {
+        params
+            .get("cursor")
+            .map(|token| {
+                Cursor::decode(token, self.filter_hash()).ok_or_else(|| {
+                    ApiError::bad_request("Invalid cursor, or a cursor of another query")
+                })
+            })
+            .transpose()
+    }
+
+    /// Runs the endpoint's query. Lists return the cursor of their next page: the position of their last row, or the
+    /// cursor they were given if empty, so clients can poll it for rows yet to land.
+    pub fn query(
+        &self,
+        conn: &PgPoolConnection,
+        params: &HashMap<String, String>,
+    ) -> Result<Page, ApiError> {
+        let limit = param::<i64>(params, "limit")?
+            .unwrap_or(DEFAULT_LIMIT)
+            .clamp(1, MAX_LIMIT);
+        let cursor = self.cursor(params)?;
+        let (body, next_cursor) = match self {
+            Self::Transactions => {
+                let start_version = match cursor {
+                    Some(cursor) => cursor.version,
+                    None => param(params, "start_version")?.unwrap_or(0),
+                };
+                let txns = queries::get_transactions_by_version_range(
+                    conn,
+                    start_version,
+                    start_version.saturating_add(limit as u64),
+                )?;
+                let next_version = match txns.last() {
+                    Some(txn) => to_u64(&txn.version)? + 1,
+                    None => start_version,
+                };
+                (to_json(txns), Some(Cursor::new(next_version, 0)))
+            }
+            Self::Transaction(version) => {
+                let (transaction, user_transaction, block_metadata_transaction, events, changes) =
+                    queries::get_transaction_with_details(conn, *version)?
+                        .ok_or_else(ApiError::not_found)?;
+                let body = json!({
+                    "transaction": transaction,
+                    "user_transaction": user_transaction,
+                    "block_metadata_transaction": block_metadata_transaction,
+                    "events": events,
+                    "write_set_changes": changes,
+                });
+                (body, None)
+            }
+            // Most recent first: positioned by the sequence number of the last transaction returned
+            Self::AccountTransactions(account) => {
+                let txns = queries::get_user_transactions_by_sender_before(
+                    conn,
+                    account,
+                    cursor.map(|cursor| cursor.index),
+                    limit,
+                )?;
+                let next_cursor = match txns.last() {
+                    Some(txn) => Some(Cursor::new(0, to_u64(&txn.sequence_number)?)),
+                    None => cursor,
+                };
+                (to_json(txns), next_cursor)
+            }
+            // Positioned by the event handle (its creation number, as the key's leading 8 bytes) and sequence number
+            // of the last event returned
+            Self::AccountEvents(account) => {
+                let after = cursor.map(|cursor| {
+                    (
+                        format!("{:016x}{}", cursor.version, account.to_hex()),
+                        cursor.index,
+                    )
+                });
+                let events = queries::get_events_by_account_after(
+                    conn,
+                    account,
+                    after
+                        .as_ref()
+                        .map(|(key, sequence_number)| (key.as_str(), *sequence_number)),
+                    limit,
+                )?;
+                let next_cursor = match events.last() {
+                    Some(event) => {
+                        let creation_number = event
+                            .key
+                            .get(..16)
+                            .and_then(|prefix| u64::from_str_radix(prefix, 16).ok())
+                            .ok_or_else(|| ApiError::internal("Malformed event key"))?;
+                        Some(Cursor::new(
+                            creation_number,
+                            to_u64(&event.sequence_number)?,
+                        ))
+                    }
+                    None => cursor,
+                };
+                (to_json(events), next_cursor)
+            }
+            Self::AccountTokens(account) => {
+                (to_json(queries::get_token_ownerships(conn, account)?), None)
+            }
+            Self::AccountBalance(account) => {
+                let asset_type: String = param(params, "asset_type")?
+                    .ok_or_else(|| ApiError::bad_request("asset_type is required"))?;
+                let version = param(params, "version")?.unwrap_or(u64::MAX);
+                let body = json!({
+                    "balance": queries::get_balance(conn, account, &asset_type, version)?,
+                });
+                (body, None)
+            }
+            Self::EventsByKey(key) => {
+                let start_sequence_number = match cursor {
+                    Some(cursor) => cursor.index,
+                    None => param(params, "start_sequence_number")?.unwrap_or(0),
+                };
+                let events = queries::get_events_by_key(conn, key, start_sequence_number, limit)?;
+                let next_sequence_number = match events.last() {
+                    Some(event) => to_u64(&event.sequence_number)? + 1,
+                    None => start_sequence_number,
+                };
+                (to_json(events), Some(Cursor::new(0, next_sequence_number)))
+            }
+            Self::StreamTransactions => unreachable!("Streams are served by `stream`"),
+            // Bounded by the number of holders tracked, so not paged
+            Self::TopHolders(asset_type) => (
+                to_json(queries::get_top_holders(conn, asset_type, limit)?),
+                None,
+            ),
+        };
+        Ok(Page {
+            body,
+            cursor: next_cursor.map(|cursor| cursor.encode(self.filter_hash())),
+        })
+    }
+}
+
+/// A response body, with the cursor of the next page of lists
+#[derive(Debug)]
+pub struct Page {
+    pub body: Value,
+    pub cursor: Option<String>,
+}
+
+fn to_u64(value: &BigDecimal) -> Result<u64, ApiError> {
+    bigdecimal_to_u64(value).map_err(|err| ApiError::internal(err.to_string()))
+}
+
+fn to_json<T: serde::Serialize>(rows: T) -> Value {
+    serde_json::to_value(rows).expect("Models should serialize to JSON")
+}
+
+/// The query parameter `name`, if given
+fn param<T: FromStr>(params: &HashMap<String, String>, name: &str) -> Result<Option<T>, ApiError> {
+    params
+        .get(name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| ApiError::bad_request(format!("Invalid {}: {}", name, value)))
+        })
+        .transpose()
+}
+
+fn json_response(status: StatusCode, body: &Value) -> Response<Body> {
+    let mut resp = Response::new(Body::from(body.to_string()));
+    *resp.status_mut() = status;
+    resp.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    resp
+}
+
+/// The key a request presents, from `x-api-key` or a bearer token
+fn api_key(req: &Request<Body>) -> Option<&str> {
+    let headers = req.headers();
+    if let Some(key) = headers.get("x-api-key") {
+        return key.to_str().ok();
+    }
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+pub struct ReadApi {
+    pool: PgDbPool,
+    keys: ApiKeys,
+    rate_limiter: RateLimiter,
+    committed_versions: Option<Arc<CommittedVersions>>,
+}
+
+impl ReadApi {
+    pub fn new(pool: PgDbPool, config: &ReadApiConfig) -> Result<Self> {
+        Ok(Self {
+            keys: ApiKeys::new(
+                &config.api_keys,
+                Some(pool.clone()),
+                config.default_requests_per_minute,
+            )?,
+            pool,
+            rate_limiter: RateLimiter::default(),
+            committed_versions: config.committed_versions.clone(),
+        })
+    }
+
+    /// Starts streaming transactions into the response (see `stream`)
+    fn stream_transactions(
+        &self,
+        req: &Request<Body>,
+        params: &HashMap<String, String>,
+    ) -> Response<Body> {
+        let last_event_id = req
+            .headers()
+            .get("last-event-id")
+            .and_then(|id| id.to_str().ok());
+        let start_version = match param(params, "start_version")
+            .and_then(|start_version| stream::start_version(start_version, last_event_id))
+        {
+            Ok(start_version) => start_version,
+            Err(error) => return error.into_response(),
+        };
+        let (sender, resp) = stream::event_stream_response();
+        tokio::spawn(stream::stream_transactions(
+            self.pool.clone(),
+            self.committed_versions
+                .as_ref()
+                .map(|committed_versions| committed_versions.subscribe()),
+            start_version,
+            sender,
+        ));
+        resp
+    }
+
+    /// Authenticates and rate limits the request, then runs its endpoint's query
+    async fn respond(
+        self: Arc<Self>,
+        req: Request<Body>,
+    ) -> (&'static str, Option<Client>, Response<Body>) {
+        if req.method() != Method::GET {
+            let error = ApiError::new(StatusCode::METHOD_NOT_ALLOWED, "Only GET is supported");
+            return (OTHER_ROUTE, None, error.into_response());
+        }
+        if req.uri().path() == SPEC_PATH {
+            return (
+                SPEC_PATH,
+                None,
+                json_response(StatusCode::OK, openapi_spec()),
+            );
+        }
+        // Refreshing the keys from the table blocks
+        let key = api_key(&req).map(str::to_string);
+        let api = self.clone();
+        let client =
+            tokio::task::spawn_blocking(move || key.and_then(|key| api.keys.authenticate(&key)))
+                .await
+                .expect("Authentication panicked");
+        let client = match client {
+            Some(client) => client,
+            None => {
+                let error = ApiError::new(StatusCode::UNAUTHORIZED, "A valid API key is required");
+                return (OTHER_ROUTE, None, error.into_response());
+            }
+        };
+        if let Err(retry_after) = self.rate_limiter.check(&client) {
+            let mut resp =
+                ApiError::new(StatusCode::TOO_MANY_REQUESTS, "Rate limit exceeded").into_response();
+            resp.headers_mut().insert(
+                header::RETRY_AFTER,
+                HeaderValue::from(retry_after.as_secs().max(1)),
+            );
+            return (OTHER_ROUTE, Some(client), resp);
+        }
+        let endpoint = match Endpoint::parse(req.uri().path()) {
+            Ok(endpoint) => endpoint,
+            Err(error) => return (OTHER_ROUTE, Some(client), error.into_response()),
+        };
+        let params: HashMap<String, String> =
+            url::form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+                .into_owned()
+                .collect();
+        if endpoint == Endpoint::StreamTransactions {
+            let resp = self.stream_transactions(&req, &params);
+            return (endpoint.route(), Some(client), resp);
+        }
+
+        let api = self.clone();
+        let query_endpoint = endpoint.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let conn = api.pool.get().map_err(|err| {
+                aptos_logger::error!(error = format!("{:?}", err), "No connection for read API");
+                ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "No database connection")
+            })?;
+            query_endpoint.query(&conn, &params)
+        })
+        .await
+        .expect("Read API query panicked");
+        let resp = match result {
+            Ok(page) => {
+                let mut resp = json_response(StatusCode::OK, &page.body);
+                if let Some(cursor) = page.cursor {
+                    resp.headers_mut().insert(
+                        CURSOR_HEADER,
+                        HeaderValue::from_str(&cursor).expect("Cursors are base64"),
+                    );
+                }
+                resp
+            }
+            Err(error) => error.into_response(),
+        };
+        (endpoint.route(), Some(client), resp)
+    }
+
+    async fn serve_request(self: Arc<Self>, req: Request<Body>) -> Response<Body> {
+        let start = Instant::now();
+        let (route, client, resp) = self.respond(req).await;
+        READ_API_REQUESTS
+            .with_label_values(&[
+                route,
+                resp.status().as_str(),
+                client
+                    .as_ref()
+                    .map_or("anonymous", |client| client.name.as_str()),
+            ])
+            .inc();
+        READ_API_REQUEST_SECONDS
+            .with_label_values(&[route])
+            .observe(start.elapsed().as_secs_f64());
+        resp
+    }
+}
+
+/// Serves the read API on `config.address` (and over gRPC on `config.grpc_address`) from `pool`, until a server fails
+pub async fn serve(pool: PgDbPool, config: ReadApiConfig) -> Result<()> {
+    let api = Arc::new(ReadApi::new(pool.clone(), &config)?);
+    let make_service = make_service_fn(move |_conn| {
+        let api = api.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let api = api.clone();
+                async move { Ok::<_, Infallible>(api.serve_request(req).await) }
+            }))
+        }
+    });
+    aptos_logger::info!(address = config.address.to_string(), "Serving the read API");
+    let http = async {
+        Server::bind(&config.address).serve(make_service).await?;
+        Ok::<_, anyhow::Error>(())
+    };
+    match config.grpc_address {
+        Some(grpc_address) => {
+            tokio::try_join!(http, grpc::serve_grpc(pool, grpc_address))?;
+            Ok(())
+        }
+        None => http.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoints() {
+        assert_eq!(
+            Endpoint::parse("/v1/transactions").unwrap(),
+            Endpoint::Transactions
+        );
+        assert_eq!(
+            Endpoint::parse("/v1/transactions/42").unwrap(),
+            Endpoint::Transaction(42)
+        );
+        assert_eq!(
+            Endpoint::parse("/v1/accounts/0x1/events").unwrap(),
+            Endpoint::AccountEvents(AccountAddress::from_hex_literal("0x1").unwrap())
+        );
+        assert_eq!(
+            Endpoint::parse(
+                "/v1/assets/0x1::coin::Wrapped%3C0x1::aptos_coin::AptosCoin%3E/top_holders"
+            )
+            .unwrap(),
+            Endpoint::TopHolders("0x1::coin::Wrapped<0x1::aptos_coin::AptosCoin>".to_string())
+        );
+        assert_eq!(
+            Endpoint::parse("/v1/accounts/0xzz/events")
+                .unwrap_err()
+                .status,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            Endpoint::parse("/v1/transactions/42/events")
+                .unwrap_err()
+                .status,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            Endpoint::parse("/v1/accounts/0x1/balance").unwrap().route(),
+            "/v1/accounts/:address/balance"
+        );
+
+        // Cursors only page through the query they came from
+        let events = Endpoint::parse("/v1/accounts/0x1/events").unwrap();
+        let token = Cursor::new(3, 5).encode(events.filter_hash());
+        let params = HashMap::from([("cursor".to_string(), token)]);
+        assert_eq!(events.cursor(&params).unwrap(), Some(Cursor::new(3, 5)));
+        let other_account = Endpoint::parse("/v1/accounts/0x2/events").unwrap();
+        assert!(other_account.cursor(&params).is_err());
+
+        let params = HashMap::from([("limit".to_string(), "ten".to_string())]);
+        assert!(param::<i64>(&params, "limit").is_err());
+        assert_eq!(param::<i64>(&params, "start_version").unwrap(), None);
+    }
+}
@@ -0,0 +1,161 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+// @generated
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct Transaction {
+    #[prost(uint64, tag = "1")]
+    pub version: u64,
+    #[prost(string, tag = "2")]
+    pub hash: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub r#type: ::prost::alloc::string::String,
+    /// JSON of the payload
+    #[prost(string, tag = "4")]
+    pub payload: ::prost::alloc::string::String,
+    #[prost(string, tag = "5")]
+    pub state_root_hash: ::prost::alloc::string::String,
+    #[prost(string, tag = "6")]
+    pub event_root_hash: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "7")]
+    pub gas_used: u64,
+    #[prost(bool, tag = "8")]
+    pub success: bool,
+    #[prost(string, tag = "9")]
+    pub vm_status: ::prost::alloc::string::String,
+    #[prost(string, tag = "10")]
+    pub accumulator_root_hash: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "11")]
+    pub num_events: u64,
+    #[prost(uint64, tag = "12")]
+    pub num_write_set_changes: u64,
+    /// Microseconds since the epoch, of the transaction's block
+    #[prost(int64, tag = "13")]
+    pub timestamp_usecs: i64,
+}
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct Event {
+    #[prost(string, tag = "1")]
+    pub transaction_hash: ::prost::alloc::string::String,
+    /// -1 for events indexed before their index was recorded, until their versions are re-processed
+    #[prost(int64, tag = "2")]
+    pub event_index: i64,
+    #[prost(string, tag = "3")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "4")]
+    pub sequence_number: u64,
+    #[prost(string, tag = "5")]
+    pub r#type: ::prost::alloc::string::String,
+    /// JSON of the event's data
+    #[prost(string, tag = "6")]
+    pub data: ::prost::alloc::string::String,
+    /// -1 for events indexed before their timestamp was recorded, until their versions are re-processed
+    #[prost(int64, tag = "7")]
+    pub transaction_timestamp_usecs: i64,
+}
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct Activity {
+    #[prost(uint64, tag = "1")]
+    pub transaction_version: u64,
+    #[prost(int64, tag = "2")]
+    pub event_index: i64,
+    #[prost(string, tag = "3")]
+    pub storage_id: ::prost::alloc::string::String,
+    /// Empty if unknown
+    #[prost(string, tag = "4")]
+    pub owner_address: ::prost::alloc::string::String,
+    #[prost(string, tag = "5")]
+    pub asset_type: ::prost::alloc::string::String,
+    #[prost(string, tag = "6")]
+    pub r#type: ::prost::alloc::string::String,
+    /// Decimal, empty for freezes
+    #[prost(string, tag = "7")]
+    pub amount: ::prost::alloc::string::String,
+    #[prost(bool, tag = "8")]
+    pub is_frozen: bool,
+    #[prost(int64, tag = "9")]
+    pub transaction_timestamp_usecs: i64,
+}
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct GetTransactionsRequest {
+    #[prost(uint64, tag = "1")]
+    pub start_version: u64,
+    #[prost(uint32, tag = "2")]
+    pub limit: u32,
+}
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct GetTransactionsResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub transactions: ::prost::alloc::vec::Vec<Transaction>,
+}
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct GetEventsByTypeRequest {
+    /// ex: 0x1::coin::DepositEvent
+    #[prost(string, tag = "1")]
+    pub r#type: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "2")]
+    pub limit: u32,
+}
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct GetEventsByTypeResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub events: ::prost::alloc::vec::Vec<Event>,
+}
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct GetAccountActivitiesRequest {
+    #[prost(string, tag = "1")]
+    pub address: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "2")]
+    pub limit: u32,
+    /// Only activities of versions below it, to page through them. 0 for the latest.
+    #[prost(uint64, tag = "3")]
+    pub before_version: u64,
+}
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct GetAccountActivitiesResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub activities: ::prost::alloc::vec::Vec<Activity>,
+}
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct SubscribeEventsRequest {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub start_sequence_number: u64,
+}
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct RawTransaction {
+    #[prost(uint64, tag = "1")]
+    pub version: u64,
+    /// How data is encoded, ex: json+gzip (the node API's JSON of the transaction, gzipped)
+    #[prost(string, tag = "2")]
+    pub encoding: ::prost::alloc::string::String,
+    #[prost(bytes = "vec", tag = "3")]
+    pub data: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct GetRawTransactionsRequest {
+    #[prost(uint64, tag = "1")]
+    pub start_version: u64,
+    #[prost(uint32, tag = "2")]
+    pub limit: u32,
+}
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct GetRawTransactionsResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub transactions: ::prost::alloc::vec::Vec<RawTransaction>,
+}
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct GetArchiveInfoRequest {}
+#[derive(Clone, PartialEq, Eq, ::prost::Message)]
+pub struct ArchiveInfo {
+    #[prost(uint32, tag = "1")]
+    pub chain_id: u32,
+    /// Unset if no version is archived
+    #[prost(uint64, optional, tag = "2")]
+    pub oldest_version: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "3")]
+    pub latest_version: ::core::option::Option<u64>,
+}
+include!("aptos.indexer.read.v1.tonic.rs");
+// @@protoc_insertion_point(module)
@@ -0,0 +1,507 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+// @generated
+/// Generated client implementations.
+pub mod indexer_read_client {
+    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
+    use tonic::codegen::*;
+    /// The read API over gRPC, for internal services. Lists take a limit, at most the read API's (0 for its default).
+    #[derive(Debug, Clone)]
+    pub struct IndexerReadClient<T> {
+        inner: tonic::client::Grpc<T>,
+    }
+    impl IndexerReadClient<tonic::transport::Channel> {
+        /// Attempt to create a new client by connecting to a given endpoint.
+        pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+        where
+            D: std::convert::TryInto<tonic::transport::Endpoint>,
+            D::Error: Into<StdError>,
+        {
+            let conn = tonic::transport::Endpoint::new(dst)?.connect().await?;
+            Ok(Self::new(conn))
+        }
+    }
+    impl<T> IndexerReadClient<T>
+    where
+        T: tonic::client::GrpcService<tonic::body::BoxBody>,
+        T::Error: Into<StdError>,
+        T::ResponseBody: Body<Data = Bytes> + Send + 'static,
+        <T::ResponseBody as Body>::Error: Into<StdError> + Send,
+    {
+        pub fn new(inner: T) -> Self {
+            let inner = tonic::client::Grpc::new(inner);
+            Self { inner }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> IndexerReadClient<InterceptedService<T, F>>
+        where
+            F: tonic::service::Interceptor,
+            T::ResponseBody: Default,
+            T: tonic::codegen::Service<
+                http::Request<tonic::body::BoxBody>,
+                Response = http::Response<
+                    <T as tonic::client::GrpcService<tonic::body::BoxBody>>::ResponseBody,
+                >,
+            >,
+            <T as tonic::codegen::Service<http::Request<tonic::body::BoxBody>>>::Error:
+                Into<StdError> + Send + Sync,
+        {
+            IndexerReadClient::new(InterceptedService::new(inner, interceptor))
+        }
+        /// Compress requests with `gzip`.
+        ///
+        /// This requires the server to support it otherwise it might respond with an
+        /// error.
+        #[must_use]
+        pub fn send_gzip(mut self) -> Self {
+            self.inner = self.inner.send_gzip();
+            self
+        }
+        /// Enable decompressing responses with `gzip`.
+        #[must_use]
+        pub fn accept_gzip(mut self) -> Self {
+            self.inner = self.inner.accept_gzip();
+            self
+        }
+        /// Transactions from a version, in version order
+        pub async fn get_transactions(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetTransactionsRequest>,
+        ) -> Result<tonic::Response<super::GetTransactionsResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/aptos.indexer.read.v1.IndexerRead/GetTransactions",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        /// Events of a type, most recent first
+        pub async fn get_events_by_type(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetEventsByTypeRequest>,
+        ) -> Result<tonic::Response<super::GetEventsByTypeResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/aptos.indexer.read.v1.IndexerRead/GetEventsByType",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        /// Fungible asset activities (deposits, withdrawals, freezes) of an account, most recent first
+        pub async fn get_account_activities(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetAccountActivitiesRequest>,
+        ) -> Result<tonic::Response<super::GetAccountActivitiesResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/aptos.indexer.read.v1.IndexerRead/GetAccountActivities",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        /// Events of an event handle from a sequence number, then new ones as they're indexed
+        pub async fn subscribe_events(
+            &mut self,
+            request: impl tonic::IntoRequest<super::SubscribeEventsRequest>,
+        ) -> Result<tonic::Response<tonic::codec::Streaming<super::Event>>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/aptos.indexer.read.v1.IndexerRead/SubscribeEvents",
+            );
+            self.inner
+                .server_streaming(request.into_request(), path, codec)
+                .await
+        }
+        /// Transactions archived in raw_transactions from a version, in version order, up to the first version missing
+        pub async fn get_raw_transactions(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetRawTransactionsRequest>,
+        ) -> Result<tonic::Response<super::GetRawTransactionsResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/aptos.indexer.read.v1.IndexerRead/GetRawTransactions",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        /// The chain id and the archived version range
+        pub async fn get_archive_info(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetArchiveInfoRequest>,
+        ) -> Result<tonic::Response<super::ArchiveInfo>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/aptos.indexer.read.v1.IndexerRead/GetArchiveInfo",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+    }
+}
+/// Generated server implementations.
+pub mod indexer_read_server {
+    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
+    use tonic::codegen::*;
+    ///Generated trait containing gRPC methods that should be implemented for use with IndexerReadServer.
+    #[async_trait]
+    pub trait IndexerRead: Send + Sync + 'static {
+        /// Transactions from a version, in version order
+        async fn get_transactions(
+            &self,
+            request: tonic::Request<super::GetTransactionsRequest>,
+        ) -> Result<tonic::Response<super::GetTransactionsResponse>, tonic::Status>;
+        /// Events of a type, most recent first
+        async fn get_events_by_type(
+            &self,
+            request: tonic::Request<super::GetEventsByTypeRequest>,
+        ) -> Result<tonic::Response<super::GetEventsByTypeResponse>, tonic::Status>;
+        /// Fungible asset activities (deposits, withdrawals, freezes) of an account, most recent first
+        async fn get_account_activities(
+            &self,
+            request: tonic::Request<super::GetAccountActivitiesRequest>,
+        ) -> Result<tonic::Response<super::GetAccountActivitiesResponse>, tonic::Status>;
+        ///Server streaming response type for the SubscribeEvents method.
+        type SubscribeEventsStream: futures_core::Stream<Item = Result<super::Event, tonic::Status>>
+            + Send
+            + 'static;
+        /// Events of an event handle from a sequence number, then new ones as they're indexed
+        async fn subscribe_events(
+            &self,
+            request: tonic::Request<super::SubscribeEventsRequest>,
+        ) -> Result<tonic::Response<Self::SubscribeEventsStream>, tonic::Status>;
+        /// Transactions archived in raw_transactions from a version, in version order, up to the first version missing
+        async fn get_raw_transactions(
+            &self,
+            request: tonic::Request<super::GetRawTransactionsRequest>,
+        ) -> Result<tonic::Response<super::GetRawTransactionsResponse>, tonic::Status>;
+        /// The chain id and the archived version range
+        async fn get_archive_info(
+            &self,
+            request: tonic::Request<super::GetArchiveInfoRequest>,
+        ) -> Result<tonic::Response<super::ArchiveInfo>, tonic::Status>;
+    }
+    /// The read API over gRPC, for internal services. Lists take a limit, at most the read API's (0 for its default).
+    #[derive(Debug)]
+    pub struct IndexerReadServer<T: IndexerRead> {
+        inner: _Inner<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+    }
+    struct _Inner<T>(Arc<T>);
+    impl<T: IndexerRead> IndexerReadServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            let inner = _Inner(inner);
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+            }
+        }
+        pub fn with_interceptor<F>(inner: T, interceptor: F) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with `gzip`.
+        #[must_use]
+        pub fn accept_gzip(mut self) -> Self {
+            self.accept_compression_encodings.enable_gzip();
+            self
+        }
+        /// Compress responses with `gzip`, if the client supports it.
+        #[must_use]
+        pub fn send_gzip(mut self) -> Self {
+            self.send_compression_encodings.enable_gzip();
+            self
+        }
+    }
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for IndexerReadServer<T>
+    where
+        T: IndexerRead,
+        B: Body + Send + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            let inner = self.inner.clone();
+            match req.uri().path() {
+                "/aptos.indexer.read.v1.IndexerRead/GetTransactions" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetTransactionsSvc<T: IndexerRead>(pub Arc<T>);
+                    impl<T: IndexerRead> tonic::server::UnaryService<super::GetTransactionsRequest>
+                        for GetTransactionsSvc<T>
+                    {
+                        type Response = super::GetTransactionsResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetTransactionsRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).get_transactions(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetTransactionsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/aptos.indexer.read.v1.IndexerRead/GetEventsByType" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetEventsByTypeSvc<T: IndexerRead>(pub Arc<T>);
+                    impl<T: IndexerRead> tonic::server::UnaryService<super::GetEventsByTypeRequest>
+                        for GetEventsByTypeSvc<T>
+                    {
+                        type Response = super::GetEventsByTypeResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetEventsByTypeRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).get_events_by_type(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetEventsByTypeSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/aptos.indexer.read.v1.IndexerRead/GetAccountActivities" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetAccountActivitiesSvc<T: IndexerRead>(pub Arc<T>);
+                    impl<T: IndexerRead>
+                        tonic::server::UnaryService<super::GetAccountActivitiesRequest>
+                        for GetAccountActivitiesSvc<T>
+                    {
+                        type Response = super::GetAccountActivitiesResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetAccountActivitiesRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).get_account_activities(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetAccountActivitiesSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/aptos.indexer.read.v1.IndexerRead/SubscribeEvents" => {
+                    #[allow(non_camel_case_types)]
+                    struct SubscribeEventsSvc<T: IndexerRead>(pub Arc<T>);
+                    impl<T: IndexerRead>
+                        tonic::server::ServerStreamingService<super::SubscribeEventsRequest>
+                        for SubscribeEventsSvc<T>
+                    {
+                        type Response = super::Event;
+                        type ResponseStream = T::SubscribeEventsStream;
+                        type Future =
+                            BoxFuture<tonic::Response<Self::ResponseStream>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SubscribeEventsRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).subscribe_events(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = SubscribeEventsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/aptos.indexer.read.v1.IndexerRead/GetRawTransactions" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetRawTransactionsSvc<T: IndexerRead>(pub Arc<T>);
+                    impl<T: IndexerRead> tonic::server::UnaryService<super::GetRawTransactionsRequest>
+                        for GetRawTransactionsSvc<T>
+                    {
+                        type Response = super::GetRawTransactionsResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetRawTransactionsRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).get_raw_transactions(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetRawTransactionsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/aptos.indexer.read.v1.IndexerRead/GetArchiveInfo" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetArchiveInfoSvc<T: IndexerRead>(pub Arc<T>);
+                    impl<T: IndexerRead> tonic::server::UnaryService<super::GetArchiveInfoRequest>
+                        for GetArchiveInfoSvc<T>
+                    {
+                        type Response = super::ArchiveInfo;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetArchiveInfoRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).get_archive_info(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetArchiveInfoSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => Box::pin(async move {
+                    Ok(http::Response::builder()
+                        .status(200)
+                        .header("grpc-status", "12")
+                        .header("content-type", "application/grpc")
+                        .body(empty_body())
+                        .unwrap())
+                }),
+            }
+        }
+    }
+    impl<T: IndexerRead> Clone for IndexerReadServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self {
+                inner,
+                accept_compression_encodings: self.accept_compression_encodings,
+                send_compression_encodings: self.send_compression_encodings,
+            }
+        }
+    }
+    impl<T: IndexerRead> Clone for _Inner<T> {
+        fn clone(&self) -> Self {
+            Self(self.0.clone())
+        }
+    }
+    impl<T: std::fmt::Debug> std::fmt::Debug for _Inner<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self.0)
+        }
+    }
+    impl<T: IndexerRead> tonic::transport::NamedService for IndexerReadServer<T> {
+        const NAME: &'static str = "aptos.indexer.read.v1.IndexerRead";
+    }
+}
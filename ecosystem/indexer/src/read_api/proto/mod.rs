@@ -0,0 +1,9 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Generated from `proto/aptos/indexer/read/v1/read.proto` with `buf generate`
+
+#[path = "aptos.indexer.read.v1.rs"]
+mod read;
+
+pub use read::*;
@@ -0,0 +1,380 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! OpenAPI document of the read API, served at `SPEC_PATH` so client SDKs can be generated from it. Operations are
+//! described in `OPERATIONS`, next to the routing in `Endpoint::parse` (a test checks each one routes to the endpoint
+//! it describes), and response bodies reuse the models' JSON schemas (`models::json_schema`) as components. Those are
+//! plain JSON schemas (ex: `{"type": "null"}` for optional fields), hence OpenAPI 3.1.
+
+use super::{CURSOR_HEADER, DEFAULT_LIMIT, MAX_LIMIT};
+use crate::models::json_schema::schema_json;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+
+pub const SPEC_PATH: &str = "/spec";
+
+/// A parameter of an operation
+pub struct Param {
+    pub name: &'static str,
+    /// `path` or `query`
+    pub location: &'static str,
+    pub description: &'static str,
+    /// JSON schema type
+    pub typ: &'static str,
+}
+
+impl Param {
+    const fn path(name: &'static str, description: &'static str) -> Self {
+        Self {
+            name,
+            location: "path",
+            description,
+            typ: "string",
+        }
+    }
+
+    const fn query(name: &'static str, description: &'static str, typ: &'static str) -> Self {
+        Self {
+            name,
+            location: "query",
+            description,
+            typ,
+        }
+    }
+}
+
+/// What an operation responds with
+pub enum ResponseBody {
+    /// A list of the model
+    List(&'static str),
+    /// A single model (by name), or other schema
+    Object(fn() -> Value),
+    /// Server-sent events, each with a model as data
+    EventStream(&'static str),
+}
+
+pub struct Operation {
+    /// OpenAPI path, ex: `/v1/transactions/{version}`
+    pub path: &'static str,
+    pub operation_id: &'static str,
+    pub summary: &'static str,
+    pub params: &'static [Param],
+    pub response: ResponseBody,
+    /// Whether it's paged with cursors
+    pub paged: bool,
+}
+
+const LIMIT: Param = Param::query("limit", "Number of rows to return", "integer");
+const CURSOR: Param = Param::query(
+    "cursor",
+    "Cursor of the page to read, from the previous page's cursor header",
+    "string",
+);
+const ADDRESS: Param = Param::path("address", "Account address, ex: 0x1");
+
+pub const OPERATIONS: &[Operation] = &[
+    Operation {
+        path: "/v1/transactions",
+        operation_id: "get_transactions",
+        summary: "Transactions from a version, in version order",
+        params: &[
+            Param::query("start_version", "First version to return", "integer"),
+            LIMIT,
+            CURSOR,
+        ],
+        response: ResponseBody::List("Transaction"),
+        paged: true,
+    },
+    Operation {
+        path: "/v1/transactions/{version}",
+        operation_id: "get_transaction",
+        summary: "A transaction with its events and write set changes",
+        params: &[Param::path("version", "Version of the transaction")],
+        response: ResponseBody::Object(transaction_with_details),
+        paged: false,
+    },
+    Operation {
+        path: "/v1/accounts/{address}/transactions",
+        operation_id: "get_account_transactions",
+        summary: "User transactions sent by an account, latest first",
+        params: &[ADDRESS, LIMIT, CURSOR],
+        response: ResponseBody::List("UserTransaction"),
+        paged: true,
+    },
+    Operation {
+        path: "/v1/accounts/{address}/events",
+        operation_id: "get_account_events",
+        summary: "Events of an account's event handles, latest first within each handle",
+        params: &[ADDRESS, LIMIT, CURSOR],
+        response: ResponseBody::List("Event"),
+        paged: true,
+    },
+    Operation {
+        path: "/v1/accounts/{address}/tokens",
+        operation_id: "get_account_tokens",
+        summary: "Tokens an account holds",
+        params: &[ADDRESS],
+        response: ResponseBody::List("Ownership"),
+        paged: false,
+    },
+    Operation {
+        path: "/v1/accounts/{address}/balance",
+        operation_id: "get_account_balance",
+        summary: "Balance of a fungible asset held by an account, as of a version",
+        params: &[
+            ADDRESS,
+            Param::query(
+                "asset_type",
+                "Asset type, ex: 0x1::aptos_coin::AptosCoin",
+                "string",
+            ),
+            Param::query(
+                "version",
+                "Version to read the balance as of, the latest by default",
+                "integer",
+            ),
+        ],
+        response: ResponseBody::Object(balance),
+        paged: false,
+    },
+    Operation {
+        path: "/v1/events/{key}",
+        operation_id: "get_events_by_key",
+        summary: "Events of an event handle, in sequence number order",
+        params: &[
+            Param::path("key", "Event key"),
+            Param::query(
+                "start_sequence_number",
+                "First sequence number to return",
+                "integer",
+            ),
+            LIMIT,
+            CURSOR,
+        ],
+        response: ResponseBody::List("Event"),
+        paged: true,
+    },
+    Operation {
+        path: "/v1/assets/{asset_type}/top_holders",
+        operation_id: "get_top_holders",
+        summary: "Largest holders of a coin or fungible asset",
+        params: &[
+            Param::path("asset_type", "Asset type, percent-encoded"),
+            LIMIT,
+        ],
+        response: ResponseBody::List("TopHolder"),
+        paged: false,
+    },
+    Operation {
+        path: "/stream/transactions",
+        operation_id: "stream_transactions",
+        summary:
+            "Server-sent events of transactions from a version, then of new ones as they're indexed",
+        params: &[Param::query(
+            "start_version",
+            "First version to stream, unless resuming after a Last-Event-ID",
+            "integer",
+        )],
+        response: ResponseBody::EventStream("Transaction"),
+        paged: false,
+    },
+];
+
+fn model_ref(model: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", model) })
+}
+
+fn transaction_with_details() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "transaction": model_ref("Transaction"),
+            "user_transaction": { "anyOf": [model_ref("UserTransaction"), { "type": "null" }] },
+            "block_metadata_transaction": {
+                "anyOf": [model_ref("BlockMetadataTransaction"), { "type": "null" }],
+            },
+            "events": { "type": "array", "items": model_ref("Event") },
+            "write_set_changes": { "type": "array", "items": model_ref("WriteSetChange") },
+        },
+        "required": [
+            "transaction",
+            "user_transaction",
+            "block_metadata_transaction",
+            "events",
+            "write_set_changes",
+        ],
+    })
+}
+
+fn balance() -> Value {
+    json!({
+        "type": "object",
+        "properties": { "balance": { "type": "string", "pattern": "^[0-9]+$" } },
+        "required": ["balance"],
+    })
+}
+
+fn error() -> Value {
+    json!({
+        "description": "An error",
+        "content": {
+            "application/json": {
+                "schema": {
+                    "type": "object",
+                    "properties": { "error": { "type": "string" } },
+                    "required": ["error"],
+                },
+            },
+        },
+    })
+}
+
+impl Operation {
+    fn to_json(&self) -> Value {
+        let params: Vec<Value> = self
+            .params
+            .iter()
+            .map(|param| {
+                let mut schema = json!({ "type": param.typ });
+                if param.name == LIMIT.name {
+                    schema["minimum"] = json!(1);
+                    schema["maximum"] = json!(MAX_LIMIT);
+                    schema["default"] = json!(DEFAULT_LIMIT);
+                }
+                json!({
+                    "name": param.name,
+                    "in": param.location,
+                    "description": param.description,
+                    "required": param.location == "path",
+                    "schema": schema,
+                })
+            })
+            .collect();
+        let content = match &self.response {
+            ResponseBody::List(model) => json!({
+                "application/json": {
+                    "schema": { "type": "array", "items": model_ref(model) },
+                },
+            }),
+            ResponseBody::Object(schema) => json!({ "application/json": { "schema": schema() } }),
+            ResponseBody::EventStream(model) => json!({
+                "text/event-stream": {
+                    "schema": {
+                        "type": "string",
+                        "description": format!(
+                            "Events whose id is the version, and data a {} as JSON",
+                            model
+                        ),
+                    },
+                },
+            }),
+        };
+        let mut ok = json!({ "description": "OK", "content": content });
+        if self.paged {
+            ok["headers"] = json!({
+                CURSOR_HEADER: {
+                    "description": "Cursor of the next page, to pass as `cursor`",
+                    "schema": { "type": "string" },
+                },
+            });
+        }
+        json!({
+            "get": {
+                "operationId": self.operation_id,
+                "summary": self.summary,
+                "parameters": params,
+                "responses": {
+                    "200": ok,
+                    "400": error(),
+                    "401": error(),
+                    "404": error(),
+                    "429": error(),
+                },
+            },
+        })
+    }
+}
+
+static SPEC: Lazy<Value> = Lazy::new(|| {
+    let paths: serde_json::Map<String, Value> = OPERATIONS
+        .iter()
+        .map(|operation| (operation.path.to_string(), operation.to_json()))
+        .collect();
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "Aptos indexer read API",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": schema_json()["definitions"],
+            "securitySchemes": {
+                "api_key": { "type": "apiKey", "in": "header", "name": "x-api-key" },
+                "bearer": { "type": "http", "scheme": "bearer" },
+            },
+        },
+        "security": [{ "api_key": [] }, { "bearer": [] }],
+    })
+});
+
+/// The OpenAPI document, built once
+pub fn openapi_spec() -> &'static Value {
+    &SPEC
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_api::Endpoint;
+
+    #[test]
+    fn test_operations_match_routes() {
+        let spec = openapi_spec();
+        for operation in OPERATIONS {
+            // Fill the path parameters in with valid values
+            let path = operation
+                .path
+                .replace("{version}", "1")
+                .replace("{address}", "0x1")
+                .replace("{key}", "0x0")
+                .replace("{asset_type}", "0x1::aptos_coin::AptosCoin");
+            let endpoint = Endpoint::parse(&path).unwrap();
+            assert_eq!(
+                endpoint
+                    .route()
+                    .replace(":version", "{version}")
+                    .replace(":address", "{address}")
+                    .replace(":key", "{key}")
+                    .replace(":asset_type", "{asset_type}"),
+                operation.path
+            );
+
+            if let ResponseBody::List(model) | ResponseBody::EventStream(model) = operation.response
+            {
+                assert!(
+                    spec["components"]["schemas"][model].is_object(),
+                    "No schema for {}",
+                    model
+                );
+            }
+            assert_eq!(
+                operation.paged,
+                operation
+                    .params
+                    .iter()
+                    .any(|param| param.name == CURSOR.name)
+            );
+        }
+        for model in [
+            "Transaction",
+            "UserTransaction",
+            "BlockMetadataTransaction",
+            "Event",
+            "WriteSetChange",
+        ] {
+            assert!(spec["components"]["schemas"][model].is_object());
+        }
+        assert_eq!(spec["paths"].as_object().unwrap().len(), OPERATIONS.len());
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Server-sent events of new transactions (`/stream/transactions?start_version=`), a streaming option browsers
+//! support natively (`EventSource`). A stream replays the indexed transactions from `start_version` (or the one after
+//! the `Last-Event-ID` a reconnecting client sends), then pushes new ones as they're committed. Each transaction is an
+//! event `transaction` whose id is its version and data its JSON, as the list endpoints serve it.
+//!
+//! When the API is served by the indexing process (`run --api-address`), `CommittedVersions` (a commit hook) wakes
+//! streams up as soon as the `default_processor` commits a batch. Served on its own (`serve`), streams poll the
+//! database every `POLL_INTERVAL` once caught up instead.
+
+use super::ApiError;
+use crate::{
+    database::PgDbPool, indexer::commit_hooks::CommitHook,
+    processors::default_processor::NAME as DEFAULT_PROCESSOR_NAME, queries,
+    util::bigdecimal_to_u64,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use hyper::{body::Sender, Body};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Transactions read from the database at a time
+const CHUNK_SIZE: u64 = 100;
+/// How often caught up streams look for new transactions, without `CommittedVersions`
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a stream stays silent at most: proxies drop idle connections
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Latest version the `default_processor` committed, for streams to wait on
+#[derive(Debug)]
+pub struct CommittedVersions {
+    sender: watch::Sender<Option<u64>>,
+}
+
+impl Default for CommittedVersions {
+    fn default() -> Self {
+        Self {
+            sender: watch::channel(None).0,
+        }
+    }
+}
+
+impl CommittedVersions {
+    pub fn subscribe(&self) -> watch::Receiver<Option<u64>> {
+        self.sender.subscribe()
+    }
+}
+
+#[async_trait]
+impl CommitHook for CommittedVersions {
+    fn name(&self) -> &str {
+        "read_api_streams"
+    }
+
+    async fn on_batch_committed(
+        &self,
+        _start_version: u64,
+        end_version: u64,
+        processors: &[String],
+    ) -> Result<()> {
+        if processors.iter().any(|name| name == DEFAULT_PROCESSOR_NAME) {
+            self.sender.send_if_modified(|latest| {
+                let modified = latest.map_or(true, |latest| latest < end_version);
+                if modified {
+                    *latest = Some(end_version);
+                }
+                modified
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The first version to stream: the one after `last_event_id` if the client is resuming, or `start_version`
+pub fn start_version(
+    start_version: Option<u64>,
+    last_event_id: Option<&str>,
+) -> Result<u64, ApiError> {
+    match last_event_id {
+        Some(id) => id
+            .trim()
+            .parse::<u64>()
+            .map(|version| version + 1)
+            .map_err(|_| ApiError::bad_request(format!("Invalid Last-Event-ID {}", id))),
+        None => Ok(start_version.unwrap_or(0)),
+    }
+}
+
+/// Streams transactions from `start_version` into `sender` until the client leaves
+pub async fn stream_transactions(
+    pool: PgDbPool,
+    mut committed_versions: Option<watch::Receiver<Option<u64>>>,
+    start_version: u64,
+    mut sender: Sender,
+) {
+    let mut next_version = start_version;
+    let mut last_sent = Instant::now();
+    loop {
+        let query_pool = pool.clone();
+        let chunk = tokio::task::spawn_blocking(move || {
+            let conn = query_pool.get()?;
+            let txns = queries::get_transactions_by_version_range(
+                &conn,
+                next_version,
+                next_version.saturating_add(CHUNK_SIZE),
+            )?;
+            txns.into_iter()
+                .map(|txn| {
+                    let version = bigdecimal_to_u64(&txn.version)?;
+                    Ok((version, serde_json::to_string(&txn)?))
+                })
+                .collect::<Result<Vec<_>>>()
+        })
+        .await
+        .expect("Stream query panicked");
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                aptos_logger::error!(
+                    version = next_version,
+                    error = format!("{:?}", err),
+                    "Transaction stream failed"
+                );
+                let _ = sender
+                    .send_data(event("error", None, "\"Query failed\"").into())
+                    .await;
+                return;
+            }
+        };
+
+        if chunk.is_empty() {
+            let notified = match &mut committed_versions {
+                Some(committed_versions) => {
+                    let committed = *committed_versions.borrow();
+                    match committed {
+                        // Committed while the chunk was read
+                        Some(committed) if committed >= next_version => true,
+                        _ => {
+                            tokio::time::timeout(KEEP_ALIVE_INTERVAL, committed_versions.changed())
+                                .await
+                                .map_or(true, |changed| changed.is_ok())
+                        }
+                    }
+                }
+                None => {
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                    true
+                }
+            };
+            if !notified {
+                // The indexer stopped: fall back to polling
+                committed_versions = None;
+            }
+            if last_sent.elapsed() >= KEEP_ALIVE_INTERVAL {
+                if sender.send_data(": keep-alive\n\n".into()).await.is_err() {
+                    return;
+                }
+                last_sent = Instant::now();
+            }
+            continue;
+        }
+
+        for (version, txn) in chunk {
+            if sender
+                .send_data(event("transaction", Some(version), &txn).into())
+                .await
+                .is_err()
+            {
+                // The client left
+                return;
+            }
+            next_version = version + 1;
+        }
+        last_sent = Instant::now();
+    }
+}
+
+/// A server-sent event, with `data` on a single line
+fn event(name: &str, id: Option<u64>, data: &str) -> String {
+    match id {
+        Some(id) => format!("id: {}\nevent: {}\ndata: {}\n\n", id, name, data),
+        None => format!("event: {}\ndata: {}\n\n", name, data),
+    }
+}
+
+/// The response of a stream, whose body `stream_transactions` writes to
+pub fn event_stream_response() -> (Sender, hyper::Response<Body>) {
+    let (sender, body) = Body::channel();
+    let resp = hyper::Response::builder()
+        .header(http::header::CONTENT_TYPE, "text/event-stream")
+        .header(http::header::CACHE_CONTROL, "no-cache")
+        .body(body)
+        .expect("Valid response");
+    (sender, resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_committed_versions() {
+        let versions = Arc::new(CommittedVersions::default());
+        let mut receiver = versions.subscribe();
+        versions
+            .on_batch_committed(0, 9, &["token_processor".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(*receiver.borrow_and_update(), None);
+        versions
+            .on_batch_committed(10, 19, &[DEFAULT_PROCESSOR_NAME.to_string()])
+            .await
+            .unwrap();
+        assert!(receiver.has_changed().unwrap());
+        assert_eq!(*receiver.borrow_and_update(), Some(19));
+        // Replayed batches don't move it back
+        versions
+            .on_batch_committed(0, 9, &[DEFAULT_PROCESSOR_NAME.to_string()])
+            .await
+            .unwrap();
+        assert!(!receiver.has_changed().unwrap());
+
+        assert_eq!(start_version(Some(5), None).unwrap(), 5);
+        assert_eq!(start_version(Some(5), Some("41")).unwrap(), 42);
+        assert!(start_version(None, Some("x")).is_err());
+        assert_eq!(
+            event("transaction", Some(1), "{}"),
+            "id: 1\nevent: transaction\ndata: {}\n\n"
+        );
+    }
+}
@@ -0,0 +1,117 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    database::{insert_chunked, PgPoolConnection},
+    models::rollups::EventTypeSeen,
+    rollups::{greatest_last_version, Rollup},
+    schema::event_types_seen::{self, dsl},
+    util::u64_to_bigdecimal,
+};
+use aptos_rest_client::{aptos_api_types::Event, Transaction};
+use diesel::{dsl::sql, pg::upsert::excluded, prelude::*, sql_types::Numeric, QueryResult};
+use std::collections::BTreeMap;
+
+pub const NAME: &str = "event_types_seen";
+
+/// First and last version and number of events of each event type ever emitted, to discover what contracts emit.
+/// Types aren't bucketed by time: each type's row is its own bucket.
+#[derive(Debug)]
+pub struct EventTypesSeenRollups;
+
+/// The version of each event emitted by `transactions`, by event type, in version order
+pub fn event_versions_by_type(transactions: &[Transaction]) -> BTreeMap<String, Vec<u64>> {
+    let mut versions: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+    for txn in transactions {
+        let events: &[Event] = match txn {
+            Transaction::UserTransaction(user_txn) => &user_txn.events,
+            Transaction::GenesisTransaction(genesis_txn) => &genesis_txn.events,
+            Transaction::BlockMetadataTransaction(block_metadata_txn) => &block_metadata_txn.events,
+            Transaction::StateCheckpointTransaction(_) | Transaction::PendingTransaction(_) => {
+                continue
+            }
+        };
+        let version = match txn.version() {
+            Some(version) => version,
+            None => continue,
+        };
+        for event in events {
+            versions
+                .entry(event.typ.to_string())
+                .or_default()
+                .push(version);
+        }
+    }
+    versions
+}
+
+impl Rollup for EventTypesSeenRollups {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    /// Adds the batch's events to their types' counts, and widens their version ranges: batches aren't folded in
+    /// version order, so a backfilled one can lower `first_version`
+    fn apply(&self, conn: &PgPoolConnection, transactions: &[Transaction]) -> QueryResult<()> {
+        let types: Vec<EventTypeSeen> = event_versions_by_type(transactions)
+            .into_iter()
+            .map(|(type_, versions)| EventTypeSeen {
+                type_,
+                first_version: u64_to_bigdecimal(versions[0]),
+                last_version: u64_to_bigdecimal(versions[versions.len() - 1]),
+                num_events: versions.len() as i64,
+                inserted_at: chrono::Utc::now().naive_utc(),
+            })
+            .collect();
+        if types.is_empty() {
+            return Ok(());
+        }
+
+        insert_chunked(conn, &types, |chunk| {
+            diesel::insert_into(event_types_seen::table)
+                .values(chunk)
+                .on_conflict(dsl::type_)
+                .do_update()
+                .set((
+                    dsl::first_version.eq(sql::<Numeric>(
+                        "LEAST(event_types_seen.first_version, EXCLUDED.first_version)",
+                    )),
+                    dsl::last_version.eq(greatest_last_version("event_types_seen")),
+                    dsl::num_events.eq(dsl::num_events + excluded(dsl::num_events)),
+                ))
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TransactionFactory;
+    use serde_json::json;
+
+    #[test]
+    fn test_event_versions_by_type() {
+        let mut factory = TransactionFactory::new(10);
+        let txns = vec![
+            factory
+                .user_transaction("0xa11ce")
+                .event(0, "0x1::coin::WithdrawEvent", json!({"amount": "1"}))
+                .event(1, "0x1::coin::DepositEvent", json!({"amount": "1"}))
+                .build(),
+            factory.state_checkpoint(),
+            factory
+                .user_transaction("0xb0b")
+                .event(0, "0x1::coin::WithdrawEvent", json!({"amount": "2"}))
+                .build(),
+        ];
+        let versions = event_versions_by_type(&txns);
+        assert_eq!(
+            versions.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("0x1::coin::DepositEvent".to_string(), vec![10]),
+                ("0x1::coin::WithdrawEvent".to_string(), vec![10, 12]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    database::{insert_chunked, PgPoolConnection},
+    models::rollups::FunctionReliabilityStat,
+    rollups::{group_by_bucket, watermark, Rollup},
+    schema::function_reliability_stats::{self, dsl},
+    util::u64_to_bigdecimal,
+};
+use aptos_rest_client::{aptos_api_types::TransactionPayload, Transaction};
+use diesel::{pg::upsert::excluded, prelude::*, result::Error as DieselError, QueryResult};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+pub const NAME: &str = "function_reliability_stats";
+const BUCKET_SECS: i64 = 24 * 60 * 60;
+
+/// Transactions, failures and abort codes per entry function and day, counting the user transactions calling the
+/// function
+#[derive(Debug)]
+pub struct FunctionReliabilityStatsRollups;
+
+type FunctionDay = (String, String, String, chrono::NaiveDate);
+
+/// The abort location and code of a failed transaction's `vm_status`, ex: "0x1::coin: EINSUFFICIENT_BALANCE(0x10006)"
+/// for "Move abort in 0x1::coin: EINSUFFICIENT_BALANCE(0x10006): Not enough coins to complete transaction".
+/// Failures which aren't aborts (ex: out of gas) have none.
+pub fn abort_code(vm_status: &str) -> Option<String> {
+    let abort = vm_status.strip_prefix("Move abort in ")?;
+    let (location, rest) = abort.split_once(": ")?;
+    // The code is followed by the reason's description, if the module documents it
+    let code = rest.split(": ").next().unwrap_or(rest);
+    Some(format!("{}: {}", location, code))
+}
+
+impl Rollup for FunctionReliabilityStatsRollups {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn apply(&self, conn: &PgPoolConnection, transactions: &[Transaction]) -> QueryResult<()> {
+        let mut calls: BTreeMap<FunctionDay, Vec<&Transaction>> = BTreeMap::new();
+        for (bucket, txns) in group_by_bucket(transactions, BUCKET_SECS) {
+            for txn in txns {
+                if let Transaction::UserTransaction(user_txn) = txn {
+                    if let TransactionPayload::EntryFunctionPayload(payload) =
+                        &user_txn.request.payload
+                    {
+                        let module = &payload.function.module;
+                        calls
+                            .entry((
+                                module.address.inner().to_hex_literal(),
+                                module.name.to_string(),
+                                payload.function.name.to_string(),
+                                bucket.date(),
+                            ))
+                            .or_default()
+                            .push(txn);
+                    }
+                }
+            }
+        }
+        if calls.is_empty() {
+            return Ok(());
+        }
+
+        let dates: Vec<chrono::NaiveDate> = calls.keys().map(|(_, _, _, date)| *date).collect();
+        let addresses: Vec<String> = calls
+            .keys()
+            .map(|(address, _, _, _)| address.clone())
+            .collect();
+        let mut existing: BTreeMap<FunctionDay, FunctionReliabilityStat> =
+            dsl::function_reliability_stats
+                .filter(dsl::date.eq_any(dates))
+                .filter(dsl::module_address.eq_any(addresses))
+                .load::<FunctionReliabilityStat>(conn)?
+                .into_iter()
+                .map(|stat| {
+                    (
+                        (
+                            stat.module_address.clone(),
+                            stat.module_name.clone(),
+                            stat.function_name.clone(),
+                            stat.date,
+                        ),
+                        stat,
+                    )
+                })
+                .collect();
+
+        let mut stats = vec![];
+        for (key, txns) in calls {
+            let (module_address, module_name, function_name, date) = key.clone();
+            let (mut num_transactions, mut num_failed, mut abort_codes, last_version) =
+                match existing.remove(&key) {
+                    Some(stat) => (
+                        stat.num_transactions,
+                        stat.num_failed,
+                        serde_json::from_value::<BTreeMap<String, i64>>(stat.abort_codes)
+                            .map_err(|e| DieselError::DeserializationError(e.into()))?,
+                        watermark(&stat.last_version)?,
+                    ),
+                    None => (0, 0, BTreeMap::new(), 0),
+                };
+            let last_version = std::cmp::max(last_version, txns.last().unwrap().version().unwrap());
+            for txn in &txns {
+                if let Transaction::UserTransaction(user_txn) = txn {
+                    num_transactions += 1;
+                    if !user_txn.info.success {
+                        num_failed += 1;
+                        if let Some(code) = abort_code(&user_txn.info.vm_status) {
+                            *abort_codes.entry(code).or_default() += 1;
+                        }
+                    }
+                }
+            }
+            stats.push(FunctionReliabilityStat {
+                module_address,
+                module_name,
+                function_name,
+                date,
+                num_transactions,
+                num_failed,
+                abort_codes: Value::Object(
+                    abort_codes
+                        .into_iter()
+                        .map(|(code, count)| (code, Value::from(count)))
+                        .collect::<Map<String, Value>>(),
+                ),
+                last_version: u64_to_bigdecimal(last_version),
+                inserted_at: chrono::Utc::now().naive_utc(),
+            });
+        }
+
+        insert_chunked(conn, &stats, |chunk| {
+            diesel::insert_into(function_reliability_stats::table)
+                .values(chunk)
+                .on_conflict((
+                    dsl::module_address,
+                    dsl::module_name,
+                    dsl::function_name,
+                    dsl::date,
+                ))
+                .do_update()
+                .set((
+                    dsl::num_transactions.eq(excluded(dsl::num_transactions)),
+                    dsl::num_failed.eq(excluded(dsl::num_failed)),
+                    dsl::abort_codes.eq(excluded(dsl::abort_codes)),
+                    dsl::last_version.eq(excluded(dsl::last_version)),
+                ))
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abort_code() {
+        assert_eq!(
+            abort_code(
+                "Move abort in 0x1::coin: EINSUFFICIENT_BALANCE(0x10006): Not enough coins to complete transaction"
+            ),
+            Some("0x1::coin: EINSUFFICIENT_BALANCE(0x10006)".to_string())
+        );
+        assert_eq!(
+            abort_code("Move abort in 0xcafe::pool: 0x3"),
+            Some("0xcafe::pool: 0x3".to_string())
+        );
+        assert_eq!(abort_code("Out of gas"), None);
+        assert_eq!(abort_code("Executed successfully"), None);
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! HyperLogLog sketches, to count distinct items (ex: accounts) per rollup bucket in a bounded number of bytes rather
+//! than by storing every item. Each of the 2^precision registers keeps the longest run of leading zeros (plus one)
+//! among the hashes of the items routed to it. Sketches merge by taking the largest of each register, so the sketches
+//! of adjacent buckets (ex: the days of a week) combine into the sketch of their union.
+//!
+//! Sketches are stored in `BYTEA` columns as `to_bytes` lays them out: the precision, then one byte per register.
+//! Items are hashed with SHA-256, so sketches written by different releases of the indexer stay mergeable.
+
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+pub const MIN_PRECISION: u8 = 4;
+pub const MAX_PRECISION: u8 = 16;
+/// 2^12 registers: 4 KiB, for a standard error of about 1.6%
+pub const DEFAULT_PRECISION: u8 = 12;
+
+/// Why bytes couldn't be read as a sketch, or two sketches couldn't be merged
+#[derive(Clone, Debug, PartialEq)]
+pub enum HyperLogLogError {
+    Malformed(usize),
+    PrecisionMismatch(u8, u8),
+}
+
+impl fmt::Display for HyperLogLogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed(len) => write!(f, "{} bytes aren't a HyperLogLog sketch", len),
+            Self::PrecisionMismatch(left, right) => write!(
+                f,
+                "Can't merge HyperLogLog sketches of precisions {} and {}",
+                left, right
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HyperLogLogError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_PRECISION)
+    }
+}
+
+impl HyperLogLog {
+    /// An empty sketch of 2^`precision` registers, `precision` being clamped to `MIN_PRECISION..=MAX_PRECISION`
+    pub fn new(precision: u8) -> Self {
+        let precision = precision.clamp(MIN_PRECISION, MAX_PRECISION);
+        Self {
+            precision,
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    /// Reads a sketch written by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, HyperLogLogError> {
+        match bytes.split_first() {
+            Some((&precision, registers))
+                if (MIN_PRECISION..=MAX_PRECISION).contains(&precision)
+                    && registers.len() == 1 << precision =>
+            {
+                Ok(Self {
+                    precision,
+                    registers: registers.to_vec(),
+                })
+            }
+            // Registers without a header, as `module_daily_stats` first wrote them
+            _ if bytes.len() == 1 << DEFAULT_PRECISION => Ok(Self {
+                precision: DEFAULT_PRECISION,
+                registers: bytes.to_vec(),
+            }),
+            _ => Err(HyperLogLogError::Malformed(bytes.len())),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(1 + self.registers.len());
+        bytes.push(self.precision);
+        bytes.extend_from_slice(&self.registers);
+        bytes
+    }
+
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registers.iter().all(|register| *register == 0)
+    }
+
+    pub fn insert(&mut self, item: &[u8]) {
+        let digest = Sha256::digest(item);
+        let mut hash = [0u8; 8];
+        hash.copy_from_slice(&digest[..8]);
+        let hash = u64::from_be_bytes(hash);
+        let precision = self.precision as u32;
+        let index = (hash >> (64 - precision)) as usize;
+        let rank = ((hash << precision).leading_zeros() + 1).min(64 - precision + 1);
+        self.registers[index] = self.registers[index].max(rank as u8);
+    }
+
+    /// Folds `other` into this sketch, which then counts the union of both
+    pub fn merge(&mut self, other: &Self) -> Result<(), HyperLogLogError> {
+        if self.precision != other.precision {
+            return Err(HyperLogLogError::PrecisionMismatch(
+                self.precision,
+                other.precision,
+            ));
+        }
+        for (register, other) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other);
+        }
+        Ok(())
+    }
+
+    /// Estimated number of distinct items inserted
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|register| 2f64.powi(-(*register as i32)))
+            .sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self
+            .registers
+            .iter()
+            .filter(|register| **register == 0)
+            .count();
+        // Linear counting is more accurate for small cardinalities
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+}
+
+/// Merges the sketches stored in `rows` (ex: a module's daily sketches over a week), failing on malformed ones
+pub fn merge_all<'a>(
+    rows: impl IntoIterator<Item = &'a [u8]>,
+) -> Result<HyperLogLog, HyperLogLogError> {
+    let mut merged: Option<HyperLogLog> = None;
+    for bytes in rows {
+        let sketch = HyperLogLog::from_bytes(bytes)?;
+        match &mut merged {
+            Some(merged) => merged.merge(&sketch)?,
+            None => merged = Some(sketch),
+        }
+    }
+    Ok(merged.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn within(estimate: u64, expected: u64, error: f64) -> bool {
+        (estimate as f64 - expected as f64).abs() <= expected as f64 * error
+    }
+
+    #[test]
+    fn test_hyper_log_log() {
+        let mut sketch = HyperLogLog::default();
+        assert!(sketch.is_empty());
+        assert_eq!(sketch.estimate(), 0);
+        for i in 0u64..10_000 {
+            sketch.insert(&i.to_be_bytes());
+            // Repeated items don't count twice
+            sketch.insert(&i.to_be_bytes());
+        }
+        assert!(within(sketch.estimate(), 10_000, 0.05));
+
+        let bytes = sketch.to_bytes();
+        assert_eq!(bytes.len(), 1 + (1 << DEFAULT_PRECISION));
+        assert_eq!(HyperLogLog::from_bytes(&bytes).unwrap(), sketch);
+        assert_eq!(
+            HyperLogLog::from_bytes(&bytes[1..]).unwrap().estimate(),
+            sketch.estimate()
+        );
+        assert_eq!(
+            HyperLogLog::from_bytes(&[12, 0]),
+            Err(HyperLogLogError::Malformed(2))
+        );
+        assert_eq!(HyperLogLog::new(0).precision(), MIN_PRECISION);
+    }
+
+    #[test]
+    fn test_hyper_log_log_merge() {
+        let mut left = HyperLogLog::default();
+        let mut right = HyperLogLog::default();
+        for i in 0u64..6_000 {
+            left.insert(&i.to_be_bytes());
+        }
+        for i in 4_000u64..10_000 {
+            right.insert(&i.to_be_bytes());
+        }
+        let merged = merge_all(
+            [left.to_bytes(), right.to_bytes()]
+                .iter()
+                .map(Vec::as_slice),
+        )
+        .unwrap();
+        assert!(within(merged.estimate(), 10_000, 0.05));
+
+        left.merge(&right).unwrap();
+        assert_eq!(left, merged);
+        assert_eq!(
+            left.merge(&HyperLogLog::new(10)),
+            Err(HyperLogLogError::PrecisionMismatch(12, 10))
+        );
+        assert!(merge_all(std::iter::empty()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    database::{insert_chunked, PgPoolConnection},
+    models::rollups::{HourlyActiveAccount, HourlyActivityRollup},
+    rollups::{greatest_last_version, group_by_bucket, Rollup},
+    schema::{
+        hourly_active_accounts,
+        hourly_activity_rollups::{self, dsl},
+    },
+};
+use aptos_rest_client::Transaction;
+use diesel::{pg::upsert::excluded, prelude::*, QueryResult};
+use std::collections::HashSet;
+
+pub const NAME: &str = "hourly_activity";
+const BUCKET_SECS: i64 = 60 * 60;
+
+/// Gas used and number of distinct active accounts (user transaction senders) per hour
+#[derive(Debug)]
+pub struct HourlyActivityRollups;
+
+impl Rollup for HourlyActivityRollups {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn apply(&self, conn: &PgPoolConnection, transactions: &[Transaction]) -> QueryResult<()> {
+        let mut rollups = vec![];
+        for (bucket, txns) in group_by_bucket(transactions, BUCKET_SECS) {
+            let last_version = match txns.last() {
+                Some(last) => last.version().unwrap(),
+                None => continue,
+            };
+            let mut gas_used = 0;
+            let mut senders = HashSet::new();
+            for txn in &txns {
+                if let Transaction::UserTransaction(user_txn) = txn {
+                    gas_used += user_txn.info.gas_used.0;
+                    senders.insert(user_txn.request.sender.inner().to_hex_literal());
+                }
+            }
+
+            // Only accounts seen for the first time in this hour add to its active accounts
+            let active_accounts: Vec<HourlyActiveAccount> = senders
+                .into_iter()
+                .map(|sender| HourlyActiveAccount::new(bucket, sender))
+                .collect();
+            let num_new_accounts = insert_chunked(conn, &active_accounts, |chunk| {
+                diesel::insert_into(hourly_active_accounts::table)
+                    .values(chunk)
+                    .on_conflict_do_nothing()
+            })?;
+
+            rollups.push(HourlyActivityRollup::new(
+                bucket,
+                gas_used,
+                num_new_accounts as i64,
+                last_version,
+            ));
+        }
+
+        insert_chunked(conn, &rollups, |chunk| {
+            diesel::insert_into(hourly_activity_rollups::table)
+                .values(chunk)
+                .on_conflict(dsl::bucket)
+                .do_update()
+                .set((
+                    dsl::gas_used.eq(dsl::gas_used + excluded(dsl::gas_used)),
+                    dsl::num_active_accounts
+                        .eq(dsl::num_active_accounts + excluded(dsl::num_active_accounts)),
+                    dsl::last_version.eq(greatest_last_version("hourly_activity_rollups")),
+                ))
+        })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    database::{insert_chunked, PgPoolConnection},
+    models::rollups::MinuteTransactionRollup,
+    rollups::{greatest_last_version, group_by_bucket, Rollup},
+    schema::minute_transaction_rollups::{self, dsl},
+};
+use aptos_rest_client::Transaction;
+use diesel::{pg::upsert::excluded, prelude::*, QueryResult};
+
+pub const NAME: &str = "minute_transactions";
+const BUCKET_SECS: i64 = 60;
+
+/// Number of transactions, user transactions and failed transactions per minute
+#[derive(Debug)]
+pub struct MinuteTransactionRollups;
+
+impl Rollup for MinuteTransactionRollups {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn apply(&self, conn: &PgPoolConnection, transactions: &[Transaction]) -> QueryResult<()> {
+        let mut rollups = vec![];
+        for (bucket, txns) in group_by_bucket(transactions, BUCKET_SECS) {
+            if let Some(last) = txns.last() {
+                rollups.push(MinuteTransactionRollup::new(
+                    bucket,
+                    txns.len() as i64,
+                    txns.iter()
+                        .filter(|txn| matches!(txn, Transaction::UserTransaction(_)))
+                        .count() as i64,
+                    txns.iter().filter(|txn| !txn.success()).count() as i64,
+                    last.version().unwrap(),
+                ));
+            }
+        }
+
+        insert_chunked(conn, &rollups, |chunk| {
+            diesel::insert_into(minute_transaction_rollups::table)
+                .values(chunk)
+                .on_conflict(dsl::bucket)
+                .do_update()
+                .set((
+                    dsl::num_transactions
+                        .eq(dsl::num_transactions + excluded(dsl::num_transactions)),
+                    dsl::num_user_transactions
+                        .eq(dsl::num_user_transactions + excluded(dsl::num_user_transactions)),
+                    dsl::num_failed_transactions
+                        .eq(dsl::num_failed_transactions + excluded(dsl::num_failed_transactions)),
+                    dsl::last_version.eq(greatest_last_version("minute_transaction_rollups")),
+                ))
+        })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,319 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rollups maintain materialized aggregates over time buckets (e.g. transactions per minute), so that
+//! dashboards don't need to `GROUP BY` over the raw tables.
+//!
+//! The `RollupTask` is run by the `Tailer` once per processed batch. Batches aren't folded in version order (they're
+//! processed concurrently, and a catch-up tailer backfills versions older than those already folded), so rollups
+//! only add to their buckets. The version ranges folded into each rollup are recorded in `rollup_ranges`, in the same
+//! DB transaction as its buckets, and versions already in them are skipped, which makes replaying a batch after a
+//! restart a no-op. Every rollup row still keeps the highest version folded into it (`last_version`).
+//!
+//! A batch is folded after its processor commits it, so the indexer may stop in between. Before tailing resumes,
+//! `Tailer::refold_rollups` folds the versions the processor committed but the rollups miss (see
+//! `RollupTask::unfolded_ranges`), fetching them again.
+
+pub mod event_types_seen;
+pub mod function_reliability_stats;
+pub mod hll;
+pub mod hourly_activity;
+pub mod minute_transactions;
+pub mod module_daily_stats;
+
+use crate::{
+    counters::ROLLUP_ERRORS,
+    database::{insert_chunked, with_advisory_lock, PgPoolConnection, ROLLUPS_LOCK_KEY},
+    models::rollups::RollupRange,
+    schema::rollup_ranges::{self, dsl},
+    util::{bigdecimal_to_u64, u64_to_bigdecimal},
+};
+use aptos_rest_client::Transaction;
+use diesel::{
+    dsl::{sql, SqlLiteral},
+    prelude::*,
+    result::Error as DieselError,
+    sql_types::Numeric,
+    QueryResult,
+};
+use std::{collections::BTreeMap, fmt::Debug};
+
+pub trait Rollup: Send + Sync + Debug {
+    /// name of the rollup, for logging and metrics
+    fn name(&self) -> &'static str;
+
+    /// Folds the given transactions into the rollup's buckets, adding to what they hold already.
+    /// This is called within a DB transaction, with transactions in version order, but earlier batches may hold
+    /// later versions. Versions are never given twice.
+    fn apply(&self, conn: &PgPoolConnection, transactions: &[Transaction]) -> QueryResult<()>;
+}
+
+#[derive(Debug)]
+pub struct RollupTask {
+    rollups: Vec<Box<dyn Rollup>>,
+}
+
+impl RollupTask {
+    pub fn new(rollups: Vec<Box<dyn Rollup>>) -> Self {
+        Self { rollups }
+    }
+
+    /// Transactions per minute, gas used and active accounts per hour, usage of each module and outcomes of each
+    /// entry function per day, and the event types emitted
+    pub fn with_default_rollups() -> Self {
+        Self::new(vec![
+            Box::new(minute_transactions::MinuteTransactionRollups),
+            Box::new(hourly_activity::HourlyActivityRollups),
+            Box::new(module_daily_stats::ModuleDailyStatsRollups),
+            Box::new(function_reliability_stats::FunctionReliabilityStatsRollups),
+            Box::new(event_types_seen::EventTypesSeenRollups),
+        ])
+    }
+
+    /// Folds `transactions` into each rollup, in its own DB transaction, skipping the versions already folded into
+    /// it. Holds `ROLLUPS_LOCK_KEY`, so batches folded concurrently (ex: by a catch-up tailer) don't overwrite each
+    /// other's updates. A failing rollup doesn't prevent the others from being updated, but its error is returned
+    /// once they were: the batch is then failed, so its versions are processed and folded again.
+    pub fn run(&self, conn: &PgPoolConnection, transactions: &[Transaction]) -> anyhow::Result<()> {
+        let ranges = version_ranges(transactions);
+        if ranges.is_empty() {
+            return Ok(());
+        }
+        with_advisory_lock(conn, ROLLUPS_LOCK_KEY, || {
+            let mut first_error = None;
+            for rollup in &self.rollups {
+                let res = conn.transaction::<(), diesel::result::Error, _>(|| {
+                    fold(conn, rollup.as_ref(), transactions, &ranges)
+                });
+                if let Err(err) = res {
+                    ROLLUP_ERRORS.with_label_values(&[rollup.name()]).inc();
+                    aptos_logger::error!(
+                        rollup_name = rollup.name(),
+                        error = format!("{:?}", err),
+                        "Failed to update rollup"
+                    );
+                    if first_error.is_none() {
+                        first_error = Some(
+                            anyhow::Error::new(err)
+                                .context(format!("Failed to update rollup {}", rollup.name())),
+                        );
+                    }
+                }
+            }
+            match first_error {
+                Some(err) => Err(err),
+                None => Ok(()),
+            }
+        })
+    }
+
+    /// The versions up to `end_version` missing from the rollups' folded ranges, after the first version folded into
+    /// each (the versions before it predate the rollup), merged across rollups. The tailer folds a batch after its
+    /// processor commits it, so a batch committed but not folded yet when the indexer stopped leaves such a gap.
+    pub fn unfolded_ranges(
+        &self,
+        conn: &PgPoolConnection,
+        end_version: u64,
+    ) -> QueryResult<Vec<(u64, u64)>> {
+        let mut unfolded = vec![];
+        for rollup in &self.rollups {
+            let folded: Vec<(u64, u64)> = dsl::rollup_ranges
+                .select((dsl::start_version, dsl::end_version))
+                .filter(dsl::rollup.eq(rollup.name()))
+                .filter(dsl::start_version.le(u64_to_bigdecimal(end_version)))
+                .order(dsl::start_version)
+                .load::<(bigdecimal::BigDecimal, bigdecimal::BigDecimal)>(conn)?
+                .iter()
+                .map(|(start, end)| Ok((watermark(start)?, watermark(end)?)))
+                .collect::<QueryResult<_>>()?;
+            unfolded = merge_ranges(&unfolded, &gaps(&folded, end_version));
+        }
+        Ok(unfolded)
+    }
+}
+
+/// The versions between `ranges` (sorted, neither overlapping nor adjacent), and after the last one up to
+/// `end_version`
+pub fn gaps(ranges: &[(u64, u64)], end_version: u64) -> Vec<(u64, u64)> {
+    let mut gaps: Vec<(u64, u64)> = ranges
+        .windows(2)
+        .filter(|pair| pair[0].1.saturating_add(1) < pair[1].0)
+        .map(|pair| (pair[0].1 + 1, pair[1].0 - 1))
+        .collect();
+    if let Some((_, last_end)) = ranges.last() {
+        if *last_end < end_version {
+            gaps.push((last_end + 1, end_version));
+        }
+    }
+    gaps
+}
+
+/// Folds the transactions whose versions aren't in `rollup`'s folded ranges yet into it, then records `ranges` (the
+/// runs of versions of `transactions`) as folded
+fn fold(
+    conn: &PgPoolConnection,
+    rollup: &dyn Rollup,
+    transactions: &[Transaction],
+    ranges: &[(u64, u64)],
+) -> QueryResult<()> {
+    let (start_version, end_version) = (ranges[0].0, ranges[ranges.len() - 1].1);
+    // The folded ranges overlapping the batch, or adjacent to it, which its ranges are merged with
+    let folded: Vec<(u64, u64)> = dsl::rollup_ranges
+        .select((dsl::start_version, dsl::end_version))
+        .filter(dsl::rollup.eq(rollup.name()))
+        .filter(dsl::start_version.le(u64_to_bigdecimal(end_version.saturating_add(1))))
+        .filter(dsl::end_version.ge(u64_to_bigdecimal(start_version.saturating_sub(1))))
+        .load::<(bigdecimal::BigDecimal, bigdecimal::BigDecimal)>(conn)?
+        .iter()
+        .map(|(start, end)| Ok((watermark(start)?, watermark(end)?)))
+        .collect::<QueryResult<_>>()?;
+    let unfolded: Vec<&Transaction> = transactions
+        .iter()
+        .filter(|txn| match txn.version() {
+            Some(version) => !folded
+                .iter()
+                .any(|(start, end)| (*start..=*end).contains(&version)),
+            None => false,
+        })
+        .collect();
+    if unfolded.is_empty() {
+        return Ok(());
+    }
+    if unfolded.len() == transactions.len() {
+        rollup.apply(conn, transactions)?;
+    } else {
+        let unfolded: Vec<Transaction> = unfolded.into_iter().cloned().collect();
+        rollup.apply(conn, &unfolded)?;
+    }
+
+    diesel::delete(
+        dsl::rollup_ranges
+            .filter(dsl::rollup.eq(rollup.name()))
+            .filter(
+                dsl::start_version.eq_any(
+                    folded
+                        .iter()
+                        .map(|(start, _)| u64_to_bigdecimal(*start))
+                        .collect::<Vec<_>>(),
+                ),
+            ),
+    )
+    .execute(conn)?;
+    let merged: Vec<RollupRange> = merge_ranges(&folded, ranges)
+        .into_iter()
+        .map(|(start, end)| RollupRange::new(rollup.name(), start, end))
+        .collect();
+    insert_chunked(conn, &merged, |chunk| {
+        diesel::insert_into(rollup_ranges::table).values(chunk)
+    })?;
+    Ok(())
+}
+
+/// The runs of consecutive versions of `transactions` (in version order), as (first, last) versions
+pub fn version_ranges(transactions: &[Transaction]) -> Vec<(u64, u64)> {
+    let mut ranges: Vec<(u64, u64)> = vec![];
+    for version in transactions.iter().filter_map(|txn| txn.version()) {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == version => *end = version,
+            _ => ranges.push((version, version)),
+        }
+    }
+    ranges
+}
+
+/// The union of two sets of version ranges (inclusive), with overlapping and adjacent ranges merged, sorted
+pub fn merge_ranges(a: &[(u64, u64)], b: &[(u64, u64)]) -> Vec<(u64, u64)> {
+    let mut ranges: Vec<(u64, u64)> = a.iter().chain(b).copied().collect();
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = vec![];
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some((_, last_end)) if start <= last_end.saturating_add(1) => {
+                *last_end = std::cmp::max(*last_end, end)
+            }
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Returns the start of the `bucket_secs` wide bucket which a block timestamp (in microseconds) falls into
+pub fn bucket_start(timestamp_usecs: u64, bucket_secs: i64) -> chrono::NaiveDateTime {
+    let secs = (timestamp_usecs / 1_000_000) as i64;
+    chrono::NaiveDateTime::from_timestamp(secs - secs.rem_euclid(bucket_secs), 0)
+}
+
+/// Groups transactions by bucket, keeping version order within each bucket.
+/// Genesis has no block timestamp, so it isn't attributed to any bucket.
+pub fn group_by_bucket(
+    transactions: &[Transaction],
+    bucket_secs: i64,
+) -> BTreeMap<chrono::NaiveDateTime, Vec<&Transaction>> {
+    let mut buckets: BTreeMap<chrono::NaiveDateTime, Vec<&Transaction>> = BTreeMap::new();
+    for txn in transactions {
+        if txn.version().is_none() || txn.timestamp() == 0 {
+            continue;
+        }
+        buckets
+            .entry(bucket_start(txn.timestamp(), bucket_secs))
+            .or_default()
+            .push(txn);
+    }
+    buckets
+}
+
+/// Reads a bucket's `last_version`, failing the rollup's DB transaction if the column holds something else than a u64
+pub fn watermark(last_version: &bigdecimal::BigDecimal) -> QueryResult<u64> {
+    bigdecimal_to_u64(last_version).map_err(|e| DieselError::DeserializationError(e.into()))
+}
+
+/// For the upserts of buckets: keeps the highest `last_version` of `table`'s row and the inserted one, as batches
+/// aren't folded in version order
+pub fn greatest_last_version(table: &str) -> SqlLiteral<Numeric> {
+    sql(&format!(
+        "GREATEST({}.last_version, EXCLUDED.last_version)",
+        table
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_start() {
+        // 2022-04-10T03:16:42.763949Z
+        let ts = 1649560602763949;
+        assert_eq!(bucket_start(ts, 60).timestamp(), 1649560560);
+        assert_eq!(bucket_start(ts, 3600).timestamp(), 1649559600);
+        assert_eq!(bucket_start(1649559600000000, 3600).timestamp(), 1649559600);
+    }
+
+    #[test]
+    fn test_merge_ranges() {
+        assert_eq!(merge_ranges(&[], &[(5, 9)]), vec![(5, 9)]);
+        // Adjacent ranges are merged, so a rollup tailed in order keeps a single range
+        assert_eq!(merge_ranges(&[(0, 4)], &[(5, 9)]), vec![(0, 9)]);
+        // A backfilled batch fills the gap between two ranges
+        assert_eq!(merge_ranges(&[(0, 4), (10, 19)], &[(5, 9)]), vec![(0, 19)]);
+        assert_eq!(
+            merge_ranges(&[(20, 29)], &[(0, 4), (7, 9)]),
+            vec![(0, 4), (7, 9), (20, 29)]
+        );
+        assert_eq!(merge_ranges(&[(0, 9)], &[(3, 12)]), vec![(0, 12)]);
+    }
+
+    #[test]
+    fn test_gaps() {
+        assert_eq!(gaps(&[], 9), Vec::<(u64, u64)>::new());
+        assert_eq!(gaps(&[(0, 9)], 9), Vec::<(u64, u64)>::new());
+        // Versions after the last folded range, ex: a batch committed but not folded before a restart
+        assert_eq!(gaps(&[(0, 4)], 9), vec![(5, 9)]);
+        assert_eq!(
+            gaps(&[(0, 4), (7, 9), (20, 29)], 25),
+            vec![(5, 6), (10, 19)]
+        );
+        // Versions before the first folded range predate the rollup
+        assert_eq!(gaps(&[(10, 19)], 19), Vec::<(u64, u64)>::new());
+    }
+}
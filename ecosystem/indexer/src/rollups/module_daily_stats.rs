@@ -0,0 +1,128 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    database::{insert_chunked, PgPoolConnection},
+    models::rollups::ModuleDailyStat,
+    rollups::{group_by_bucket, hll::HyperLogLog, watermark, Rollup},
+    schema::module_daily_stats::{self, dsl},
+    util::{bigdecimal_to_u64, u64_to_bigdecimal},
+};
+use aptos_rest_client::{aptos_api_types::TransactionPayload, Transaction};
+use diesel::{pg::upsert::excluded, prelude::*, result::Error as DieselError, QueryResult};
+use std::collections::BTreeMap;
+
+pub const NAME: &str = "module_daily_stats";
+const BUCKET_SECS: i64 = 24 * 60 * 60;
+
+/// Transactions, gas used and (approximately) distinct senders per module and day, counting the user transactions
+/// calling an entry function of the module
+#[derive(Debug)]
+pub struct ModuleDailyStatsRollups;
+
+type ModuleDay = (String, String, chrono::NaiveDate);
+
+impl Rollup for ModuleDailyStatsRollups {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn apply(&self, conn: &PgPoolConnection, transactions: &[Transaction]) -> QueryResult<()> {
+        let mut calls: BTreeMap<ModuleDay, Vec<&Transaction>> = BTreeMap::new();
+        for (bucket, txns) in group_by_bucket(transactions, BUCKET_SECS) {
+            for txn in txns {
+                if let Transaction::UserTransaction(user_txn) = txn {
+                    if let TransactionPayload::EntryFunctionPayload(payload) =
+                        &user_txn.request.payload
+                    {
+                        let module = &payload.function.module;
+                        calls
+                            .entry((
+                                module.address.inner().to_hex_literal(),
+                                module.name.to_string(),
+                                bucket.date(),
+                            ))
+                            .or_default()
+                            .push(txn);
+                    }
+                }
+            }
+        }
+        if calls.is_empty() {
+            return Ok(());
+        }
+
+        let dates: Vec<chrono::NaiveDate> = calls.keys().map(|(_, _, date)| *date).collect();
+        let addresses: Vec<String> = calls
+            .keys()
+            .map(|(address, _, _)| address.clone())
+            .collect();
+        let mut existing: BTreeMap<ModuleDay, ModuleDailyStat> = dsl::module_daily_stats
+            .filter(dsl::date.eq_any(dates))
+            .filter(dsl::module_address.eq_any(addresses))
+            .load::<ModuleDailyStat>(conn)?
+            .into_iter()
+            .map(|stat| {
+                (
+                    (
+                        stat.module_address.clone(),
+                        stat.module_name.clone(),
+                        stat.date,
+                    ),
+                    stat,
+                )
+            })
+            .collect();
+
+        let mut stats = vec![];
+        for (key, txns) in calls {
+            let (module_address, module_name, date) = key.clone();
+            let (mut num_transactions, mut gas_used, mut senders, last_version) =
+                match existing.remove(&key) {
+                    Some(stat) => (
+                        stat.num_transactions,
+                        bigdecimal_to_u64(&stat.gas_used)
+                            .map_err(|e| DieselError::DeserializationError(e.into()))?,
+                        HyperLogLog::from_bytes(&stat.senders_sketch)
+                            .map_err(|e| DieselError::DeserializationError(e.into()))?,
+                        watermark(&stat.last_version)?,
+                    ),
+                    None => (0, 0, HyperLogLog::default(), 0),
+                };
+            let last_version = std::cmp::max(last_version, txns.last().unwrap().version().unwrap());
+            for txn in &txns {
+                if let Transaction::UserTransaction(user_txn) = txn {
+                    num_transactions += 1;
+                    gas_used += user_txn.info.gas_used.0;
+                    senders.insert(user_txn.request.sender.inner().as_ref());
+                }
+            }
+            stats.push(ModuleDailyStat {
+                module_address,
+                module_name,
+                date,
+                num_transactions,
+                gas_used: u64_to_bigdecimal(gas_used),
+                unique_senders: senders.estimate() as i64,
+                senders_sketch: senders.to_bytes(),
+                last_version: u64_to_bigdecimal(last_version),
+                inserted_at: chrono::Utc::now().naive_utc(),
+            });
+        }
+
+        insert_chunked(conn, &stats, |chunk| {
+            diesel::insert_into(module_daily_stats::table)
+                .values(chunk)
+                .on_conflict((dsl::module_address, dsl::module_name, dsl::date))
+                .do_update()
+                .set((
+                    dsl::num_transactions.eq(excluded(dsl::num_transactions)),
+                    dsl::gas_used.eq(excluded(dsl::gas_used)),
+                    dsl::unique_senders.eq(excluded(dsl::unique_senders)),
+                    dsl::senders_sketch.eq(excluded(dsl::senders_sketch)),
+                    dsl::last_version.eq(excluded(dsl::last_version)),
+                ))
+        })?;
+        Ok(())
+    }
+}
@@ -1,6 +1,36 @@
 // Copyright (c) Aptos
 // SPDX-License-Identifier: Apache-2.0
 
+table! {
+    account_summaries (address) {
+        address -> Varchar,
+        transactions_sent -> Int8,
+        gas_spent -> Numeric,
+        last_active_version -> Numeric,
+        coins_held -> Int8,
+        inserted_at -> Timestamp,
+        last_updated -> Timestamp,
+    }
+}
+
+table! {
+    account_summary_batches (start_version, end_version) {
+        start_version -> Numeric,
+        end_version -> Numeric,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    api_keys (name) {
+        name -> Varchar,
+        key_hash -> Varchar,
+        requests_per_minute -> Nullable<Int8>,
+        is_revoked -> Bool,
+        inserted_at -> Timestamp,
+    }
+}
+
 table! {
     block_metadata_transactions (hash) {
         hash -> Varchar,
@@ -29,6 +59,82 @@ table! {
     }
 }
 
+table! {
+    collections_v2 (transaction_version, write_set_change_index) {
+        transaction_version -> Numeric,
+        write_set_change_index -> Int8,
+        collection_id -> Varchar,
+        creator_address -> Varchar,
+        collection_name -> Varchar,
+        description -> Text,
+        uri -> Varchar,
+        current_supply -> Nullable<Numeric>,
+        max_supply -> Nullable<Numeric>,
+        total_minted -> Nullable<Numeric>,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    current_object_ownerships (object_address) {
+        object_address -> Varchar,
+        owner_address -> Nullable<Varchar>,
+        state_key_hash -> Varchar,
+        allow_ungated_transfer -> Nullable<Bool>,
+        last_transaction_version -> Numeric,
+        is_deleted -> Bool,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    current_token_ownerships_v2 (token_data_id) {
+        token_data_id -> Varchar,
+        owner_address -> Nullable<Varchar>,
+        last_transaction_version -> Numeric,
+        is_burned -> Bool,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    dex_swaps (event_key, sequence_number) {
+        event_key -> Varchar,
+        sequence_number -> Numeric,
+        transaction_hash -> Varchar,
+        dex_address -> Varchar,
+        pool -> Text,
+        sender -> Varchar,
+        coin_in -> Text,
+        coin_out -> Text,
+        amount_in -> Numeric,
+        amount_out -> Numeric,
+        swapped_at -> Timestamp,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    epochs (epoch) {
+        epoch -> Numeric,
+        transaction_version -> Numeric,
+        transaction_hash -> Varchar,
+        started_at -> Timestamp,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    event_types_seen (type_) {
+        #[sql_name = "type"]
+        type_ -> Text,
+        first_version -> Numeric,
+        last_version -> Numeric,
+        num_events -> Int8,
+        inserted_at -> Timestamp,
+    }
+}
+
 table! {
     events (key, sequence_number) {
         transaction_hash -> Varchar,
@@ -38,6 +144,104 @@ table! {
         type_ -> Text,
         data -> Jsonb,
         inserted_at -> Timestamp,
+        event_index -> Nullable<Int8>,
+        transaction_timestamp -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    function_reliability_stats (module_address, module_name, function_name, date) {
+        module_address -> Varchar,
+        module_name -> Varchar,
+        function_name -> Varchar,
+        date -> Date,
+        num_transactions -> Int8,
+        num_failed -> Int8,
+        abort_codes -> Jsonb,
+        last_version -> Numeric,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    fungible_asset_activities (transaction_version, event_index) {
+        transaction_version -> Numeric,
+        event_index -> Int8,
+        storage_id -> Varchar,
+        owner_address -> Nullable<Varchar>,
+        asset_type -> Nullable<Varchar>,
+        #[sql_name = "type"]
+        type_ -> Varchar,
+        amount -> Nullable<Numeric>,
+        is_frozen -> Nullable<Bool>,
+        transaction_timestamp -> Timestamp,
+        inserted_at -> Timestamp,
+        amount_usd -> Nullable<Numeric>,
+    }
+}
+
+table! {
+    fungible_asset_balance_history (storage_id, transaction_version) {
+        storage_id -> Varchar,
+        transaction_version -> Numeric,
+        asset_type -> Varchar,
+        amount -> Numeric,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    fungible_asset_balances (storage_id) {
+        storage_id -> Varchar,
+        owner_address -> Nullable<Varchar>,
+        asset_type -> Varchar,
+        amount -> Numeric,
+        is_frozen -> Bool,
+        last_transaction_version -> Numeric,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    fungible_asset_metadata (asset_type) {
+        asset_type -> Varchar,
+        creator_address -> Nullable<Varchar>,
+        name -> Varchar,
+        symbol -> Varchar,
+        decimals -> Int8,
+        icon_uri -> Varchar,
+        project_uri -> Varchar,
+        transaction_version -> Numeric,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    hourly_active_accounts (bucket, account) {
+        bucket -> Timestamp,
+        account -> Varchar,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    hourly_activity_rollups (bucket) {
+        bucket -> Timestamp,
+        gas_used -> Numeric,
+        num_active_accounts -> Int8,
+        last_version -> Numeric,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    ledger_info_history (chain_id, ledger_version) {
+        chain_id -> Int8,
+        epoch -> Numeric,
+        ledger_version -> Numeric,
+        ledger_timestamp -> Timestamp,
+        oldest_ledger_version -> Numeric,
+        inserted_at -> Timestamp,
     }
 }
 
@@ -64,6 +268,83 @@ table! {
     }
 }
 
+table! {
+    minute_transaction_rollups (bucket) {
+        bucket -> Timestamp,
+        num_transactions -> Int8,
+        num_user_transactions -> Int8,
+        num_failed_transactions -> Int8,
+        last_version -> Numeric,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    module_daily_stats (module_address, module_name, date) {
+        module_address -> Varchar,
+        module_name -> Varchar,
+        date -> Date,
+        num_transactions -> Int8,
+        gas_used -> Numeric,
+        unique_senders -> Int8,
+        senders_sketch -> Bytea,
+        last_version -> Numeric,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    objects (transaction_version, write_set_change_index) {
+        transaction_version -> Numeric,
+        write_set_change_index -> Int8,
+        object_address -> Varchar,
+        owner_address -> Nullable<Varchar>,
+        state_key_hash -> Varchar,
+        guid_creation_num -> Nullable<Numeric>,
+        allow_ungated_transfer -> Nullable<Bool>,
+        is_deleted -> Bool,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    online_migrations (table_name) {
+        table_name -> Varchar,
+        shadow_table -> Varchar,
+        backfill_end -> Nullable<Jsonb>,
+        backfilled_up_to -> Nullable<Jsonb>,
+        state -> Varchar,
+        started_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    operator_audit_log (id) {
+        id -> Int8,
+        actor -> Varchar,
+        remote_address -> Nullable<Varchar>,
+        action -> Varchar,
+        parameters -> Jsonb,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    outbox (id) {
+        id -> Int8,
+        topic -> Varchar,
+        transaction_version -> Numeric,
+        message_index -> Int8,
+        message_key -> Varchar,
+        payload -> Jsonb,
+        delivered_at -> Nullable<Timestamp>,
+        delivery_attempts -> Int4,
+        last_error -> Nullable<Text>,
+        inserted_at -> Timestamp,
+    }
+}
+
 table! {
     ownerships (ownership_id) {
         ownership_id -> Varchar,
@@ -85,6 +366,25 @@ table! {
     }
 }
 
+table! {
+    raw_transactions (version) {
+        version -> Numeric,
+        hash -> Varchar,
+        encoding -> Varchar,
+        data -> Bytea,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    rollup_ranges (rollup, start_version) {
+        rollup -> Varchar,
+        start_version -> Numeric,
+        end_version -> Numeric,
+        inserted_at -> Timestamp,
+    }
+}
+
 table! {
     token_activities (event_key, sequence_number) {
         event_key -> Varchar,
@@ -99,6 +399,21 @@ table! {
     }
 }
 
+table! {
+    token_activities_v2 (transaction_version, event_index) {
+        transaction_version -> Numeric,
+        event_index -> Int8,
+        token_data_id -> Varchar,
+        collection_id -> Nullable<Varchar>,
+        #[sql_name = "type"]
+        type_ -> Varchar,
+        from_address -> Nullable<Varchar>,
+        to_address -> Nullable<Varchar>,
+        transaction_timestamp -> Timestamp,
+        inserted_at -> Timestamp,
+    }
+}
+
 table! {
     token_datas (token_data_id) {
         token_data_id -> Varchar,
@@ -122,6 +437,21 @@ table! {
     }
 }
 
+table! {
+    token_metadata_cache (uri) {
+        uri -> Varchar,
+        resolved_uri -> Nullable<Text>,
+        metadata -> Nullable<Jsonb>,
+        image_uri -> Nullable<Text>,
+        image_content_type -> Nullable<Varchar>,
+        image_size_bytes -> Nullable<Int8>,
+        status -> Varchar,
+        error -> Nullable<Text>,
+        fetched_at -> Timestamp,
+        inserted_at -> Timestamp,
+    }
+}
+
 table! {
     token_propertys (token_id) {
         token_id -> Varchar,
@@ -134,6 +464,31 @@ table! {
     }
 }
 
+table! {
+    tokens_v2 (transaction_version, write_set_change_index) {
+        transaction_version -> Numeric,
+        write_set_change_index -> Int8,
+        token_data_id -> Varchar,
+        collection_id -> Varchar,
+        token_name -> Varchar,
+        description -> Text,
+        uri -> Varchar,
+        token_index -> Nullable<Numeric>,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    top_holders (asset_type, storage_id) {
+        asset_type -> Varchar,
+        storage_id -> Varchar,
+        owner_address -> Nullable<Varchar>,
+        amount -> Numeric,
+        last_transaction_version -> Numeric,
+        inserted_at -> Timestamp,
+    }
+}
+
 table! {
     transactions (hash) {
         #[sql_name = "type"]
@@ -148,6 +503,43 @@ table! {
         vm_status -> Text,
         accumulator_root_hash -> Varchar,
         inserted_at -> Timestamp,
+        num_events -> Int8,
+        num_write_set_changes -> Int8,
+        payload_size_bytes -> Nullable<Int8>,
+        timestamp -> Timestamp,
+    }
+}
+
+table! {
+    transfer_edges (transaction_version, event_index) {
+        transaction_version -> Numeric,
+        event_index -> Int8,
+        from_address -> Nullable<Varchar>,
+        to_address -> Nullable<Varchar>,
+        asset -> Varchar,
+        amount -> Numeric,
+        kind -> Varchar,
+        transaction_timestamp -> Timestamp,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    txn_latency_stat_batches (start_version, end_version) {
+        start_version -> Numeric,
+        end_version -> Numeric,
+        inserted_at -> Timestamp,
+    }
+}
+
+table! {
+    txn_latency_stats (period_start, latency_bucket_secs) {
+        period_start -> Timestamp,
+        latency_bucket_secs -> Int8,
+        num_transactions -> Int8,
+        total_latency_ms -> Int8,
+        inserted_at -> Timestamp,
+        last_updated -> Timestamp,
     }
 }
 
@@ -165,6 +557,18 @@ table! {
     }
 }
 
+table! {
+    validator_set_snapshots (epoch, validator_address) {
+        epoch -> Numeric,
+        validator_address -> Varchar,
+        status -> Varchar,
+        voting_power -> Numeric,
+        validator_index -> Nullable<Int8>,
+        consensus_pubkey -> Nullable<Varchar>,
+        inserted_at -> Timestamp,
+    }
+}
+
 table! {
     write_set_changes (transaction_hash, hash) {
         transaction_hash -> Varchar,
@@ -176,21 +580,56 @@ table! {
         resource -> Jsonb,
         data -> Jsonb,
         inserted_at -> Timestamp,
+        write_set_change_index -> Nullable<Int8>,
+        transaction_timestamp -> Nullable<Timestamp>,
     }
 }
 
 allow_tables_to_appear_in_same_query!(
+    account_summaries,
+    account_summary_batches,
+    api_keys,
     block_metadata_transactions,
     collections,
+    collections_v2,
+    current_object_ownerships,
+    current_token_ownerships_v2,
+    dex_swaps,
+    epochs,
+    event_types_seen,
     events,
+    function_reliability_stats,
+    fungible_asset_activities,
+    fungible_asset_balance_history,
+    fungible_asset_balances,
+    fungible_asset_metadata,
+    hourly_active_accounts,
+    hourly_activity_rollups,
+    ledger_info_history,
     ledger_infos,
     metadatas,
+    minute_transaction_rollups,
+    module_daily_stats,
+    objects,
+    online_migrations,
+    operator_audit_log,
+    outbox,
     ownerships,
     processor_statuses,
+    raw_transactions,
+    rollup_ranges,
     token_activities,
+    token_activities_v2,
     token_datas,
+    token_metadata_cache,
     token_propertys,
+    tokens_v2,
+    top_holders,
     transactions,
+    transfer_edges,
+    txn_latency_stat_batches,
+    txn_latency_stats,
     user_transactions,
+    validator_set_snapshots,
     write_set_changes,
 );
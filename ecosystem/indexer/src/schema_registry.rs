@@ -0,0 +1,354 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Confluent Schema Registry client, for the `kafka_processor`: registers schemas under subjects named by the
+//! configured strategy, and frames serialized messages in Confluent's wire format (a zero byte, the schema id as a
+//! big-endian u32, then the payload), so consumers can decode messages with the registry. Stream records are
+//! serialized in Avro (see `stream_record_avro_schema`), models in JSON with their JSON schemas (see
+//! `models::json_schema`). Only built with the `kafka` feature.
+
+use crate::{models::json_schema::schema_json, processors::stream_records::StreamRecord};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::HashMap, str::FromStr, sync::Mutex};
+
+/// First byte of messages in Confluent's wire format
+pub const MAGIC_BYTE: u8 = 0;
+
+/// Namespace of the models' record names, ex: "aptos.indexer.Transaction"
+pub const RECORD_NAMESPACE: &str = "aptos.indexer";
+
+/// Record name of `StreamRecord`s' Avro schema
+pub const STREAM_RECORD: &str = "StreamRecord";
+
+/// How subjects are named, as Confluent's serializers' `subject.name.strategy`
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubjectNameStrategy {
+    /// `<topic>-value`: one model per topic
+    TopicName,
+    /// `<namespace>.<model>`: a model has the same schema in every topic
+    RecordName,
+    /// `<topic>-<namespace>.<model>`: several models per topic
+    TopicRecordName,
+}
+
+impl SubjectNameStrategy {
+    pub fn subject(&self, topic: &str, model: &str) -> String {
+        match self {
+            Self::TopicName => format!("{}-value", topic),
+            Self::RecordName => format!("{}.{}", RECORD_NAMESPACE, model),
+            Self::TopicRecordName => format!("{}-{}.{}", topic, RECORD_NAMESPACE, model),
+        }
+    }
+}
+
+impl FromStr for SubjectNameStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "topic_name" => Self::TopicName,
+            "record_name" => Self::RecordName,
+            "topic_record_name" => Self::TopicRecordName,
+            _ => bail!(
+                "Unknown subject name strategy {}, expected topic_name, record_name or topic_record_name",
+                s
+            ),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SchemaRegistryConfig {
+    /// Base URL of the registry, ex: "http://localhost:8081"
+    pub url: String,
+    /// Basic auth credentials, ex: a Confluent Cloud API key and secret
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub subject_name_strategy: SubjectNameStrategy,
+}
+
+#[derive(Debug, Deserialize)]
+struct RegisteredSchema {
+    id: u32,
+}
+
+/// Registers schemas on first use and caches their ids
+#[derive(Debug)]
+pub struct SchemaRegistryClient {
+    config: SchemaRegistryConfig,
+    client: reqwest::Client,
+    /// Schema id of each subject registered so far
+    schema_ids: Mutex<HashMap<String, u32>>,
+}
+
+/// The standalone JSON schema of `model` (ex: "Transaction"), as registered
+pub fn model_schema(model: &str) -> Result<Value> {
+    let schema = schema_json();
+    let mut model_schema = schema["definitions"]
+        .get(model)
+        .cloned()
+        .with_context(|| format!("No schema for {}", model))?;
+    model_schema["$schema"] = schema["$schema"].clone();
+    model_schema["$id"] = json!(format!("{}.{}", RECORD_NAMESPACE, model));
+    Ok(model_schema)
+}
+
+/// The Avro schema of `StreamRecord`s, as registered. `data` is the transaction or event's JSON, as a string.
+pub fn stream_record_avro_schema() -> Value {
+    json!({
+        "type": "record",
+        "name": STREAM_RECORD,
+        "namespace": RECORD_NAMESPACE,
+        "fields": [
+            {"name": "kind", "type": "string"},
+            {"name": "version", "type": "long"},
+            {"name": "event_index", "type": ["null", "long"], "default": null},
+            {"name": "account", "type": "string"},
+            {"name": "data", "type": "string"},
+            {"name": "changes_omitted", "type": "boolean"},
+        ],
+    })
+}
+
+/// Appends `value` in Avro's encoding of longs: zigzag, then as a varint
+fn write_avro_long(buffer: &mut Vec<u8>, value: i64) {
+    let mut value = ((value << 1) ^ (value >> 63)) as u64;
+    while value >= 0x80 {
+        buffer.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn write_avro_string(buffer: &mut Vec<u8>, value: &str) {
+    write_avro_long(buffer, value.len() as i64);
+    buffer.extend_from_slice(value.as_bytes());
+}
+
+fn avro_long(value: u64) -> Result<i64> {
+    i64::try_from(value).with_context(|| format!("{} doesn't fit in an Avro long", value))
+}
+
+/// `record` in Avro's binary encoding, per `stream_record_avro_schema`
+pub fn avro_encode(record: &StreamRecord) -> Result<Vec<u8>> {
+    let data = serde_json::to_string(&record.data).context("Unable to serialize record")?;
+    let mut buffer = Vec::with_capacity(data.len() + record.account.len() + 32);
+    write_avro_string(&mut buffer, record.kind);
+    write_avro_long(&mut buffer, avro_long(record.version)?);
+    match record.event_index {
+        None => write_avro_long(&mut buffer, 0),
+        Some(event_index) => {
+            write_avro_long(&mut buffer, 1);
+            write_avro_long(&mut buffer, avro_long(event_index)?);
+        }
+    }
+    write_avro_string(&mut buffer, &record.account);
+    write_avro_string(&mut buffer, &data);
+    buffer.push(record.changes_omitted as u8);
+    Ok(buffer)
+}
+
+/// Frames `payload` in Confluent's wire format
+pub fn encode(schema_id: u32, payload: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(5 + payload.len());
+    message.push(MAGIC_BYTE);
+    message.extend_from_slice(&schema_id.to_be_bytes());
+    message.extend_from_slice(payload);
+    message
+}
+
+/// Schema id and payload of a message in Confluent's wire format
+pub fn decode(message: &[u8]) -> Result<(u32, &[u8])> {
+    if message.len() < 5 || message[0] != MAGIC_BYTE {
+        bail!("Not a schema registry framed message");
+    }
+    let schema_id = u32::from_be_bytes([message[1], message[2], message[3], message[4]]);
+    Ok((schema_id, &message[5..]))
+}
+
+impl SchemaRegistryClient {
+    pub fn new(config: SchemaRegistryConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+            schema_ids: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Registers the JSON schema of `model` for messages to `topic` (a no-op if the registry already has it) and
+    /// returns its id. Fails if the registry rejects it, ex: as incompatible with the subject's previous version.
+    pub async fn register(&self, topic: &str, model: &str) -> Result<u32> {
+        let subject = self.config.subject_name_strategy.subject(topic, model);
+        self.register_schema(subject, "JSON", || model_schema(model))
+            .await
+    }
+
+    /// Registers the Avro schema of stream records for messages to `topic`, as `register`
+    pub async fn register_stream_record(&self, topic: &str) -> Result<u32> {
+        let subject = self
+            .config
+            .subject_name_strategy
+            .subject(topic, STREAM_RECORD);
+        self.register_schema(subject, "AVRO", || Ok(stream_record_avro_schema()))
+            .await
+    }
+
+    async fn register_schema(
+        &self,
+        subject: String,
+        schema_type: &str,
+        schema: impl FnOnce() -> Result<Value>,
+    ) -> Result<u32> {
+        if let Some(schema_id) = self.schema_ids.lock().unwrap().get(&subject) {
+            return Ok(*schema_id);
+        }
+
+        let url = format!(
+            "{}/subjects/{}/versions",
+            self.config.url.trim_end_matches('/'),
+            subject
+        );
+        let mut request = self
+            .client
+            .post(url)
+            .header("Content-Type", "application/vnd.schemaregistry.v1+json")
+            .json(&json!({
+                "schemaType": schema_type,
+                "schema": schema()?.to_string(),
+            }));
+        if let Some(user) = &self.config.user {
+            request = request.basic_auth(user, self.config.password.as_ref());
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Failed to register the schema of {}", subject))?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!(
+                "The schema registry rejected the schema of {} ({}): {}",
+                subject,
+                status,
+                response.text().await.unwrap_or_default()
+            );
+        }
+        let schema_id = response.json::<RegisteredSchema>().await?.id;
+        self.schema_ids.lock().unwrap().insert(subject, schema_id);
+        Ok(schema_id)
+    }
+
+    /// Serializes `value`, a `model`, into a message for `topic`, registering its schema if needed
+    pub async fn serialize<T: Serialize>(
+        &self,
+        topic: &str,
+        model: &str,
+        value: &T,
+    ) -> Result<Vec<u8>> {
+        let schema_id = self.register(topic, model).await?;
+        Ok(encode(schema_id, &serde_json::to_vec(value)?))
+    }
+
+    /// Serializes `record` in Avro into a message for `topic`, registering its schema if needed
+    pub async fn serialize_stream_record(
+        &self,
+        topic: &str,
+        record: &StreamRecord,
+    ) -> Result<Vec<u8>> {
+        let schema_id = self.register_stream_record(topic).await?;
+        Ok(encode(schema_id, &avro_encode(record)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subject_names() {
+        let subject = |strategy: &str| {
+            SubjectNameStrategy::from_str(strategy)
+                .unwrap()
+                .subject("aptos.events", "Event")
+        };
+        assert_eq!(subject("topic_name"), "aptos.events-value");
+        assert_eq!(subject("record_name"), "aptos.indexer.Event");
+        assert_eq!(
+            subject("topic_record_name"),
+            "aptos.events-aptos.indexer.Event"
+        );
+        assert!(SubjectNameStrategy::from_str("topic").is_err());
+    }
+
+    #[test]
+    fn test_wire_format() {
+        let message = encode(258, b"{}");
+        assert_eq!(message, vec![0, 0, 0, 1, 2, b'{', b'}']);
+        assert_eq!(decode(&message).unwrap(), (258, &b"{}"[..]));
+        assert!(decode(b"{}").is_err());
+
+        let schema = model_schema("Event").unwrap();
+        assert_eq!(schema["$id"], "aptos.indexer.Event");
+        assert!(schema["properties"]["transaction_hash"].is_object());
+    }
+
+    #[test]
+    fn test_avro_encode() {
+        let record = StreamRecord {
+            kind: "event",
+            version: 300,
+            event_index: Some(1),
+            account: "0x1".to_string(),
+            data: json!({"a": -1}),
+            changes_omitted: false,
+        };
+        let mut expected = vec![10];
+        expected.extend_from_slice(b"event");
+        // 300 zigzags to 600, 0b100_1011000
+        expected.extend_from_slice(&[0xd8, 0x04]);
+        // The union's "long" branch, then 1
+        expected.extend_from_slice(&[2, 2]);
+        expected.push(6);
+        expected.extend_from_slice(b"0x1");
+        expected.push(16);
+        expected.extend_from_slice(br#"{"a":-1}"#);
+        expected.push(0);
+        assert_eq!(avro_encode(&record).unwrap(), expected);
+
+        let record = StreamRecord {
+            kind: "transaction",
+            event_index: None,
+            changes_omitted: true,
+            ..record
+        };
+        let encoded = avro_encode(&record).unwrap();
+        assert_eq!(encoded[12..15], [0xd8, 0x04, 0]);
+        assert_eq!(encoded.last(), Some(&1));
+
+        let mut buffer = vec![];
+        write_avro_long(&mut buffer, -1);
+        write_avro_long(&mut buffer, -65);
+        assert_eq!(buffer, vec![1, 0x81, 0x01]);
+
+        let schema = stream_record_avro_schema();
+        let fields: Vec<_> = schema["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| field["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                "kind",
+                "version",
+                "event_index",
+                "account",
+                "data",
+                "changes_omitted"
+            ]
+        );
+    }
+}
@@ -0,0 +1,253 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Resolves secrets referenced indirectly in the configuration, so credentials (ex: the password in the database
+//! URL) don't have to be passed in plaintext. A reference is one of:
+//! - `env://<NAME>`: the environment variable `NAME`
+//! - `file://<path>`: the contents of a file, ex: a mounted Kubernetes secret
+//! - `vault://<mount>/<path>#<key>`: the `key` field (`value` by default) of a Vault KV v2 secret, read from
+//!   `VAULT_ADDR` with `VAULT_TOKEN`
+//! - `awssm://<secret id>#<key>`: an AWS Secrets Manager secret, or the `key` field of a JSON secret, read in
+//!   `AWS_REGION` with the standard `AWS_*` credentials
+//! - `awskms://<ciphertext>`: a base64 ciphertext decrypted by AWS KMS in `AWS_REGION`, resolving to the base64
+//!   plaintext, ex: a data key encrypted under a KMS key
+//!
+//! Anything else is used as is. `Secret`s can be refreshed periodically, to pick up rotated credentials.
+
+use crate::aws::{signed_post_headers, AwsCredentials};
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+/// Field of Vault secrets read when a reference doesn't name one
+pub const DEFAULT_VAULT_KEY: &str = "value";
+
+const SCHEMES: &[&str] = &["env://", "file://", "vault://", "awssm://", "awskms://"];
+
+/// Whether `value` references a secret rather than being the value itself
+pub fn is_reference(value: &str) -> bool {
+    SCHEMES.iter().any(|scheme| value.starts_with(scheme))
+}
+
+/// Splits `<location>#<key>` into its location and optional key
+fn split_key(reference: &str) -> (&str, Option<&str>) {
+    match reference.split_once('#') {
+        Some((location, key)) => (location, Some(key)),
+        None => (reference, None),
+    }
+}
+
+/// Resolves `reference` (see the module's documentation), or returns it if it isn't a reference
+pub async fn resolve(reference: &str) -> Result<String> {
+    if let Some(name) = reference.strip_prefix("env://") {
+        std::env::var(name).with_context(|| format!("{} must be set", name))
+    } else if let Some(path) = reference.strip_prefix("file://") {
+        Ok(std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read the secret file {}", path))?
+            .trim_end()
+            .to_string())
+    } else if let Some(location) = reference.strip_prefix("vault://") {
+        let (path, key) = split_key(location);
+        resolve_vault(path, key.unwrap_or(DEFAULT_VAULT_KEY)).await
+    } else if let Some(location) = reference.strip_prefix("awssm://") {
+        let (secret_id, key) = split_key(location);
+        resolve_aws_secrets_manager(secret_id, key).await
+    } else if let Some(ciphertext) = reference.strip_prefix("awskms://") {
+        resolve_aws_kms(ciphertext).await
+    } else {
+        Ok(reference.to_string())
+    }
+}
+
+async fn resolve_vault(path: &str, key: &str) -> Result<String> {
+    let (mount, path) = path
+        .split_once('/')
+        .with_context(|| format!("Expected vault://<mount>/<path>, got vault://{}", path))?;
+    let addr = std::env::var("VAULT_ADDR").context("VAULT_ADDR must be set")?;
+    let token = std::env::var("VAULT_TOKEN").context("VAULT_TOKEN must be set")?;
+    let response = reqwest::Client::new()
+        .get(format!(
+            "{}/v1/{}/data/{}",
+            addr.trim_end_matches('/'),
+            mount,
+            path
+        ))
+        .header("X-Vault-Token", token)
+        .send()
+        .await?
+        .error_for_status()
+        .with_context(|| format!("Could not read the Vault secret {}/{}", mount, path))?;
+    let body: Value = response.json().await?;
+    body["data"]["data"][key]
+        .as_str()
+        .map(|value| value.to_string())
+        .with_context(|| format!("The Vault secret {}/{} has no {} field", mount, path, key))
+}
+
+/// Calls the `target` action of an AWS JSON API (ex: "secretsmanager.GetSecretValue") in `AWS_REGION`
+async fn call_aws_json_api(service: &str, target: &str, body: Value) -> Result<Value> {
+    let credentials = AwsCredentials::from_env()?;
+    let region = std::env::var("AWS_REGION")
+        .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+        .context("AWS_REGION must be set")?;
+    let host = format!("{}.{}.amazonaws.com", service, region);
+    let body = body.to_string();
+    let headers = signed_post_headers(
+        &credentials,
+        &region,
+        service,
+        &host,
+        &[
+            ("Content-Type", "application/x-amz-json-1.1"),
+            ("X-Amz-Target", target),
+        ],
+        body.as_bytes(),
+        chrono::Utc::now(),
+    );
+    let mut request = reqwest::Client::new().post(format!("https://{}/", host));
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    let response = request.body(body).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        bail!(
+            "{} failed ({}): {}",
+            target,
+            status,
+            response.text().await.unwrap_or_default()
+        );
+    }
+    Ok(response.json().await?)
+}
+
+async fn resolve_aws_secrets_manager(secret_id: &str, key: Option<&str>) -> Result<String> {
+    let body = call_aws_json_api(
+        "secretsmanager",
+        "secretsmanager.GetSecretValue",
+        json!({ "SecretId": secret_id }),
+    )
+    .await
+    .with_context(|| format!("Could not read the AWS secret {}", secret_id))?;
+    let secret = body["SecretString"]
+        .as_str()
+        .with_context(|| format!("The AWS secret {} isn't a string", secret_id))?;
+    match key {
+        Some(key) => {
+            let fields: Value = serde_json::from_str(secret)
+                .with_context(|| format!("The AWS secret {} isn't JSON", secret_id))?;
+            fields[key]
+                .as_str()
+                .map(|value| value.to_string())
+                .with_context(|| format!("The AWS secret {} has no {} field", secret_id, key))
+        }
+        None => Ok(secret.to_string()),
+    }
+}
+
+async fn resolve_aws_kms(ciphertext: &str) -> Result<String> {
+    let body = call_aws_json_api(
+        "kms",
+        "TrentService.Decrypt",
+        json!({ "CiphertextBlob": ciphertext }),
+    )
+    .await
+    .context("Could not decrypt with AWS KMS")?;
+    body["Plaintext"]
+        .as_str()
+        .map(|plaintext| plaintext.to_string())
+        .context("AWS KMS returned no plaintext")
+}
+
+/// A resolved secret, which can be re-resolved to pick up rotations
+pub struct Secret {
+    reference: String,
+    value: RwLock<String>,
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret {{ reference: {:?} }}", self.reference)
+    }
+}
+
+impl Secret {
+    pub async fn resolve(reference: impl Into<String>) -> Result<Arc<Self>> {
+        let reference = reference.into();
+        let value = resolve(&reference).await?;
+        Ok(Arc::new(Self {
+            reference,
+            value: RwLock::new(value),
+        }))
+    }
+
+    /// The latest resolved value
+    pub fn get(&self) -> String {
+        self.value.read().unwrap().clone()
+    }
+
+    /// Re-resolves the secret, returning whether its value changed
+    pub async fn refresh(&self) -> Result<bool> {
+        let value = resolve(&self.reference).await?;
+        let mut current = self.value.write().unwrap();
+        let changed = *current != value;
+        *current = value;
+        Ok(changed)
+    }
+
+    /// Refreshes the secret every `interval` in the background. Failures keep the previous value.
+    pub fn spawn_refresh(self: &Arc<Self>, interval: Duration) {
+        let secret = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                match secret.refresh().await {
+                    Ok(true) => aptos_logger::info!(
+                        reference = secret.reference.as_str(),
+                        "Refreshed a rotated secret"
+                    ),
+                    Ok(false) => (),
+                    Err(err) => aptos_logger::error!(
+                        reference = secret.reference.as_str(),
+                        error = format!("{:?}", err),
+                        "Failed to refresh a secret"
+                    ),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resolve() {
+        assert!(is_reference("vault://secret/indexer#pg_uri"));
+        assert!(!is_reference("postgresql://localhost/postgres"));
+        assert_eq!(
+            split_key("secret/indexer#pg_uri"),
+            ("secret/indexer", Some("pg_uri"))
+        );
+        assert_eq!(split_key("indexer/db"), ("indexer/db", None));
+
+        assert_eq!(
+            resolve("postgresql://localhost/postgres").await.unwrap(),
+            "postgresql://localhost/postgres"
+        );
+        std::env::set_var("INDEXER_TEST_SECRET", "hunter2");
+        let secret = Secret::resolve("env://INDEXER_TEST_SECRET").await.unwrap();
+        assert_eq!(secret.get(), "hunter2");
+        assert!(!secret.refresh().await.unwrap());
+        std::env::set_var("INDEXER_TEST_SECRET", "hunter3");
+        assert!(secret.refresh().await.unwrap());
+        assert_eq!(secret.get(), "hunter3");
+        assert!(!format!("{:?}", secret).contains("hunter"));
+
+        assert!(resolve("env://INDEXER_TEST_MISSING_SECRET").await.is_err());
+    }
+}
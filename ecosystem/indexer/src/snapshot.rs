@@ -0,0 +1,242 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Snapshots of the indexer's database, so new deployments can start from a recent version rather than reindexing
+//! from genesis. `create` runs `pg_dump` within an exported Postgres snapshot, so the dump and the watermark recorded
+//! in its manifest (the last version every processor has processed) are consistent. `restore` runs `pg_restore` into
+//! an empty database and checks the chain matches the manifest's.
+
+use crate::{database::PgPoolConnection, indexer::processor_metadata::query_start_version};
+use anyhow::{bail, ensure, Context, Result};
+use diesel::{prelude::*, sql_query, sql_types::Text};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Bumped when snapshots stop being restorable by older versions of `restore`
+pub const FORMAT_VERSION: u32 = 1;
+pub const MANIFEST_FILE: &str = "manifest.json";
+pub const DUMP_FILE: &str = "indexer.dump";
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SnapshotManifest {
+    pub format_version: u32,
+    pub chain_id: u64,
+    /// Every processor has processed all versions up to this one (inclusive)
+    pub watermark_version: u64,
+    /// The version each processor will resume from
+    pub processors: BTreeMap<String, u64>,
+    /// Version of the indexer which created the snapshot, for its migrations
+    pub indexer_version: String,
+    pub dump_file: String,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+impl SnapshotManifest {
+    pub fn read(dir: &Path) -> Result<Self> {
+        let path = dir.join(MANIFEST_FILE);
+        let file = std::fs::File::open(&path)
+            .with_context(|| format!("Could not open the manifest {:?}", path))?;
+        let manifest: Self = serde_json::from_reader(file)
+            .with_context(|| format!("Invalid manifest {:?}", path))?;
+        ensure!(
+            manifest.format_version <= FORMAT_VERSION,
+            "Snapshot format {} is newer than this indexer's ({})",
+            manifest.format_version,
+            FORMAT_VERSION
+        );
+        Ok(manifest)
+    }
+
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(MANIFEST_FILE);
+        let file = std::fs::File::create(&path)
+            .with_context(|| format!("Could not create the manifest {:?}", path))?;
+        serde_json::to_writer_pretty(file, self)
+            .with_context(|| format!("Could not write the manifest {:?}", path))
+    }
+}
+
+/// The last version processed by every processor, given the version each resumes from. `None` if no processor has
+/// processed anything (version 0 included).
+pub fn watermark_version(processors: &BTreeMap<String, u64>) -> Option<u64> {
+    processors
+        .values()
+        .min()
+        .and_then(|start_version| start_version.checked_sub(1))
+}
+
+#[derive(Debug, QueryableByName)]
+struct ExportedSnapshot {
+    #[sql_type = "Text"]
+    snapshot: String,
+}
+
+fn get_chain_id(conn: &PgPoolConnection) -> Result<Option<u64>> {
+    use crate::schema::ledger_infos;
+
+    Ok(ledger_infos::table
+        .select(ledger_infos::chain_id)
+        .first::<i64>(conn)
+        .optional()?
+        .map(|chain_id| chain_id as u64))
+}
+
+fn has_processor_statuses(conn: &PgPoolConnection) -> Result<bool> {
+    use crate::schema::processor_statuses::dsl;
+
+    #[derive(Debug, QueryableByName)]
+    struct Table {
+        #[sql_type = "diesel::sql_types::Bool"]
+        exists: bool,
+    }
+    let table: Table = sql_query("SELECT to_regclass('processor_statuses') IS NOT NULL AS exists")
+        .get_result(conn)?;
+    Ok(table.exists
+        && dsl::processor_statuses
+            .select(dsl::name)
+            .first::<String>(conn)
+            .optional()?
+            .is_some())
+}
+
+/// Runs a `pg_*` tool, failing if it doesn't exit successfully
+fn run_tool(command: &mut Command) -> Result<()> {
+    let program = format!("{:?}", command.get_program());
+    let status = command.status().with_context(|| {
+        format!(
+            "Could not run {} (is it installed and in the PATH?)",
+            program
+        )
+    })?;
+    ensure!(status.success(), "{} failed: {}", program, status);
+    Ok(())
+}
+
+/// Dumps the database into `dir` (created if needed) with `pg_dump`, along with a manifest describing it.
+/// `pg_uri` is passed to `pg_dump` as is.
+pub fn create(conn: &PgPoolConnection, pg_uri: &str, dir: &Path) -> Result<SnapshotManifest> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Could not create the snapshot directory {:?}", dir))?;
+    let dump_path = dir.join(DUMP_FILE);
+
+    // Everything's read within one snapshot, which `pg_dump` is pointed at. The snapshot only lives as long as this
+    // transaction, so it's held open until `pg_dump` exits.
+    let manifest = conn
+        .build_transaction()
+        .repeatable_read()
+        .read_only()
+        .run::<_, anyhow::Error, _>(|| {
+            let snapshot = sql_query("SELECT pg_export_snapshot() AS snapshot")
+                .get_result::<ExportedSnapshot>(conn)?
+                .snapshot;
+
+            let chain_id = get_chain_id(conn)?
+                .context("No chain id in ledger_infos: nothing has been indexed yet")?;
+            let names = {
+                use crate::schema::processor_statuses::dsl;
+                dsl::processor_statuses
+                    .select(dsl::name)
+                    .distinct()
+                    .load::<String>(conn)?
+            };
+            let mut processors = BTreeMap::new();
+            for name in names {
+                if let Some(start_version) = query_start_version(conn, &name)? {
+                    processors.insert(name, start_version);
+                }
+            }
+            let watermark_version = watermark_version(&processors)
+                .context("No processor has processed any version yet")?;
+
+            run_tool(
+                Command::new("pg_dump")
+                    .arg(format!("--dbname={}", pg_uri))
+                    .arg(format!("--snapshot={}", snapshot))
+                    .args(["--format=custom", "--no-owner", "--no-privileges"])
+                    .arg("--file")
+                    .arg(&dump_path),
+            )?;
+            Ok(SnapshotManifest {
+                format_version: FORMAT_VERSION,
+                chain_id,
+                watermark_version,
+                processors,
+                indexer_version: env!("CARGO_PKG_VERSION").to_string(),
+                dump_file: DUMP_FILE.to_string(),
+                created_at: chrono::Utc::now().naive_utc(),
+            })
+        })?;
+    manifest.write(dir)?;
+    Ok(manifest)
+}
+
+/// Restores the snapshot in `dir` with `pg_restore`. Refuses to if processors have already recorded statuses,
+/// unless `force`, in which case the snapshot's tables replace the existing ones.
+pub fn restore(
+    conn: &PgPoolConnection,
+    pg_uri: &str,
+    dir: &Path,
+    jobs: Option<u32>,
+    force: bool,
+) -> Result<SnapshotManifest> {
+    let manifest = SnapshotManifest::read(dir)?;
+    let dump_path: PathBuf = dir.join(&manifest.dump_file);
+    ensure!(dump_path.exists(), "Missing dump file {:?}", dump_path);
+
+    if has_processor_statuses(conn)? {
+        if !force {
+            bail!("The database already has processor statuses: restore into an empty database, or pass --force to replace its tables");
+        }
+        if let Some(chain_id) = get_chain_id(conn)? {
+            ensure!(
+                chain_id == manifest.chain_id,
+                "The database indexes chain {}, but the snapshot is of chain {}",
+                chain_id,
+                manifest.chain_id
+            );
+        }
+    }
+
+    let mut command = Command::new("pg_restore");
+    command.arg(format!("--dbname={}", pg_uri)).args([
+        "--no-owner",
+        "--no-privileges",
+        "--exit-on-error",
+    ]);
+    if force {
+        command.args(["--clean", "--if-exists"]);
+    }
+    if let Some(jobs) = jobs {
+        command.arg(format!("--jobs={}", jobs));
+    }
+    run_tool(command.arg(&dump_path))?;
+
+    let chain_id = get_chain_id(conn)?;
+    ensure!(
+        chain_id == Some(manifest.chain_id),
+        "Restored chain id {:?} doesn't match the manifest's ({})",
+        chain_id,
+        manifest.chain_id
+    );
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watermark_version() {
+        let mut processors = BTreeMap::new();
+        assert_eq!(watermark_version(&processors), None);
+        processors.insert("default_processor".to_string(), 1_001);
+        processors.insert("token_processor".to_string(), 950);
+        assert_eq!(watermark_version(&processors), Some(949));
+        processors.insert("coin_processor".to_string(), 0);
+        assert_eq!(watermark_version(&processors), None);
+    }
+}
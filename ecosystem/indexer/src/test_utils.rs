@@ -0,0 +1,462 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Deterministic transactions for processor tests, built programmatically rather than from JSON fixtures.
+//! `TransactionFactory` hands out consecutive versions, and keeps the chain's bookkeeping consistent across the
+//! transactions it builds: blocks advance the round and the timestamp, user transactions take their block's timestamp
+//! and their sender's next sequence number, and events take their stream's next sequence number. Hashes derive from
+//! versions and state keys, so the same calls always build the same transactions.
+//!
+//! ```ignore
+//! let mut factory = TransactionFactory::new(100);
+//! let block = factory.block_metadata("0xb10c");
+//! let transfer = factory
+//!     .user_transaction("0xa11ce")
+//!     .entry_function("0x1::coin::transfer", &["0x1::aptos_coin::AptosCoin"], vec![json!("0xb0b"), json!("10")])
+//!     .event(3, "0x1::coin::WithdrawEvent", json!({"amount": "10"}))
+//!     .event_from("0xb0b", 2, "0x1::coin::DepositEvent", json!({"amount": "10"}))
+//!     .change(write_resource("0xb0b", "0x1::coin::CoinStore<0x1::aptos_coin::AptosCoin>", json!({...})))
+//!     .build();
+//! ```
+
+use aptos_rest_client::{
+    aptos_api_types::{Event, WriteSetChange},
+    Transaction,
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Time between the blocks of a factory
+pub const BLOCK_INTERVAL_USECS: u64 = 1_000_000;
+/// Timestamp of the first block after genesis
+pub const FIRST_BLOCK_TIMESTAMP_USECS: u64 = 1_665_000_000_000_000;
+/// Gas of user transactions, unless set otherwise
+pub const DEFAULT_GAS_USED: u64 = 10;
+pub const DEFAULT_GAS_UNIT_PRICE: u64 = 100;
+pub const DEFAULT_MAX_GAS_AMOUNT: u64 = 2000;
+/// Creation number of the framework's `NewBlockEvent` stream
+const NEW_BLOCK_EVENT_CREATION_NUMBER: u64 = 3;
+
+fn hash_hex(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    format!("0x{}", hex::encode(hasher.finalize()))
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(value: Value) -> T {
+    serde_json::from_value(value).expect("The factory builds valid API types")
+}
+
+/// A resource written at `address`, ex: `write_resource("0xa11ce", "0x1::account::Account", json!({...}))`
+pub fn write_resource(address: &str, typ: &str, data: Value) -> WriteSetChange {
+    from_json(json!({
+        "type": "write_resource",
+        "address": address,
+        "state_key_hash": hash_hex(&["resource", address, typ]),
+        "data": {"type": typ, "data": data},
+    }))
+}
+
+pub fn delete_resource(address: &str, typ: &str) -> WriteSetChange {
+    from_json(json!({
+        "type": "delete_resource",
+        "address": address,
+        "state_key_hash": hash_hex(&["resource", address, typ]),
+        "resource": typ,
+    }))
+}
+
+/// An item written to the table at `handle`, with its decoded key and value (as a node with the table info indexer
+/// serves them). The raw key and value are their JSON's bytes, which processors don't decode.
+pub fn write_table_item(
+    handle: &str,
+    key: Value,
+    key_type: &str,
+    value: Value,
+    value_type: &str,
+) -> WriteSetChange {
+    let key_bytes = key.to_string();
+    from_json(json!({
+        "type": "write_table_item",
+        "state_key_hash": hash_hex(&["table_item", handle, &key_bytes]),
+        "handle": handle,
+        "key": format!("0x{}", hex::encode(&key_bytes)),
+        "value": format!("0x{}", hex::encode(value.to_string())),
+        "data": {
+            "key": key,
+            "key_type": key_type,
+            "value": value,
+            "value_type": value_type,
+        },
+    }))
+}
+
+pub fn delete_table_item(handle: &str, key: Value, key_type: &str) -> WriteSetChange {
+    let key_bytes = key.to_string();
+    from_json(json!({
+        "type": "delete_table_item",
+        "state_key_hash": hash_hex(&["table_item", handle, &key_bytes]),
+        "handle": handle,
+        "key": format!("0x{}", hex::encode(&key_bytes)),
+        "data": {"key": key, "key_type": key_type},
+    }))
+}
+
+/// Builds consecutive transactions from a starting version
+#[derive(Debug)]
+pub struct TransactionFactory {
+    next_version: u64,
+    epoch: u64,
+    round: u64,
+    timestamp_usecs: u64,
+    /// Next sequence number of each sender
+    sequence_numbers: HashMap<String, u64>,
+    /// Next sequence number of each event stream, by account and creation number
+    event_sequence_numbers: HashMap<(String, u64), u64>,
+}
+
+impl Default for TransactionFactory {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl TransactionFactory {
+    pub fn new(start_version: u64) -> Self {
+        Self {
+            next_version: start_version,
+            epoch: 1,
+            round: 0,
+            timestamp_usecs: FIRST_BLOCK_TIMESTAMP_USECS - BLOCK_INTERVAL_USECS,
+            sequence_numbers: HashMap::new(),
+            event_sequence_numbers: HashMap::new(),
+        }
+    }
+
+    /// Version of the next transaction built
+    pub fn next_version(&self) -> u64 {
+        self.next_version
+    }
+
+    /// Timestamp of the current block
+    pub fn timestamp_usecs(&self) -> u64 {
+        self.timestamp_usecs
+    }
+
+    /// The next event of the stream `creation_number` of `account`
+    pub fn event(&mut self, account: &str, creation_number: u64, typ: &str, data: Value) -> Event {
+        let sequence_number = self
+            .event_sequence_numbers
+            .entry((account.to_string(), creation_number))
+            .or_default();
+        let event = from_json(json!({
+            "guid": {"creation_number": creation_number.to_string(), "account_address": account},
+            "sequence_number": sequence_number.to_string(),
+            "type": typ,
+            "data": data,
+        }));
+        *sequence_number += 1;
+        event
+    }
+
+    /// The fields every committed transaction has, for the next version
+    fn info(
+        &mut self,
+        gas_used: u64,
+        vm_status: Option<&str>,
+        changes: &[WriteSetChange],
+    ) -> serde_json::Map<String, Value> {
+        let version = self.next_version;
+        self.next_version += 1;
+        let info = json!({
+            "version": version.to_string(),
+            "hash": format!("0x{:064x}", version),
+            "state_change_hash": hash_hex(&["state_change", &version.to_string()]),
+            "event_root_hash": hash_hex(&["event_root", &version.to_string()]),
+            "gas_used": gas_used.to_string(),
+            "success": vm_status.is_none(),
+            "vm_status": vm_status.unwrap_or("Executed successfully"),
+            "accumulator_root_hash": hash_hex(&["accumulator_root", &version.to_string()]),
+            "changes": changes,
+        });
+        match info {
+            Value::Object(info) => info,
+            _ => unreachable!(),
+        }
+    }
+
+    fn transaction(mut info: serde_json::Map<String, Value>, fields: Value) -> Transaction {
+        if let Value::Object(fields) = fields {
+            info.extend(fields);
+        }
+        from_json(Value::Object(info))
+    }
+
+    /// The genesis transaction, writing `changes` and emitting `events`
+    pub fn genesis(&mut self, changes: Vec<WriteSetChange>, events: Vec<Event>) -> Transaction {
+        let info = self.info(0, None, &changes);
+        Self::transaction(
+            info,
+            json!({
+                "type": "genesis_transaction",
+                "payload": {
+                    "type": "write_set_payload",
+                    "write_set": {
+                        "type": "direct_write_set",
+                        "changes": changes,
+                        "events": events,
+                    },
+                },
+                "events": events,
+            }),
+        )
+    }
+
+    /// Starts a new block proposed by `proposer`, one `BLOCK_INTERVAL_USECS` after the previous one
+    pub fn block_metadata(&mut self, proposer: &str) -> Transaction {
+        self.round += 1;
+        self.timestamp_usecs += BLOCK_INTERVAL_USECS;
+        let (epoch, round, timestamp_usecs) = (self.epoch, self.round, self.timestamp_usecs);
+        let id = hash_hex(&["block", &epoch.to_string(), &round.to_string()]);
+        let event = self.event(
+            "0x1",
+            NEW_BLOCK_EVENT_CREATION_NUMBER,
+            "0x1::block::NewBlockEvent",
+            json!({
+                "hash": id,
+                "epoch": epoch.to_string(),
+                "round": round.to_string(),
+                "height": round.to_string(),
+                "previous_block_votes_bitvec": "0x",
+                "proposer": proposer,
+                "failed_proposer_indices": [],
+                "time_microseconds": timestamp_usecs.to_string(),
+            }),
+        );
+        let info = self.info(0, None, &[]);
+        Self::transaction(
+            info,
+            json!({
+                "type": "block_metadata_transaction",
+                "id": id,
+                "epoch": epoch.to_string(),
+                "round": round.to_string(),
+                "events": [event],
+                "previous_block_votes_bitvec": [],
+                "proposer": proposer,
+                "failed_proposer_indices": [],
+                "timestamp": timestamp_usecs.to_string(),
+            }),
+        )
+    }
+
+    /// Ends the current block
+    pub fn state_checkpoint(&mut self) -> Transaction {
+        let timestamp_usecs = self.timestamp_usecs;
+        let info = self.info(0, None, &[]);
+        Self::transaction(
+            info,
+            json!({
+                "type": "state_checkpoint_transaction",
+                "timestamp": timestamp_usecs.to_string(),
+            }),
+        )
+    }
+
+    /// A user transaction sent by `sender` in the current block, calling `0x1::aptos_account::transfer` unless
+    /// given another payload
+    pub fn user_transaction(&mut self, sender: &str) -> UserTransactionBuilder<'_> {
+        UserTransactionBuilder {
+            factory: self,
+            sender: sender.to_string(),
+            payload: json!({
+                "type": "entry_function_payload",
+                "function": "0x1::aptos_account::transfer",
+                "type_arguments": [],
+                "arguments": [],
+            }),
+            gas_used: DEFAULT_GAS_USED,
+            gas_unit_price: DEFAULT_GAS_UNIT_PRICE,
+            vm_status: None,
+            changes: vec![],
+            events: vec![],
+        }
+    }
+}
+
+pub struct UserTransactionBuilder<'a> {
+    factory: &'a mut TransactionFactory,
+    sender: String,
+    payload: Value,
+    gas_used: u64,
+    gas_unit_price: u64,
+    /// Set if the transaction failed
+    vm_status: Option<String>,
+    changes: Vec<WriteSetChange>,
+    events: Vec<Event>,
+}
+
+impl<'a> UserTransactionBuilder<'a> {
+    /// Calls `function`, ex: `0x1::coin::transfer`
+    pub fn entry_function(
+        mut self,
+        function: &str,
+        type_arguments: &[&str],
+        arguments: Vec<Value>,
+    ) -> Self {
+        self.payload = json!({
+            "type": "entry_function_payload",
+            "function": function,
+            "type_arguments": type_arguments,
+            "arguments": arguments,
+        });
+        self
+    }
+
+    pub fn gas_used(mut self, gas_used: u64) -> Self {
+        self.gas_used = gas_used;
+        self
+    }
+
+    pub fn gas_unit_price(mut self, gas_unit_price: u64) -> Self {
+        self.gas_unit_price = gas_unit_price;
+        self
+    }
+
+    /// Fails the transaction with `vm_status`, ex: `Move abort in 0x1::coin: EINSUFFICIENT_BALANCE(0x10006)`
+    pub fn failed(mut self, vm_status: &str) -> Self {
+        self.vm_status = Some(vm_status.to_string());
+        self
+    }
+
+    pub fn change(mut self, change: WriteSetChange) -> Self {
+        self.changes.push(change);
+        self
+    }
+
+    /// Emits the next event of the sender's stream `creation_number`
+    pub fn event(self, creation_number: u64, typ: &str, data: Value) -> Self {
+        let sender = self.sender.clone();
+        self.event_from(&sender, creation_number, typ, data)
+    }
+
+    /// Emits the next event of `account`'s stream `creation_number`
+    pub fn event_from(
+        mut self,
+        account: &str,
+        creation_number: u64,
+        typ: &str,
+        data: Value,
+    ) -> Self {
+        let event = self.factory.event(account, creation_number, typ, data);
+        self.events.push(event);
+        self
+    }
+
+    pub fn build(self) -> Transaction {
+        let factory = self.factory;
+        let sequence_number = factory
+            .sequence_numbers
+            .entry(self.sender.clone())
+            .or_default();
+        *sequence_number += 1;
+        let fields = json!({
+            "type": "user_transaction",
+            "sender": self.sender,
+            "sequence_number": (*sequence_number - 1).to_string(),
+            "max_gas_amount": DEFAULT_MAX_GAS_AMOUNT.to_string(),
+            "gas_unit_price": self.gas_unit_price.to_string(),
+            "expiration_timestamp_secs": (factory.timestamp_usecs / 1_000_000 + 600).to_string(),
+            "payload": self.payload,
+            "events": self.events,
+            "timestamp": factory.timestamp_usecs.to_string(),
+        });
+        let info = factory.info(self.gas_used, self.vm_status.as_deref(), &self.changes);
+        TransactionFactory::transaction(info, fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(factory: &mut TransactionFactory) -> Vec<Transaction> {
+        vec![
+            factory.genesis(
+                vec![write_resource(
+                    "0x1",
+                    "0x1::chain_id::ChainId",
+                    json!({"id": 4}),
+                )],
+                vec![],
+            ),
+            factory.block_metadata("0xb10c"),
+            factory
+                .user_transaction("0xa11ce")
+                .entry_function(
+                    "0x1::coin::transfer",
+                    &["0x1::aptos_coin::AptosCoin"],
+                    vec![json!("0xb0b"), json!("10")],
+                )
+                .event(3, "0x1::coin::WithdrawEvent", json!({"amount": "10"}))
+                .event_from(
+                    "0xb0b",
+                    2,
+                    "0x1::coin::DepositEvent",
+                    json!({"amount": "10"}),
+                )
+                .change(write_table_item(
+                    "0x7ab1e",
+                    json!("0xb0b"),
+                    "address",
+                    json!("10"),
+                    "u64",
+                ))
+                .build(),
+            factory
+                .user_transaction("0xa11ce")
+                .failed("Out of gas")
+                .build(),
+            factory.state_checkpoint(),
+            factory.block_metadata("0xb10c"),
+        ]
+    }
+
+    #[test]
+    fn test_transaction_factory() {
+        let txns = build(&mut TransactionFactory::default());
+        assert_eq!(txns, build(&mut TransactionFactory::default()));
+        assert_eq!(
+            txns.iter().map(|txn| txn.version()).collect::<Vec<_>>(),
+            (0..6).map(Some).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            txns.iter().map(|txn| txn.timestamp()).collect::<Vec<_>>(),
+            vec![
+                0,
+                FIRST_BLOCK_TIMESTAMP_USECS,
+                FIRST_BLOCK_TIMESTAMP_USECS,
+                FIRST_BLOCK_TIMESTAMP_USECS,
+                FIRST_BLOCK_TIMESTAMP_USECS,
+                FIRST_BLOCK_TIMESTAMP_USECS + BLOCK_INTERVAL_USECS,
+            ]
+        );
+        assert!(txns[2].success());
+        assert!(!txns[3].success());
+
+        let json = serde_json::to_value(&txns).unwrap();
+        // Sequence numbers count per sender, and per event stream
+        assert_eq!(json[2]["sequence_number"], "0");
+        assert_eq!(json[3]["sequence_number"], "1");
+        assert_eq!(json[1]["events"][0]["sequence_number"], "0");
+        assert_eq!(json[5]["events"][0]["sequence_number"], "1");
+        assert_eq!(json[5]["round"], "2");
+        assert_eq!(json[2]["events"][1]["guid"]["account_address"], "0xb0b");
+        assert_eq!(json[2]["changes"][0]["data"]["value_type"], "u64");
+        assert_eq!(json[2]["payload"]["function"], "0x1::coin::transfer");
+    }
+}
@@ -0,0 +1,162 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! Optional TimescaleDB integration, for deployments on Postgres with the Timescale extension. `setup` converts
+//! `transactions` and `events` into hypertables partitioned by block timestamp and creates continuous aggregates
+//! of the transaction rollups. It runs after the migrations and is idempotent, so it can run on every start.
+//!
+//! Hypertables require every unique constraint to include their time column, and can't be referenced by foreign
+//! keys, so `setup` appends the time column to the tables' primary keys and unique constraints, and drops the
+//! foreign keys to `transactions`. A transaction always has the same timestamp, so this doesn't let duplicates in,
+//! and the processors' inserts (`ON CONFLICT DO NOTHING`, without a conflict target) work unchanged.
+
+use crate::database::{with_advisory_lock, PgPoolConnection, MIGRATIONS_LOCK_KEY};
+use anyhow::{ensure, Context, Result};
+use diesel::{connection::SimpleConnection, prelude::*, sql_query, sql_types::Text};
+
+/// Tables converted into hypertables, with their time column, in order
+pub const HYPERTABLES: &[(&str, &str)] = &[
+    ("transactions", "timestamp"),
+    ("events", "transaction_timestamp"),
+];
+
+/// Time range of each hypertable chunk
+pub const CHUNK_TIME_INTERVAL: &str = "1 day";
+
+/// Continuous aggregates of `transactions`, with the width of their buckets. Timescale doesn't support joins and
+/// distinct counts in continuous aggregates, so `hourly_activity_rollups.num_active_accounts` has no equivalent: the
+/// rollup task is still needed for it.
+pub const CONTINUOUS_AGGREGATES: &[(&str, &str)] = &[
+    ("minute_transaction_aggregates", "1 minute"),
+    ("hourly_transaction_aggregates", "1 hour"),
+];
+
+#[derive(Debug, QueryableByName)]
+struct Exists {
+    #[sql_type = "diesel::sql_types::Bool"]
+    exists: bool,
+}
+
+pub(crate) fn is_hypertable(conn: &PgPoolConnection, table: &str) -> QueryResult<bool> {
+    let result: Exists = sql_query(
+        "SELECT EXISTS (SELECT 1 FROM timescaledb_information.hypertables WHERE hypertable_name = $1) AS exists",
+    )
+    .bind::<Text, _>(table)
+    .get_result(conn)?;
+    Ok(result.exists)
+}
+
+/// Drops the foreign keys referencing `table`, and adds `time_column` to its primary key and unique constraints
+fn prepare_hypertable_sql(table: &str, time_column: &str) -> String {
+    format!(
+        r#"
+DO $$
+DECLARE
+    c RECORD;
+BEGIN
+    FOR c IN
+        SELECT conrelid::regclass AS referencing_table, conname
+        FROM pg_constraint
+        WHERE confrelid = '{table}'::regclass AND contype = 'f'
+    LOOP
+        EXECUTE format('ALTER TABLE %s DROP CONSTRAINT %I', c.referencing_table, c.conname);
+    END LOOP;
+
+    FOR c IN
+        SELECT con.conname, con.contype, string_agg(quote_ident(a.attname), ', ' ORDER BY k.ord) AS columns
+        FROM pg_constraint con
+        CROSS JOIN LATERAL unnest(con.conkey) WITH ORDINALITY AS k(attnum, ord)
+        JOIN pg_attribute a ON a.attrelid = con.conrelid AND a.attnum = k.attnum
+        WHERE con.conrelid = '{table}'::regclass AND con.contype IN ('p', 'u')
+        GROUP BY con.conname, con.contype
+        HAVING NOT bool_or(a.attname = '{time_column}')
+    LOOP
+        EXECUTE format('ALTER TABLE {table} DROP CONSTRAINT %I', c.conname);
+        EXECUTE format(
+            'ALTER TABLE {table} ADD CONSTRAINT %I %s (%s, {time_column})',
+            c.conname,
+            CASE c.contype WHEN 'p' THEN 'PRIMARY KEY' ELSE 'UNIQUE' END,
+            c.columns
+        );
+    END LOOP;
+END $$;
+"#,
+        table = table,
+        time_column = time_column
+    )
+}
+
+fn continuous_aggregate_sql(view: &str, bucket_width: &str) -> String {
+    format!(
+        r#"
+CREATE MATERIALIZED VIEW IF NOT EXISTS {view}
+WITH (timescaledb.continuous) AS
+SELECT time_bucket(INTERVAL '{bucket_width}', timestamp) AS bucket,
+       COUNT(*) AS num_transactions,
+       SUM(CASE WHEN type = 'user_transaction' THEN 1 ELSE 0 END) AS num_user_transactions,
+       SUM(CASE WHEN success THEN 0 ELSE 1 END) AS num_failed_transactions,
+       SUM(gas_used) AS gas_used
+FROM transactions
+GROUP BY bucket
+WITH NO DATA;
+
+SELECT add_continuous_aggregate_policy(
+    '{view}',
+    start_offset => INTERVAL '1 day',
+    end_offset => INTERVAL '{bucket_width}',
+    schedule_interval => INTERVAL '{bucket_width}',
+    if_not_exists => true
+);
+"#,
+        view = view,
+        bucket_width = bucket_width
+    )
+}
+
+/// Creates the Timescale extension if needed, then the hypertables and continuous aggregates which don't exist yet.
+/// Converting tables with rows moves them into chunks, which locks the tables for a while. Holds
+/// `MIGRATIONS_LOCK_KEY`, so replicas starting at the same time set up once.
+pub fn setup(conn: &PgPoolConnection) -> Result<()> {
+    with_advisory_lock(conn, MIGRATIONS_LOCK_KEY, || {
+        conn.batch_execute("CREATE EXTENSION IF NOT EXISTS timescaledb")
+            .context("Could not create the timescaledb extension: is TimescaleDB installed?")?;
+
+        for (table, time_column) in HYPERTABLES {
+            if is_hypertable(conn, table)? {
+                continue;
+            }
+            // Hypertables' time column is NOT NULL, and rows indexed before `events.transaction_timestamp` existed
+            // are NULL until re-processed
+            let has_nulls: Exists = sql_query(&format!(
+                "SELECT EXISTS (SELECT 1 FROM {} WHERE {} IS NULL) AS exists",
+                table, time_column
+            ))
+            .get_result(conn)?;
+            ensure!(
+                !has_nulls.exists,
+                "{}.{} has NULLs: re-process the versions indexed before it was added (see the README) before \
+                 converting {} to a hypertable",
+                table,
+                time_column,
+                table
+            );
+            aptos_logger::info!(table = table, "Converting to a hypertable...");
+            conn.transaction::<_, anyhow::Error, _>(|| {
+                conn.batch_execute(&prepare_hypertable_sql(table, time_column))?;
+                conn.batch_execute(&format!(
+                    "SELECT create_hypertable('{}', '{}', chunk_time_interval => INTERVAL '{}', migrate_data => true)",
+                    table, time_column, CHUNK_TIME_INTERVAL
+                ))?;
+                Ok(())
+            })
+            .with_context(|| format!("Could not convert {} to a hypertable", table))?;
+        }
+
+        // Continuous aggregates can't be created within a transaction
+        for (view, bucket_width) in CONTINUOUS_AGGREGATES {
+            conn.batch_execute(&continuous_aggregate_sql(view, bucket_width))
+                .with_context(|| format!("Could not create the continuous aggregate {}", view))?;
+        }
+        Ok(())
+    })
+}
@@ -2,13 +2,43 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use bigdecimal::{FromPrimitive, Signed, ToPrimitive, Zero};
+use std::fmt;
 
+/// Every u64 is representable, so unlike `bigdecimal_to_u64` this can't fail
 pub fn u64_to_bigdecimal(val: u64) -> bigdecimal::BigDecimal {
     bigdecimal::BigDecimal::from_u64(val).expect("Unable to convert u64 to big decimal")
 }
 
-pub fn bigdecimal_to_u64(val: &bigdecimal::BigDecimal) -> u64 {
-    val.to_u64().expect("Unable to convert big decimal to u64")
+/// Why a numeric couldn't be converted to a u64, ex: a version column corrupted by a manual edit
+#[derive(Clone, Debug, PartialEq)]
+pub enum BigDecimalConversionError {
+    Negative(bigdecimal::BigDecimal),
+    NotInteger(bigdecimal::BigDecimal),
+    TooLarge(bigdecimal::BigDecimal),
+}
+
+impl fmt::Display for BigDecimalConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Negative(val) => write!(f, "{} is negative", val),
+            Self::NotInteger(val) => write!(f, "{} is not an integer", val),
+            Self::TooLarge(val) => write!(f, "{} is out of the range of a u64", val),
+        }
+    }
+}
+
+impl std::error::Error for BigDecimalConversionError {}
+
+/// Fails if `val` is negative, too large for a u64 or has a fractional part, ex: a corrupted version column
+pub fn bigdecimal_to_u64(val: &bigdecimal::BigDecimal) -> Result<u64, BigDecimalConversionError> {
+    if val.is_negative() {
+        return Err(BigDecimalConversionError::Negative(val.clone()));
+    }
+    if val.with_scale(0) != *val {
+        return Err(BigDecimalConversionError::NotInteger(val.clone()));
+    }
+    val.to_u64()
+        .ok_or_else(|| BigDecimalConversionError::TooLarge(val.clone()))
 }
 
 pub fn ensure_not_negative(val: bigdecimal::BigDecimal) -> bigdecimal::BigDecimal {
@@ -17,3 +47,53 @@ pub fn ensure_not_negative(val: bigdecimal::BigDecimal) -> bigdecimal::BigDecima
     }
     val
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_bigdecimal_to_u64_edge_values() {
+        assert_eq!(bigdecimal_to_u64(&u64_to_bigdecimal(0)).unwrap(), 0);
+        assert_eq!(
+            bigdecimal_to_u64(&u64_to_bigdecimal(u64::MAX)).unwrap(),
+            u64::MAX
+        );
+        assert_eq!(
+            bigdecimal_to_u64(&bigdecimal::BigDecimal::from_str("42.000").unwrap()).unwrap(),
+            42
+        );
+        assert_eq!(
+            bigdecimal_to_u64(&bigdecimal::BigDecimal::from_str("-1").unwrap()),
+            Err(BigDecimalConversionError::Negative(
+                bigdecimal::BigDecimal::from_str("-1").unwrap()
+            ))
+        );
+        assert!(bigdecimal_to_u64(
+            &bigdecimal::BigDecimal::from_str("18446744073709551616").unwrap()
+        )
+        .is_err());
+        assert!(bigdecimal_to_u64(&bigdecimal::BigDecimal::from_str("1.5").unwrap()).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn test_u64_bigdecimal_round_trip(val in any::<u64>()) {
+            prop_assert_eq!(bigdecimal_to_u64(&u64_to_bigdecimal(val)).unwrap(), val);
+        }
+
+        #[test]
+        fn test_negative_bigdecimal_to_u64_fails(val in 1..=i64::MAX) {
+            let val = bigdecimal::BigDecimal::from_i64(-val).unwrap();
+            prop_assert!(bigdecimal_to_u64(&val).is_err());
+        }
+
+        #[test]
+        fn test_too_large_bigdecimal_to_u64_fails(excess in 1..=u64::MAX) {
+            let val = u64_to_bigdecimal(u64::MAX) + u64_to_bigdecimal(excess);
+            prop_assert!(bigdecimal_to_u64(&val).is_err());
+        }
+    }
+}
@@ -127,6 +127,16 @@ impl LedgerStore {
             .ok_or_else(|| AptosDbError::NotFound(String::from("Genesis LedgerInfo")).into())
     }
 
+    /// Re-reads the latest ledger info from the DB, ex: after a secondary instance caught up with the primary
+    pub fn reload_latest_ledger_info(&self) -> Result<()> {
+        let mut iter = self.db.iter::<LedgerInfoSchema>(ReadOptions::default())?;
+        iter.seek_to_last();
+        if let Some((_, ledger_info)) = iter.next().transpose()? {
+            self.set_latest_ledger_info(ledger_info);
+        }
+        Ok(())
+    }
+
     pub fn set_latest_ledger_info(&self, ledger_info_with_sigs: LedgerInfoWithSignatures) {
         self.latest_ledger_info
             .store(Arc::new(Some(ledger_info_with_sigs)));
@@ -458,6 +458,14 @@ impl AptosDB {
         ))
     }
 
+    /// Catches a DB opened with `open_as_secondary` up with the node writing to it, so reads see what it committed
+    /// since
+    pub fn try_catch_up_with_primary(&self) -> Result<()> {
+        self.ledger_db.try_catch_up_with_primary()?;
+        self.state_merkle_db.try_catch_up_with_primary()?;
+        self.ledger_store.reload_latest_ledger_info()
+    }
+
     #[cfg(any(test, feature = "fuzzing"))]
     fn new_without_pruner<P: AsRef<Path> + Clone>(
         db_root_path: P,
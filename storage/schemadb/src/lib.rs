@@ -286,6 +286,12 @@ impl DB {
         Ok(Self::log_construct(name, inner))
     }
 
+    /// Catches up a secondary instance (see `open_cf_as_secondary`) with what the primary instance wrote since
+    pub fn try_catch_up_with_primary(&self) -> Result<()> {
+        self.inner.try_catch_up_with_primary()?;
+        Ok(())
+    }
+
     fn log_construct(name: &'static str, inner: rocksdb::DB) -> DB {
         info!(rocksdb_name = name, "Opened RocksDB.");
         DB { name, inner }
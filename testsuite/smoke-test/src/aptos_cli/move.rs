@@ -5,6 +5,7 @@ use crate::smoke_test_environment::SwarmBuilder;
 use aptos::move_tool::MemberId;
 use aptos::test::CliTestFramework;
 use aptos_logger::info;
+use forge::{NodeExt, Swarm};
 use framework::{BuildOptions, BuiltPackage};
 use move_deps::move_core_types::account_address::AccountAddress;
 use move_deps::move_package::source_package::manifest_parser::parse_move_manifest_from_file;
@@ -14,6 +15,27 @@ use std::str::FromStr;
 
 const PACKAGE_NAME: &str = "AwesomePackage";
 const HELLO_BLOCKCHAIN: &str = "hello_blockchain";
+const COUNTER: &str = "counter";
+
+const COUNTER_MODULE: &str = r#"
+module counter::counter {
+    use std::signer;
+
+    struct Counter has key {
+        value: u64,
+    }
+
+    public entry fun increment(account: &signer, by: u64) acquires Counter {
+        let addr = signer::address_of(account);
+        if (exists<Counter>(addr)) {
+            let counter = borrow_global_mut<Counter>(addr);
+            counter.value = counter.value + by;
+        } else {
+            move_to(account, Counter { value: by });
+        }
+    }
+}
+"#;
 
 fn aptos_framework_dir() -> PathBuf {
     PathBuf::from(env!("CARGO_MANIFEST_DIR"))
@@ -167,3 +189,62 @@ async fn test_move_publish_flow() {
         ),
     };
 }
+
+#[tokio::test]
+async fn test_publish_and_call_custom_module() {
+    let (swarm, mut cli, _faucet) = SwarmBuilder::new_local(1)
+        .with_aptos()
+        .build_with_cli(1)
+        .await;
+    let account = cli.account_id(0);
+    let account_str = account.to_hex_literal();
+
+    cli.init_move_dir();
+    let mut package_addresses = BTreeMap::new();
+    package_addresses.insert(COUNTER, "_");
+    cli.init_package(
+        "Counter".to_string(),
+        package_addresses,
+        Some(aptos_framework_dir()),
+    )
+    .await
+    .expect("Should succeed");
+    cli.add_move_source(COUNTER, COUNTER_MODULE);
+    cli.wait_for_account(0)
+        .await
+        .expect("Should create account");
+
+    let mut named_addresses = BTreeMap::new();
+    named_addresses.insert(COUNTER, account_str.as_str());
+    let publish = cli
+        .publish_package(0, None, named_addresses, false, None)
+        .await
+        .expect("Should publish the package");
+    assert_eq!(publish.success, Some(true));
+
+    let increment = format!("{}::counter::increment", account_str);
+    for by in ["u64:3", "u64:4"] {
+        let summary = cli
+            .run_entry_function(0, &increment, vec![by], vec![])
+            .await
+            .expect("Should increment the counter");
+        assert_eq!(summary.success, Some(true));
+    }
+    // Missing argument
+    assert!(cli
+        .run_entry_function(0, &increment, vec![], vec![])
+        .await
+        .is_err());
+
+    let counter = swarm
+        .validators()
+        .next()
+        .unwrap()
+        .rest_client()
+        .get_account_resource(account, &format!("{}::counter::Counter", account_str))
+        .await
+        .unwrap()
+        .into_inner()
+        .expect("The counter should have been created");
+    assert_eq!(counter.data["value"], "7");
+}
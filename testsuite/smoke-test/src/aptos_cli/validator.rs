@@ -727,6 +727,160 @@ async fn test_join_and_leave_validator() {
     .await;
 }
 
+#[tokio::test]
+async fn test_stake_pool_lifecycle() {
+    let (mut swarm, mut cli, _faucet) = SwarmBuilder::new_local(1)
+        .with_aptos()
+        .with_init_config(Arc::new(|_i, conf, genesis_stake_amount| {
+            // reduce timeout, as we will have dead node during rounds
+            conf.consensus.round_initial_timeout_ms = 200;
+            conf.consensus.quorum_store_poll_count = 4;
+            *genesis_stake_amount = 100000;
+        }))
+        .with_init_genesis_config(Arc::new(|genesis_config| {
+            genesis_config.allow_new_validators = true;
+            genesis_config.epoch_duration_secs = 5;
+            genesis_config.recurring_lockup_duration_secs = 10;
+            genesis_config.voting_duration_secs = 5;
+        }))
+        .build_with_cli(0)
+        .await;
+
+    let transaction_factory = swarm.chain_info().transaction_factory();
+    let rest_client = swarm.validators().next().unwrap().rest_client();
+
+    let mut keygen = KeyGen::from_os_rng();
+    let (validator_cli_index, keys) =
+        init_validator_account(&mut cli, &mut keygen, Some(DEFAULT_FUNDED_COINS * 3)).await;
+
+    // faucet can make our root LocalAccount sequence number get out of sync.
+    swarm
+        .chain_info()
+        .resync_root_account_seq_num(&rest_client)
+        .await
+        .unwrap();
+
+    cli.initialize_validator(
+        validator_cli_index,
+        keys.consensus_public_key(),
+        keys.consensus_proof_of_possession(),
+        HostAndPort {
+            host: dns_name("0.0.0.0"),
+            port: 1234,
+        },
+        keys.network_public_key(),
+    )
+    .await
+    .unwrap();
+    assert_stake_amounts(&cli, validator_cli_index, 0, 0, 0, 0).await;
+
+    // Stake added to a pool outside of the validator set is active right away
+    let stake_coins = 7;
+    cli.add_stake(validator_cli_index, stake_coins)
+        .await
+        .unwrap();
+    assert_stake_amounts(&cli, validator_cli_index, stake_coins, 0, 0, 0).await;
+
+    cli.join_validator_set(validator_cli_index, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        get_validator_state(&cli, validator_cli_index).await,
+        ValidatorState::JOINING
+    );
+
+    reconfig(
+        &rest_client,
+        &transaction_factory,
+        swarm.chain_info().root_account(),
+    )
+    .await;
+    assert_eq!(
+        get_validator_state(&cli, validator_cli_index).await,
+        ValidatorState::ACTIVE
+    );
+    assert_stake_amounts(&cli, validator_cli_index, stake_coins, 0, 0, 0).await;
+
+    cli.leave_validator_set(validator_cli_index, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        get_validator_state(&cli, validator_cli_index).await,
+        ValidatorState::LEAVING
+    );
+
+    reconfig(
+        &rest_client,
+        &transaction_factory,
+        swarm.chain_info().root_account(),
+    )
+    .await;
+    assert_eq!(
+        get_validator_state(&cli, validator_cli_index).await,
+        ValidatorState::NONE
+    );
+    assert_stake_amounts(&cli, validator_cli_index, stake_coins, 0, 0, 0).await;
+
+    // Unlocked stake stays pending inactive until the lockup expires
+    let unlock_coins = 3;
+    cli.unlock_stake(validator_cli_index, unlock_coins)
+        .await
+        .unwrap();
+    assert_stake_amounts(
+        &cli,
+        validator_cli_index,
+        stake_coins - unlock_coins,
+        0,
+        unlock_coins,
+        0,
+    )
+    .await;
+
+    // Conservatively wait until the recurring lockup is over.
+    let locked_until_secs = cli
+        .show_stake_pool(validator_cli_index)
+        .await
+        .unwrap()
+        .locked_until_secs();
+    tokio::time::sleep(Duration::from_secs(10)).await;
+    let now_secs = rest_client
+        .get_ledger_information()
+        .await
+        .unwrap()
+        .into_inner()
+        .timestamp_usecs
+        / 1_000_000;
+    assert!(
+        now_secs >= locked_until_secs,
+        "Lockup until {} should be over at {}",
+        locked_until_secs,
+        now_secs
+    );
+
+    // Withdrawing moves the expired pending inactive stake to inactive, and withdraws from it
+    let withdraw_coins = 2;
+    let balance_before = cli.account_balance_now(validator_cli_index).await.unwrap();
+    let gas_used = get_gas(
+        cli.withdraw_stake(validator_cli_index, withdraw_coins)
+            .await
+            .unwrap(),
+    );
+    assert_stake_amounts(
+        &cli,
+        validator_cli_index,
+        stake_coins - unlock_coins,
+        0,
+        0,
+        unlock_coins - withdraw_coins,
+    )
+    .await;
+    cli.assert_account_balance_now(
+        validator_cli_index,
+        balance_before + withdraw_coins - gas_used,
+    )
+    .await;
+}
+
 #[tokio::test]
 async fn test_owner_create_and_delegate_flow() {
     let (mut swarm, mut cli, _faucet) = SwarmBuilder::new_local(1)
@@ -1029,6 +1183,31 @@ async fn assert_validator_set_sizes(
     );
 }
 
+async fn assert_stake_amounts(
+    cli: &CliTestFramework,
+    pool_index: usize,
+    active: u64,
+    pending_active: u64,
+    pending_inactive: u64,
+    inactive: u64,
+) {
+    let stake_pool = cli.show_stake_pool(pool_index).await.unwrap();
+    assert_eq!(active, stake_pool.active(), "{:?}", stake_pool);
+    assert_eq!(
+        pending_active,
+        stake_pool.pending_active(),
+        "{:?}",
+        stake_pool
+    );
+    assert_eq!(
+        pending_inactive,
+        stake_pool.pending_inactive(),
+        "{:?}",
+        stake_pool
+    );
+    assert_eq!(inactive, stake_pool.inactive(), "{:?}", stake_pool);
+}
+
 #[derive(Debug, PartialEq, Eq)]
 enum ValidatorState {
     ACTIVE,
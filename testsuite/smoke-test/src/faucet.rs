@@ -0,0 +1,184 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::smoke_test_environment::{launch_faucet_with_limits, SwarmBuilder};
+use aptos_config::utils::get_available_port;
+use aptos_rest_client::Client;
+use aptos_types::{
+    account_address::AccountAddress, account_config::aptos_test_root_address,
+    transaction::SignedTransaction,
+};
+use forge::{LocalSwarm, Node, Swarm};
+use reqwest::Url;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+const MAXIMUM_AMOUNT: u64 = 1_000;
+
+/// A faucet minting from `swarm`'s root account, once it's serving requests
+async fn start_faucet(
+    swarm: &LocalSwarm,
+    maximum_amount: Option<u64>,
+    do_not_delegate: bool,
+) -> (Url, JoinHandle<()>) {
+    let port = get_available_port();
+    let faucet = launch_faucet_with_limits(
+        swarm.validators().next().unwrap().rest_api_endpoint(),
+        swarm.root_key(),
+        swarm.chain_id(),
+        port,
+        maximum_amount,
+        do_not_delegate,
+    );
+    let faucet_url: Url = format!("http://localhost:{}", port).parse().unwrap();
+
+    // Delegating submits a few transactions before the faucet listens
+    let health_url = faucet_url.join("health").unwrap();
+    for _ in 0..120 {
+        if let Ok(response) = reqwest::get(health_url.clone()).await {
+            if response.status().is_success() {
+                return (faucet_url, faucet);
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    panic!("The faucet didn't become healthy");
+}
+
+/// Requests `amount` for `address`, and waits for every transaction the faucet submitted
+async fn mint(
+    faucet_url: &Url,
+    client: &Client,
+    address: AccountAddress,
+    amount: u64,
+) -> anyhow::Result<()> {
+    let mut url = faucet_url.join("mint")?;
+    url.set_query(Some(&format!(
+        "address={}&amount={}&return_txns=true",
+        address, amount
+    )));
+    let response = reqwest::Client::new().post(url).send().await?;
+    let status = response.status();
+    let body = response.text().await?;
+    anyhow::ensure!(status.is_success(), "{}: {}", status, body);
+
+    let txns: Vec<SignedTransaction> = bcs::from_bytes(&hex::decode(body)?)?;
+    for txn in &txns {
+        client.wait_for_signed_transaction(txn).await?;
+    }
+    Ok(())
+}
+
+async fn balance(client: &Client, address: AccountAddress) -> u64 {
+    client
+        .get_account_balance(address)
+        .await
+        .unwrap()
+        .into_inner()
+        .get()
+}
+
+#[tokio::test]
+async fn test_faucet_maximum_amount() {
+    let swarm = SwarmBuilder::new_local(1).with_aptos().build().await;
+    let client = swarm.validators().next().unwrap().rest_client();
+    let (faucet_url, _faucet) = start_faucet(&swarm, Some(MAXIMUM_AMOUNT), true).await;
+
+    // Larger requests are capped to the maximum
+    let account = AccountAddress::random();
+    mint(&faucet_url, &client, account, MAXIMUM_AMOUNT * 10)
+        .await
+        .unwrap();
+    assert_eq!(balance(&client, account).await, MAXIMUM_AMOUNT);
+
+    // Smaller ones are minted as requested, and the limit is per request
+    mint(&faucet_url, &client, account, MAXIMUM_AMOUNT / 2)
+        .await
+        .unwrap();
+    assert_eq!(balance(&client, account).await, MAXIMUM_AMOUNT * 3 / 2);
+
+    // An amount of 0 only creates the account
+    let empty_account = AccountAddress::random();
+    mint(&faucet_url, &client, empty_account, 0).await.unwrap();
+    assert_eq!(balance(&client, empty_account).await, 0);
+
+    // Requests without a receiver are rejected
+    let response = reqwest::Client::new()
+        .post(faucet_url.join("mint?amount=1").unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.status(),
+        reqwest::StatusCode::INTERNAL_SERVER_ERROR
+    );
+}
+
+#[tokio::test]
+async fn test_faucet_concurrent_mints() {
+    let swarm = SwarmBuilder::new_local(1).with_aptos().build().await;
+    let client = swarm.validators().next().unwrap().rest_client();
+    let (faucet_url, _faucet) = start_faucet(&swarm, None, true).await;
+
+    // More transactions than the faucet lets be outstanding at once (50), so some requests wait for earlier ones
+    // to commit
+    let accounts: Vec<_> = (0..40).map(|_| AccountAddress::random()).collect();
+    let results = futures::future::join_all(
+        accounts
+            .iter()
+            .enumerate()
+            .map(|(i, account)| mint(&faucet_url, &client, *account, 100 + i as u64)),
+    )
+    .await;
+    for (i, (account, result)) in accounts.iter().zip(results).enumerate() {
+        result.unwrap_or_else(|e| panic!("Mint to {} failed: {:?}", account, e));
+        assert_eq!(balance(&client, *account).await, 100 + i as u64);
+    }
+
+    // Concurrent mints to the same account all land
+    let account = AccountAddress::random();
+    mint(&faucet_url, &client, account, 0).await.unwrap();
+    let results =
+        futures::future::join_all((0..10).map(|_| mint(&faucet_url, &client, account, 7))).await;
+    assert!(results.iter().all(Result::is_ok), "{:?}", results);
+    assert_eq!(balance(&client, account).await, 70);
+}
+
+#[tokio::test]
+async fn test_faucet_delegation() {
+    let swarm = SwarmBuilder::new_local(1).with_aptos().build().await;
+    let client = swarm.validators().next().unwrap().rest_client();
+    let root_address = aptos_test_root_address();
+    let root_sequence_number = client
+        .get_account(root_address)
+        .await
+        .unwrap()
+        .into_inner()
+        .sequence_number;
+    let (faucet_url, _faucet) = start_faucet(&swarm, Some(MAXIMUM_AMOUNT), false).await;
+
+    // Only delegating used the root account...
+    let root_sequence_number_after_delegation = client
+        .get_account(root_address)
+        .await
+        .unwrap()
+        .into_inner()
+        .sequence_number;
+    assert!(root_sequence_number_after_delegation > root_sequence_number);
+
+    // ...the delegated account mints, still within the maximum
+    let account = AccountAddress::random();
+    mint(&faucet_url, &client, account, MAXIMUM_AMOUNT * 10)
+        .await
+        .unwrap();
+    assert_eq!(balance(&client, account).await, MAXIMUM_AMOUNT);
+    assert_eq!(
+        client
+            .get_account(root_address)
+            .await
+            .unwrap()
+            .into_inner()
+            .sequence_number,
+        root_sequence_number_after_delegation
+    );
+}
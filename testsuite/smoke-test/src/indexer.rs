@@ -25,6 +25,39 @@ pub fn wipe_database(conn: &PgPoolConnection) {
         "token_propertys",
         "collections",
         "ownerships",
+        "dex_swaps",
+        "minute_transaction_rollups",
+        "hourly_activity_rollups",
+        "hourly_active_accounts",
+        "epochs",
+        "validator_set_snapshots",
+        "objects",
+        "current_object_ownerships",
+        "fungible_asset_activities",
+        "fungible_asset_balances",
+        "fungible_asset_metadata",
+        "collections_v2",
+        "tokens_v2",
+        "token_activities_v2",
+        "current_token_ownerships_v2",
+        "token_metadata_cache",
+        "ledger_info_history",
+        "transfer_edges",
+        "account_summaries",
+        "account_summary_batches",
+        "txn_latency_stats",
+        "txn_latency_stat_batches",
+        "outbox",
+        "raw_transactions",
+        "fungible_asset_balance_history",
+        "top_holders",
+        "module_daily_stats",
+        "function_reliability_stats",
+        "event_types_seen",
+        "rollup_ranges",
+        "api_keys",
+        "operator_audit_log",
+        "online_migrations",
         "write_set_changes",
         "events",
         "user_transactions",
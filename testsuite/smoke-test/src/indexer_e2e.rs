@@ -0,0 +1,323 @@
+// Copyright (c) Aptos
+// SPDX-License-Identifier: Apache-2.0
+
+//! End to end tests of the indexer: a local swarm, transactions submitted through the CLI, and processors writing
+//! into a throwaway Postgres (a container started with the `docker` CLI), with assertions on the rows. As they need
+//! docker, they're ignored by default: run them with `cargo test -p smoke-test indexer_e2e -- --ignored`.
+
+use crate::smoke_test_environment::{restart_validator, stop_validator, SwarmBuilder};
+use aptos::test::CliTestFramework;
+use aptos_crypto::{PrivateKey, ValidCryptoMaterialStringExt};
+use aptos_indexer::{
+    database::{PgDbPool, PgPoolConnection},
+    indexer::builder::IndexerBuilder,
+    queries::{get_transaction_with_details, get_user_transactions_by_sender},
+};
+use aptos_keygen::KeyGen;
+use aptos_types::transaction::authenticator::AuthenticationKey;
+use forge::{LocalSwarm, Node, Swarm};
+use std::{
+    process::Command,
+    time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+
+const BATCH_SIZE: u8 = 10;
+const POSTGRES_IMAGE: &str = "postgres:14";
+/// How long Postgres may take to accept connections once its container is started
+const POSTGRES_STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Runs `docker` with `args`, returning its trimmed stdout
+fn docker(args: &[&str]) -> String {
+    let output = Command::new("docker")
+        .args(args)
+        .output()
+        .expect("Failed to run docker, which the indexer e2e tests need");
+    assert!(
+        output.status.success(),
+        "docker {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// A Postgres container, removed when dropped
+pub struct PostgresContainer {
+    id: String,
+    pub port: u16,
+}
+
+impl PostgresContainer {
+    /// Starts a container on a random local port, and waits for it to accept connections
+    pub fn start() -> Self {
+        let id = docker(&[
+            "run",
+            "--detach",
+            "--rm",
+            "--env",
+            "POSTGRES_HOST_AUTH_METHOD=trust",
+            "--publish",
+            "127.0.0.1::5432",
+            POSTGRES_IMAGE,
+        ]);
+        // ex: "127.0.0.1:49153"
+        let address = docker(&["port", &id, "5432/tcp"]);
+        let port = address
+            .lines()
+            .next()
+            .and_then(|address| address.rsplit(':').next())
+            .and_then(|port| port.parse().ok())
+            .unwrap_or_else(|| panic!("Unexpected port mapping {}", address));
+        let container = Self { id, port };
+
+        let start = Instant::now();
+        while !Command::new("docker")
+            .args(&[
+                "exec",
+                &container.id,
+                "pg_isready",
+                "-U",
+                "postgres",
+                "-h",
+                "127.0.0.1",
+            ])
+            .output()
+            .map_or(false, |output| output.status.success())
+        {
+            assert!(
+                start.elapsed() < POSTGRES_STARTUP_TIMEOUT,
+                "Postgres didn't start within {:?}",
+                POSTGRES_STARTUP_TIMEOUT
+            );
+            std::thread::sleep(Duration::from_millis(500));
+        }
+        container
+    }
+}
+
+impl Drop for PostgresContainer {
+    fn drop(&mut self) {
+        let _ = Command::new("docker")
+            .args(&["rm", "--force", &self.id])
+            .output();
+    }
+}
+
+/// A swarm, the CLI to submit transactions to it, and an indexer writing into a Postgres container. The container
+/// is removed when the harness is dropped.
+pub struct IndexerTestHarness {
+    pub swarm: LocalSwarm,
+    pub cli: CliTestFramework,
+    pub conn_pool: PgDbPool,
+    builder: IndexerBuilder,
+    _faucet: JoinHandle<()>,
+    _postgres: PostgresContainer,
+}
+
+impl IndexerTestHarness {
+    /// Launches a single validator swarm with `num_cli_accounts` funded CLI accounts, and an indexer running
+    /// `processors` against it
+    pub async fn new(processors: &[&str], num_cli_accounts: usize) -> Self {
+        Self::new_with_validators(1, processors, num_cli_accounts).await
+    }
+
+    /// Like `new`, with `num_validators` validators. The CLI and the indexer use the first one, so the others can
+    /// be stopped without losing their endpoint.
+    pub async fn new_with_validators(
+        num_validators: usize,
+        processors: &[&str],
+        num_cli_accounts: usize,
+    ) -> Self {
+        let postgres = PostgresContainer::start();
+        let pg_uri = format!("postgres://postgres@127.0.0.1:{}/postgres", postgres.port);
+        let (swarm, cli, faucet) = SwarmBuilder::new_local(num_validators)
+            .with_aptos()
+            .build_with_cli(num_cli_accounts)
+            .await;
+        let node_url = swarm
+            .validators()
+            .next()
+            .unwrap()
+            .rest_api_endpoint()
+            .to_string();
+        let builder = IndexerBuilder::new(pg_uri)
+            .node_url(node_url)
+            .processors(processors.iter().map(|name| name.to_string()).collect());
+        let (conn_pool, _) = builder
+            .build_pools()
+            .expect("Failed to connect to Postgres");
+        aptos_indexer::database::run_migrations(&conn_pool);
+        Self {
+            swarm,
+            cli,
+            conn_pool,
+            builder,
+            _faucet: faucet,
+            _postgres: postgres,
+        }
+    }
+
+    pub fn conn(&self) -> PgPoolConnection {
+        self.conn_pool.get().unwrap()
+    }
+
+    /// Runs every processor over all versions up to the node's latest one, returning that version. Each call uses
+    /// new tailers, since a tailer's fetcher can only be started once.
+    pub async fn index_to_latest_version(&mut self) -> u64 {
+        let version = self
+            .swarm
+            .aptos_public_info()
+            .client()
+            .get_ledger_information()
+            .await
+            .unwrap()
+            .into_inner()
+            .version;
+        let indexer = self
+            .builder
+            .clone()
+            .build()
+            .expect("Failed to build the indexer");
+        for (processor_name, tailer) in &indexer.tailers {
+            tailer
+                .backfill(0, version, BATCH_SIZE)
+                .await
+                .unwrap_or_else(|e| panic!("{} failed: {:?}", processor_name, e));
+            assert_eq!(
+                tailer.get_start_version(processor_name),
+                Some(version + 1),
+                "{} didn't mark every version successful",
+                processor_name
+            );
+        }
+        version
+    }
+}
+
+#[ignore] // Needs docker
+#[tokio::test]
+async fn test_indexer_e2e_coin_transfer() {
+    let mut harness = IndexerTestHarness::new(&["default_processor"], 2).await;
+
+    let transfer = harness.cli.transfer_coins(0, 1, 717, None).await.unwrap();
+    let latest_version = harness.index_to_latest_version().await;
+    assert!(latest_version >= transfer.version);
+
+    let conn = harness.conn();
+    let (txn, user_txn, bm_txn, events, write_set_changes) =
+        get_transaction_with_details(&conn, transfer.version)
+            .unwrap()
+            .expect("The transfer should be indexed");
+    assert_eq!(txn.type_, "user_transaction");
+    assert_eq!(txn.hash, transfer.transaction_hash.to_string());
+    assert!(txn.success);
+    assert!(bm_txn.is_none());
+    assert_eq!(
+        user_txn.unwrap().sender,
+        harness.cli.account_id(0).to_hex_literal()
+    );
+    assert_eq!(
+        events.iter().map(|e| e.type_.as_str()).collect::<Vec<_>>(),
+        vec!["0x1::coin::WithdrawEvent", "0x1::coin::DepositEvent"]
+    );
+    assert_eq!(txn.num_events, 2);
+    assert_eq!(txn.num_write_set_changes, write_set_changes.len() as i64);
+
+    let sent = get_user_transactions_by_sender(&conn, &harness.cli.account_id(0), 10).unwrap();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].hash, txn.hash);
+
+    // Re-processing the same versions is idempotent
+    harness.index_to_latest_version().await;
+    let (reprocessed, ..) = get_transaction_with_details(&harness.conn(), transfer.version)
+        .unwrap()
+        .unwrap();
+    assert_eq!(reprocessed.hash, txn.hash);
+}
+
+#[ignore] // Needs docker
+#[tokio::test]
+async fn test_indexer_e2e_through_validator_outage() {
+    // 4 validators tolerate one being down
+    let mut harness = IndexerTestHarness::new_with_validators(4, &["default_processor"], 2).await;
+
+    let stopped = stop_validator(&mut harness.swarm, 3);
+    let during_outage = harness.cli.transfer_coins(0, 1, 10, None).await.unwrap();
+    assert!(harness.index_to_latest_version().await >= during_outage.version);
+
+    restart_validator(&mut harness.swarm, stopped).await;
+    let after_outage = harness.cli.transfer_coins(1, 0, 5, None).await.unwrap();
+    assert!(harness.index_to_latest_version().await >= after_outage.version);
+
+    let conn = harness.conn();
+    for transfer in [during_outage, after_outage] {
+        let (txn, ..) = get_transaction_with_details(&conn, transfer.version)
+            .unwrap()
+            .expect("The transfer should be indexed");
+        assert!(txn.success);
+        assert_eq!(txn.hash, transfer.transaction_hash.to_string());
+    }
+}
+
+#[ignore] // Needs docker
+#[tokio::test]
+async fn test_indexer_e2e_key_rotation() {
+    let mut harness = IndexerTestHarness::new(&["default_processor"], 2).await;
+    let account = harness.cli.account_id(0);
+    let old_public_key = harness.cli.private_key(0).public_key();
+
+    let new_private_key = KeyGen::from_seed([9u8; 32]).generate_ed25519_private_key();
+    let new_public_key = new_private_key.public_key();
+    let rotation = harness
+        .cli
+        .rotate_key(0, new_private_key.to_encoded_string().unwrap(), None)
+        .await
+        .unwrap()
+        .transaction;
+    harness.cli.set_private_key(0, new_private_key);
+    let transfer = harness.cli.transfer_coins(0, 1, 5, None).await.unwrap();
+    harness.index_to_latest_version().await;
+
+    let conn = harness.conn();
+    // The account's new authentication key is in the rotation's write set
+    let (_, _, _, _, write_set_changes) = get_transaction_with_details(&conn, rotation.version)
+        .unwrap()
+        .expect("The rotation should be indexed");
+    let account_resource = write_set_changes
+        .iter()
+        .find(|change| {
+            change.address == account.to_hex_literal()
+                && change.data["type"] == "0x1::account::Account"
+        })
+        .expect("The rotation should write the account resource");
+    assert_eq!(
+        account_resource.data["data"]["authentication_key"],
+        AuthenticationKey::ed25519(&new_public_key).to_string()
+    );
+
+    // The rotation was signed with the old key and the transfer with the new one, with no gap in the account's
+    // sequence numbers
+    let sent = get_user_transactions_by_sender(&conn, &account, 10).unwrap();
+    let hashes: Vec<_> = sent.iter().map(|txn| txn.hash.clone()).collect();
+    assert_eq!(
+        hashes,
+        vec![
+            transfer.transaction_hash.to_string(),
+            rotation.transaction_hash.to_string()
+        ]
+    );
+    let sequence_numbers: Vec<u64> = sent
+        .iter()
+        .map(|txn| txn.sequence_number.to_string().parse().unwrap())
+        .collect();
+    assert_eq!(sequence_numbers[0], sequence_numbers[1] + 1);
+    assert_eq!(
+        sent[0].signature["public_key"],
+        new_public_key.to_encoded_string().unwrap()
+    );
+    assert_eq!(
+        sent[1].signature["public_key"],
+        old_public_key.to_encoded_string().unwrap()
+    );
+}
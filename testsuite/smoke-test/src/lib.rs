@@ -12,12 +12,16 @@ mod client;
 #[cfg(test)]
 mod consensus;
 #[cfg(test)]
+mod faucet;
+#[cfg(test)]
 mod full_nodes;
 #[cfg(test)]
 mod fullnode;
 #[cfg(test)]
 mod indexer;
 #[cfg(test)]
+mod indexer_e2e;
+#[cfg(test)]
 mod network;
 #[cfg(test)]
 mod nft_transaction;
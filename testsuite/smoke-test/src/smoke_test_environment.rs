@@ -8,13 +8,18 @@ use aptos_faucet::FaucetArgs;
 use aptos_genesis::builder::{InitConfigFn, InitGenesisConfigFn};
 use aptos_infallible::Mutex;
 use aptos_logger::info;
-use aptos_types::{account_config::aptos_test_root_address, chain_id::ChainId};
-use forge::{ActiveNodesGuard, Node};
+use aptos_types::{account_config::aptos_test_root_address, chain_id::ChainId, PeerId};
+use forge::{ActiveNodesGuard, Node, NodeExt, SwarmExt};
 use forge::{Factory, LocalFactory, LocalSwarm};
 use framework::ReleaseBundle;
 use once_cell::sync::Lazy;
 use rand::rngs::OsRng;
-use std::{num::NonZeroUsize, path::PathBuf, sync::Arc};
+use std::{
+    num::NonZeroUsize,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio::task::JoinHandle;
 
 pub struct SwarmBuilder {
@@ -152,11 +157,51 @@ async fn test_prevent_starting_nodes_twice() {
     assert!(validator.start().is_err());
 }
 
+/// Stops the `index`th validator, ex: to test liveness through an outage, returning its peer id to restart it with
+/// `restart_validator`. The swarm's other validators keep running.
+pub fn stop_validator(swarm: &mut LocalSwarm, index: usize) -> PeerId {
+    let validator = swarm
+        .validators_mut()
+        .nth(index)
+        .unwrap_or_else(|| panic!("The swarm has no validator {}", index));
+    validator.stop();
+    info!("Stopped validator {}", validator.peer_id());
+    validator.peer_id()
+}
+
+/// Restarts a validator stopped with `stop_validator`, and waits for it to be healthy and caught up with the others
+pub async fn restart_validator(swarm: &mut LocalSwarm, peer_id: PeerId) {
+    let validator = swarm.validator_mut(peer_id).unwrap();
+    validator.start().unwrap();
+    validator
+        .wait_until_healthy(Instant::now() + Duration::from_secs(30))
+        .await
+        .unwrap_or_else(|e| panic!("Validator {} didn't restart: {:?}", peer_id, e));
+    swarm
+        .wait_for_all_nodes_to_catchup(Duration::from_secs(60))
+        .await
+        .unwrap();
+    info!("Restarted validator {}", peer_id);
+}
+
 pub fn launch_faucet(
     endpoint: reqwest::Url,
     mint_key: Ed25519PrivateKey,
     chain_id: ChainId,
     port: u16,
+) -> JoinHandle<()> {
+    launch_faucet_with_limits(endpoint, mint_key, chain_id, port, None, true)
+}
+
+/// Launches a faucet which mints at most `maximum_amount` per request, and which, unless `do_not_delegate`, first
+/// delegates minting to a new account (as deployed faucets do)
+pub fn launch_faucet_with_limits(
+    endpoint: reqwest::Url,
+    mint_key: Ed25519PrivateKey,
+    chain_id: ChainId,
+    port: u16,
+    maximum_amount: Option<u64>,
+    do_not_delegate: bool,
 ) -> JoinHandle<()> {
     let faucet = FaucetArgs {
         address: "127.0.0.1".to_string(),
@@ -166,8 +211,8 @@ pub fn launch_faucet(
         mint_key: Some(ConfigKey::new(mint_key)),
         mint_account_address: Some(aptos_test_root_address()),
         chain_id,
-        maximum_amount: None,
-        do_not_delegate: true,
+        maximum_amount,
+        do_not_delegate,
     };
     tokio::spawn(faucet.run())
 }